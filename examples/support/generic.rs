@@ -15,6 +15,7 @@
 mod client;
 
 use oxide_auth::endpoint::Solicitation;
+use oxide_auth::frontends::templates::{ConsentPage, Templates};
 use std::fmt;
 
 pub use self::client::{Client, Config as ClientConfig, Error as ClientError};
@@ -52,36 +53,6 @@ pub fn open_in_browser(port: u16) {
 }
 
 pub fn consent_page_html(route: &str, solicitation: Solicitation) -> String {
-    macro_rules! template {
-        () => {
-            "<html>'{0:}' (at {1:}) is requesting permission for '{2:}'
-<form method=\"post\">
-    <input type=\"submit\" value=\"Accept\" formaction=\"{4:}?{3:}&allow=true\">
-    <input type=\"submit\" value=\"Deny\" formaction=\"{4:}?{3:}&deny=true\">
-</form>
-</html>"
-        };
-    }
-
-    let grant = solicitation.pre_grant();
-    let state = solicitation.state();
-
-    let mut extra = vec![
-        ("response_type", "code"),
-        ("client_id", grant.client_id.as_str()),
-        ("redirect_uri", grant.redirect_uri.as_str()),
-    ];
-
-    if let Some(state) = state {
-        extra.push(("state", state));
-    }
-
-    format!(
-        template!(),
-        grant.client_id,
-        grant.redirect_uri,
-        grant.scope,
-        serde_urlencoded::to_string(extra).unwrap(),
-        &route,
-    )
+    let page = ConsentPage::new(&solicitation, route);
+    Templates::new().consent(&page).unwrap()
 }
@@ -1,27 +1,27 @@
-extern crate rocket;
-
 #[path = "generic.rs"]
 mod generic;
 
 use self::generic::{Client, ClientConfig, ClientError};
 
-use rocket::{Rocket, State};
-use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Build, Rocket, State};
+use rocket::fairing::{self, Fairing, Info, Kind};
 use rocket::http::Status;
-use rocket::response::{Redirect, content::Html, status::Custom};
+use rocket::response::{Redirect, content::RawHtml, status::Custom};
+use rocket::tokio::task::spawn_blocking;
 
 pub use self::generic::consent_page_html;
 pub struct ClientFairing;
 
+#[rocket::async_trait]
 impl Fairing for ClientFairing {
     fn info(&self) -> Info {
         Info {
             name: "Simple oauth client implementation",
-            kind: Kind::Attach,
+            kind: Kind::Ignite,
         }
     }
 
-    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
         let config = ClientConfig {
             client_id: "LocalClient".into(),
             protected_url: "http://localhost:8000/".into(),
@@ -37,9 +37,22 @@ impl Fairing for ClientFairing {
     }
 }
 
+// The client is blocking and requests the server it runs in, so it must not occupy a worker.
+async fn blocking<T, F>(state: &State<Client>, call: F) -> Result<T, Custom<String>>
+where
+    T: Send + 'static,
+    F: FnOnce(Client) -> Result<T, ClientError> + Send + 'static,
+{
+    let client = state.inner().clone();
+    spawn_blocking(move || call(client))
+        .await
+        .map_err(|err| Custom(Status::InternalServerError, err.to_string()))?
+        .map_err(internal_error)
+}
+
 #[get("/endpoint?<code>&<error>")]
-fn oauth_endpoint<'r>(
-    code: Option<String>, error: Option<String>, state: State<Client>,
+async fn oauth_endpoint(
+    code: Option<String>, error: Option<String>, state: &State<Client>,
 ) -> Result<Redirect, Custom<String>> {
     if let Some(error) = error {
         return Err(Custom(
@@ -54,14 +67,14 @@ fn oauth_endpoint<'r>(
             "Endpoint hit without an authorization code".into(),
         )
     })?;
-    state.authorize(&code).map_err(internal_error)?;
+    blocking(state, move |client| client.authorize(&code)).await?;
 
     Ok(Redirect::found("/clientside"))
 }
 
 #[get("/")]
-fn client_view(state: State<Client>) -> Result<Html<String>, Custom<String>> {
-    let protected_page = state.retrieve_protected_page().map_err(internal_error)?;
+async fn client_view(state: &State<Client>) -> Result<RawHtml<String>, Custom<String>> {
+    let protected_page = blocking(state, |client| client.retrieve_protected_page()).await?;
 
     let display_page = format!(
         "<html><style>
@@ -77,20 +90,18 @@ fn client_view(state: State<Client>) -> Result<Html<String>, Custom<String>> {
         <form action=\"/clientside/refresh\" method=\"post\"><button>Refresh token</button></form>
         </main></html>", state.as_html(), protected_page);
 
-    Ok(Html(display_page))
+    Ok(RawHtml(display_page))
 }
 
 #[post("/refresh")]
-fn refresh(state: State<Client>) -> Result<Redirect, Custom<String>> {
-    state
-        .refresh()
-        .map_err(internal_error)
-        .map(|()| Redirect::found("/clientside"))
+async fn refresh(state: &State<Client>) -> Result<Redirect, Custom<String>> {
+    blocking(state, |client| client.refresh()).await?;
+    Ok(Redirect::found("/clientside"))
 }
 
 #[get("/debug")]
-fn client_debug(state: State<Client>) -> Html<String> {
-    Html(state.as_html())
+fn client_debug(state: &State<Client>) -> RawHtml<String> {
+    RawHtml(state.as_html())
 }
 
 fn internal_error(err: ClientError) -> Custom<String> {
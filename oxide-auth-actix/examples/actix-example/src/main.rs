@@ -2,19 +2,25 @@ mod support;
 
 use actix::{Actor, Addr, Context, Handler};
 use actix_web::{
-    middleware::{Logger, NormalizePath, TrailingSlash},
-    web::{self, Data},
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    middleware::{ErrorHandlerResponse, ErrorHandlers, Logger, NormalizePath, TrailingSlash},
+    web::{self, Data, ReqData},
     App, HttpRequest, HttpServer, rt,
 };
 use oxide_auth::{
     endpoint::{Endpoint, OwnerConsent, OwnerSolicitor, Solicitation, QueryParameter},
     frontends::simple::endpoint::{ErrorInto, FnSolicitor, Generic, Vacant},
-    primitives::prelude::{AuthMap, Client, ClientMap, RandomGenerator, Scope, TokenMap},
+    primitives::{
+        grant::Grant,
+        prelude::{AuthMap, Client, ClientMap, RandomGenerator, Scope, TokenMap},
+    },
 };
 use oxide_auth_actix::{
-    Authorize, OAuthMessage, OAuthOperation, OAuthRequest, OAuthResource, OAuthResponse, Refresh,
-    Resource, Token, WebError, ClientCredentials,
+    Authorize, OAuthGuard, OAuthMessage, OAuthOperation, OAuthRequest, OAuthResponse, Refresh, Token,
+    WebError, ClientCredentials,
 };
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 static DENY_TEXT: &str = "<html>
@@ -24,17 +30,12 @@ here</a> to begin the authorization process.
 </html>
 ";
 
-type ExampleEndpoint = Generic<
-    ClientMap,
-    AuthMap<RandomGenerator>,
-    TokenMap<RandomGenerator>,
-    Vacant,
-    Vec<Scope>,
-    fn() -> OAuthResponse,
->;
-
 struct State {
-    endpoint: ExampleEndpoint,
+    registrar: ClientMap,
+    authorizer: AuthMap<RandomGenerator>,
+    // Shared with the guard of the protected resources.
+    issuer: Arc<Mutex<TokenMap<RandomGenerator>>>,
+    scopes: Vec<Scope>,
 }
 
 enum Extras {
@@ -83,19 +84,24 @@ async fn refresh(
     state.send(Refresh(req).wrap(Extras::Nothing)).await?
 }
 
-async fn index(
-    (req, state): (OAuthResource, web::Data<Addr<State>>),
-) -> Result<OAuthResponse, WebError> {
-    match state
-        .send(Resource(req.into_request()).wrap(Extras::Nothing))
-        .await?
-    {
-        Ok(_grant) => Ok(OAuthResponse::ok()
-            .content_type("text/plain")?
-            .body("Hello world!")),
-        Err(Ok(e)) => Ok(e.body(DENY_TEXT)),
-        Err(Err(e)) => Err(e),
-    }
+async fn index(_grant: ReqData<Grant>) -> Result<OAuthResponse, WebError> {
+    // The guard has already checked the token, requests only get here with a valid grant.
+    Ok(OAuthResponse::ok()
+        .content_type("text/plain")?
+        .body("Hello world!"))
+}
+
+/// Replace the empty body of rejected requests with a hint on how to get a token.
+fn deny_page<B>(res: ServiceResponse<B>) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    let (req, mut res) = res.into_parts();
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("text/html"),
+    );
+    let res = ServiceResponse::new(req, res.set_body(DENY_TEXT))
+        .map_into_boxed_body()
+        .map_into_right_body();
+    Ok(ErrorHandlerResponse::Response(res))
 }
 
 async fn start_browser() -> () {
@@ -114,7 +120,9 @@ pub async fn main() -> std::io::Result<()> {
     // Start, then open in browser, don't care about this finishing.
     rt::spawn(start_browser());
 
-    let state = State::preconfigured().start();
+    let state = State::preconfigured();
+    let guard = state.guard();
+    let state = state.start();
 
     // Create the main server instance
     let server = HttpServer::new(move || {
@@ -129,7 +137,16 @@ pub async fn main() -> std::io::Result<()> {
             )
             .route("/token", web::post().to(token))
             .route("/refresh", web::post().to(refresh))
-            .route("/", web::get().to(index))
+            .service(
+                web::resource("/")
+                    .wrap(guard.clone())
+                    .wrap(
+                        ErrorHandlers::new()
+                            .handler(StatusCode::UNAUTHORIZED, deny_page)
+                            .handler(StatusCode::FORBIDDEN, deny_page),
+                    )
+                    .route(web::get().to(index)),
+            )
     })
     .bind("localhost:8020")
     .expect("Failed to bind to socket")
@@ -143,39 +160,37 @@ pub async fn main() -> std::io::Result<()> {
 impl State {
     pub fn preconfigured() -> Self {
         State {
-            endpoint: Generic {
-                // A registrar with one pre-registered client
-                registrar: vec![Client::confidential(
-                    "LocalClient",
-                    "http://localhost:8021/endpoint"
-                        .parse::<url::Url>()
-                        .unwrap()
-                        .into(),
-                    "default-scope".parse().unwrap(),
-                    "SecretSecret".as_bytes(),
-                )]
-                .into_iter()
-                .collect(),
-                // Authorization tokens are 16 byte random keys to a memory hash map.
-                authorizer: AuthMap::new(RandomGenerator::new(16)),
-                // Bearer tokens are also random generated but 256-bit tokens, since they live longer
-                // and this example is somewhat paranoid.
-                //
-                // We could also use a `TokenSigner::ephemeral` here to create signed tokens which can
-                // be read and parsed by anyone, but not maliciously created. However, they can not be
-                // revoked and thus don't offer even longer lived refresh tokens.
-                issuer: TokenMap::new(RandomGenerator::new(16)),
-
-                solicitor: Vacant,
-
-                // A single scope that will guard resources for this endpoint
-                scopes: vec!["default-scope".parse().unwrap()],
-
-                response: OAuthResponse::ok,
-            },
+            // A registrar with one pre-registered client
+            registrar: vec![Client::confidential(
+                "LocalClient",
+                "http://localhost:8021/endpoint"
+                    .parse::<url::Url>()
+                    .unwrap()
+                    .into(),
+                "default-scope".parse().unwrap(),
+                "SecretSecret".as_bytes(),
+            )]
+            .into_iter()
+            .collect(),
+            // Authorization tokens are 16 byte random keys to a memory hash map.
+            authorizer: AuthMap::new(RandomGenerator::new(16)),
+            // Bearer tokens are also random generated but 256-bit tokens, since they live longer
+            // and this example is somewhat paranoid.
+            //
+            // We could also use a `TokenSigner::ephemeral` here to create signed tokens which can
+            // be read and parsed by anyone, but not maliciously created. However, they can not be
+            // revoked and thus don't offer even longer lived refresh tokens.
+            issuer: Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16)))),
+            // A single scope that will guard resources for this endpoint
+            scopes: vec!["default-scope".parse().unwrap()],
         }
     }
 
+    /// The middleware protecting resources with the tokens issued by this endpoint.
+    pub fn guard(&self) -> OAuthGuard {
+        OAuthGuard::new(self.issuer.clone(), self.scopes.clone()).with_realm("actix-example")
+    }
+
     pub fn with_solicitor<'a, S>(
         &'a mut self, solicitor: S,
     ) -> impl Endpoint<OAuthRequest, Error = WebError> + 'a
@@ -183,11 +198,11 @@ impl State {
         S: OwnerSolicitor<OAuthRequest> + 'static,
     {
         ErrorInto::new(Generic {
-            authorizer: &mut self.endpoint.authorizer,
-            registrar: &mut self.endpoint.registrar,
-            issuer: &mut self.endpoint.issuer,
+            authorizer: &mut self.authorizer,
+            registrar: &mut self.registrar,
+            issuer: self.issuer.lock().unwrap(),
             solicitor,
-            scopes: &mut self.endpoint.scopes,
+            scopes: &mut self.scopes,
             response: OAuthResponse::ok,
         })
     }
@@ -244,7 +259,7 @@ where
 
                 op.run(self.with_solicitor(solicitor))
             }
-            _ => op.run(&mut self.endpoint),
+            _ => op.run(self.with_solicitor(Vacant)),
         }
     }
 }
@@ -0,0 +1,172 @@
+//! The authorization server, asking the owner for consent and issuing signed tokens.
+use std::sync::{Arc, Mutex};
+
+use actix::{Actor, Addr, Context, Handler};
+use actix_web::{
+    middleware::{Logger, NormalizePath, TrailingSlash},
+    web::{self, Data},
+    App, HttpRequest, HttpServer,
+};
+use actix_three_party::{
+    profile_scope, SignedIssuer, AUTHORIZATION_ADDR, CLIENT_ID, CLIENT_SECRET, REDIRECT_URL,
+};
+use oxide_auth::{
+    endpoint::{Endpoint, OwnerConsent, OwnerSolicitor, QueryParameter, Solicitation},
+    frontends::{
+        simple::endpoint::{ErrorInto, FnSolicitor, Generic, Vacant},
+        simple::extensions::{AddonList, Extended, Pkce},
+        templates::{ConsentPage, Templates},
+    },
+    primitives::{
+        prelude::{AuthMap, ClientMap, RandomGenerator, Scope},
+        registrar::{Client, ClientAuthMethod, GrantType},
+    },
+};
+use oxide_auth_actix::{
+    Authorize, OAuthMessage, OAuthOperation, OAuthRequest, OAuthResponse, Refresh, Token, WebError,
+};
+
+struct State {
+    registrar: ClientMap,
+    authorizer: AuthMap<RandomGenerator>,
+    issuer: Arc<Mutex<SignedIssuer>>,
+    scopes: Vec<Scope>,
+    addons: AddonList,
+}
+
+enum Extras {
+    /// Show the consent page.
+    AuthGet,
+    /// The owner submitted the consent page, with its query.
+    AuthPost(String),
+    Nothing,
+}
+
+async fn get_authorize(
+    (req, state): (OAuthRequest, Data<Addr<State>>),
+) -> Result<OAuthResponse, WebError> {
+    state.send(Authorize(req).wrap(Extras::AuthGet)).await?
+}
+
+async fn post_authorize(
+    (r, req, state): (HttpRequest, OAuthRequest, Data<Addr<State>>),
+) -> Result<OAuthResponse, WebError> {
+    // The owner should be logged in at this point, this example knows only a single one.
+    let query = r.query_string().to_owned();
+    state.send(Authorize(req).wrap(Extras::AuthPost(query))).await?
+}
+
+async fn token((req, state): (OAuthRequest, Data<Addr<State>>)) -> Result<OAuthResponse, WebError> {
+    let grant_type = req.body().and_then(|body| body.unique_value("grant_type"));
+    // The client sends both code exchanges and refreshes to the token endpoint.
+    match grant_type.as_deref() {
+        Some("refresh_token") => state.send(Refresh(req).wrap(Extras::Nothing)).await?,
+        _ => state.send(Token(req).wrap(Extras::Nothing)).await?,
+    }
+}
+
+#[actix_web::main]
+pub async fn main() -> std::io::Result<()> {
+    std::env::set_var("RUST_LOG", "authorization=info,actix_web=info");
+    env_logger::init();
+
+    let state = State::preconfigured().start();
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(Data::new(state.clone()))
+            .wrap(NormalizePath::new(TrailingSlash::Trim))
+            .wrap(Logger::default())
+            .service(
+                web::resource("/authorize")
+                    .route(web::get().to(get_authorize))
+                    .route(web::post().to(post_authorize)),
+            )
+            .route("/token", web::post().to(token))
+    })
+    .bind(AUTHORIZATION_ADDR)?
+    .run()
+    .await
+}
+
+impl State {
+    fn preconfigured() -> Self {
+        let client = Client::builder(CLIENT_ID)
+            .redirect_uri(REDIRECT_URL.parse::<url::Url>().unwrap())
+            .default_scope(profile_scope())
+            .grant_types([GrantType::AuthorizationCode, GrantType::RefreshToken])
+            .passphrase(CLIENT_SECRET.as_bytes())
+            .auth_method(ClientAuthMethod::ClientSecretBasic)
+            .metadata("client_name", "Three-party example client")
+            .build()
+            .expect("The example client is complete");
+
+        // The only client uses PKCE, so it can be required of all clients.
+        let mut addons = AddonList::new();
+        addons.push_code(Pkce::required());
+
+        State {
+            registrar: vec![client].into_iter().collect(),
+            authorizer: AuthMap::new(RandomGenerator::new(16)),
+            issuer: SignedIssuer::shared(),
+            scopes: vec![profile_scope()],
+            addons,
+        }
+    }
+
+    fn with_solicitor<'a, S>(
+        &'a mut self, solicitor: S,
+    ) -> impl Endpoint<OAuthRequest, Error = WebError> + 'a
+    where
+        S: OwnerSolicitor<OAuthRequest> + 'static,
+    {
+        let generic = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: self.issuer.lock().unwrap(),
+            solicitor,
+            scopes: &mut self.scopes,
+            response: OAuthResponse::ok,
+        };
+        ErrorInto::new(Extended::extend_with(generic, &mut self.addons))
+    }
+}
+
+impl Actor for State {
+    type Context = Context<Self>;
+}
+
+impl<Op> Handler<OAuthMessage<Op, Extras>> for State
+where
+    Op: OAuthOperation,
+{
+    type Result = Result<Op::Item, Op::Error>;
+
+    fn handle(&mut self, msg: OAuthMessage<Op, Extras>, _: &mut Self::Context) -> Self::Result {
+        let (op, ex) = msg.into_inner();
+
+        match ex {
+            Extras::AuthGet => {
+                let solicitor = FnSolicitor(|_: &mut OAuthRequest, solicitation: Solicitation| {
+                    let page = ConsentPage::new(&solicitation, "/authorize");
+                    let html = Templates::new().consent(&page).unwrap();
+                    OwnerConsent::InProgress(
+                        OAuthResponse::ok().content_type("text/html").unwrap().body(&html),
+                    )
+                });
+                op.run(self.with_solicitor(solicitor))
+            }
+            Extras::AuthPost(query) => {
+                let solicitor = FnSolicitor(move |_: &mut OAuthRequest, _: Solicitation| {
+                    if query.contains("allow") {
+                        OwnerConsent::Authorized("alice".to_owned())
+                    } else {
+                        OwnerConsent::Denied
+                    }
+                });
+                op.run(self.with_solicitor(solicitor))
+            }
+            Extras::Nothing => op.run(self.with_solicitor(Vacant)),
+        }
+    }
+}
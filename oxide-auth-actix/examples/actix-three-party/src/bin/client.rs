@@ -0,0 +1,173 @@
+//! The client, a web application using the `oauth2` crate to act on behalf of its user.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use actix_web::{
+    http::header,
+    middleware::Logger,
+    web::{self, Data},
+    App, HttpResponse, HttpServer,
+};
+use actix_three_party::{
+    AUTHORIZE_URL, CLIENT_ADDR, CLIENT_ID, CLIENT_SECRET, PROFILE_SCOPE, REDIRECT_URL, RESOURCE_URL,
+    TOKEN_URL,
+};
+use oauth2::basic::{BasicClient, BasicTokenResponse};
+use oauth2::reqwest::async_http_client;
+use oauth2::{
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, PkceCodeVerifier,
+    RedirectUrl, Scope, TokenResponse, TokenUrl,
+};
+
+/// The state of the single user of this client.
+#[derive(Default)]
+struct Session {
+    /// The pending authorization request.
+    pending: Option<(CsrfToken, PkceCodeVerifier)>,
+    token: Option<BasicTokenResponse>,
+}
+
+async fn index(session: Data<Mutex<Session>>) -> HttpResponse {
+    let token = match session.lock().unwrap().token.clone() {
+        None => {
+            return html("<p>Not authorized yet. <a href=\"/login\">Log in</a> to read the profile.</p>")
+        }
+        Some(token) => token,
+    };
+
+    let response = reqwest::Client::new()
+        .get(RESOURCE_URL)
+        .bearer_auth(token.access_token().secret())
+        .send()
+        .await;
+    let profile = match response {
+        Ok(response) if response.status().is_success() => response.text().await.unwrap_or_default(),
+        Ok(response) => format!("The resource server refused the token: {}", response.status()),
+        Err(err) => format!("The resource server is not reachable: {}", err),
+    };
+
+    html(&format!(
+        "<p>Used the token <code>{}</code> to read the profile:</p>
+        <blockquote>{}</blockquote>
+        <form action=\"/refresh\" method=\"post\"><button>Refresh token</button></form>",
+        token.access_token().secret(),
+        profile,
+    ))
+}
+
+async fn login(client: Data<BasicClient>, session: Data<Mutex<Session>>) -> HttpResponse {
+    let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+    let (url, csrf) = client
+        .authorize_url(CsrfToken::new_random)
+        .add_scope(Scope::new(PROFILE_SCOPE.to_owned()))
+        .set_pkce_challenge(challenge)
+        .url();
+
+    session.lock().unwrap().pending = Some((csrf, verifier));
+    redirect(url.as_str())
+}
+
+async fn callback(
+    query: web::Query<HashMap<String, String>>, client: Data<BasicClient>, session: Data<Mutex<Session>>,
+) -> HttpResponse {
+    if let Some(error) = query.get("error") {
+        return HttpResponse::BadRequest().body(format!("The authorization was denied: {}", error));
+    }
+
+    let (csrf, verifier) = match session.lock().unwrap().pending.take() {
+        None => return HttpResponse::BadRequest().body("No authorization was requested"),
+        Some(pending) => pending,
+    };
+    if query.get("state") != Some(csrf.secret()) {
+        return HttpResponse::BadRequest().body("The state does not match the request");
+    }
+    let code = match query.get("code") {
+        None => return HttpResponse::BadRequest().body("Missing code"),
+        Some(code) => AuthorizationCode::new(code.clone()),
+    };
+
+    let token = client
+        .exchange_code(code)
+        .set_pkce_verifier(verifier)
+        .request_async(async_http_client)
+        .await;
+    store(&session, token)
+}
+
+async fn refresh(client: Data<BasicClient>, session: Data<Mutex<Session>>) -> HttpResponse {
+    let refresh_token = session
+        .lock()
+        .unwrap()
+        .token
+        .as_ref()
+        .and_then(|token| token.refresh_token().cloned());
+    let refresh_token = match refresh_token {
+        None => return HttpResponse::BadRequest().body("No refresh token to use"),
+        Some(refresh_token) => refresh_token,
+    };
+
+    let token = client
+        .exchange_refresh_token(&refresh_token)
+        .request_async(async_http_client)
+        .await;
+    store(&session, token)
+}
+
+/// Keep the token, or show why the token request failed.
+fn store<E: std::error::Error>(
+    session: &Mutex<Session>, token: Result<BasicTokenResponse, E>,
+) -> HttpResponse {
+    match token {
+        Ok(token) => {
+            session.lock().unwrap().token = Some(token);
+            redirect("/")
+        }
+        Err(err) => {
+            HttpResponse::InternalServerError().body(format!("The token request failed: {}", err))
+        }
+    }
+}
+
+fn html(body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(format!("<html><body>{}</body></html>", body))
+}
+
+fn redirect(location: &str) -> HttpResponse {
+    HttpResponse::Found()
+        .append_header((header::LOCATION, location))
+        .finish()
+}
+
+#[actix_web::main]
+pub async fn main() -> std::io::Result<()> {
+    std::env::set_var("RUST_LOG", "client=info,actix_web=info");
+    env_logger::init();
+
+    // The secret is sent with HTTP Basic authentication, as the client was registered.
+    let client = BasicClient::new(
+        ClientId::new(CLIENT_ID.to_owned()),
+        Some(ClientSecret::new(CLIENT_SECRET.to_owned())),
+        AuthUrl::new(AUTHORIZE_URL.to_owned()).unwrap(),
+        Some(TokenUrl::new(TOKEN_URL.to_owned()).unwrap()),
+    )
+    .set_redirect_uri(RedirectUrl::new(REDIRECT_URL.to_owned()).unwrap());
+    let client = Data::new(client);
+    let session = Data::new(Mutex::new(Session::default()));
+
+    println!("Open http://{} in a browser to start.", CLIENT_ADDR);
+    HttpServer::new(move || {
+        App::new()
+            .app_data(client.clone())
+            .app_data(session.clone())
+            .wrap(Logger::default())
+            .route("/", web::get().to(index))
+            .route("/login", web::get().to(login))
+            .route("/callback", web::get().to(callback))
+            .route("/refresh", web::post().to(refresh))
+    })
+    .bind(CLIENT_ADDR)?
+    .run()
+    .await
+}
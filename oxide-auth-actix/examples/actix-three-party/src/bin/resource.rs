@@ -0,0 +1,39 @@
+//! The resource server, verifying the signed tokens of the authorization server on its own.
+use actix_web::{
+    middleware::Logger,
+    web::{self, ReqData},
+    App, HttpServer,
+};
+use actix_three_party::{profile_scope, SignedIssuer, RESOURCE_ADDR};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth_actix::{OAuthGuard, OAuthResponse, WebError};
+
+async fn profile(grant: ReqData<Grant>) -> Result<OAuthResponse, WebError> {
+    // The guard has already checked the token, requests only get here with a valid grant.
+    let body = format!(
+        "Profile of {}, read by {} until {}.",
+        grant.owner_id, grant.client_id, grant.until
+    );
+    Ok(OAuthResponse::ok().content_type("text/plain")?.body(&body))
+}
+
+#[actix_web::main]
+pub async fn main() -> std::io::Result<()> {
+    std::env::set_var("RUST_LOG", "resource=info,actix_web=info");
+    env_logger::init();
+
+    // The same key as the authorization server, so tokens are verified without asking it.
+    let guard =
+        OAuthGuard::new(SignedIssuer::shared(), vec![profile_scope()]).with_realm("actix-three-party");
+
+    HttpServer::new(move || {
+        App::new().wrap(Logger::default()).service(
+            web::resource("/profile")
+                .wrap(guard.clone())
+                .route(web::get().to(profile)),
+        )
+    })
+    .bind(RESOURCE_ADDR)?
+    .run()
+    .await
+}
@@ -0,0 +1,95 @@
+//! The configuration shared by the three processes of the example.
+//!
+//! In a real deployment the client only knows the public urls of the servers, its id and its
+//! secret. The signing key is known to the authorization and the resource server alone, the
+//! resource server uses it to verify tokens without asking the authorization server.
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::generator::{Assertion, AssertionKind};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, Issuer, RefreshedToken, TokenType};
+use oxide_auth::primitives::scope::Scope;
+
+type Time = DateTime<Utc>;
+
+pub const AUTHORIZATION_ADDR: &str = "localhost:8020";
+pub const RESOURCE_ADDR: &str = "localhost:8021";
+pub const CLIENT_ADDR: &str = "localhost:8022";
+
+pub const AUTHORIZE_URL: &str = "http://localhost:8020/authorize";
+pub const TOKEN_URL: &str = "http://localhost:8020/token";
+pub const RESOURCE_URL: &str = "http://localhost:8021/profile";
+pub const REDIRECT_URL: &str = "http://localhost:8022/callback";
+
+pub const CLIENT_ID: &str = "LocalClient";
+pub const CLIENT_SECRET: &str = "SecretSecret";
+
+/// The scope guarding the profile of the resource server.
+pub const PROFILE_SCOPE: &str = "profile";
+
+/// Never hardcode a key like this, generate it with `openssl rand` and keep it secret.
+const SIGNING_KEY: &[u8] = b"actix-three-party-example-signing-key";
+
+/// Signs tokens instead of storing them, so that the resource server verifies them on its own.
+///
+/// The `TokenSigner` of `oxide-auth` does not refresh tokens, since it can not revoke the used
+/// refresh token. This issuer refreshes anyway: a refresh token stays valid for a day, even after
+/// it has been used. Access tokens are short lived so that refreshing them can be tried out.
+pub struct SignedIssuer {
+    assertion: Assertion,
+    counter: u64,
+}
+
+impl SignedIssuer {
+    /// The issuer of the authorization server, or the verifier of the resource server.
+    pub fn shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(SignedIssuer {
+            assertion: Assertion::new(AssertionKind::HmacSha256, SIGNING_KEY),
+            counter: 0,
+        }))
+    }
+
+    fn sign(&mut self, tag: &str, mut grant: Grant, valid_for: Duration) -> Result<(String, Time), ()> {
+        self.counter += 1;
+        grant.until = Utc::now() + valid_for;
+        let token = self.assertion.tag(tag).sign(self.counter, &grant)?;
+        Ok((token, grant.until))
+    }
+}
+
+impl Issuer for SignedIssuer {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        let (token, until) = self.sign("token", grant.clone(), Duration::minutes(2))?;
+        let (refresh, _) = self.sign("refresh", grant, Duration::days(1))?;
+        Ok(IssuedToken {
+            token,
+            refresh: Some(refresh),
+            until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, _refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        // The refresh flow has already verified the refresh token with `recover_refresh`.
+        let issued = self.issue(grant)?;
+        Ok(RefreshedToken {
+            token: issued.token,
+            refresh: issued.refresh,
+            until: issued.until,
+            token_type: issued.token_type,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        Ok(self.assertion.tag("token").extract(token).ok())
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        Ok(self.assertion.tag("refresh").extract(token).ok())
+    }
+}
+
+pub fn profile_scope() -> Scope {
+    PROFILE_SCOPE.parse().unwrap()
+}
@@ -0,0 +1,186 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+        Method,
+    },
+    Error, HttpResponse,
+};
+use futures::future::{self, FutureExt, LocalBoxFuture, Ready};
+use oxide_auth::frontends::dev::Cors;
+
+/// Middleware allowing browsers to call the wrapped services from the origins of a [`Cors`]
+/// policy.
+///
+/// Meant for the token endpoint and other endpoints called by scripts of public clients, not for
+/// the authorization endpoint which is navigated to. Preflight requests are answered directly with
+/// `204 No Content`, all other responses receive the headers allowing their origin.
+///
+/// ```no_run
+/// use actix_web::{web, App, HttpResponse};
+/// use oxide_auth::frontends::dev::Cors;
+/// use oxide_auth::primitives::registrar::ClientMap;
+/// use oxide_auth_actix::AllowOrigins;
+///
+/// # let clients = ClientMap::new();
+/// let cors = AllowOrigins::new(Cors::from_clients(clients.clients()));
+/// let app = App::new().service(
+///     web::resource("/token")
+///         .wrap(cors)
+///         .route(web::post().to(HttpResponse::Ok)),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct AllowOrigins {
+    cors: Arc<Cors>,
+}
+
+/// The service created by an [`AllowOrigins`] middleware.
+pub struct AllowOriginsMiddleware<S> {
+    service: Rc<S>,
+    cors: Arc<Cors>,
+}
+
+impl AllowOrigins {
+    /// Allow the origins of the policy.
+    pub fn new(cors: Cors) -> Self {
+        AllowOrigins { cors: Arc::new(cors) }
+    }
+
+    /// The policy deciding which origins are allowed.
+    pub fn cors(&self) -> &Cors {
+        &self.cors
+    }
+}
+
+fn is_preflight(request: &ServiceRequest) -> bool {
+    request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+fn decorate(headers: &mut HeaderMap, cors_headers: Vec<(&'static str, String)>) {
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    for (name, value) in cors_headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AllowOrigins
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AllowOriginsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ok(AllowOriginsMiddleware {
+            service: Rc::new(service),
+            cors: self.cors.clone(),
+        })
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for AllowOriginsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let origin = request
+            .headers()
+            .get(header::ORIGIN)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let origin = match origin {
+            Some(origin) => origin,
+            None => {
+                return self
+                    .service
+                    .call(request)
+                    .map(|response| response.map(ServiceResponse::map_into_left_body))
+                    .boxed_local()
+            }
+        };
+
+        if is_preflight(&request) {
+            let mut response = HttpResponse::NoContent().finish();
+            decorate(response.headers_mut(), self.cors.preflight_headers(&origin));
+            let response = request.into_response(response).map_into_right_body();
+            return future::ok(response).boxed_local();
+        }
+
+        let cors_headers = self.cors.response_headers(&origin);
+        self.service
+            .call(request)
+            .map(move |response| {
+                let mut response = response?;
+                decorate(response.headers_mut(), cors_headers);
+                Ok(response.map_into_left_body())
+            })
+            .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App};
+
+    fn request(method: Method, origin: &str) -> test::TestRequest {
+        test::TestRequest::default()
+            .method(method)
+            .uri("/token")
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+    }
+
+    #[actix_rt::test]
+    async fn allows_origins() {
+        let mut cors = Cors::new();
+        cors.allow_origin(&"https://app.example/callback".parse().unwrap());
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/token")
+                    .wrap(AllowOrigins::new(cors))
+                    .route(web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let preflight = request(Method::OPTIONS, "https://app.example").to_request();
+        let response = test::call_service(&app, preflight).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let allowed = response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+        assert_eq!(allowed.unwrap(), "https://app.example");
+
+        let actual = request(Method::POST, "https://app.example").to_request();
+        let response = test::call_service(&app, actual).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let allowed = response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+        assert_eq!(allowed.unwrap(), "https://app.example");
+
+        let preflight = request(Method::OPTIONS, "https://evil.example").to_request();
+        let response = test::call_service(&app, preflight).await;
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
@@ -0,0 +1,209 @@
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpResponse, Responder, ResponseError,
+};
+use futures::future::{self, FutureExt, LocalBoxFuture, Ready};
+use oxide_auth::frontends::simple::endpoint::resource_flow;
+use oxide_auth::primitives::{issuer::Issuer, scope::Scope};
+
+use crate::{OAuthResource, WebError};
+
+/// Middleware requiring a valid bearer token on every request of the wrapped services.
+///
+/// Accepts tokens whose grant allows any of the configured scopes. The `Grant` of an accepted
+/// token is inserted into the request extensions where handlers pick it up with
+/// `web::ReqData<Grant>`. Other requests are answered directly with `401 Unauthorized`, or
+/// `403 Forbidden` if the token lacks the necessary scope, and a matching `WWW-Authenticate`
+/// header.
+///
+/// ```no_run
+/// # use std::sync::{Arc, Mutex};
+/// use actix_web::{web, App};
+/// use oxide_auth::primitives::{generator::RandomGenerator, grant::Grant, issuer::TokenMap};
+/// use oxide_auth_actix::OAuthGuard;
+///
+/// async fn me(grant: web::ReqData<Grant>) -> String {
+///     grant.owner_id.clone()
+/// }
+///
+/// let issuer = Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16))));
+/// let guard = OAuthGuard::new(issuer, vec!["profile".parse().unwrap()]);
+/// let app = App::new().service(web::resource("/me").wrap(guard).route(web::get().to(me)));
+/// ```
+#[derive(Clone)]
+pub struct OAuthGuard {
+    issuer: Arc<Mutex<dyn Issuer + Send>>,
+    scopes: Arc<[Scope]>,
+    realm: Option<Arc<str>>,
+}
+
+/// The service created by an [`OAuthGuard`].
+pub struct OAuthGuardMiddleware<S> {
+    service: Rc<S>,
+    guard: OAuthGuard,
+}
+
+impl OAuthGuard {
+    /// Accept tokens of the issuer which grant at least one of the scopes.
+    ///
+    /// The issuer is usually shared with the token endpoint of the authorization server.
+    pub fn new<I: Issuer + Send + 'static>(issuer: Arc<Mutex<I>>, scopes: Vec<Scope>) -> Self {
+        OAuthGuard {
+            issuer,
+            scopes: scopes.into(),
+            realm: None,
+        }
+    }
+
+    /// Name the protection space in the `realm` of the `WWW-Authenticate` challenges.
+    pub fn with_realm(self, realm: &str) -> Self {
+        OAuthGuard {
+            realm: Some(realm.into()),
+            ..self
+        }
+    }
+
+    /// The scopes of which a token must grant at least one.
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// Attach the grant to the request extensions, or reject it with the returned response.
+    fn protect(&self, request: &ServiceRequest) -> Option<HttpResponse> {
+        let resource = match OAuthResource::new(request.request()) {
+            Ok(resource) => resource,
+            Err(err) => return Some(err.error_response()),
+        };
+        let mut issuer = match self.issuer.lock() {
+            Ok(issuer) => issuer,
+            Err(_) => {
+                let err = WebError::InternalError(Some("Issuer lock poisoned".into()));
+                return Some(err.error_response());
+            }
+        };
+
+        let mut flow = resource_flow(&mut *issuer, &self.scopes);
+        if let Some(realm) = &self.realm {
+            flow.realm(realm);
+        }
+
+        match flow.execute(resource.into_request()) {
+            Ok(grant) => {
+                request.extensions_mut().insert(grant);
+                None
+            }
+            Err(Ok(response)) => Some(response.respond_to(request.request())),
+            Err(Err(err)) => Some(WebError::from(err).error_response()),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for OAuthGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = OAuthGuardMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ok(OAuthGuardMiddleware {
+            service: Rc::new(service),
+            guard: self.clone(),
+        })
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for OAuthGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        match self.guard.protect(&request) {
+            None => self
+                .service
+                .call(request)
+                .map(|response| response.map(ServiceResponse::map_into_left_body))
+                .boxed_local(),
+            Some(response) => {
+                let response = request.into_response(response).map_into_right_body();
+                future::ok(response).boxed_local()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+    use chrono::{Duration, Utc};
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::{Extensions, Grant};
+    use oxide_auth::primitives::issuer::TokenMap;
+
+    /// A guard requiring the `profile` scope, with a token granting it and one that does not.
+    fn guard() -> (OAuthGuard, String, String) {
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        let mut grant = Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "profile".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        };
+        let profile = issuer.issue(grant.clone()).unwrap().token;
+        grant.scope = "email".parse().unwrap();
+        let email = issuer.issue(grant).unwrap().token;
+
+        let guard = OAuthGuard::new(Arc::new(Mutex::new(issuer)), vec!["profile".parse().unwrap()]);
+        (guard, profile, email)
+    }
+
+    fn request(token: Option<&str>) -> test::TestRequest {
+        let request = test::TestRequest::get().uri("/");
+        match token {
+            Some(token) => request.insert_header((header::AUTHORIZATION, format!("Bearer {}", token))),
+            None => request,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn guards_resources() {
+        let (guard, profile, email) = guard();
+        let guard = guard.with_realm("example");
+        let app =
+            test::init_service(App::new().service(web::resource("/").wrap(guard).route(
+                web::get().to(|grant: web::ReqData<Grant>| async move { grant.owner_id.clone() }),
+            )))
+            .await;
+
+        let response = test::call_service(&app, request(Some(&profile)).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "Owner");
+
+        let response = test::call_service(&app, request(Some(&email)).to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = test::call_service(&app, request(None).to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let challenge = response.headers().get(header::WWW_AUTHENTICATE).unwrap();
+        assert_eq!(challenge, "Bearer realm=\"example\"");
+    }
+}
@@ -1,7 +1,8 @@
 //! Bindings and utilities for creating an oauth endpoint with actix.
 //!
 //! Use the provided methods to use code grant methods in an asynchronous fashion, or use an
-//! `AsActor<_>` to create an actor implementing endpoint functionality via messages.
+//! `AsActor<_>` to create an actor implementing endpoint functionality via messages. Resources
+//! are best protected by wrapping their services in an [`OAuthGuard`].
 #![warn(missing_docs)]
 
 use actix::{MailboxError, Message};
@@ -9,24 +10,39 @@ use actix_web::{
     body::BoxBody,
     dev::Payload,
     http::{
-        header::{self, HeaderMap, InvalidHeaderValue},
+        header::{self, HeaderMap, HeaderName, InvalidHeaderValue},
         StatusCode,
     },
-    web::Form,
-    web::Query,
-    FromRequest, HttpRequest, HttpResponse, HttpResponseBuilder, Responder, ResponseError,
+    web::{Bytes, BytesMut, Form, Query},
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder, Responder, ResponseError,
+};
+use futures::{
+    future::{self, FutureExt, LocalBoxFuture, Ready},
+    stream::{BoxStream, Stream},
+    StreamExt,
 };
-use futures::future::{self, FutureExt, LocalBoxFuture, Ready};
 use oxide_auth::{
-    endpoint::{Endpoint, NormalizedParameter, OAuthError, QueryParameter, WebRequest, WebResponse},
-    frontends::simple::endpoint::Error,
+    endpoint::{
+        Endpoint, NormalizedParameter, OAuthError, QueryParameter, RequestContext, WebRequest,
+        WebResponse,
+    },
+    frontends::{
+        dev::{Limits, PeerCertificate, TrustedProxy},
+        simple::endpoint::Error,
+    },
 };
 use std::{borrow::Cow, convert::TryFrom, error, fmt};
 use url::Url;
 
+mod cors;
+mod guard;
 mod operations;
+mod render;
 
+pub use cors::{AllowOrigins, AllowOriginsMiddleware};
+pub use guard::{OAuthGuard, OAuthGuardMiddleware};
 pub use operations::{Authorize, Refresh, Resource, Token, ClientCredentials};
+pub use render::{RenderErrors, RenderErrorsMiddleware};
 
 /// Describes an operation that can be performed in the presence of an `Endpoint`
 ///
@@ -111,9 +127,24 @@ pub struct OAuthMessage<Operation, Extras>(Operation, Extras);
 /// Type implementing `WebRequest` as well as `FromRequest` for use in route handlers
 ///
 /// This type consumes the body of the HttpRequest upon extraction, so be careful not to use it in
-/// places you also expect an application payload
+/// places you also expect an application payload. Bodies are read as a urlencoded form, or as a
+/// JSON object if the `Content-Type` is `application/json`.
+///
+/// The certificate of a mutual TLS connection is found in the connection data, where it is put by
+/// a callback of `HttpServer::on_connect`, or in the request extensions. When a [`TrustedProxy`]
+/// in the app data trusts client certificates, it is instead read from the
+/// `X-Forwarded-Client-Cert` header. Similarly, the [`url`](OAuthRequest::url) of the request
+/// honors the forwarding headers only behind a trusted proxy, unlike `HttpRequest::connection_info`.
+///
+/// The [`context`](OAuthRequest::context) of the request, passed on to solicitors and scope
+/// policies, starts from a [`RequestContext`] in the request extensions, which a middleware can
+/// insert with the result of a geo IP lookup or a device cookie. The address of the peer and the
+/// `User-Agent` header fill in what the extension does not.
 pub struct OAuthRequest {
     auth: Option<String>,
+    certificate: Option<PeerCertificate>,
+    context: RequestContext,
+    url: Option<Url>,
     query: Option<NormalizedParameter>,
     body: Option<NormalizedParameter>,
 }
@@ -136,16 +167,23 @@ impl OAuthResponse {
 /// request upon extraction
 pub struct OAuthResource {
     auth: Option<String>,
+    certificate: Option<PeerCertificate>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 /// Type implementing `WebResponse` and `Responder` for use in route handlers
+///
+/// Solicitors can add arbitrary headers and cookies, or stream the body, for example to serve a
+/// rendered consent page while starting a session.
 pub struct OAuthResponse {
     status: StatusCode,
     headers: HeaderMap,
     body: Option<String>,
+    stream: Option<BodyStream>,
 }
 
+struct BodyStream(BoxStream<'static, Result<Bytes, Box<dyn error::Error>>>);
+
 #[derive(Debug)]
 /// The error type for Oxide Auth operations
 pub enum WebError {
@@ -170,6 +208,9 @@ pub enum WebError {
     /// The Authorization header was invalid
     Authorization,
 
+    /// The request exceeded the configured size limits
+    TooLarge,
+
     /// Processing part of the request was canceled
     Canceled,
 
@@ -182,15 +223,46 @@ pub enum WebError {
 
 impl OAuthRequest {
     /// Create a new OAuthRequest from an HttpRequest and Payload
+    /// Requests exceeding the `Limits` of the app data, or the default limits, are rejected with
+    /// `WebError::TooLarge` before their parameters are parsed.
     pub async fn new(req: HttpRequest, mut payload: Payload) -> Result<Self, WebError> {
+        let limits = req.app_data::<Limits>().copied().unwrap_or_default();
+        if !limits.allow_query(req.query_string()) {
+            return Err(WebError::TooLarge);
+        }
+        if content_length(&req).is_some_and(|length| !limits.allow_length(length)) {
+            return Err(WebError::TooLarge);
+        }
+
         let query = Query::extract(&req)
             .await
             .ok()
             .map(|q: Query<NormalizedParameter>| q.into_inner());
-        let body = Form::from_request(&req, &mut payload)
-            .await
-            .ok()
-            .map(|b: Form<NormalizedParameter>| b.into_inner());
+
+        // Bodies without a length are only read up to the limit.
+        let mut bytes = BytesMut::new();
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk.map_err(|_| WebError::Body)?;
+            if !limits.allow_length(bytes.len() + chunk.len()) {
+                return Err(WebError::TooLarge);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let body = if is_json(&req) {
+            match NormalizedParameter::from_json(&bytes) {
+                Some(body) if !limits.allow_parameters(&body) => return Err(WebError::TooLarge),
+                body => body,
+            }
+        } else {
+            if !limits.allow_form(&bytes) {
+                return Err(WebError::TooLarge);
+            }
+            Form::from_request(&req, &mut Payload::from(bytes.freeze()))
+                .await
+                .ok()
+                .map(|b: Form<NormalizedParameter>| b.into_inner())
+        };
 
         let mut all_auth = req.headers().get_all(header::AUTHORIZATION);
         let optional = all_auth.next();
@@ -201,7 +273,14 @@ impl OAuthRequest {
             optional.and_then(|hv| hv.to_str().ok().map(str::to_owned))
         };
 
-        Ok(OAuthRequest { auth, query, body })
+        Ok(OAuthRequest {
+            auth,
+            certificate: peer_certificate(&req),
+            context: request_context(&req),
+            url: request_url(&req),
+            query,
+            body,
+        })
     }
 
     /// Fetch the authorization header from the request
@@ -209,6 +288,29 @@ impl OAuthRequest {
         self.auth.as_deref()
     }
 
+    /// The certificate the client presented over mutual TLS
+    pub fn client_certificate(&self) -> Option<&PeerCertificate> {
+        self.certificate.as_ref()
+    }
+
+    /// The context of the request, such as the remote address
+    pub fn context(&self) -> &RequestContext {
+        &self.context
+    }
+
+    /// Fetch the context mutably, to add signals before passing the request to a flow
+    pub fn context_mut(&mut self) -> &mut RequestContext {
+        &mut self.context
+    }
+
+    /// The url under which the client reached the server
+    ///
+    /// Compare redirect targets and generate metadata against it rather than against the address
+    /// the server is bound to.
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
     /// Fetch the query for this request
     pub fn query(&self) -> Option<&NormalizedParameter> {
         self.query.as_ref()
@@ -225,6 +327,86 @@ impl OAuthRequest {
     }
 }
 
+fn content_length(req: &HttpRequest) -> Option<usize> {
+    req.headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+fn peer_certificate(req: &HttpRequest) -> Option<PeerCertificate> {
+    if let Some(certificate) = req.conn_data::<PeerCertificate>() {
+        return Some(certificate.clone());
+    }
+    if let Some(certificate) = req.extensions().get::<PeerCertificate>() {
+        return Some(certificate.clone());
+    }
+
+    let trusted = req.app_data::<TrustedProxy>().copied().unwrap_or_default();
+    if !trusted.client_certificate {
+        return None;
+    }
+
+    req.headers()
+        .get(PeerCertificate::FORWARDED_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(PeerCertificate::from_forwarded)
+}
+
+fn request_context(req: &HttpRequest) -> RequestContext {
+    let mut context = req
+        .extensions()
+        .get::<RequestContext>()
+        .cloned()
+        .unwrap_or_default();
+    if context.remote_addr.is_none() {
+        context.remote_addr = req.peer_addr().map(|addr| addr.ip().to_string());
+    }
+    if context.user_agent.is_none() {
+        context.user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+    }
+    if context.idempotency_key.is_none() {
+        context.idempotency_key = req
+            .headers()
+            .get("Idempotency-Key")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+    }
+
+    context
+}
+
+fn request_url(req: &HttpRequest) -> Option<Url> {
+    let proxy = req.app_data::<TrustedProxy>().copied().unwrap_or_default();
+    let config = req.app_config();
+    let headers = req.headers();
+    let host = req
+        .uri()
+        .authority()
+        .map(|authority| authority.as_str())
+        .or_else(|| headers.get(header::HOST).and_then(|value| value.to_str().ok()))
+        .unwrap_or_else(|| config.host());
+    let path_and_query = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    let scheme = if config.secure() { "https" } else { "http" };
+
+    proxy.request_url(scheme, host, path_and_query, |name| {
+        headers.get(name).and_then(|value| value.to_str().ok())
+    })
+}
+
+/// Whether the body is sent as `application/json`, possibly with parameters such as a charset.
+fn is_json(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
 impl OAuthResource {
     /// Create a new OAuthResource from an HttpRequest
     pub fn new(req: &HttpRequest) -> Result<Self, WebError> {
@@ -237,15 +419,26 @@ impl OAuthResource {
             optional.and_then(|hv| hv.to_str().ok().map(str::to_owned))
         };
 
-        Ok(OAuthResource { auth })
+        Ok(OAuthResource {
+            auth,
+            certificate: peer_certificate(req),
+        })
+    }
+
+    /// The certificate the client presented over mutual TLS
+    pub fn client_certificate(&self) -> Option<&PeerCertificate> {
+        self.certificate.as_ref()
     }
 
     /// Turn this OAuthResource into an OAuthRequest for processing
     pub fn into_request(self) -> OAuthRequest {
         OAuthRequest {
+            url: None,
             query: None,
             body: None,
             auth: self.auth,
+            certificate: self.certificate,
+            context: RequestContext::default(),
         }
     }
 }
@@ -257,6 +450,7 @@ impl OAuthResponse {
             status: StatusCode::OK,
             headers: HeaderMap::new(),
             body: None,
+            stream: None,
         }
     }
 
@@ -270,6 +464,35 @@ impl OAuthResponse {
     /// Set the bodyfor the response
     pub fn body(mut self, body: &str) -> Self {
         self.body = Some(body.to_owned());
+        self.stream = None;
+        self
+    }
+
+    /// Set a header on the response, replacing previous values of the same name
+    pub fn header(mut self, name: HeaderName, value: &str) -> Result<Self, WebError> {
+        self.headers.insert(name, TryFrom::try_from(value)?);
+        Ok(self)
+    }
+
+    /// Add a `Set-Cookie` header to the response, keeping previously added cookies
+    pub fn cookie(mut self, cookie: &str) -> Result<Self, WebError> {
+        self.headers
+            .append(header::SET_COOKIE, TryFrom::try_from(cookie)?);
+        Ok(self)
+    }
+
+    /// Stream the body of the response
+    ///
+    /// Replaces a body set previously. Bodies set by the flows in turn replace the stream. The
+    /// stream must be `Send` as responses are passed between actors.
+    pub fn streaming_body<S, E>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Box<dyn error::Error>> + 'static,
+    {
+        let stream = stream.map(|chunk| chunk.map_err(Into::into));
+        self.body = None;
+        self.stream = Some(BodyStream(stream.boxed()));
         self
     }
 }
@@ -302,6 +525,10 @@ impl WebRequest for OAuthRequest {
     fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
         Ok(self.auth.as_deref().map(Cow::Borrowed))
     }
+
+    fn context(&mut self) -> Option<Cow<'_, RequestContext>> {
+        Some(Cow::Borrowed(&self.context))
+    }
 }
 
 impl WebResponse for OAuthResponse {
@@ -332,8 +559,16 @@ impl WebResponse for OAuthResponse {
         Ok(())
     }
 
+    fn forbidden(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = StatusCode::FORBIDDEN;
+        self.headers
+            .insert(header::WWW_AUTHENTICATE, TryFrom::try_from(kind)?);
+        Ok(())
+    }
+
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.body = Some(text.to_owned());
+        self.stream = None;
         self.headers
             .insert(header::CONTENT_TYPE, TryFrom::try_from("text/plain")?);
         Ok(())
@@ -341,10 +576,26 @@ impl WebResponse for OAuthResponse {
 
     fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
         self.body = Some(json.to_owned());
+        self.stream = None;
         self.headers
             .insert(header::CONTENT_TYPE, TryFrom::try_from("application/json")?);
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| WebError::InternalError(Some(err.to_string())))?;
+        self.headers.insert(name, TryFrom::try_from(value)?);
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.status = StatusCode::TOO_MANY_REQUESTS;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
 }
 
 impl<Operation, Extras> Message for OAuthMessage<Operation, Extras>
@@ -378,17 +629,23 @@ impl Responder for OAuthResponse {
     fn respond_to(self, _: &HttpRequest) -> HttpResponse {
         let mut builder = HttpResponseBuilder::new(self.status);
         for (k, v) in self.headers.into_iter() {
-            builder.insert_header((k, v.to_owned()));
+            builder.append_header((k, v.to_owned()));
         }
 
-        if let Some(body) = self.body {
-            builder.body(body)
-        } else {
-            builder.finish()
+        match (self.stream, self.body) {
+            (Some(BodyStream(stream)), _) => builder.streaming(stream),
+            (None, Some(body)) => builder.body(body),
+            (None, None) => builder.finish(),
         }
     }
 }
 
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BodyStream")
+    }
+}
+
 impl From<OAuthResource> for OAuthRequest {
     fn from(o: OAuthResource) -> Self {
         o.into_request()
@@ -401,6 +658,7 @@ impl Default for OAuthResponse {
             status: StatusCode::OK,
             headers: HeaderMap::new(),
             body: None,
+            stream: None,
         }
     }
 }
@@ -445,6 +703,7 @@ impl fmt::Display for WebError {
             WebError::Query => write!(f, "No query present"),
             WebError::Body => write!(f, "No body present"),
             WebError::Authorization => write!(f, "Request has invalid Authorization headers"),
+            WebError::TooLarge => write!(f, "Request exceeds the size limits"),
             WebError::Canceled => write!(f, "Operation canceled"),
             WebError::Mailbox => write!(f, "An actor's mailbox was full"),
             WebError::InternalError(None) => write!(f, "An internal server error occured"),
@@ -461,6 +720,7 @@ impl error::Error for WebError {
             WebError::Encoding
             | WebError::Form
             | WebError::Authorization
+            | WebError::TooLarge
             | WebError::Query
             | WebError::Body
             | WebError::Canceled
@@ -471,5 +731,149 @@ impl error::Error for WebError {
 }
 
 impl ResponseError for WebError {
-    // Default to 500 for now
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebError::Endpoint(OAuthError::DenySilently)
+            | WebError::Endpoint(OAuthError::BadRequest)
+            | WebError::Encoding
+            | WebError::Form
+            | WebError::Query
+            | WebError::Body
+            | WebError::Authorization => StatusCode::BAD_REQUEST,
+            WebError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::to_bytes, test::TestRequest};
+
+    async fn extract(request: TestRequest) -> Result<OAuthRequest, WebError> {
+        let limits = Limits {
+            body: 32,
+            parameters: 2,
+            ..Limits::default()
+        };
+        let (req, payload) = request.app_data(limits).to_http_parts();
+        OAuthRequest::new(req, payload).await
+    }
+
+    fn form(body: &'static str) -> TestRequest {
+        TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload(body)
+    }
+
+    #[actix_rt::test]
+    async fn rejects_large_requests() {
+        let request = extract(form("a=1&b=2")).await.unwrap();
+        assert_eq!(
+            request.body().and_then(|b| b.unique_value("b")).as_deref(),
+            Some("2")
+        );
+
+        let request = form("grant_type=refresh_token&refresh_token=abcdefghijklmnop");
+        assert!(matches!(extract(request).await, Err(WebError::TooLarge)));
+        assert!(matches!(
+            extract(form("a=1&b=2&c=3")).await,
+            Err(WebError::TooLarge)
+        ));
+
+        let request = TestRequest::get().uri("/authorize?a=1&b=2&c=3");
+        assert!(matches!(extract(request).await, Err(WebError::TooLarge)));
+    }
+
+    #[actix_rt::test]
+    async fn reads_request_context() {
+        let request = TestRequest::get()
+            .peer_addr("192.0.2.1:4000".parse().unwrap())
+            .insert_header((header::USER_AGENT, "curl/8.0"))
+            .insert_header(("Idempotency-Key", "retry"))
+            .to_http_request();
+        request.extensions_mut().insert(RequestContext {
+            geo: Some("NL".to_owned()),
+            ..RequestContext::default()
+        });
+
+        let request = OAuthRequest::new(request, Payload::None).await.unwrap();
+        assert_eq!(request.context().remote_addr.as_deref(), Some("192.0.2.1"));
+        assert_eq!(request.context().user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!(request.context().geo.as_deref(), Some("NL"));
+        assert_eq!(request.context().idempotency_key.as_deref(), Some("retry"));
+    }
+
+    #[actix_rt::test]
+    async fn reads_forwarded_certificates() {
+        let cert = "-----BEGIN%20CERTIFICATE-----%0AAAECAwQF%0A-----END%20CERTIFICATE-----";
+        let forwarded = || {
+            TestRequest::get().insert_header((
+                PeerCertificate::FORWARDED_HEADER,
+                format!("Hash=abc;Cert=\"{}\"", cert),
+            ))
+        };
+
+        let request = extract(forwarded()).await.unwrap();
+        assert!(request.client_certificate().is_none());
+
+        let trusted = TrustedProxy {
+            client_certificate: true,
+            ..TrustedProxy::default()
+        };
+        let request = extract(forwarded().app_data(trusted)).await.unwrap();
+        let certificate = request.client_certificate().unwrap();
+        assert_eq!(certificate.der(), &[0, 1, 2, 3, 4, 5]);
+    }
+
+    #[actix_rt::test]
+    async fn reconstructs_forwarded_url() {
+        let forwarded = || {
+            TestRequest::get()
+                .uri("/authorize?state=a")
+                .insert_header((header::HOST, "127.0.0.1:8020"))
+                .insert_header((header::FORWARDED, "proto=https;host=auth.example"))
+        };
+
+        let request = extract(forwarded()).await.unwrap();
+        assert_eq!(
+            request.url().map(Url::as_str),
+            Some("http://127.0.0.1:8020/authorize?state=a")
+        );
+
+        let trusted = TrustedProxy {
+            forwarded: true,
+            ..TrustedProxy::default()
+        };
+        let request = extract(forwarded().app_data(trusted)).await.unwrap();
+        assert_eq!(
+            request.url().map(Url::as_str),
+            Some("https://auth.example/authorize?state=a")
+        );
+    }
+
+    #[actix_rt::test]
+    async fn sets_headers_cookies_and_streams() {
+        let chunks = ["<html>", "consent", "</html>"].map(|chunk| Ok::<_, WebError>(Bytes::from(chunk)));
+        let response = OAuthResponse::ok()
+            .content_type("text/html")
+            .unwrap()
+            .header(header::X_FRAME_OPTIONS, "DENY")
+            .unwrap()
+            .cookie("session=a; HttpOnly")
+            .unwrap()
+            .cookie("csrf=b")
+            .unwrap()
+            .streaming_body(futures::stream::iter(chunks))
+            .respond_to(&TestRequest::get().to_http_request());
+
+        let headers = response.headers();
+        assert_eq!(headers.get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(headers.get_all(header::SET_COOKIE).count(), 2);
+        assert!(OAuthResponse::ok().cookie("session=\n").is_err());
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"<html>consent</html>");
+    }
 }
@@ -0,0 +1,183 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_web::{
+    body::{self, EitherBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderValue},
+    Error, HttpResponse,
+};
+use futures::future::{self, FutureExt, LocalBoxFuture, Ready};
+use oxide_auth::frontends::dev::{ErrorInfo, RenderError};
+
+/// Middleware rendering the bodies of error responses with a [`RenderError`].
+///
+/// Applies to all responses of the wrapped services with a client or server error status, those
+/// produced by the flows as well as a [`WebError`] returned from a handler. The status and all
+/// headers other than the content type are kept, in particular the `WWW-Authenticate` header.
+///
+/// ```no_run
+/// use actix_web::{web, App, HttpResponse};
+/// use oxide_auth::frontends::dev::{ErrorInfo, RenderedError};
+/// use oxide_auth_actix::RenderErrors;
+///
+/// let branded = RenderErrors::new(|error: &ErrorInfo| {
+///     let code = error.error.as_deref().unwrap_or("server_error");
+///     Some(RenderedError::Html(format!("<h1>Sign in failed</h1><p>{}</p>", code)))
+/// });
+/// let app = App::new().service(
+///     web::resource("/authorize")
+///         .wrap(branded)
+///         .route(web::get().to(HttpResponse::Ok)),
+/// );
+/// ```
+///
+/// [`WebError`]: crate::WebError
+#[derive(Clone)]
+pub struct RenderErrors {
+    renderer: Arc<dyn RenderError>,
+}
+
+/// The service created by a [`RenderErrors`] middleware.
+pub struct RenderErrorsMiddleware<S> {
+    service: Rc<S>,
+    renderer: Arc<dyn RenderError>,
+}
+
+impl RenderErrors {
+    /// Render error bodies with the renderer.
+    pub fn new<R: RenderError + 'static>(renderer: R) -> Self {
+        RenderErrors {
+            renderer: Arc::new(renderer),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RenderErrors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RenderErrorsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ok(RenderErrorsMiddleware {
+            service: Rc::new(service),
+            renderer: self.renderer.clone(),
+        })
+    }
+}
+
+async fn render<B: MessageBody>(renderer: &dyn RenderError, response: HttpResponse<B>) -> HttpResponse {
+    let (mut response, body) = response.into_parts();
+    let bytes = match body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => return response.set_body(body::BoxBody::new(())),
+    };
+
+    let status = response.status().as_u16();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    let error = if is_json {
+        ErrorInfo::from_json(status, &bytes)
+    } else {
+        ErrorInfo::new(status)
+    };
+
+    match renderer.render(&error) {
+        Some(rendered) => {
+            let (content_type, body) = rendered.into_body(&error);
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            response.set_body(body::BoxBody::new(body))
+        }
+        None => response.set_body(body::BoxBody::new(bytes)),
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for RenderErrorsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let renderer = self.renderer.clone();
+        let response = self.service.call(request);
+        async move {
+            let response = response.await?;
+            let status = response.status();
+            if !status.is_client_error() && !status.is_server_error() {
+                return Ok(response.map_into_left_body());
+            }
+
+            let (request, response) = response.into_parts();
+            let response = render(&*renderer, response).await;
+            Ok(ServiceResponse::new(request, response).map_into_right_body())
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OAuthResponse, WebError};
+    use actix_web::{http::StatusCode, test, web, App};
+    use oxide_auth::frontends::dev::{OAuthError, RenderedError, WebResponse};
+
+    async fn invalid_grant() -> Result<OAuthResponse, WebError> {
+        let mut response = OAuthResponse::ok();
+        response.client_error()?;
+        response.body_json(r#"{"error":"invalid_grant"}"#)?;
+        Ok(response)
+    }
+
+    async fn denied() -> Result<HttpResponse, WebError> {
+        Err(OAuthError::DenySilently.into())
+    }
+
+    #[actix_rt::test]
+    async fn renders_error_bodies() {
+        let renderer = RenderErrors::new(|error: &ErrorInfo| {
+            let code = error.error.clone().unwrap_or_default();
+            Some(RenderedError::Text(format!("{} {}", error.status, code)))
+        });
+        let app = test::init_service(
+            App::new().service(
+                web::scope("")
+                    .wrap(renderer)
+                    .route("/token", web::get().to(invalid_grant))
+                    .route("/authorize", web::get().to(denied))
+                    .route("/", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let request = test::TestRequest::get().uri("/token").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(test::read_body(response).await, "400 invalid_grant");
+
+        let request = test::TestRequest::get().uri("/authorize").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(test::read_body(response).await, "400 ");
+
+        let request = test::TestRequest::get().uri("/").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,102 @@
+//! Clients, codes and tokens stored in the SQL tables of `oxide-auth-db`.
+//!
+//! The tables are shared by all SQL stores, so this also administers the Postgres and SQLite
+//! databases of the `SqlStore` and `SeaOrmStore`.
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::issuer::Issuer;
+use oxide_auth::primitives::registrar::EncodedClient;
+use oxide_auth_db::db_service::diesel_store::DieselStore;
+use oxide_auth_db::db_service::stored::StoredClient;
+use oxide_auth_db::db_service::transfer::{Export, Import, Record};
+
+use crate::{AdminStore, Revoked};
+
+macro_rules! admin_store {
+    ($connection:ty) => {
+        impl AdminStore for DieselStore<$connection> {
+            fn clients(&self) -> anyhow::Result<Vec<EncodedClient>> {
+                let mut clients = Vec::new();
+                for record in self.export()? {
+                    if let Record::Client(client) = record? {
+                        clients.push(client.into_encoded()?);
+                    }
+                }
+                Ok(clients)
+            }
+
+            fn save_client(&mut self, client: EncodedClient) -> anyhow::Result<()> {
+                self.import(Record::Client(StoredClient::from_encoded(&client)?))
+            }
+
+            fn revoke_token(&mut self, token: &str) -> anyhow::Result<Revoked> {
+                let revoked = DieselStore::<$connection>::revoke_token(self, token)?;
+                Ok(Revoked::Deleted(usize::from(revoked)))
+            }
+
+            fn revoke_client_tokens(&mut self, client_id: &str) -> anyhow::Result<Revoked> {
+                let failed = || anyhow::anyhow!("Failed to revoke the grants of {}", client_id);
+                let codes = Authorizer::revoke_client(self, client_id).map_err(|()| failed())?;
+                let tokens = Issuer::revoke_client(self, client_id).map_err(|()| failed())?;
+                Ok(Revoked::Deleted(codes + tokens))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "diesel-postgres")]
+admin_store!(::diesel::PgConnection);
+
+#[cfg(feature = "diesel-sqlite")]
+admin_store!(::diesel::SqliteConnection);
+
+#[cfg(all(test, feature = "diesel-sqlite"))]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use oxide_auth::primitives::grant::{Extensions, Grant};
+    use oxide_auth::primitives::registrar::{Client, Registrar};
+    use oxide_auth_db::db_service::PoolConfig;
+
+    use crate::Admin;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn disables_clients() {
+        // Every connection would see its own in-memory database.
+        let mut store =
+            DieselStore::<::diesel::SqliteConnection>::new(":memory:", PoolConfig::with_max_size(1))
+                .unwrap();
+        store.migrate().unwrap();
+        store.authorize(grant()).unwrap();
+        let issued = store.issue(grant()).unwrap();
+
+        let mut admin = Admin::new(store.clone());
+        let client = Client::builder("Client")
+            .redirect_uri("https://client.example/endpoint".parse::<url::Url>().unwrap())
+            .default_scope("default".parse().unwrap())
+            .passphrase(b"secret")
+            .build()
+            .unwrap();
+        admin.create(client).unwrap();
+        let secret = admin.rotate_secret("Client").unwrap();
+        assert!(store.check("Client", Some(secret.as_bytes())).is_ok());
+
+        assert_eq!(admin.disable("Client").unwrap(), Revoked::Deleted(2));
+        assert!(admin.list().unwrap()[0].disabled);
+        assert!(store.check("Client", Some(secret.as_bytes())).is_err());
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+
+        admin.enable("Client").unwrap();
+        assert!(store.check("Client", Some(secret.as_bytes())).is_ok());
+    }
+}
@@ -0,0 +1,315 @@
+//! Administration of the clients and tokens of a deployment backed by `oxide-auth-db`.
+//!
+//! Operators register clients, disable them, rotate their secrets and revoke tokens without going
+//! through the authorization server and without writing to the keys or tables of a backend by hand.
+//! [`Admin`] implements these operations once on top of an [`AdminStore`], which is implemented
+//! for the backends selected by the features of this crate:
+//!
+//! * `redis` (default) for the `RedisDataSource`. Redis only stores clients, revoking tokens
+//!   announces the revocation to the caches of all replicas instead.
+//! * `sled` (default) for the `SledStore`, which also stores codes and tokens.
+//! * `diesel-postgres` and `diesel-sqlite` for the `DieselStore`. All SQL stores of `oxide-auth-db`
+//!   share their tables, so these also administer the Postgres and SQLite databases of the
+//!   `SqlStore` and `SeaOrmStore`. Their MySQL databases are not supported.
+//!
+//! The `cli` feature builds the `oxide-auth-admin` binary offering the same operations.
+//!
+//! Disabled clients stay stored but neither authenticate nor bind a redirect uri, so that they can
+//! be enabled again later. Disabling a client also revokes its codes and tokens.
+//!
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! use oxide_auth::primitives::registrar::Client;
+//! use oxide_auth_admin::{Admin, MemoryStore};
+//!
+//! let mut admin = Admin::new(MemoryStore::default());
+//! let client = Client::builder("LocalClient")
+//!     .redirect_uri("http://localhost:8021/endpoint".parse::<url::Url>()?)
+//!     .default_scope("profile".parse().unwrap())
+//!     .passphrase(b"initial secret")
+//!     .build()?;
+//! admin.create(client)?;
+//!
+//! let secret = admin.rotate_secret("LocalClient")?;
+//! admin.disable("LocalClient")?;
+//! assert!(admin.list()?[0].disabled);
+//! # let _ = secret;
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use oxide_auth::primitives::registrar::{Argon2, Client, ClientType, EncodedClient, PasswordPolicy};
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "sled")]
+mod sled;
+
+#[cfg(any(feature = "diesel-postgres", feature = "diesel-sqlite"))]
+mod diesel;
+
+/// Length in bytes of generated client secrets.
+const SECRET_LENGTH: usize = 32;
+
+/// Access to the stored clients and tokens of a backend.
+pub trait AdminStore {
+    /// All clients of the backend.
+    fn clients(&self) -> anyhow::Result<Vec<EncodedClient>>;
+
+    /// The client with the id, if it is stored.
+    fn client(&self, client_id: &str) -> anyhow::Result<Option<EncodedClient>> {
+        let clients = self.clients()?;
+        Ok(clients.into_iter().find(|client| client.client_id == client_id))
+    }
+
+    /// Insert or replace a client, keeping its passphrase as encoded.
+    fn save_client(&mut self, client: EncodedClient) -> anyhow::Result<()>;
+
+    /// Revoke an access or refresh token.
+    fn revoke_token(&mut self, token: &str) -> anyhow::Result<Revoked>;
+
+    /// Revoke all codes and tokens issued to a client.
+    fn revoke_client_tokens(&mut self, client_id: &str) -> anyhow::Result<Revoked>;
+}
+
+/// The outcome of a revocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Revoked {
+    /// This many stored codes and tokens were deleted.
+    Deleted(usize),
+
+    /// The backend does not store tokens, the revocation was announced to the caches of all
+    /// replicas instead.
+    Announced,
+}
+
+/// Administrative operations on the clients and tokens of a backend.
+pub struct Admin<S> {
+    store: S,
+    password_policy: Arc<dyn PasswordPolicy>,
+}
+
+/// Clients and revoked tokens kept in memory, for tests and examples.
+#[derive(Default)]
+pub struct MemoryStore {
+    clients: BTreeMap<String, EncodedClient>,
+    revoked: Vec<String>,
+}
+
+impl<S: AdminStore> Admin<S> {
+    /// Administrate a backend whose passphrases are encoded with the default `Argon2` policy.
+    pub fn new(store: S) -> Self {
+        Admin {
+            store,
+            password_policy: Arc::new(Argon2::default()),
+        }
+    }
+
+    /// Encode passphrases with the policy of the authorization server instead.
+    pub fn with_password_policy<P: PasswordPolicy + 'static>(mut self, policy: P) -> Self {
+        self.password_policy = Arc::new(policy);
+        self
+    }
+
+    /// The administrated backend.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Register a new client, failing if its id is already taken.
+    pub fn create(&mut self, client: Client) -> anyhow::Result<()> {
+        let encoded = client.encode(&*self.password_policy);
+        if self.store.client(&encoded.client_id)?.is_some() {
+            anyhow::bail!("The client {} already exists", encoded.client_id);
+        }
+
+        self.store.save_client(encoded)
+    }
+
+    /// All clients, ordered by their id.
+    pub fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let mut clients = self.store.clients()?;
+        clients.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        Ok(clients)
+    }
+
+    /// Disable a client and revoke everything issued to it.
+    pub fn disable(&mut self, client_id: &str) -> anyhow::Result<Revoked> {
+        let mut client = self.existing(client_id)?;
+        client.disabled = true;
+        self.store.save_client(client)?;
+        self.store.revoke_client_tokens(client_id)
+    }
+
+    /// Enable a previously disabled client.
+    pub fn enable(&mut self, client_id: &str) -> anyhow::Result<()> {
+        let mut client = self.existing(client_id)?;
+        client.disabled = false;
+        self.store.save_client(client)
+    }
+
+    /// Replace the secret of a confidential client with a generated one, which is returned.
+    ///
+    /// Tokens issued before stay valid, revoke them separately if the old secret was leaked.
+    pub fn rotate_secret(&mut self, client_id: &str) -> anyhow::Result<String> {
+        let mut client = self.existing(client_id)?;
+        if let ClientType::Public = client.encoded_client {
+            anyhow::bail!("The client {} is public and has no secret", client_id);
+        }
+
+        let secret = generate_secret();
+        client.encoded_client = ClientType::Confidential {
+            passdata: self.password_policy.store(client_id, secret.as_bytes()),
+        };
+        self.store.save_client(client)?;
+        Ok(secret)
+    }
+
+    /// Revoke an access or refresh token.
+    pub fn revoke_token(&mut self, token: &str) -> anyhow::Result<Revoked> {
+        self.store.revoke_token(token)
+    }
+
+    /// Revoke all codes and tokens issued to a client, which stays enabled.
+    pub fn revoke_client_tokens(&mut self, client_id: &str) -> anyhow::Result<Revoked> {
+        self.existing(client_id)?;
+        self.store.revoke_client_tokens(client_id)
+    }
+
+    fn existing(&self, client_id: &str) -> anyhow::Result<EncodedClient> {
+        match self.store.client(client_id)? {
+            Some(client) => Ok(client),
+            None => anyhow::bail!("The client {} does not exist", client_id),
+        }
+    }
+}
+
+/// A random client secret, URL-safe base64 encoded.
+pub fn generate_secret() -> String {
+    let mut secret = [0; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    URL_SAFE_NO_PAD.encode(secret)
+}
+
+impl MemoryStore {
+    /// The tokens and client ids revoked so far, in order.
+    pub fn revoked(&self) -> &[String] {
+        &self.revoked
+    }
+}
+
+impl AdminStore for MemoryStore {
+    fn clients(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        Ok(self.clients.values().cloned().collect())
+    }
+
+    fn save_client(&mut self, client: EncodedClient) -> anyhow::Result<()> {
+        self.clients.insert(client.client_id.clone(), client);
+        Ok(())
+    }
+
+    fn revoke_token(&mut self, token: &str) -> anyhow::Result<Revoked> {
+        self.revoked.push(token.to_owned());
+        Ok(Revoked::Announced)
+    }
+
+    fn revoke_client_tokens(&mut self, client_id: &str) -> anyhow::Result<Revoked> {
+        self.revoked.push(client_id.to_owned());
+        Ok(Revoked::Announced)
+    }
+}
+
+impl<S: AdminStore + ?Sized> AdminStore for Box<S> {
+    fn clients(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        (**self).clients()
+    }
+
+    fn client(&self, client_id: &str) -> anyhow::Result<Option<EncodedClient>> {
+        (**self).client(client_id)
+    }
+
+    fn save_client(&mut self, client: EncodedClient) -> anyhow::Result<()> {
+        (**self).save_client(client)
+    }
+
+    fn revoke_token(&mut self, token: &str) -> anyhow::Result<Revoked> {
+        (**self).revoke_token(token)
+    }
+
+    fn revoke_client_tokens(&mut self, client_id: &str) -> anyhow::Result<Revoked> {
+        (**self).revoke_client_tokens(client_id)
+    }
+}
+
+impl fmt::Display for Revoked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Revoked::Deleted(0) => f.write_str("nothing was stored"),
+            Revoked::Deleted(1) => f.write_str("deleted 1 code or token"),
+            Revoked::Deleted(count) => write!(f, "deleted {} codes and tokens", count),
+            Revoked::Announced => f.write_str("announced the revocation to all replicas"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::RegisteredClient;
+
+    fn client(id: &str) -> Client {
+        Client::builder(id)
+            .redirect_uri("https://client.example/endpoint".parse::<url::Url>().unwrap())
+            .default_scope("default".parse().unwrap())
+            .passphrase(b"secret")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn manages_clients() {
+        let mut admin = Admin::new(MemoryStore::default());
+        admin.create(client("b")).unwrap();
+        admin.create(client("a")).unwrap();
+        assert!(admin.create(client("a")).is_err());
+
+        let ids: Vec<_> = admin.list().unwrap().into_iter().map(|c| c.client_id).collect();
+        assert_eq!(ids, ["a", "b"]);
+
+        assert_eq!(admin.disable("a").unwrap(), Revoked::Announced);
+        assert_eq!(admin.store().revoked(), ["a"]);
+        assert!(admin.store().client("a").unwrap().unwrap().disabled);
+        admin.enable("a").unwrap();
+        assert!(!admin.store().client("a").unwrap().unwrap().disabled);
+        assert!(admin.disable("c").is_err());
+    }
+
+    #[test]
+    fn rotates_secrets() {
+        let policy = Argon2::default();
+        let mut admin = Admin::new(MemoryStore::default());
+        admin.create(client("a")).unwrap();
+        let public = Client::builder("public")
+            .redirect_uri("https://client.example/endpoint".parse::<url::Url>().unwrap())
+            .default_scope("default".parse().unwrap())
+            .build()
+            .unwrap();
+        admin.create(public).unwrap();
+
+        let secret = admin.rotate_secret("a").unwrap();
+        let stored = admin.store().client("a").unwrap().unwrap();
+        let registered = RegisteredClient::new(&stored, &policy);
+        assert!(registered.check_authentication(Some(secret.as_bytes())).is_ok());
+        assert!(registered.check_authentication(Some(b"secret")).is_err());
+
+        assert!(admin.rotate_secret("public").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Command line administration of clients and tokens stored with `oxide-auth-db`.
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use oxide_auth::primitives::registrar::{Client, ClientType, EncodedClient, RegisteredUrl};
+use oxide_auth_admin::{generate_secret, Admin, AdminStore};
+use url::Url;
+
+#[derive(Parser)]
+#[command(name = "oxide-auth-admin", version, about)]
+struct Cli {
+    #[command(flatten)]
+    backend: Backend,
+
+    /// The prefix of the keys of clients in Redis.
+    #[arg(long, default_value = "client:", global = true)]
+    client_prefix: String,
+
+    /// The tenant to manage, the default tenant if not given.
+    #[arg(long, env = "OXIDE_AUTH_TENANT", global = true)]
+    tenant: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct Backend {
+    /// Manage the clients stored in Redis at this url.
+    #[arg(long, env = "OXIDE_AUTH_REDIS", global = true)]
+    redis: Option<String>,
+
+    /// Manage the sled database in this directory.
+    #[arg(long, env = "OXIDE_AUTH_SLED", global = true)]
+    sled: Option<std::path::PathBuf>,
+
+    /// Manage the SQL tables of the Postgres database at this url.
+    #[arg(long, env = "OXIDE_AUTH_POSTGRES", global = true)]
+    postgres: Option<String>,
+
+    /// Manage the SQL tables of the SQLite database in this file.
+    #[arg(long, env = "OXIDE_AUTH_SQLITE", global = true)]
+    sqlite: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage registered clients.
+    #[command(subcommand)]
+    Client(ClientCommand),
+
+    /// Revoke issued tokens.
+    #[command(subcommand)]
+    Token(TokenCommand),
+}
+
+#[derive(Subcommand)]
+enum ClientCommand {
+    /// Register a client, printing the generated secret of confidential clients.
+    Create {
+        client_id: String,
+
+        /// A redirect uri, the first one is the default.
+        #[arg(long = "redirect-uri", required = true)]
+        redirect_uris: Vec<Url>,
+
+        /// The scope granted when the client requests none.
+        #[arg(long)]
+        scope: String,
+
+        /// Register a public client without a secret.
+        #[arg(long)]
+        public: bool,
+    },
+
+    /// List all clients.
+    List,
+
+    /// Disable a client and revoke its codes and tokens.
+    Disable { client_id: String },
+
+    /// Enable a disabled client.
+    Enable { client_id: String },
+
+    /// Replace the secret of a confidential client, printing the new one.
+    RotateSecret { client_id: String },
+}
+
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Revoke an access or refresh token.
+    Revoke { token: String },
+
+    /// Revoke all codes and tokens of a client.
+    RevokeClient { client_id: String },
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let mut admin = Admin::new(open(&cli)?);
+
+    match cli.command {
+        Command::Client(ClientCommand::Create {
+            client_id,
+            redirect_uris,
+            scope,
+            public,
+        }) => {
+            let scope = scope
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid scope {:?}", scope))?;
+            let mut builder = Client::builder(&client_id)
+                .redirect_uris(redirect_uris.into_iter().map(RegisteredUrl::from))
+                .default_scope(scope);
+            let secret = (!public).then(generate_secret);
+            if let Some(secret) = &secret {
+                builder = builder.passphrase(secret.as_bytes());
+            }
+
+            admin.create(builder.build()?)?;
+            match secret {
+                Some(secret) => println!("Created {} with the secret {}", client_id, secret),
+                None => println!("Created the public client {}", client_id),
+            }
+        }
+        Command::Client(ClientCommand::List) => {
+            for client in admin.list()? {
+                println!("{}", describe(&client));
+            }
+        }
+        Command::Client(ClientCommand::Disable { client_id }) => {
+            let revoked = admin.disable(&client_id)?;
+            println!("Disabled {}, {}", client_id, revoked);
+        }
+        Command::Client(ClientCommand::Enable { client_id }) => {
+            admin.enable(&client_id)?;
+            println!("Enabled {}", client_id);
+        }
+        Command::Client(ClientCommand::RotateSecret { client_id }) => {
+            let secret = admin.rotate_secret(&client_id)?;
+            println!("The new secret of {} is {}", client_id, secret);
+        }
+        Command::Token(TokenCommand::Revoke { token }) => {
+            println!("Revoked the token, {}", admin.revoke_token(&token)?);
+        }
+        Command::Token(TokenCommand::RevokeClient { client_id }) => {
+            let revoked = admin.revoke_client_tokens(&client_id)?;
+            println!("Revoked the tokens of {}, {}", client_id, revoked);
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(unused_variables)]
+fn open(cli: &Cli) -> anyhow::Result<Box<dyn AdminStore>> {
+    #[cfg(any(
+        feature = "redis",
+        feature = "sled",
+        feature = "diesel-postgres",
+        feature = "diesel-sqlite"
+    ))]
+    use oxide_auth_db::db_service::TenantScoped;
+
+    #[cfg(feature = "redis")]
+    if let Some(url) = &cli.backend.redis {
+        use oxide_auth_db::db_service::redis::RedisDataSource;
+
+        let source = RedisDataSource::new(url.clone(), 1, cli.client_prefix.clone())
+            .with_context(|| format!("Failed to connect to {}", url))?;
+        let source = match &cli.tenant {
+            Some(tenant) => source.for_tenant(tenant),
+            None => source,
+        };
+        return Ok(Box::new(source));
+    }
+
+    #[cfg(feature = "sled")]
+    if let Some(path) = &cli.backend.sled {
+        use oxide_auth_db::db_service::sled_store::SledStore;
+
+        let store = SledStore::open(path)
+            .with_context(|| format!("Failed to open the database in {}", path.display()))?;
+        let store = match &cli.tenant {
+            Some(tenant) => store.for_tenant(tenant),
+            None => store,
+        };
+        return Ok(Box::new(store));
+    }
+
+    #[cfg(feature = "diesel-postgres")]
+    if let Some(url) = &cli.backend.postgres {
+        use oxide_auth_db::db_service::diesel_store::DieselStore;
+        use oxide_auth_db::db_service::PoolConfig;
+
+        let store = DieselStore::<diesel::PgConnection>::new(url, PoolConfig::with_max_size(1))
+            .with_context(|| format!("Failed to connect to {}", url))?;
+        store.verify_schema()?;
+        let store = match &cli.tenant {
+            Some(tenant) => store.for_tenant(tenant),
+            None => store,
+        };
+        return Ok(Box::new(store));
+    }
+
+    #[cfg(feature = "diesel-sqlite")]
+    if let Some(path) = &cli.backend.sqlite {
+        use oxide_auth_db::db_service::diesel_store::DieselStore;
+        use oxide_auth_db::db_service::PoolConfig;
+
+        let store = DieselStore::<diesel::SqliteConnection>::new(path, PoolConfig::with_max_size(1))
+            .with_context(|| format!("Failed to open the database {}", path))?;
+        store.verify_schema()?;
+        let store = match &cli.tenant {
+            Some(tenant) => store.for_tenant(tenant),
+            None => store,
+        };
+        return Ok(Box::new(store));
+    }
+
+    anyhow::bail!("The selected backend was not enabled when building oxide-auth-admin")
+}
+
+/// One line per client: id, type, state, default scope and redirect uris.
+fn describe(client: &EncodedClient) -> String {
+    let kind = match client.encoded_client {
+        ClientType::Public => "public",
+        ClientType::Confidential { .. } => "confidential",
+    };
+    let state = if client.disabled { "disabled" } else { "enabled" };
+    let uris = std::iter::once(&client.redirect_uri)
+        .chain(&client.additional_redirect_uris)
+        .map(RegisteredUrl::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        client.client_id, kind, state, client.default_scope, uris
+    )
+}
@@ -0,0 +1,29 @@
+//! Clients stored in Redis.
+use oxide_auth::primitives::registrar::EncodedClient;
+use oxide_auth_db::db_service::redis::RedisDataSource;
+use oxide_auth_db::primitives::db_registrar::OauthClientDBRepository;
+use oxide_auth_db::primitives::revocation::Revocation;
+
+use crate::{AdminStore, Revoked};
+
+/// Changes to clients reach the caches of the replicas through keyspace notifications. Tokens are
+/// not stored in Redis, their revocations are published on the revocation channel.
+impl AdminStore for RedisDataSource {
+    fn clients(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.list()
+    }
+
+    fn save_client(&mut self, client: EncodedClient) -> anyhow::Result<()> {
+        self.regist_from_encoded_client(client)
+    }
+
+    fn revoke_token(&mut self, token: &str) -> anyhow::Result<Revoked> {
+        self.publish_revocation(&Revocation::Token(token.to_owned()))?;
+        Ok(Revoked::Announced)
+    }
+
+    fn revoke_client_tokens(&mut self, client_id: &str) -> anyhow::Result<Revoked> {
+        self.publish_revocation(&Revocation::Client(client_id.to_owned()))?;
+        Ok(Revoked::Announced)
+    }
+}
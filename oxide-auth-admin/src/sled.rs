@@ -0,0 +1,88 @@
+//! Clients, codes and tokens stored in an embedded `sled` database.
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::issuer::Issuer;
+use oxide_auth::primitives::registrar::EncodedClient;
+use oxide_auth_db::db_service::sled_store::SledStore;
+use oxide_auth_db::db_service::stored::StoredClient;
+use oxide_auth_db::db_service::transfer::{Export, Import, Record};
+
+use crate::{AdminStore, Revoked};
+
+impl AdminStore for SledStore {
+    fn clients(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let mut clients = Vec::new();
+        for record in self.export()? {
+            if let Record::Client(client) = record? {
+                clients.push(client.into_encoded()?);
+            }
+        }
+        Ok(clients)
+    }
+
+    fn save_client(&mut self, client: EncodedClient) -> anyhow::Result<()> {
+        self.import(Record::Client(StoredClient::from_encoded(&client)?))?;
+        self.flush()?;
+        Ok(())
+    }
+
+    fn revoke_token(&mut self, token: &str) -> anyhow::Result<Revoked> {
+        let revoked = SledStore::revoke_token(self, token)?;
+        self.flush()?;
+        Ok(Revoked::Deleted(usize::from(revoked)))
+    }
+
+    fn revoke_client_tokens(&mut self, client_id: &str) -> anyhow::Result<Revoked> {
+        let failed = || anyhow::anyhow!("Failed to revoke the grants of {}", client_id);
+        let codes = Authorizer::revoke_client(self, client_id).map_err(|()| failed())?;
+        let tokens = Issuer::revoke_client(self, client_id).map_err(|()| failed())?;
+        self.flush()?;
+        Ok(Revoked::Deleted(codes + tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use oxide_auth::primitives::grant::{Extensions, Grant};
+    use oxide_auth::primitives::registrar::{Client, Registrar};
+
+    use crate::Admin;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn disables_clients() {
+        let db = ::sled::Config::new().temporary(true).open().unwrap();
+        let mut store = SledStore::with_db(db).unwrap();
+        store.authorize(grant()).unwrap();
+        let issued = store.issue(grant()).unwrap();
+
+        let mut admin = Admin::new(store.clone());
+        let client = Client::builder("Client")
+            .redirect_uri("https://client.example/endpoint".parse::<url::Url>().unwrap())
+            .default_scope("default".parse().unwrap())
+            .passphrase(b"secret")
+            .build()
+            .unwrap();
+        admin.create(client).unwrap();
+        let secret = admin.rotate_secret("Client").unwrap();
+        assert!(store.check("Client", Some(secret.as_bytes())).is_ok());
+
+        assert_eq!(admin.disable("Client").unwrap(), Revoked::Deleted(2));
+        assert!(store.check("Client", Some(secret.as_bytes())).is_err());
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+
+        admin.enable("Client").unwrap();
+        assert!(store.check("Client", Some(secret.as_bytes())).is_ok());
+    }
+}
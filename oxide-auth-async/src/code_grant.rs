@@ -1,6 +1,12 @@
 pub mod refresh {
+    use oxide_auth::code_grant::error::AccessTokenErrorType;
     use oxide_auth::code_grant::refresh::{BearerToken, Error, Input, Output, Refresh, Request};
-    use oxide_auth::primitives::{grant::Grant, registrar::RegistrarError};
+    use oxide_auth::endpoint::{GrantDecision, GrantEvent};
+    use oxide_auth::primitives::{
+        grant::Grant,
+        registrar::{ClientAuthMethod, GrantType, RegistrarError},
+    };
+    use zeroize::Zeroizing;
 
     pub trait Endpoint {
         /// Authenticate the requesting confidential client.
@@ -8,6 +14,18 @@ pub mod refresh {
 
         /// Recover and test the provided refresh token then issue new tokens.
         fn issuer(&mut self) -> &mut (dyn crate::primitives::Issuer + Send);
+
+        /// Decides over the grant just before the refreshed token is issued.
+        ///
+        /// The default implementation has no policy and refreshes all grants.
+        fn grant_policy(&mut self) -> Option<&mut (dyn crate::endpoint::GrantPolicy + Send)> {
+            None
+        }
+
+        /// Binds the grant to the context of the request, just before the refreshed token is issued.
+        ///
+        /// The default implementation leaves the grant unbound.
+        fn bind(&mut self, _grant: &mut Grant) {}
     }
 
     pub async fn refresh(
@@ -15,9 +33,17 @@ pub mod refresh {
     ) -> Result<BearerToken, Error> {
         enum Requested {
             None,
-            Refresh { token: String, grant: Box<Grant> },
-            RecoverRefresh { token: String },
-            Authenticate { client: String, pass: Option<Vec<u8>> },
+            Refresh {
+                token: String,
+                grant: Box<Grant>,
+            },
+            RecoverRefresh {
+                token: String,
+            },
+            Authenticate {
+                client: String,
+                pass: Option<Zeroizing<Vec<u8>>>,
+            },
         }
         let mut refresh = Refresh::new(request);
         let mut requested = Requested::None;
@@ -44,14 +70,29 @@ pub mod refresh {
                     }
                 }
                 Requested::Authenticate { client, pass } => {
-                    handler
-                        .registrar()
-                        .check(&client, pass.as_deref())
+                    let registrar = handler.registrar();
+                    registrar
+                        .check(&client, pass.as_ref().map(|pass| pass.as_slice()))
                         .await
                         .map_err(|err| match err {
                             RegistrarError::PrimitiveError => Error::Primitive,
                             RegistrarError::Unspecified => Error::unauthorized("basic"),
                         })?;
+                    // Refresh requests only authenticate with the authorization header.
+                    let auth_method = match pass {
+                        Some(_) => ClientAuthMethod::ClientSecretBasic,
+                        None => ClientAuthMethod::None,
+                    };
+                    if registrar
+                        .auth_method(&client)
+                        .await
+                        .is_some_and(|registered| registered != auth_method)
+                    {
+                        return Err(Error::unauthorized("basic"));
+                    }
+                    if !registrar.permits_grant(&client, GrantType::RefreshToken).await {
+                        return Err(Error::invalid(AccessTokenErrorType::UnauthorizedClient));
+                    }
                     Input::Authenticated {
                         scope: request.scope(),
                     }
@@ -61,16 +102,28 @@ pub mod refresh {
             requested = match refresh.advance(input) {
                 Output::Err(error) => return Err(error),
                 Output::Ok(token) => return Ok(token),
-                Output::Refresh { token, grant } => Requested::Refresh {
-                    token: token.to_string(),
-                    grant,
-                },
+                Output::Refresh { token, grant } => {
+                    match super::police(handler.grant_policy(), GrantEvent::Refresh, grant).await {
+                        GrantDecision::Allow => (),
+                        GrantDecision::Deny => {
+                            return Err(Error::invalid(AccessTokenErrorType::InvalidGrant))
+                        }
+                        GrantDecision::Error => return Err(Error::Primitive),
+                    }
+
+                    handler.bind(grant);
+
+                    Requested::Refresh {
+                        token: token.to_string(),
+                        grant: Box::new(grant.clone()),
+                    }
+                }
                 Output::RecoverRefresh { token } => Requested::RecoverRefresh {
                     token: token.to_string(),
                 },
                 Output::Unauthenticated { client, pass } => Requested::Authenticate {
                     client: client.to_string(),
-                    pass: pass.map(|p| p.to_vec()),
+                    pass: pass.map(|p| Zeroizing::new(p.to_vec())),
                 },
             };
         }
@@ -78,16 +131,23 @@ pub mod refresh {
 }
 
 pub mod resource {
+    use async_trait::async_trait;
     use oxide_auth::code_grant::resource::{Error, Input, Output, Request, Resource};
     use oxide_auth::primitives::grant::Grant;
-    use oxide_auth::primitives::scope::Scope;
+    use oxide_auth::primitives::scope::{Scope, ScopeMatching};
 
+    #[async_trait]
     pub trait Endpoint {
         /// The list of possible scopes required by the resource endpoint.
-        fn scopes(&mut self) -> &[Scope];
+        async fn scopes(&mut self) -> &[Scope];
 
         /// Recover and test the provided refresh token then issue new tokens.
         fn issuer(&mut self) -> &mut (dyn crate::primitives::Issuer + Send);
+
+        /// How the scope of a grant is matched against the scopes of the resource.
+        fn scope_matching(&mut self) -> ScopeMatching {
+            ScopeMatching::Exact
+        }
     }
 
     pub async fn protect(
@@ -100,13 +160,13 @@ pub mod resource {
             Grant(String),
         }
 
-        let mut resource = Resource::new();
+        let mut resource = Resource::with_matching(handler.scope_matching());
         let mut requested = Requested::None;
         loop {
             let input = match requested {
                 Requested::None => Input::None,
                 Requested::Request => Input::Request { request: req },
-                Requested::Scopes => Input::Scopes(handler.scopes()),
+                Requested::Scopes => Input::Scopes(handler.scopes().await),
                 Requested::Grant(token) => {
                     let grant = handler
                         .issuer()
@@ -137,14 +197,16 @@ pub mod client_credentials {
         code_grant::{
             accesstoken::{PrimitiveError, BearerToken},
             client_credentials::{ClientCredentials, Error, Input, Output, Request as TokenRequest},
+            error::AccessTokenErrorType,
         },
-        endpoint::{PreGrant, Scope, Solicitation},
+        endpoint::{GrantDecision, GrantEvent, PreGrant, Scope, Solicitation},
         primitives::{
             grant::{Extensions, Grant},
             prelude::ClientUrl,
-            registrar::{BoundClient, RegistrarError},
+            registrar::{BoundClient, ClientAuthMethod, GrantType, RegistrarError},
         },
     };
+    use zeroize::Zeroizing;
 
     #[async_trait]
     pub trait Extension {
@@ -180,6 +242,18 @@ pub mod client_credentials {
         ///
         /// It is possible to use `&mut ()`.
         fn extension(&mut self) -> &mut (dyn Extension + Send);
+
+        /// Decides over the grant just before the access token is issued.
+        ///
+        /// The default implementation has no policy and issues all grants.
+        fn grant_policy(&mut self) -> Option<&mut (dyn crate::endpoint::GrantPolicy + Send)> {
+            None
+        }
+
+        /// Binds the grant to the context of the request, just before the access token is issued.
+        ///
+        /// The default implementation leaves the grant unbound.
+        fn bind(&mut self, _grant: &mut Grant) {}
     }
 
     /// Represents a valid, currently pending client credentials not bound to an owner.
@@ -198,7 +272,19 @@ pub mod client_credentials {
     impl Pending {
         /// Reference this pending state as a solicitation.
         pub fn as_solicitation(&self) -> Solicitation<'_> {
-            Solicitation::new(&self.pre_grant)
+            Solicitation::new(&self.pre_grant).with_extensions(&self.extensions)
+        }
+
+        /// Restrict the grant to the part of its scope approved by the resource owner.
+        ///
+        /// Returns `false` and leaves the grant unchanged if the scope exceeds the negotiated scope.
+        pub fn restrict(&mut self, scope: Scope) -> bool {
+            if !self.pre_grant.scope.priviledged_to(&scope) {
+                return false;
+            }
+
+            self.pre_grant.scope = scope;
+            true
         }
 
         /// Inform the backend about consent from a resource owner.
@@ -208,18 +294,31 @@ pub mod client_credentials {
         pub async fn issue(
             self, handler: &mut (dyn Endpoint + Send), owner_id: String, allow_refresh_token: bool,
         ) -> Result<BearerToken, Error> {
-            let pre_grant = self.pre_grant.clone();
+            let mut pre_grant = self.pre_grant;
+            let mut grant = Grant {
+                owner_id,
+                client_id: pre_grant.client_id.clone(),
+                redirect_uri: pre_grant.redirect_uri.to_url(),
+                scope: pre_grant.scope.clone(),
+                until: Utc::now() + Duration::minutes(10),
+                extensions: self.extensions,
+            };
+
+            match super::police(handler.grant_policy(), GrantEvent::Token, &mut grant).await {
+                GrantDecision::Allow => (),
+                GrantDecision::Deny => {
+                    return Err(Error::invalid_with(AccessTokenErrorType::InvalidGrant))
+                }
+                GrantDecision::Error => return Err(Error::Primitive(Box::new(PrimitiveError::empty()))),
+            }
+
+            handler.bind(&mut grant);
 
+            // The response reports the scope of the grant as changed by the policy.
+            pre_grant.scope = grant.scope.clone();
             let mut token = handler
                 .issuer()
-                .issue(Grant {
-                    owner_id,
-                    client_id: pre_grant.client_id,
-                    redirect_uri: pre_grant.redirect_uri.into_url(),
-                    scope: pre_grant.scope.clone(),
-                    until: Utc::now() + Duration::minutes(10),
-                    extensions: self.extensions,
-                })
+                .issue(grant)
                 .await
                 .map_err(|()| Error::Primitive(Box::new(PrimitiveError::empty())))?;
 
@@ -227,7 +326,7 @@ pub mod client_credentials {
                 token.refresh = None;
             }
 
-            Ok(token.convert_bearer_token(self.pre_grant))
+            Ok(token.convert_bearer_token(pre_grant))
         }
     }
 
@@ -238,7 +337,8 @@ pub mod client_credentials {
             None,
             Authenticate {
                 client: String,
-                passdata: Vec<u8>,
+                passdata: Zeroizing<Vec<u8>>,
+                auth_method: ClientAuthMethod,
             },
             Bind {
                 client_id: String,
@@ -256,9 +356,13 @@ pub mod client_credentials {
         loop {
             let input = match requested {
                 Requested::None => Input::None,
-                Requested::Authenticate { client, passdata } => {
-                    handler
-                        .registrar()
+                Requested::Authenticate {
+                    client,
+                    passdata,
+                    auth_method,
+                } => {
+                    let registrar = handler.registrar();
+                    registrar
                         .check(&client, Some(passdata.as_slice()))
                         .await
                         .map_err(|err| match err {
@@ -270,6 +374,19 @@ pub mod client_credentials {
                                 }))
                             }
                         })?;
+                    if registrar
+                        .auth_method(&client)
+                        .await
+                        .is_some_and(|registered| registered != auth_method)
+                    {
+                        return Err(Error::unauthorized("basic"));
+                    }
+                    if !registrar
+                        .permits_grant(&client, GrantType::ClientCredentials)
+                        .await
+                    {
+                        return Err(Error::invalid_with(AccessTokenErrorType::UnauthorizedClient));
+                    }
                     Input::Authenticated
                 }
                 Requested::Bind { client_id } => {
@@ -316,9 +433,14 @@ pub mod client_credentials {
             };
 
             requested = match client_credentials.advance(input) {
-                Output::Authenticate { client, passdata } => Requested::Authenticate {
+                Output::Authenticate {
+                    client,
+                    passdata,
+                    auth_method,
+                } => Requested::Authenticate {
                     client: client.to_owned(),
-                    passdata: passdata.to_vec(),
+                    passdata: Zeroizing::new(passdata.to_vec()),
+                    auth_method,
                 },
                 Output::Binding { client_id } => Requested::Bind {
                     client_id: client_id.to_owned(),
@@ -349,9 +471,11 @@ pub mod access_token {
         code_grant::accesstoken::{
             AccessToken, BearerToken, Error, Input, Output, PrimitiveError, Request as TokenRequest,
         },
+        code_grant::error::AccessTokenErrorType,
+        endpoint::{GrantDecision, GrantEvent},
         primitives::{
             grant::{Extensions, Grant},
-            registrar::RegistrarError,
+            registrar::{ClientAuthMethod, GrantType, RegistrarError},
         },
     };
     // use crate::endpoint::access_token::WrappedRequest;
@@ -390,6 +514,18 @@ pub mod access_token {
         ///
         /// It is possible to use `&mut ()`.
         fn extension(&mut self) -> &mut (dyn Extension + Send);
+
+        /// Decides over the grant just before the access token is issued.
+        ///
+        /// The default implementation has no policy and issues all grants.
+        fn grant_policy(&mut self) -> Option<&mut (dyn crate::endpoint::GrantPolicy + Send)> {
+            None
+        }
+
+        /// Binds the grant to the context of the request, just before the access token is issued.
+        ///
+        /// The default implementation leaves the grant unbound.
+        fn bind(&mut self, _grant: &mut Grant) {}
     }
 
     pub async fn access_token(
@@ -400,13 +536,14 @@ pub mod access_token {
             Authenticate {
                 client: &'a str,
                 passdata: Option<&'a [u8]>,
+                auth_method: ClientAuthMethod,
             },
             Recover(&'a str),
             Extend {
                 extensions: &'a mut Extensions,
             },
             Issue {
-                grant: &'a Grant,
+                grant: &'a mut Grant,
             },
         }
 
@@ -416,20 +553,32 @@ pub mod access_token {
         loop {
             let input = match requested {
                 Requested::None => Input::None,
-                Requested::Authenticate { client, passdata } => {
-                    handler
-                        .registrar()
-                        .check(client, passdata)
+                Requested::Authenticate {
+                    client,
+                    passdata,
+                    auth_method,
+                } => {
+                    let registrar = handler.registrar();
+                    registrar.check(client, passdata).await.map_err(|err| match err {
+                        RegistrarError::Unspecified => Error::unauthorized("basic"),
+                        RegistrarError::PrimitiveError => Error::Primitive(Box::new(PrimitiveError {
+                            grant: None,
+                            extensions: None,
+                        })),
+                    })?;
+                    if registrar
+                        .auth_method(client)
                         .await
-                        .map_err(|err| match err {
-                            RegistrarError::Unspecified => Error::unauthorized("basic"),
-                            RegistrarError::PrimitiveError => {
-                                Error::Primitive(Box::new(PrimitiveError {
-                                    grant: None,
-                                    extensions: None,
-                                }))
-                            }
-                        })?;
+                        .is_some_and(|registered| registered != auth_method)
+                    {
+                        return Err(Error::unauthorized("basic"));
+                    }
+                    if !registrar
+                        .permits_grant(client, GrantType::AuthorizationCode)
+                        .await
+                    {
+                        return Err(Error::invalid_with(AccessTokenErrorType::UnauthorizedClient));
+                    }
                     Input::Authenticated
                 }
                 Requested::Recover(code) => {
@@ -451,21 +600,44 @@ pub mod access_token {
                     Input::Extended { access_extensions }
                 }
                 Requested::Issue { grant } => {
-                    let token = handler.issuer().issue(grant.clone()).await.map_err(|_| {
+                    match super::police(handler.grant_policy(), GrantEvent::Token, grant).await {
+                        GrantDecision::Allow => (),
+                        GrantDecision::Deny => {
+                            return Err(Error::invalid_with(AccessTokenErrorType::InvalidGrant))
+                        }
+                        GrantDecision::Error => {
+                            return Err(Error::Primitive(Box::new(PrimitiveError::empty())))
+                        }
+                    }
+
+                    handler.bind(grant);
+
+                    let policy = handler.registrar().refresh_policy(&grant.client_id).await;
+                    let refreshable = policy.narrow(&mut grant.scope);
+                    let mut token = handler.issuer().issue(grant.clone()).await.map_err(|_| {
                         Error::Primitive(Box::new(PrimitiveError {
                             // FIXME: endpoint should get and handle these.
                             grant: None,
                             extensions: None,
                         }))
                     })?;
+                    if !refreshable {
+                        token.refresh = None;
+                    }
                     Input::Issued(token)
                 }
             };
 
             requested = match access_token.advance(input) {
-                Output::Authenticate { client, passdata } => {
-                    Requested::Authenticate { client, passdata }
-                }
+                Output::Authenticate {
+                    client,
+                    passdata,
+                    auth_method,
+                } => Requested::Authenticate {
+                    client,
+                    passdata,
+                    auth_method,
+                },
                 Output::Recover { code } => Requested::Recover(code),
                 Output::Extend { extensions, .. } => Requested::Extend { extensions },
                 Output::Issue { grant } => Requested::Issue { grant },
@@ -484,7 +656,7 @@ pub mod authorization {
             authorization::{Authorization, Error, ErrorUrl, Input, Output, Request},
             error::{AuthorizationError, AuthorizationErrorType},
         },
-        endpoint::{PreGrant, Scope, Solicitation},
+        endpoint::{GrantDecision, GrantEvent, PreGrant, Scope, Solicitation},
         primitives::{
             grant::{Extensions, Grant},
             prelude::ClientUrl,
@@ -529,6 +701,13 @@ pub mod authorization {
         ///
         /// It is possible to use `&mut ()`.
         fn extension(&mut self) -> &mut (dyn Extension + Send);
+
+        /// Decides over the grant just before its code is generated.
+        ///
+        /// The default implementation has no policy and generates codes for all grants.
+        fn grant_policy(&mut self) -> Option<&mut (dyn crate::endpoint::GrantPolicy + Send)> {
+            None
+        }
     }
 
     /// Represents a valid, currently pending authorization request not bound to an owner. The frontend
@@ -543,20 +722,39 @@ pub mod authorization {
     impl Pending {
         /// Reference this pending state as a solicitation.
         pub fn as_solicitation(&self) -> Solicitation<'_> {
-            let base = Solicitation::new(&self.pre_grant);
+            let base = Solicitation::new(&self.pre_grant).with_extensions(&self.extensions);
             match self.state {
                 None => base,
                 Some(ref state) => base.with_state(state),
             }
         }
 
+        /// Restrict the grant to the part of its scope approved by the resource owner.
+        ///
+        /// Returns `false` and leaves the grant unchanged if the scope exceeds the negotiated scope.
+        pub fn restrict(&mut self, scope: Scope) -> bool {
+            if !self.pre_grant.scope.priviledged_to(&scope) {
+                return false;
+            }
+
+            self.pre_grant.scope = scope;
+            true
+        }
+
         /// Denies the request, which redirects to the client for which the request originated.
         pub fn deny(self) -> Result<Url, Error> {
-            let url = self.pre_grant.redirect_uri;
+            Err(access_denied(self.pre_grant.redirect_uri.into(), self.state))
+        }
+
+        /// Refuses the request with an error, which redirects to the client.
+        ///
+        /// Use this for refusals other than a denial by the owner, such as `login_required` for a
+        /// request that does not permit to interact with the owner.
+        pub fn refuse(self, kind: AuthorizationErrorType) -> Result<Url, Error> {
             let mut error = AuthorizationError::default();
-            error.set_type(AuthorizationErrorType::AccessDenied);
-            let error = ErrorUrl::new(url.into(), self.state.as_deref(), error);
-            Err(Error::Redirect(error))
+            error.set_type(kind);
+            let url = self.pre_grant.redirect_uri.into();
+            Err(Error::Redirect(ErrorUrl::new(url, self.state.as_deref(), error)))
         }
 
         /// Inform the backend about consent from a resource owner.
@@ -567,17 +765,24 @@ pub mod authorization {
             self, handler: &mut (dyn Endpoint + Send), owner_id: Cow<'_, str>,
         ) -> Result<Url, Error> {
             let mut url = self.pre_grant.redirect_uri.to_url();
+            let mut grant = Grant {
+                owner_id: owner_id.into_owned(),
+                client_id: self.pre_grant.client_id,
+                redirect_uri: self.pre_grant.redirect_uri.into(),
+                scope: self.pre_grant.scope,
+                until: Utc::now() + Duration::minutes(10),
+                extensions: self.extensions,
+            };
+
+            match super::police(handler.grant_policy(), GrantEvent::Code, &mut grant).await {
+                GrantDecision::Allow => (),
+                GrantDecision::Deny => return Err(access_denied(url, self.state)),
+                GrantDecision::Error => return Err(Error::PrimitiveError),
+            }
 
             let grant = handler
                 .authorizer()
-                .authorize(Grant {
-                    owner_id: owner_id.into_owned(),
-                    client_id: self.pre_grant.client_id,
-                    redirect_uri: self.pre_grant.redirect_uri.into(),
-                    scope: self.pre_grant.scope,
-                    until: Utc::now() + Duration::minutes(10),
-                    extensions: self.extensions,
-                })
+                .authorize(grant)
                 .await
                 .map_err(|()| Error::PrimitiveError)?;
 
@@ -595,6 +800,12 @@ pub mod authorization {
         }
     }
 
+    fn access_denied(url: Url, state: Option<String>) -> Error {
+        let mut error = AuthorizationError::default();
+        error.set_type(AuthorizationErrorType::AccessDenied);
+        Error::Redirect(ErrorUrl::new(url, state.as_deref(), error))
+    }
+
     /// Retrieve allowed scope and redirect url from the registrar.
     ///
     /// Checks the validity of any given input as the registrar instance communicates the registrated
@@ -720,3 +931,17 @@ pub mod authorization {
         }
     }
 }
+
+/// Consult the grant policy of an endpoint, if any, over a grant about to be issued.
+async fn police(
+    policy: Option<&mut (dyn crate::endpoint::GrantPolicy + Send)>,
+    event: oxide_auth::endpoint::GrantEvent, grant: &mut oxide_auth::primitives::grant::Grant,
+) -> oxide_auth::endpoint::GrantDecision {
+    match policy {
+        Some(policy) => {
+            let original = grant.clone();
+            policy.decide(event, grant).await.check(&original, grant)
+        }
+        None => oxide_auth::endpoint::GrantDecision::Allow,
+    }
+}
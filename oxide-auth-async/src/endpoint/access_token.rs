@@ -3,16 +3,25 @@ use std::{borrow::Cow, marker::PhantomData};
 
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
+use zeroize::Zeroizing;
 use oxide_auth::{
-    endpoint::{QueryParameter, WebRequest, OAuthError, WebResponse, Template, NormalizedParameter},
+    endpoint::{
+        QueryParameter, WebRequest, OAuthError, WebResponse, Template, NormalizedParameter, GrantEvent,
+        GrantOutcome, GrantRecord, IdempotentResponse, LimitedRequest, bind_grant, request_fingerprint,
+    },
+    primitives::grant::Grant,
     code_grant::{
         accesstoken::{
             Error as TokenError, Request as TokenRequest, Authorization as TokenAuthorization,
         },
+        error::AccessTokenErrorType,
     },
 };
 
-use super::Endpoint;
+use super::{
+    context_binding, Endpoint, GrantPolicy, explain_access_token_error, rate_limit, record, token_json,
+    trace,
+};
 use crate::{
     code_grant::access_token::{Extension, Endpoint as TokenEndpoint, access_token},
     primitives::{Issuer, Registrar, Authorizer},
@@ -45,6 +54,7 @@ where
     R: WebRequest,
 {
     inner: E,
+    binding: Option<String>,
     extension_fallback: (),
     r_type: PhantomData<R>,
 }
@@ -74,7 +84,7 @@ enum FailParse<E> {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-struct Authorization(String, Option<Vec<u8>>);
+struct Authorization(String, Option<Zeroizing<Vec<u8>>>);
 
 impl<E, R> AccessTokenFlow<E, R>
 where
@@ -108,6 +118,7 @@ where
         Ok(AccessTokenFlow {
             endpoint: WrappedToken {
                 inner: endpoint,
+                binding: None,
                 extension_fallback: (),
                 r_type: PhantomData,
             },
@@ -132,44 +143,147 @@ where
     ///
     /// When the registrar, authorizer, or issuer returned by the endpoint is suddenly
     /// `None` when previously it was `Some(_)`.
-    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let issued = access_token(
-            &mut self.endpoint,
-            &WrappedRequest::new(&mut request, self.allow_credentials_in_body),
-        )
-        .await;
+    pub async fn execute(&mut self, request: R) -> Result<R::Response, E::Error> {
+        trace::instrument(trace::access_token(), self.run(request)).await
+    }
+
+    async fn run(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        if let Some(limited) = self.rate_limited(&mut request).await {
+            return limited;
+        }
+
+        let idempotency = self.idempotency(&mut request);
+        if let Some((key, fingerprint)) = &idempotency {
+            if let Some(replayed) = self.replay(&mut request, key, fingerprint).await {
+                return replayed;
+            }
+        }
+
+        self.endpoint.binding = context_binding(&mut self.endpoint.inner, &mut request).await;
+        let (issued, client_id) = {
+            let wrapped = WrappedRequest::new(&mut request, self.allow_credentials_in_body);
+            let issued = access_token(&mut self.endpoint, &wrapped).await;
+            (issued, wrapped.requesting_client())
+        };
 
         let token = match issued {
-            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Err(error) => {
+                let outcome = match error {
+                    TokenError::Primitive(_) => GrantOutcome::Failed,
+                    _ => GrantOutcome::Denied,
+                };
+                let refused = GrantRecord {
+                    client_id,
+                    error: error.code(),
+                    ..GrantRecord::new(GrantEvent::Token, outcome)
+                };
+                record(&mut self.endpoint.inner, &mut request, refused).await;
+                return token_error(&mut self.endpoint.inner, &mut request, error).await;
+            }
             Ok(token) => token,
         };
 
-        let mut response = self.endpoint.inner.response(&mut request, Template::new_ok())?;
-        response
-            .body_json(&token.to_json())
-            .map_err(|err| self.endpoint.inner.web_error(err))?;
-        Ok(response)
+        let issued = GrantRecord {
+            client_id: Some(token.client_id().to_owned()),
+            owner_id: token.owner_id().map(str::to_owned),
+            scope: Some(token.scope().clone()),
+            ..GrantRecord::new(GrantEvent::Token, GrantOutcome::Issued)
+        };
+        let body = token_json(
+            &mut self.endpoint.inner,
+            &mut request,
+            token.to_response(),
+            &issued,
+        )
+        .await;
+        record(&mut self.endpoint.inner, &mut request, issued).await;
+
+        if let Some((key, fingerprint)) = idempotency {
+            if let Some(store) = self.endpoint.inner.idempotency_store() {
+                let body = body.clone();
+                store
+                    .remember(&key, IdempotentResponse { fingerprint, body })
+                    .await;
+            }
+        }
+
+        token_response(&mut self.endpoint.inner, &mut request, &body)
+    }
+
+    /// The idempotency key and fingerprint of the request, if the endpoint has a store.
+    fn idempotency(&mut self, request: &mut R) -> Option<(String, String)> {
+        self.endpoint.inner.idempotency_store()?;
+        let key = request.context()?.idempotency_key.clone()?;
+        let fingerprint = request_fingerprint(request)?;
+        Some((key, fingerprint))
     }
+
+    /// Answer a retried request with the remembered response, if there is one.
+    async fn replay(
+        &mut self, request: &mut R, key: &str, fingerprint: &str,
+    ) -> Option<Result<R::Response, E::Error>> {
+        let remembered = self.endpoint.inner.idempotency_store()?.replay(key).await?;
+        if remembered.fingerprint != fingerprint {
+            let mut error = TokenError::invalid_with(AccessTokenErrorType::InvalidRequest);
+            if let TokenError::Invalid(json) = &mut error {
+                json.description()
+                    .explain("The idempotency key was used for a different request");
+            }
+            return Some(token_error(&mut self.endpoint.inner, request, error).await);
+        }
+
+        Some(token_response(
+            &mut self.endpoint.inner,
+            request,
+            &remembered.body,
+        ))
+    }
+
+    /// Consult the rate limiter of the endpoint, if there is one.
+    async fn rate_limited(&mut self, request: &mut R) -> Option<Result<R::Response, E::Error>> {
+        // Only parse the client from the request when there is a limiter to consult.
+        self.endpoint.inner.rate_limiter()?;
+        let client_id = WrappedRequest::new(request, self.allow_credentials_in_body).requesting_client();
+        let limited = LimitedRequest {
+            kind: "authorization_code",
+            client_id: client_id.as_deref(),
+        };
+        rate_limit(&mut self.endpoint.inner, request, limited).await
+    }
+}
+
+fn token_response<E, R>(endpoint: &mut E, request: &mut R, body: &str) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    let mut response = endpoint.response(request, Template::new_ok())?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response.body_json(body).map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
 }
 
-fn token_error<E, R>(
+async fn token_error<E, R>(
     endpoint: &mut E, request: &mut R, error: TokenError,
 ) -> Result<R::Response, E::Error>
 where
     E: Endpoint<R>,
-    R: WebRequest,
+    R: WebRequest + Send,
 {
     Ok(match error {
         TokenError::Invalid(mut json) => {
+            explain_access_token_error(endpoint, request, json.description()).await;
             let mut response =
                 endpoint.response(request, Template::new_bad(Some(json.description())))?;
             response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
         TokenError::Unauthorized(mut json, scheme) => {
+            explain_access_token_error(endpoint, request, json.description()).await;
             let mut response = endpoint.response(
                 request,
                 Template::new_unauthorized(None, Some(json.description())),
@@ -177,6 +291,7 @@ where
             response
                 .unauthorized(&scheme)
                 .map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
@@ -212,6 +327,16 @@ where
             .and_then(super::Extension::access_token)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn grant_policy(&mut self) -> Option<&mut (dyn GrantPolicy + Send)> {
+        self.inner.grant_policy()
+    }
+
+    fn bind(&mut self, grant: &mut Grant) {
+        if let Some(binding) = &self.binding {
+            bind_grant(grant, binding);
+        }
+    }
 }
 
 impl<R: WebRequest> WrappedRequest<R> {
@@ -244,6 +369,14 @@ impl<R: WebRequest> WrappedRequest<R> {
         }
     }
 
+    /// The client as claimed by the request, whether it could be authenticated or not.
+    fn requesting_client(&self) -> Option<String> {
+        match &self.authorization {
+            Some(Authorization(username, _)) => Some(username.clone()),
+            None => self.body.unique_value("client_id").map(Cow::into_owned),
+        }
+    }
+
     fn parse_header(header: Cow<str>) -> Result<Authorization, Invalid> {
         let authorization = {
             if !header.starts_with("Basic ") {
@@ -252,7 +385,7 @@ impl<R: WebRequest> WrappedRequest<R> {
 
             let combined = match STANDARD.decode(&header[6..]) {
                 Err(_) => return Err(Invalid),
-                Ok(vec) => vec,
+                Ok(vec) => Zeroizing::new(vec),
             };
 
             let mut split = combined.splitn(2, |&c| c == b':');
@@ -271,7 +404,10 @@ impl<R: WebRequest> WrappedRequest<R> {
                 Ok(client) => client,
             };
 
-            Authorization(client.to_string(), passwd.map(|passwd| passwd.to_vec()))
+            Authorization(
+                client.to_string(),
+                passwd.map(|passwd| Zeroizing::new(passwd.to_vec())),
+            )
         };
 
         Ok(authorization)
@@ -292,7 +428,7 @@ impl<R: WebRequest> TokenRequest for WrappedRequest<R> {
             None => TokenAuthorization::None,
             Some(Authorization(username, None)) => TokenAuthorization::Username(username.into()),
             Some(Authorization(username, Some(password))) => {
-                TokenAuthorization::UsernamePassword(username.into(), password.into())
+                TokenAuthorization::UsernamePassword(username.into(), password.as_slice().into())
             }
         }
     }
@@ -342,6 +478,9 @@ mod test {
         let result = WrappedRequest::<Request>::parse_header("Basic Zm9vOmJhcg==".into());
         assert!(result.is_ok());
         let result = result.unwrap();
-        assert_eq!(result, Authorization("foo".into(), Some("bar".into())));
+        assert_eq!(
+            result,
+            Authorization("foo".into(), Some(Zeroizing::new(b"bar".to_vec())))
+        );
     }
 }
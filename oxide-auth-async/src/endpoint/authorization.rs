@@ -1,8 +1,13 @@
 use std::{borrow::Cow, marker::PhantomData};
 
 use oxide_auth::{
-    endpoint::{WebResponse, QueryParameter, NormalizedParameter},
+    endpoint::{
+        WebResponse, QueryParameter, NormalizedParameter, GrantEvent, GrantOutcome, GrantRecord,
+        RequestContext,
+    },
     code_grant::authorization::{Error as AuthorizationError, Request as AuthorizationRequest},
+    code_grant::error::AuthorizationErrorType,
+    primitives::{consent::Consent, session::Session},
 };
 
 use crate::code_grant::authorization::{
@@ -50,6 +55,8 @@ where
 {
     endpoint: &'a mut WrappedAuthorization<E, R>,
     pending: Pending,
+    parameters: NormalizedParameter,
+    context: Option<RequestContext>,
     request: R,
 }
 
@@ -145,19 +152,38 @@ where
     ///
     /// When the registrar or the authorizer returned by the endpoint is suddenly `None` when
     /// previously it was `Some(_)`.
-    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let negotiated =
-            authorization_code(&mut self.endpoint, &WrappedRequest::new(&mut request)).await;
+    pub async fn execute(&mut self, request: R) -> Result<R::Response, E::Error> {
+        trace::instrument(trace::authorization(), self.run(request)).await
+    }
+
+    async fn run(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let (negotiated, client_id, parameters) = {
+            let wrapped = WrappedRequest::new(&mut request);
+            let negotiated = authorization_code(&mut self.endpoint, &wrapped).await;
+            let client_id = wrapped.client_id().map(Cow::into_owned);
+            (negotiated, client_id, wrapped.query)
+        };
+        let context = request.context().map(Cow::into_owned);
 
         let inner = match negotiated {
-            Err(err) => match authorization_error(&mut self.endpoint.inner, &mut request, err) {
-                Ok(response) => AuthorizationPartialInner::Failed { request, response },
-                Err(error) => AuthorizationPartialInner::Error { request, error },
-            },
+            Err(err) => {
+                let refused = GrantRecord {
+                    client_id,
+                    error: err.code(),
+                    ..GrantRecord::new(GrantEvent::Code, error_outcome(&err))
+                };
+                record(&mut self.endpoint.inner, &mut request, refused).await;
+                match authorization_error(&mut self.endpoint.inner, &mut request, err).await {
+                    Ok(response) => AuthorizationPartialInner::Failed { request, response },
+                    Err(error) => AuthorizationPartialInner::Error { request, error },
+                }
+            }
             Ok(negotiated) => AuthorizationPartialInner::Pending {
                 pending: AuthorizationPending {
                     endpoint: &mut self.endpoint,
                     pending: negotiated,
+                    parameters,
+                    context,
                     request,
                 },
             },
@@ -192,16 +218,35 @@ where
     }
 }
 
-fn authorization_error<E, R>(
+/// The solicitation of a pending request, with its parameters and context.
+fn solicitation<'p>(
+    pending: &'p Pending, parameters: &'p NormalizedParameter, context: Option<&'p RequestContext>,
+) -> Solicitation<'p> {
+    let solicitation = pending.as_solicitation().with_parameters(parameters);
+    match context {
+        Some(context) => solicitation.with_context(context),
+        None => solicitation,
+    }
+}
+
+fn error_outcome(error: &AuthorizationError) -> GrantOutcome {
+    match error {
+        AuthorizationError::PrimitiveError => GrantOutcome::Failed,
+        _ => GrantOutcome::Denied,
+    }
+}
+
+async fn authorization_error<E, R>(
     endpoint: &mut E, request: &mut R, error: AuthorizationError,
 ) -> Result<R::Response, E::Error>
 where
     E: Endpoint<R>,
-    R: WebRequest,
+    R: WebRequest + Send,
 {
     match error {
         AuthorizationError::Ignore => Err(endpoint.error(OAuthError::DenySilently)),
         AuthorizationError::Redirect(mut target) => {
+            explain_authorization_error(endpoint, request, target.description()).await;
             let mut response =
                 endpoint.response(request, Template::new_redirect(Some(target.description())))?;
             response
@@ -220,17 +265,167 @@ where
 {
     /// Resolve the pending status using the endpoint to query owner consent.
     async fn finish(mut self) -> (R, Result<R::Response, E::Error>) {
+        match self.police().await {
+            Some(true) => (),
+            Some(false) => return self.fail().await,
+            None => return self.deny().await,
+        }
+
+        if self.endpoint.inner.session_store().is_some() {
+            if self.parameters.unique_value("max_age").is_some() && self.max_age().is_none() {
+                return self.refuse(AuthorizationErrorType::InvalidRequest).await;
+            }
+
+            if self.prompt_none() {
+                return self.silently().await;
+            }
+        }
+
+        if let Some(who) = self.remembered().await {
+            if !self.stale(&who).await {
+                return self.authorize(who).await;
+            }
+        }
+
         let checked = self
             .endpoint
             .owner_solicitor()
-            .check_consent(&mut self.request, self.pending.as_solicitation())
+            .check_consent(
+                &mut self.request,
+                solicitation(&self.pending, &self.parameters, self.context.as_ref()),
+            )
             .await;
 
         match checked {
-            OwnerConsent::Denied => self.deny(),
+            OwnerConsent::Denied => self.deny().await,
             OwnerConsent::InProgress(resp) => self.in_progress(resp),
             OwnerConsent::Authorized(who) => self.authorize(who).await,
-            OwnerConsent::Error(err) => (self.request, Err(self.endpoint.inner.web_error(err))),
+            OwnerConsent::AuthorizedScope(who, scope) => self.authorize_scope(who, scope).await,
+            OwnerConsent::Remember(who) => {
+                self.remember(&who).await;
+                self.authorize(who).await
+            }
+            OwnerConsent::Error(err) => {
+                let failed = self.record(GrantOutcome::Failed, None);
+                record(&mut self.endpoint.inner, &mut self.request, failed).await;
+                (self.request, Err(self.endpoint.inner.web_error(err)))
+            }
+        }
+    }
+
+    /// Narrows the scope with the scope policy of the endpoint, if any.
+    ///
+    /// Returns `None` if the policy denies the request and `Some(false)` if it exceeded the
+    /// negotiated scope.
+    async fn police(&mut self) -> Option<bool> {
+        if self.endpoint.inner.scope_policy().is_none() {
+            return Some(true);
+        }
+
+        let owner_id = self.endpoint.owner_solicitor().owner_id(&mut self.request).await;
+        let solicitation = solicitation(&self.pending, &self.parameters, self.context.as_ref());
+        let scope = self
+            .endpoint
+            .inner
+            .scope_policy()?
+            .decide(&mut self.request, owner_id.as_deref(), solicitation)
+            .await?;
+        Some(self.pending.restrict(scope))
+    }
+
+    /// The owner of the request, if they have already approved the client for its scope.
+    async fn remembered(&mut self) -> Option<String> {
+        self.endpoint.inner.consent_store()?;
+        let owner_id = self
+            .endpoint
+            .owner_solicitor()
+            .owner_id(&mut self.request)
+            .await?;
+        if self.consented(&owner_id).await {
+            Some(owner_id)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the owner has already approved the client for the scope of the request.
+    async fn consented(&mut self, owner_id: &str) -> bool {
+        let pre_grant = self.pending.pre_grant();
+        let scope = match self.endpoint.inner.consent_store() {
+            Some(store) => store.recall(owner_id, &pre_grant.client_id).await,
+            None => None,
+        };
+
+        match scope {
+            Some(scope) => scope.priviledged_to(&pre_grant.scope),
+            None => false,
+        }
+    }
+
+    /// Whether the request forbids interacting with the owner.
+    fn prompt_none(&self) -> bool {
+        match self.parameters.unique_value("prompt") {
+            Some(prompt) => prompt.split(' ').any(|value| value == "none"),
+            None => false,
+        }
+    }
+
+    /// The maximum age of the authentication of the owner requested by the client.
+    fn max_age(&self) -> Option<chrono::Duration> {
+        let seconds: u32 = self.parameters.unique_value("max_age")?.parse().ok()?;
+        Some(chrono::Duration::seconds(seconds.into()))
+    }
+
+    /// The session the request was made in, if its owner authenticated recently enough.
+    async fn session(&mut self) -> Option<(String, Session)> {
+        self.endpoint.inner.session_store()?;
+        let session_id = self
+            .endpoint
+            .owner_solicitor()
+            .session_id(&mut self.request)
+            .await?;
+        let session = self.endpoint.inner.session_store()?.session(&session_id).await?;
+
+        match self.max_age() {
+            Some(max_age) if !session.authenticated_within(max_age, chrono::Utc::now()) => None,
+            _ => Some((session_id, session)),
+        }
+    }
+
+    /// Whether the owner must authenticate again before the client may be granted a code.
+    async fn stale(&mut self, owner_id: &str) -> bool {
+        if self.endpoint.inner.session_store().is_none() || self.max_age().is_none() {
+            return false;
+        }
+
+        !matches!(self.session().await, Some((_, session)) if session.owner_id == owner_id)
+    }
+
+    /// Decides a request that forbids interacting with the owner, from their session alone.
+    async fn silently(mut self) -> (R, Result<R::Response, E::Error>) {
+        let owner_id = match self.session().await {
+            Some((_, session)) => session.owner_id,
+            None => return self.refuse(AuthorizationErrorType::LoginRequired).await,
+        };
+
+        if self.consented(&owner_id).await {
+            self.authorize(owner_id).await
+        } else {
+            self.refuse(AuthorizationErrorType::ConsentRequired).await
+        }
+    }
+
+    /// Stores the consent of the owner, if the endpoint has a consent store.
+    async fn remember(&mut self, owner_id: &str) {
+        let pre_grant = self.pending.pre_grant();
+        let consent = Consent {
+            owner_id: owner_id.to_owned(),
+            client_id: pre_grant.client_id.clone(),
+            scope: pre_grant.scope.clone(),
+        };
+
+        if let Some(store) = self.endpoint.inner.consent_store() {
+            store.remember(consent).await;
         }
     }
 
@@ -245,22 +440,86 @@ where
     }
 
     /// Denies the request, the client is not allowed access.
-    fn deny(mut self) -> (R, Result<R::Response, E::Error>) {
+    async fn deny(mut self) -> (R, Result<R::Response, E::Error>) {
+        let denied = GrantRecord {
+            error: Some("access_denied"),
+            ..self.record(GrantOutcome::Denied, None)
+        };
+        record(&mut self.endpoint.inner, &mut self.request, denied).await;
         let result = self.pending.deny();
-        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
+        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request).await;
+
+        (self.request, result)
+    }
+
+    /// Refuses the request with an error, without the owner having denied it.
+    async fn refuse(mut self, kind: AuthorizationErrorType) -> (R, Result<R::Response, E::Error>) {
+        let mut refused = self.record(GrantOutcome::Denied, None);
+        let result = self.pending.refuse(kind);
+        refused.error = result.as_ref().err().and_then(AuthorizationError::code);
+        record(&mut self.endpoint.inner, &mut self.request, refused).await;
+        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request).await;
 
         (self.request, result)
     }
 
+    /// Tells the system that the resource owner has approved only part of the grant.
+    async fn authorize_scope(mut self, who: String, scope: Scope) -> (R, Result<R::Response, E::Error>) {
+        if !self.pending.restrict(scope) {
+            return self.fail().await;
+        }
+
+        self.authorize(who).await
+    }
+
+    /// Fails the request after a scope exceeding the negotiated one was approved.
+    async fn fail(mut self) -> (R, Result<R::Response, E::Error>) {
+        let failed = self.record(GrantOutcome::Failed, None);
+        record(&mut self.endpoint.inner, &mut self.request, failed).await;
+        (
+            self.request,
+            Err(self.endpoint.inner.error(OAuthError::PrimitiveError)),
+        )
+    }
+
     /// Tells the system that the resource owner with the given id has approved the grant.
     async fn authorize(mut self, who: String) -> (R, Result<R::Response, E::Error>) {
-        let result = self.pending.authorize(self.endpoint, who.into()).await;
-        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
+        if self.stale(&who).await {
+            return self.refuse(AuthorizationErrorType::LoginRequired).await;
+        }
+
+        let session = self.session().await;
+        let client_id = self.pending.pre_grant().client_id.clone();
+        let mut decided = self.record(GrantOutcome::Issued, Some(who.clone()));
+        let result = self.pending.authorize(self.endpoint, who.clone().into()).await;
+        match (&result, session) {
+            (Err(err), _) => decided.outcome = error_outcome(err),
+            (Ok(_), Some((session_id, session))) if session.owner_id == who => {
+                if let Some(store) = self.endpoint.inner.session_store() {
+                    store.associate(&session_id, &client_id).await;
+                }
+            }
+            (Ok(_), _) => (),
+        }
+        record(&mut self.endpoint.inner, &mut self.request, decided).await;
+        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request).await;
 
         (self.request, result)
     }
 
-    fn convert_result(
+    fn record(&self, outcome: GrantOutcome, owner_id: Option<String>) -> GrantRecord {
+        let pre_grant = self.pending.pre_grant();
+        GrantRecord {
+            event: GrantEvent::Code,
+            outcome,
+            client_id: Some(pre_grant.client_id.clone()),
+            owner_id,
+            scope: Some(pre_grant.scope.clone()),
+            error: None,
+        }
+    }
+
+    async fn convert_result(
         result: Result<Url, AuthorizationError>, endpoint: &mut E, request: &mut R,
     ) -> Result<R::Response, E::Error> {
         match result {
@@ -269,7 +528,7 @@ where
                 response.redirect(url).map_err(|err| endpoint.web_error(err))?;
                 Ok(response)
             }
-            Err(err) => authorization_error(endpoint, request, err),
+            Err(err) => authorization_error(endpoint, request, err).await,
         }
     }
 }
@@ -303,6 +562,10 @@ where
             .and_then(super::Extension::authorization)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn grant_policy(&mut self) -> Option<&mut (dyn GrantPolicy + Send)> {
+        self.inner.grant_policy()
+    }
 }
 
 impl<'a, R> WrappedRequest<R>
@@ -3,10 +3,13 @@ use std::str::from_utf8;
 use std::marker::PhantomData;
 
 use base64::{engine::general_purpose::STANDARD, Engine};
+use zeroize::Zeroizing;
 use oxide_auth::{
     endpoint::{
         NormalizedParameter, QueryParameter, WebResponse, WebRequest, Template, is_authorization_method,
+        GrantEvent, GrantOutcome, GrantRecord, LimitedRequest, bind_grant,
     },
+    primitives::grant::Grant,
     code_grant::{
         accesstoken::ErrorDescription,
         client_credentials::{Error as ClientCredentialsError, Request as ClientCredentialsRequest},
@@ -14,7 +17,10 @@ use oxide_auth::{
     },
 };
 
-use super::{Endpoint, OAuthError, OwnerConsent};
+use super::{
+    context_binding, Endpoint, GrantPolicy, OAuthError, OwnerConsent, explain_access_token_error,
+    rate_limit, record, token_json, trace,
+};
 use crate::{
     primitives::{Issuer, Registrar, Authorizer},
     code_grant::client_credentials::{
@@ -43,6 +49,7 @@ where
 
 struct WrappedToken<E: Endpoint<R>, R: WebRequest> {
     inner: E,
+    binding: Option<String>,
     extension_fallback: (),
     r_type: PhantomData<R>,
 }
@@ -71,7 +78,7 @@ enum FailParse<E> {
     Err(E),
 }
 
-struct Authorization(String, Vec<u8>);
+struct Authorization(String, Zeroizing<Vec<u8>>);
 
 impl<E, R> ClientCredentialsFlow<E, R>
 where
@@ -101,6 +108,7 @@ where
         Ok(ClientCredentialsFlow {
             endpoint: WrappedToken {
                 inner: endpoint,
+                binding: None,
                 extension_fallback: (),
                 r_type: PhantomData,
             },
@@ -140,20 +148,45 @@ where
     ///
     /// When the registrar, authorizer, or issuer returned by the endpoint is suddenly
     /// `None` when previously it was `Some(_)`.
-    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let pending = client_credentials(
-            &mut self.endpoint,
-            &WrappedRequest::new(&mut request, self.allow_credentials_in_body),
-        )
-        .await;
+    pub async fn execute(&mut self, request: R) -> Result<R::Response, E::Error> {
+        trace::instrument(trace::client_credentials(), self.run(request)).await
+    }
 
-        let pending = match pending {
+    async fn run(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        if let Some(limited) = self.rate_limited(&mut request).await {
+            return limited;
+        }
+
+        self.endpoint.binding = context_binding(&mut self.endpoint.inner, &mut request).await;
+        let (pending, client_id) = {
+            let wrapped = WrappedRequest::new(&mut request, self.allow_credentials_in_body);
+            let pending = client_credentials(&mut self.endpoint, &wrapped).await;
+            (pending, wrapped.requesting_client())
+        };
+
+        let mut pending = match pending {
             Err(error) => {
-                return client_credentials_error(&mut self.endpoint.inner, &mut request, error)
+                let refused = GrantRecord {
+                    client_id,
+                    error: error.code(),
+                    ..GrantRecord::new(GrantEvent::Token, error_outcome(&error))
+                };
+                record(&mut self.endpoint.inner, &mut request, refused).await;
+                return client_credentials_error(&mut self.endpoint.inner, &mut request, error).await;
             }
             Ok(pending) => pending,
         };
 
+        let pre_grant = pending.as_solicitation().pre_grant().clone();
+        let decided = |outcome, owner_id| GrantRecord {
+            event: GrantEvent::Token,
+            outcome,
+            client_id: Some(pre_grant.client_id.clone()),
+            owner_id,
+            scope: Some(pre_grant.scope.clone()),
+            error: None,
+        };
+
         let consent = self
             .endpoint
             .inner
@@ -163,14 +196,34 @@ where
             .await;
 
         let owner_id = match consent {
-            OwnerConsent::Authorized(owner_id) => owner_id,
-            OwnerConsent::Error(error) => return Err(self.endpoint.inner.web_error(error)),
+            OwnerConsent::Authorized(owner_id) | OwnerConsent::Remember(owner_id) => owner_id,
+            OwnerConsent::AuthorizedScope(owner_id, scope) => {
+                if !pending.restrict(scope) {
+                    let failed = decided(GrantOutcome::Failed, None);
+                    record(&mut self.endpoint.inner, &mut request, failed).await;
+                    return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
+                }
+                owner_id
+            }
+            OwnerConsent::Error(error) => {
+                let failed = decided(GrantOutcome::Failed, None);
+                record(&mut self.endpoint.inner, &mut request, failed).await;
+                return Err(self.endpoint.inner.web_error(error));
+            }
             OwnerConsent::InProgress(..) => {
                 // User interaction is not permitted in the client credentials flow, so
                 // an InProgress response is invalid.
+                let failed = decided(GrantOutcome::Failed, None);
+                record(&mut self.endpoint.inner, &mut request, failed).await;
                 return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
             }
             OwnerConsent::Denied => {
+                let denied = GrantRecord {
+                    error: Some("invalid_client"),
+                    ..decided(GrantOutcome::Denied, None)
+                };
+                record(&mut self.endpoint.inner, &mut request, denied).await;
+
                 let mut error = AccessTokenError::default();
                 error.set_type(AccessTokenErrorType::InvalidClient);
                 let mut json = ErrorDescription::new(error);
@@ -182,6 +235,9 @@ where
                 response
                     .client_error()
                     .map_err(|err| self.endpoint.inner.web_error(err))?;
+                response
+                    .no_store()
+                    .map_err(|err| self.endpoint.inner.web_error(err))?;
                 response
                     .body_json(&json.to_json())
                     .map_err(|err| self.endpoint.inner.web_error(err))?;
@@ -189,40 +245,86 @@ where
             }
         };
 
+        let owner = Some(owner_id.clone());
+        let scope = Some(pending.as_solicitation().pre_grant().scope.clone());
         let token = match pending
             .issue(&mut self.endpoint, owner_id, self.allow_refresh_token)
             .await
         {
             Err(error) => {
-                return client_credentials_error(&mut self.endpoint.inner, &mut request, error)
+                let refused = GrantRecord {
+                    scope,
+                    error: error.code(),
+                    ..decided(error_outcome(&error), owner)
+                };
+                record(&mut self.endpoint.inner, &mut request, refused).await;
+                return client_credentials_error(&mut self.endpoint.inner, &mut request, error).await;
             }
             Ok(token) => token,
         };
 
+        let issued = GrantRecord {
+            scope,
+            ..decided(GrantOutcome::Issued, owner)
+        };
+        let body = token_json(
+            &mut self.endpoint.inner,
+            &mut request,
+            token.to_response(),
+            &issued,
+        )
+        .await;
+        record(&mut self.endpoint.inner, &mut request, issued).await;
+
         let mut response = self.endpoint.inner.response(&mut request, Template::new_ok())?;
         response
-            .body_json(&token.to_json())
+            .no_store()
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        response
+            .body_json(&body)
             .map_err(|err| self.endpoint.inner.web_error(err))?;
         Ok(response)
     }
+
+    /// Consult the rate limiter of the endpoint, if there is one.
+    async fn rate_limited(&mut self, request: &mut R) -> Option<Result<R::Response, E::Error>> {
+        // Only parse the client from the request when there is a limiter to consult.
+        self.endpoint.inner.rate_limiter()?;
+        let client_id = WrappedRequest::new(request, self.allow_credentials_in_body).requesting_client();
+        let limited = LimitedRequest {
+            kind: "client_credentials",
+            client_id: client_id.as_deref(),
+        };
+        rate_limit(&mut self.endpoint.inner, request, limited).await
+    }
+}
+
+fn error_outcome(error: &ClientCredentialsError) -> GrantOutcome {
+    match error {
+        ClientCredentialsError::Primitive(_) => GrantOutcome::Failed,
+        _ => GrantOutcome::Denied,
+    }
 }
 
-fn client_credentials_error<E: Endpoint<R>, R: WebRequest>(
+async fn client_credentials_error<E: Endpoint<R>, R: WebRequest + Send>(
     endpoint: &mut E, request: &mut R, error: ClientCredentialsError,
 ) -> Result<R::Response, E::Error> {
     Ok(match error {
         ClientCredentialsError::Ignore => return Err(endpoint.error(OAuthError::DenySilently)),
         ClientCredentialsError::Invalid(mut json) => {
+            explain_access_token_error(endpoint, request, json.description()).await;
             let mut response =
                 endpoint.response(request, Template::new_bad(Some(json.description())))?;
 
             response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
         ClientCredentialsError::Unauthorized(mut json, scheme) => {
+            explain_access_token_error(endpoint, request, json.description()).await;
             let mut response = endpoint.response(
                 request,
                 Template::new_unauthorized(None, Some(json.description())),
@@ -231,6 +333,7 @@ fn client_credentials_error<E: Endpoint<R>, R: WebRequest>(
             response
                 .unauthorized(&scheme)
                 .map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
@@ -262,6 +365,16 @@ impl<E: Endpoint<R>, R: WebRequest> ClientCredentialsEndpoint for WrappedToken<E
             .and_then(super::Extension::client_credentials)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn grant_policy(&mut self) -> Option<&mut (dyn GrantPolicy + Send)> {
+        self.inner.grant_policy()
+    }
+
+    fn bind(&mut self, grant: &mut Grant) {
+        if let Some(binding) = &self.binding {
+            bind_grant(grant, binding);
+        }
+    }
 }
 
 impl<R: WebRequest> WrappedRequest<R> {
@@ -299,6 +412,14 @@ impl<R: WebRequest> WrappedRequest<R> {
         }
     }
 
+    /// The client as claimed by the request, whether it could be authenticated or not.
+    fn requesting_client(&self) -> Option<String> {
+        match &self.authorization {
+            Some(Authorization(username, _)) => Some(username.clone()),
+            None => self.body.unique_value("client_id").map(Cow::into_owned),
+        }
+    }
+
     fn parse_header(header: Cow<str>) -> Result<Authorization, Invalid> {
         let authorization = {
             let auth_data = match is_authorization_method(&header, "Basic ") {
@@ -308,7 +429,7 @@ impl<R: WebRequest> WrappedRequest<R> {
 
             let combined = match STANDARD.decode(auth_data) {
                 Err(_) => return Err(Invalid),
-                Ok(vec) => vec,
+                Ok(vec) => Zeroizing::new(vec),
             };
 
             let mut split = combined.splitn(2, |&c| c == b':');
@@ -326,7 +447,7 @@ impl<R: WebRequest> WrappedRequest<R> {
                 Ok(client) => client,
             };
 
-            Authorization(client.to_string(), passwd.to_vec())
+            Authorization(client.to_string(), Zeroizing::new(passwd.to_vec()))
         };
 
         Ok(authorization)
@@ -1,16 +1,28 @@
+use std::collections::HashMap;
+
 use async_trait::async_trait;
-use oxide_auth::endpoint::{OAuthError, Template, WebRequest, OwnerConsent, Solicitation, Scopes};
+use oxide_auth::code_grant::accesstoken::{ErrorDescription, TokenResponse};
+use oxide_auth::code_grant::error::{AccessTokenError, AccessTokenErrorType, AuthorizationError};
+use oxide_auth::endpoint::{
+    BindingDecision, GrantDecision, GrantEvent, GrantRecord, IdempotentResponse, LimitedRequest,
+    Metrics, OAuthError, RateDecision, RequestContext, Template, WebRequest, WebResponse, OwnerConsent,
+    Solicitation, Scope, ScopeMatching,
+};
+use oxide_auth::primitives::grant::Grant;
+use serde_json::Value as JsonValue;
 
 pub use crate::code_grant::access_token::{Extension as AccessTokenExtension};
 pub use crate::code_grant::authorization::Extension as AuthorizationExtension;
 pub use crate::code_grant::client_credentials::{Extension as ClientCredentialsExtension};
-use crate::primitives::{Authorizer, Registrar, Issuer};
+use crate::primitives::{Authorizer, ConsentStore, NonceStore, Registrar, Issuer, SessionStore};
 
 pub mod authorization;
 pub mod access_token;
 pub mod client_credentials;
 pub mod refresh;
 pub mod resource;
+pub mod revocation;
+mod trace;
 
 pub trait Endpoint<Request>
 where
@@ -47,7 +59,7 @@ where
     ///
     /// The client must fulfill any one scope, so returning an empty slice will always deny the
     /// request.
-    fn scopes(&mut self) -> Option<&mut dyn Scopes<Request>>;
+    fn scopes(&mut self) -> Option<&mut (dyn Scopes<Request> + Send)>;
 
     /// Generate a prototype response.
     ///
@@ -69,6 +81,94 @@ where
     fn extension(&mut self) -> Option<&mut (dyn Extension + Send)> {
         None
     }
+
+    /// An outbox receiving a record of each decided grant.
+    ///
+    /// Returning `None` is the default implementation and disables recording.
+    fn outbox(&mut self) -> Option<&mut (dyn Outbox<Request> + Send)> {
+        None
+    }
+
+    /// Adds members to the response of each issued token.
+    ///
+    /// Returning `None` is the default implementation and leaves responses unchanged.
+    fn token_customizer(&mut self) -> Option<&mut (dyn TokenResponseCustomizer<Request> + Send)> {
+        None
+    }
+
+    /// Explains the errors reported to clients.
+    ///
+    /// Returning `None` is the default implementation and sends errors without description.
+    fn error_customizer(&mut self) -> Option<&mut (dyn ErrorCustomizer<Request> + Send)> {
+        None
+    }
+
+    /// Remembers the consent of resource owners.
+    ///
+    /// Returning `None` is the default implementation and asks owners for consent on every request.
+    fn consent_store(&mut self) -> Option<&mut (dyn ConsentStore + Send)> {
+        None
+    }
+
+    /// Decides the scope of authorization requests per client and owner.
+    ///
+    /// Returning `None` is the default implementation and grants the negotiated scope.
+    fn scope_policy(&mut self) -> Option<&mut (dyn ScopePolicy<Request> + Send)> {
+        None
+    }
+
+    /// Collects metrics of the flows.
+    ///
+    /// Returning `None` is the default implementation and collects nothing.
+    fn metrics(&mut self) -> Option<&(dyn Metrics + Sync)> {
+        None
+    }
+
+    /// Limits the rate of requests to the token endpoint.
+    ///
+    /// Returning `None` is the default implementation and processes all requests.
+    fn rate_limiter(&mut self) -> Option<&mut (dyn RateLimiter<Request> + Send)> {
+        None
+    }
+
+    /// Decides over grants just before they are issued.
+    ///
+    /// Returning `None` is the default implementation and issues all grants unchanged.
+    fn grant_policy(&mut self) -> Option<&mut (dyn GrantPolicy + Send)> {
+        None
+    }
+
+    /// Remembers token responses, to replay them to retried requests.
+    ///
+    /// Returning `None` is the default implementation and processes retries as new requests.
+    fn idempotency_store(&mut self) -> Option<&mut (dyn IdempotencyStore + Send)> {
+        None
+    }
+
+    /// Tracks the authenticated sessions of resource owners.
+    ///
+    /// With a store the authorization flow honors the `prompt=none` and `max_age` parameters, as
+    /// the synchronous flow does. Returning `None` is the default implementation and leaves
+    /// authentication to the owner solicitor.
+    fn session_store(&mut self) -> Option<&mut (dyn SessionStore + Send)> {
+        None
+    }
+
+    /// Provides the nonces that DPoP proofs must contain, checked by [`require_dpop_nonce`].
+    ///
+    /// Returning `None` is the default implementation and accepts proofs without a nonce.
+    ///
+    /// [`require_dpop_nonce`]: fn.require_dpop_nonce.html
+    fn nonce_store(&mut self) -> Option<&mut (dyn NonceStore + Send)> {
+        None
+    }
+
+    /// Binds issued tokens to the context of requests, checked by the resource flow.
+    ///
+    /// Returning `None` is the default implementation and issues unbound tokens.
+    fn binding_policy(&mut self) -> Option<&mut (dyn BindingPolicy + Send)> {
+        None
+    }
 }
 
 pub trait Extension {
@@ -90,9 +190,13 @@ pub trait Extension {
 
 /// Checks consent with the owner of a resource, identified in a request.
 ///
-/// See [`frontends::simple`] for an implementation that permits arbitrary functions.
+/// The check may perform I/O, for example look up the session of the owner in a store, ask an
+/// identity provider or query previously given consent from a database. See [`FnSolicitor`] for
+/// an implementation that permits arbitrary functions returning futures, and [`Blocking`] to use a
+/// synchronous solicitor.
 ///
-/// [`frontends::simple`]: ../frontends/simple/endpoint/struct.FnSolicitor.html
+/// [`FnSolicitor`]: ../frontends/simple/endpoint/struct.FnSolicitor.html
+/// [`Blocking`]: ../frontends/simple/endpoint/struct.Blocking.html
 #[async_trait]
 pub trait OwnerSolicitor<Request: WebRequest> {
     /// Ensure that a user (resource owner) is currently authenticated (for example via a session
@@ -100,17 +204,483 @@ pub trait OwnerSolicitor<Request: WebRequest> {
     async fn check_consent(
         &mut self, req: &mut Request, solicitation: Solicitation<'_>,
     ) -> OwnerConsent<Request::Response>;
+
+    /// The resource owner already authenticated with the request, without interacting with them.
+    ///
+    /// The authorization flow uses this to look up remembered consent before checking consent.
+    /// Returning `None` is the default implementation and always checks consent.
+    async fn owner_id(&mut self, _: &mut Request) -> Option<String> {
+        None
+    }
+
+    /// The id of the session the request was made in, for example read from a session cookie.
+    ///
+    /// Returning `None` is the default implementation and treats every request as made outside a
+    /// session.
+    async fn session_id(&mut self, _: &mut Request) -> Option<String> {
+        None
+    }
 }
 
 #[async_trait]
-impl<T, Request: WebRequest> OwnerSolicitor<Request> for T
+impl<S, Request> OwnerSolicitor<Request> for &mut S
 where
-    T: oxide_auth::endpoint::OwnerSolicitor<Request> + ?Sized + Send,
-    Request: Send,
+    S: OwnerSolicitor<Request> + ?Sized + Send,
+    Request: WebRequest + Send,
 {
     async fn check_consent(
         &mut self, req: &mut Request, solicitation: Solicitation<'_>,
     ) -> OwnerConsent<Request::Response> {
-        oxide_auth::endpoint::OwnerSolicitor::check_consent(self, req, solicitation)
+        (**self).check_consent(req, solicitation).await
+    }
+
+    async fn owner_id(&mut self, req: &mut Request) -> Option<String> {
+        (**self).owner_id(req).await
+    }
+
+    async fn session_id(&mut self, req: &mut Request) -> Option<String> {
+        (**self).session_id(req).await
+    }
+}
+
+#[async_trait]
+impl<S, Request> OwnerSolicitor<Request> for Box<S>
+where
+    S: OwnerSolicitor<Request> + ?Sized + Send,
+    Request: WebRequest + Send,
+{
+    async fn check_consent(
+        &mut self, req: &mut Request, solicitation: Solicitation<'_>,
+    ) -> OwnerConsent<Request::Response> {
+        (**self).check_consent(req, solicitation).await
+    }
+
+    async fn owner_id(&mut self, req: &mut Request) -> Option<String> {
+        (**self).owner_id(req).await
+    }
+
+    async fn session_id(&mut self, req: &mut Request) -> Option<String> {
+        (**self).session_id(req).await
+    }
+}
+
+/// Determines the scopes required to access a resource.
+///
+/// Any synchronous `Scopes` implementation is usable as well. Implement this directly when the
+/// scopes of a resource are, for example, looked up from a policy service.
+#[async_trait]
+pub trait Scopes<Request: WebRequest> {
+    /// A list of alternative scopes.
+    ///
+    /// One of the scopes needs to be fulfilled by the access token in the request to grant access.
+    /// If the slice is empty, then no scope can be fulfilled and the request is always blocked.
+    async fn scopes(&mut self, request: &mut Request) -> &[Scope];
+
+    /// How the scope of the access token is matched against these scopes.
+    fn matching(&mut self) -> ScopeMatching {
+        ScopeMatching::Exact
+    }
+}
+
+#[async_trait]
+impl<T, Request: WebRequest> Scopes<Request> for T
+where
+    T: oxide_auth::endpoint::Scopes<Request> + ?Sized + Send,
+    Request: Send,
+{
+    async fn scopes(&mut self, request: &mut Request) -> &[Scope] {
+        oxide_auth::endpoint::Scopes::scopes(self, request)
+    }
+
+    fn matching(&mut self) -> ScopeMatching {
+        oxide_auth::endpoint::Scopes::matching(self)
+    }
+}
+
+/// Receives a record of every grant decided by a flow.
+///
+/// The flow awaits the record before returning its response, so an outbox may write to a
+/// database directly. Any synchronous `Outbox` implementation is usable as well.
+#[async_trait]
+pub trait Outbox<Request: WebRequest> {
+    /// Record the outcome of a flow.
+    async fn record(&mut self, request: &mut Request, record: GrantRecord);
+}
+
+#[async_trait]
+impl<T, Request: WebRequest> Outbox<Request> for T
+where
+    T: oxide_auth::endpoint::Outbox<Request> + ?Sized + Send,
+    Request: Send,
+{
+    async fn record(&mut self, request: &mut Request, record: GrantRecord) {
+        oxide_auth::endpoint::Outbox::record(self, request, record)
+    }
+}
+
+/// Adds members to the response of an issued token.
+///
+/// The async counterpart of the customizer in `oxide_auth`, so that members can be looked up from
+/// another service. Any synchronous `TokenResponseCustomizer` implementation is usable as well.
+#[async_trait]
+pub trait TokenResponseCustomizer<Request: WebRequest> {
+    /// Add, change or remove additional members of the token response.
+    async fn customize(
+        &mut self, request: &mut Request, grant: &GrantRecord,
+        additional: &mut HashMap<String, JsonValue>,
+    );
+}
+
+#[async_trait]
+impl<T, Request: WebRequest> TokenResponseCustomizer<Request> for T
+where
+    T: oxide_auth::endpoint::TokenResponseCustomizer<Request> + ?Sized + Send,
+    Request: Send,
+{
+    async fn customize(
+        &mut self, request: &mut Request, grant: &GrantRecord,
+        additional: &mut HashMap<String, JsonValue>,
+    ) {
+        oxide_auth::endpoint::TokenResponseCustomizer::customize(self, request, grant, additional)
+    }
+}
+
+/// Explains errors to the client, for example with a localized description.
+///
+/// Like the synchronous customizer in `oxide_auth`, only the description, `error_uri` and
+/// additional members of an error can be changed. Any synchronous `ErrorCustomizer`
+/// implementation is usable as well.
+#[async_trait]
+pub trait ErrorCustomizer<Request: WebRequest> {
+    /// Explain an error sent to the client in the redirect of an authorization request.
+    async fn authorization_error(&mut self, request: &mut Request, error: &mut AuthorizationError);
+
+    /// Explain an error sent to the client in the body of a token response.
+    async fn access_token_error(&mut self, request: &mut Request, error: &mut AccessTokenError);
+}
+
+#[async_trait]
+impl<T, Request: WebRequest> ErrorCustomizer<Request> for T
+where
+    T: oxide_auth::endpoint::ErrorCustomizer<Request> + ?Sized + Send,
+    Request: Send,
+{
+    async fn authorization_error(&mut self, request: &mut Request, error: &mut AuthorizationError) {
+        oxide_auth::endpoint::ErrorCustomizer::authorization_error(self, request, error)
+    }
+
+    async fn access_token_error(&mut self, request: &mut Request, error: &mut AccessTokenError) {
+        oxide_auth::endpoint::ErrorCustomizer::access_token_error(self, request, error)
+    }
+}
+
+/// Decides the scope of authorization requests per client and resource owner.
+///
+/// The decision may perform I/O, for example look up the roles of the owner. Any synchronous
+/// `ScopePolicy` implementation is usable as well.
+#[async_trait]
+pub trait ScopePolicy<Request: WebRequest> {
+    /// The scope to grant, or `None` to deny the request.
+    ///
+    /// The returned scope must not exceed the negotiated scope of the pre grant, otherwise the
+    /// request fails.
+    async fn decide(
+        &mut self, request: &mut Request, owner_id: Option<&str>, solicitation: Solicitation<'_>,
+    ) -> Option<Scope>;
+}
+
+#[async_trait]
+impl<T, Request: WebRequest> ScopePolicy<Request> for T
+where
+    T: oxide_auth::endpoint::ScopePolicy<Request> + ?Sized + Send,
+    Request: Send,
+{
+    async fn decide(
+        &mut self, request: &mut Request, owner_id: Option<&str>, solicitation: Solicitation<'_>,
+    ) -> Option<Scope> {
+        oxide_auth::endpoint::ScopePolicy::decide(self, request, owner_id, solicitation)
+    }
+}
+
+/// Limits the rate of requests to the token endpoint.
+///
+/// The decision may perform I/O, for example count requests in a store shared between servers.
+/// Any synchronous `RateLimiter` implementation is usable as well.
+#[async_trait]
+pub trait RateLimiter<Request: WebRequest> {
+    /// Decide whether the request is processed.
+    async fn check(&mut self, request: &mut Request, limited: &LimitedRequest<'_>) -> RateDecision;
+}
+
+#[async_trait]
+impl<T, Request: WebRequest> RateLimiter<Request> for T
+where
+    T: oxide_auth::endpoint::RateLimiter<Request> + ?Sized + Send,
+    Request: Send,
+{
+    async fn check(&mut self, request: &mut Request, limited: &LimitedRequest<'_>) -> RateDecision {
+        oxide_auth::endpoint::RateLimiter::check(self, request, limited)
+    }
+}
+
+/// Decides over grants just before they are issued.
+///
+/// The decision may perform I/O, for example ask a central policy engine. Any synchronous
+/// `GrantPolicy` implementation is usable as well.
+#[async_trait]
+pub trait GrantPolicy {
+    /// Allow, change or deny the grant about to be issued.
+    ///
+    /// The same bounds as for the synchronous `GrantPolicy` apply to changes of the grant.
+    async fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision;
+}
+
+#[async_trait]
+impl<T> GrantPolicy for T
+where
+    T: oxide_auth::endpoint::GrantPolicy + ?Sized + Send,
+{
+    async fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+        oxide_auth::endpoint::GrantPolicy::decide(self, event, grant)
+    }
+}
+
+/// Remembers the responses of token requests carrying an idempotency key.
+///
+/// The store may perform I/O, for example to share responses between replicas. Any synchronous
+/// `IdempotencyStore` implementation is usable as well.
+#[async_trait]
+pub trait IdempotencyStore {
+    /// The response remembered under a key, unless it has been forgotten.
+    async fn replay(&mut self, key: &str) -> Option<IdempotentResponse>;
+
+    /// Remember the response to a request with a key.
+    async fn remember(&mut self, key: &str, response: IdempotentResponse);
+}
+
+#[async_trait]
+impl<T> IdempotencyStore for T
+where
+    T: oxide_auth::endpoint::IdempotencyStore + ?Sized + Send,
+{
+    async fn replay(&mut self, key: &str) -> Option<IdempotentResponse> {
+        oxide_auth::endpoint::IdempotencyStore::replay(self, key)
+    }
+
+    async fn remember(&mut self, key: &str, response: IdempotentResponse) {
+        oxide_auth::endpoint::IdempotencyStore::remember(self, key, response)
     }
 }
+
+/// Binds issued tokens to the context of the request they were issued to.
+///
+/// The policy may perform I/O, for example to look up the network of an address. Any synchronous
+/// `BindingPolicy` implementation is usable as well.
+#[async_trait]
+pub trait BindingPolicy {
+    /// The binding of a token issued to a request with this context, or `None` to not bind it.
+    async fn bind(&mut self, context: &RequestContext) -> Option<String>;
+
+    /// Decide over a resource request presenting a token with a binding.
+    async fn check(&mut self, binding: &str, context: Option<&RequestContext>) -> BindingDecision;
+}
+
+#[async_trait]
+impl<T> BindingPolicy for T
+where
+    T: oxide_auth::endpoint::BindingPolicy + ?Sized + Send,
+{
+    async fn bind(&mut self, context: &RequestContext) -> Option<String> {
+        oxide_auth::endpoint::BindingPolicy::bind(self, context)
+    }
+
+    async fn check(&mut self, binding: &str, context: Option<&RequestContext>) -> BindingDecision {
+        oxide_auth::endpoint::BindingPolicy::check(self, binding, context)
+    }
+}
+
+/// The binding of tokens issued to the request, if the endpoint has a binding policy.
+async fn context_binding<R, E>(endpoint: &mut E, request: &mut R) -> Option<String>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint.binding_policy()?;
+    let context = request.context()?.into_owned();
+    endpoint.binding_policy()?.bind(&context).await
+}
+
+/// Pass a record to the outbox of the endpoint, if there is one.
+async fn record<R, E>(endpoint: &mut E, request: &mut R, record: GrantRecord)
+where
+    E: Endpoint<R>,
+    R: WebRequest + Send,
+{
+    trace::grant(&record);
+    if let Some(metrics) = endpoint.metrics() {
+        metrics.grant(&record);
+    }
+    if let Some(outbox) = endpoint.outbox() {
+        outbox.record(request, record).await;
+    }
+}
+
+/// Encode a token response, after the customizer of the endpoint has added its members.
+async fn token_json<R, E>(
+    endpoint: &mut E, request: &mut R, mut response: TokenResponse, grant: &GrantRecord,
+) -> String
+where
+    E: Endpoint<R>,
+    R: WebRequest + Send,
+{
+    if let Some(customizer) = endpoint.token_customizer() {
+        customizer
+            .customize(request, grant, &mut response.additional)
+            .await;
+        response
+            .additional
+            .retain(|key, _| !TokenResponse::MEMBERS.contains(&key.as_str()));
+    }
+
+    serde_json::to_string(&response).unwrap()
+}
+
+/// Let the customizer of the endpoint explain an authorization error, keeping its kind.
+async fn explain_authorization_error<R, E>(
+    endpoint: &mut E, request: &mut R, error: &mut AuthorizationError,
+) where
+    E: Endpoint<R>,
+    R: WebRequest + Send,
+{
+    trace::error(error.kind().as_ref());
+    if let Some(metrics) = endpoint.metrics() {
+        metrics.error(error.kind().as_ref());
+    }
+    if let Some(customizer) = endpoint.error_customizer() {
+        let kind = error.kind();
+        customizer.authorization_error(request, error).await;
+        error.set_type(kind);
+    }
+}
+
+/// Let the customizer of the endpoint explain an access token error, keeping its kind.
+async fn explain_access_token_error<R, E>(
+    endpoint: &mut E, request: &mut R, error: &mut AccessTokenError,
+) where
+    E: Endpoint<R>,
+    R: WebRequest + Send,
+{
+    trace::error(error.kind().as_ref());
+    if let Some(metrics) = endpoint.metrics() {
+        metrics.error(error.kind().as_ref());
+    }
+    if let Some(customizer) = endpoint.error_customizer() {
+        let kind = error.kind();
+        customizer.access_token_error(request, error).await;
+        error.set_type(kind);
+    }
+}
+
+/// Consult the rate limiter of the endpoint, answering the request if it is limited.
+///
+/// The flows of the token endpoint call this before processing a request. Handlers of extension
+/// grants and of other endpoints, such as token introspection, can call it in the same way.
+/// Returns `None` if the request is to be processed.
+pub async fn rate_limit<R, E>(
+    endpoint: &mut E, request: &mut R, limited: LimitedRequest<'_>,
+) -> Option<Result<R::Response, E::Error>>
+where
+    E: Endpoint<R>,
+    R: WebRequest + Send,
+{
+    let decision = endpoint.rate_limiter()?.check(request, &limited).await;
+    let mut error = AccessTokenError::default();
+    match decision {
+        RateDecision::Allow => return None,
+        RateDecision::SlowDown => error.set_type(AccessTokenErrorType::SlowDown),
+        RateDecision::TooManyRequests { .. } => error.explain("Too many requests"),
+    }
+
+    explain_access_token_error(endpoint, request, &mut error).await;
+    Some(rate_limited(
+        endpoint,
+        request,
+        decision,
+        ErrorDescription::new(error),
+    ))
+}
+
+fn rate_limited<R, E>(
+    endpoint: &mut E, request: &mut R, decision: RateDecision, mut json: ErrorDescription,
+) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    let mut response = endpoint.response(request, Template::new_bad(Some(json.description())))?;
+    match decision {
+        RateDecision::TooManyRequests { retry_after } => response.too_many_requests(retry_after),
+        _ => response.client_error(),
+    }
+    .map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&json.to_json())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
+
+/// Check the nonce of a DPoP proof, answering the request if it is not current.
+///
+/// As for the synchronous `require_dpop_nonce`, the `nonce` is the claim of a proof that the
+/// caller has already verified. Returns `None` if the request is to be processed, also when the
+/// endpoint has no nonce store.
+pub async fn require_dpop_nonce<R, E>(
+    endpoint: &mut E, request: &mut R, nonce: Option<&str>,
+) -> Option<Result<R::Response, E::Error>>
+where
+    E: Endpoint<R>,
+    R: WebRequest + Send,
+{
+    let store = endpoint.nonce_store()?;
+    if let Some(nonce) = nonce {
+        if store.check(nonce).await {
+            return None;
+        }
+    }
+
+    let current = match store.current().await {
+        Ok(current) => current,
+        Err(_) => return Some(Err(endpoint.error(OAuthError::PrimitiveError))),
+    };
+
+    let mut error = AccessTokenError::default();
+    error.set_type(AccessTokenErrorType::UseDpopNonce);
+    error.explain("Authorization server requires nonce in DPoP proof");
+    explain_access_token_error(endpoint, request, &mut error).await;
+    Some(nonce_required(
+        endpoint,
+        request,
+        &current,
+        ErrorDescription::new(error),
+    ))
+}
+
+fn nonce_required<R, E>(
+    endpoint: &mut E, request: &mut R, current: &str, mut json: ErrorDescription,
+) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    let mut response = endpoint.response(request, Template::new_bad(Some(json.description())))?;
+    response.client_error().map_err(|err| endpoint.web_error(err))?;
+    response
+        .set_header("DPoP-Nonce", current)
+        .map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&json.to_json())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
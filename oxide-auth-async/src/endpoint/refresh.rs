@@ -1,24 +1,38 @@
 use std::{borrow::Cow, marker::PhantomData, str::from_utf8};
 
 use base64::{engine::general_purpose::STANDARD, Engine};
+use zeroize::Zeroizing;
 use oxide_auth::{
     code_grant::refresh::{Error, Request},
-    endpoint::{WebRequest, WebResponse, OAuthError, QueryParameter, Template, NormalizedParameter},
+    endpoint::{
+        WebRequest, WebResponse, OAuthError, QueryParameter, Template, NormalizedParameter, GrantEvent,
+        GrantOutcome, GrantRecord, LimitedRequest, bind_grant,
+    },
+    primitives::grant::Grant,
 };
 
-use super::Endpoint;
+use super::{
+    context_binding, Endpoint, GrantPolicy, explain_access_token_error, rate_limit, record, token_json,
+    trace,
+};
 use crate::{
     code_grant::refresh::{refresh, Endpoint as RefreshEndpoint},
     primitives::{Issuer, Registrar},
 };
 
 /// Takes requests from clients to refresh their access tokens.
+///
+/// As in the synchronous flow, the response omits the `scope` if it is exactly the requested one,
+/// unless [`always_echo_scope`] is enabled.
+///
+/// [`always_echo_scope`]: #method.always_echo_scope
 pub struct RefreshFlow<E, R>
 where
     E: Endpoint<R>,
     R: WebRequest,
 {
     endpoint: WrappedRefresh<E, R>,
+    always_echo_scope: bool,
 }
 
 struct WrappedRefresh<E, R>
@@ -27,6 +41,7 @@ where
     R: WebRequest,
 {
     inner: E,
+    binding: Option<String>,
     r_type: PhantomData<R>,
 }
 
@@ -41,7 +56,7 @@ struct WrappedRequest<R: WebRequest> {
     error: Option<Option<R::Error>>,
 }
 
-struct Authorization(String, Vec<u8>);
+struct Authorization(String, Zeroizing<Vec<u8>>);
 
 impl<E, R> RefreshFlow<E, R>
 where
@@ -74,43 +89,108 @@ where
         Ok(RefreshFlow {
             endpoint: WrappedRefresh {
                 inner: endpoint,
+                binding: None,
                 r_type: PhantomData,
             },
+            always_echo_scope: false,
         })
     }
 
-    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let refreshed = refresh(&mut self.endpoint, &WrappedRequest::new(&mut request)).await;
+    /// Include the `scope` in every response, even when it is exactly the requested scope.
+    pub fn always_echo_scope(&mut self, always: bool) {
+        self.always_echo_scope = always;
+    }
+
+    pub async fn execute(&mut self, request: R) -> Result<R::Response, E::Error> {
+        trace::instrument(trace::refresh(), self.run(request)).await
+    }
+
+    async fn run(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        if let Some(limited) = self.rate_limited(&mut request).await {
+            return limited;
+        }
+
+        self.endpoint.binding = context_binding(&mut self.endpoint.inner, &mut request).await;
+        let (refreshed, client_id) = {
+            let wrapped = WrappedRequest::new(&mut request);
+            let refreshed = refresh(&mut self.endpoint, &wrapped).await;
+            (refreshed, wrapped.requesting_client())
+        };
 
         let token = match refreshed {
-            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Err(error) => {
+                let outcome = match error {
+                    Error::Primitive => GrantOutcome::Failed,
+                    _ => GrantOutcome::Denied,
+                };
+                let refused = GrantRecord {
+                    client_id,
+                    error: error.code(),
+                    ..GrantRecord::new(GrantEvent::Refresh, outcome)
+                };
+                record(&mut self.endpoint.inner, &mut request, refused).await;
+                return token_error(&mut self.endpoint.inner, &mut request, error).await;
+            }
             Ok(token) => token,
         };
 
+        let refreshed = GrantRecord {
+            client_id: Some(token.client_id().to_owned()),
+            owner_id: token.owner_id().map(str::to_owned),
+            scope: Some(token.scope().clone()),
+            ..GrantRecord::new(GrantEvent::Refresh, GrantOutcome::Issued)
+        };
+        let mut response = token.to_response();
+        if self.always_echo_scope {
+            response.scope = Some(token.scope().to_string());
+        }
+        let body = token_json(&mut self.endpoint.inner, &mut request, response, &refreshed).await;
+        record(&mut self.endpoint.inner, &mut request, refreshed).await;
+
         let mut response = self.endpoint.inner.response(&mut request, Template::new_ok())?;
         response
-            .body_json(&token.to_json())
+            .no_store()
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        response
+            .body_json(&body)
             .map_err(|err| self.endpoint.inner.web_error(err))?;
         Ok(response)
     }
+
+    /// Consult the rate limiter of the endpoint, if there is one.
+    async fn rate_limited(&mut self, request: &mut R) -> Option<Result<R::Response, E::Error>> {
+        // Only parse the client from the request when there is a limiter to consult.
+        self.endpoint.inner.rate_limiter()?;
+        let client_id = WrappedRequest::new(request).requesting_client();
+        let limited = LimitedRequest {
+            kind: "refresh_token",
+            client_id: client_id.as_deref(),
+        };
+        rate_limit(&mut self.endpoint.inner, request, limited).await
+    }
 }
 
-fn token_error<E, R>(endpoint: &mut E, request: &mut R, error: Error) -> Result<R::Response, E::Error>
+async fn token_error<E, R>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error>
 where
     E: Endpoint<R>,
-    R: WebRequest,
+    R: WebRequest + Send,
 {
     Ok(match error {
         Error::Invalid(mut json) => {
+            explain_access_token_error(endpoint, request, json.description()).await;
             let mut response =
                 endpoint.response(request, Template::new_bad(Some(json.description())))?;
             response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
         Error::Unauthorized(mut json, scheme) => {
+            explain_access_token_error(endpoint, request, json.description()).await;
             let mut response = endpoint.response(
                 request,
                 Template::new_unauthorized(None, Some(json.description())),
@@ -118,6 +198,7 @@ where
             response
                 .unauthorized(&scheme)
                 .map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
@@ -158,6 +239,14 @@ impl<'a, R: WebRequest> WrappedRequest<R> {
         }
     }
 
+    /// The client as claimed by the request, whether it could be authenticated or not.
+    fn requesting_client(&self) -> Option<String> {
+        match &self.authorization {
+            Some(Authorization(username, _)) => Some(username.clone()),
+            None => self.body.unique_value("client_id").map(Cow::into_owned),
+        }
+    }
+
     fn parse_header(header: Cow<str>) -> Result<Authorization, Option<R::Error>> {
         let authorization = {
             if !header.starts_with("Basic ") {
@@ -166,7 +255,7 @@ impl<'a, R: WebRequest> WrappedRequest<R> {
 
             let combined = match STANDARD.decode(&header[6..]) {
                 Err(_) => return Err(None),
-                Ok(vec) => vec,
+                Ok(vec) => Zeroizing::new(vec),
             };
 
             let mut split = combined.splitn(2, |&c| c == b':');
@@ -184,7 +273,7 @@ impl<'a, R: WebRequest> WrappedRequest<R> {
                 Ok(client) => client,
             };
 
-            Authorization(client.to_string(), passwd.to_vec())
+            Authorization(client.to_string(), Zeroizing::new(passwd.to_vec()))
         };
 
         Ok(authorization)
@@ -203,6 +292,16 @@ where
     fn issuer(&mut self) -> &mut (dyn Issuer + Send) {
         self.inner.issuer_mut().unwrap()
     }
+
+    fn grant_policy(&mut self) -> Option<&mut (dyn GrantPolicy + Send)> {
+        self.inner.grant_policy()
+    }
+
+    fn bind(&mut self, grant: &mut Grant) {
+        if let Some(binding) = &self.binding {
+            bind_grant(grant, binding);
+        }
+    }
 }
 
 impl<R: WebRequest> Request for WrappedRequest<R> {
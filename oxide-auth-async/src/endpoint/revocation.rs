@@ -0,0 +1,286 @@
+use std::marker::PhantomData;
+
+use oxide_auth::endpoint::GrantOutcome;
+use serde_json::json;
+
+use super::*;
+
+/// Revokes every code and token issued to a client.
+///
+/// This is an administrative operation, for example to contain a leaked client secret. As in the
+/// synchronous flow, the request names the client with the `client_id` parameter of its body and
+/// the response reports the number of revoked codes and tokens.
+///
+/// The flow does NOT authenticate the request. Only mount it behind the access control of the
+/// operators, never where clients or resource owners can reach it.
+pub struct ClientRevocationFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: E,
+    r_type: PhantomData<R>,
+}
+
+impl<E, R> ClientRevocationFlow<E, R>
+where
+    E: Endpoint<R> + Send,
+    R: WebRequest + Send,
+{
+    /// Check that the endpoint supports the necessary operations for handling requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * an `Authorizer` from `authorizer_mut`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.authorizer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(ClientRevocationFlow {
+            endpoint,
+            r_type: PhantomData,
+        })
+    }
+
+    /// Revoke the codes and tokens of the client named by the request.
+    ///
+    /// Codes are revoked first, so that none of them can be redeemed for a new token afterwards.
+    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let client_id = match request.urlbody() {
+            Ok(body) => body
+                .unique_value("client_id")
+                .map(|client_id| client_id.into_owned()),
+            Err(err) => return Err(self.endpoint.web_error(err)),
+        };
+
+        let client_id = match client_id {
+            Some(client_id) => client_id,
+            None => {
+                return invalid(
+                    &mut self.endpoint,
+                    &mut request,
+                    "The client to revoke is missing",
+                )
+            }
+        };
+
+        let revoked_codes = self
+            .endpoint
+            .authorizer_mut()
+            .unwrap()
+            .revoke_client(&client_id)
+            .await
+            .map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?;
+        let revoked_tokens = self
+            .endpoint
+            .issuer_mut()
+            .unwrap()
+            .revoke_client(&client_id)
+            .await
+            .map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?;
+
+        if revoked_tokens > 0 {
+            let mut revocation = GrantRecord::new(GrantEvent::Revocation, GrantOutcome::Issued);
+            revocation.client_id = Some(client_id.clone());
+            record(&mut self.endpoint, &mut request, revocation).await;
+        }
+
+        let body = json!({
+            "client_id": client_id,
+            "revoked_codes": revoked_codes,
+            "revoked_tokens": revoked_tokens,
+        });
+
+        let mut response = self.endpoint.response(&mut request, Template::new_ok())?;
+        response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_json(&body.to_string())
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
+
+/// Lists and withdraws the authorizations a resource owner has given to clients.
+///
+/// As in the synchronous flow, the owner is identified by `OwnerSolicitor::owner_id` and both
+/// operations fail with `DenySilently` without one. [`list`] reports the consents of the owner,
+/// [`revoke`] forgets the consent to the client named by the `client_id` parameter of its body and
+/// revokes the codes and tokens issued to it on behalf of the owner.
+///
+/// [`list`]: #method.list
+/// [`revoke`]: #method.revoke
+pub struct OwnerGrantsFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: E,
+    r_type: PhantomData<R>,
+}
+
+impl<E, R> OwnerGrantsFlow<E, R>
+where
+    E: Endpoint<R> + Send,
+    R: WebRequest + Send,
+{
+    /// Check that the endpoint supports the necessary operations for handling requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * an `OwnerSolicitor` from `owner_solicitor`
+    /// * a `ConsentStore` from `consent_store`
+    /// * an `Authorizer` from `authorizer_mut`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.owner_solicitor().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.consent_store().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.authorizer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(OwnerGrantsFlow {
+            endpoint,
+            r_type: PhantomData,
+        })
+    }
+
+    /// List the clients the owner of the request has consented to.
+    pub async fn list(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let owner_id = self.owner(&mut request).await?;
+        let clients: Vec<_> = self
+            .endpoint
+            .consent_store()
+            .unwrap()
+            .consents(&owner_id)
+            .await
+            .into_iter()
+            .map(|consent| {
+                json!({
+                    "client_id": consent.client_id,
+                    "scope": consent.scope.to_string(),
+                })
+            })
+            .collect();
+
+        let body = json!({
+            "owner_id": owner_id,
+            "clients": clients,
+        });
+        self.respond(&mut request, body)
+    }
+
+    /// Withdraw the authorization of the client named by the request.
+    ///
+    /// Codes are revoked before tokens, so that none of them can be redeemed for a new token
+    /// afterwards.
+    pub async fn revoke(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let owner_id = self.owner(&mut request).await?;
+        let client_id = match request.urlbody() {
+            Ok(body) => body
+                .unique_value("client_id")
+                .map(|client_id| client_id.into_owned()),
+            Err(err) => return Err(self.endpoint.web_error(err)),
+        };
+
+        let client_id = match client_id {
+            Some(client_id) => client_id,
+            None => {
+                return invalid(
+                    &mut self.endpoint,
+                    &mut request,
+                    "The client to revoke is missing",
+                )
+            }
+        };
+
+        let consent = self
+            .endpoint
+            .consent_store()
+            .unwrap()
+            .revoke(&owner_id, &client_id)
+            .await;
+        let revoked_codes = self
+            .endpoint
+            .authorizer_mut()
+            .unwrap()
+            .revoke_grant(&owner_id, &client_id)
+            .await
+            .map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?;
+        let revoked_tokens = self
+            .endpoint
+            .issuer_mut()
+            .unwrap()
+            .revoke_grant(&owner_id, &client_id)
+            .await
+            .map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?;
+
+        if revoked_tokens > 0 {
+            let mut revocation = GrantRecord::new(GrantEvent::Revocation, GrantOutcome::Issued);
+            revocation.client_id = Some(client_id.clone());
+            revocation.owner_id = Some(owner_id);
+            record(&mut self.endpoint, &mut request, revocation).await;
+        }
+
+        let body = json!({
+            "client_id": client_id,
+            "consent": consent,
+            "revoked_codes": revoked_codes,
+            "revoked_tokens": revoked_tokens,
+        });
+        self.respond(&mut request, body)
+    }
+
+    async fn owner(&mut self, request: &mut R) -> Result<String, E::Error> {
+        match self.endpoint.owner_solicitor().unwrap().owner_id(request).await {
+            Some(owner_id) => Ok(owner_id),
+            None => Err(self.endpoint.error(OAuthError::DenySilently)),
+        }
+    }
+
+    fn respond(&mut self, request: &mut R, body: serde_json::Value) -> Result<R::Response, E::Error> {
+        let mut response = self.endpoint.response(request, Template::new_ok())?;
+        response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_json(&body.to_string())
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
+
+/// Answer a malformed request with `invalid_request`.
+fn invalid<E, R>(
+    endpoint: &mut E, request: &mut R, explanation: &'static str,
+) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    let mut error = AccessTokenError::default();
+    error.set_type(AccessTokenErrorType::InvalidRequest);
+    error.explain(explanation);
+    let mut json = ErrorDescription::new(error);
+
+    let mut response = endpoint.response(request, Template::new_bad(Some(json.description())))?;
+    response.client_error().map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&json.to_json())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
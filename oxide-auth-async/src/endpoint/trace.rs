@@ -0,0 +1,146 @@
+//! Spans describing the flows, with the `tracing` feature.
+//!
+//! The same spans and fields as those of the synchronous flows in `oxide_auth`. Each flow future
+//! is instrumented with its span, so that fields can be recorded in the current span. The span is
+//! created when the flow future is first polled, as a child of the span current at that time.
+use std::future::Future;
+
+#[cfg(feature = "tracing")]
+use tracing::{field::Empty, Instrument, Span};
+
+use oxide_auth::endpoint::{GrantOutcome, GrantRecord, Scope};
+
+/// The span of a single flow.
+pub(crate) struct FlowSpan {
+    #[cfg(feature = "tracing")]
+    span: Span,
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! flow_span {
+    ($name:literal $(, $grant_type:literal)?) => {
+        FlowSpan {
+            span: tracing::info_span!(
+                target: "oxide_auth",
+                $name,
+                client_id = Empty,
+                $(grant_type = $grant_type,)?
+                outcome = Empty,
+                error = Empty,
+                otel.kind = "internal",
+                otel.status_code = Empty,
+                enduser.id = Empty,
+                enduser.scope = Empty,
+            ),
+        }
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! flow_span {
+    ($name:literal $(, $grant_type:literal)?) => {
+        FlowSpan {}
+    };
+}
+
+pub(crate) fn authorization() -> FlowSpan {
+    flow_span!("authorization")
+}
+
+pub(crate) fn access_token() -> FlowSpan {
+    flow_span!("access_token", "authorization_code")
+}
+
+pub(crate) fn refresh() -> FlowSpan {
+    flow_span!("refresh", "refresh_token")
+}
+
+pub(crate) fn client_credentials() -> FlowSpan {
+    flow_span!("client_credentials", "client_credentials")
+}
+
+pub(crate) fn resource() -> FlowSpan {
+    flow_span!("resource")
+}
+
+/// Run a flow in its span.
+#[cfg(feature = "tracing")]
+pub(crate) async fn instrument<F: Future>(span: FlowSpan, flow: F) -> F::Output {
+    flow.instrument(span.span).await
+}
+
+/// Run a flow in its span.
+#[cfg(not(feature = "tracing"))]
+pub(crate) async fn instrument<F: Future>(_: FlowSpan, flow: F) -> F::Output {
+    flow.await
+}
+
+/// Record the decided grant in the current flow span.
+pub(crate) fn grant(record: &GrantRecord) {
+    if let Some(client_id) = &record.client_id {
+        client(client_id);
+    }
+
+    if let Some(owner_id) = &record.owner_id {
+        owner(owner_id);
+    }
+
+    if let Some(scope) = &record.scope {
+        scope_of(scope);
+    }
+
+    outcome(match record.outcome {
+        GrantOutcome::Issued => "issued",
+        GrantOutcome::Denied => "denied",
+        GrantOutcome::Failed => "failed",
+    });
+}
+
+/// Record the client of the current flow span.
+#[cfg(feature = "tracing")]
+pub(crate) fn client(client_id: &str) {
+    Span::current().record("client_id", client_id);
+}
+
+/// Record the resource owner of the current flow span.
+#[cfg(feature = "tracing")]
+pub(crate) fn owner(owner_id: &str) {
+    Span::current().record("enduser.id", owner_id);
+}
+
+/// Record the granted scope in the current flow span.
+#[cfg(feature = "tracing")]
+fn scope_of(scope: &Scope) {
+    Span::current().record("enduser.scope", scope.to_string().as_str());
+}
+
+/// Record the outcome of the current flow span, marking failed flows as errors.
+#[cfg(feature = "tracing")]
+pub(crate) fn outcome(outcome: &str) {
+    let span = Span::current();
+    span.record("outcome", outcome);
+    if outcome == "failed" {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+/// Record the error code sent to the client in the current flow span.
+#[cfg(feature = "tracing")]
+pub(crate) fn error(code: &str) {
+    Span::current().record("error", code);
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn client(_: &str) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn owner(_: &str) {}
+
+#[cfg(not(feature = "tracing"))]
+fn scope_of(_: &Scope) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn outcome(_: &str) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn error(_: &str) {}
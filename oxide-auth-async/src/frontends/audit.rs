@@ -0,0 +1,70 @@
+//! Hands audit events off to an asynchronous task.
+//!
+//! Audit sinks are called synchronously by the flows, before the response is sent. Writing events
+//! to a database or a log shipper from there would delay every response. [`ChannelSink`] instead
+//! queues the events on a bounded channel, to be drained by a task of your runtime.
+//!
+//! [`ChannelSink`]: struct.ChannelSink.html
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures_channel::mpsc::{self, Receiver, Sender};
+use oxide_auth::frontends::audit::{AuditEvent, AuditSink};
+
+/// An audit sink sending events to a bounded channel.
+///
+/// Events are dropped instead of blocking the flow when the channel is full, or when the receiver
+/// has gone away. The number of dropped events is counted, so that it can be monitored.
+#[derive(Clone)]
+pub struct ChannelSink {
+    sender: Sender<AuditEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl ChannelSink {
+    /// A sink with room for `capacity` queued events, and the receiver of its events.
+    pub fn new(capacity: usize) -> (Self, Receiver<AuditEvent>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let sink = ChannelSink {
+            sender,
+            dropped: Arc::default(),
+        };
+        (sink, receiver)
+    }
+
+    /// The number of events that could not be queued.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl AuditSink for ChannelSink {
+    fn event(&mut self, event: AuditEvent) {
+        if self.sender.try_send(event).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::endpoint::{GrantEvent, GrantOutcome, GrantRecord};
+    use oxide_auth::frontends::audit::{AuditKind, RequestMetadata};
+
+    fn event() -> AuditEvent {
+        let record = GrantRecord::new(GrantEvent::Token, GrantOutcome::Issued);
+        AuditEvent::from_record(record, RequestMetadata::default()).unwrap()
+    }
+
+    #[test]
+    fn queues_until_full() {
+        let (mut sink, mut receiver) = ChannelSink::new(0);
+        sink.event(event());
+        sink.event(event());
+        assert_eq!(sink.dropped(), 1);
+
+        let received = receiver.try_recv().unwrap();
+        assert_eq!(received.kind, AuditKind::TokenIssued);
+    }
+}
@@ -0,0 +1,111 @@
+//! Streams the outcomes of flows to the embedding application.
+//!
+//! An [`EventStream`] is an outbox broadcasting each `GrantRecord` to all of its subscribers. Each
+//! subscription is a `Stream` of records, so that a task can update session stores, metrics or a
+//! user interface as grants are decided, without polling the issuer:
+//!
+//! ```no_run
+//! # use futures_util::StreamExt;
+//! # use oxide_auth_async::frontends::events::EventStream;
+//! # async fn watch() {
+//! let events = EventStream::new();
+//! let mut records = events.subscribe(64);
+//! // Return a clone of `events` from `Endpoint::outbox`, then in a task of your runtime:
+//! while let Some(record) = records.next().await {
+//!     println!("{:?} for {:?}", record.outcome, record.client_id);
+//! }
+//! # }
+//! ```
+//!
+//! Flows never wait on a subscriber. Records are dropped for a subscriber whose queue is full, and
+//! dropped subscriptions are forgotten with the next record.
+//!
+//! [`EventStream`]: struct.EventStream.html
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_channel::mpsc::{self, Receiver, Sender};
+use oxide_auth::endpoint::{GrantRecord, Outbox, WebRequest};
+
+/// An outbox broadcasting grant records to subscribers.
+///
+/// Clones share their subscribers, so that one clone can serve as the outbox of an endpoint while
+/// another accepts new subscriptions.
+#[derive(Clone, Default)]
+pub struct EventStream {
+    subscribers: Arc<Mutex<Vec<Sender<GrantRecord>>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl EventStream {
+    /// A stream without subscribers.
+    pub fn new() -> Self {
+        EventStream::default()
+    }
+
+    /// Receive all records published from now on, queueing up to `capacity` of them.
+    pub fn subscribe(&self, capacity: usize) -> Receiver<GrantRecord> {
+        let (sender, receiver) = mpsc::channel(capacity);
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// The number of current subscriptions.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// The number of records that could not be queued for a subscriber.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Send a record to all subscribers.
+    pub fn publish(&self, record: &GrantRecord) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain_mut(|subscriber| match subscriber.try_send(record.clone()) {
+            Ok(()) => true,
+            Err(err) if err.is_disconnected() => false,
+            Err(_) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+        });
+    }
+}
+
+impl<Request: WebRequest> Outbox<Request> for EventStream {
+    fn record(&mut self, _: &mut Request, record: GrantRecord) {
+        self.publish(&record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::endpoint::{GrantEvent, GrantOutcome};
+
+    fn record() -> GrantRecord {
+        GrantRecord::new(GrantEvent::Token, GrantOutcome::Issued)
+    }
+
+    #[test]
+    fn broadcasts_to_subscribers() {
+        let events = EventStream::new();
+        let mut first = events.subscribe(4);
+        let mut second = events.subscribe(0);
+
+        events.publish(&record());
+        events.publish(&record());
+        assert_eq!(events.dropped(), 1);
+
+        assert_eq!(first.try_recv().unwrap(), record());
+        assert_eq!(first.try_recv().unwrap(), record());
+        assert_eq!(second.try_recv().unwrap(), record());
+        assert!(second.try_recv().is_err());
+
+        drop(second);
+        events.publish(&record());
+        assert_eq!(events.subscribers(), 1);
+    }
+}
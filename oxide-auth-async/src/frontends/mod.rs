@@ -1 +1,6 @@
+pub mod audit;
+pub mod events;
 pub mod simple;
+pub mod sweep;
+#[cfg(feature = "webhooks")]
+pub mod webhook;
@@ -0,0 +1,116 @@
+//! Ad-hoc implementations of the asynchronous endpoint primitives.
+use std::future::Future;
+
+use async_trait::async_trait;
+use oxide_auth::endpoint::{OwnerConsent, Solicitation, WebRequest};
+use oxide_auth::frontends::simple::endpoint as sync;
+
+use crate::endpoint::OwnerSolicitor;
+
+/// A wrapper for functions returning futures to be used as solicitors.
+///
+/// The function inspects the request, for example to read a session cookie, and returns a future
+/// resolving to the consent of the owner. The future does not borrow the request, so it may
+/// await a session store, an identity provider or a consent database and is sent across threads
+/// with the flow.
+///
+/// ```
+/// # extern crate oxide_auth;
+/// # extern crate oxide_auth_async;
+/// use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+/// use oxide_auth::frontends::simple::request::Request;
+/// use oxide_auth_async::endpoint::OwnerSolicitor;
+/// use oxide_auth_async::frontends::simple::endpoint::FnSolicitor;
+///
+/// async fn lookup_owner(session: Option<String>, client_id: String) -> Option<String> {
+///     // Ask the session store who is logged in and whether they approved the client.
+///     session
+/// }
+///
+/// fn solicitor() -> impl OwnerSolicitor<Request> {
+///     FnSolicitor(|request: &mut Request, solicitation: Solicitation<'static>| {
+///         let session = request.auth.clone();
+///         let client_id = solicitation.pre_grant().client_id.clone();
+///         async move {
+///             match lookup_owner(session, client_id).await {
+///                 Some(owner) => OwnerConsent::Authorized(owner),
+///                 None => OwnerConsent::Denied,
+///             }
+///         }
+///     })
+/// }
+/// ```
+pub struct FnSolicitor<F>(pub F);
+
+#[async_trait]
+impl<W, F, Fut> OwnerSolicitor<W> for FnSolicitor<F>
+where
+    W: WebRequest + Send,
+    F: FnMut(&mut W, Solicitation<'static>) -> Fut + Send,
+    Fut: Future<Output = OwnerConsent<W::Response>> + Send,
+{
+    async fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation<'_>,
+    ) -> OwnerConsent<W::Response> {
+        (self.0)(request, solicitation.into_owned()).await
+    }
+}
+
+/// Use a synchronous solicitor, which can not perform I/O without blocking.
+pub struct Blocking<S>(pub S);
+
+#[async_trait]
+impl<W, S> OwnerSolicitor<W> for Blocking<S>
+where
+    W: WebRequest + Send,
+    S: oxide_auth::endpoint::OwnerSolicitor<W> + Send,
+{
+    async fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation<'_>,
+    ) -> OwnerConsent<W::Response> {
+        self.0.check_consent(request, solicitation)
+    }
+
+    async fn owner_id(&mut self, request: &mut W) -> Option<String> {
+        self.0.owner_id(request)
+    }
+
+    async fn session_id(&mut self, request: &mut W) -> Option<String> {
+        self.0.session_id(request)
+    }
+}
+
+#[async_trait]
+impl<W, F> OwnerSolicitor<W> for sync::FnSolicitor<F>
+where
+    W: WebRequest + Send,
+    F: FnMut(&mut W, Solicitation) -> OwnerConsent<W::Response> + Send,
+{
+    async fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation<'_>,
+    ) -> OwnerConsent<W::Response> {
+        oxide_auth::endpoint::OwnerSolicitor::check_consent(self, request, solicitation)
+    }
+}
+
+#[async_trait]
+impl<W> OwnerSolicitor<W> for sync::ApprovedGrant
+where
+    W: WebRequest + Send,
+{
+    async fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation<'_>,
+    ) -> OwnerConsent<W::Response> {
+        oxide_auth::endpoint::OwnerSolicitor::check_consent(self, request, solicitation)
+    }
+}
+
+#[async_trait]
+impl<W> OwnerSolicitor<W> for sync::Vacant
+where
+    W: WebRequest + Send,
+{
+    async fn check_consent(&mut self, _: &mut W, _: Solicitation<'_>) -> OwnerConsent<W::Response> {
+        OwnerConsent::Denied
+    }
+}
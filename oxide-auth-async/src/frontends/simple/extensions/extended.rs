@@ -1,11 +1,14 @@
 use oxide_auth::{
     frontends::simple::extensions::Extended,
-    endpoint::{WebRequest, Scopes, Template, OAuthError},
+    endpoint::{Metrics, WebRequest, Template, OAuthError},
 };
 
 use crate::{
-    endpoint::{Endpoint, Extension, OwnerSolicitor},
-    primitives::{Registrar, Authorizer, Issuer},
+    endpoint::{
+        BindingPolicy, Endpoint, ErrorCustomizer, Extension, GrantPolicy, IdempotencyStore, Outbox,
+        OwnerSolicitor, RateLimiter, ScopePolicy, Scopes, TokenResponseCustomizer,
+    },
+    primitives::{Registrar, Authorizer, ConsentStore, Issuer, NonceStore, SessionStore},
 };
 
 impl<Request, Inner, Ext> Endpoint<Request> for Extended<Inner, Ext>
@@ -32,7 +35,7 @@ where
         self.inner.owner_solicitor()
     }
 
-    fn scopes(&mut self) -> Option<&mut dyn Scopes<Request>> {
+    fn scopes(&mut self) -> Option<&mut (dyn Scopes<Request> + Send)> {
         self.inner.scopes()
     }
 
@@ -53,4 +56,52 @@ where
     fn extension(&mut self) -> Option<&mut (dyn Extension + Send)> {
         Some(&mut self.addons)
     }
+
+    fn outbox(&mut self) -> Option<&mut (dyn Outbox<Request> + Send)> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut (dyn TokenResponseCustomizer<Request> + Send)> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut (dyn ErrorCustomizer<Request> + Send)> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut (dyn ConsentStore + Send)> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut (dyn ScopePolicy<Request> + Send)> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&(dyn Metrics + Sync)> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut (dyn RateLimiter<Request> + Send)> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut (dyn GrantPolicy + Send)> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut (dyn IdempotencyStore + Send)> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut (dyn SessionStore + Send)> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut (dyn NonceStore + Send)> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut (dyn BindingPolicy + Send)> {
+        self.inner.binding_policy()
+    }
 }
@@ -1 +1,3 @@
+pub mod endpoint;
+
 pub mod extensions;
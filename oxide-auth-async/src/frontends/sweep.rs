@@ -0,0 +1,113 @@
+//! Removing expired codes and tokens from async stores.
+//!
+//! The async counterpart of `oxide_auth::frontends::sweep`. Every synchronous store that can be
+//! swept is also an async [`Expiring`] store, so memory and database stores can be mixed in one
+//! [`Sweeper`]. With the `sweeper` feature, [`Sweeper::run`] sweeps periodically on `tokio`,
+//! otherwise call `sweep` from a task of any runtime:
+//!
+//! ```no_run
+//! # use std::sync::{Arc, Mutex};
+//! # use oxide_auth::primitives::authorizer::AuthMap;
+//! # use oxide_auth::primitives::generator::RandomGenerator;
+//! # use oxide_auth_async::frontends::sweep::Sweeper;
+//! # async fn sweep() {
+//! let authorizer = Arc::new(Mutex::new(AuthMap::new(RandomGenerator::new(16))));
+//! let mut sweeper = Sweeper::new().store("codes", authorizer.clone());
+//! sweeper.sweep().await;
+//! # }
+//! ```
+//!
+//! [`Expiring`]: trait.Expiring.html
+//! [`Sweeper`]: struct.Sweeper.html
+//! [`Sweeper::run`]: struct.Sweeper.html#method.run
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use oxide_auth::endpoint::Metrics;
+use oxide_auth::frontends::sweep;
+
+/// A store keeping codes or tokens past their expiry.
+#[async_trait]
+pub trait Expiring {
+    /// Remove the entries expired at `now`, returning how many there were.
+    async fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()>;
+}
+
+#[async_trait]
+impl<T> Expiring for T
+where
+    T: sweep::Expiring + Send + ?Sized,
+{
+    async fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        sweep::Expiring::purge_expired(self, now)
+    }
+}
+
+/// Purges expired entries from a set of async stores.
+#[derive(Default)]
+pub struct Sweeper {
+    stores: Vec<(String, Box<dyn Expiring + Send>)>,
+    metrics: Option<Box<dyn Metrics + Send + Sync>>,
+}
+
+impl Sweeper {
+    /// A sweeper without any stores.
+    pub fn new() -> Self {
+        Sweeper::default()
+    }
+
+    /// Add a store, reported to metrics under `name`.
+    pub fn store<E>(mut self, name: &str, store: E) -> Self
+    where
+        E: Expiring + Send + 'static,
+    {
+        self.stores.push((name.to_owned(), Box::new(store)));
+        self
+    }
+
+    /// Report the removed entries of each store to a collector.
+    pub fn with_metrics<M>(mut self, metrics: M) -> Self
+    where
+        M: Metrics + Send + Sync + 'static,
+    {
+        self.metrics = Some(Box::new(metrics));
+        self
+    }
+
+    /// Purge all stores, returning how many entries were removed in total.
+    ///
+    /// A store that fails is skipped and tried again on the next sweep.
+    pub async fn sweep(&mut self) -> usize {
+        self.sweep_at(Utc::now()).await
+    }
+
+    /// Purge all stores of the entries expired at `now`.
+    pub async fn sweep_at(&mut self, now: DateTime<Utc>) -> usize {
+        let mut total = 0;
+        for (name, store) in self.stores.iter_mut() {
+            let removed = match store.purge_expired(now).await {
+                Ok(removed) => removed,
+                Err(()) => continue,
+            };
+
+            if let Some(metrics) = &self.metrics {
+                metrics.swept(name, removed);
+            }
+            total += removed;
+        }
+        total
+    }
+
+    /// Sweep once every `period`, forever.
+    ///
+    /// The first sweep happens immediately. Sweeps that are late, for example because a store was
+    /// slow, are not made up for.
+    #[cfg(feature = "sweeper")]
+    pub async fn run(mut self, period: std::time::Duration) {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.sweep().await;
+        }
+    }
+}
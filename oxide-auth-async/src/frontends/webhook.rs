@@ -0,0 +1,344 @@
+//! Notifies webhooks of audit events.
+//!
+//! The [`WebhookDispatcher`] POSTs each audit event as a JSON document to the configured urls. It
+//! is driven by the receiver of a [`ChannelSink`], so that slow or unreachable webhooks never delay
+//! a flow:
+//!
+//! ```no_run
+//! # use oxide_auth_async::frontends::{audit::ChannelSink, webhook::{Webhook, WebhookDispatcher}};
+//! # async fn drain() {
+//! let (sink, events) = ChannelSink::new(1024);
+//! let url = "https://hooks.example.com/oauth".parse().unwrap();
+//! let dispatcher = WebhookDispatcher::new(vec![Webhook::new(url, b"shared secret")]);
+//! // Hand `sink` to an `Audited` outbox or `AuditedConsent`, then in a task of your runtime:
+//! dispatcher.run(events).await;
+//! # }
+//! ```
+//!
+//! The document names the event `type`, as given by `AuditKind::as_str`, its `occurred_at` time in
+//! rfc3339 format, and the `client_id`, `owner_id`, `scope` and `remote_addr` when they are known.
+//!
+//! Each delivery is signed with the secret of its webhook. The `Webhook-Signature` header holds
+//! `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>">`, which receivers can check with
+//! [`verify`]. The `Webhook-Id` header is the same for all attempts of one delivery, so that
+//! receivers can ignore duplicates. Failed deliveries are retried with exponential backoff, unless
+//! the webhook rejected the request as malformed.
+//!
+//! [`WebhookDispatcher`]: struct.WebhookDispatcher.html
+//! [`ChannelSink`]: ../audit/struct.ChannelSink.html
+//! [`verify`]: fn.verify.html
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_channel::mpsc::Receiver;
+use futures_util::StreamExt;
+use oxide_auth::frontends::audit::{AuditEvent, AuditKind};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Map, Value};
+use subtle::ConstantTimeEq;
+use url::Url;
+use zeroize::Zeroizing;
+
+/// A url to notify, and the secret signing its notifications.
+pub struct Webhook {
+    url: Url,
+    secret: Zeroizing<Vec<u8>>,
+    kinds: Option<Vec<AuditKind>>,
+}
+
+/// Delivers audit events to webhooks.
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    hooks: Vec<Webhook>,
+    attempts: u32,
+    backoff: Duration,
+    rng: SystemRandom,
+}
+
+/// A webhook that could not be notified of an event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Undelivered {
+    /// The url of the webhook.
+    pub url: Url,
+
+    /// The status of the last response, if the webhook answered at all.
+    pub status: Option<u16>,
+}
+
+impl Webhook {
+    /// Notify `url` of all events, signing them with `secret`.
+    pub fn new(url: Url, secret: &[u8]) -> Self {
+        Webhook {
+            url,
+            secret: Zeroizing::new(secret.to_vec()),
+            kinds: None,
+        }
+    }
+
+    /// Only notify the webhook of events of the given kinds.
+    pub fn with_kinds(mut self, kinds: &[AuditKind]) -> Self {
+        self.kinds = Some(kinds.to_vec());
+        self
+    }
+
+    /// The url that is notified.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    fn wants(&self, kind: AuditKind) -> bool {
+        match &self.kinds {
+            Some(kinds) => kinds.contains(&kind),
+            None => true,
+        }
+    }
+}
+
+impl WebhookDispatcher {
+    /// Deliver to the given webhooks, with four attempts starting at a backoff of one second.
+    pub fn new(hooks: Vec<Webhook>) -> Self {
+        WebhookDispatcher {
+            client: reqwest::Client::new(),
+            hooks,
+            attempts: 4,
+            backoff: Duration::from_secs(1),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Use a preconfigured client, for example with timeouts or a proxy.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Attempt each delivery at most `attempts` times, at least once.
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Wait `backoff` before the first retry, doubling the wait for each further one.
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Notify all interested webhooks of an event.
+    ///
+    /// Returns the webhooks that could not be notified after all attempts.
+    pub async fn dispatch(&self, event: &AuditEvent) -> Vec<Undelivered> {
+        let body = payload(event).to_string();
+        let mut undelivered = Vec::new();
+
+        for hook in self.hooks.iter().filter(|hook| hook.wants(event.kind)) {
+            if let Err(status) = self.deliver(hook, &body).await {
+                undelivered.push(Undelivered {
+                    url: hook.url.clone(),
+                    status,
+                });
+            }
+        }
+
+        undelivered
+    }
+
+    /// Dispatch all events of the receiver, until its senders are gone.
+    ///
+    /// Undelivered notifications are dropped.
+    pub async fn run(&self, mut events: Receiver<AuditEvent>) {
+        while let Some(event) = events.next().await {
+            self.dispatch(&event).await;
+        }
+    }
+
+    async fn deliver(&self, hook: &Webhook, body: &str) -> Result<(), Option<u16>> {
+        let id = self.delivery_id();
+        let mut backoff = self.backoff;
+        let mut status = None;
+
+        for attempt in 0..self.attempts {
+            if attempt > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2);
+            }
+
+            let signature = signature(&hook.secret, Utc::now().timestamp(), body);
+            let response = self
+                .client
+                .post(hook.url.clone())
+                .header("Content-Type", "application/json")
+                .header("Webhook-Id", id.as_str())
+                .header("Webhook-Signature", signature)
+                .body(body.to_owned())
+                .send()
+                .await;
+
+            match response {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let code = response.status().as_u16();
+                    status = Some(code);
+                    if !retryable(code) {
+                        break;
+                    }
+                }
+                Err(_) => status = None,
+            }
+        }
+
+        Err(status)
+    }
+
+    fn delivery_id(&self) -> String {
+        let mut id = [0; 16];
+        // The id only serves deduplication, an all zero id on failure is acceptable.
+        let _ = self.rng.fill(&mut id);
+        hex(&id)
+    }
+}
+
+/// The JSON document describing an event.
+pub fn payload(event: &AuditEvent) -> Value {
+    let mut document = Map::new();
+    document.insert("type".into(), event.kind.as_str().into());
+    document.insert("occurred_at".into(), event.occurred_at.to_rfc3339().into());
+    if let Some(client_id) = &event.client_id {
+        document.insert("client_id".into(), client_id.as_str().into());
+    }
+    if let Some(owner_id) = &event.owner_id {
+        document.insert("owner_id".into(), owner_id.as_str().into());
+    }
+    if let Some(scope) = &event.scope {
+        document.insert("scope".into(), scope.to_string().into());
+    }
+    if let Some(remote_addr) = &event.metadata.remote_addr {
+        document.insert("remote_addr".into(), remote_addr.as_str().into());
+    }
+    Value::Object(document)
+}
+
+/// The `Webhook-Signature` header of a body sent at `timestamp`, in unix seconds.
+pub fn signature(secret: &[u8], timestamp: i64, body: &str) -> String {
+    format!("t={},v1={}", timestamp, hex(&mac(secret, timestamp, body)))
+}
+
+/// Check the `Webhook-Signature` header of a received body.
+///
+/// The signature must have been made with `secret` at most `tolerance` before or after `now`, to
+/// limit the replay of captured notifications.
+pub fn verify(
+    secret: &[u8], header: &str, body: &str, now: DateTime<Utc>, tolerance: chrono::Duration,
+) -> bool {
+    let mut timestamp = None;
+    let mut candidates = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => candidates.push(value),
+            _ => (),
+        }
+    }
+
+    let timestamp = match timestamp {
+        Some(timestamp) => timestamp,
+        None => return false,
+    };
+
+    if (now.timestamp() - timestamp).abs() > tolerance.num_seconds() {
+        return false;
+    }
+
+    let expected = hex(&mac(secret, timestamp, body));
+    candidates
+        .iter()
+        .any(|candidate| bool::from(candidate.as_bytes().ct_eq(expected.as_bytes())))
+}
+
+/// Whether a delivery answered with `status` could succeed when repeated.
+fn retryable(status: u16) -> bool {
+    status >= 500 || status == 408 || status == 429
+}
+
+fn mac(secret: &[u8], timestamp: i64, body: &str) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let mut context = hmac::Context::with_key(&key);
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(body.as_bytes());
+    context.sign().as_ref().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::endpoint::{GrantEvent, GrantOutcome, GrantRecord};
+    use oxide_auth::frontends::audit::RequestMetadata;
+
+    fn event() -> AuditEvent {
+        let mut record = GrantRecord::new(GrantEvent::Token, GrantOutcome::Issued);
+        record.client_id = Some("client".to_owned());
+        record.scope = Some("read".parse().unwrap());
+        let metadata = RequestMetadata {
+            remote_addr: Some("192.0.2.1".to_owned()),
+            user_agent: None,
+        };
+        AuditEvent::from_record(record, metadata).unwrap()
+    }
+
+    #[test]
+    fn payload_fields() {
+        let event = event();
+        let document = payload(&event);
+        assert_eq!(document["type"], "token_issued");
+        assert_eq!(document["occurred_at"], event.occurred_at.to_rfc3339());
+        assert_eq!(document["client_id"], "client");
+        assert_eq!(document["scope"], "read");
+        assert_eq!(document["remote_addr"], "192.0.2.1");
+        assert!(document.get("owner_id").is_none());
+    }
+
+    #[test]
+    fn signature_verifies() {
+        let now = Utc::now();
+        let tolerance = chrono::Duration::minutes(5);
+        let header = signature(b"secret", now.timestamp(), "{}");
+
+        assert!(verify(b"secret", &header, "{}", now, tolerance));
+        assert!(!verify(b"other", &header, "{}", now, tolerance));
+        assert!(!verify(b"secret", &header, "{ }", now, tolerance));
+        assert!(!verify(
+            b"secret",
+            &header,
+            "{}",
+            now + chrono::Duration::minutes(6),
+            tolerance
+        ));
+        assert!(!verify(b"secret", "v1=00", "{}", now, tolerance));
+    }
+
+    #[test]
+    fn filtered_kinds() {
+        let url: Url = "https://hooks.example.com".parse().unwrap();
+        let all = Webhook::new(url.clone(), b"secret");
+        let revocations = Webhook::new(url, b"secret")
+            .with_kinds(&[AuditKind::TokenRevoked, AuditKind::ConsentRevoked]);
+
+        assert!(all.wants(AuditKind::TokenIssued));
+        assert!(!revocations.wants(AuditKind::TokenIssued));
+        assert!(revocations.wants(AuditKind::ConsentRevoked));
+    }
+
+    #[test]
+    fn retried_statuses() {
+        assert!(retryable(503));
+        assert!(retryable(429));
+        assert!(!retryable(400));
+        assert!(!retryable(404));
+    }
+}
@@ -1,17 +1,56 @@
 //! Async versions of all primitives traits.
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use oxide_auth::primitives::{grant::Grant, scope::Scope};
+use oxide_auth::primitives::claims::{self, Claims, ClaimsError, TokenKind};
+use oxide_auth::primitives::generator::{self, SignError};
 use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken};
+use oxide_auth::primitives::nonce::{self, NonceError};
 use oxide_auth::primitives::{
-    authorizer, registrar, issuer,
-    registrar::{ClientUrl, BoundClient, RegistrarError, PreGrant},
+    authorizer, consent, registrar, issuer, session,
+    authorizer::GuardError,
+    consent::Consent,
+    session::Session,
+    registrar::{
+        ClientAuthMethod, ClientUrl, BoundClient, GrantType, RefreshPolicy, RegistrarError, PreGrant,
+    },
 };
 
+#[cfg(feature = "federation")]
+pub mod federation;
+#[cfg(feature = "introspection")]
+pub mod introspection;
+#[cfg(any(feature = "jwt", feature = "federation"))]
+mod jwk;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+#[cfg(feature = "opa")]
+pub mod opa;
+pub mod secrets;
+pub mod signer;
+#[cfg(feature = "vault")]
+pub mod vault;
+
 #[async_trait]
 pub trait Authorizer {
     async fn authorize(&mut self, _: Grant) -> Result<String, ()>;
 
     async fn extract(&mut self, _: &str) -> Result<Option<Grant>, ()>;
+
+    /// Invalidate all codes issued to a client, returning how many there were.
+    ///
+    /// The default implementation supports no bulk revocation and fails.
+    async fn revoke_client(&mut self, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
+
+    /// Invalidate the codes issued to a client on behalf of one owner, returning how many there
+    /// were.
+    ///
+    /// The default implementation supports no bulk revocation and fails.
+    async fn revoke_grant(&mut self, _owner_id: &str, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
 }
 
 #[async_trait]
@@ -26,6 +65,87 @@ where
     async fn extract(&mut self, token: &str) -> Result<Option<Grant>, ()> {
         authorizer::Authorizer::extract(self, token)
     }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        authorizer::Authorizer::revoke_client(self, client_id)
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        authorizer::Authorizer::revoke_grant(self, owner_id, client_id)
+    }
+}
+
+/// Ensures that each code is redeemed at most once, across all replicas of a server.
+///
+/// The async counterpart of the guard in `oxide_auth`, so that claims can be recorded in a
+/// database. Any synchronous `SingleUseGuard` is usable as well.
+#[async_trait]
+pub trait SingleUseGuard {
+    /// Claim a code, returning `true` only for the first claim.
+    async fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError>;
+}
+
+#[async_trait]
+impl<T> SingleUseGuard for T
+where
+    T: authorizer::SingleUseGuard + Sync + ?Sized,
+{
+    async fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        authorizer::SingleUseGuard::claim(self, code, until)
+    }
+}
+
+/// An authorizer whose codes are claimed with an async `SingleUseGuard` when extracted.
+///
+/// Behaves like the `SingleUse` of `oxide_auth`: a code that was already claimed yields no grant
+/// and a failing guard fails the extraction.
+pub struct SingleUse<A, G> {
+    authorizer: A,
+    guard: G,
+}
+
+impl<A, G> SingleUse<A, G> {
+    /// Claim the codes extracted from `authorizer` with the `guard`.
+    pub fn new(authorizer: A, guard: G) -> Self {
+        SingleUse { authorizer, guard }
+    }
+
+    /// Unwrap the authorizer and the guard.
+    pub fn into_inner(self) -> (A, G) {
+        (self.authorizer, self.guard)
+    }
+}
+
+#[async_trait]
+impl<A, G> Authorizer for SingleUse<A, G>
+where
+    A: Authorizer + Send,
+    G: SingleUseGuard + Send + Sync,
+{
+    async fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+        self.authorizer.authorize(grant).await
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        let grant = match self.authorizer.extract(code).await? {
+            Some(grant) => grant,
+            None => return Ok(None),
+        };
+
+        match self.guard.claim(code, grant.until).await {
+            Ok(true) => Ok(Some(grant)),
+            Ok(false) => Ok(None),
+            Err(GuardError) => Err(()),
+        }
+    }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.authorizer.revoke_client(client_id).await
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.authorizer.revoke_grant(owner_id, client_id).await
+    }
 }
 
 #[async_trait]
@@ -37,6 +157,22 @@ pub trait Issuer {
     async fn recover_token(&mut self, _: &str) -> Result<Option<Grant>, ()>;
 
     async fn recover_refresh(&mut self, _: &str) -> Result<Option<Grant>, ()>;
+
+    /// Invalidate all access and refresh tokens issued to a client, returning how many grants
+    /// they belonged to.
+    ///
+    /// The default implementation supports no bulk revocation and fails.
+    async fn revoke_client(&mut self, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
+
+    /// Invalidate the access and refresh tokens issued to a client on behalf of one owner,
+    /// returning how many grants they belonged to.
+    ///
+    /// The default implementation supports no bulk revocation and fails.
+    async fn revoke_grant(&mut self, _owner_id: &str, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
 }
 
 #[async_trait]
@@ -59,6 +195,14 @@ where
     async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, ()> {
         issuer::Issuer::recover_refresh(self, token)
     }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        issuer::Issuer::revoke_client(self, client_id)
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        issuer::Issuer::revoke_grant(self, owner_id, client_id)
+    }
 }
 
 #[async_trait]
@@ -70,6 +214,21 @@ pub trait Registrar {
     ) -> Result<PreGrant, RegistrarError>;
 
     async fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError>;
+
+    /// When the client is issued refresh tokens, by default always.
+    async fn refresh_policy(&self, _client_id: &str) -> RefreshPolicy {
+        RefreshPolicy::Always
+    }
+
+    /// Whether the client may use a grant type at the token endpoint, by default all.
+    async fn permits_grant(&self, _client_id: &str, _grant_type: GrantType) -> bool {
+        true
+    }
+
+    /// How the client must authenticate at the token endpoint, by default with any method.
+    async fn auth_method(&self, _client_id: &str) -> Option<ClientAuthMethod> {
+        None
+    }
 }
 
 #[async_trait]
@@ -90,4 +249,162 @@ where
     async fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         registrar::Registrar::check(self, client_id, passphrase)
     }
+
+    async fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        registrar::Registrar::refresh_policy(self, client_id)
+    }
+
+    async fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        registrar::Registrar::permits_grant(self, client_id, grant_type)
+    }
+
+    async fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        registrar::Registrar::auth_method(self, client_id)
+    }
+}
+
+#[async_trait]
+pub trait ConsentStore {
+    async fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope>;
+
+    async fn remember(&mut self, consent: Consent);
+
+    async fn consents(&self, owner_id: &str) -> Vec<Consent>;
+
+    async fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool;
+}
+
+#[async_trait]
+impl<T> ConsentStore for T
+where
+    T: consent::ConsentStore + Send + Sync + ?Sized,
+{
+    async fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope> {
+        consent::ConsentStore::recall(self, owner_id, client_id)
+    }
+
+    async fn remember(&mut self, consent: Consent) {
+        consent::ConsentStore::remember(self, consent)
+    }
+
+    async fn consents(&self, owner_id: &str) -> Vec<Consent> {
+        consent::ConsentStore::consents(self, owner_id)
+    }
+
+    async fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool {
+        consent::ConsentStore::revoke(self, owner_id, client_id)
+    }
+}
+
+/// Tracks the authenticated sessions of resource owners.
+///
+/// The async counterpart of the session store in `oxide_auth`, so that sessions can be kept in a
+/// remote cache. Any synchronous `SessionStore` implementation is usable as well.
+#[async_trait]
+pub trait SessionStore {
+    async fn session(&self, session_id: &str) -> Option<Session>;
+
+    async fn start(&mut self, session_id: &str, session: Session);
+
+    async fn associate(&mut self, session_id: &str, client_id: &str);
+
+    async fn end(&mut self, session_id: &str) -> Option<Session>;
+}
+
+#[async_trait]
+impl<T> SessionStore for T
+where
+    T: session::SessionStore + Send + Sync + ?Sized,
+{
+    async fn session(&self, session_id: &str) -> Option<Session> {
+        session::SessionStore::session(self, session_id)
+    }
+
+    async fn start(&mut self, session_id: &str, session: Session) {
+        session::SessionStore::start(self, session_id, session)
+    }
+
+    async fn associate(&mut self, session_id: &str, client_id: &str) {
+        session::SessionStore::associate(self, session_id, client_id)
+    }
+
+    async fn end(&mut self, session_id: &str) -> Option<Session> {
+        session::SessionStore::end(self, session_id)
+    }
+}
+
+/// Provides the nonces that DPoP proofs must contain.
+///
+/// The async counterpart of the nonce store in `oxide_auth`, so that replicas can share nonces in
+/// a remote cache. Any synchronous `NonceStore` implementation is usable as well.
+#[async_trait]
+pub trait NonceStore {
+    async fn current(&mut self) -> Result<String, NonceError>;
+
+    async fn check(&mut self, nonce: &str) -> bool;
+}
+
+#[async_trait]
+impl<T> NonceStore for T
+where
+    T: nonce::NonceStore + Send + ?Sized,
+{
+    async fn current(&mut self) -> Result<String, NonceError> {
+        nonce::NonceStore::current(self)
+    }
+
+    async fn check(&mut self, nonce: &str) -> bool {
+        nonce::NonceStore::check(self, nonce)
+    }
+}
+
+/// Signs the data of tokens, for example by calling a remote KMS.
+#[async_trait]
+pub trait Signer {
+    async fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError>;
+}
+
+#[async_trait]
+impl<T> Signer for T
+where
+    T: generator::Signer + Sync + ?Sized,
+{
+    async fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        generator::Signer::sign(self, data)
+    }
+}
+
+/// Verifies the signatures of a `Signer`.
+#[async_trait]
+pub trait Verifier {
+    async fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError>;
+}
+
+#[async_trait]
+impl<T> Verifier for T
+where
+    T: generator::Verifier + Sync + ?Sized,
+{
+    async fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError> {
+        generator::Verifier::verify(self, data, signature)
+    }
+}
+
+/// Provides additional claims of the tokens issued for a grant.
+///
+/// The async counterpart of the claims mapper in `oxide_auth`, so that attributes of the owner can
+/// be looked up in a directory or database. Any synchronous `ClaimsMapper` is usable as well.
+#[async_trait]
+pub trait ClaimsMapper {
+    async fn claims(&self, grant: &Grant, kind: TokenKind) -> Result<Claims, ClaimsError>;
+}
+
+#[async_trait]
+impl<T> ClaimsMapper for T
+where
+    T: claims::ClaimsMapper + Sync + ?Sized,
+{
+    async fn claims(&self, grant: &Grant, kind: TokenKind) -> Result<Claims, ClaimsError> {
+        claims::ClaimsMapper::claims(self, grant, kind)
+    }
 }
@@ -0,0 +1,729 @@
+//! Clients established automatically through OpenID Federation 1.0.
+//!
+//! A client of a federation does not register with each authorization server. It identifies with
+//! its entity identifier, an https url, and publishes a signed entity configuration describing
+//! itself at `/.well-known/openid-federation` below that url. Its configuration names superior
+//! authorities, which publish signed statements about their subordinates, up to a trust anchor
+//! whose keys the authorization server knows beforehand.
+//!
+//! The [`TrustChainResolver`] resolves such a chain for an entity identifier:
+//!
+//! * the entity configuration of the client, signed with its own keys,
+//! * for each authority on the way to a configured trust anchor, its entity configuration and its
+//!   subordinate statement about the previous entity, fetched from its `federation_fetch_endpoint`,
+//! * the keys of each subordinate statement must have signed the configuration of its subject, and
+//!   those of the trust anchor are the configured ones.
+//!
+//! The `openid_relying_party` metadata of the client is then restricted by the metadata policies of
+//! the chain, applied from the trust anchor downwards. The operators `value`, `default`, `add`,
+//! `one_of`, `subset_of`, `superset_of` and `essential` are supported. Chains whose policies use
+//! other operators are refused, as are chains requiring the `metadata_policy_crit` of a superior.
+//!
+//! The resolved client is turned into a `Client` of the registrar with [`ResolvedEntity::client`].
+//! This crate has no machinery for dynamic registration, the application registers it with its
+//! registrar, for example when the registrar does not know a client id that is an https url. The
+//! client should be resolved again once the chain expires.
+//!
+//! [`TrustChainResolver`]: struct.TrustChainResolver.html
+//! [`ResolvedEntity::client`]: struct.ResolvedEntity.html#method.client
+use std::fmt;
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use oxide_auth::primitives::registrar::{Client, RegisteredUrl};
+use oxide_auth::primitives::scope::Scope;
+use serde_json::{Map, Value};
+use url::Url;
+
+use super::jwk::{decode, decode_json, Jwk};
+
+/// The metadata type of clients in entity statements.
+const RELYING_PARTY: &str = "openid_relying_party";
+
+/// Policy operators understood by the resolver.
+const OPERATORS: [&str; 7] = [
+    "value",
+    "default",
+    "add",
+    "one_of",
+    "subset_of",
+    "superset_of",
+    "essential",
+];
+
+/// Retrieves the signed statements of a federation.
+///
+/// [`HttpFetcher`] retrieves them over https. Other fetchers can serve statements from a cache or,
+/// in tests, from memory.
+///
+/// [`HttpFetcher`]: struct.HttpFetcher.html
+#[async_trait]
+pub trait EntityFetcher {
+    /// The body of a successful `GET` of the url, a signed entity statement.
+    async fn fetch(&mut self, url: Url) -> Result<String, FederationError>;
+}
+
+/// Fetches statements with `reqwest`.
+#[derive(Clone, Default)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+/// A trust anchor of the federation, with its keys as configured beforehand.
+#[derive(Clone, Debug)]
+pub struct TrustAnchor {
+    /// The entity identifier of the anchor.
+    pub entity_id: String,
+
+    /// The JSON Web Key Set of the anchor.
+    pub jwks: Value,
+}
+
+/// Resolves the trust chains of clients to one of the configured trust anchors.
+pub struct TrustChainResolver<F: EntityFetcher = HttpFetcher> {
+    fetcher: F,
+    anchors: Vec<TrustAnchor>,
+    max_depth: usize,
+}
+
+/// A client whose trust chain was resolved.
+#[derive(Clone, Debug)]
+pub struct ResolvedEntity {
+    /// The entity identifier of the client.
+    pub entity_id: String,
+
+    /// The trust anchor the chain was resolved to.
+    pub trust_anchor: String,
+
+    /// The `openid_relying_party` metadata of the client, restricted by the metadata policies.
+    pub metadata: Map<String, Value>,
+
+    /// When the first statement of the chain expires.
+    pub expires: DateTime<Utc>,
+}
+
+/// The trust chain of an entity could not be resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FederationError {
+    /// A statement could not be retrieved.
+    Fetch,
+
+    /// A statement is malformed, expired or its signature is not valid.
+    InvalidStatement,
+
+    /// No chain leads to a configured trust anchor within the maximum depth.
+    NoTrustChain,
+
+    /// The metadata of the client violates the policy of the federation.
+    PolicyViolation,
+
+    /// The metadata does not describe a usable client.
+    InvalidMetadata,
+}
+
+/// A verified entity statement.
+struct Statement {
+    claims: Value,
+}
+
+/// A chain from an entity to a trust anchor.
+struct Chain {
+    anchor: String,
+    /// The metadata policies, the one of the trust anchor first.
+    policies: Vec<Value>,
+    expires: i64,
+}
+
+impl HttpFetcher {
+    /// Fetch statements with the default client.
+    pub fn new() -> Self {
+        HttpFetcher::default()
+    }
+
+    /// Fetch statements with another client, for example one with timeouts.
+    pub fn with_client(client: reqwest::Client) -> Self {
+        HttpFetcher { client }
+    }
+}
+
+#[async_trait]
+impl EntityFetcher for HttpFetcher {
+    async fn fetch(&mut self, url: Url) -> Result<String, FederationError> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|_| FederationError::Fetch)?;
+        if !response.status().is_success() {
+            return Err(FederationError::Fetch);
+        }
+
+        response.text().await.map_err(|_| FederationError::Fetch)
+    }
+}
+
+impl TrustAnchor {
+    /// An anchor with its identifier and key set.
+    pub fn new(entity_id: &str, jwks: Value) -> Self {
+        TrustAnchor {
+            entity_id: entity_id.to_owned(),
+            jwks,
+        }
+    }
+}
+
+impl TrustChainResolver {
+    /// Resolve chains to the anchors, fetching statements over https.
+    pub fn new(anchors: Vec<TrustAnchor>) -> Self {
+        TrustChainResolver::with_fetcher(HttpFetcher::new(), anchors)
+    }
+}
+
+impl<F: EntityFetcher + Send> TrustChainResolver<F> {
+    /// Resolve chains to the anchors, retrieving statements with another fetcher.
+    ///
+    /// Chains may have up to five authorities between the client and its trust anchor.
+    pub fn with_fetcher(fetcher: F, anchors: Vec<TrustAnchor>) -> Self {
+        TrustChainResolver {
+            fetcher,
+            anchors,
+            max_depth: 5,
+        }
+    }
+
+    /// Allow another number of authorities between a client and its trust anchor.
+    pub fn with_max_depth(self, max_depth: usize) -> Self {
+        TrustChainResolver { max_depth, ..self }
+    }
+
+    /// Resolve the trust chain of a client and its metadata.
+    pub async fn resolve(&mut self, entity_id: &str) -> Result<ResolvedEntity, FederationError> {
+        let now = Utc::now().timestamp();
+        let (token, configuration) = self.configuration(entity_id, None, now).await?;
+        let chain = self.chain(entity_id, &token, &configuration, 0, now).await?;
+
+        let mut metadata = match &configuration.claims["metadata"][RELYING_PARTY] {
+            Value::Object(metadata) => metadata.clone(),
+            _ => return Err(FederationError::InvalidMetadata),
+        };
+        for policy in &chain.policies {
+            apply_policy(&mut metadata, policy)?;
+        }
+
+        let expires = chain.expires.min(configuration.expires());
+        Ok(ResolvedEntity {
+            entity_id: entity_id.to_owned(),
+            trust_anchor: chain.anchor,
+            metadata,
+            expires: Utc
+                .timestamp_opt(expires, 0)
+                .single()
+                .ok_or(FederationError::InvalidStatement)?,
+        })
+    }
+
+    /// Find a chain from an entity whose configuration was fetched to a trust anchor.
+    ///
+    /// Each authority hint is tried in turn.
+    async fn chain(
+        &mut self, subject: &str, token: &str, configuration: &Statement, depth: usize, now: i64,
+    ) -> Result<Chain, FederationError> {
+        if depth > self.max_depth {
+            return Err(FederationError::NoTrustChain);
+        }
+
+        let hints: Vec<String> = configuration.claims["authority_hints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|hint| hint.as_str().map(str::to_owned))
+            .collect();
+
+        for authority in hints {
+            let anchor = self
+                .anchors
+                .iter()
+                .find(|anchor| anchor.entity_id == authority)
+                .map(|anchor| anchor.jwks.clone());
+            match self
+                .link(&authority, anchor.as_ref(), subject, token, depth, now)
+                .await
+            {
+                Ok(chain) => return Ok(chain),
+                Err(FederationError::Fetch) | Err(FederationError::InvalidStatement) => continue,
+                Err(FederationError::NoTrustChain) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(FederationError::NoTrustChain)
+    }
+
+    /// Follow the chain through one authority of the subject.
+    async fn link(
+        &mut self, authority: &str, anchor: Option<&Value>, subject: &str, token: &str, depth: usize,
+        now: i64,
+    ) -> Result<Chain, FederationError> {
+        let (authority_token, configuration) = self.configuration(authority, anchor, now).await?;
+        let fetch_endpoint = configuration.claims["metadata"]["federation_entity"]
+            ["federation_fetch_endpoint"]
+            .as_str()
+            .and_then(|url| url.parse::<Url>().ok())
+            .ok_or(FederationError::InvalidStatement)?;
+
+        let mut url = fetch_endpoint;
+        url.query_pairs_mut().append_pair("sub", subject);
+        let keys = parse_keys(&configuration.claims["jwks"]);
+        let statement = self.statement(url, &keys, authority, subject, now).await?;
+
+        // The subordinate statement vouches for the keys of its subject.
+        Statement::verify(
+            token,
+            &parse_keys(&statement.claims["jwks"]),
+            subject,
+            subject,
+            now,
+        )?;
+
+        if statement.claims["metadata_policy_crit"].is_array() {
+            return Err(FederationError::PolicyViolation);
+        }
+        let policy = statement.claims["metadata_policy"][RELYING_PARTY].clone();
+        let expires = statement.expires().min(configuration.expires());
+
+        let mut chain = match anchor {
+            Some(_) => Chain {
+                anchor: authority.to_owned(),
+                policies: Vec::new(),
+                expires,
+            },
+            None => {
+                let chain = self.chain(authority, &authority_token, &configuration, depth + 1, now);
+                let mut chain = Box::pin(chain).await?;
+                chain.expires = chain.expires.min(expires);
+                chain
+            }
+        };
+        if !policy.is_null() {
+            chain.policies.push(policy);
+        }
+        Ok(chain)
+    }
+
+    /// Fetch and verify the entity configuration of an entity.
+    ///
+    /// Configurations are signed with the keys they contain, except for trust anchors whose keys
+    /// are configured.
+    async fn configuration(
+        &mut self, entity_id: &str, anchor: Option<&Value>, now: i64,
+    ) -> Result<(String, Statement), FederationError> {
+        let url = format!(
+            "{}/.well-known/openid-federation",
+            entity_id.trim_end_matches('/')
+        );
+        let url = url.parse().map_err(|_| FederationError::InvalidStatement)?;
+        let token = self.fetcher.fetch(url).await?;
+
+        let keys = match anchor {
+            Some(jwks) => parse_keys(jwks),
+            None => {
+                let (_, claims, _) = split(&token).ok_or(FederationError::InvalidStatement)?;
+                parse_keys(&claims["jwks"])
+            }
+        };
+
+        let statement = Statement::verify(&token, &keys, entity_id, entity_id, now)?;
+        Ok((token, statement))
+    }
+
+    /// Fetch and verify a subordinate statement.
+    async fn statement(
+        &mut self, url: Url, keys: &[Jwk], issuer: &str, subject: &str, now: i64,
+    ) -> Result<Statement, FederationError> {
+        let token = self.fetcher.fetch(url).await?;
+        Statement::verify(&token, keys, issuer, subject, now)
+    }
+}
+
+impl Statement {
+    /// Verify the signature, type, issuer, subject and validity of a statement.
+    fn verify(
+        token: &str, keys: &[Jwk], issuer: &str, subject: &str, now: i64,
+    ) -> Result<Self, FederationError> {
+        let invalid = FederationError::InvalidStatement;
+        let (header, claims, signature) = split(token).ok_or(invalid)?;
+        let message = &token[..token.rfind('.').ok_or(invalid)?];
+
+        if header["typ"].as_str() != Some("entity-statement+jwt") {
+            return Err(invalid);
+        }
+
+        let alg = header["alg"].as_str().unwrap_or_default();
+        let kid = header["kid"].as_str();
+        let verified = keys
+            .iter()
+            .filter(|jwk| kid.is_none() || jwk.kid.as_deref() == kid)
+            .any(|jwk| jwk.verify(alg, message.as_bytes(), &signature));
+        if !verified {
+            return Err(invalid);
+        }
+
+        if claims["iss"].as_str() != Some(issuer) || claims["sub"].as_str() != Some(subject) {
+            return Err(invalid);
+        }
+
+        match (claims["iat"].as_i64(), claims["exp"].as_i64()) {
+            (Some(iat), Some(exp)) if iat <= now + 60 && now < exp => Ok(Statement { claims }),
+            _ => Err(invalid),
+        }
+    }
+
+    fn expires(&self) -> i64 {
+        self.claims["exp"].as_i64().unwrap_or_default()
+    }
+}
+
+impl ResolvedEntity {
+    /// The client described by the metadata, to be registered with a registrar.
+    ///
+    /// The first of its `redirect_uris` is the default one. Its `scope` is the default scope, or
+    /// `default_scope` if the metadata does not restrict it. The client is public, federation
+    /// clients authenticate with their keys rather than a shared secret.
+    pub fn client(&self, default_scope: Scope) -> Result<Client, FederationError> {
+        let invalid = FederationError::InvalidMetadata;
+        let mut redirect_uris = self.metadata["redirect_uris"]
+            .as_array()
+            .ok_or(invalid)?
+            .iter()
+            .map(|uri| {
+                let uri = uri.as_str()?.parse::<Url>().ok()?;
+                Some(RegisteredUrl::from(uri))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(invalid)?
+            .into_iter();
+
+        let redirect_uri = redirect_uris.next().ok_or(invalid)?;
+        let scope = match self.metadata.get("scope") {
+            Some(Value::String(scope)) => scope.parse().map_err(|_| invalid)?,
+            Some(_) => return Err(invalid),
+            None => default_scope,
+        };
+
+        Ok(Client::public(&self.entity_id, redirect_uri, scope)
+            .with_additional_redirect_uris(redirect_uris.collect()))
+    }
+}
+
+/// Restrict metadata by the policy of one statement.
+fn apply_policy(metadata: &mut Map<String, Value>, policy: &Value) -> Result<(), FederationError> {
+    let violation = FederationError::PolicyViolation;
+    let policy = match policy {
+        Value::Object(policy) => policy,
+        _ => return Err(violation),
+    };
+
+    for (parameter, operators) in policy {
+        let operators = operators.as_object().ok_or(violation)?;
+        if operators
+            .keys()
+            .any(|operator| !OPERATORS.contains(&operator.as_str()))
+        {
+            return Err(violation);
+        }
+
+        if let Some(value) = operators.get("value") {
+            match value {
+                Value::Null => metadata.remove(parameter),
+                value => metadata.insert(parameter.clone(), value.clone()),
+            };
+        }
+
+        if let Some(add) = operators.get("add") {
+            let add = add.as_array().ok_or(violation)?;
+            let values = metadata
+                .entry(parameter.clone())
+                .or_insert_with(|| Value::Array(Vec::new()))
+                .as_array_mut()
+                .ok_or(violation)?;
+            for value in add {
+                if !values.contains(value) {
+                    values.push(value.clone());
+                }
+            }
+        }
+
+        if let Some(default) = operators.get("default") {
+            metadata
+                .entry(parameter.clone())
+                .or_insert_with(|| default.clone());
+        }
+
+        if let Some(one_of) = operators.get("one_of") {
+            let one_of = one_of.as_array().ok_or(violation)?;
+            match metadata.get(parameter) {
+                Some(value) if !one_of.contains(value) => return Err(violation),
+                _ => (),
+            }
+        }
+
+        if let Some(subset_of) = operators.get("subset_of") {
+            let subset_of = subset_of.as_array().ok_or(violation)?;
+            if let Some(value) = metadata.get_mut(parameter) {
+                // Scopes are space separated strings rather than arrays.
+                let restricted: Vec<_> = match value {
+                    Value::Array(values) => {
+                        values.iter().filter(|v| subset_of.contains(v)).cloned().collect()
+                    }
+                    Value::String(values) => values
+                        .split(' ')
+                        .filter(|v| subset_of.iter().any(|allowed| allowed.as_str() == Some(v)))
+                        .map(|v| Value::String(v.to_owned()))
+                        .collect(),
+                    _ => return Err(violation),
+                };
+                *value = match value {
+                    Value::String(_) => Value::String(
+                        restricted
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                    _ => Value::Array(restricted),
+                };
+            }
+        }
+
+        if let Some(superset_of) = operators.get("superset_of") {
+            let superset_of = superset_of.as_array().ok_or(violation)?;
+            if let Some(value) = metadata.get(parameter) {
+                let values = value.as_array().ok_or(violation)?;
+                if !superset_of.iter().all(|required| values.contains(required)) {
+                    return Err(violation);
+                }
+            }
+        }
+
+        if operators.get("essential") == Some(&Value::Bool(true)) && !metadata.contains_key(parameter) {
+            return Err(violation);
+        }
+    }
+
+    Ok(())
+}
+
+/// The decoded header, claims and signature of a JWT.
+fn split(token: &str) -> Option<(Value, Value, Vec<u8>)> {
+    let mut parts = token.split('.');
+    let (header, claims, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((decode_json(header)?, decode_json(claims)?, decode(signature)?))
+}
+
+fn parse_keys(jwks: &Value) -> Vec<Jwk> {
+    jwks["keys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Jwk::parse)
+        .collect()
+}
+
+impl fmt::Display for FederationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FederationError::Fetch => f.write_str("an entity statement could not be fetched"),
+            FederationError::InvalidStatement => f.write_str("an entity statement is not valid"),
+            FederationError::NoTrustChain => f.write_str("no trust chain leads to a trust anchor"),
+            FederationError::PolicyViolation => {
+                f.write_str("the metadata violates the federation policy")
+            }
+            FederationError::InvalidMetadata => f.write_str("the metadata does not describe a client"),
+        }
+    }
+}
+
+impl std::error::Error for FederationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use oxide_auth::primitives::registrar::{ClientMap, ClientUrl, Registrar};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+
+    struct Entity {
+        id: &'static str,
+        key: Ed25519KeyPair,
+    }
+
+    impl Entity {
+        fn new(id: &'static str) -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            Entity {
+                id,
+                key: Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap(),
+            }
+        }
+
+        fn jwks(&self) -> Value {
+            json!({ "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "kid": self.id,
+                "x": URL_SAFE_NO_PAD.encode(self.key.public_key()),
+            }]})
+        }
+
+        fn sign(&self, mut claims: Value) -> String {
+            let now = Utc::now().timestamp();
+            claims["iss"] = json!(self.id);
+            claims["iat"] = json!(now);
+            claims["exp"] = json!(now + 3600);
+            let header = json!({ "typ": "entity-statement+jwt", "alg": "EdDSA", "kid": self.id });
+            let message = format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(header.to_string()),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            );
+            let signature = self.key.sign(message.as_bytes());
+            format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature))
+        }
+
+        fn configuration(&self, mut claims: Value) -> String {
+            claims["sub"] = json!(self.id);
+            claims["jwks"] = self.jwks();
+            self.sign(claims)
+        }
+
+        fn fetch_endpoint(&self) -> String {
+            format!("{}/fetch", self.id)
+        }
+    }
+
+    #[derive(Default)]
+    struct Statements(HashMap<String, String>);
+
+    #[async_trait]
+    impl EntityFetcher for Statements {
+        async fn fetch(&mut self, url: Url) -> Result<String, FederationError> {
+            self.0.get(url.as_str()).cloned().ok_or(FederationError::Fetch)
+        }
+    }
+
+    impl Statements {
+        fn configuration(&mut self, entity: &Entity, claims: Value) {
+            let url = format!("{}/.well-known/openid-federation", entity.id);
+            self.0.insert(url, entity.configuration(claims));
+        }
+
+        fn subordinate(&mut self, authority: &Entity, subject: &Entity, policy: Value) {
+            let mut url: Url = authority.fetch_endpoint().parse().unwrap();
+            url.query_pairs_mut().append_pair("sub", subject.id);
+            let statement = authority.sign(json!({
+                "sub": subject.id,
+                "jwks": subject.jwks(),
+                "metadata_policy": { "openid_relying_party": policy },
+            }));
+            self.0.insert(url.to_string(), statement);
+        }
+    }
+
+    #[test]
+    fn chain_through_intermediate() {
+        let anchor = Entity::new("https://anchor.example");
+        let intermediate = Entity::new("https://university.example");
+        let client = Entity::new("https://rp.university.example");
+
+        let federation_entity = |entity: &Entity| json!({ "federation_entity": { "federation_fetch_endpoint": entity.fetch_endpoint() } });
+        let mut statements = Statements::default();
+        statements.configuration(&anchor, json!({ "metadata": federation_entity(&anchor) }));
+        statements.configuration(
+            &intermediate,
+            json!({ "metadata": federation_entity(&intermediate), "authority_hints": [anchor.id] }),
+        );
+        statements.configuration(
+            &client,
+            json!({
+                "authority_hints": [intermediate.id],
+                "metadata": { "openid_relying_party": {
+                    "redirect_uris": ["https://rp.university.example/callback"],
+                    "scope": "openid profile admin",
+                }},
+            }),
+        );
+        statements.subordinate(
+            &anchor,
+            &intermediate,
+            json!({ "scope": { "subset_of": ["openid", "profile", "email"] } }),
+        );
+        statements.subordinate(
+            &intermediate,
+            &client,
+            json!({ "contacts": { "add": ["it@university.example"] } }),
+        );
+
+        let anchors = vec![TrustAnchor::new(anchor.id, anchor.jwks())];
+        let mut resolver = TrustChainResolver::with_fetcher(statements, anchors);
+        let resolved = smol::block_on(resolver.resolve(client.id)).unwrap();
+        assert_eq!(resolved.trust_anchor, anchor.id);
+        assert_eq!(resolved.metadata["scope"], "openid profile");
+        assert_eq!(resolved.metadata["contacts"], json!(["it@university.example"]));
+
+        let mut registrar = ClientMap::new();
+        registrar.register_client(resolved.client("openid".parse().unwrap()).unwrap());
+        let bound = registrar
+            .bound_redirect(ClientUrl {
+                client_id: client.id.into(),
+                redirect_uri: None,
+            })
+            .unwrap();
+        assert_eq!(
+            bound.redirect_uri.to_url().as_str(),
+            "https://rp.university.example/callback"
+        );
+        let pre_grant = registrar.negotiate(bound, None).unwrap();
+        assert_eq!(pre_grant.scope, "openid profile".parse().unwrap());
+
+        // Without the anchor, no chain can be resolved.
+        resolver.anchors.clear();
+        let unanchored = smol::block_on(resolver.resolve(client.id));
+        assert_eq!(unanchored.unwrap_err(), FederationError::NoTrustChain);
+    }
+
+    #[test]
+    fn policy_violations() {
+        let mut metadata = json!({ "token_endpoint_auth_method": "client_secret_basic" });
+        let metadata = metadata.as_object_mut().unwrap();
+        let one_of = json!({ "token_endpoint_auth_method": { "one_of": ["private_key_jwt"] } });
+        assert_eq!(
+            apply_policy(metadata, &one_of),
+            Err(FederationError::PolicyViolation)
+        );
+
+        let essential = json!({ "contacts": { "essential": true } });
+        assert_eq!(
+            apply_policy(metadata, &essential),
+            Err(FederationError::PolicyViolation)
+        );
+
+        let unknown = json!({ "contacts": { "regexp": ".*" } });
+        assert_eq!(
+            apply_policy(metadata, &unknown),
+            Err(FederationError::PolicyViolation)
+        );
+
+        let value = json!({ "token_endpoint_auth_method": { "value": "private_key_jwt" } });
+        assert_eq!(apply_policy(metadata, &value), Ok(()));
+        assert_eq!(metadata["token_endpoint_auth_method"], "private_key_jwt");
+    }
+}
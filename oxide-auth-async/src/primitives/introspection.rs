@@ -0,0 +1,580 @@
+//! Tokens recovered from a remote introspection endpoint.
+//!
+//! A resource server usually runs apart from the authorization server that issued its tokens. The
+//! [`IntrospectionIssuer`] asks the introspection endpoint of the authorization server, as defined
+//! in [rfc7662], about each token instead of sharing an issuer in process. It authenticates as a
+//! client of the authorization server with its client id and secret.
+//!
+//! The grants of active tokens are kept until they expire or for a time to live, whichever is
+//! shorter, and inactive tokens are remembered for a time to live of their own. A token revoked at
+//! the authorization server is thus accepted until its cached grant is dropped.
+//!
+//! The cache of the issuer belongs to one endpoint. Resource servers handling requests concurrently
+//! share a [`CachedIntrospection`] instead, which asks the endpoint only once for concurrent
+//! lookups of the same token, spreads the expiry of cached grants with jitter and forgets tokens
+//! immediately when told of their revocation.
+//!
+//! [`IntrospectionIssuer`]: struct.IntrospectionIssuer.html
+//! [`CachedIntrospection`]: struct.CachedIntrospection.html
+//! [rfc7662]: https://tools.ietf.org/html/rfc7662
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use futures_channel::oneshot;
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken};
+use serde_json::Value;
+use url::Url;
+use zeroize::Zeroizing;
+
+use super::Issuer;
+
+/// Recovers access tokens with a remote introspection endpoint.
+///
+/// The issuer only recovers access tokens, issuing and refreshing fails. The recovered grants carry
+/// the url of the introspection endpoint as their redirect uri, as introspection responses do not
+/// name one.
+pub struct IntrospectionIssuer {
+    client: reqwest::Client,
+    endpoint: Url,
+    client_id: String,
+    secret: Zeroizing<String>,
+    ttl: Duration,
+    inactive_ttl: Duration,
+    capacity: usize,
+    entries: HashMap<String, Cached>,
+}
+
+struct Cached {
+    grant: Option<Grant>,
+    until: DateTime<Utc>,
+}
+
+/// Asks about the grant of a token, without caching the answer.
+#[async_trait]
+pub trait Introspect {
+    /// The grant of an active token, or `None` for an inactive one.
+    async fn introspect(&self, token: &str) -> Result<Option<Grant>, IntrospectionError>;
+}
+
+/// The introspection endpoint could not be asked about a token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IntrospectionError;
+
+/// Caches introspected tokens for concurrent requests.
+///
+/// All methods take `&self`, so that one cache can be shared by the resource endpoints of all
+/// requests, for example in an `Arc`. Concurrent lookups of a token that is not cached wait for the
+/// first of them, so that a popular token which expires from the cache causes a single request to
+/// the endpoint. Failed lookups are not cached.
+pub struct CachedIntrospection<I> {
+    introspect: I,
+    ttl: Duration,
+    inactive_ttl: Duration,
+    jitter: f64,
+    random: RandomState,
+    state: Mutex<Lookups>,
+}
+
+#[derive(Default)]
+struct Lookups {
+    next_id: u64,
+    entries: HashMap<String, Entry>,
+}
+
+type Waiter = oneshot::Sender<Result<Option<Grant>, IntrospectionError>>;
+
+enum Entry {
+    Ready {
+        grant: Option<Box<Grant>>,
+        until: DateTime<Utc>,
+    },
+    Pending {
+        id: u64,
+        waiters: Vec<Waiter>,
+    },
+}
+
+/// A lookup started by `recover`, abandoned on drop when it was not completed.
+struct Pending<'a, I> {
+    cache: &'a CachedIntrospection<I>,
+    token: &'a str,
+    id: u64,
+}
+
+impl IntrospectionIssuer {
+    /// Ask the introspection endpoint at `endpoint`, authenticating as a confidential client.
+    ///
+    /// Grants of active tokens are kept for up to a minute and inactive tokens for ten seconds by
+    /// default.
+    pub fn new(endpoint: Url, client_id: &str, secret: &str) -> Self {
+        IntrospectionIssuer {
+            client: reqwest::Client::new(),
+            endpoint,
+            client_id: client_id.to_owned(),
+            secret: Zeroizing::new(secret.to_owned()),
+            ttl: Duration::minutes(1),
+            inactive_ttl: Duration::seconds(10),
+            capacity: 1024,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Keep the grants of active tokens for at most `ttl`.
+    ///
+    /// A zero duration asks the endpoint on every request, for resources which must notice
+    /// revocations immediately.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        IntrospectionIssuer { ttl, ..self }
+    }
+
+    /// Remember inactive tokens for another duration.
+    pub fn with_inactive_ttl(self, inactive_ttl: Duration) -> Self {
+        IntrospectionIssuer { inactive_ttl, ..self }
+    }
+
+    /// Drop expired entries once the cache holds more than `capacity` tokens.
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        IntrospectionIssuer { capacity, ..self }
+    }
+
+    /// Send requests with a configured client, for example one with a timeout.
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        IntrospectionIssuer { client, ..self }
+    }
+
+    /// Forget all cached tokens.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn cached(&mut self, token: &str, now: DateTime<Utc>) -> Option<Option<Grant>> {
+        match self.entries.get(token) {
+            Some(cached) if cached.until > now => Some(cached.grant.clone()),
+            Some(_) => {
+                self.entries.remove(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, token: &str, grant: Option<Grant>, now: DateTime<Utc>) {
+        let until = match &grant {
+            Some(grant) => grant.until.min(now + self.ttl),
+            None => now + self.inactive_ttl,
+        };
+        if until <= now {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            self.entries.retain(|_, cached| cached.until > now);
+        }
+        self.entries.insert(token.to_owned(), Cached { grant, until });
+    }
+
+    async fn request(&self, token: &str) -> Result<Value, ()> {
+        let response = self
+            .client
+            .post(self.endpoint.clone())
+            .basic_auth(&self.client_id, Some(self.secret.as_str()))
+            .form(&[("token", token), ("token_type_hint", "access_token")])
+            .send()
+            .await
+            .map_err(|_| ())?;
+        if !response.status().is_success() {
+            return Err(());
+        }
+
+        let body = response.bytes().await.map_err(|_| ())?;
+        serde_json::from_slice(&body).map_err(|_| ())
+    }
+}
+
+/// The grant described by an introspection response, or `None` if the token is not active.
+fn introspected(response: &Value, redirect_uri: &Url, now: DateTime<Utc>) -> Result<Option<Grant>, ()> {
+    if response["active"] != Value::Bool(true) {
+        return Ok(None);
+    }
+
+    let until = match response["exp"].as_i64() {
+        Some(exp) => Utc.timestamp_opt(exp, 0).single().ok_or(())?,
+        None => return Err(()),
+    };
+    if until <= now {
+        return Ok(None);
+    }
+
+    let scope = response["scope"].as_str().ok_or(())?.parse().map_err(|_| ())?;
+    Ok(Some(Grant {
+        owner_id: response["sub"].as_str().unwrap_or_default().to_owned(),
+        client_id: response["client_id"].as_str().unwrap_or_default().to_owned(),
+        scope,
+        redirect_uri: redirect_uri.clone(),
+        until,
+        extensions: Extensions::new(),
+    }))
+}
+
+#[async_trait]
+impl Issuer for IntrospectionIssuer {
+    async fn issue(&mut self, _: Grant) -> Result<IssuedToken, ()> {
+        Err(())
+    }
+
+    async fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, ()> {
+        Err(())
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        let now = Utc::now();
+        if let Some(grant) = self.cached(token, now) {
+            return Ok(grant);
+        }
+
+        // Failures are not cached, the next request asks the endpoint again.
+        let response = self.request(token).await?;
+        let grant = introspected(&response, &self.endpoint, now)?;
+        self.insert(token, grant.clone(), now);
+        Ok(grant)
+    }
+
+    async fn recover_refresh(&mut self, _: &str) -> Result<Option<Grant>, ()> {
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl Introspect for IntrospectionIssuer {
+    async fn introspect(&self, token: &str) -> Result<Option<Grant>, IntrospectionError> {
+        let response = self.request(token).await.map_err(|_| IntrospectionError)?;
+        introspected(&response, &self.endpoint, Utc::now()).map_err(|_| IntrospectionError)
+    }
+}
+
+impl<I> CachedIntrospection<I> {
+    /// Cache the answers of an introspection, by default as long as an `IntrospectionIssuer`.
+    pub fn new(introspect: I) -> Self {
+        CachedIntrospection {
+            introspect,
+            ttl: Duration::minutes(1),
+            inactive_ttl: Duration::seconds(10),
+            jitter: 0.0,
+            random: RandomState::new(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Keep the grants of active tokens for at most `ttl`.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        CachedIntrospection { ttl, ..self }
+    }
+
+    /// Remember inactive tokens for another duration.
+    pub fn with_inactive_ttl(self, inactive_ttl: Duration) -> Self {
+        CachedIntrospection { inactive_ttl, ..self }
+    }
+
+    /// Shorten each time to live by a random fraction of up to `jitter`.
+    ///
+    /// Spreads the expiry of tokens cached at the same time, such as after a restart, so that they
+    /// are not all introspected again at once. The jitter is clamped to between `0` and `1`.
+    pub fn with_jitter(self, jitter: f64) -> Self {
+        CachedIntrospection {
+            jitter: jitter.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// Forget a token immediately, for example when told of its revocation.
+    ///
+    /// A lookup of the token that is in progress is not cached either.
+    pub fn invalidate(&self, token: &str) {
+        self.lock().entries.remove(token);
+    }
+
+    /// Forget all tokens of a client, for example when it was deleted.
+    pub fn invalidate_client(&self, client_id: &str) {
+        self.lock().entries.retain(|_, entry| match entry {
+            Entry::Ready {
+                grant: Some(grant), ..
+            } => grant.client_id != client_id,
+            _ => true,
+        });
+    }
+
+    /// Forget all tokens.
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lookups> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The time to live, shortened by the jitter.
+    fn jittered(&self, ttl: Duration, id: u64) -> Duration {
+        let mut hasher = self.random.build_hasher();
+        hasher.write_u64(id);
+        let fraction = hasher.finish() as f64 / u64::MAX as f64;
+        let millis = ttl.num_milliseconds() as f64 * (1.0 - self.jitter * fraction);
+        Duration::milliseconds(millis as i64)
+    }
+
+    fn complete(
+        &self, token: &str, id: u64, result: Option<&Result<Option<Grant>, IntrospectionError>>,
+    ) {
+        let now = Utc::now();
+        let until = match result {
+            Some(Ok(Some(grant))) => grant.until.min(now + self.jittered(self.ttl, id)),
+            Some(Ok(None)) => now + self.jittered(self.inactive_ttl, id),
+            _ => now,
+        };
+
+        let mut state = self.lock();
+        let waiters = match state.entries.remove(token) {
+            Some(Entry::Pending { id: pending, waiters }) if pending == id => waiters,
+            // The token was invalidated during the lookup, its waiters ask again on their own.
+            Some(other) => {
+                state.entries.insert(token.to_owned(), other);
+                return;
+            }
+            None => return,
+        };
+
+        let result = match result {
+            Some(result) => result,
+            // Dropping the waiters lets them ask on their own.
+            None => return,
+        };
+        match result {
+            Ok(grant) if until > now => {
+                let grant = grant.clone().map(Box::new);
+                state
+                    .entries
+                    .insert(token.to_owned(), Entry::Ready { grant, until });
+            }
+            _ => {}
+        }
+        drop(state);
+
+        for waiter in waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+impl<I: Introspect + Sync> CachedIntrospection<I> {
+    /// Recover the grant of a token, from the cache or by introspecting it.
+    pub async fn recover(&self, token: &str) -> Result<Option<Grant>, IntrospectionError> {
+        let now = Utc::now();
+        let waiting = {
+            let mut state = self.lock();
+            match state.entries.get_mut(token) {
+                Some(Entry::Ready { grant, until }) if *until > now => {
+                    return Ok(grant.as_deref().cloned())
+                }
+                Some(Entry::Pending { waiters, .. }) => {
+                    let (sender, receiver) = oneshot::channel();
+                    waiters.push(sender);
+                    Err(receiver)
+                }
+                _ => {
+                    let id = state.next_id;
+                    state.next_id += 1;
+                    let waiters = Vec::new();
+                    state
+                        .entries
+                        .insert(token.to_owned(), Entry::Pending { id, waiters });
+                    Ok(id)
+                }
+            }
+        };
+
+        let id = match waiting {
+            Ok(id) => id,
+            Err(receiver) => match receiver.await {
+                Ok(result) => return result,
+                // The lookup was abandoned or invalidated.
+                Err(oneshot::Canceled) => return self.introspect.introspect(token).await,
+            },
+        };
+
+        // Should this future be dropped, the guard lets the waiters ask on their own.
+        let pending = Pending {
+            cache: self,
+            token,
+            id,
+        };
+        let result = self.introspect.introspect(token).await;
+        pending.complete(&result);
+        result
+    }
+}
+
+impl<I> Pending<'_, I> {
+    fn complete(self, result: &Result<Option<Grant>, IntrospectionError>) {
+        self.cache.complete(self.token, self.id, Some(result));
+        std::mem::forget(self);
+    }
+}
+
+impl<I> Drop for Pending<'_, I> {
+    fn drop(&mut self) {
+        self.cache.complete(self.token, self.id, None);
+    }
+}
+
+#[async_trait]
+impl<I: Introspect + Send + Sync> Issuer for CachedIntrospection<I> {
+    async fn issue(&mut self, _: Grant) -> Result<IssuedToken, ()> {
+        Err(())
+    }
+
+    async fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, ()> {
+        Err(())
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        self.recover(token).await.map_err(|_| ())
+    }
+
+    async fn recover_refresh(&mut self, _: &str) -> Result<Option<Grant>, ()> {
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl<I: Introspect + Send + Sync> Issuer for &'_ CachedIntrospection<I> {
+    async fn issue(&mut self, _: Grant) -> Result<IssuedToken, ()> {
+        Err(())
+    }
+
+    async fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, ()> {
+        Err(())
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        self.recover(token).await.map_err(|_| ())
+    }
+
+    async fn recover_refresh(&mut self, _: &str) -> Result<Option<Grant>, ()> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn issuer() -> IntrospectionIssuer {
+        let endpoint = "https://as.example/introspect".parse().unwrap();
+        IntrospectionIssuer::new(endpoint, "resource", "secret")
+    }
+
+    #[test]
+    fn introspected_grant() {
+        let issuer = issuer();
+        let now = Utc::now();
+        let exp = (now + Duration::minutes(5)).timestamp();
+        let active = json!({
+            "active": true,
+            "scope": "read write",
+            "client_id": "client",
+            "sub": "owner",
+            "exp": exp,
+        });
+
+        let grant = grant_of(&active, &issuer).unwrap().unwrap();
+        assert_eq!(grant.client_id, "client");
+        assert_eq!(grant.owner_id, "owner");
+        assert_eq!(grant.scope, "write read".parse().unwrap());
+        assert_eq!(grant.until.timestamp(), exp);
+
+        let inactive = json!({ "active": false });
+        assert!(grant_of(&inactive, &issuer).unwrap().is_none());
+        let expired = json!({ "active": true, "scope": "read", "exp": now.timestamp() - 1 });
+        assert!(grant_of(&expired, &issuer).unwrap().is_none());
+        assert!(grant_of(&json!({ "active": true, "scope": "read" }), &issuer).is_err());
+    }
+
+    #[test]
+    fn cached_results() {
+        let mut issuer = issuer().with_ttl(Duration::minutes(1)).with_capacity(1);
+        let now = Utc::now();
+        let active = json!({
+            "active": true,
+            "scope": "read",
+            "exp": (now + Duration::seconds(30)).timestamp(),
+        });
+        let active = grant_of(&active, &issuer).unwrap();
+
+        issuer.insert("active", active, now);
+        issuer.insert("inactive", None, now);
+        assert!(issuer.cached("active", now).unwrap().is_some());
+        assert!(issuer.cached("inactive", now).unwrap().is_none());
+
+        // Active tokens are kept until they expire, inactive ones for their time to live.
+        let later = now + Duration::seconds(20);
+        assert!(issuer.cached("active", later).is_some());
+        assert!(issuer.cached("inactive", later).is_none());
+        assert!(issuer.cached("active", now + Duration::seconds(40)).is_none());
+        assert!(issuer.entries.is_empty());
+    }
+
+    fn grant_of(response: &Value, issuer: &IntrospectionIssuer) -> Result<Option<Grant>, ()> {
+        introspected(response, &issuer.endpoint, Utc::now())
+    }
+
+    /// Answers after a delay and counts the lookups.
+    #[derive(Default)]
+    struct Slow(std::sync::atomic::AtomicUsize);
+
+    #[async_trait]
+    impl Introspect for Slow {
+        async fn introspect(&self, token: &str) -> Result<Option<Grant>, IntrospectionError> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            smol::Timer::after(std::time::Duration::from_millis(20)).await;
+            let response = json!({
+                "active": token == "active",
+                "scope": "read",
+                "client_id": "client",
+                "exp": (Utc::now() + Duration::minutes(5)).timestamp(),
+            });
+            introspected(&response, &issuer().endpoint, Utc::now()).map_err(|_| IntrospectionError)
+        }
+    }
+
+    #[test]
+    fn coalesced_lookups() {
+        let cache = CachedIntrospection::new(Slow::default()).with_jitter(0.2);
+        let lookups = || cache.introspect.0.load(std::sync::atomic::Ordering::Relaxed);
+
+        let (first, second) = smol::block_on(smol::future::zip(
+            cache.recover("active"),
+            cache.recover("active"),
+        ));
+        assert!(first.unwrap().is_some());
+        assert!(second.unwrap().is_some());
+        assert_eq!(lookups(), 1);
+
+        assert!(smol::block_on(cache.recover("active")).unwrap().is_some());
+        assert!(smol::block_on(cache.recover("inactive")).unwrap().is_none());
+        assert_eq!(lookups(), 2);
+
+        cache.invalidate("active");
+        assert!(smol::block_on(cache.recover("active")).unwrap().is_some());
+        assert_eq!(lookups(), 3);
+
+        cache.invalidate_client("client");
+        assert!(smol::block_on(cache.recover("active")).unwrap().is_some());
+        assert!(smol::block_on(cache.recover("inactive")).unwrap().is_none());
+        assert_eq!(lookups(), 4);
+    }
+}
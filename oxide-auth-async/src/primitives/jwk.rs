@@ -0,0 +1,95 @@
+//! JSON Web Keys verifying the signatures of JWTs, shared by the features validating them.
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde_json::Value;
+
+/// A verification key of the key set.
+pub(crate) struct Jwk {
+    pub kid: Option<String>,
+    pub alg: Option<String>,
+    key: Key,
+}
+
+enum Key {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    P256(Vec<u8>),
+    P384(Vec<u8>),
+    Ed25519(Vec<u8>),
+}
+
+impl Jwk {
+    pub fn parse(jwk: &Value) -> Option<Self> {
+        if jwk["use"].as_str().is_some_and(|usage| usage != "sig") {
+            return None;
+        }
+
+        let member = |name: &str| jwk[name].as_str().and_then(decode);
+        let key = match (jwk["kty"].as_str()?, jwk["crv"].as_str()) {
+            ("RSA", _) => Key::Rsa {
+                n: member("n")?,
+                e: member("e")?,
+            },
+            ("EC", Some(crv @ "P-256")) | ("EC", Some(crv @ "P-384")) => {
+                // Uncompressed points, as ring expects them.
+                let mut point = vec![0x04];
+                point.extend(member("x")?);
+                point.extend(member("y")?);
+                match crv {
+                    "P-256" => Key::P256(point),
+                    _ => Key::P384(point),
+                }
+            }
+            ("OKP", Some("Ed25519")) => Key::Ed25519(member("x")?),
+            _ => return None,
+        };
+
+        Some(Jwk {
+            kid: jwk["kid"].as_str().map(str::to_owned),
+            alg: jwk["alg"].as_str().map(str::to_owned),
+            key,
+        })
+    }
+
+    pub fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+        if self.alg.as_deref().is_some_and(|expected| expected != alg) {
+            return false;
+        }
+
+        let verified = match (&self.key, alg) {
+            (Key::Rsa { n, e }, alg) => {
+                let parameters = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                    "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                    "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                    "PS512" => &signature::RSA_PSS_2048_8192_SHA512,
+                    _ => return false,
+                };
+                RsaPublicKeyComponents { n, e }.verify(parameters, message, signature)
+            }
+            (Key::P256(point), "ES256") => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, signature)
+            }
+            (Key::P384(point), "ES384") => {
+                UnparsedPublicKey::new(&signature::ECDSA_P384_SHA384_FIXED, point)
+                    .verify(message, signature)
+            }
+            (Key::Ed25519(key), "EdDSA") => {
+                UnparsedPublicKey::new(&signature::ED25519, key).verify(message, signature)
+            }
+            _ => return false,
+        };
+
+        verified.is_ok()
+    }
+}
+
+pub fn decode(part: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(part).ok()
+}
+
+pub fn decode_json(part: &str) -> Option<Value> {
+    serde_json::from_slice(&decode(part)?).ok()
+}
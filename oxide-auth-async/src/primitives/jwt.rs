@@ -0,0 +1,336 @@
+//! JWT access tokens validated with the published keys of the authorization server.
+//!
+//! The [`JwtIssuer`] lets a resource server accept the access tokens of [rfc9068] without asking
+//! the authorization server about each of them. It fetches the JSON Web Key Set of the
+//! authorization server, verifies the signature of each token locally and maps its claims back into
+//! a `Grant`:
+//!
+//! * `sub` is the owner and `client_id` the client of the grant,
+//! * `scope` is its space separated scope, an absent claim being the empty scope,
+//! * `exp` is the end of its validity.
+//!
+//! Tokens must be typed `at+jwt`, issued by the configured issuer and addressed to the configured
+//! audience. RSA keys with `RS256`, `RS384`, `RS512` and the `PS` variants, P-256 and P-384 keys
+//! with `ES256` and `ES384`, and Ed25519 keys with `EdDSA` are supported.
+//!
+//! The key set is fetched again after a maximum age, and when a token names a key that is not yet
+//! known, but not more often than a minimum interval. This picks up rotated keys without letting
+//! forged key ids trigger a request each.
+//!
+//! [`JwtIssuer`]: struct.JwtIssuer.html
+//! [rfc9068]: https://tools.ietf.org/html/rfc9068
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken};
+use serde_json::Value;
+use url::Url;
+
+use super::jwk::{decode, decode_json, Jwk};
+use super::Issuer;
+
+/// Validates JWT access tokens with the keys of a JSON Web Key Set.
+///
+/// The issuer only recovers access tokens, issuing and refreshing fails. The recovered grants carry
+/// the url of the key set as their redirect uri, as access tokens do not name one.
+pub struct JwtIssuer {
+    client: reqwest::Client,
+    jwks_uri: Url,
+    issuer: String,
+    audience: String,
+    leeway: Duration,
+    max_age: Duration,
+    min_refresh: Duration,
+    keys: Vec<Jwk>,
+    fetched: Option<DateTime<Utc>>,
+    attempted: Option<DateTime<Utc>>,
+}
+
+/// The outcome of validating a token with the current keys.
+enum Validation {
+    Valid(Box<Grant>),
+    Invalid,
+    UnknownKey,
+}
+
+impl JwtIssuer {
+    /// Validate tokens of `issuer` for `audience` with the keys published at `jwks_uri`.
+    ///
+    /// The issuer and audience are compared exactly with the `iss` and `aud` claims. By default,
+    /// the key set is fetched again after an hour or, for unknown keys, after a minute, and the
+    /// times of tokens are allowed to be off by a minute.
+    pub fn new(jwks_uri: Url, issuer: &str, audience: &str) -> Self {
+        JwtIssuer {
+            client: reqwest::Client::new(),
+            jwks_uri,
+            issuer: issuer.to_owned(),
+            audience: audience.to_owned(),
+            leeway: Duration::minutes(1),
+            max_age: Duration::hours(1),
+            min_refresh: Duration::minutes(1),
+            keys: Vec::new(),
+            fetched: None,
+            attempted: None,
+        }
+    }
+
+    /// Allow the times of tokens to be off by another duration, to account for clock skew.
+    pub fn with_leeway(self, leeway: Duration) -> Self {
+        JwtIssuer { leeway, ..self }
+    }
+
+    /// Fetch the key set again once it is older than `max_age`.
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        JwtIssuer { max_age, ..self }
+    }
+
+    /// Fetch the key set for unknown keys at most once per `min_refresh`.
+    pub fn with_min_refresh(self, min_refresh: Duration) -> Self {
+        JwtIssuer { min_refresh, ..self }
+    }
+
+    /// Send requests with a configured client, for example one with a timeout.
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        JwtIssuer { client, ..self }
+    }
+
+    /// Replace the keys with those of a key set.
+    ///
+    /// Keys of unsupported types are ignored. Use this to provide the keys without fetching them,
+    /// the set is still fetched once it reaches its maximum age.
+    pub fn set_keys(&mut self, jwks: &Value) {
+        self.keys = jwks["keys"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Jwk::parse)
+            .collect();
+        self.fetched = Some(Utc::now());
+    }
+
+    async fn fetch(&mut self, now: DateTime<Utc>) -> Result<(), ()> {
+        self.attempted = Some(now);
+        let response = self
+            .client
+            .get(self.jwks_uri.clone())
+            .send()
+            .await
+            .map_err(|_| ())?;
+        if !response.status().is_success() {
+            return Err(());
+        }
+
+        let body = response.bytes().await.map_err(|_| ())?;
+        let jwks: Value = serde_json::from_slice(&body).map_err(|_| ())?;
+        self.set_keys(&jwks);
+        Ok(())
+    }
+
+    fn is_stale(&self, now: DateTime<Utc>) -> bool {
+        match self.fetched {
+            Some(fetched) => fetched + self.max_age <= now,
+            None => true,
+        }
+    }
+
+    fn may_refresh(&self, now: DateTime<Utc>) -> bool {
+        match self.attempted {
+            Some(attempted) => attempted + self.min_refresh <= now,
+            None => true,
+        }
+    }
+
+    fn validate(&self, token: &str, now: DateTime<Utc>) -> Validation {
+        let (message, signature) = match token.rsplit_once('.') {
+            Some(parts) => parts,
+            None => return Validation::Invalid,
+        };
+        let (header, claims) = match message.split_once('.') {
+            Some(parts) => parts,
+            None => return Validation::Invalid,
+        };
+        let (header, claims, signature) =
+            match (decode_json(header), decode_json(claims), decode(signature)) {
+                (Some(header), Some(claims), Some(signature)) => (header, claims, signature),
+                _ => return Validation::Invalid,
+            };
+
+        let typ = header["typ"].as_str().unwrap_or_default().to_ascii_lowercase();
+        if typ != "at+jwt" && typ != "application/at+jwt" {
+            return Validation::Invalid;
+        }
+
+        let alg = header["alg"].as_str().unwrap_or_default();
+        let kid = header["kid"].as_str();
+        let mut candidates = self
+            .keys
+            .iter()
+            .filter(|jwk| kid.is_none() || jwk.kid.as_deref() == kid)
+            .peekable();
+        if candidates.peek().is_none() {
+            return Validation::UnknownKey;
+        }
+
+        if !candidates.any(|jwk| jwk.verify(alg, message.as_bytes(), &signature)) {
+            return Validation::Invalid;
+        }
+
+        match self.grant(&claims, now) {
+            Some(grant) => Validation::Valid(Box::new(grant)),
+            None => Validation::Invalid,
+        }
+    }
+
+    /// The grant of verified claims, or `None` if they are not valid for this resource server.
+    fn grant(&self, claims: &Value, now: DateTime<Utc>) -> Option<Grant> {
+        if claims["iss"].as_str() != Some(&self.issuer) {
+            return None;
+        }
+
+        let audience = match &claims["aud"] {
+            Value::String(aud) => aud == &self.audience,
+            Value::Array(aud) => aud.iter().any(|aud| aud.as_str() == Some(&self.audience)),
+            _ => false,
+        };
+        if !audience {
+            return None;
+        }
+
+        let until = Utc.timestamp_opt(claims["exp"].as_i64()?, 0).single()?;
+        if until + self.leeway <= now {
+            return None;
+        }
+
+        if let Some(nbf) = claims["nbf"].as_i64() {
+            if Utc.timestamp_opt(nbf, 0).single()? > now + self.leeway {
+                return None;
+            }
+        }
+
+        let scope = claims["scope"].as_str().unwrap_or_default().parse().ok()?;
+        Some(Grant {
+            owner_id: claims["sub"].as_str()?.to_owned(),
+            client_id: claims["client_id"].as_str()?.to_owned(),
+            scope,
+            redirect_uri: self.jwks_uri.clone(),
+            until,
+            extensions: Extensions::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl Issuer for JwtIssuer {
+    async fn issue(&mut self, _: Grant) -> Result<IssuedToken, ()> {
+        Err(())
+    }
+
+    async fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, ()> {
+        Err(())
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        let now = Utc::now();
+        // Keep validating with the previous keys while the key set can not be fetched.
+        if self.is_stale(now)
+            && self.may_refresh(now)
+            && self.fetch(now).await.is_err()
+            && self.keys.is_empty()
+        {
+            return Err(());
+        }
+
+        match self.validate(token, now) {
+            Validation::Valid(grant) => return Ok(Some(*grant)),
+            Validation::Invalid => return Ok(None),
+            Validation::UnknownKey if self.may_refresh(now) => self.fetch(now).await?,
+            Validation::UnknownKey => return Ok(None),
+        }
+
+        match self.validate(token, now) {
+            Validation::Valid(grant) => Ok(Some(*grant)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn recover_refresh(&mut self, _: &str) -> Result<Option<Grant>, ()> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+
+    fn encode(value: &Value) -> String {
+        URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    fn sign(key: &Ed25519KeyPair, header: &Value, claims: &Value) -> String {
+        let message = format!("{}.{}", encode(header), encode(claims));
+        let signature = key.sign(message.as_bytes());
+        format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    #[test]
+    fn validated_tokens() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let jwks = json!({ "keys": [{
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "kid": "current",
+            "x": URL_SAFE_NO_PAD.encode(key.public_key()),
+        }]});
+
+        let mut issuer = JwtIssuer::new(
+            "https://as.example/jwks".parse().unwrap(),
+            "https://as.example",
+            "https://rs.example",
+        );
+        issuer.set_keys(&jwks);
+
+        let now = Utc::now();
+        let header = json!({ "typ": "at+jwt", "alg": "EdDSA", "kid": "current" });
+        let claims = json!({
+            "iss": "https://as.example",
+            "aud": ["https://rs.example"],
+            "sub": "owner",
+            "client_id": "client",
+            "scope": "read",
+            "exp": (now + Duration::minutes(5)).timestamp(),
+        });
+
+        let token = sign(&key, &header, &claims);
+        let grant = match issuer.validate(&token, now) {
+            Validation::Valid(grant) => grant,
+            _ => panic!("Expected a valid token"),
+        };
+        assert_eq!(grant.owner_id, "owner");
+        assert_eq!(grant.client_id, "client");
+        assert_eq!(grant.scope, "read".parse().unwrap());
+
+        let invalid = |token: &str| matches!(issuer.validate(token, now), Validation::Invalid);
+        let tampered = token.replacen('.', ".e30", 1);
+        assert!(invalid(&tampered));
+
+        let untyped = json!({ "alg": "EdDSA", "kid": "current" });
+        assert!(invalid(&sign(&key, &untyped, &claims)));
+
+        let mut foreign = claims.clone();
+        foreign["aud"] = json!("https://other.example");
+        assert!(invalid(&sign(&key, &header, &foreign)));
+
+        let mut expired = claims.clone();
+        expired["exp"] = json!((now - Duration::minutes(5)).timestamp());
+        assert!(invalid(&sign(&key, &header, &expired)));
+
+        let rotated = json!({ "typ": "at+jwt", "alg": "EdDSA", "kid": "next" });
+        let unknown = issuer.validate(&sign(&key, &rotated, &claims), now);
+        assert!(matches!(unknown, Validation::UnknownKey));
+    }
+}
@@ -0,0 +1,138 @@
+//! Grant decisions of an Open Policy Agent.
+//!
+//! [`Opa`] asks the data API of an OPA server to decide over each grant before it is issued. The
+//! policy receives the grant as its input document:
+//!
+//! ```json
+//! {
+//!   "event": "code",
+//!   "client_id": "client",
+//!   "owner_id": "owner",
+//!   "scope": "profile email",
+//!   "redirect_uri": "https://client.example/endpoint",
+//!   "until": "2024-01-01T00:00:00Z",
+//!   "extensions": { "pkce": null }
+//! }
+//! ```
+//!
+//! The event is one of `code`, `token` and `refresh`, the extensions are the public extensions of
+//! the grant. The decision of the policy is either a boolean or an object such as
+//! `{ "allow": true, "scope": "profile" }`, which may narrow the scope of the grant. A decision
+//! that is undefined denies the grant.
+//!
+//! Other engines, for example Cedar which is evaluated in process, are used by implementing the
+//! `GrantPolicy` trait over their authorizer.
+//!
+//! [`Opa`]: struct.Opa.html
+use async_trait::async_trait;
+use oxide_auth::endpoint::{GrantDecision, GrantEvent};
+use oxide_auth::primitives::grant::Grant;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Map, Value};
+
+use crate::endpoint::GrantPolicy;
+
+/// Decides over grants with a policy of an OPA server.
+pub struct Opa {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Opa {
+    /// Evaluate the document at `path` of the OPA server at `address`.
+    ///
+    /// The address includes the scheme and port, for example `http://localhost:8181`. The path
+    /// names a package and rule, for example `oauth/grant/allow`.
+    pub fn new(address: &str, path: &str) -> Self {
+        Opa {
+            client: reqwest::Client::new(),
+            url: format!(
+                "{}/v1/data/{}",
+                address.trim_end_matches('/'),
+                path.trim_matches('/')
+            ),
+        }
+    }
+
+    /// Send requests with a configured client, for example one with a timeout.
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        Opa { client, ..self }
+    }
+
+    async fn evaluate(&self, input: Value) -> Option<Value> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(json!({ "input": input }).to_string())
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+
+        let body = response.bytes().await.ok()?;
+        let mut value: Value = serde_json::from_slice(&body).ok()?;
+        Some(value["result"].take())
+    }
+}
+
+fn input(event: GrantEvent, grant: &Grant) -> Value {
+    let event = match event {
+        GrantEvent::Code => "code",
+        GrantEvent::Token => "token",
+        GrantEvent::Refresh => "refresh",
+        GrantEvent::Revocation => "revocation",
+    };
+    let extensions: Map<String, Value> = grant
+        .extensions
+        .public()
+        .map(|(name, value)| (name.to_owned(), value.into()))
+        .collect();
+    json!({
+        "event": event,
+        "client_id": grant.client_id,
+        "owner_id": grant.owner_id,
+        "scope": grant.scope.to_string(),
+        "redirect_uri": grant.redirect_uri.as_str(),
+        "until": grant.until.to_rfc3339(),
+        "extensions": extensions,
+    })
+}
+
+/// Apply the result of the policy to the grant.
+fn apply(result: Value, grant: &mut Grant) -> GrantDecision {
+    match result {
+        Value::Bool(true) => GrantDecision::Allow,
+        Value::Bool(false) | Value::Null => GrantDecision::Deny,
+        Value::Object(mut result) => {
+            if result.get("allow") != Some(&Value::Bool(true)) {
+                return GrantDecision::Deny;
+            }
+
+            match result.remove("scope") {
+                None | Some(Value::Null) => GrantDecision::Allow,
+                Some(Value::String(scope)) => match scope.parse() {
+                    Ok(scope) => {
+                        grant.scope = scope;
+                        GrantDecision::Allow
+                    }
+                    Err(_) => GrantDecision::Error,
+                },
+                Some(_) => GrantDecision::Error,
+            }
+        }
+        _ => GrantDecision::Error,
+    }
+}
+
+#[async_trait]
+impl GrantPolicy for Opa {
+    async fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+        match self.evaluate(input(event, grant)).await {
+            Some(result) => apply(result, grant),
+            None => GrantDecision::Error,
+        }
+    }
+}
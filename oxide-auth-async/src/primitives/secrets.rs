@@ -0,0 +1,316 @@
+//! Client secrets and signing keys resolved from an external secret manager.
+//!
+//! A [`SecretStore`] looks up secrets by name when they are first needed, instead of all secrets
+//! being loaded into a `ClientMap` at startup. [`CachedSecrets`] keeps the looked up secrets, and
+//! the knowledge that there is none, for a time to live so that the manager is not asked on every
+//! request. With the `vault` feature, `vault::Kv` reads the secrets from the key value engine of
+//! HashiCorp Vault.
+//!
+//! [`SecretRegistrar`] authenticates clients with the secrets of a store, while the wrapped
+//! registrar continues to hold their redirect uris and scopes. [`SecretAssertion`] signs tokens
+//! with an HMAC key from the store.
+//!
+//! [`SecretStore`]: trait.SecretStore.html
+//! [`CachedSecrets`]: struct.CachedSecrets.html
+//! [`SecretRegistrar`]: struct.SecretRegistrar.html
+//! [`SecretAssertion`]: struct.SecretAssertion.html
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::generator::{self, Assertion, AssertionKind, SignError};
+use oxide_auth::primitives::registrar::{
+    BoundClient, ClientAuthMethod, ClientUrl, GrantType, PreGrant, RefreshPolicy, RegistrarError,
+};
+use oxide_auth::primitives::scope::Scope;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use super::{Registrar, Signer, Verifier};
+
+/// Looks up secrets by name.
+#[async_trait]
+pub trait SecretStore {
+    /// The secret stored under `name`, or `None` if there is none.
+    async fn secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError>;
+}
+
+/// The secret manager could not be asked for a secret.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SecretError;
+
+/// Keeps the secrets of a store for a time to live.
+pub struct CachedSecrets<S> {
+    store: S,
+    ttl: Duration,
+    absent_ttl: Duration,
+    entries: Mutex<HashMap<String, Cached>>,
+}
+
+struct Cached {
+    secret: Option<Zeroizing<Vec<u8>>>,
+    until: DateTime<Utc>,
+}
+
+/// Authenticates clients with the secrets of a store.
+///
+/// The secret of a client is stored under its id, prefixed with `clients/` by default. Clients
+/// without a secret in the store are authenticated by the wrapped registrar, which also binds the
+/// redirect uris and negotiates the scopes of all clients.
+pub struct SecretRegistrar<R, S> {
+    registrar: R,
+    secrets: S,
+    prefix: String,
+}
+
+/// Signs and verifies with an HMAC key stored under a name.
+///
+/// The key is looked up for each signature, wrap the store in `CachedSecrets` to keep it.
+pub struct SecretAssertion<S> {
+    secrets: S,
+    name: String,
+}
+
+impl<S> CachedSecrets<S> {
+    /// Keep secrets for `ttl`, and the absence of a secret for as long.
+    pub fn new(store: S, ttl: Duration) -> Self {
+        CachedSecrets {
+            store,
+            ttl,
+            absent_ttl: ttl,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Keep the absence of a secret for another duration.
+    pub fn with_absent_ttl(self, absent_ttl: Duration) -> Self {
+        CachedSecrets { absent_ttl, ..self }
+    }
+
+    /// Forget the cached secret of a name, after it has been rotated.
+    pub fn invalidate(&self, name: &str) {
+        self.lock().remove(name);
+    }
+
+    /// Forget all cached secrets.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Cached>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn cached(&self, name: &str, now: DateTime<Utc>) -> Option<Option<Zeroizing<Vec<u8>>>> {
+        let mut entries = self.lock();
+        match entries.get(name) {
+            Some(cached) if cached.until > now => Some(cached.secret.clone()),
+            Some(_) => {
+                entries.remove(name);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: SecretStore + Send + Sync> SecretStore for CachedSecrets<S> {
+    async fn secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError> {
+        let now = Utc::now();
+        if let Some(secret) = self.cached(name, now) {
+            return Ok(secret);
+        }
+
+        // Errors are not cached, the next request asks the store again.
+        let secret = self.store.secret(name).await?;
+        let ttl = match secret {
+            Some(_) => self.ttl,
+            None => self.absent_ttl,
+        };
+        let cached = Cached {
+            secret: secret.clone(),
+            until: now + ttl,
+        };
+        self.lock().insert(name.to_owned(), cached);
+        Ok(secret)
+    }
+}
+
+#[async_trait]
+impl<S: SecretStore + Send + Sync + ?Sized> SecretStore for &S {
+    async fn secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError> {
+        (**self).secret(name).await
+    }
+}
+
+#[async_trait]
+impl<S: SecretStore + Send + Sync + ?Sized> SecretStore for Arc<S> {
+    async fn secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError> {
+        (**self).secret(name).await
+    }
+}
+
+impl<R, S> SecretRegistrar<R, S> {
+    /// Authenticate the clients of `registrar` with the secrets of a store.
+    pub fn new(registrar: R, secrets: S) -> Self {
+        SecretRegistrar {
+            registrar,
+            secrets,
+            prefix: "clients/".to_owned(),
+        }
+    }
+
+    /// Look up the secret of a client under its id with another prefix.
+    pub fn with_prefix(self, prefix: &str) -> Self {
+        SecretRegistrar {
+            prefix: prefix.to_owned(),
+            ..self
+        }
+    }
+
+    /// The wrapped registrar.
+    pub fn registrar(&self) -> &R {
+        &self.registrar
+    }
+}
+
+#[async_trait]
+impl<R, S> Registrar for SecretRegistrar<R, S>
+where
+    R: Registrar + Send + Sync,
+    S: SecretStore + Send + Sync,
+{
+    async fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        self.registrar.bound_redirect(bound).await
+    }
+
+    async fn negotiate<'a>(
+        &self, client: BoundClient<'a>, scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        self.registrar.negotiate(client, scope).await
+    }
+
+    async fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        let name = format!("{}{}", self.prefix, client_id);
+        let secret = match self.secrets.secret(&name).await {
+            Ok(Some(secret)) => secret,
+            Ok(None) => return self.registrar.check(client_id, passphrase).await,
+            Err(SecretError) => return Err(RegistrarError::PrimitiveError),
+        };
+
+        match passphrase {
+            Some(passphrase) if bool::from(secret.as_slice().ct_eq(passphrase)) => Ok(()),
+            _ => Err(RegistrarError::Unspecified),
+        }
+    }
+
+    async fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.registrar.refresh_policy(client_id).await
+    }
+
+    async fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.registrar.permits_grant(client_id, grant_type).await
+    }
+
+    async fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.registrar.auth_method(client_id).await
+    }
+}
+
+impl<S> SecretAssertion<S> {
+    /// Use the key stored under `name`.
+    pub fn new(secrets: S, name: &str) -> Self {
+        SecretAssertion {
+            secrets,
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl<S: SecretStore + Send + Sync> SecretAssertion<S> {
+    async fn assertion(&self) -> Result<Assertion, SignError> {
+        match self.secrets.secret(&self.name).await {
+            Ok(Some(key)) => Ok(Assertion::new(AssertionKind::HmacSha256, &key)),
+            _ => Err(SignError),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: SecretStore + Send + Sync> Signer for SecretAssertion<S> {
+    async fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        generator::Signer::sign(&self.assertion().await?, data)
+    }
+}
+
+#[async_trait]
+impl<S: SecretStore + Send + Sync> Verifier for SecretAssertion<S> {
+    async fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError> {
+        generator::Verifier::verify(&self.assertion().await?, data, signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use oxide_auth::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+    /// Holds the secret of one client and counts the lookups.
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    #[async_trait]
+    impl SecretStore for Counting {
+        async fn secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            match name {
+                "clients/confidential" => Ok(Some(Zeroizing::new(b"secret".to_vec()))),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    fn registrar() -> ClientMap {
+        let mut registrar = ClientMap::new();
+        for client in ["confidential", "public"] {
+            registrar.register_client(Client::public(
+                client,
+                RegisteredUrl::Semantic("https://client.example/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+            ));
+        }
+        registrar
+    }
+
+    #[test]
+    fn cached_lookups() {
+        let cached = CachedSecrets::new(Counting::default(), Duration::minutes(5));
+        for _ in 0..2 {
+            let secret = smol::block_on(cached.secret("clients/confidential")).unwrap();
+            assert_eq!(secret.as_deref().map(Vec::as_slice), Some(&b"secret"[..]));
+            assert!(smol::block_on(cached.secret("clients/other")).unwrap().is_none());
+        }
+        assert_eq!(cached.store.0.load(Ordering::Relaxed), 2);
+
+        cached.invalidate("clients/confidential");
+        smol::block_on(cached.secret("clients/confidential")).unwrap();
+        assert_eq!(cached.store.0.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn secrets_authenticate() {
+        let registrar = SecretRegistrar::new(registrar(), Counting::default());
+        let check = |client, passphrase| smol::block_on(registrar.check(client, passphrase));
+
+        assert!(check("confidential", Some(b"secret")).is_ok());
+        assert!(check("confidential", Some(b"guess")).is_err());
+        assert!(check("confidential", None).is_err());
+        assert!(check("public", None).is_ok());
+        assert!(check("public", Some(b"secret")).is_err());
+    }
+}
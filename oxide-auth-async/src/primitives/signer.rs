@@ -0,0 +1,162 @@
+//! Signs grants with keys held outside the process.
+//!
+//! [`TokenSigner`] issues the tokens of the synchronous `TokenSigner` of `oxide_auth`, but awaits
+//! an asynchronous [`Signer`] and [`Verifier`]. The key can thus live in a KMS, an HSM or the
+//! transit engine of Vault, which are reached over the network. Synchronous signers such as
+//! `Assertion` can be used as well.
+//!
+//! [`TokenSigner`]: struct.TokenSigner.html
+//! [`Signer`]: ../trait.Signer.html
+//! [`Verifier`]: ../trait.Verifier.html
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use oxide_auth::primitives::generator::{SignError, SignedGrant};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenType};
+
+use super::{Issuer, Signer, Verifier};
+
+/// Signs grants instead of storing them, with an asynchronous signer.
+///
+/// As for the synchronous `TokenSigner`, issued tokens can not be revoked.
+pub struct TokenSigner<S> {
+    duration: Option<Duration>,
+    signer: S,
+    counter: AtomicU64,
+    have_refresh: bool,
+}
+
+impl<S> TokenSigner<S> {
+    /// Construct a signing instance from a signer of the key.
+    pub fn new(signer: S) -> Self {
+        TokenSigner {
+            duration: None,
+            signer,
+            counter: AtomicU64::new(0),
+            have_refresh: false,
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.duration = Some(duration);
+    }
+
+    /// Set all grants to be valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.duration = None;
+    }
+
+    /// Determine whether to generate refresh tokens.
+    ///
+    /// By default, this option is *off*, as refresh tokens can not be revoked either.
+    pub fn generate_refresh_tokens(&mut self, refresh: bool) {
+        self.have_refresh = refresh;
+    }
+
+    /// The signer of the key.
+    pub fn signer(&self) -> &S {
+        &self.signer
+    }
+
+    fn next_counter(&self) -> u64 {
+        self.counter.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl<S: Signer + Verifier + Send + Sync> TokenSigner<S> {
+    async fn sign(&self, tag: &str, grant: &Grant) -> Result<String, SignError> {
+        let signed = SignedGrant::new(self.next_counter(), grant, tag)?;
+        let signature = self.signer.sign(signed.data()).await?;
+        Ok(signed.into_token(signature))
+    }
+
+    async fn extract(&self, tag: &str, token: &str) -> Option<Grant> {
+        let (signed, signature) = SignedGrant::from_token(token).ok()?;
+        self.signer.verify(signed.data(), &signature).await.ok()?;
+        match signed.grant() {
+            Ok((grant, signed_tag)) if signed_tag == tag => Some(grant),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Signer + Verifier + Send + Sync> Issuer for TokenSigner<S> {
+    async fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
+        if let Some(duration) = self.duration {
+            grant.until = Utc::now() + duration;
+        }
+
+        let token = self.sign("token", &grant).await.map_err(|_| ())?;
+        if !self.have_refresh {
+            return Ok(IssuedToken::without_refresh(token, grant.until));
+        }
+
+        let refresh = self.sign("refresh", &grant).await.map_err(|_| ())?;
+        Ok(IssuedToken {
+            token,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, ()> {
+        Err(())
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        Ok(self.extract("token", token).await)
+    }
+
+    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        if !self.have_refresh {
+            return Ok(None);
+        }
+
+        Ok(self.extract("refresh", token).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::Assertion;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::issuer;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "owner".into(),
+            client_id: "client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::hours(1),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn compatible_tokens() {
+        let key = Assertion::ephemeral();
+        let mut signer = TokenSigner::new(&key);
+        signer.generate_refresh_tokens(true);
+
+        let issued = smol::block_on(signer.issue(grant())).unwrap();
+        let refresh = issued.refresh.clone().unwrap();
+        let recovered = smol::block_on(signer.recover_token(&issued.token)).unwrap();
+        assert_eq!(recovered.unwrap().client_id, "client");
+        assert!(smol::block_on(signer.recover_token(&refresh)).unwrap().is_none());
+        assert!(smol::block_on(signer.recover_refresh(&refresh))
+            .unwrap()
+            .is_some());
+
+        // The synchronous signer reads the same tokens.
+        let sync = issuer::TokenSigner::new(&key);
+        let recovered = issuer::Issuer::recover_token(&sync, &issued.token).unwrap();
+        assert!(recovered.is_some());
+    }
+}
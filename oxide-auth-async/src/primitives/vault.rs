@@ -0,0 +1,203 @@
+//! Keys and secrets held by HashiCorp Vault.
+//!
+//! [`Transit`] signs and verifies with a named key of the transit secrets engine, so that the key
+//! never leaves Vault. The key must be of a type supporting signatures, such as `ed25519` or
+//! `ecdsa-p256`. The signatures are the `vault:v1:...` strings returned by Vault, which record the
+//! key version.
+//!
+//! [`Kv`] reads client secrets and keys from the version 2 key value secrets engine, as a
+//! `SecretStore`.
+//!
+//! [`Transit`]: struct.Transit.html
+//! [`Kv`]: struct.Kv.html
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use oxide_auth::primitives::generator::SignError;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use zeroize::Zeroizing;
+
+use super::secrets::{SecretError, SecretStore};
+use super::{Signer, Verifier};
+
+/// Signs with a key of the transit secrets engine.
+pub struct Transit {
+    vault: Connection,
+    mount: String,
+    key: String,
+}
+
+/// Reads secrets from the key value secrets engine.
+///
+/// Each secret is the value of one field of the secret stored under its name.
+pub struct Kv {
+    vault: Connection,
+    mount: String,
+    field: String,
+}
+
+struct Connection {
+    client: reqwest::Client,
+    address: String,
+    token: Zeroizing<String>,
+}
+
+impl Connection {
+    fn new(address: &str, token: &str) -> Self {
+        Connection {
+            client: reqwest::Client::new(),
+            address: address.trim_end_matches('/').to_owned(),
+            token: Zeroizing::new(token.to_owned()),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/v1/{}", self.address, path);
+        self.client
+            .request(method, url)
+            .header("X-Vault-Token", self.token.as_str())
+    }
+
+    /// The `data` of a successful response, or `None` if nothing was found.
+    async fn data(request: reqwest::RequestBuilder) -> Result<Option<Value>, ()> {
+        let response = request.send().await.map_err(|_| ())?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(());
+        }
+
+        let body = response.bytes().await.map_err(|_| ())?;
+        let mut value: Value = serde_json::from_slice(&body).map_err(|_| ())?;
+        Ok(Some(value["data"].take()))
+    }
+}
+
+impl Transit {
+    /// Use the key `key` of the engine mounted at `transit` of the Vault at `address`.
+    ///
+    /// The address includes the scheme and port, for example `https://vault.example:8200`. The
+    /// token needs the permission to update the `sign` and `verify` paths of the key.
+    pub fn new(address: &str, token: &str, key: &str) -> Self {
+        Transit {
+            vault: Connection::new(address, token),
+            mount: "transit".to_owned(),
+            key: key.to_owned(),
+        }
+    }
+
+    /// Use the engine mounted at another path.
+    pub fn with_mount(self, mount: &str) -> Self {
+        Transit {
+            mount: mount.trim_matches('/').to_owned(),
+            ..self
+        }
+    }
+
+    /// Send requests with a configured client, for example one trusting a private CA.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.vault.client = client;
+        self
+    }
+
+    async fn post(&self, operation: &str, body: Value) -> Result<Value, SignError> {
+        let path = format!("{}/{}/{}", self.mount, operation, self.key);
+        let request = self
+            .vault
+            .request(reqwest::Method::POST, &path)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        match Connection::data(request).await {
+            Ok(Some(data)) => Ok(data),
+            _ => Err(SignError),
+        }
+    }
+}
+
+impl Kv {
+    /// Read the `value` field of secrets in the engine mounted at `secret` of the Vault at
+    /// `address`.
+    ///
+    /// The token needs the permission to read the `data` paths of the secrets.
+    pub fn new(address: &str, token: &str) -> Self {
+        Kv {
+            vault: Connection::new(address, token),
+            mount: "secret".to_owned(),
+            field: "value".to_owned(),
+        }
+    }
+
+    /// Use the engine mounted at another path.
+    pub fn with_mount(self, mount: &str) -> Self {
+        Kv {
+            mount: mount.trim_matches('/').to_owned(),
+            ..self
+        }
+    }
+
+    /// Read another field of the secrets.
+    pub fn with_field(self, field: &str) -> Self {
+        Kv {
+            field: field.to_owned(),
+            ..self
+        }
+    }
+
+    /// Send requests with a configured client, for example one trusting a private CA.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.vault.client = client;
+        self
+    }
+}
+
+#[async_trait]
+impl Signer for Transit {
+    async fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        let body = json!({ "input": STANDARD.encode(data) });
+        let data = self.post("sign", body).await?;
+        match data["signature"].as_str() {
+            Some(signature) => Ok(signature.as_bytes().to_vec()),
+            None => Err(SignError),
+        }
+    }
+}
+
+#[async_trait]
+impl Verifier for Transit {
+    async fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError> {
+        let signature = std::str::from_utf8(signature).map_err(|_| SignError)?;
+        let body = json!({
+            "input": STANDARD.encode(data),
+            "signature": signature,
+        });
+        let data = self.post("verify", body).await?;
+        match data["valid"].as_bool() {
+            Some(true) => Ok(()),
+            _ => Err(SignError),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretStore for Kv {
+    async fn secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError> {
+        let path = format!("{}/data/{}", self.mount, name);
+        let request = self.vault.request(reqwest::Method::GET, &path);
+        let mut data = match Connection::data(request).await {
+            Ok(Some(data)) => data,
+            Ok(None) => return Ok(None),
+            Err(()) => return Err(SecretError),
+        };
+
+        // Secrets without the field are treated as absent.
+        let secret = match data["data"][self.field.as_str()].take() {
+            Value::String(secret) => Zeroizing::new(secret.into_bytes()),
+            Value::Null => return Ok(None),
+            _ => return Err(SecretError),
+        };
+        Ok(Some(secret))
+    }
+}
@@ -72,7 +72,7 @@ impl<'a> Endpoint<CraftedRequest> for AccessTokenEndpoint<'a> {
     fn web_error(&mut self, _err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
         unimplemented!()
     }
-    fn scopes(&mut self) -> Option<&mut dyn oxide_auth::endpoint::Scopes<CraftedRequest>> {
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
         None
     }
     fn owner_solicitor(
@@ -4,10 +4,11 @@ use oxide_auth::primitives::authorizer::AuthMap;
 use oxide_auth::{
     primitives::registrar::{Client, ClientMap, RegisteredUrl},
     frontends::simple::endpoint::Error,
-    endpoint::WebRequest,
+    endpoint::{OwnerConsent, Solicitation, WebRequest},
 };
 
 use crate::endpoint::{Endpoint, OwnerSolicitor, authorization::AuthorizationFlow};
+use crate::frontends::simple::endpoint::FnSolicitor;
 
 use super::{CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
 use super::{Allow, Deny};
@@ -44,7 +45,7 @@ impl<'a> Endpoint<CraftedRequest> for AuthorizationEndpoint<'a> {
     fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
         None
     }
-    fn scopes(&mut self) -> Option<&mut dyn oxide_auth::endpoint::Scopes<CraftedRequest>> {
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
         None
     }
     fn response(
@@ -304,3 +305,50 @@ fn auth_request_error_malformed_scope() {
 
     AuthorizationSetup::new().test_error_redirect(malformed_scope, Allow(EXAMPLE_OWNER_ID.to_string()));
 }
+
+/// Looks up the owner of the session named in the request, as a session store would.
+fn session_solicitor() -> impl OwnerSolicitor<CraftedRequest> + Send + Sync {
+    FnSolicitor(|request: &mut CraftedRequest, _: Solicitation<'static>| {
+        let session = request.auth.take();
+        async move {
+            match session.as_deref() {
+                Some("session") => OwnerConsent::Authorized(EXAMPLE_OWNER_ID.to_string()),
+                _ => OwnerConsent::Denied,
+            }
+        }
+    })
+}
+
+#[test]
+fn auth_request_async_solicitor() {
+    let request = |auth: Option<&str>| CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: auth.map(str::to_owned),
+    };
+
+    let mut setup = AuthorizationSetup::new();
+    let mut solicitor = session_solicitor();
+    let mut authorization_flow = AuthorizationFlow::prepare(AuthorizationEndpoint::new(
+        &setup.registrar,
+        &mut setup.authorizer,
+        &mut solicitor,
+    ))
+    .unwrap();
+    let response =
+        smol::block_on(authorization_flow.execute(request(Some("session")))).expect("Should not error");
+    match response.location {
+        Some(ref url) if !url.as_str().contains("error") => (),
+        other => panic!("Expected successful redirect: {:?}", other),
+    }
+
+    setup.test_error_redirect(request(None), session_solicitor());
+}
@@ -0,0 +1,115 @@
+use oxide_auth::endpoint::{bind_grant, BindingDecision, RequestContext, Template};
+use oxide_auth::frontends::binding::NetworkBinding;
+use oxide_auth::frontends::simple::endpoint::Error;
+use oxide_auth::frontends::simple::request::{Request, Response, Status};
+use oxide_auth::primitives::generator::RandomGenerator;
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::primitives::issuer::TokenMap;
+use oxide_auth::primitives::scope::Scope;
+
+use chrono::{Duration, Utc};
+
+use super::defaults::*;
+use crate::endpoint::{resource::ResourceFlow, BindingPolicy, Endpoint, Scopes};
+use crate::primitives::Issuer;
+
+struct BindingEndpoint {
+    issuer: TokenMap<RandomGenerator>,
+    scopes: Vec<Scope>,
+    policy: NetworkBinding,
+}
+
+impl Endpoint<Request> for BindingEndpoint {
+    type Error = Error<Request>;
+
+    fn registrar(&self) -> Option<&(dyn crate::primitives::Registrar + Sync)> {
+        None
+    }
+    fn authorizer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Authorizer + Send)> {
+        None
+    }
+    fn issuer_mut(&mut self) -> Option<&mut (dyn Issuer + Send)> {
+        Some(&mut self.issuer)
+    }
+    fn scopes(&mut self) -> Option<&mut (dyn Scopes<Request> + Send)> {
+        Some(&mut self.scopes)
+    }
+    fn response(&mut self, _: &mut Request, _: Template) -> Result<Response, Self::Error> {
+        Ok(Default::default())
+    }
+    fn error(&mut self, err: oxide_auth::endpoint::OAuthError) -> Self::Error {
+        Error::OAuth(err)
+    }
+    fn web_error(&mut self, err: oxide_auth::frontends::simple::request::NoError) -> Self::Error {
+        Error::Web(err)
+    }
+    fn owner_solicitor(&mut self) -> Option<&mut (dyn crate::endpoint::OwnerSolicitor<Request> + Send)> {
+        None
+    }
+    fn binding_policy(&mut self) -> Option<&mut (dyn BindingPolicy + Send)> {
+        Some(&mut self.policy)
+    }
+}
+
+fn context(remote_addr: &str) -> RequestContext {
+    RequestContext {
+        remote_addr: Some(remote_addr.to_owned()),
+        user_agent: Some("Client/1.0".to_owned()),
+        ..RequestContext::default()
+    }
+}
+
+fn request(token: &str, remote_addr: &str) -> Request {
+    Request {
+        auth: Some(format!("Bearer {}", token)),
+        context: Some(context(remote_addr)),
+        ..Request::default()
+    }
+}
+
+/// An endpoint holding one token, bound to `198.51.100.7` and the `Client/1.0` user agent.
+fn bound_endpoint(mut policy: NetworkBinding) -> (BindingEndpoint, String) {
+    let mut grant = Grant {
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    };
+    let binding = smol::block_on(BindingPolicy::bind(&mut policy, &context("198.51.100.7"))).unwrap();
+    bind_grant(&mut grant, &binding);
+
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    let token = smol::block_on(issuer.issue(grant)).unwrap().token;
+    let endpoint = BindingEndpoint {
+        issuer,
+        scopes: vec![EXAMPLE_SCOPE.parse().unwrap()],
+        policy,
+    };
+    (endpoint, token)
+}
+
+#[test]
+fn resource_checks_binding() {
+    let (endpoint, token) = bound_endpoint(NetworkBinding::new());
+    let mut flow = ResourceFlow::prepare(endpoint).unwrap();
+
+    assert!(smol::block_on(flow.execute(request(&token, "198.51.100.42"))).is_ok());
+    match smol::block_on(flow.execute(request(&token, "203.0.113.7"))) {
+        Err(Ok(response)) => {
+            assert_eq!(response.status, Status::Unauthorized);
+            assert!(response.www_authenticate.unwrap().contains("invalid_token"));
+        }
+        other => panic!("Expected a refusal, got {:?}", other.map(|grant| grant.client_id)),
+    }
+    assert!(smol::block_on(flow.execute(request(&token, "198.51.100.7"))).is_ok());
+
+    let policy = NetworkBinding::new().on_mismatch(BindingDecision::Reauthenticate);
+    let (endpoint, token) = bound_endpoint(policy);
+    let mut flow = ResourceFlow::prepare(endpoint).unwrap();
+
+    assert!(smol::block_on(flow.execute(request(&token, "203.0.113.7"))).is_err());
+    // The token was revoked along with the rest of the grant.
+    assert!(smol::block_on(flow.execute(request(&token, "198.51.100.7"))).is_err());
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use oxide_auth::primitives::claims::{access_token_claims, id_token_claims, Claims, ClaimsError, TokenKind};
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use serde_json::json;
+
+use crate::primitives::ClaimsMapper;
+
+use super::defaults::*;
+
+/// Looks up the roles of owners, as a directory reached over the network would.
+struct Directory(HashMap<&'static str, &'static str>);
+
+#[async_trait::async_trait]
+impl ClaimsMapper for Directory {
+    async fn claims(&self, grant: &Grant, kind: TokenKind) -> Result<Claims, ClaimsError> {
+        let role = self.0.get(grant.owner_id.as_str()).ok_or(ClaimsError)?;
+        let mut claims = Claims::new();
+        match kind {
+            TokenKind::AccessToken => claims.insert("roles".into(), json!([role])),
+            TokenKind::IdToken => claims.insert("role".into(), json!(role)),
+        };
+        Ok(claims)
+    }
+}
+
+fn grant(owner_id: &str) -> Grant {
+    Grant {
+        owner_id: owner_id.to_string(),
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    }
+}
+
+#[test]
+fn looked_up_claims() {
+    let directory = Directory([(EXAMPLE_OWNER_ID, "admin")].iter().cloned().collect());
+
+    let grant = grant(EXAMPLE_OWNER_ID);
+    let mapped = smol::block_on(directory.claims(&grant, TokenKind::AccessToken)).unwrap();
+    let claims = access_token_claims(&grant, "https://as.example", "https://rs.example", mapped);
+    assert_eq!(claims["sub"], EXAMPLE_OWNER_ID);
+    assert_eq!(claims["roles"], json!(["admin"]));
+
+    let mapped = smol::block_on(directory.claims(&grant, TokenKind::IdToken)).unwrap();
+    let claims = id_token_claims(&grant, "https://as.example", None, mapped);
+    assert_eq!(claims["aud"], EXAMPLE_CLIENT_ID);
+    assert_eq!(claims["role"], "admin");
+
+    let unknown = smol::block_on(directory.claims(&self::grant("Mallory"), TokenKind::IdToken));
+    assert_eq!(unknown, Err(ClaimsError));
+}
+
+#[test]
+fn synchronous_mappers() {
+    let tenant = |_: &Grant, _: TokenKind| {
+        let mut claims = Claims::new();
+        claims.insert("tenant".into(), json!("acme"));
+        Ok(claims)
+    };
+
+    let claims = smol::block_on(ClaimsMapper::claims(
+        &tenant,
+        &grant(EXAMPLE_OWNER_ID),
+        TokenKind::AccessToken,
+    ));
+    assert_eq!(claims.unwrap()["tenant"], "acme");
+}
@@ -46,7 +46,7 @@ impl<'a> Endpoint<CraftedRequest> for ClientCredentialsEndpoint<'a> {
     fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
         Some(self.issuer)
     }
-    fn scopes(&mut self) -> Option<&mut dyn oxide_auth::endpoint::Scopes<CraftedRequest>> {
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
         None
     }
     fn response(
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use oxide_auth::code_grant::accesstoken::TokenResponse;
+use oxide_auth::code_grant::error::{AccessTokenError, AuthorizationError};
+use oxide_auth::primitives::authorizer::AuthMap;
+use oxide_auth::primitives::issuer::TokenMap;
+use oxide_auth::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use oxide_auth::{
+    frontends::simple::endpoint::Error,
+    endpoint::{GrantRecord, WebRequest},
+};
+use serde_json::Value;
+
+use crate::endpoint::{
+    access_token::AccessTokenFlow, authorization::AuthorizationFlow, Endpoint, ErrorCustomizer,
+    OwnerSolicitor, TokenResponseCustomizer,
+};
+
+use super::{Allow, Body, CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// Names the owner of the grant and tries to replace the token type.
+struct Tenant;
+
+#[async_trait::async_trait]
+impl TokenResponseCustomizer<CraftedRequest> for Tenant {
+    async fn customize(
+        &mut self, _: &mut CraftedRequest, grant: &GrantRecord, additional: &mut HashMap<String, Value>,
+    ) {
+        additional.insert("owner".to_owned(), grant.owner_id.clone().into());
+        additional.insert("token_type".to_owned(), "forged".into());
+    }
+}
+
+/// Points clients to a support page.
+struct Support;
+
+#[async_trait::async_trait]
+impl ErrorCustomizer<CraftedRequest> for Support {
+    async fn authorization_error(&mut self, _: &mut CraftedRequest, _: &mut AuthorizationError) {}
+
+    async fn access_token_error(&mut self, _: &mut CraftedRequest, error: &mut AccessTokenError) {
+        error.explain_uri("https://support.example/errors".parse().unwrap());
+        assert!(error.add_member("trace_id", "42"));
+    }
+}
+
+struct CustomizedEndpoint<'a> {
+    registrar: &'a ClientMap,
+    authorizer: &'a mut AuthMap<TestGenerator>,
+    issuer: &'a mut TokenMap<TestGenerator>,
+    solicitor: Allow,
+    customizer: Tenant,
+    support: Support,
+}
+
+impl<'a> Endpoint<CraftedRequest> for CustomizedEndpoint<'a> {
+    type Error = Error<CraftedRequest>;
+
+    fn registrar(&self) -> Option<&(dyn crate::primitives::Registrar + Sync)> {
+        Some(self.registrar)
+    }
+    fn authorizer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Authorizer + Send)> {
+        Some(self.authorizer)
+    }
+    fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
+        Some(self.issuer)
+    }
+    fn response(
+        &mut self, _: &mut CraftedRequest, _: oxide_auth::endpoint::Template,
+    ) -> Result<<CraftedRequest as WebRequest>::Response, Self::Error> {
+        Ok(Default::default())
+    }
+    fn error(&mut self, _err: oxide_auth::endpoint::OAuthError) -> Self::Error {
+        unimplemented!()
+    }
+    fn web_error(&mut self, _err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
+        unimplemented!()
+    }
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
+        None
+    }
+    fn owner_solicitor(&mut self) -> Option<&mut (dyn OwnerSolicitor<CraftedRequest> + Send)> {
+        Some(&mut self.solicitor)
+    }
+    fn token_customizer(&mut self) -> Option<&mut (dyn TokenResponseCustomizer<CraftedRequest> + Send)> {
+        Some(&mut self.customizer)
+    }
+    fn error_customizer(&mut self) -> Option<&mut (dyn ErrorCustomizer<CraftedRequest> + Send)> {
+        Some(&mut self.support)
+    }
+}
+
+fn endpoint<'a>(
+    registrar: &'a ClientMap, authorizer: &'a mut AuthMap<TestGenerator>,
+    issuer: &'a mut TokenMap<TestGenerator>,
+) -> CustomizedEndpoint<'a> {
+    CustomizedEndpoint {
+        registrar,
+        authorizer,
+        issuer,
+        solicitor: Allow(EXAMPLE_OWNER_ID.to_owned()),
+        customizer: Tenant,
+        support: Support,
+    }
+}
+
+#[test]
+fn customizer_adds_members() {
+    let mut registrar = ClientMap::new();
+    registrar.register_client(Client::confidential(
+        EXAMPLE_CLIENT_ID,
+        RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+        EXAMPLE_SCOPE.parse().unwrap(),
+        EXAMPLE_PASSPHRASE.as_bytes(),
+    ));
+    let mut authorizer = AuthMap::new(TestGenerator("AuthToken".to_string()));
+    let mut issuer = TokenMap::new(TestGenerator("AccessToken".to_string()));
+
+    let authorization = CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+    let response = smol::block_on(
+        AuthorizationFlow::prepare(endpoint(&registrar, &mut authorizer, &mut issuer))
+            .unwrap()
+            .execute(authorization),
+    )
+    .expect("Should not error");
+    assert_eq!(response.status, Status::Redirect);
+
+    let basic_authorization = STANDARD.encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("code", "AuthToken"),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some("Basic ".to_string() + &basic_authorization),
+    };
+    let response = smol::block_on(
+        AccessTokenFlow::prepare(endpoint(&registrar, &mut authorizer, &mut issuer))
+            .unwrap()
+            .execute(request),
+    )
+    .expect("Should not error");
+    assert_eq!(response.status, Status::Ok);
+
+    let token: TokenResponse = match response.body {
+        Some(Body::Json(body)) => serde_json::from_str(&body).expect("Body not json encoded"),
+        other => panic!("Expected json formated credentials, got {:?}", other),
+    };
+    assert_eq!(token.token_type.as_deref(), Some("bearer"));
+    assert_eq!(token.additional.get("owner"), Some(&EXAMPLE_OWNER_ID.into()));
+    assert!(!token.additional.contains_key("token_type"));
+}
+
+#[test]
+fn error_customizer_explains_token_error() {
+    let mut registrar = ClientMap::new();
+    registrar.register_client(Client::confidential(
+        EXAMPLE_CLIENT_ID,
+        RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+        EXAMPLE_SCOPE.parse().unwrap(),
+        EXAMPLE_PASSPHRASE.as_bytes(),
+    ));
+    let mut authorizer = AuthMap::new(TestGenerator("AuthToken".to_string()));
+    let mut issuer = TokenMap::new(TestGenerator("AccessToken".to_string()));
+
+    let basic_authorization = STANDARD.encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("code", "NotACode"),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some("Basic ".to_string() + &basic_authorization),
+    };
+    let endpoint = endpoint(&registrar, &mut authorizer, &mut issuer);
+    let response = smol::block_on(AccessTokenFlow::prepare(endpoint).unwrap().execute(request))
+        .expect("Should not error");
+    assert_eq!(response.status, Status::BadRequest);
+
+    let body: HashMap<String, String> = match response.body {
+        Some(Body::Json(body)) => serde_json::from_str(&body).expect("Body not json encoded"),
+        other => panic!("Expected json formated error, got {:?}", other),
+    };
+    assert_eq!(body.get("error").map(String::as_str), Some("invalid_request"));
+    assert_eq!(
+        body.get("error_uri").map(String::as_str),
+        Some("https://support.example/errors")
+    );
+    assert_eq!(body.get("trace_id").map(String::as_str), Some("42"));
+}
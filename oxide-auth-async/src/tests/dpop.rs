@@ -0,0 +1,68 @@
+use oxide_auth::primitives::nonce::NonceWindow;
+use oxide_auth::{frontends::simple::endpoint::Error, endpoint::WebRequest};
+
+use crate::endpoint::{require_dpop_nonce, Endpoint};
+use crate::primitives::NonceStore;
+
+use super::{Body, CraftedRequest, Status};
+
+struct NonceEndpoint {
+    nonces: NonceWindow,
+}
+
+impl Endpoint<CraftedRequest> for NonceEndpoint {
+    type Error = Error<CraftedRequest>;
+
+    fn registrar(&self) -> Option<&(dyn crate::primitives::Registrar + Sync)> {
+        None
+    }
+    fn authorizer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Authorizer + Send)> {
+        None
+    }
+    fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
+        None
+    }
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
+        None
+    }
+    fn response(
+        &mut self, _: &mut CraftedRequest, _: oxide_auth::endpoint::Template,
+    ) -> Result<<CraftedRequest as WebRequest>::Response, Self::Error> {
+        Ok(Default::default())
+    }
+    fn error(&mut self, err: oxide_auth::endpoint::OAuthError) -> Self::Error {
+        Error::OAuth(err)
+    }
+    fn web_error(&mut self, err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
+        Error::Web(err)
+    }
+    fn owner_solicitor(
+        &mut self,
+    ) -> Option<&mut (dyn crate::endpoint::OwnerSolicitor<CraftedRequest> + Send)> {
+        None
+    }
+    fn nonce_store(&mut self) -> Option<&mut (dyn NonceStore + Send)> {
+        Some(&mut self.nonces)
+    }
+}
+
+#[test]
+fn dpop_nonce_required() {
+    let mut endpoint = NonceEndpoint {
+        nonces: NonceWindow::new(),
+    };
+    let mut request = CraftedRequest::default();
+
+    let response = smol::block_on(require_dpop_nonce(&mut endpoint, &mut request, Some("stale")))
+        .expect("Expected the request to be answered")
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::BadRequest);
+    match response.body {
+        Some(Body::Json(ref body)) => assert!(body.contains("use_dpop_nonce")),
+        ref other => panic!("Expected json body, got {:?}", other),
+    }
+
+    let nonce = smol::block_on(NonceStore::current(&mut endpoint.nonces)).unwrap();
+    let accepted = smol::block_on(require_dpop_nonce(&mut endpoint, &mut request, Some(&nonce)));
+    assert!(accepted.is_none());
+}
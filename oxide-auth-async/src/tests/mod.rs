@@ -58,6 +58,9 @@ enum Status {
 
     /// Http status code 401.
     Unauthorized,
+
+    /// Http status code 403.
+    Forbidden,
 }
 
 /// Models the necessary body contents.
@@ -134,6 +137,13 @@ impl WebResponse for CraftedResponse {
         Ok(())
     }
 
+    fn forbidden(&mut self, header_value: &str) -> Result<(), Self::Error> {
+        self.status = Status::Forbidden;
+        self.location = None;
+        self.www_authenticate = Some(header_value.to_owned());
+        Ok(())
+    }
+
     /// A pure text response with no special media type set.
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.body = Some(Body::Text(text.to_owned()));
@@ -226,4 +236,10 @@ mod client_credentials;
 mod type_properties;
 mod resource;
 mod refresh;
+mod outbox;
+mod customizer;
+mod revocation;
+mod claims;
+mod dpop;
+mod binding;
 // mod pkce;
@@ -0,0 +1,194 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use oxide_auth::primitives::authorizer::AuthMap;
+use oxide_auth::primitives::issuer::TokenMap;
+use oxide_auth::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use oxide_auth::{
+    frontends::simple::endpoint::Error,
+    endpoint::{GrantEvent, GrantOutcome, GrantRecord, WebRequest},
+};
+
+use crate::endpoint::{
+    access_token::AccessTokenFlow, authorization::AuthorizationFlow, Endpoint, Outbox, OwnerSolicitor,
+};
+
+use super::{Allow, CraftedRequest, Deny, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// Collects records, awaiting nothing but exercising the async trait.
+#[derive(Default)]
+struct Records(Vec<GrantRecord>);
+
+#[async_trait::async_trait]
+impl Outbox<CraftedRequest> for Records {
+    async fn record(&mut self, _: &mut CraftedRequest, record: GrantRecord) {
+        self.0.push(record);
+    }
+}
+
+struct OutboxEndpoint<'a, S> {
+    registrar: &'a ClientMap,
+    authorizer: &'a mut AuthMap<TestGenerator>,
+    issuer: &'a mut TokenMap<TestGenerator>,
+    solicitor: S,
+    records: &'a mut Records,
+}
+
+impl<'a, S> Endpoint<CraftedRequest> for OutboxEndpoint<'a, S>
+where
+    S: OwnerSolicitor<CraftedRequest> + Send,
+{
+    type Error = Error<CraftedRequest>;
+
+    fn registrar(&self) -> Option<&(dyn crate::primitives::Registrar + Sync)> {
+        Some(self.registrar)
+    }
+    fn authorizer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Authorizer + Send)> {
+        Some(self.authorizer)
+    }
+    fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
+        Some(self.issuer)
+    }
+    fn response(
+        &mut self, _: &mut CraftedRequest, _: oxide_auth::endpoint::Template,
+    ) -> Result<<CraftedRequest as WebRequest>::Response, Self::Error> {
+        Ok(Default::default())
+    }
+    fn error(&mut self, _err: oxide_auth::endpoint::OAuthError) -> Self::Error {
+        unimplemented!()
+    }
+    fn web_error(&mut self, _err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
+        unimplemented!()
+    }
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
+        None
+    }
+    fn owner_solicitor(&mut self) -> Option<&mut (dyn OwnerSolicitor<CraftedRequest> + Send)> {
+        Some(&mut self.solicitor)
+    }
+    fn outbox(&mut self) -> Option<&mut (dyn Outbox<CraftedRequest> + Send)> {
+        Some(self.records)
+    }
+}
+
+struct OutboxSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<TestGenerator>,
+    records: Records,
+}
+
+impl OutboxSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        OutboxSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_string())),
+            issuer: TokenMap::new(TestGenerator("AccessToken".to_string())),
+            records: Records::default(),
+        }
+    }
+
+    fn endpoint<S>(&mut self, solicitor: S) -> OutboxEndpoint<'_, S> {
+        OutboxEndpoint {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            solicitor,
+            records: &mut self.records,
+        }
+    }
+
+    fn authorize(&mut self, owner: Option<&str>) -> Status {
+        let request = CraftedRequest {
+            query: Some(
+                [
+                    ("response_type", "code"),
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+
+        let response = match owner {
+            Some(owner) => {
+                let endpoint = self.endpoint(Allow(owner.to_owned()));
+                smol::block_on(AuthorizationFlow::prepare(endpoint).unwrap().execute(request))
+            }
+            None => {
+                let endpoint = self.endpoint(Deny);
+                smol::block_on(AuthorizationFlow::prepare(endpoint).unwrap().execute(request))
+            }
+        };
+
+        response.expect("Should not error").status
+    }
+
+    fn access_token(&mut self, code: &str) -> Status {
+        let basic_authorization =
+            STANDARD.encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", "authorization_code"),
+                    ("code", code),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: Some("Basic ".to_string() + &basic_authorization),
+        };
+
+        let endpoint = self.endpoint(Deny);
+        smol::block_on(AccessTokenFlow::prepare(endpoint).unwrap().execute(request))
+            .expect("Should not error")
+            .status
+    }
+}
+
+#[test]
+fn records_issued_code_and_token() {
+    let mut setup = OutboxSetup::new();
+    assert_eq!(setup.authorize(Some(EXAMPLE_OWNER_ID)), Status::Redirect);
+    assert_eq!(setup.access_token("AuthToken"), Status::Ok);
+
+    let records = &setup.records.0;
+    assert_eq!(records.len(), 2);
+    for (record, event) in records.iter().zip([GrantEvent::Code, GrantEvent::Token]) {
+        assert_eq!(record.event, event);
+        assert_eq!(record.outcome, GrantOutcome::Issued);
+        assert_eq!(record.client_id.as_deref(), Some(EXAMPLE_CLIENT_ID));
+        assert_eq!(record.owner_id.as_deref(), Some(EXAMPLE_OWNER_ID));
+        assert_eq!(record.scope, Some(EXAMPLE_SCOPE.parse().unwrap()));
+    }
+}
+
+#[test]
+fn records_refusals() {
+    let mut setup = OutboxSetup::new();
+    assert_eq!(setup.authorize(None), Status::Redirect);
+    assert_eq!(setup.access_token("NotACode"), Status::BadRequest);
+
+    let records = &setup.records.0;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].event, GrantEvent::Code);
+    assert_eq!(records[1].event, GrantEvent::Token);
+    for record in records.iter() {
+        assert_eq!(record.outcome, GrantOutcome::Denied);
+        assert_eq!(record.client_id.as_deref(), Some(EXAMPLE_CLIENT_ID));
+        assert_eq!(record.owner_id, None);
+    }
+}
@@ -2,15 +2,16 @@ use base64::{engine::general_purpose::STANDARD, Engine};
 use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenMap, TokenType};
 use oxide_auth::primitives::generator::RandomGenerator;
 use oxide_auth::primitives::grant::{Grant, Extensions};
+use async_trait::async_trait;
 use oxide_auth::{
     code_grant::accesstoken::TokenResponse,
-    endpoint::{WebRequest},
+    endpoint::{GrantDecision, GrantEvent, WebRequest},
     primitives::registrar::{Client, ClientMap, RegisteredUrl},
     frontends::simple::endpoint::Error,
 };
 
 use crate::{
-    endpoint::{refresh::RefreshFlow, Endpoint, resource::ResourceFlow},
+    endpoint::{refresh::RefreshFlow, Endpoint, GrantPolicy, resource::ResourceFlow},
     primitives::{Issuer},
 };
 
@@ -24,11 +25,31 @@ use super::{defaults::*, resource::ResourceEndpoint};
 struct RefreshTokenEndpoint<'a> {
     registrar: &'a ClientMap,
     issuer: &'a mut TokenMap<RandomGenerator>,
+    policy: Option<Suspended>,
+}
+
+/// Denies to refresh grants of the example owner.
+struct Suspended;
+
+#[async_trait]
+impl GrantPolicy for Suspended {
+    async fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+        assert_eq!(event, GrantEvent::Refresh);
+        if grant.owner_id == EXAMPLE_OWNER_ID {
+            GrantDecision::Deny
+        } else {
+            GrantDecision::Allow
+        }
+    }
 }
 
 impl<'a> RefreshTokenEndpoint<'a> {
     fn new(registrar: &'a ClientMap, issuer: &'a mut TokenMap<RandomGenerator>) -> Self {
-        Self { registrar, issuer }
+        Self {
+            registrar,
+            issuer,
+            policy: None,
+        }
     }
 }
 
@@ -55,7 +76,7 @@ impl<'a> Endpoint<CraftedRequest> for RefreshTokenEndpoint<'a> {
     fn web_error(&mut self, _err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
         unimplemented!()
     }
-    fn scopes(&mut self) -> Option<&mut dyn oxide_auth::endpoint::Scopes<CraftedRequest>> {
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
         None
     }
     fn owner_solicitor(
@@ -63,6 +84,11 @@ impl<'a> Endpoint<CraftedRequest> for RefreshTokenEndpoint<'a> {
     ) -> Option<&mut (dyn crate::endpoint::OwnerSolicitor<CraftedRequest> + Send)> {
         None
     }
+    fn grant_policy(&mut self) -> Option<&mut (dyn GrantPolicy + Send)> {
+        self.policy
+            .as_mut()
+            .map(|policy| policy as &mut (dyn GrantPolicy + Send))
+    }
 }
 
 struct RefreshTokenSetup {
@@ -248,7 +274,7 @@ impl RefreshTokenSetup {
             urlbody: None,
             auth: Some(format!("Bearer {}", token)),
         };
-        let mut scopes = [EXAMPLE_SCOPE.parse().unwrap()];
+        let mut scopes = vec![EXAMPLE_SCOPE.parse().unwrap()];
         let mut resource_flow =
             ResourceFlow::prepare(ResourceEndpoint::new(&mut self.issuer, &mut scopes)).unwrap();
         smol::block_on(resource_flow.execute(request)).expect("Expected access allowed");
@@ -438,3 +464,32 @@ fn private_invalid_token() {
 
     setup.assert_invalid_grant(valid_private);
 }
+
+#[test]
+fn grant_policy_denies() {
+    let mut setup = RefreshTokenSetup::private_client();
+
+    let valid_private = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &setup.refresh_token),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some(setup.basic_authorization.clone()),
+    };
+
+    let mut endpoint = RefreshTokenEndpoint::new(&setup.registrar, &mut setup.issuer);
+    endpoint.policy = Some(Suspended);
+    let mut refresh_flow = RefreshFlow::prepare(endpoint).unwrap();
+    let response = smol::block_on(refresh_flow.execute(valid_private.clone()))
+        .expect("Expected non-failed reponse");
+    let body = setup.assert_json_body(&response);
+    assert_eq!(body.get("error").map(String::as_str), Some("invalid_grant"));
+
+    // The refresh token was not used up by the denied request.
+    setup.assert_success(valid_private);
+}
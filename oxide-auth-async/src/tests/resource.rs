@@ -5,13 +5,30 @@ use oxide_auth::{frontends::simple::endpoint::Error, primitives::scope::Scope, e
 
 use chrono::{Utc, Duration};
 
-use super::CraftedRequest;
+use super::{CraftedRequest, ToSingleValueQuery};
 use super::defaults::*;
-use crate::endpoint::{resource::ResourceFlow, Endpoint};
+use crate::endpoint::{resource::ResourceFlow, Endpoint, Scopes};
+
+/// Requires the scope named in the query, as a lookup in a policy service would.
+struct QueryScopes(Vec<Scope>);
+
+#[async_trait::async_trait]
+impl Scopes<CraftedRequest> for QueryScopes {
+    async fn scopes(&mut self, request: &mut CraftedRequest) -> &[Scope] {
+        let query = request.query.as_ref();
+        self.0 = query
+            .and_then(|query| query.get("scope"))
+            .and_then(|scope| scope.first())
+            .and_then(|scope| scope.parse().ok())
+            .into_iter()
+            .collect();
+        &self.0
+    }
+}
 
 pub struct ResourceEndpoint<'a> {
     issuer: &'a mut TokenMap<RandomGenerator>,
-    scopes: &'a mut [Scope],
+    scopes: &'a mut (dyn Scopes<CraftedRequest> + Send + Sync),
 }
 
 impl<'a> Endpoint<CraftedRequest> for ResourceEndpoint<'a> {
@@ -37,8 +54,8 @@ impl<'a> Endpoint<CraftedRequest> for ResourceEndpoint<'a> {
     fn web_error(&mut self, _err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
         unimplemented!()
     }
-    fn scopes(&mut self) -> Option<&mut dyn oxide_auth::endpoint::Scopes<CraftedRequest>> {
-        Some(&mut self.scopes)
+    fn scopes(&mut self) -> Option<&mut (dyn Scopes<CraftedRequest> + Send)> {
+        Some(self.scopes)
     }
     fn owner_solicitor(
         &mut self,
@@ -48,7 +65,10 @@ impl<'a> Endpoint<CraftedRequest> for ResourceEndpoint<'a> {
 }
 
 impl<'a> ResourceEndpoint<'a> {
-    pub fn new(issuer: &'a mut TokenMap<RandomGenerator>, scopes: &'a mut [Scope]) -> Self {
+    pub fn new(
+        issuer: &'a mut TokenMap<RandomGenerator>,
+        scopes: &'a mut (dyn Scopes<CraftedRequest> + Send + Sync),
+    ) -> Self {
         Self { issuer, scopes }
     }
 }
@@ -58,7 +78,7 @@ struct ResourceSetup {
     authtoken: String,
     wrong_scope_token: String,
     small_scope_token: String,
-    resource_scope: [Scope; 1],
+    resource_scope: Vec<Scope>,
 }
 
 impl ResourceSetup {
@@ -103,7 +123,7 @@ impl ResourceSetup {
             authtoken: authtoken.token,
             wrong_scope_token: wrong_scope_token.token,
             small_scope_token: small_scope_token.token,
-            resource_scope: ["needed legit".parse().unwrap()],
+            resource_scope: vec!["needed legit".parse().unwrap()],
         }
     }
 
@@ -210,3 +230,21 @@ fn resource_wrong_scope() {
 
     setup.test_access_error(wrong_scope);
 }
+
+#[test]
+fn resource_async_scopes() {
+    let mut setup = ResourceSetup::new();
+    let mut scopes = QueryScopes(Vec::new());
+    let request = |scope: &str| CraftedRequest {
+        query: Some([("scope", scope)].iter().to_single_value_query()),
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &setup.small_scope_token),
+    };
+    let allowed = request("legit");
+    let denied = request("needed");
+
+    let endpoint = ResourceEndpoint::new(&mut setup.issuer, &mut scopes);
+    let mut flow = ResourceFlow::prepare(endpoint).unwrap();
+    assert!(smol::block_on(flow.execute(allowed)).is_ok());
+    assert!(smol::block_on(flow.execute(denied)).is_err());
+}
@@ -0,0 +1,181 @@
+use chrono::{Duration, Utc};
+use oxide_auth::primitives::authorizer::AuthMap;
+use oxide_auth::primitives::generator::RandomGenerator;
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::primitives::issuer::TokenMap;
+use oxide_auth::{frontends::simple::endpoint::Error, endpoint::WebRequest};
+
+use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+use oxide_auth::primitives::consent::{Consent, ConsentMap, ConsentStore};
+
+use crate::endpoint::revocation::{ClientRevocationFlow, OwnerGrantsFlow};
+use crate::endpoint::{Endpoint, OwnerSolicitor};
+use crate::primitives::{Authorizer, Issuer};
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+struct RevocationEndpoint<'a> {
+    authorizer: &'a mut AuthMap<RandomGenerator>,
+    issuer: &'a mut TokenMap<RandomGenerator>,
+    consents: Option<&'a mut ConsentMap>,
+    account: Account,
+}
+
+/// Identifies the owner of the account page.
+struct Account(&'static str);
+
+#[async_trait::async_trait]
+impl OwnerSolicitor<CraftedRequest> for Account {
+    async fn check_consent(
+        &mut self, _: &mut CraftedRequest, _: Solicitation<'_>,
+    ) -> OwnerConsent<CraftedResponse> {
+        OwnerConsent::Denied
+    }
+
+    async fn owner_id(&mut self, _: &mut CraftedRequest) -> Option<String> {
+        Some(self.0.to_string())
+    }
+}
+
+impl<'a> Endpoint<CraftedRequest> for RevocationEndpoint<'a> {
+    type Error = Error<CraftedRequest>;
+
+    fn registrar(&self) -> Option<&(dyn crate::primitives::Registrar + Sync)> {
+        None
+    }
+    fn authorizer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Authorizer + Send)> {
+        Some(self.authorizer)
+    }
+    fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
+        Some(self.issuer)
+    }
+    fn scopes(&mut self) -> Option<&mut (dyn crate::endpoint::Scopes<CraftedRequest> + Send)> {
+        None
+    }
+    fn response(
+        &mut self, _: &mut CraftedRequest, _: oxide_auth::endpoint::Template,
+    ) -> Result<<CraftedRequest as WebRequest>::Response, Self::Error> {
+        Ok(Default::default())
+    }
+    fn error(&mut self, err: oxide_auth::endpoint::OAuthError) -> Self::Error {
+        Error::OAuth(err)
+    }
+    fn web_error(&mut self, err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
+        Error::Web(err)
+    }
+    fn owner_solicitor(&mut self) -> Option<&mut (dyn OwnerSolicitor<CraftedRequest> + Send)> {
+        Some(&mut self.account)
+    }
+    fn consent_store(&mut self) -> Option<&mut (dyn crate::primitives::ConsentStore + Send)> {
+        match &mut self.consents {
+            Some(consents) => Some(*consents),
+            None => None,
+        }
+    }
+}
+
+fn grant(client_id: &str) -> Grant {
+    Grant {
+        client_id: client_id.to_string(),
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    }
+}
+
+#[test]
+fn revoke_client() {
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+    let code = smol::block_on(Authorizer::authorize(&mut authorizer, grant(EXAMPLE_CLIENT_ID))).unwrap();
+    let token = smol::block_on(Issuer::issue(&mut issuer, grant(EXAMPLE_CLIENT_ID))).unwrap();
+    let other = smol::block_on(Issuer::issue(&mut issuer, grant("OtherClient"))).unwrap();
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some([("client_id", EXAMPLE_CLIENT_ID)].iter().to_single_value_query()),
+        auth: None,
+    };
+
+    let endpoint = RevocationEndpoint {
+        authorizer: &mut authorizer,
+        issuer: &mut issuer,
+        consents: None,
+        account: Account(EXAMPLE_OWNER_ID),
+    };
+    let mut flow = ClientRevocationFlow::prepare(endpoint).unwrap_or_else(|_| panic!("Should prepare"));
+    let response = smol::block_on(flow.execute(request)).expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+
+    let body: serde_json::Value = match response.body {
+        Some(Body::Json(ref body)) => serde_json::from_str(body).unwrap(),
+        ref other => panic!("Expected json body, got {:?}", other),
+    };
+    assert_eq!(body["revoked_codes"], 1);
+    assert_eq!(body["revoked_tokens"], 1);
+
+    assert_eq!(
+        smol::block_on(Authorizer::extract(&mut authorizer, &code)),
+        Ok(None)
+    );
+    assert_eq!(
+        smol::block_on(Issuer::recover_token(&mut issuer, &token.token)),
+        Ok(None)
+    );
+    assert!(smol::block_on(Issuer::recover_token(&mut issuer, &other.token))
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn owner_grants_revoke() {
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    let mut consents = ConsentMap::new();
+    consents.remember(Consent {
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+    });
+    let token = smol::block_on(Issuer::issue(&mut issuer, grant(EXAMPLE_CLIENT_ID))).unwrap();
+
+    let endpoint = RevocationEndpoint {
+        authorizer: &mut authorizer,
+        issuer: &mut issuer,
+        consents: Some(&mut consents),
+        account: Account(EXAMPLE_OWNER_ID),
+    };
+    let mut flow = OwnerGrantsFlow::prepare(endpoint).unwrap_or_else(|_| panic!("Should prepare"));
+
+    let listed =
+        smol::block_on(flow.list(CraftedRequest::default())).expect("Expected non-error response");
+    let body: serde_json::Value = match listed.body {
+        Some(Body::Json(ref body)) => serde_json::from_str(body).unwrap(),
+        ref other => panic!("Expected json body, got {:?}", other),
+    };
+    assert_eq!(body["clients"][0]["client_id"], EXAMPLE_CLIENT_ID);
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some([("client_id", EXAMPLE_CLIENT_ID)].iter().to_single_value_query()),
+        auth: None,
+    };
+    let revoked = smol::block_on(flow.revoke(request)).expect("Expected non-error response");
+    assert_eq!(revoked.status, Status::Ok);
+    let body: serde_json::Value = match revoked.body {
+        Some(Body::Json(ref body)) => serde_json::from_str(body).unwrap(),
+        ref other => panic!("Expected json body, got {:?}", other),
+    };
+    assert_eq!(body["consent"], true);
+    assert_eq!(body["revoked_tokens"], 1);
+
+    assert!(consents.consents(EXAMPLE_OWNER_ID).is_empty());
+    assert_eq!(
+        smol::block_on(Issuer::recover_token(&mut issuer, &token.token)),
+        Ok(None)
+    );
+}
@@ -0,0 +1,183 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use oxide_auth::frontends::dev::Cors;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// A `tower::Layer` allowing browsers to call the wrapped routes from the origins of a [`Cors`]
+/// policy.
+///
+/// Meant for the token endpoint and other endpoints called by scripts of public clients, not for
+/// the authorization endpoint which is navigated to. Preflight requests are answered directly with
+/// `204 No Content`, all other responses receive the headers allowing their origin.
+///
+/// ```no_run
+/// use axum::{routing::post, Router};
+/// use oxide_auth::frontends::dev::Cors;
+/// use oxide_auth::primitives::registrar::ClientMap;
+/// use oxide_auth_axum::AllowOrigins;
+///
+/// # let clients = ClientMap::new();
+/// let cors = Cors::from_clients(clients.clients());
+/// let app: Router = Router::new()
+///     .route("/token", post(|| async { "token" }))
+///     .layer(AllowOrigins::new(cors));
+/// ```
+#[derive(Clone, Debug)]
+pub struct AllowOrigins {
+    cors: Arc<Cors>,
+}
+
+/// The service created by an [`AllowOrigins`] layer.
+#[derive(Clone, Debug)]
+pub struct AllowOriginsService<S> {
+    inner: S,
+    cors: Arc<Cors>,
+}
+
+impl AllowOrigins {
+    /// Allow the origins of the policy.
+    pub fn new(cors: Cors) -> Self {
+        AllowOrigins { cors: Arc::new(cors) }
+    }
+
+    /// The policy deciding which origins are allowed.
+    pub fn cors(&self) -> &Cors {
+        &self.cors
+    }
+}
+
+impl<S> Layer<S> for AllowOrigins {
+    type Service = AllowOriginsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AllowOriginsService {
+            inner,
+            cors: self.cors.clone(),
+        }
+    }
+}
+
+fn origin(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+fn is_preflight(request: &Request) -> bool {
+    request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+fn decorate(headers: &mut HeaderMap, cors_headers: Vec<(&'static str, String)>) {
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    for (name, value) in cors_headers {
+        if let Ok(value) = HeaderValue::try_from(value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+impl<S> Service<Request> for AllowOriginsService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let origin = match origin(&request) {
+            Some(origin) => origin,
+            None => return Box::pin(self.inner.call(request)),
+        };
+
+        if is_preflight(&request) {
+            let mut response = StatusCode::NO_CONTENT.into_response();
+            decorate(response.headers_mut(), self.cors.preflight_headers(&origin));
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let cors_headers = self.cors.response_headers(&origin);
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = response.await?;
+            decorate(response.headers_mut(), cors_headers);
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::post, Router};
+
+    fn request(method: Method, origin: &str) -> Request {
+        Request::builder()
+            .method(method)
+            .uri("/token")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn allows_origins() {
+        let mut cors = Cors::new();
+        cors.allow_origin(&"https://app.example/callback".parse().unwrap());
+        let mut app = Router::new()
+            .route("/token", post(|| async { "token" }))
+            .layer(AllowOrigins::new(cors));
+
+        let response = app
+            .call(request(Method::OPTIONS, "https://app.example"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example"
+        );
+        assert!(response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_HEADERS));
+
+        let response = app
+            .call(request(Method::POST, "https://app.example"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example"
+        );
+
+        let response = app
+            .call(request(Method::OPTIONS, "https://evil.example"))
+            .await
+            .unwrap();
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(response.headers()[header::VARY], "origin");
+    }
+}
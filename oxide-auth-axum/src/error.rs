@@ -29,6 +29,9 @@ pub enum WebError {
     /// The Authorization header was invalid
     Authorization,
 
+    /// The request exceeded the configured size limits
+    TooLarge,
+
     /// General internal server error
     InternalError(Option<String>),
 }
@@ -43,6 +46,7 @@ impl std::fmt::Display for WebError {
             WebError::Query => write!(f, "No query present"),
             WebError::Body => write!(f, "No body present"),
             WebError::Authorization => write!(f, "Request has invalid Authorization headers"),
+            WebError::TooLarge => write!(f, "Request exceeds the size limits"),
             WebError::InternalError(None) => write!(f, "An internal server error occured"),
             WebError::InternalError(Some(ref e)) => write!(f, "An internal server error occured: {}", e),
         }
@@ -61,7 +65,17 @@ impl std::error::Error for WebError {
 
 impl IntoResponse for WebError {
     fn into_response(self) -> Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+        let status = match self {
+            WebError::Endpoint(OAuthError::DenySilently | OAuthError::BadRequest)
+            | WebError::Encoding
+            | WebError::Form
+            | WebError::Query
+            | WebError::Body
+            | WebError::Authorization => StatusCode::BAD_REQUEST,
+            WebError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.to_string()).into_response()
     }
 }
 
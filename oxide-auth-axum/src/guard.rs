@@ -0,0 +1,290 @@
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use axum::{
+    extract::{FromRef, FromRequestParts, Request},
+    http::{request::Parts, Extensions, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use oxide_auth::frontends::simple::endpoint::resource_flow;
+use oxide_auth::primitives::{grant::Grant, issuer::Issuer, scope::Scope};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{OAuthRequest, OAuthResource, WebError};
+
+/// Validates bearer tokens against a shared issuer and a set of scopes.
+///
+/// Accepts tokens whose grant allows any of the configured scopes. Obtain the `Grant` of a request
+/// either with the [`Protected`] extractor, keeping the guard in the router state, or for whole
+/// routers with the layer returned by [`ResourceGuard::layer`].
+#[derive(Clone)]
+pub struct ResourceGuard {
+    issuer: Arc<Mutex<dyn Issuer + Send>>,
+    scopes: Arc<[Scope]>,
+    realm: Option<Arc<str>>,
+}
+
+/// A `tower::Layer` requiring a valid bearer token on every request of the wrapped routes.
+///
+/// The `Grant` of an accepted token is inserted into the request extensions where handlers can
+/// pick it up with `Extension<Grant>` or [`Protected`]. Other requests are answered directly with
+/// `401 Unauthorized`, or `403 Forbidden` if the token lacks the necessary scope, and a matching
+/// `WWW-Authenticate` header.
+///
+/// ```no_run
+/// # use std::sync::{Arc, Mutex};
+/// use axum::{routing::get, Extension, Router};
+/// use oxide_auth::primitives::{generator::RandomGenerator, grant::Grant, issuer::TokenMap};
+/// use oxide_auth_axum::OAuthGuardLayer;
+///
+/// let issuer = Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16))));
+/// let app: Router = Router::new()
+///     .route("/me", get(|Extension(grant): Extension<Grant>| async move { grant.owner_id }))
+///     .layer(OAuthGuardLayer::new(issuer, vec!["profile".parse().unwrap()]));
+/// ```
+#[derive(Clone)]
+pub struct OAuthGuardLayer {
+    guard: ResourceGuard,
+}
+
+/// The service created by an [`OAuthGuardLayer`].
+#[derive(Clone)]
+pub struct OAuthGuard<S> {
+    inner: S,
+    guard: ResourceGuard,
+}
+
+impl ResourceGuard {
+    /// Accept tokens of the issuer which grant at least one of the scopes.
+    ///
+    /// The issuer is usually shared with the token endpoint of the authorization server.
+    pub fn new<I: Issuer + Send + 'static>(issuer: Arc<Mutex<I>>, scopes: Vec<Scope>) -> Self {
+        ResourceGuard {
+            issuer,
+            scopes: scopes.into(),
+            realm: None,
+        }
+    }
+
+    /// Name the protection space in the `realm` of the `WWW-Authenticate` challenges.
+    pub fn with_realm(self, realm: &str) -> Self {
+        ResourceGuard {
+            realm: Some(realm.into()),
+            ..self
+        }
+    }
+
+    /// The scopes of which a token must grant at least one.
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// A layer applying this guard to all routes of a router.
+    pub fn layer(&self) -> OAuthGuardLayer {
+        OAuthGuardLayer { guard: self.clone() }
+    }
+
+    /// Attach the grant to the request extensions, or reject it with the returned response.
+    pub(crate) fn protect(&self, headers: &HeaderMap, extensions: &mut Extensions) -> Option<Response> {
+        let resource = match OAuthResource::read(headers, extensions) {
+            Ok(resource) => resource,
+            Err(err) => return Some(err.into_response()),
+        };
+        let mut issuer = match self.issuer.lock() {
+            Ok(issuer) => issuer,
+            Err(_) => {
+                let err = WebError::InternalError(Some("Issuer lock poisoned".into()));
+                return Some(err.into_response());
+            }
+        };
+
+        let mut flow = resource_flow(&mut *issuer, &self.scopes);
+        if let Some(realm) = &self.realm {
+            flow.realm(realm);
+        }
+
+        match flow.execute(OAuthRequest::from(resource)) {
+            Ok(grant) => {
+                extensions.insert(grant);
+                None
+            }
+            Err(Ok(response)) => Some(response.into_response()),
+            Err(Err(err)) => Some(WebError::from(err).into_response()),
+        }
+    }
+}
+
+impl OAuthGuardLayer {
+    /// Protect routes with tokens of the issuer, which grant at least one of the scopes.
+    pub fn new<I: Issuer + Send + 'static>(issuer: Arc<Mutex<I>>, scopes: Vec<Scope>) -> Self {
+        ResourceGuard::new(issuer, scopes).layer()
+    }
+}
+
+impl<S> Layer<S> for OAuthGuardLayer {
+    type Service = OAuthGuard<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OAuthGuard {
+            inner,
+            guard: self.guard.clone(),
+        }
+    }
+}
+
+/// Extracts the `Grant` of a request with a valid bearer token.
+///
+/// The token is validated by the [`ResourceGuard`] of the router state, which must be obtainable
+/// through `FromRef`. Requests that already passed an [`OAuthGuardLayer`] are not validated again,
+/// the grant found by the layer is used instead. Rejections are the same as those of the layer.
+///
+/// ```no_run
+/// # use std::sync::{Arc, Mutex};
+/// use axum::{routing::get, Router};
+/// use oxide_auth::primitives::{generator::RandomGenerator, issuer::TokenMap};
+/// use oxide_auth_axum::{Protected, ResourceGuard};
+///
+/// async fn me(Protected(grant): Protected) -> String {
+///     grant.owner_id
+/// }
+///
+/// let issuer = Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16))));
+/// let guard = ResourceGuard::new(issuer, vec!["profile".parse().unwrap()]);
+/// let app: Router = Router::new().route("/me", get(me)).with_state(guard);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Protected(pub Grant);
+
+impl Deref for Protected {
+    type Target = Grant;
+
+    fn deref(&self) -> &Grant {
+        &self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for Protected
+where
+    ResourceGuard: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if parts.extensions.get::<Grant>().is_none() {
+            let guard = ResourceGuard::from_ref(state);
+            if let Some(response) = guard.protect(&parts.headers, &mut parts.extensions) {
+                return Err(response);
+            }
+        }
+
+        match parts.extensions.get::<Grant>() {
+            Some(grant) => Ok(Protected(grant.clone())),
+            None => Err(WebError::InternalError(None).into_response()),
+        }
+    }
+}
+
+impl<S> Service<Request> for OAuthGuard<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+        match self.guard.protect(&parts.headers, &mut parts.extensions) {
+            None => Box::pin(self.inner.call(Request::from_parts(parts, body))),
+            Some(response) => Box::pin(async move { Ok(response) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, StatusCode};
+    use axum::{body::Body, routing::get, Extension, Router};
+    use chrono::{Duration, Utc};
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::issuer::TokenMap;
+
+    fn request(token: Option<&str>) -> Request {
+        let mut request = Request::builder().uri("/");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    /// A guard requiring the `profile` scope, with a token granting it and one that does not.
+    fn guard() -> (ResourceGuard, String, String) {
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        let mut grant = Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "profile".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        };
+        let profile = issuer.issue(grant.clone()).unwrap().token;
+        grant.scope = "email".parse().unwrap();
+        let email = issuer.issue(grant).unwrap().token;
+
+        let guard = ResourceGuard::new(Arc::new(Mutex::new(issuer)), vec!["profile".parse().unwrap()]);
+        (guard, profile, email)
+    }
+
+    #[tokio::test]
+    async fn guards_routes() {
+        let (guard, profile, email) = guard();
+        let guard = guard.with_realm("example");
+        let mut app = Router::new()
+            .route(
+                "/",
+                get(|Extension(grant): Extension<Grant>| async move { grant.owner_id }),
+            )
+            .layer(guard.layer());
+
+        let response = app.call(request(Some(&profile))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.call(request(Some(&email))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app.call(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let challenge = response.headers().get(header::WWW_AUTHENTICATE).unwrap();
+        assert_eq!(challenge, "Bearer realm=\"example\"");
+    }
+
+    #[tokio::test]
+    async fn extracts_grant() {
+        let (guard, profile, email) = guard();
+        let mut app = Router::new()
+            .route("/", get(|grant: Protected| async move { grant.owner_id.clone() }))
+            .with_state(guard);
+
+        let response = app.call(request(Some(&profile))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.call(request(Some(&email))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app.call(request(Some("unknown"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
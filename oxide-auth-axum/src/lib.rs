@@ -9,3 +9,18 @@ pub use request::{OAuthResource, OAuthRequest};
 
 mod response;
 pub use response::OAuthResponse;
+
+mod guard;
+pub use guard::{OAuthGuard, OAuthGuardLayer, Protected, ResourceGuard};
+
+mod scope;
+pub use scope::{RequireScope, RequireScopeService};
+
+mod cors;
+pub use cors::{AllowOrigins, AllowOriginsService};
+
+mod render;
+pub use render::{RenderErrors, RenderErrorsService};
+
+mod router;
+pub use router::{OAuthRouter, OAuthRouterBuilder, SigningKey};
@@ -0,0 +1,181 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use oxide_auth::frontends::dev::{ErrorInfo, RenderError};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Error bodies are small, the body of a larger response is dropped instead of buffered.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// A `tower::Layer` rendering the bodies of error responses with a [`RenderError`].
+///
+/// Applies to all responses of the wrapped routes with a client or server error status, those
+/// produced by the flows as well as rejections such as a [`WebError`]. The status and all headers
+/// other than the content type are kept, in particular the `WWW-Authenticate` header.
+///
+/// ```no_run
+/// use axum::{routing::get, Router};
+/// use oxide_auth::frontends::dev::{ErrorInfo, RenderedError};
+/// use oxide_auth_axum::RenderErrors;
+///
+/// let branded = RenderErrors::new(|error: &ErrorInfo| {
+///     let code = error.error.as_deref().unwrap_or("server_error");
+///     Some(RenderedError::Html(format!("<h1>Sign in failed</h1><p>{}</p>", code)))
+/// });
+/// let app: Router = Router::new()
+///     .route("/authorize", get(|| async { "authorize" }))
+///     .layer(branded);
+/// ```
+///
+/// [`WebError`]: crate::WebError
+#[derive(Clone)]
+pub struct RenderErrors {
+    renderer: Arc<dyn RenderError>,
+}
+
+/// The service created by a [`RenderErrors`] layer.
+#[derive(Clone)]
+pub struct RenderErrorsService<S> {
+    inner: S,
+    renderer: Arc<dyn RenderError>,
+}
+
+impl RenderErrors {
+    /// Render error bodies with the renderer.
+    pub fn new<R: RenderError + 'static>(renderer: R) -> Self {
+        RenderErrors {
+            renderer: Arc::new(renderer),
+        }
+    }
+}
+
+impl<S> Layer<S> for RenderErrors {
+    type Service = RenderErrorsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RenderErrorsService {
+            inner,
+            renderer: self.renderer.clone(),
+        }
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"))
+}
+
+async fn render(renderer: &dyn RenderError, response: Response) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_ERROR_BODY).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+
+    let status = parts.status.as_u16();
+    let error = if is_json(&parts.headers) {
+        ErrorInfo::from_json(status, &bytes)
+    } else {
+        ErrorInfo::new(status)
+    };
+
+    match renderer.render(&error) {
+        Some(rendered) => {
+            let (content_type, body) = rendered.into_body(&error);
+            parts
+                .headers
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(body))
+        }
+        None => Response::from_parts(parts, Body::from(bytes)),
+    }
+}
+
+impl<S> Service<Request> for RenderErrorsService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let renderer = self.renderer.clone();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            let status = response.status();
+            if status.is_client_error() || status.is_server_error() {
+                Ok(render(&*renderer, response).await)
+            } else {
+                Ok(response)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OAuthResponse, WebError};
+    use axum::{http::StatusCode, routing::get, Router};
+    use oxide_auth::frontends::dev::{OAuthError, RenderedError, WebResponse};
+    use serde_json::{Map, Value};
+
+    async fn invalid_grant() -> OAuthResponse {
+        let mut response = OAuthResponse::default();
+        response.client_error().unwrap();
+        response.body_json(r#"{"error":"invalid_grant"}"#).unwrap();
+        response
+    }
+
+    async fn denied() -> Result<&'static str, WebError> {
+        Err(OAuthError::DenySilently.into())
+    }
+
+    #[tokio::test]
+    async fn renders_error_bodies() {
+        let mut app = Router::new()
+            .route("/token", get(invalid_grant))
+            .route("/authorize", get(denied))
+            .route("/", get(|| async { "ok" }))
+            .layer(RenderErrors::new(|error: &ErrorInfo| {
+                let mut object = Map::new();
+                object.insert("status".into(), error.status.into());
+                Some(RenderedError::Json(object))
+            }));
+        let request = |uri| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = app.call(request("/token")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), MAX_ERROR_BODY).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "invalid_grant");
+        assert_eq!(body["status"], 400);
+
+        let response = app.call(request("/authorize")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), MAX_ERROR_BODY).await.unwrap();
+        assert_eq!(&body[..], br#"{"status":400}"#);
+
+        let response = app.call(request("/")).await.unwrap();
+        let body = to_bytes(response.into_body(), MAX_ERROR_BODY).await.unwrap();
+        assert_eq!(&body[..], b"ok");
+    }
+}
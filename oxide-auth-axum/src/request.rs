@@ -1,7 +1,11 @@
-use oxide_auth::frontends::dev::{NormalizedParameter, QueryParameter, WebRequest};
+use oxide_auth::frontends::dev::{
+    Limits, NormalizedParameter, PeerCertificate, QueryParameter, RequestContext, TrustedProxy, Url,
+    WebRequest,
+};
 use axum::{
+    body::{to_bytes, Body},
     extract::{Query, Form, FromRequest, FromRequestParts, Request},
-    http::{header, request::Parts},
+    http::{header, request::Parts, Extensions, HeaderMap},
 };
 use crate::{OAuthResponse, WebError};
 use std::borrow::Cow;
@@ -10,9 +14,27 @@ use std::borrow::Cow;
 /// Type implementing `WebRequest` as well as `FromRequest` for use in route handlers
 ///
 /// This type consumes the body of the Request upon extraction, so be careful not to use it in
-/// places you also expect an application payload
+/// places you also expect an application payload. Bodies are read as a urlencoded form, or as a
+/// JSON object if the `Content-Type` is `application/json`.
+///
+/// Requests exceeding the `Limits` found in the request extensions, or the default limits, are
+/// rejected with `WebError::TooLarge` before their parameters are parsed. Configure them for a
+/// router with `.layer(Extension(limits))`.
+///
+/// The certificate of a mutual TLS connection is taken from the request extensions, where the
+/// server inserts it after the handshake. A [`TrustedProxy`] extension that trusts client
+/// certificates reads it from the `X-Forwarded-Client-Cert` header instead. Similarly, the
+/// [`url`](OAuthRequest::url) of the request honors the forwarding headers of a trusted proxy.
+///
+/// The [`context`](OAuthRequest::context) of the request, passed on to solicitors and scope
+/// policies, starts from a [`RequestContext`] found in the request extensions. A middleware can
+/// insert one with the remote address from `ConnectInfo` or the result of a geo IP lookup. The
+/// `User-Agent` header fills in the user agent if the extension does not.
 pub struct OAuthRequest {
     auth: Option<String>,
+    certificate: Option<PeerCertificate>,
+    context: RequestContext,
+    url: Option<Url>,
     query: Option<NormalizedParameter>,
     body: Option<NormalizedParameter>,
 }
@@ -23,6 +45,7 @@ pub struct OAuthRequest {
 /// request upon extraction
 pub struct OAuthResource {
     auth: Option<String>,
+    certificate: Option<PeerCertificate>,
 }
 
 impl OAuthRequest {
@@ -31,6 +54,29 @@ impl OAuthRequest {
         self.auth.as_deref()
     }
 
+    /// The certificate the client presented over mutual TLS
+    pub fn client_certificate(&self) -> Option<&PeerCertificate> {
+        self.certificate.as_ref()
+    }
+
+    /// The context of the request, such as the remote address
+    pub fn context(&self) -> &RequestContext {
+        &self.context
+    }
+
+    /// Fetch the context mutably, to add signals before passing the request to a flow
+    pub fn context_mut(&mut self) -> &mut RequestContext {
+        &mut self.context
+    }
+
+    /// The url under which the client reached the server
+    ///
+    /// Compare redirect targets and generate metadata against it rather than against the address
+    /// the server is bound to.
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
     /// Fetch the query for this request
     pub fn query(&self) -> Option<&NormalizedParameter> {
         self.query.as_ref()
@@ -51,6 +97,7 @@ impl From<OAuthResource> for OAuthRequest {
     fn from(r: OAuthResource) -> OAuthRequest {
         OAuthRequest {
             auth: r.auth,
+            certificate: r.certificate,
             ..Default::default()
         }
     }
@@ -77,6 +124,10 @@ impl WebRequest for OAuthRequest {
     fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
         Ok(self.auth.as_deref().map(Cow::Borrowed))
     }
+
+    fn context(&mut self) -> Option<Cow<'_, RequestContext>> {
+        Some(Cow::Borrowed(&self.context))
+    }
 }
 
 impl<S> FromRequest<S> for OAuthRequest
@@ -95,22 +146,124 @@ where
             optional.and_then(|hv| hv.to_str().ok().map(str::to_owned))
         };
 
+        let certificate = peer_certificate(req.headers(), req.extensions());
+        let context = request_context(req.headers(), req.extensions());
+        let url = request_url(&req);
+        let limits = req.extensions().get::<Limits>().copied().unwrap_or_default();
+        if !limits.allow_query(req.uri().query().unwrap_or("")) {
+            return Err(WebError::TooLarge);
+        }
+        if content_length(req.headers()).is_some_and(|length| !limits.allow_length(length)) {
+            return Err(WebError::TooLarge);
+        }
+
         let (mut parts, body) = req.into_parts();
         let query = Query::from_request_parts(&mut parts, state)
             .await
             .ok()
             .map(|q: Query<NormalizedParameter>| q.0);
 
-        let req = Request::from_parts(parts, body);
-        let body = Form::from_request(req, state)
+        // Bodies without a length are only read up to the limit.
+        let bytes = to_bytes(body, limits.body)
             .await
-            .ok()
-            .map(|b: Form<NormalizedParameter>| b.0);
+            .map_err(|_| WebError::TooLarge)?;
+        let body = if is_json(&parts.headers) {
+            match NormalizedParameter::from_json(&bytes) {
+                Some(body) if !limits.allow_parameters(&body) => return Err(WebError::TooLarge),
+                body => body,
+            }
+        } else {
+            if !limits.allow_form(&bytes) {
+                return Err(WebError::TooLarge);
+            }
+            let req = Request::from_parts(parts, Body::from(bytes));
+            Form::from_request(req, state)
+                .await
+                .ok()
+                .map(|b: Form<NormalizedParameter>| b.0)
+        };
 
-        Ok(Self { auth, query, body })
+        Ok(Self {
+            auth,
+            certificate,
+            context,
+            url,
+            query,
+            body,
+        })
     }
 }
 
+fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+fn peer_certificate(headers: &HeaderMap, extensions: &Extensions) -> Option<PeerCertificate> {
+    if let Some(certificate) = extensions.get::<PeerCertificate>() {
+        return Some(certificate.clone());
+    }
+
+    let trusted = extensions.get::<TrustedProxy>().copied().unwrap_or_default();
+    if !trusted.client_certificate {
+        return None;
+    }
+
+    headers
+        .get(PeerCertificate::FORWARDED_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(PeerCertificate::from_forwarded)
+}
+
+fn request_context(headers: &HeaderMap, extensions: &Extensions) -> RequestContext {
+    let mut context = extensions.get::<RequestContext>().cloned().unwrap_or_default();
+    if context.user_agent.is_none() {
+        context.user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+    }
+    if context.idempotency_key.is_none() {
+        context.idempotency_key = headers
+            .get("Idempotency-Key")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+    }
+
+    context
+}
+
+fn request_url(req: &Request) -> Option<Url> {
+    let proxy = req
+        .extensions()
+        .get::<TrustedProxy>()
+        .copied()
+        .unwrap_or_default();
+    let headers = req.headers();
+    let host = req
+        .uri()
+        .authority()
+        .map(|authority| authority.as_str())
+        .or_else(|| headers.get(header::HOST).and_then(|value| value.to_str().ok()))?;
+    let path_and_query = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    let scheme = req.uri().scheme_str().unwrap_or("http");
+
+    proxy.request_url(scheme, host, path_and_query, |name| {
+        headers.get(name).and_then(|value| value.to_str().ok())
+    })
+}
+
+/// Whether the body is sent as `application/json`, possibly with parameters such as a charset.
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
 impl<S> FromRequestParts<S> for OAuthResource
 where
     S: Send + Sync,
@@ -118,7 +271,13 @@ where
     type Rejection = WebError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let mut all_auth = parts.headers.get_all(header::AUTHORIZATION).iter();
+        Self::read(&parts.headers, &parts.extensions)
+    }
+}
+
+impl OAuthResource {
+    pub(crate) fn read(headers: &HeaderMap, extensions: &Extensions) -> Result<Self, WebError> {
+        let mut all_auth = headers.get_all(header::AUTHORIZATION).iter();
         let optional = all_auth.next();
 
         let auth = if all_auth.next().is_some() {
@@ -127,13 +286,155 @@ where
             optional.and_then(|hv| hv.to_str().ok().map(str::to_owned))
         };
 
-        Ok(Self { auth })
+        Ok(Self {
+            auth,
+            certificate: peer_certificate(headers, extensions),
+        })
     }
-}
 
-impl OAuthResource {
     /// Fetch the authorization header from the request
     pub fn authorization_header(&self) -> Option<&str> {
         self.auth.as_deref()
     }
+
+    /// The certificate the client presented over mutual TLS
+    pub fn client_certificate(&self) -> Option<&PeerCertificate> {
+        self.certificate.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(content_type: &str, body: &'static str) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri("/token")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn extract(content_type: &str, body: &'static str) -> OAuthRequest {
+        OAuthRequest::from_request(request(content_type, body), &())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_form_and_json_bodies() {
+        let form = extract("application/x-www-form-urlencoded", "grant_type=refresh_token").await;
+        let json = extract(
+            "application/json; charset=utf-8",
+            r#"{"grant_type":"refresh_token"}"#,
+        )
+        .await;
+
+        for request in [form, json] {
+            let grant_type = request.body().and_then(|body| body.unique_value("grant_type"));
+            assert_eq!(grant_type.as_deref(), Some("refresh_token"));
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_large_requests() {
+        let limits = Limits {
+            body: 32,
+            parameters: 2,
+            ..Limits::default()
+        };
+        let form = "application/x-www-form-urlencoded";
+        let rejected = [
+            request(form, "grant_type=refresh_token&refresh_token=abcdefghijklmnop"),
+            request(form, "a=1&b=2&c=3"),
+            request("application/json", r#"{"a":1,"b":2,"c":3}"#),
+        ];
+
+        for mut request in rejected {
+            request.extensions_mut().insert(limits);
+            let result = OAuthRequest::from_request(request, &()).await;
+            assert!(matches!(result, Err(WebError::TooLarge)));
+        }
+
+        let mut request = request(form, "a=1&b=2");
+        request.extensions_mut().insert(limits);
+        assert!(OAuthRequest::from_request(request, &()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reads_client_certificates() {
+        let certificate = PeerCertificate::from_der(vec![0, 1, 2]);
+        let mut request = request("application/x-www-form-urlencoded", "");
+        request.extensions_mut().insert(certificate.clone());
+        let extracted = OAuthRequest::from_request(request, &()).await.unwrap();
+        assert_eq!(extracted.client_certificate(), Some(&certificate));
+
+        let forwarded = || {
+            let cert = "-----BEGIN%20CERTIFICATE-----%0AAAEC%0A-----END%20CERTIFICATE-----";
+            Request::builder()
+                .header(PeerCertificate::FORWARDED_HEADER, format!("Cert=\"{}\"", cert))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let extracted = OAuthRequest::from_request(forwarded(), &()).await.unwrap();
+        assert!(extracted.client_certificate().is_none());
+
+        let mut request = forwarded();
+        request.extensions_mut().insert(TrustedProxy {
+            client_certificate: true,
+            ..TrustedProxy::default()
+        });
+        let extracted = OAuthRequest::from_request(request, &()).await.unwrap();
+        assert_eq!(extracted.client_certificate(), Some(&certificate));
+    }
+
+    #[tokio::test]
+    async fn reconstructs_forwarded_url() {
+        let forwarded = || {
+            Request::builder()
+                .uri("/authorize?state=a")
+                .header(header::HOST, "127.0.0.1:8020")
+                .header("x-forwarded-proto", "https")
+                .header("x-forwarded-host", "auth.example")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let extracted = OAuthRequest::from_request(forwarded(), &()).await.unwrap();
+        assert_eq!(
+            extracted.url().map(Url::as_str),
+            Some("http://127.0.0.1:8020/authorize?state=a")
+        );
+
+        let mut request = forwarded();
+        request.extensions_mut().insert(TrustedProxy {
+            forwarded: true,
+            ..TrustedProxy::default()
+        });
+        let extracted = OAuthRequest::from_request(request, &()).await.unwrap();
+        assert_eq!(
+            extracted.url().map(Url::as_str),
+            Some("https://auth.example/authorize?state=a")
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_request_context() {
+        let mut request = Request::builder()
+            .header(header::USER_AGENT, "curl/8.0")
+            .header("Idempotency-Key", "retry")
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(RequestContext {
+            remote_addr: Some("192.0.2.1".to_owned()),
+            ..RequestContext::default()
+        });
+
+        let extracted = OAuthRequest::from_request(request, &()).await.unwrap();
+        assert_eq!(extracted.context().remote_addr.as_deref(), Some("192.0.2.1"));
+        assert_eq!(extracted.context().user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!(extracted.context().idempotency_key.as_deref(), Some("retry"));
+    }
 }
@@ -1,19 +1,24 @@
 use crate::WebError;
 use axum::{
+    body::Body,
     response::{IntoResponse, Response},
     http::{
         StatusCode,
-        header::{self, HeaderMap, HeaderValue},
+        header::{self, HeaderMap, HeaderName, HeaderValue, IntoHeaderName},
     },
 };
 use oxide_auth::frontends::dev::{WebResponse, Url};
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Debug)]
 /// Type implementing `WebResponse` and `IntoResponse` for use in route handlers
+///
+/// Besides the parts set by the flows, solicitors can add arbitrary headers and cookies, or stream
+/// the body, for example to serve a rendered consent page while starting a session.
 pub struct OAuthResponse {
     status: StatusCode,
     headers: HeaderMap,
     body: Option<String>,
+    stream: Option<Body>,
 }
 
 impl OAuthResponse {
@@ -27,6 +32,28 @@ impl OAuthResponse {
     /// Set the body for the response
     pub fn body(mut self, body: &str) -> Self {
         self.body = Some(body.to_owned());
+        self.stream = None;
+        self
+    }
+
+    /// Set a header on the response, replacing previous values of the same name
+    pub fn header<K: IntoHeaderName>(mut self, name: K, value: &str) -> Result<Self, WebError> {
+        self.headers.insert(name, value.try_into()?);
+        Ok(self)
+    }
+
+    /// Add a `Set-Cookie` header to the response, keeping previously added cookies
+    pub fn cookie(mut self, cookie: &str) -> Result<Self, WebError> {
+        self.headers.append(header::SET_COOKIE, cookie.try_into()?);
+        Ok(self)
+    }
+
+    /// Stream the body of the response, such as one created with `Body::from_stream`
+    ///
+    /// Replaces a body set previously. Bodies set by the flows in turn replace the stream.
+    pub fn streaming_body(mut self, body: Body) -> Self {
+        self.body = None;
+        self.stream = Some(body);
         self
     }
 }
@@ -56,8 +83,15 @@ impl WebResponse for OAuthResponse {
         Ok(())
     }
 
+    fn forbidden(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = StatusCode::FORBIDDEN;
+        self.headers.insert(header::WWW_AUTHENTICATE, kind.try_into()?);
+        Ok(())
+    }
+
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.body = Some(text.to_owned());
+        self.stream = None;
         self.headers
             .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
         Ok(())
@@ -65,14 +99,65 @@ impl WebResponse for OAuthResponse {
 
     fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
         self.body = Some(json.to_owned());
+        self.stream = None;
         self.headers
             .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| WebError::InternalError(Some(err.to_string())))?;
+        self.headers.insert(name, value.try_into()?);
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.status = StatusCode::TOO_MANY_REQUESTS;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
 }
 
 impl IntoResponse for OAuthResponse {
     fn into_response(self) -> Response {
-        (self.status, self.headers, self.body.unwrap_or_default()).into_response()
+        let body = match (self.stream, self.body) {
+            (Some(stream), _) => stream,
+            (None, body) => Body::from(body.unwrap_or_default()),
+        };
+        (self.status, self.headers, body).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    #[tokio::test]
+    async fn sets_headers_cookies_and_streams() {
+        let chunks = ["<html>", "consent", "</html>"].map(Ok::<_, std::io::Error>);
+        let response = OAuthResponse::default()
+            .content_type("text/html")
+            .unwrap()
+            .header("x-frame-options", "DENY")
+            .unwrap()
+            .cookie("session=a; HttpOnly")
+            .unwrap()
+            .cookie("csrf=b")
+            .unwrap()
+            .streaming_body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .into_response();
+
+        let headers = response.headers();
+        assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+        assert_eq!(headers["x-frame-options"], "DENY");
+        assert_eq!(headers.get_all(header::SET_COOKIE).iter().count(), 2);
+        assert!(OAuthResponse::default().header("x-invalid", "\n").is_err());
+
+        let body = to_bytes(response.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"<html>consent</html>");
     }
 }
@@ -0,0 +1,744 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use oxide_auth::endpoint::{
+    is_authorization_method, AccessTokenFlow, AuthorizationFlow, Authorizer, ClientCredentialsFlow,
+    Issuer, OwnerSolicitor, RefreshFlow, Registrar,
+};
+use oxide_auth::frontends::dev::{QueryParameter, Url};
+use oxide_auth::frontends::simple::endpoint::{Generic, Vacant};
+use oxide_auth::primitives::delegation::Delegation;
+use oxide_auth::primitives::generator::Signer;
+use oxide_auth::primitives::grant::Grant;
+use serde_json::{json, Value};
+
+use crate::{OAuthRequest, OAuthResponse, WebError};
+
+/// Routes for all endpoints of an authorization server.
+///
+/// Created by [`OAuthRouter::builder`], which mounts
+///
+/// * `GET` and `POST` on `/authorize` running the authorization flow, with consent asked for by
+///   the solicitor on both the initial request and the submitted consent form,
+/// * `POST` on `/token` dispatching on the `grant_type` to the access token, refresh and client
+///   credentials flows,
+/// * `POST` on `/revoke` and `/introspect` for token revocation (RFC 7009) and introspection
+///   (RFC 7662) by authenticated clients,
+/// * `GET` on `/.well-known/oauth-authorization-server` with the server metadata (RFC 8414).
+///
+/// Responses of the token, revocation and introspection endpoints are never cached. With a
+/// [`signing_key`](OAuthRouterBuilder::signing_key), introspection requests accepting
+/// `application/token-introspection+jwt` are answered with a signed JWT (RFC 9701), which resource
+/// servers can cache and forward.
+///
+/// ```no_run
+/// use axum::Router;
+/// use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+/// use oxide_auth::frontends::simple::endpoint::FnSolicitor;
+/// use oxide_auth::primitives::prelude::*;
+/// use oxide_auth_axum::{OAuthRequest, OAuthResponse, OAuthRouter};
+///
+/// fn consent(_: &mut OAuthRequest, _: Solicitation) -> OwnerConsent<OAuthResponse> {
+///     OwnerConsent::Denied
+/// }
+///
+/// let issuer = "https://auth.example".parse().unwrap();
+/// let oauth = OAuthRouter::builder(issuer)
+///     .registrar(ClientMap::new())
+///     .authorizer(AuthMap::new(RandomGenerator::new(16)))
+///     .issuer(TokenMap::new(RandomGenerator::new(16)))
+///     .revocation(|issuer: &mut TokenMap<RandomGenerator>, token: &str| issuer.revoke(token))
+///     .solicitor(FnSolicitor(consent))
+///     .build();
+/// let app: Router = Router::new().merge(oauth);
+/// ```
+pub struct OAuthRouter;
+
+/// Collects the primitives of an [`OAuthRouter`].
+pub struct OAuthRouterBuilder<R, A, I, S> {
+    registrar: R,
+    authorizer: A,
+    issuer: I,
+    solicitor: S,
+    issuer_url: Url,
+    revocation: Option<Revocation<I>>,
+    signing_key: Option<SigningKey>,
+}
+
+/// A key signing the JWT responses of the introspection endpoint.
+///
+/// The router does not know which algorithm the signer implements, so it is named along with it.
+/// An `Assertion` for example signs with `HS256`.
+pub struct SigningKey {
+    alg: String,
+    kid: Option<String>,
+    signer: Box<dyn Signer + Send + Sync>,
+}
+
+type Revocation<I> = Box<dyn Fn(&mut I, &str) + Send + Sync>;
+
+struct Shared<R, A, I, S> {
+    endpoint: Mutex<Generic<R, A, I, S>>,
+    issuer_url: Url,
+    revocation: Option<Revocation<I>>,
+    signing_key: Option<SigningKey>,
+}
+
+/// The media type of JWT introspection responses (RFC 9701).
+const INTROSPECTION_JWT: &str = "application/token-introspection+jwt";
+
+type SharedState<R, A, I, S> = State<Arc<Shared<R, A, I, S>>>;
+
+impl OAuthRouter {
+    /// Start with no primitives, all of which must be provided before building the router.
+    ///
+    /// The `issuer_url` identifies the server in its metadata and in signed responses, and the
+    /// endpoints are found below it. It is never derived from requests, whose `Host` header is
+    /// chosen by the client and would let it pick the issuer stamped on responses.
+    pub fn builder(issuer_url: Url) -> OAuthRouterBuilder<Vacant, Vacant, Vacant, Vacant> {
+        OAuthRouterBuilder {
+            registrar: Vacant,
+            authorizer: Vacant,
+            issuer: Vacant,
+            solicitor: Vacant,
+            issuer_url,
+            revocation: None,
+            signing_key: None,
+        }
+    }
+}
+
+impl SigningKey {
+    /// A key signing with the JWS algorithm `alg`, such as `HS256` or `ES256`.
+    pub fn new<K>(alg: &str, signer: K) -> Self
+    where
+        K: Signer + Send + Sync + 'static,
+    {
+        SigningKey {
+            alg: alg.to_owned(),
+            kid: None,
+            signer: Box::new(signer),
+        }
+    }
+
+    /// Name the key in the `kid` header, to find it in the key set published to resource servers.
+    pub fn with_kid(self, kid: &str) -> Self {
+        SigningKey {
+            kid: Some(kid.to_owned()),
+            ..self
+        }
+    }
+
+    /// Sign the claims into a JWT of the type `typ`.
+    fn sign(&self, typ: &str, claims: &Value) -> Option<String> {
+        let mut header = json!({ "typ": typ, "alg": self.alg });
+        if let Some(kid) = &self.kid {
+            header["kid"] = kid.as_str().into();
+        }
+
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = self.signer.sign(message.as_bytes()).ok()?;
+        Some(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+    }
+}
+
+impl<R, A, I, S> OAuthRouterBuilder<R, A, I, S> {
+    /// The registrar of clients.
+    pub fn registrar<T: Registrar>(self, registrar: T) -> OAuthRouterBuilder<T, A, I, S> {
+        OAuthRouterBuilder {
+            registrar,
+            authorizer: self.authorizer,
+            issuer: self.issuer,
+            solicitor: self.solicitor,
+            issuer_url: self.issuer_url,
+            revocation: self.revocation,
+            signing_key: self.signing_key,
+        }
+    }
+
+    /// The authorizer storing authorization codes.
+    pub fn authorizer<T: Authorizer>(self, authorizer: T) -> OAuthRouterBuilder<R, T, I, S> {
+        OAuthRouterBuilder {
+            registrar: self.registrar,
+            authorizer,
+            issuer: self.issuer,
+            solicitor: self.solicitor,
+            issuer_url: self.issuer_url,
+            revocation: self.revocation,
+            signing_key: self.signing_key,
+        }
+    }
+
+    /// The issuer of access and refresh tokens.
+    ///
+    /// Resets a previously configured [`revocation`](Self::revocation).
+    pub fn issuer<T: Issuer>(self, issuer: T) -> OAuthRouterBuilder<R, A, T, S> {
+        OAuthRouterBuilder {
+            registrar: self.registrar,
+            authorizer: self.authorizer,
+            issuer,
+            solicitor: self.solicitor,
+            issuer_url: self.issuer_url,
+            revocation: None,
+            signing_key: self.signing_key,
+        }
+    }
+
+    /// The solicitor asking the resource owner for consent.
+    pub fn solicitor<T>(self, solicitor: T) -> OAuthRouterBuilder<R, A, I, T>
+    where
+        T: OwnerSolicitor<OAuthRequest>,
+    {
+        OAuthRouterBuilder {
+            registrar: self.registrar,
+            authorizer: self.authorizer,
+            issuer: self.issuer,
+            solicitor,
+            issuer_url: self.issuer_url,
+            revocation: self.revocation,
+            signing_key: self.signing_key,
+        }
+    }
+
+    /// Revoke tokens of the issuer.
+    ///
+    /// The `Issuer` trait has no notion of revocation, so the revocation endpoint answers with
+    /// `unsupported_token_type` unless configured.
+    pub fn revocation<F>(mut self, revoke: F) -> Self
+    where
+        F: Fn(&mut I, &str) + Send + Sync + 'static,
+    {
+        self.revocation = Some(Box::new(revoke));
+        self
+    }
+
+    /// Sign introspection responses for callers that accept them as a JWT.
+    ///
+    /// Callers accepting only `application/json` are still answered with a plain response.
+    pub fn signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+}
+
+impl<R, A, I, S> OAuthRouterBuilder<R, A, I, S>
+where
+    R: Registrar + Send + 'static,
+    A: Authorizer + Send + 'static,
+    I: Issuer + Send + 'static,
+    S: OwnerSolicitor<OAuthRequest> + Send + 'static,
+{
+    /// Mount all endpoints on a new router.
+    pub fn build<T: Clone + Send + Sync + 'static>(self) -> Router<T> {
+        let shared = Arc::new(Shared {
+            endpoint: Mutex::new(Generic {
+                registrar: self.registrar,
+                authorizer: self.authorizer,
+                issuer: self.issuer,
+                solicitor: self.solicitor,
+                scopes: Vacant,
+                response: Vacant,
+            }),
+            issuer_url: self.issuer_url,
+            revocation: self.revocation,
+            signing_key: self.signing_key,
+        });
+
+        Router::new()
+            .route(
+                "/authorize",
+                get(authorize::<R, A, I, S>).post(authorize::<R, A, I, S>),
+            )
+            .route("/token", post(token::<R, A, I, S>))
+            .route("/revoke", post(revoke::<R, A, I, S>))
+            .route("/introspect", post(introspect::<R, A, I, S>))
+            .route(
+                "/.well-known/oauth-authorization-server",
+                get(metadata::<R, A, I, S>),
+            )
+            .with_state(shared)
+    }
+}
+
+impl<R, A, I, S> Shared<R, A, I, S> {
+    fn lock(&self) -> Result<MutexGuard<'_, Generic<R, A, I, S>>, WebError> {
+        self.endpoint
+            .lock()
+            .map_err(|_| WebError::InternalError(Some("Endpoint lock poisoned".into())))
+    }
+}
+
+/// Responses containing tokens or details about them must not be stored (RFC 6749 section 5.1).
+fn no_store(response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+    response
+}
+
+fn json_error(status: StatusCode, error: &str) -> Response {
+    let body = json!({ "error": error }).to_string();
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+async fn authorize<R, A, I, S>(
+    State(shared): SharedState<R, A, I, S>, request: OAuthRequest,
+) -> Result<OAuthResponse, WebError>
+where
+    R: Registrar,
+    A: Authorizer,
+    I: Issuer,
+    S: OwnerSolicitor<OAuthRequest>,
+{
+    let mut endpoint = shared.lock()?;
+    Ok(AuthorizationFlow::prepare(&mut *endpoint)?.execute(request)?)
+}
+
+async fn token<R, A, I, S>(State(shared): SharedState<R, A, I, S>, request: OAuthRequest) -> Response
+where
+    R: Registrar,
+    A: Authorizer,
+    I: Issuer,
+    S: OwnerSolicitor<OAuthRequest>,
+{
+    no_store(dispatch_token(&shared, request))
+}
+
+fn dispatch_token<R, A, I, S>(
+    shared: &Shared<R, A, I, S>, request: OAuthRequest,
+) -> Result<OAuthResponse, WebError>
+where
+    R: Registrar,
+    A: Authorizer,
+    I: Issuer,
+    S: OwnerSolicitor<OAuthRequest>,
+{
+    let grant_type = request
+        .body()
+        .and_then(|body| body.unique_value("grant_type"))
+        .map(|grant_type| grant_type.into_owned());
+
+    let mut endpoint = shared.lock()?;
+    let endpoint = &mut *endpoint;
+    let response = match grant_type.as_deref() {
+        Some("refresh_token") => RefreshFlow::prepare(endpoint)?.execute(request)?,
+        Some("client_credentials") => ClientCredentialsFlow::prepare(endpoint)?.execute(request)?,
+        // Also answers unsupported grant types with the appropriate error.
+        _ => AccessTokenFlow::prepare(endpoint)?.execute(request)?,
+    };
+
+    Ok(response)
+}
+
+/// The client authenticated by the request, with HTTP Basic or as a public client in the body.
+fn authenticate(registrar: &dyn Registrar, request: &OAuthRequest) -> Option<String> {
+    let (client_id, passphrase) = match request.authorization_header() {
+        Some(header) => {
+            let encoded = is_authorization_method(header, "Basic ")?;
+            let decoded = STANDARD.decode(encoded).ok()?;
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (client_id, passphrase) = decoded.split_once(':')?;
+            (client_id.to_owned(), Some(passphrase.as_bytes().to_vec()))
+        }
+        None => (request.body()?.unique_value("client_id")?.into_owned(), None),
+    };
+
+    registrar.check(&client_id, passphrase.as_deref()).ok()?;
+    Some(client_id)
+}
+
+fn unauthenticated() -> Response {
+    let mut response = json_error(StatusCode::UNAUTHORIZED, "invalid_client");
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+    response
+}
+
+/// Look up a token, trying refresh tokens first if hinted.
+fn recover(issuer: &dyn Issuer, token: &str, hint: Option<&str>) -> Result<Option<Grant>, WebError> {
+    let failed = |()| WebError::InternalError(Some("Issuer failed to recover the token".into()));
+    let lookup = |refresh: bool| match refresh {
+        true => issuer.recover_refresh(token).map_err(failed),
+        false => issuer.recover_token(token).map_err(failed),
+    };
+
+    let refresh_first = hint == Some("refresh_token");
+    match lookup(refresh_first)? {
+        Some(grant) => Ok(Some(grant)),
+        None => lookup(!refresh_first),
+    }
+}
+
+async fn revoke<R, A, I, S>(State(shared): SharedState<R, A, I, S>, request: OAuthRequest) -> Response
+where
+    R: Registrar,
+    I: Issuer,
+{
+    let mut endpoint = match shared.lock() {
+        Ok(endpoint) => endpoint,
+        Err(err) => return err.into_response(),
+    };
+
+    let client_id = match authenticate(&endpoint.registrar, &request) {
+        Some(client_id) => client_id,
+        None => return no_store(unauthenticated()),
+    };
+
+    let body = request.body();
+    let token = match body.and_then(|body| body.unique_value("token")) {
+        Some(token) => token,
+        None => return no_store(json_error(StatusCode::BAD_REQUEST, "invalid_request")),
+    };
+
+    let revocation = match &shared.revocation {
+        Some(revocation) => revocation,
+        None => return no_store(json_error(StatusCode::BAD_REQUEST, "unsupported_token_type")),
+    };
+
+    let hint = body.and_then(|body| body.unique_value("token_type_hint"));
+    let grant = match recover(&endpoint.issuer, &token, hint.as_deref()) {
+        Ok(grant) => grant,
+        Err(err) => return err.into_response(),
+    };
+
+    // Unknown tokens and those of other clients are not an error, to not leak their existence.
+    if grant.is_some_and(|grant| grant.client_id == client_id) {
+        revocation(&mut endpoint.issuer, &token);
+    }
+
+    no_store(StatusCode::OK)
+}
+
+async fn introspect<R, A, I, S>(
+    State(shared): SharedState<R, A, I, S>, headers: HeaderMap, request: OAuthRequest,
+) -> Response
+where
+    R: Registrar,
+    I: Issuer,
+{
+    let endpoint = match shared.lock() {
+        Ok(endpoint) => endpoint,
+        Err(err) => return err.into_response(),
+    };
+
+    let client_id = match authenticate(&endpoint.registrar, &request) {
+        Some(client_id) => client_id,
+        None => return no_store(unauthenticated()),
+    };
+
+    let body = request.body();
+    let token = match body.and_then(|body| body.unique_value("token")) {
+        Some(token) => token,
+        None => return no_store(json_error(StatusCode::BAD_REQUEST, "invalid_request")),
+    };
+
+    let hint = body.and_then(|body| body.unique_value("token_type_hint"));
+    let grant = match recover(&endpoint.issuer, &token, hint.as_deref()) {
+        Ok(grant) => grant,
+        Err(err) => return err.into_response(),
+    };
+
+    let description = match grant {
+        Some(grant) if grant.until > chrono::Utc::now() => {
+            let mut description = json!({
+                "active": true,
+                "scope": grant.scope.to_string(),
+                "client_id": grant.client_id,
+                "sub": grant.owner_id,
+                "exp": grant.until.timestamp(),
+            });
+            // Expose on-behalf-of chains, a malformed chain is not published.
+            if let Some(Ok(delegation)) = Delegation::of(&grant) {
+                description["act"] = delegation.to_claim();
+            }
+            description
+        }
+        _ => json!({ "active": false }),
+    };
+
+    let key = match &shared.signing_key {
+        Some(key) if accepts(&headers, INTROSPECTION_JWT) => key,
+        _ => {
+            return no_store((
+                [(header::CONTENT_TYPE, "application/json")],
+                description.to_string(),
+            ))
+        }
+    };
+
+    let issuer = &shared.issuer_url;
+
+    // The caller is the audience, the response describes the token in a claim of its own.
+    let claims = json!({
+        "iss": issuer.as_str().trim_end_matches('/'),
+        "aud": client_id,
+        "iat": chrono::Utc::now().timestamp(),
+        "token_introspection": description,
+    });
+    match key.sign("token-introspection+jwt", &claims) {
+        Some(jwt) => no_store(([(header::CONTENT_TYPE, INTROSPECTION_JWT)], jwt)),
+        None => WebError::InternalError(Some("Failed to sign the introspection response".into()))
+            .into_response(),
+    }
+}
+
+/// Whether the `Accept` header names the media type, ignoring parameters such as weights.
+fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|range| range.split(';').next())
+        .any(|range| range.trim().eq_ignore_ascii_case(media_type))
+}
+
+async fn metadata<R, A, I, S>(State(shared): SharedState<R, A, I, S>) -> Response {
+    let issuer = &shared.issuer_url;
+
+    // Relative paths resolve below the issuer, also when it has a path.
+    let mut base = issuer.clone();
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    let endpoint = |path: &str| base.join(path).map(String::from).unwrap_or_default();
+
+    let mut metadata: Value = json!({
+        "issuer": issuer.as_str().trim_end_matches('/'),
+        "authorization_endpoint": endpoint("authorize"),
+        "token_endpoint": endpoint("token"),
+        "revocation_endpoint": endpoint("revoke"),
+        "introspection_endpoint": endpoint("introspect"),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token", "client_credentials"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "none"],
+    });
+    if let Some(key) = &shared.signing_key {
+        metadata["introspection_signing_alg_values_supported"] = json!([key.alg]);
+    }
+
+    ([(header::CONTENT_TYPE, "application/json")], metadata.to_string()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        extract::Request,
+    };
+    use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+    use oxide_auth::frontends::simple::endpoint::FnSolicitor;
+    use oxide_auth::primitives::delegation::Actor;
+    use oxide_auth::primitives::generator::AssertionKind;
+    use oxide_auth::primitives::prelude::*;
+    use oxide_auth::primitives::registrar::RegisteredUrl;
+    use tower_service::Service;
+
+    const BASIC: &str = "Basic TG9jYWxDbGllbnQ6U2VjcmV0U2VjcmV0";
+    const SIGNING_KEY: &[u8] = b"IntrospectionSigningKey";
+    const ISSUER: &str = "http://auth.example";
+
+    fn consent(_: &mut OAuthRequest, _: Solicitation) -> OwnerConsent<OAuthResponse> {
+        OwnerConsent::Authorized("owner".into())
+    }
+
+    fn router() -> Router {
+        let client = Client::confidential(
+            "LocalClient",
+            RegisteredUrl::Semantic("https://client.example/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+            b"SecretSecret",
+        );
+        OAuthRouter::builder(ISSUER.parse().unwrap())
+            .registrar(vec![client].into_iter().collect::<ClientMap>())
+            .authorizer(AuthMap::new(RandomGenerator::new(16)))
+            .issuer(TokenMap::new(RandomGenerator::new(16)))
+            .revocation(|issuer: &mut TokenMap<RandomGenerator>, token: &str| issuer.revoke(token))
+            .solicitor(FnSolicitor(consent))
+            .signing_key(
+                SigningKey::new("HS256", Assertion::new(AssertionKind::HmacSha256, SIGNING_KEY))
+                    .with_kid("introspection"),
+            )
+            .build()
+    }
+
+    fn post(uri: &str, params: &[(&str, &str)]) -> Request {
+        let mut body = Url::parse("http://form").unwrap();
+        body.query_pairs_mut().extend_pairs(params);
+        let body = body.query().unwrap_or("").to_owned();
+        Request::post(uri)
+            .header(header::HOST, "auth.example")
+            .header(header::AUTHORIZATION, BASIC)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn json(response: Response) -> Value {
+        let body = to_bytes(response.into_body(), 4096).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn mounts_endpoints() {
+        let mut app = router();
+
+        let authorize = Request::get("/authorize?response_type=code&client_id=LocalClient")
+            .header(header::HOST, "auth.example")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.call(authorize).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        let location = Url::parse(location).unwrap();
+        let (_, code) = location.query_pairs().find(|(key, _)| key == "code").unwrap();
+
+        let token = [
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", "https://client.example/endpoint"),
+        ];
+        let response = app.call(post("/token", &token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+        let token = json(response).await["access_token"].as_str().unwrap().to_owned();
+
+        let introspection = || post("/introspect", &[("token", &token)]);
+        let response = app.call(introspection()).await.unwrap();
+        let description = json(response).await;
+        assert_eq!(description["active"], true);
+        assert_eq!(description["client_id"], "LocalClient");
+
+        let response = app.call(post("/revoke", &[("token", &token)])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.call(introspection()).await.unwrap();
+        assert_eq!(json(response).await["active"], false);
+
+        let response = app
+            .call(post("/token", &[("grant_type", "password")]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let metadata = Request::get("/.well-known/oauth-authorization-server")
+            .header(header::HOST, "auth.example")
+            .body(Body::empty())
+            .unwrap();
+        let metadata = json(app.call(metadata).await.unwrap()).await;
+        assert_eq!(metadata["issuer"], ISSUER);
+        assert_eq!(metadata["token_endpoint"], "http://auth.example/token");
+        assert_eq!(
+            metadata["introspection_signing_alg_values_supported"],
+            json!(["HS256"])
+        );
+    }
+
+    #[tokio::test]
+    async fn introspection_exposes_delegation() {
+        let client = Client::confidential(
+            "LocalClient",
+            RegisteredUrl::Semantic("https://client.example/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+            b"SecretSecret",
+        );
+        let mut grant = Grant {
+            owner_id: "owner".into(),
+            client_id: "LocalClient".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: chrono::Utc::now() + chrono::Duration::minutes(5),
+            extensions: Default::default(),
+        };
+        let delegation = Delegation::new(Actor::new("gateway"));
+        delegation.attach(&mut grant);
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        issuer.import_grant("DelegatedToken".into(), grant);
+
+        let mut app: Router = OAuthRouter::builder(ISSUER.parse().unwrap())
+            .registrar(vec![client].into_iter().collect::<ClientMap>())
+            .authorizer(AuthMap::new(RandomGenerator::new(16)))
+            .issuer(issuer)
+            .solicitor(FnSolicitor(consent))
+            .build();
+
+        let introspection = post("/introspect", &[("token", "DelegatedToken")]);
+        let description = json(app.call(introspection).await.unwrap()).await;
+        assert_eq!(description["active"], true);
+        assert_eq!(description["act"], json!({ "sub": "gateway" }));
+    }
+
+    #[tokio::test]
+    async fn signed_introspection() {
+        let mut app = router();
+
+        let token = [("grant_type", "client_credentials")];
+        let response = app.call(post("/token", &token)).await.unwrap();
+        let token = json(response).await["access_token"].as_str().unwrap().to_owned();
+
+        let mut introspection = post("/introspect", &[("token", &token)]);
+        introspection.headers_mut().insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/token-introspection+jwt;q=1, application/json;q=0.5"),
+        );
+        let response = app.call(introspection).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], INTROSPECTION_JWT);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let body = to_bytes(response.into_body(), 4096).await.unwrap();
+        let jwt = std::str::from_utf8(&body).unwrap();
+        let (message, signature) = jwt.rsplit_once('.').unwrap();
+        let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+        let verifier = Assertion::new(AssertionKind::HmacSha256, SIGNING_KEY);
+        assert!(verifier.verify(message.as_bytes(), &signature).is_ok());
+
+        let (header, claims) = message.split_once('.').unwrap();
+        let decode = |part: &str| -> Value {
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(part).unwrap()).unwrap()
+        };
+        let header = decode(header);
+        assert_eq!(header["typ"], "token-introspection+jwt");
+        assert_eq!(header["kid"], "introspection");
+
+        let claims = decode(claims);
+        assert_eq!(claims["iss"], ISSUER);
+        assert_eq!(claims["aud"], "LocalClient");
+        assert_eq!(claims["token_introspection"]["active"], true);
+        assert_eq!(claims["token_introspection"]["client_id"], "LocalClient");
+
+        // Without asking for a JWT, the description is answered as plain JSON.
+        let response = app.call(post("/introspect", &[("token", &token)])).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(json(response).await["active"], true);
+    }
+
+    #[tokio::test]
+    async fn issuer_ignores_host() {
+        let mut app = router();
+
+        let metadata = Request::get("/.well-known/oauth-authorization-server")
+            .header(header::HOST, "attacker.example")
+            .body(Body::empty())
+            .unwrap();
+        let metadata = json(app.call(metadata).await.unwrap()).await;
+        assert_eq!(metadata["issuer"], ISSUER);
+        assert_eq!(metadata["token_endpoint"], "http://auth.example/token");
+    }
+}
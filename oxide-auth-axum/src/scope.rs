@@ -0,0 +1,177 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use oxide_auth::code_grant::resource::{AccessFailure, Authenticate, Error as ResourceError, ErrorCode};
+use oxide_auth::primitives::{
+    grant::Grant,
+    scope::{ParseScopeErr, Scope},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// A `tower::Layer` requiring the grant of a request to include a scope.
+///
+/// Declares the scope policy of individual routes at the router. The grant is the one found by an
+/// [`OAuthGuardLayer`] further out, so this layer must be applied inside of it, for example to the
+/// method router of a route of a router that is wrapped by the guard. Requests whose grant lacks any of the
+/// scope tokens are answered with `403 Forbidden` and an `insufficient_scope` error as described in
+/// RFC 6750. Requests without a grant, which did not pass a guard, are answered with
+/// `401 Unauthorized`.
+///
+/// ```no_run
+/// # use std::sync::{Arc, Mutex};
+/// use axum::{routing::get, Router};
+/// use oxide_auth::primitives::{generator::RandomGenerator, issuer::TokenMap};
+/// use oxide_auth_axum::{OAuthGuardLayer, RequireScope};
+///
+/// let issuer = Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16))));
+/// let write: RequireScope = "api.read api.write".parse().unwrap();
+/// let app: Router = Router::new()
+///     .route("/items", get(|| async { "items" }))
+///     .route("/items/new", get(|| async { "created" }).layer(write))
+///     .layer(OAuthGuardLayer::new(issuer, vec!["api.read".parse().unwrap()]));
+/// ```
+///
+/// [`OAuthGuardLayer`]: crate::OAuthGuardLayer
+#[derive(Clone, Debug)]
+pub struct RequireScope {
+    scope: Arc<Scope>,
+}
+
+/// The service created by a [`RequireScope`] layer.
+#[derive(Clone, Debug)]
+pub struct RequireScopeService<S> {
+    inner: S,
+    scope: Arc<Scope>,
+}
+
+impl RequireScope {
+    /// Require grants to include all tokens of the scope.
+    pub fn new(scope: Scope) -> Self {
+        RequireScope {
+            scope: Arc::new(scope),
+        }
+    }
+
+    /// The required scope.
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+}
+
+/// Reject the request with the returned response unless its grant includes the scope.
+fn check(scope: &Scope, request: &Request) -> Option<Response> {
+    let (status, failure) = match request.extensions().get::<Grant>() {
+        None => (StatusCode::UNAUTHORIZED, None),
+        Some(grant) if scope.allow_access(&grant.scope) => return None,
+        Some(_) => (StatusCode::FORBIDDEN, Some(ErrorCode::InsufficientScope)),
+    };
+
+    let error = ResourceError::AccessDenied {
+        failure: AccessFailure { code: failure },
+        authenticate: Authenticate {
+            realm: None,
+            scope: Some(scope.clone()),
+        },
+    };
+    let mut response = status.into_response();
+    if let Ok(value) = HeaderValue::try_from(error.www_authenticate()) {
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, value);
+    }
+
+    Some(response)
+}
+
+impl From<Scope> for RequireScope {
+    fn from(scope: Scope) -> Self {
+        RequireScope::new(scope)
+    }
+}
+
+impl FromStr for RequireScope {
+    type Err = ParseScopeErr;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        scope.parse().map(RequireScope::new)
+    }
+}
+
+impl<S> Layer<S> for RequireScope {
+    type Service = RequireScopeService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireScopeService {
+            inner,
+            scope: self.scope.clone(),
+        }
+    }
+}
+
+impl<S> Service<Request> for RequireScopeService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        match check(&self.scope, &request) {
+            None => Box::pin(self.inner.call(request)),
+            Some(response) => Box::pin(async move { Ok(response) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use chrono::{Duration, Utc};
+    use oxide_auth::primitives::grant::Extensions;
+
+    fn request(scope: Option<&str>) -> Request {
+        let mut request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        if let Some(scope) = scope {
+            request.extensions_mut().insert(Grant {
+                owner_id: "Owner".into(),
+                client_id: "Client".into(),
+                scope: scope.parse().unwrap(),
+                redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+                until: Utc::now() + Duration::minutes(10),
+                extensions: Extensions::new(),
+            });
+        }
+        request
+    }
+
+    #[tokio::test]
+    async fn requires_all_tokens() {
+        let require: RequireScope = "api.read api.write".parse().unwrap();
+        let mut app = Router::new().route("/", get(|| async { "ok" }).layer(require));
+
+        let response = app.call(request(Some("api.write api.read admin"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.call(request(Some("api.read"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let authenticate = response.headers()[header::WWW_AUTHENTICATE].to_str().unwrap();
+        assert!(authenticate.contains("error=\"insufficient_scope\""));
+
+        let response = app.call(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,418 @@
+//! The individual checks, each a sequence of requests to a fresh server.
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::{Exchange, Server, CLIENT_ID, CLIENT_SECRET, PUBLIC_CLIENT_ID, REDIRECT_URI, SCOPE};
+
+/// A check of one required behavior.
+pub struct Check {
+    pub name: &'static str,
+    pub reference: &'static str,
+    pub run: fn(&mut dyn Server) -> Result<(), String>,
+}
+
+pub const ALL: &[Check] = &[
+    Check {
+        name: "authorization code is redirected with the state",
+        reference: "RFC 6749, 4.1.2",
+        run: code_redirected,
+    },
+    Check {
+        name: "unknown client is not redirected",
+        reference: "RFC 6749, 4.1.2.1",
+        run: unknown_client_not_redirected,
+    },
+    Check {
+        name: "unregistered redirect uri is not redirected",
+        reference: "RFC 6749, 4.1.2.1",
+        run: foreign_redirect_not_redirected,
+    },
+    Check {
+        name: "unsupported response type is redirected",
+        reference: "RFC 6749, 4.1.2.1",
+        run: unsupported_response_type,
+    },
+    Check {
+        name: "token response is a bearer token not to be stored",
+        reference: "RFC 6749, 5.1",
+        run: token_response,
+    },
+    Check {
+        name: "authorization code can be used once",
+        reference: "RFC 6749, 4.1.2",
+        run: code_single_use,
+    },
+    Check {
+        name: "code is bound to its redirect uri",
+        reference: "RFC 6749, 4.1.3",
+        run: code_bound_to_redirect_uri,
+    },
+    Check {
+        name: "code is bound to its client",
+        reference: "RFC 6749, 4.1.3",
+        run: code_bound_to_client,
+    },
+    Check {
+        name: "wrong client secret is invalid_client",
+        reference: "RFC 6749, 5.2",
+        run: wrong_client_secret,
+    },
+    Check {
+        name: "missing code is invalid_request",
+        reference: "RFC 6749, 5.2",
+        run: missing_code,
+    },
+    Check {
+        name: "unknown grant type is unsupported_grant_type",
+        reference: "RFC 6749, 5.2",
+        run: unsupported_grant_type,
+    },
+    Check {
+        name: "refresh token issues a new access token",
+        reference: "RFC 6749, 6",
+        run: refresh_token,
+    },
+    Check {
+        name: "S256 code verifier is accepted",
+        reference: "RFC 7636, 4.6",
+        run: pkce_verified,
+    },
+    Check {
+        name: "wrong code verifier is refused",
+        reference: "RFC 7636, 4.6",
+        run: pkce_wrong_verifier,
+    },
+    Check {
+        name: "missing code verifier is refused",
+        reference: "RFC 7636, 4.6",
+        run: pkce_missing_verifier,
+    },
+    Check {
+        name: "resource without token is challenged",
+        reference: "RFC 6750, 3.1",
+        run: resource_without_token,
+    },
+    Check {
+        name: "resource with unknown token is invalid_token",
+        reference: "RFC 6750, 3.1",
+        run: resource_invalid_token,
+    },
+    Check {
+        name: "resource with issued token is accessible",
+        reference: "RFC 6750, 2.1",
+        run: resource_with_token,
+    },
+];
+
+const STATE: &str = "xyz-conformance";
+
+/// A code verifier of 43 characters, the minimum length.
+const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+fn code_redirected(server: &mut dyn Server) -> Result<(), String> {
+    authorize(server, CLIENT_ID, &[]).map(drop)
+}
+
+fn unknown_client_not_redirected(server: &mut dyn Server) -> Result<(), String> {
+    let response = server.authorize(&[
+        ("response_type", "code"),
+        ("client_id", "conformance-unknown"),
+        ("redirect_uri", REDIRECT_URI),
+        ("state", STATE),
+    ]);
+    not_redirected(&response)
+}
+
+fn foreign_redirect_not_redirected(server: &mut dyn Server) -> Result<(), String> {
+    let response = server.authorize(&[
+        ("response_type", "code"),
+        ("client_id", CLIENT_ID),
+        ("redirect_uri", "https://attacker.example/callback"),
+        ("state", STATE),
+    ]);
+    not_redirected(&response)
+}
+
+fn unsupported_response_type(server: &mut dyn Server) -> Result<(), String> {
+    let response = server.authorize(&[
+        ("response_type", "conformance"),
+        ("client_id", CLIENT_ID),
+        ("redirect_uri", REDIRECT_URI),
+        ("state", STATE),
+    ]);
+    let error = redirected(&response, "error")?;
+    expect_eq("error", &error, "unsupported_response_type")?;
+    expect_eq("state", &redirected(&response, "state")?, STATE)
+}
+
+fn token_response(server: &mut dyn Server) -> Result<(), String> {
+    let code = authorize(server, CLIENT_ID, &[])?;
+    let response = exchange(server, &code, REDIRECT_URI, Some(CLIENT_SECRET));
+    let json = expect_json(&response, 200)?;
+
+    if !json["access_token"].is_string() {
+        return Err(format!("Expected an access_token, got {}", response.body));
+    }
+    let token_type = json["token_type"].as_str().unwrap_or_default();
+    if !token_type.eq_ignore_ascii_case("bearer") {
+        return Err(format!("Expected token_type bearer, got {:?}", token_type));
+    }
+    match response.header("Cache-Control") {
+        Some(value) if value.contains("no-store") => Ok(()),
+        other => Err(format!("Expected Cache-Control: no-store, got {:?}", other)),
+    }
+}
+
+fn code_single_use(server: &mut dyn Server) -> Result<(), String> {
+    let code = authorize(server, CLIENT_ID, &[])?;
+    let first = exchange(server, &code, REDIRECT_URI, Some(CLIENT_SECRET));
+    expect_json(&first, 200)?;
+
+    let second = exchange(server, &code, REDIRECT_URI, Some(CLIENT_SECRET));
+    expect_refused_grant(&second)
+}
+
+fn code_bound_to_redirect_uri(server: &mut dyn Server) -> Result<(), String> {
+    let code = authorize(server, CLIENT_ID, &[])?;
+    let response = exchange(server, &code, "https://client.example/other", Some(CLIENT_SECRET));
+    expect_error(&response, 400, "invalid_grant")
+}
+
+fn code_bound_to_client(server: &mut dyn Server) -> Result<(), String> {
+    let code = authorize(server, CLIENT_ID, &[])?;
+    let response = server.token(
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", REDIRECT_URI),
+            ("client_id", PUBLIC_CLIENT_ID),
+        ],
+        None,
+    );
+    expect_error(&response, 400, "invalid_grant")
+}
+
+fn wrong_client_secret(server: &mut dyn Server) -> Result<(), String> {
+    let code = authorize(server, CLIENT_ID, &[])?;
+    let response = exchange(server, &code, REDIRECT_URI, Some("conformance-wrong"));
+    expect_error(&response, 401, "invalid_client")?;
+    match response.header("WWW-Authenticate") {
+        Some(_) => Ok(()),
+        None => Err("Expected a WWW-Authenticate header".to_owned()),
+    }
+}
+
+fn missing_code(server: &mut dyn Server) -> Result<(), String> {
+    let response = server.token(
+        &[
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", REDIRECT_URI),
+        ],
+        Some(&basic(CLIENT_ID, CLIENT_SECRET)),
+    );
+    expect_error(&response, 400, "invalid_request")
+}
+
+fn unsupported_grant_type(server: &mut dyn Server) -> Result<(), String> {
+    let response = server.token(
+        &[("grant_type", "urn:conformance:unknown")],
+        Some(&basic(CLIENT_ID, CLIENT_SECRET)),
+    );
+    expect_error(&response, 400, "unsupported_grant_type")
+}
+
+fn refresh_token(server: &mut dyn Server) -> Result<(), String> {
+    let code = authorize(server, CLIENT_ID, &[])?;
+    let response = exchange(server, &code, REDIRECT_URI, Some(CLIENT_SECRET));
+    let json = expect_json(&response, 200)?;
+
+    // Refresh tokens are optional, servers not issuing them pass.
+    let refresh = match json["refresh_token"].as_str() {
+        Some(refresh) => refresh.to_owned(),
+        None => return Ok(()),
+    };
+
+    let response = server.token(
+        &[("grant_type", "refresh_token"), ("refresh_token", &refresh)],
+        Some(&basic(CLIENT_ID, CLIENT_SECRET)),
+    );
+    let refreshed = expect_json(&response, 200)?;
+    match refreshed["access_token"].as_str() {
+        Some(token) if Some(token) != json["access_token"].as_str() => Ok(()),
+        _ => Err(format!("Expected a new access_token, got {}", response.body)),
+    }
+}
+
+fn pkce_verified(server: &mut dyn Server) -> Result<(), String> {
+    let code = pkce_authorize(server)?;
+    let response = pkce_exchange(server, &code, Some(VERIFIER));
+    expect_json(&response, 200).map(drop)
+}
+
+fn pkce_wrong_verifier(server: &mut dyn Server) -> Result<(), String> {
+    let code = pkce_authorize(server)?;
+    let wrong = "xBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    let response = pkce_exchange(server, &code, Some(wrong));
+    expect_refused_grant(&response)
+}
+
+fn pkce_missing_verifier(server: &mut dyn Server) -> Result<(), String> {
+    let code = pkce_authorize(server)?;
+    let response = pkce_exchange(server, &code, None);
+    expect_refused_grant(&response)
+}
+
+fn resource_without_token(server: &mut dyn Server) -> Result<(), String> {
+    let response = server.resource(None);
+    expect_status(&response, 401)?;
+    match response.header("WWW-Authenticate") {
+        Some(challenge) if challenge.to_ascii_lowercase().starts_with("bearer") => Ok(()),
+        other => Err(format!("Expected a Bearer challenge, got {:?}", other)),
+    }
+}
+
+fn resource_invalid_token(server: &mut dyn Server) -> Result<(), String> {
+    let response = server.resource(Some("Bearer conformance-unknown-token"));
+    expect_status(&response, 401)?;
+    match response.header("WWW-Authenticate") {
+        Some(challenge) if challenge.contains("error=\"invalid_token\"") => Ok(()),
+        other => Err(format!("Expected an invalid_token challenge, got {:?}", other)),
+    }
+}
+
+fn resource_with_token(server: &mut dyn Server) -> Result<(), String> {
+    let code = authorize(server, CLIENT_ID, &[])?;
+    let response = exchange(server, &code, REDIRECT_URI, Some(CLIENT_SECRET));
+    let json = expect_json(&response, 200)?;
+    let token = json["access_token"].as_str().unwrap_or_default();
+
+    let response = server.resource(Some(&format!("Bearer {}", token)));
+    expect_status(&response, 200)
+}
+
+/// Request a code for the client, with additional parameters.
+fn authorize(
+    server: &mut dyn Server, client_id: &str, additional: &[(&str, &str)],
+) -> Result<String, String> {
+    let mut query = vec![
+        ("response_type", "code"),
+        ("client_id", client_id),
+        ("redirect_uri", REDIRECT_URI),
+        ("scope", SCOPE),
+        ("state", STATE),
+    ];
+    query.extend_from_slice(additional);
+    let response = server.authorize(&query);
+
+    if let Ok(error) = redirected(&response, "error") {
+        return Err(format!("Expected a code, got the error {}", error));
+    }
+    expect_eq("state", &redirected(&response, "state")?, STATE)?;
+    redirected(&response, "code")
+}
+
+fn pkce_authorize(server: &mut dyn Server) -> Result<String, String> {
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(VERIFIER.as_bytes()));
+    authorize(
+        server,
+        PUBLIC_CLIENT_ID,
+        &[("code_challenge", &challenge), ("code_challenge_method", "S256")],
+    )
+}
+
+/// Exchange a code of the confidential client, authenticating with the secret.
+fn exchange(server: &mut dyn Server, code: &str, redirect_uri: &str, secret: Option<&str>) -> Exchange {
+    let authorization = secret.map(|secret| basic(CLIENT_ID, secret));
+    server.token(
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+        ],
+        authorization.as_deref(),
+    )
+}
+
+fn pkce_exchange(server: &mut dyn Server, code: &str, verifier: Option<&str>) -> Exchange {
+    let mut body = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", REDIRECT_URI),
+        ("client_id", PUBLIC_CLIENT_ID),
+    ];
+    body.extend(verifier.map(|verifier| ("code_verifier", verifier)));
+    server.token(&body, None)
+}
+
+fn basic(client_id: &str, secret: &str) -> String {
+    format!("Basic {}", STANDARD.encode(format!("{}:{}", client_id, secret)))
+}
+
+/// A parameter of a redirect to the registered redirect uri.
+fn redirected(response: &Exchange, parameter: &str) -> Result<String, String> {
+    expect_status(response, 302)?;
+    let location = response
+        .location()
+        .ok_or_else(|| "Expected a Location header".to_owned())?;
+    if !location.as_str().starts_with(REDIRECT_URI) {
+        return Err(format!("Expected a redirect to the client, got {}", location));
+    }
+
+    location
+        .query_pairs()
+        .find(|(name, _)| name == parameter)
+        .map(|(_, value)| value.into_owned())
+        .ok_or_else(|| format!("Expected {} in the redirect {}", parameter, location))
+}
+
+fn not_redirected(response: &Exchange) -> Result<(), String> {
+    match response.location() {
+        Some(location) if response.status / 100 == 3 => {
+            Err(format!("Expected no redirect, got one to {}", location))
+        }
+        _ if response.status / 100 == 4 => Ok(()),
+        _ => Err(format!("Expected a client error, got status {}", response.status)),
+    }
+}
+
+fn expect_status(response: &Exchange, status: u16) -> Result<(), String> {
+    if response.status == status {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected status {}, got {} with body {:?}",
+            status, response.status, response.body
+        ))
+    }
+}
+
+fn expect_json(response: &Exchange, status: u16) -> Result<serde_json::Value, String> {
+    expect_status(response, status)?;
+    response
+        .json()
+        .ok_or_else(|| format!("Expected a JSON body, got {:?}", response.body))
+}
+
+fn expect_error(response: &Exchange, status: u16, error: &str) -> Result<(), String> {
+    let json = expect_json(response, status)?;
+    expect_eq("error", json["error"].as_str().unwrap_or_default(), error)
+}
+
+/// A refused code, with `invalid_grant` or `invalid_request`.
+///
+/// The specifications require `invalid_grant` for codes that were already used or whose verifier
+/// does not match, while `oxide-auth` answers `invalid_request` for these. Both are accepted so
+/// that integrations of `oxide-auth` pass.
+fn expect_refused_grant(response: &Exchange) -> Result<(), String> {
+    expect_error(response, 400, "invalid_grant")
+        .or_else(|_| expect_error(response, 400, "invalid_request"))
+}
+
+fn expect_eq(what: &str, actual: &str, expected: &str) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("Expected {} {:?}, got {:?}", what, expected, actual))
+    }
+}
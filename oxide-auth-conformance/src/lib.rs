@@ -0,0 +1,217 @@
+//! Behavioral conformance tests of OAuth 2.0 authorization servers.
+//!
+//! The checks of this crate exercise an authorization server through its authorization, token and
+//! resource endpoints and compare its responses with the behavior required by [rfc6749], [rfc6750]
+//! and [rfc7636]: error codes, when errors may be redirected, codes being usable only once, PKCE
+//! and the format of token responses.
+//!
+//! The server is reached through the [`Server`] trait, in-process. Frontend authors implement it by
+//! passing the requests through their integration, storage backend authors use the
+//! [`PrimitiveServer`] with their own primitives. In both cases the server must know the
+//! [`clients`] of this crate and approve all authorization requests on behalf of [`OWNER_ID`].
+//!
+//! ```
+//! use oxide_auth::primitives::prelude::*;
+//! use oxide_auth_conformance::{clients, run, PrimitiveServer};
+//!
+//! let report = run(|| {
+//!     let registrar = clients().into_iter().collect::<ClientMap>();
+//!     PrimitiveServer::new(
+//!         registrar,
+//!         AuthMap::new(RandomGenerator::new(16)),
+//!         TokenMap::new(RandomGenerator::new(16)),
+//!     )
+//! });
+//! report.assert_conformant();
+//! ```
+//!
+//! Each check runs against a fresh server, so that the checks do not depend on each other.
+//!
+//! [rfc6749]: https://tools.ietf.org/html/rfc6749
+//! [rfc6750]: https://tools.ietf.org/html/rfc6750
+//! [rfc7636]: https://tools.ietf.org/html/rfc7636
+//! [`Server`]: trait.Server.html
+//! [`PrimitiveServer`]: struct.PrimitiveServer.html
+//! [`clients`]: fn.clients.html
+//! [`OWNER_ID`]: constant.OWNER_ID.html
+#![warn(missing_docs)]
+
+use std::fmt;
+
+use oxide_auth::primitives::registrar::{Client, RegisteredUrl};
+use serde_json::Value;
+use url::Url;
+
+mod checks;
+mod primitives;
+
+pub use primitives::PrimitiveServer;
+
+/// The confidential client of the checks, authenticating with HTTP Basic.
+pub const CLIENT_ID: &str = "conformance-client";
+
+/// The passphrase of the confidential client.
+pub const CLIENT_SECRET: &str = "conformance-secret";
+
+/// The public client of the checks, which uses PKCE.
+pub const PUBLIC_CLIENT_ID: &str = "conformance-public";
+
+/// The redirect uri registered for both clients.
+pub const REDIRECT_URI: &str = "https://client.example/callback";
+
+/// The scope of both clients, required by the protected resource.
+pub const SCOPE: &str = "read";
+
+/// The resource owner approving all authorization requests.
+pub const OWNER_ID: &str = "conformance-owner";
+
+/// An authorization server under test.
+///
+/// Each method performs one request and returns the response. Parameters are given decoded, the
+/// implementation encodes them into the query or the `application/x-www-form-urlencoded` body as
+/// appropriate for its frontend.
+pub trait Server {
+    /// A `GET` request to the authorization endpoint with the query parameters.
+    ///
+    /// The server approves the request on behalf of [`OWNER_ID`] without asking.
+    ///
+    /// [`OWNER_ID`]: constant.OWNER_ID.html
+    fn authorize(&mut self, query: &[(&str, &str)]) -> Exchange;
+
+    /// A `POST` request to the token endpoint, with the body parameters and the `Authorization`
+    /// header if any.
+    fn token(&mut self, body: &[(&str, &str)], authorization: Option<&str>) -> Exchange;
+
+    /// A request to a resource requiring the [`SCOPE`], with the `Authorization` header if any.
+    ///
+    /// A successful request is answered with status 200.
+    ///
+    /// [`SCOPE`]: constant.SCOPE.html
+    fn resource(&mut self, authorization: Option<&str>) -> Exchange;
+}
+
+/// A response of the server.
+#[derive(Clone, Debug, Default)]
+pub struct Exchange {
+    /// The HTTP status code.
+    pub status: u16,
+
+    /// The headers, with their names in any case.
+    pub headers: Vec<(String, String)>,
+
+    /// The body, empty if there is none.
+    pub body: String,
+}
+
+/// The result of one check.
+#[derive(Clone, Debug)]
+pub struct CheckResult {
+    /// A short name of the check.
+    pub name: &'static str,
+
+    /// The section of the specification requiring the checked behavior.
+    pub reference: &'static str,
+
+    /// Whether the server behaved as required, or how it did not.
+    pub outcome: Result<(), String>,
+}
+
+/// The results of all checks.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The results, in the order the checks were run.
+    pub results: Vec<CheckResult>,
+}
+
+/// The clients that the server under test must know.
+///
+/// A confidential client [`CLIENT_ID`] with passphrase [`CLIENT_SECRET`] and a public client
+/// [`PUBLIC_CLIENT_ID`], both with the [`REDIRECT_URI`] and the default scope [`SCOPE`].
+///
+/// [`CLIENT_ID`]: constant.CLIENT_ID.html
+/// [`CLIENT_SECRET`]: constant.CLIENT_SECRET.html
+/// [`PUBLIC_CLIENT_ID`]: constant.PUBLIC_CLIENT_ID.html
+/// [`REDIRECT_URI`]: constant.REDIRECT_URI.html
+/// [`SCOPE`]: constant.SCOPE.html
+pub fn clients() -> Vec<Client> {
+    let redirect_uri = || RegisteredUrl::Semantic(REDIRECT_URI.parse().unwrap());
+    vec![
+        Client::confidential(
+            CLIENT_ID,
+            redirect_uri(),
+            SCOPE.parse().unwrap(),
+            CLIENT_SECRET.as_bytes(),
+        ),
+        Client::public(PUBLIC_CLIENT_ID, redirect_uri(), SCOPE.parse().unwrap()),
+    ]
+}
+
+/// Run all checks, each against a new server.
+pub fn run<S, F>(mut server: F) -> Report
+where
+    S: Server,
+    F: FnMut() -> S,
+{
+    let results = checks::ALL
+        .iter()
+        .map(|check| CheckResult {
+            name: check.name,
+            reference: check.reference,
+            outcome: (check.run)(&mut server()),
+        })
+        .collect();
+    Report { results }
+}
+
+impl Exchange {
+    /// The value of the first header with the name, compared case insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The parsed `Location` header.
+    pub fn location(&self) -> Option<Url> {
+        self.header("Location")?.parse().ok()
+    }
+
+    /// The body parsed as JSON.
+    pub fn json(&self) -> Option<Value> {
+        serde_json::from_str(&self.body).ok()
+    }
+}
+
+impl Report {
+    /// Whether all checks passed.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.outcome.is_ok())
+    }
+
+    /// The checks that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().filter(|result| result.outcome.is_err())
+    }
+
+    /// Panic with the failed checks, if any.
+    pub fn assert_conformant(&self) {
+        if !self.passed() {
+            panic!("{}", self);
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            match &result.outcome {
+                Ok(()) => writeln!(f, "ok      {} ({})", result.name, result.reference)?,
+                Err(reason) => {
+                    writeln!(f, "FAILED  {} ({}): {}", result.name, result.reference, reason)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use oxide_auth::endpoint::{
+    AccessTokenFlow, AuthorizationFlow, OAuthError, OwnerConsent, RefreshFlow, ResourceFlow, Scope,
+    Solicitation,
+};
+use oxide_auth::frontends::simple::endpoint::{Error, FnSolicitor, Generic, Vacant};
+use oxide_auth::frontends::simple::extensions::{AddonList, Extended, Pkce};
+use oxide_auth::frontends::simple::request::{Body, Request, Response, Status};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::issuer::Issuer;
+use oxide_auth::primitives::registrar::Registrar;
+
+use crate::{Exchange, Server, OWNER_ID, SCOPE};
+
+/// A server of the flows of `oxide-auth` over some primitives.
+///
+/// Use this to check a registrar, authorizer or issuer, for example one of a storage backend. The
+/// requests are passed to the flows with the simple request types, PKCE is supported for all
+/// clients and the protected resource requires the [`SCOPE`].
+///
+/// [`SCOPE`]: constant.SCOPE.html
+pub struct PrimitiveServer<R, A, I> {
+    registrar: R,
+    authorizer: A,
+    issuer: I,
+    addons: AddonList,
+}
+
+type Endpoint<'a, R, A, I> = Extended<
+    Generic<
+        &'a R,
+        &'a mut A,
+        &'a mut I,
+        FnSolicitor<fn(&mut Request, Solicitation) -> OwnerConsent<Response>>,
+        Vec<Scope>,
+    >,
+    &'a mut AddonList,
+>;
+
+impl<R, A, I> PrimitiveServer<R, A, I>
+where
+    R: Registrar,
+    A: Authorizer,
+    I: Issuer,
+{
+    /// Serve the flows with the primitives.
+    ///
+    /// The registrar must contain the [`clients`] of the checks.
+    ///
+    /// [`clients`]: fn.clients.html
+    pub fn new(registrar: R, authorizer: A, issuer: I) -> Self {
+        let mut addons = AddonList::new();
+        addons.push_code(Pkce::optional());
+        PrimitiveServer {
+            registrar,
+            authorizer,
+            issuer,
+            addons,
+        }
+    }
+
+    fn endpoint(&mut self) -> Endpoint<'_, R, A, I> {
+        let approve: fn(&mut Request, Solicitation) -> OwnerConsent<Response> =
+            |_, _| OwnerConsent::Authorized(OWNER_ID.to_owned());
+        let generic = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            solicitor: FnSolicitor(approve),
+            scopes: vec![SCOPE.parse().unwrap()],
+            response: Vacant,
+        };
+        Extended::extend_with(generic, &mut self.addons)
+    }
+}
+
+impl<R, A, I> Server for PrimitiveServer<R, A, I>
+where
+    R: Registrar,
+    A: Authorizer,
+    I: Issuer,
+{
+    fn authorize(&mut self, query: &[(&str, &str)]) -> Exchange {
+        let request = Request {
+            query: parameters(query),
+            ..Request::default()
+        };
+        let response =
+            AuthorizationFlow::prepare(self.endpoint()).and_then(|mut flow| flow.execute(request));
+        exchange(response)
+    }
+
+    fn token(&mut self, body: &[(&str, &str)], authorization: Option<&str>) -> Exchange {
+        let request = Request {
+            urlbody: parameters(body),
+            auth: authorization.map(str::to_owned),
+            ..Request::default()
+        };
+
+        let refresh = body
+            .iter()
+            .any(|&(name, value)| name == "grant_type" && value == "refresh_token");
+        let response = if refresh {
+            RefreshFlow::prepare(self.endpoint()).and_then(|mut flow| flow.execute(request))
+        } else {
+            AccessTokenFlow::prepare(self.endpoint()).and_then(|mut flow| flow.execute(request))
+        };
+        exchange(response)
+    }
+
+    fn resource(&mut self, authorization: Option<&str>) -> Exchange {
+        let request = Request {
+            auth: authorization.map(str::to_owned),
+            ..Request::default()
+        };
+        let mut flow = match ResourceFlow::prepare(self.endpoint()) {
+            Ok(flow) => flow,
+            Err(err) => return exchange(Err(err)),
+        };
+        match flow.execute(request) {
+            Ok(grant) => Exchange {
+                status: 200,
+                headers: Vec::new(),
+                body: grant.owner_id,
+            },
+            Err(response) => exchange(response),
+        }
+    }
+}
+
+fn parameters(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+/// The exchange of a response of the simple frontend, or of the error of a flow.
+fn exchange(response: Result<Response, Error<Request>>) -> Exchange {
+    let response = match response {
+        Ok(response) => response,
+        Err(Error::OAuth(OAuthError::PrimitiveError)) => {
+            return Exchange {
+                status: 500,
+                ..Exchange::default()
+            }
+        }
+        Err(_) => {
+            return Exchange {
+                status: 400,
+                ..Exchange::default()
+            }
+        }
+    };
+
+    let status = match response.status {
+        Status::Ok => 200,
+        Status::Redirect => 302,
+        Status::BadRequest => 400,
+        Status::Unauthorized => 401,
+        Status::Forbidden => 403,
+        Status::TooManyRequests => 429,
+    };
+
+    let mut headers = response.headers;
+    if let Some(location) = response.location {
+        headers.push(("Location".to_owned(), location.to_string()));
+    }
+    if let Some(challenge) = response.www_authenticate {
+        headers.push(("WWW-Authenticate".to_owned(), challenge));
+    }
+
+    let body = match response.body {
+        Some(Body::Text(text)) => {
+            headers.push(("Content-Type".to_owned(), "text/plain".to_owned()));
+            text
+        }
+        Some(Body::Json(json)) => {
+            headers.push(("Content-Type".to_owned(), "application/json".to_owned()));
+            json
+        }
+        None => String::new(),
+    };
+
+    Exchange {
+        status,
+        headers,
+        body,
+    }
+}
@@ -0,0 +1,15 @@
+use oxide_auth::primitives::prelude::*;
+use oxide_auth_conformance::{clients, run, PrimitiveServer};
+
+#[test]
+fn memory_primitives_conform() {
+    let report = run(|| {
+        let registrar = clients().into_iter().collect::<ClientMap>();
+        PrimitiveServer::new(
+            registrar,
+            AuthMap::new(RandomGenerator::new(16)),
+            TokenMap::new(RandomGenerator::new(16)),
+        )
+    });
+    report.assert_conformant();
+}
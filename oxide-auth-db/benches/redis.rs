@@ -0,0 +1,96 @@
+//! Benchmarks of the round trips to Redis.
+//!
+//! Requires a Redis server at `REDIS_URL`, `redis://localhost/3` by default, and writes clients
+//! under the `bench` tenant. Run with `cargo bench -p oxide-auth-db`. The `per_key` functions
+//! issue one command per client as the datasource did before batching its commands, for
+//! comparison with the batched operations of the datasource.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use oxide_auth::primitives::registrar::{Argon2, Client, ExactUrl, RegisteredUrl};
+use oxide_auth_db::db_service::redis::RedisDataSource;
+use oxide_auth_db::db_service::TenantScoped;
+use oxide_auth_db::primitives::db_registrar::OauthClientDBRepository;
+use r2d2_redis::r2d2::Pool;
+use r2d2_redis::redis::Commands;
+use r2d2_redis::RedisConnectionManager;
+
+const CLIENT_PREFIX: &str = "client:";
+
+fn datasource() -> Option<RedisDataSource> {
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost/3".to_owned());
+    let datasource = RedisDataSource::new(url, 4, CLIENT_PREFIX.to_owned())
+        .map_err(anyhow::Error::from)
+        .and_then(|datasource| {
+            let datasource = datasource.for_tenant("bench");
+            datasource.health_check()?;
+            Ok(datasource)
+        });
+
+    match datasource {
+        Ok(datasource) => Some(datasource),
+        Err(err) => {
+            eprintln!(
+                "Skipping the Redis benchmarks, the server is unavailable: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+fn register(datasource: &RedisDataSource, count: usize) {
+    let policy = Argon2::default();
+    let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+    for index in 0..count {
+        let client = Client::public(
+            &format!("BenchClient{}", index),
+            RegisteredUrl::from(url.clone()),
+            "default".parse().unwrap(),
+        );
+        datasource
+            .regist_from_encoded_client(client.encode(&policy))
+            .unwrap();
+    }
+}
+
+fn clear(pool: &Pool<RedisConnectionManager>, pattern: &str) {
+    let mut conn = pool.get().unwrap();
+    let keys: Vec<String> = conn.keys(pattern).unwrap();
+    if !keys.is_empty() {
+        conn.del::<_, ()>(keys).unwrap();
+    }
+}
+
+fn list_clients(c: &mut Criterion) {
+    let datasource = match datasource() {
+        Some(datasource) => datasource,
+        None => return,
+    };
+
+    let mut group = c.benchmark_group("redis_list");
+    let pattern = format!("tenant:bench:{}*", CLIENT_PREFIX);
+    let pool = datasource.get_pool();
+
+    for count in [10, 100] {
+        clear(&pool, &pattern);
+        register(&datasource, count);
+
+        group.bench_with_input(BenchmarkId::new("per_key", count), &count, |b, _| {
+            b.iter(|| {
+                let mut conn = pool.get().unwrap();
+                let keys: Vec<String> = conn.keys(&pattern).unwrap();
+                keys.iter().map(|key| conn.get::<_, String>(key).unwrap()).count()
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("batched", count), &count, |b, _| {
+            b.iter(|| datasource.list().unwrap().len())
+        });
+    }
+
+    clear(&pool, &pattern);
+    group.finish();
+}
+
+criterion_group!(benches, list_clients);
+criterion_main!(benches);
@@ -0,0 +1,160 @@
+//! Append-only audit log of decided grants.
+//!
+//! The flows of `oxide-auth` report every issued, refused or failed code, token and refresh to the
+//! [`Outbox`] of their endpoint. [`AuditOutbox`] adapts such reports into [`AuditEntry`] rows and
+//! appends them to an [`AuditLog`]. The Redis datasource writes into a stream, the SQL stores into
+//! the `oauth_audit_log` table created by the migrations.
+use chrono::Utc;
+use oxide_auth::endpoint::{GrantEvent, GrantOutcome, GrantRecord, Outbox, WebRequest};
+use serde::{Deserialize, Serialize};
+
+/// Appends a single audit entry.
+///
+/// The first parameter is the tenant, followed by the fields of [`AuditEntry`] in order.
+pub const INSERT_AUDIT: &str = "INSERT INTO oauth_audit_log \
+    (tenant_id, occurred_at, event, outcome, client_id, owner_id, scope, remote_addr) \
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+
+/// A single row of the audit log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the flow concluded, in milliseconds since the unix epoch.
+    pub occurred_at: i64,
+
+    /// One of `code`, `token`, `refresh` or `revocation`.
+    pub event: String,
+
+    /// One of `issued`, `denied` or `failed`.
+    pub outcome: String,
+
+    /// The client that made the request, if it was identified.
+    pub client_id: Option<String>,
+
+    /// The resource owner, if known.
+    pub owner_id: Option<String>,
+
+    /// The granted or requested scope.
+    pub scope: Option<String>,
+
+    /// The address of the requesting party.
+    pub remote_addr: Option<String>,
+}
+
+/// Storage accepting audit entries.
+///
+/// Implementations must only ever append, existing entries are never changed.
+pub trait AuditLog {
+    /// Append an entry to the log.
+    fn append(&self, entry: &AuditEntry) -> anyhow::Result<()>;
+}
+
+/// An outbox for endpoints which appends every record to an audit log.
+///
+/// The remote address is determined from the request by a function, as `WebRequest` does not
+/// expose it. Failures to append are logged and do not affect the response of the flow.
+pub struct AuditOutbox<L, F> {
+    log: L,
+    remote_addr: F,
+}
+
+impl AuditEntry {
+    /// Create an entry for a record, occurring now.
+    pub fn new(record: &GrantRecord, remote_addr: Option<String>) -> Self {
+        AuditEntry {
+            occurred_at: Utc::now().timestamp_millis(),
+            event: event_name(record.event).to_owned(),
+            outcome: outcome_name(record.outcome).to_owned(),
+            client_id: record.client_id.clone(),
+            owner_id: record.owner_id.clone(),
+            scope: record.scope.as_ref().map(ToString::to_string),
+            remote_addr,
+        }
+    }
+}
+
+impl<L: AuditLog, F> AuditOutbox<L, F> {
+    /// Append to `log`, extracting the remote address of each request with `remote_addr`.
+    pub fn new(log: L, remote_addr: F) -> Self {
+        AuditOutbox { log, remote_addr }
+    }
+
+    /// The underlying log.
+    pub fn log(&self) -> &L {
+        &self.log
+    }
+}
+
+impl<R, L, F> Outbox<R> for AuditOutbox<L, F>
+where
+    R: WebRequest,
+    L: AuditLog,
+    F: FnMut(&mut R) -> Option<String>,
+{
+    fn record(&mut self, request: &mut R, record: GrantRecord) {
+        let entry = AuditEntry::new(&record, (self.remote_addr)(request));
+        if let Err(err) = self.log.append(&entry) {
+            log::error!("Failed to append to audit log: {:?}", err);
+        }
+    }
+}
+
+impl<L: AuditLog + ?Sized> AuditLog for &L {
+    fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        (**self).append(entry)
+    }
+}
+
+fn event_name(event: GrantEvent) -> &'static str {
+    match event {
+        GrantEvent::Code => "code",
+        GrantEvent::Token => "token",
+        GrantEvent::Refresh => "refresh",
+        GrantEvent::Revocation => "revocation",
+    }
+}
+
+fn outcome_name(outcome: GrantOutcome) -> &'static str {
+    match outcome {
+        GrantOutcome::Issued => "issued",
+        GrantOutcome::Denied => "denied",
+        GrantOutcome::Failed => "failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use oxide_auth::frontends::simple::request::Request;
+
+    #[derive(Default)]
+    struct Memory(Mutex<Vec<AuditEntry>>);
+
+    impl AuditLog for Memory {
+        fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(entry.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn records_into_log() {
+        let memory = Memory::default();
+        let mut outbox = AuditOutbox::new(&memory, |_: &mut Request| Some("192.0.2.1".to_owned()));
+        let record = GrantRecord {
+            client_id: Some("client".into()),
+            owner_id: Some("owner".into()),
+            scope: Some("read".parse().unwrap()),
+            ..GrantRecord::new(GrantEvent::Refresh, GrantOutcome::Issued)
+        };
+        outbox.record(&mut Request::default(), record);
+
+        let entries = memory.0.lock().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event, "refresh");
+        assert_eq!(entries[0].outcome, "issued");
+        assert_eq!(entries[0].scope.as_deref(), Some("read"));
+        assert_eq!(entries[0].remote_addr.as_deref(), Some("192.0.2.1"));
+    }
+}
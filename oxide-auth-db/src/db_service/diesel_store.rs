@@ -0,0 +1,1217 @@
+//! A synchronous storage for clients, grants and tokens using `diesel` with `r2d2` pooling.
+//!
+//! This is suited to servers such as `actix-web` deployments that use blocking database access,
+//! it implements the primitive traits of `oxide-auth` directly. The store is available for
+//! Postgres with the `diesel-postgres` feature and for SQLite with the `diesel-sqlite` feature. The
+//! tables are the same as those of the other SQL stores of this crate and are created with
+//! `migrate`. Each store operates within one tenant, see [`TenantScoped`].
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection, R2D2Connection};
+use diesel::sql_types::{BigInt, Nullable, Text};
+use once_cell::sync::Lazy;
+use oxide_auth::frontends::sweep::Expiring;
+use oxide_auth::primitives::generator::{RandomGenerator, TagGrant};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenType};
+use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
+use oxide_auth::primitives::registrar::{
+    Argon2, BoundClient, Client, ClientAuthMethod, EncodedClient, GrantType, PasswordPolicy,
+    RefreshPolicy, RegisteredClient, Registrar, RegistrarError,
+};
+use oxide_auth::primitives::authorizer::{Authorizer, GuardError, SingleUseGuard};
+use oxide_auth::primitives::issuer::Issuer;
+
+use crate::db_service::audit::{self, AuditEntry, AuditLog};
+use crate::db_service::encryption::{self, ValueCipher};
+use crate::db_service::migration::{self, Dialect};
+use crate::db_service::stored::{bind_redirect, StoredClient, StoredGrant};
+use crate::db_service::transfer::{Export, Import, Record, Records};
+use crate::db_service::{PoolConfig, TenantScoped, DEFAULT_TENANT};
+
+/// Length in bytes of generated codes and tokens.
+const TOKEN_LENGTH: usize = 16;
+
+static DEFAULT_PASSWORD_POLICY: Lazy<Argon2> = Lazy::new(Argon2::default);
+
+/// Table definitions used by the store.
+pub mod schema {
+    diesel::table! {
+        oauth_clients (tenant_id, client_id) {
+            tenant_id -> Text,
+            client_id -> Text,
+            redirect_uri -> Text,
+            additional_redirect_uris -> Text,
+            default_scope -> Text,
+            client_secret -> Nullable<Text>,
+            settings -> Nullable<Text>,
+            disabled -> BigInt,
+        }
+    }
+
+    diesel::table! {
+        oauth_grants (code) {
+            code -> Text,
+            tenant_id -> Text,
+            grant_data -> Text,
+            expires_at -> BigInt,
+        }
+    }
+
+    diesel::table! {
+        oauth_tokens (access_token) {
+            access_token -> Text,
+            tenant_id -> Text,
+            refresh_token -> Nullable<Text>,
+            grant_data -> Text,
+            expires_at -> BigInt,
+        }
+    }
+
+    diesel::table! {
+        oauth_used_codes (tenant_id, code) {
+            tenant_id -> Text,
+            code -> Text,
+            expires_at -> BigInt,
+        }
+    }
+}
+
+use self::schema::{oauth_clients, oauth_grants, oauth_tokens, oauth_used_codes};
+
+#[derive(Queryable, Insertable, AsChangeset)]
+#[diesel(
+    table_name = oauth_clients,
+    primary_key(tenant_id, client_id),
+    treat_none_as_null = true
+)]
+struct ClientRow {
+    tenant_id: String,
+    client_id: String,
+    redirect_uri: String,
+    additional_redirect_uris: String,
+    default_scope: String,
+    client_secret: Option<String>,
+    settings: Option<String>,
+    disabled: i64,
+}
+
+#[derive(QueryableByName)]
+struct VersionRow {
+    #[diesel(sql_type = BigInt)]
+    version: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = oauth_grants)]
+struct GrantRow {
+    code: String,
+    tenant_id: String,
+    grant_data: String,
+    expires_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = oauth_tokens)]
+struct TokenRow {
+    access_token: String,
+    tenant_id: String,
+    refresh_token: Option<String>,
+    grant_data: String,
+    expires_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = oauth_used_codes)]
+struct ClaimRow<'a> {
+    tenant_id: &'a str,
+    code: &'a str,
+    expires_at: i64,
+}
+
+/// Clients, grants and tokens stored through a pool of `diesel` connections.
+pub struct DieselStore<C: R2D2Connection + 'static> {
+    pool: Pool<ConnectionManager<C>>,
+    tenant: String,
+    password_policy: Arc<dyn PasswordPolicy>,
+    cipher: Option<Arc<dyn ValueCipher>>,
+    generator: RandomGenerator,
+    usage: u64,
+    token_duration: Duration,
+}
+
+impl<C: R2D2Connection + 'static> DieselStore<C> {
+    /// Connect to the database at `url`.
+    pub fn new(url: &str, config: PoolConfig) -> Result<Self, diesel::r2d2::PoolError> {
+        let pool = Pool::builder()
+            .max_size(config.max_size)
+            .min_idle(config.min_idle)
+            .connection_timeout(config.connection_timeout)
+            .idle_timeout(config.idle_timeout)
+            .max_lifetime(config.max_lifetime)
+            .test_on_check_out(config.test_on_check_out)
+            .build(ConnectionManager::new(url))?;
+        Ok(DieselStore::with_pool(pool))
+    }
+
+    /// Use an existing connection pool, in the default tenant.
+    pub fn with_pool(pool: Pool<ConnectionManager<C>>) -> Self {
+        DieselStore {
+            pool,
+            tenant: DEFAULT_TENANT.to_owned(),
+            password_policy: Arc::new(DEFAULT_PASSWORD_POLICY.clone()),
+            cipher: None,
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: Duration::hours(1),
+        }
+    }
+
+    /// The underlying connection pool.
+    pub fn pool(&self) -> &Pool<ConnectionManager<C>> {
+        &self.pool
+    }
+
+    /// Change how passwords are encoded while stored.
+    pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
+        self.password_policy = Arc::new(new_policy);
+    }
+
+    /// Encrypt client secrets and grants before they are stored.
+    ///
+    /// Access and refresh tokens are stored as their `ValueCipher::lookup_key` instead, tokens
+    /// issued before the cipher was set are no longer found. Secrets and grants stored before
+    /// remain readable.
+    pub fn set_cipher<V: ValueCipher + 'static>(&mut self, cipher: V) {
+        self.cipher = Some(Arc::new(cipher));
+    }
+
+    /// Set the validity of all issued tokens, one hour by default.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.token_duration = duration;
+    }
+
+    fn connection(&self) -> Result<PooledConnection<ConnectionManager<C>>, ()> {
+        self.pool.get().map_err(|_| ())
+    }
+
+    fn tag(&mut self, grant: &Grant) -> Result<String, ()> {
+        let tag = self.generator.tag(self.usage, grant)?;
+        self.usage = self.usage.wrapping_add(1);
+        Ok(tag)
+    }
+
+    /// Prepare a new token row, extending the validity of the grant.
+    ///
+    /// Returns the row along with the issued access and refresh token.
+    fn new_token(&mut self, mut grant: Grant) -> Result<(TokenRow, String, String, Grant), ()> {
+        grant.until = Utc::now() + self.token_duration;
+        let access = self.tag(&grant)?;
+        let refresh = self.tag(&grant)?;
+        let row = TokenRow {
+            access_token: self.lookup_key(&access)?,
+            tenant_id: self.tenant.clone(),
+            refresh_token: Some(self.lookup_key(&refresh)?),
+            grant_data: self.encode_grant(&grant)?,
+            expires_at: grant.until.timestamp_millis(),
+        };
+        Ok((row, access, refresh, grant))
+    }
+
+    /// The stored form of an access or refresh token.
+    fn lookup_key(&self, token: &str) -> Result<String, ()> {
+        encryption::lookup_key(self.cipher.as_deref(), token).map_err(|_| ())
+    }
+
+    fn seal_grant(&self, grant: &StoredGrant) -> anyhow::Result<String> {
+        encryption::seal(self.cipher.as_deref(), serde_json::to_string(grant)?)
+    }
+
+    fn open_grant(&self, data: String) -> anyhow::Result<StoredGrant> {
+        let data = encryption::open(self.cipher.as_deref(), data)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    fn encode_grant(&self, grant: &Grant) -> Result<String, ()> {
+        self.seal_grant(&StoredGrant::from(grant)).map_err(|_| ())
+    }
+
+    fn decode_grant(&self, data: String) -> Result<Grant, ()> {
+        let stored = self.open_grant(data).map_err(|_| ())?;
+        stored.into_grant().map_err(|_| ())
+    }
+
+    fn client_row(&self, mut client: StoredClient) -> anyhow::Result<ClientRow> {
+        let cipher = self.cipher.as_deref();
+        client.client_secret = client
+            .client_secret
+            .map(|secret| encryption::seal(cipher, secret))
+            .transpose()?;
+        Ok(ClientRow::new(&self.tenant, client))
+    }
+
+    fn open_client(&self, row: ClientRow) -> anyhow::Result<StoredClient> {
+        let mut client = StoredClient::from(row);
+        let cipher = self.cipher.as_deref();
+        client.client_secret = client
+            .client_secret
+            .map(|secret| encryption::open(cipher, secret))
+            .transpose()?;
+        Ok(client)
+    }
+
+    /// The keys of the rows whose grant was issued to a client, and to an owner if one is given.
+    ///
+    /// Rows that can not be decoded are kept.
+    fn issued_to(
+        &self, rows: Vec<(String, String)>, client_id: &str, owner_id: Option<&str>,
+    ) -> Vec<String> {
+        rows.into_iter()
+            .filter(|(_, data)| {
+                self.open_grant(data.clone())
+                    .is_ok_and(|stored| stored.issued_to(client_id, owner_id))
+            })
+            .map(|(key, _)| key)
+            .collect()
+    }
+}
+
+impl<C: R2D2Connection + 'static> Clone for DieselStore<C> {
+    fn clone(&self) -> Self {
+        DieselStore {
+            pool: self.pool.clone(),
+            tenant: self.tenant.clone(),
+            password_policy: self.password_policy.clone(),
+            cipher: self.cipher.clone(),
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: self.token_duration,
+        }
+    }
+}
+
+impl<C: R2D2Connection + 'static> TenantScoped for DieselStore<C> {
+    fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    fn for_tenant(&self, tenant: &str) -> Self {
+        DieselStore {
+            tenant: tenant.to_owned(),
+            ..self.clone()
+        }
+    }
+}
+
+impl ClientRow {
+    fn new(tenant: &str, client: StoredClient) -> Self {
+        ClientRow {
+            tenant_id: tenant.to_owned(),
+            client_id: client.client_id,
+            redirect_uri: client.redirect_uri,
+            additional_redirect_uris: client.additional_redirect_uris,
+            default_scope: client.default_scope,
+            client_secret: client.client_secret,
+            settings: client.settings,
+            disabled: i64::from(client.disabled),
+        }
+    }
+}
+
+impl From<ClientRow> for StoredClient {
+    fn from(row: ClientRow) -> Self {
+        StoredClient {
+            client_id: row.client_id,
+            redirect_uri: row.redirect_uri,
+            additional_redirect_uris: row.additional_redirect_uris,
+            default_scope: row.default_scope,
+            client_secret: row.client_secret,
+            settings: row.settings,
+            disabled: row.disabled != 0,
+        }
+    }
+}
+
+/// Implements the primitives for a concrete connection type.
+///
+/// The query builder of `diesel` resolves most of its bounds per backend, which makes a single
+/// generic implementation impractical.
+macro_rules! diesel_store {
+    ($connection:ty, $dialect:expr) => {
+        impl DieselStore<$connection> {
+            /// Apply all pending schema migrations.
+            ///
+            /// Fails if the database has been migrated by a newer version of this crate. Concurrent
+            /// calls of several replicas wait for each other on PostgreSQL and MySQL, see
+            /// `Dialect::migration_lock`.
+            pub fn migrate(&self) -> anyhow::Result<()> {
+                let mut conn = self.pool.get()?;
+                let (lock, unlock) = match $dialect.migration_lock() {
+                    Some(lock) => lock,
+                    None => return Self::migrate_on(&mut conn),
+                };
+
+                diesel::sql_query(lock).execute(&mut conn)?;
+                let migrated = Self::migrate_on(&mut conn);
+                let unlocked = diesel::sql_query(unlock).execute(&mut conn);
+                migrated?;
+                unlocked?;
+                Ok(())
+            }
+
+            fn migrate_on(conn: &mut $connection) -> anyhow::Result<()> {
+                diesel::sql_query(migration::CREATE_VERSION_TABLE).execute(conn)?;
+                let insert_version = $dialect.placeholders(migration::INSERT_VERSION);
+
+                // Read only now, another replica may have migrated while this one waited.
+                let found = Self::current_version(conn)?;
+                for step in migration::pending($dialect, found)? {
+                    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                        for statement in step.statements {
+                            diesel::sql_query(*statement).execute(conn)?;
+                        }
+
+                        diesel::sql_query(insert_version.as_str())
+                            .bind::<BigInt, _>(step.version)
+                            .bind::<Text, _>(step.description)
+                            .execute(conn)?;
+                        Ok(())
+                    })?;
+                }
+
+                Ok(())
+            }
+
+            /// Check that a connection can be obtained and answers queries.
+            pub fn health_check(&self) -> anyhow::Result<()> {
+                let mut conn = self.pool.get()?;
+                diesel::sql_query("SELECT 1").execute(&mut conn)?;
+                Ok(())
+            }
+
+            /// Refuse to work with a database whose schema version is not the expected one.
+            ///
+            /// Call this during startup, before serving any requests.
+            pub fn verify_schema(&self) -> anyhow::Result<()> {
+                migration::verify(self.schema_version()?)?;
+                Ok(())
+            }
+
+            /// The current version of the schema, 0 for a database without any migrations.
+            pub fn schema_version(&self) -> anyhow::Result<i64> {
+                let mut conn = self.pool.get()?;
+                Self::current_version(&mut conn)
+            }
+
+            fn current_version(conn: &mut $connection) -> anyhow::Result<i64> {
+                match diesel::sql_query(migration::SELECT_VERSION).load::<VersionRow>(conn) {
+                    Ok(rows) => Ok(rows.first().map_or(0, |row| row.version)),
+                    // The table of migrations does not exist yet.
+                    Err(diesel::result::Error::DatabaseError(..)) => Ok(0),
+                    Err(err) => Err(err.into()),
+                }
+            }
+
+            /// Remove all expired codes and tokens of the tenant, returning how many were removed.
+            ///
+            /// Refresh tokens are removed along with their access token.
+            pub fn purge_expired(&self) -> anyhow::Result<usize> {
+                self.purge_expired_at(Utc::now())
+            }
+
+            fn purge_expired_at(&self, now: DateTime<Utc>) -> anyhow::Result<usize> {
+                let now = now.timestamp_millis();
+                let mut conn = self.pool.get()?;
+                let tenant = &self.tenant;
+                let purged = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let grants = diesel::delete(
+                        oauth_grants::table
+                            .filter(oauth_grants::tenant_id.eq(tenant))
+                            .filter(oauth_grants::expires_at.le(now)),
+                    )
+                    .execute(conn)?;
+                    let tokens = diesel::delete(
+                        oauth_tokens::table
+                            .filter(oauth_tokens::tenant_id.eq(tenant))
+                            .filter(oauth_tokens::expires_at.le(now)),
+                    )
+                    .execute(conn)?;
+                    // Claims are no codes of their own and are not counted.
+                    diesel::delete(
+                        oauth_used_codes::table
+                            .filter(oauth_used_codes::tenant_id.eq(tenant))
+                            .filter(oauth_used_codes::expires_at.le(now)),
+                    )
+                    .execute(conn)?;
+                    Ok(grants + tokens)
+                })?;
+                Ok(purged)
+            }
+
+            /// Revoke an access or refresh token of the tenant, along with the token issued with it.
+            ///
+            /// Returns whether any token was revoked.
+            pub fn revoke_token(&self, token: &str) -> anyhow::Result<bool> {
+                let key = encryption::lookup_key(self.cipher.as_deref(), token)?;
+                let mut conn = self.pool.get()?;
+                // Both tokens of a pair share a row.
+                let revoked = diesel::delete(
+                    oauth_tokens::table
+                        .filter(oauth_tokens::tenant_id.eq(&self.tenant))
+                        .filter(
+                            oauth_tokens::access_token
+                                .eq(&key)
+                                .or(oauth_tokens::refresh_token.eq(&key)),
+                        ),
+                )
+                .execute(&mut conn)?;
+                Ok(revoked > 0)
+            }
+
+            /// Insert or update the client record.
+            pub fn register_client(&self, client: Client) -> Result<(), RegistrarError> {
+                let encoded = client.encode(&*self.password_policy);
+                let stored =
+                    StoredClient::from_encoded(&encoded).map_err(|_| RegistrarError::PrimitiveError)?;
+                let row = self
+                    .client_row(stored)
+                    .map_err(|_| RegistrarError::PrimitiveError)?;
+                let mut conn = self
+                    .connection()
+                    .map_err(|_| RegistrarError::PrimitiveError)?;
+
+                diesel::insert_into(oauth_clients::table)
+                    .values(&row)
+                    .on_conflict((oauth_clients::tenant_id, oauth_clients::client_id))
+                    .do_update()
+                    .set(&row)
+                    .execute(&mut conn)
+                    .map_err(|_| RegistrarError::PrimitiveError)?;
+                Ok(())
+            }
+
+            fn client(&self, client_id: &str) -> Result<EncodedClient, RegistrarError> {
+                let mut conn = self
+                    .connection()
+                    .map_err(|_| RegistrarError::PrimitiveError)?;
+                let row = oauth_clients::table
+                    .find((&self.tenant, client_id))
+                    .first::<ClientRow>(&mut conn)
+                    .optional()
+                    .map_err(|_| RegistrarError::PrimitiveError)?
+                    .ok_or(RegistrarError::Unspecified)?;
+
+                self.open_client(row)
+                    .and_then(StoredClient::into_encoded)
+                    .map_err(|_| RegistrarError::PrimitiveError)
+            }
+
+            fn find_grant(
+                &self, token: oauth_tokens::BoxedQuery<'_, <$connection as Connection>::Backend>,
+            ) -> Result<Option<Grant>, ()> {
+                let mut conn = self.connection()?;
+                let data = token
+                    .filter(oauth_tokens::tenant_id.eq(&self.tenant))
+                    .select(oauth_tokens::grant_data)
+                    .first::<String>(&mut conn)
+                    .optional()
+                    .map_err(|_| ())?;
+                data.map(|data| self.decode_grant(data)).transpose()
+            }
+
+            fn delete_client_grants(
+                &self, client_id: &str, owner_id: Option<&str>,
+            ) -> Result<usize, ()> {
+                let mut conn = self.connection()?;
+                let tenant = &self.tenant;
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let rows = oauth_grants::table
+                        .filter(oauth_grants::tenant_id.eq(tenant))
+                        .select((oauth_grants::code, oauth_grants::grant_data))
+                        .load::<(String, String)>(conn)?;
+                    let codes = self.issued_to(rows, client_id, owner_id);
+                    diesel::delete(
+                        oauth_grants::table
+                            .filter(oauth_grants::tenant_id.eq(tenant))
+                            .filter(oauth_grants::code.eq_any(codes)),
+                    )
+                    .execute(conn)
+                })
+                .map_err(|_| ())
+            }
+
+            fn delete_client_tokens(
+                &self, client_id: &str, owner_id: Option<&str>,
+            ) -> Result<usize, ()> {
+                let mut conn = self.connection()?;
+                let tenant = &self.tenant;
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let rows = oauth_tokens::table
+                        .filter(oauth_tokens::tenant_id.eq(tenant))
+                        .select((oauth_tokens::access_token, oauth_tokens::grant_data))
+                        .load::<(String, String)>(conn)?;
+                    let tokens = self.issued_to(rows, client_id, owner_id);
+                    diesel::delete(
+                        oauth_tokens::table
+                            .filter(oauth_tokens::tenant_id.eq(tenant))
+                            .filter(oauth_tokens::access_token.eq_any(tokens)),
+                    )
+                    .execute(conn)
+                })
+                .map_err(|_| ())
+            }
+        }
+
+        impl AuditLog for DieselStore<$connection> {
+            fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+                let mut conn = self.pool.get()?;
+                diesel::sql_query($dialect.placeholders(audit::INSERT_AUDIT))
+                    .bind::<Text, _>(&self.tenant)
+                    .bind::<BigInt, _>(entry.occurred_at)
+                    .bind::<Text, _>(&entry.event)
+                    .bind::<Text, _>(&entry.outcome)
+                    .bind::<Nullable<Text>, _>(&entry.client_id)
+                    .bind::<Nullable<Text>, _>(&entry.owner_id)
+                    .bind::<Nullable<Text>, _>(&entry.scope)
+                    .bind::<Nullable<Text>, _>(&entry.remote_addr)
+                    .execute(&mut conn)?;
+                Ok(())
+            }
+        }
+
+        impl Expiring for DieselStore<$connection> {
+            fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+                self.purge_expired_at(now).map_err(|_| ())
+            }
+        }
+
+        impl Export for DieselStore<$connection> {
+            fn export(&self) -> anyhow::Result<Records<'_>> {
+                let mut conn = self.pool.get()?;
+                let clients = oauth_clients::table
+                    .filter(oauth_clients::tenant_id.eq(&self.tenant))
+                    .load::<ClientRow>(&mut conn)?;
+                let grants = oauth_grants::table
+                    .filter(oauth_grants::tenant_id.eq(&self.tenant))
+                    .select((oauth_grants::code, oauth_grants::grant_data))
+                    .load::<(String, String)>(&mut conn)?;
+                let tokens = oauth_tokens::table
+                    .filter(oauth_tokens::tenant_id.eq(&self.tenant))
+                    .select((
+                        oauth_tokens::access_token,
+                        oauth_tokens::refresh_token,
+                        oauth_tokens::grant_data,
+                    ))
+                    .load::<(String, Option<String>, String)>(&mut conn)?;
+
+                let clients = clients
+                    .into_iter()
+                    .map(move |row| Ok(Record::Client(self.open_client(row)?)));
+                let grants = grants.into_iter().map(move |(code, data)| {
+                    let grant = self.open_grant(data)?;
+                    Ok(Record::Grant { code, grant })
+                });
+                let tokens = tokens.into_iter().map(move |(access, refresh, data)| {
+                    let grant = self.open_grant(data)?;
+                    Ok(Record::Token {
+                        access,
+                        refresh,
+                        grant,
+                    })
+                });
+                Ok(Box::new(clients.chain(grants).chain(tokens)))
+            }
+        }
+
+        impl Import for DieselStore<$connection> {
+            fn import(&mut self, record: Record) -> anyhow::Result<()> {
+                let mut conn = self.pool.get()?;
+                match self.stored_form(record)? {
+                    Record::Client(client) => {
+                        let row = self.client_row(client)?;
+                        diesel::insert_into(oauth_clients::table)
+                            .values(&row)
+                            .on_conflict((oauth_clients::tenant_id, oauth_clients::client_id))
+                            .do_update()
+                            .set(&row)
+                            .execute(&mut conn)?;
+                    }
+                    Record::Grant { code, grant } => {
+                        let row = GrantRow {
+                            tenant_id: self.tenant.clone(),
+                            grant_data: self.seal_grant(&grant)?,
+                            expires_at: grant.expires_at(),
+                            code,
+                        };
+                        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                            diesel::delete(oauth_grants::table.find(&row.code)).execute(conn)?;
+                            diesel::insert_into(oauth_grants::table)
+                                .values(&row)
+                                .execute(conn)
+                        })?;
+                    }
+                    Record::Token {
+                        access,
+                        refresh,
+                        grant,
+                    } => {
+                        let row = TokenRow {
+                            tenant_id: self.tenant.clone(),
+                            refresh_token: refresh,
+                            grant_data: self.seal_grant(&grant)?,
+                            expires_at: grant.expires_at(),
+                            access_token: access,
+                        };
+                        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                            diesel::delete(oauth_tokens::table.find(&row.access_token)).execute(conn)?;
+                            diesel::insert_into(oauth_tokens::table)
+                                .values(&row)
+                                .execute(conn)
+                        })?;
+                    }
+                }
+                Ok(())
+            }
+
+            fn stored_form(&self, record: Record) -> anyhow::Result<Record> {
+                record.with_lookup_keys(self.cipher.as_deref())
+            }
+        }
+
+        impl Registrar for DieselStore<$connection> {
+            fn bound_redirect<'a>(
+                &self, bound: ClientUrl<'a>,
+            ) -> Result<BoundClient<'a>, RegistrarError> {
+                let client = self.client(&bound.client_id)?;
+                bind_redirect(client, bound)
+            }
+
+            fn negotiate<'a>(
+                &self, bound: BoundClient<'a>, _scope: Option<Scope>,
+            ) -> Result<PreGrant, RegistrarError> {
+                let client = self.client(&bound.client_id)?;
+                Ok(PreGrant {
+                    client_id: bound.client_id.into_owned(),
+                    redirect_uri: bound.redirect_uri.into_owned(),
+                    scope: client.default_scope,
+                })
+            }
+
+            fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+                let client = self.client(client_id)?;
+                RegisteredClient::new(&client, &*self.password_policy).check_authentication(passphrase)
+            }
+
+            fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+                self.client(client_id)
+                    .map(|client| client.refresh_policy)
+                    .unwrap_or_default()
+            }
+
+            fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+                self.client(client_id)
+                    .is_ok_and(|client| client.permits_grant(grant_type))
+            }
+
+            fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+                self.client(client_id).ok()?.auth_method
+            }
+        }
+
+        impl Authorizer for DieselStore<$connection> {
+            fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+                let code = self.tag(&grant)?;
+                let row = GrantRow {
+                    code: code.clone(),
+                    tenant_id: self.tenant.clone(),
+                    grant_data: self.encode_grant(&grant)?,
+                    expires_at: grant.until.timestamp_millis(),
+                };
+
+                let mut conn = self.connection()?;
+                diesel::insert_into(oauth_grants::table)
+                    .values(&row)
+                    .execute(&mut conn)
+                    .map_err(|_| ())?;
+                Ok(code)
+            }
+
+            fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+                let mut conn = self.connection()?;
+                let tenant = &self.tenant;
+                let data = conn
+                    .transaction::<_, diesel::result::Error, _>(|conn| {
+                        let grant = oauth_grants::table
+                            .find(code)
+                            .filter(oauth_grants::tenant_id.eq(tenant));
+                        let data = grant
+                            .select(oauth_grants::grant_data)
+                            .first::<String>(conn)
+                            .optional()?;
+                        // Only the request that actually deletes the code may use it.
+                        let deleted = diesel::delete(grant).execute(conn)?;
+                        Ok(data.filter(|_| deleted == 1))
+                    })
+                    .map_err(|_| ())?;
+
+                data.map(|data| self.decode_grant(data)).transpose()
+            }
+
+            fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+                self.delete_client_grants(client_id, None)
+            }
+
+            fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+                self.delete_client_grants(client_id, Some(owner_id))
+            }
+        }
+
+        /// Claims are rows of the `oauth_used_codes` table, inserted only if absent.
+        ///
+        /// Guards another authorizer through `SingleUse`, the codes of the store itself are already
+        /// redeemed atomically. Claims are removed by `purge_expired` once their code expired.
+        impl SingleUseGuard for DieselStore<$connection> {
+            fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+                let mut conn = self.connection().map_err(|_| GuardError)?;
+                let row = ClaimRow {
+                    tenant_id: &self.tenant,
+                    code,
+                    expires_at: until.timestamp_millis(),
+                };
+
+                let inserted = diesel::insert_into(oauth_used_codes::table)
+                    .values(&row)
+                    .on_conflict_do_nothing()
+                    .execute(&mut conn)
+                    .map_err(|_| GuardError)?;
+                Ok(inserted == 1)
+            }
+        }
+
+        impl Issuer for DieselStore<$connection> {
+            fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+                let (row, access, refresh, grant) = self.new_token(grant)?;
+                let mut conn = self.connection()?;
+                diesel::insert_into(oauth_tokens::table)
+                    .values(&row)
+                    .execute(&mut conn)
+                    .map_err(|_| ())?;
+
+                Ok(IssuedToken {
+                    token: access,
+                    refresh: Some(refresh),
+                    until: grant.until,
+                    token_type: TokenType::Bearer,
+                })
+            }
+
+            fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+                let old = self.lookup_key(refresh)?;
+                let (row, access, refresh, grant) = self.new_token(grant)?;
+                let mut conn = self.connection()?;
+                let tenant = &self.tenant;
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let old = oauth_tokens::table
+                        .filter(oauth_tokens::tenant_id.eq(tenant))
+                        .filter(oauth_tokens::refresh_token.eq(old));
+                    // Should only be called on valid refresh tokens.
+                    if diesel::delete(old).execute(conn)? != 1 {
+                        return Err(diesel::result::Error::RollbackTransaction);
+                    }
+
+                    diesel::insert_into(oauth_tokens::table)
+                        .values(&row)
+                        .execute(conn)
+                })
+                .map_err(|_| ())?;
+
+                Ok(RefreshedToken {
+                    token: access,
+                    refresh: Some(refresh),
+                    until: grant.until,
+                    token_type: TokenType::Bearer,
+                })
+            }
+
+            fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+                let key = self.lookup_key(token)?;
+                self.find_grant(oauth_tokens::table.find(key).into_boxed())
+            }
+
+            fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+                let key = self.lookup_key(token)?;
+                self.find_grant(
+                    oauth_tokens::table
+                        .filter(oauth_tokens::refresh_token.eq(key))
+                        .into_boxed(),
+                )
+            }
+
+            fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+                self.delete_client_tokens(client_id, None)
+            }
+
+            fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+                self.delete_client_tokens(client_id, Some(owner_id))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "diesel-postgres")]
+diesel_store!(diesel::PgConnection, Dialect::Postgres);
+
+#[cfg(feature = "diesel-sqlite")]
+diesel_store!(diesel::SqliteConnection, Dialect::Sqlite);
+
+#[cfg(all(test, feature = "diesel-sqlite"))]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::registrar::{ExactUrl, RegisteredUrl};
+
+    fn store() -> DieselStore<diesel::SqliteConnection> {
+        // Every connection would see its own in-memory database.
+        let store =
+            DieselStore::<diesel::SqliteConnection>::new(":memory:", PoolConfig::with_max_size(1))
+                .unwrap();
+        store.migrate().unwrap();
+        store.verify_schema().unwrap();
+        store.health_check().unwrap();
+        store
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn registrar() {
+        let store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        let client = |pass: &[u8]| {
+            Client::confidential(
+                "Client",
+                RegisteredUrl::from(url.clone()),
+                "default".parse().unwrap(),
+                pass,
+            )
+        };
+
+        store.register_client(client(b"old passphrase")).unwrap();
+        store.register_client(client(pass)).unwrap();
+
+        let bound = store
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Borrowed("Client"),
+                redirect_uri: None,
+            })
+            .unwrap();
+        store.negotiate(bound, None).unwrap();
+        store.check("Client", Some(pass)).unwrap();
+        assert!(store.check("Client", Some(b"old passphrase")).is_err());
+        assert!(store.check("Unknown", None).is_err());
+    }
+
+    #[test]
+    fn registration_settings() {
+        let store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::builder("Client")
+            .redirect_uri(RegisteredUrl::from(url))
+            .default_scope("default".parse().unwrap())
+            .allowed_scope("default extra".parse().unwrap())
+            .grant_types([GrantType::ClientCredentials])
+            .passphrase(b"secret")
+            .auth_method(ClientAuthMethod::ClientSecretPost)
+            .metadata("client_name", "Example")
+            .build()
+            .unwrap();
+        store.register_client(client).unwrap();
+
+        assert!(store.permits_grant("Client", GrantType::ClientCredentials));
+        assert!(!store.permits_grant("Client", GrantType::RefreshToken));
+        assert_eq!(
+            store.auth_method("Client"),
+            Some(ClientAuthMethod::ClientSecretPost)
+        );
+        let client = store.client("Client").unwrap();
+        assert_eq!(client.allowed_scope, Some("default extra".parse().unwrap()));
+        assert_eq!(client.metadata["client_name"], "Example");
+    }
+
+    #[test]
+    fn refresh_policy() {
+        let store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        for (id, policy) in [
+            ("Never", RefreshPolicy::Never),
+            ("Offline", RefreshPolicy::OfflineAccess),
+        ] {
+            let client =
+                Client::public(id, RegisteredUrl::from(url.clone()), "default".parse().unwrap())
+                    .with_refresh_policy(policy);
+            store.register_client(client).unwrap();
+            assert_eq!(store.refresh_policy(id), policy);
+        }
+
+        assert_eq!(store.refresh_policy("Unknown"), RefreshPolicy::Always);
+    }
+
+    #[test]
+    fn disabled_client() {
+        let mut store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::public("Client", RegisteredUrl::from(url), "default".parse().unwrap());
+        let mut stored = StoredClient::from_encoded(&client.encode(&Argon2::default())).unwrap();
+        stored.disabled = true;
+        store.import(Record::Client(stored)).unwrap();
+
+        assert!(store.client("Client").unwrap().disabled);
+        let bound = store.bound_redirect(ClientUrl {
+            client_id: Cow::Borrowed("Client"),
+            redirect_uri: None,
+        });
+        assert!(bound.is_err());
+        assert!(store.check("Client", None).is_err());
+    }
+
+    #[test]
+    fn revoke_token() {
+        let mut store = store();
+        let first = store.issue(grant()).unwrap();
+        let second = store.issue(grant()).unwrap();
+
+        assert!(store.revoke_token(&first.refresh.unwrap()).unwrap());
+        assert_eq!(store.recover_token(&first.token).unwrap(), None);
+        assert!(store.revoke_token(&second.token).unwrap());
+        assert!(!store.revoke_token(&second.token).unwrap());
+    }
+
+    #[test]
+    fn claims_race() {
+        let store = store();
+        let replica = store.clone();
+        let until = Utc::now() + Duration::minutes(10);
+
+        let (first, second) = std::thread::scope(|scope| {
+            let first = scope.spawn(|| store.claim("code", until));
+            let second = scope.spawn(|| replica.claim("code", until));
+            (first.join().unwrap(), second.join().unwrap())
+        });
+        assert!(first.unwrap() != second.unwrap(), "exactly one claim must win");
+        assert!(!store.claim("code", until).unwrap());
+        assert!(store.for_tenant("other").claim("code", until).unwrap());
+
+        let expired = Utc::now() - Duration::minutes(1);
+        assert!(store.claim("expired", expired).unwrap());
+        store.purge_expired().unwrap();
+        assert!(store.claim("expired", expired).unwrap());
+        assert!(!store.claim("code", until).unwrap());
+    }
+
+    #[test]
+    fn authorizer_and_issuer() {
+        let mut store = store();
+        let code = store.authorize(grant()).unwrap();
+        let grant = store.extract(&code).unwrap().unwrap();
+        assert_eq!(store.extract(&code).unwrap(), None);
+
+        let issued = store.issue(grant).unwrap();
+        let refresh = issued.refresh.unwrap();
+        let grant = store.recover_refresh(&refresh).unwrap().unwrap();
+        let refreshed = store.refresh(&refresh, grant.clone()).unwrap();
+
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+        assert!(store.recover_token(&refreshed.token).unwrap().is_some());
+        assert!(store.refresh(&refresh, grant).is_err());
+    }
+
+    #[test]
+    fn purge_expired() {
+        let mut store = store();
+        let expired = Grant {
+            until: Utc::now() - Duration::minutes(1),
+            ..grant()
+        };
+        let code = store.authorize(expired).unwrap();
+        let kept_code = store.authorize(grant()).unwrap();
+        store.valid_for(Duration::minutes(-1));
+        let issued = store.issue(grant()).unwrap();
+
+        assert_eq!(store.purge_expired().unwrap(), 2);
+        assert_eq!(store.extract(&code).unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+        assert!(store.extract(&kept_code).unwrap().is_some());
+    }
+
+    #[test]
+    fn revoke_client() {
+        let mut store = store();
+        let other = Grant {
+            client_id: "Other".into(),
+            ..grant()
+        };
+        let code = store.authorize(grant()).unwrap();
+        let kept_code = store.authorize(other.clone()).unwrap();
+        let issued = store.issue(grant()).unwrap();
+        let kept = store.issue(other).unwrap();
+
+        assert_eq!(Authorizer::revoke_client(&mut store, "Client"), Ok(1));
+        assert_eq!(Issuer::revoke_client(&mut store, "Client"), Ok(1));
+        assert_eq!(store.extract(&code).unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+        assert_eq!(store.recover_refresh(&issued.refresh.unwrap()).unwrap(), None);
+        assert!(store.extract(&kept_code).unwrap().is_some());
+        assert!(store.recover_token(&kept.token).unwrap().is_some());
+    }
+
+    #[test]
+    fn tenants_are_isolated() {
+        let store = store();
+        let mut first = store.for_tenant("first");
+        let mut second = store.for_tenant("second");
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        first
+            .register_client(Client::public(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+            ))
+            .unwrap();
+        first.check("Client", None).unwrap();
+        assert!(second.check("Client", None).is_err());
+        assert!(store.check("Client", None).is_err());
+
+        let code = first.authorize(grant()).unwrap();
+        assert_eq!(second.extract(&code).unwrap(), None);
+        assert!(first.extract(&code).unwrap().is_some());
+
+        let issued = first.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        assert_eq!(second.recover_refresh(&refresh).unwrap(), None);
+        assert!(second.refresh(&refresh, grant()).is_err());
+        assert!(first.recover_token(&issued.token).unwrap().is_some());
+    }
+
+    #[test]
+    fn transfer_between_tenants() {
+        use crate::db_service::transfer::transfer;
+
+        let store = store();
+        let mut first = store.for_tenant("first");
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        first
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+                pass,
+            ))
+            .unwrap();
+        let code = first.authorize(grant()).unwrap();
+        let issued = first.issue(grant()).unwrap();
+
+        let mut second = store.for_tenant("second");
+        let report = transfer(&first, &mut second).unwrap();
+        assert_eq!((report.clients, report.grants, report.tokens), (1, 1, 1));
+        second.check("Client", Some(pass)).unwrap();
+        assert!(second.recover_token(&issued.token).unwrap().is_some());
+        assert!(second.extract(&code).unwrap().is_some());
+    }
+
+    #[test]
+    fn sealed_values() {
+        use crate::db_service::encryption::{is_sealed, EnvelopeCipher, StaticKeys};
+        use crate::db_service::transfer::transfer;
+        use oxide_auth::primitives::grant::Value;
+
+        let store = store();
+        let mut plain = store.for_tenant("plain");
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        plain
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+                pass,
+            ))
+            .unwrap();
+        let mut grant = grant();
+        grant
+            .extensions
+            .set_raw("pkce".into(), Value::private(Some("challenge".into())));
+        let issued = plain.issue(grant.clone()).unwrap();
+        let refresh = issued.refresh.clone().unwrap();
+
+        let mut sealed = store.for_tenant("sealed");
+        sealed.set_cipher(EnvelopeCipher::new(StaticKeys::new("1", [7; 32])));
+        transfer(&plain, &mut sealed).unwrap();
+        let code = sealed.authorize(grant.clone()).unwrap();
+
+        let mut conn = store.pool().get().unwrap();
+        let secret = oauth_clients::table
+            .find(("sealed", "Client"))
+            .select(oauth_clients::client_secret)
+            .first::<Option<String>>(&mut conn)
+            .unwrap();
+        assert!(is_sealed(&secret.unwrap()));
+        let data = oauth_grants::table
+            .find(&code)
+            .select(oauth_grants::grant_data)
+            .first::<String>(&mut conn)
+            .unwrap();
+        assert!(!data.contains("challenge") && !data.contains("Owner"));
+        let (access, stored_refresh, data) = oauth_tokens::table
+            .filter(oauth_tokens::tenant_id.eq("sealed"))
+            .select((
+                oauth_tokens::access_token,
+                oauth_tokens::refresh_token,
+                oauth_tokens::grant_data,
+            ))
+            .first::<(String, Option<String>, String)>(&mut conn)
+            .unwrap();
+        assert!(access != issued.token && stored_refresh != Some(refresh.clone()));
+        assert!(!data.contains("challenge") && !data.contains("Owner"));
+        drop(conn);
+
+        sealed.check("Client", Some(pass)).unwrap();
+        assert_eq!(
+            sealed.extract(&code).unwrap().unwrap().extensions,
+            grant.extensions
+        );
+        let recovered = sealed.recover_token(&issued.token).unwrap().unwrap();
+        assert_eq!(recovered.extensions, grant.extensions);
+        assert!(sealed.recover_refresh(&refresh).unwrap().is_some());
+        sealed.refresh(&refresh, recovered).unwrap();
+        assert_eq!(sealed.recover_token(&issued.token).unwrap(), None);
+        assert_eq!(Issuer::revoke_client(&mut sealed, "Client"), Ok(1));
+    }
+
+    #[test]
+    fn audit_log() {
+        use oxide_auth::endpoint::{GrantEvent, GrantOutcome, GrantRecord};
+
+        let store = store();
+        let record = GrantRecord {
+            client_id: Some("Client".into()),
+            ..GrantRecord::new(GrantEvent::Code, GrantOutcome::Issued)
+        };
+        store
+            .append(&AuditEntry::new(&record, Some("192.0.2.1".into())))
+            .unwrap();
+    }
+}
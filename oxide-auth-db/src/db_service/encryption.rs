@@ -0,0 +1,385 @@
+//! Encryption of sensitive values before they are written to a datasource.
+//!
+//! Values are protected with envelope encryption: every value is sealed with a fresh random data
+//! key using AES-256-GCM and the data key itself is wrapped by a key encryption key. The wrapping
+//! is delegated to a [`KeyWrapper`], which is the place to plug in an external key management
+//! service such as a cloud KMS or Vault's transit engine. The key encryption key therefore never
+//! needs to be present in the process.
+//!
+//! Every sealed value records the version of the key encryption key that wrapped it. After a key
+//! rotation old values can still be opened, and [`ValueCipher::needs_rotation`] identifies those
+//! that should be re-encrypted with the current key.
+//!
+//! Tokens are looked up by their value and can not be sealed with a random data key. Stores keep
+//! a [`ValueCipher::lookup_key`] in their place instead, a keyed hash from which the token can not
+//! be recovered. Presented tokens are always hashed before the lookup, a stored lookup key does not
+//! work as a token. Only [`import_lookup_key`] keeps lookup keys, for records transferred between
+//! datasources sharing the key.
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Context};
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Prefix marking a stored value as sealed by a [`ValueCipher`].
+const SEALED_PREFIX: &str = "enc1";
+
+/// Prefix marking a stored token as the lookup key of a [`ValueCipher`].
+const LOOKUP_PREFIX: &str = "mac1";
+
+/// Length of AES-GCM nonces, which are stored in front of each ciphertext.
+const NONCE_LEN: usize = 12;
+
+/// HKDF label of the key encryption keys derived by [`StaticKeys`].
+const WRAP_LABEL: &[u8] = b"oxide-auth-db key encryption";
+
+/// HKDF label of the key authenticating lookup keys in [`StaticKeys`].
+const MAC_LABEL: &[u8] = b"oxide-auth-db lookup key";
+
+/// Encrypts and decrypts single values stored by a datasource.
+pub trait ValueCipher: Send + Sync {
+    /// Seal a value into a printable string.
+    fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<String>;
+
+    /// Open a value previously sealed with `encrypt`, possibly with an older key version.
+    fn decrypt(&self, sealed: &str) -> anyhow::Result<Vec<u8>>;
+
+    /// Determine if a stored value should be re-encrypted.
+    ///
+    /// This is the case if it is not sealed at all or sealed by a key version that is no longer
+    /// current.
+    fn needs_rotation(&self, stored: &str) -> bool;
+
+    /// Derive the key under which a token is stored and looked up.
+    ///
+    /// The same token always yields the same key, but the token can not be recovered from it.
+    fn lookup_key(&self, token: &str) -> anyhow::Result<String>;
+}
+
+/// Wraps and unwraps data keys with a key encryption key.
+///
+/// Implement this on top of the key management service of your choice.
+pub trait KeyWrapper: Send + Sync {
+    /// The version of the key encryption key used for wrapping new data keys.
+    ///
+    /// Must not contain a `:`.
+    fn current_version(&self) -> String;
+
+    /// Wrap a fresh data key with the current key encryption key.
+    fn wrap(&self, data_key: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    /// Unwrap a data key that was wrapped with the given key version.
+    fn unwrap(&self, version: &str, wrapped: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    /// Authenticate data with a key that is kept across rotations.
+    ///
+    /// Lookup keys of tokens are derived with it, they can not be re-encrypted and must stay the
+    /// same for as long as the tokens are valid.
+    fn mac(&self, data: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Envelope encryption of values, with AES-256-GCM data keys wrapped by a `KeyWrapper`.
+///
+/// Sealed values have the form `enc1:<key version>:<wrapped key>:<nonce and ciphertext>` with the
+/// binary parts encoded as base64.
+pub struct EnvelopeCipher<W> {
+    wrapper: W,
+}
+
+/// A key wrapper holding its key encryption keys locally.
+///
+/// Useful for tests and deployments where keys are provided through configuration. The most
+/// recently added key is used for wrapping, all keys remain available for unwrapping. Lookup keys
+/// are authenticated with the first key. The keys used for wrapping and authenticating are derived
+/// from the configured ones with HKDF under distinct labels, neither is used for both.
+pub struct StaticKeys {
+    keys: Vec<(String, Aes256Gcm)>,
+    mac: Hmac<Sha256>,
+}
+
+/// Whether a stored value looks like it has been sealed.
+///
+/// Datasources use this to read values written before encryption was enabled.
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(SEALED_PREFIX) && stored[SEALED_PREFIX.len()..].starts_with(':')
+}
+
+/// Whether a stored token looks like a lookup key.
+pub fn is_lookup_key(stored: &str) -> bool {
+    stored.starts_with(LOOKUP_PREFIX) && stored[LOOKUP_PREFIX.len()..].starts_with(':')
+}
+
+/// Seal a value with the cipher of a datasource, if it has one.
+pub fn seal(cipher: Option<&dyn ValueCipher>, value: String) -> anyhow::Result<String> {
+    match cipher {
+        Some(cipher) => cipher.encrypt(value.as_bytes()),
+        None => Ok(value),
+    }
+}
+
+/// Open a value stored by a datasource, which may have been written before encryption was enabled.
+pub fn open(cipher: Option<&dyn ValueCipher>, stored: String) -> anyhow::Result<String> {
+    match cipher {
+        Some(cipher) if is_sealed(&stored) => Ok(String::from_utf8(cipher.decrypt(&stored)?)?),
+        None if is_sealed(&stored) => Err(anyhow!("Value is encrypted but no cipher is configured")),
+        _ => Ok(stored),
+    }
+}
+
+/// The form in which a datasource stores a token, its lookup key if it has a cipher.
+///
+/// Tokens presented by clients are always hashed, even if they look like a lookup key. Otherwise
+/// anyone reading the stored lookup keys could present them as working tokens.
+pub fn lookup_key(cipher: Option<&dyn ValueCipher>, token: &str) -> anyhow::Result<String> {
+    match cipher {
+        Some(cipher) => cipher.lookup_key(token),
+        None => Ok(token.to_owned()),
+    }
+}
+
+/// The form in which a datasource stores an imported token.
+///
+/// Unlike [`lookup_key`] this keeps lookup keys as they are, so that records can be transferred
+/// between datasources sharing the same key. Never use it for tokens presented by clients.
+pub fn import_lookup_key(cipher: Option<&dyn ValueCipher>, token: &str) -> anyhow::Result<String> {
+    match cipher {
+        Some(_) if is_lookup_key(token) => Ok(token.to_owned()),
+        _ => lookup_key(cipher, token),
+    }
+}
+
+impl<W: KeyWrapper> EnvelopeCipher<W> {
+    /// Create a cipher wrapping its data keys with `wrapper`.
+    pub fn new(wrapper: W) -> Self {
+        EnvelopeCipher { wrapper }
+    }
+
+    fn split(sealed: &str) -> anyhow::Result<(&str, &str, &str)> {
+        let mut parts = sealed.splitn(4, ':');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(SEALED_PREFIX), Some(version), Some(key), Some(body)) => Ok((version, key, body)),
+            _ => Err(anyhow!("Value is not sealed")),
+        }
+    }
+}
+
+impl<W: KeyWrapper> ValueCipher for EnvelopeCipher<W> {
+    fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<String> {
+        let data_key = Aes256Gcm::generate_key(&mut OsRng);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Aes256Gcm::new(&data_key)
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("Encryption failed"))?;
+
+        let version = self.wrapper.current_version();
+        let wrapped = self.wrapper.wrap(&data_key)?;
+        let mut body = nonce.to_vec();
+        body.extend_from_slice(&ciphertext);
+
+        Ok(format!(
+            "{}:{}:{}:{}",
+            SEALED_PREFIX,
+            version,
+            STANDARD_NO_PAD.encode(wrapped),
+            STANDARD_NO_PAD.encode(body)
+        ))
+    }
+
+    fn decrypt(&self, sealed: &str) -> anyhow::Result<Vec<u8>> {
+        let (version, wrapped, body) = Self::split(sealed)?;
+        let wrapped = STANDARD_NO_PAD.decode(wrapped).context("Malformed data key")?;
+        let body = STANDARD_NO_PAD.decode(body).context("Malformed ciphertext")?;
+
+        let data_key = self.wrapper.unwrap(version, &wrapped)?;
+        if data_key.len() != 32 {
+            return Err(anyhow!("Unwrapped data key has wrong length"));
+        }
+
+        if body.len() < NONCE_LEN {
+            return Err(anyhow!("Ciphertext too short"));
+        }
+
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Decryption failed"))
+    }
+
+    fn needs_rotation(&self, stored: &str) -> bool {
+        match Self::split(stored) {
+            Ok((version, _, _)) => version != self.wrapper.current_version(),
+            Err(_) => true,
+        }
+    }
+
+    fn lookup_key(&self, token: &str) -> anyhow::Result<String> {
+        let mac = self.wrapper.mac(token.as_bytes())?;
+        Ok(format!("{}:{}", LOOKUP_PREFIX, STANDARD_NO_PAD.encode(mac)))
+    }
+}
+
+impl StaticKeys {
+    /// Start with a single 256-bit key, from which the lookup keys are authenticated as well.
+    pub fn new(version: &str, key: [u8; 32]) -> Self {
+        let mac = derive(&key, MAC_LABEL);
+        let mut keys = StaticKeys {
+            keys: Vec::new(),
+            mac: <Hmac<Sha256> as Mac>::new_from_slice(&mac).expect("HMAC accepts keys of any length"),
+        };
+        keys.add_key(version, key);
+        keys
+    }
+
+    /// Add a new key encryption key, which becomes the current one.
+    pub fn add_key(&mut self, version: &str, key: [u8; 32]) {
+        let key = derive(&key, WRAP_LABEL);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        self.keys.push((version.to_owned(), cipher));
+    }
+
+    fn cipher(&self, version: &str) -> anyhow::Result<&Aes256Gcm> {
+        self.keys
+            .iter()
+            .rev()
+            .find(|(candidate, _)| candidate == version)
+            .map(|(_, cipher)| cipher)
+            .ok_or_else(|| anyhow!("Unknown key version {}", version))
+    }
+}
+
+/// Derive a key for one purpose from a configured key.
+fn derive(key: &[u8; 32], label: &[u8]) -> [u8; 32] {
+    let mut derived = [0; 32];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(label, &mut derived)
+        .expect("HKDF expands to a single block");
+    derived
+}
+
+impl KeyWrapper for StaticKeys {
+    fn current_version(&self) -> String {
+        self.keys.last().unwrap().0.clone()
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (_, cipher) = self.keys.last().unwrap();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut wrapped = nonce.to_vec();
+        wrapped.extend(
+            cipher
+                .encrypt(&nonce, data_key)
+                .map_err(|_| anyhow!("Wrapping data key failed"))?,
+        );
+        Ok(wrapped)
+    }
+
+    fn unwrap(&self, version: &str, wrapped: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let cipher = self.cipher(version)?;
+        if wrapped.len() < NONCE_LEN {
+            return Err(anyhow!("Wrapped data key too short"));
+        }
+
+        let (nonce, wrapped) = wrapped.split_at(NONCE_LEN);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), wrapped)
+            .map_err(|_| anyhow!("Unwrapping data key failed"))
+    }
+
+    fn mac(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut mac = self.mac.clone();
+        mac.update(data);
+        Ok(mac.finalize().into_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let cipher = EnvelopeCipher::new(StaticKeys::new("1", [7; 32]));
+        let sealed = cipher.encrypt(b"client secret").unwrap();
+
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("client secret"));
+        assert!(!cipher.needs_rotation(&sealed));
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), b"client secret");
+    }
+
+    #[test]
+    fn rotation() {
+        let mut keys = StaticKeys::new("1", [7; 32]);
+        let old = EnvelopeCipher::new(StaticKeys::new("1", [7; 32]))
+            .encrypt(b"refresh")
+            .unwrap();
+
+        keys.add_key("2", [9; 32]);
+        let cipher = EnvelopeCipher::new(keys);
+
+        assert!(cipher.needs_rotation(&old));
+        assert!(cipher.needs_rotation("plaintext"));
+        assert_eq!(cipher.decrypt(&old).unwrap(), b"refresh");
+
+        let new = cipher.encrypt(b"refresh").unwrap();
+        assert!(!cipher.needs_rotation(&new));
+    }
+
+    #[test]
+    fn lookup_keys() {
+        let mut keys = StaticKeys::new("1", [7; 32]);
+        let before = EnvelopeCipher::new(StaticKeys::new("1", [7; 32]))
+            .lookup_key("token")
+            .unwrap();
+        keys.add_key("2", [9; 32]);
+        let cipher = EnvelopeCipher::new(keys);
+
+        let key = cipher.lookup_key("token").unwrap();
+        assert!(is_lookup_key(&key));
+        assert!(!key.contains("token"));
+        assert_eq!(key, before);
+        assert_ne!(key, cipher.lookup_key("other").unwrap());
+        assert_eq!(lookup_key(None, "token").unwrap(), "token");
+
+        // Stored lookup keys are hashed again when presented, only imports keep them.
+        assert_ne!(lookup_key(Some(&cipher), &key).unwrap(), key);
+        assert_eq!(import_lookup_key(Some(&cipher), &key).unwrap(), key);
+        assert_eq!(import_lookup_key(Some(&cipher), "token").unwrap(), key);
+
+        let sealed = seal(Some(&cipher), "grant".into()).unwrap();
+        assert_eq!(open(Some(&cipher), sealed.clone()).unwrap(), "grant");
+        assert_eq!(open(Some(&cipher), "grant".into()).unwrap(), "grant");
+        assert!(open(None, sealed).is_err());
+    }
+
+    #[test]
+    fn tampering() {
+        let cipher = EnvelopeCipher::new(StaticKeys::new("1", [7; 32]));
+        let mut sealed = cipher.encrypt(b"client secret").unwrap();
+        let last = sealed.pop().unwrap();
+        sealed.push(if last == 'A' { 'B' } else { 'A' });
+
+        assert!(cipher.decrypt(&sealed).is_err());
+        assert!(cipher.decrypt("enc1:3:AAAA:AAAA").is_err());
+    }
+
+    #[test]
+    fn separate_keys() {
+        let key = [7; 32];
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).unwrap();
+        mac.update(b"token");
+        let raw = mac.finalize().into_bytes().to_vec();
+
+        let keys = StaticKeys::new("1", key);
+        assert_ne!(keys.mac(b"token").unwrap(), raw);
+        assert_ne!(derive(&key, MAC_LABEL), derive(&key, WRAP_LABEL));
+
+        let wrapped = keys.wrap(&[1; 32]).unwrap();
+        let (nonce, wrapped) = wrapped.split_at(NONCE_LEN);
+        let raw = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        assert!(raw.decrypt(Nonce::from_slice(nonce), wrapped).is_err());
+    }
+}
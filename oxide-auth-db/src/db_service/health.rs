@@ -0,0 +1,182 @@
+//! Health of datasource connections.
+//!
+//! The connection pools of all backends replace broken connections on their own. What they do not
+//! do is stop hammering a database that is down: every operation would wait for the full connection
+//! timeout before failing. A [`HealthMonitor`] remembers consecutive failures and makes further
+//! attempts fail fast until an exponentially growing [`Backoff`] has passed, while its
+//! [`HealthStatus`] tells monitoring whether the datasource is currently degraded.
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Delays between reconnect attempts after the database became unreachable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// The delay after the first failure.
+    pub initial: Duration,
+
+    /// Upper bound of the delay, which doubles with every consecutive failure.
+    pub max: Duration,
+}
+
+/// A snapshot of the connection health of a datasource.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HealthStatus {
+    /// The most recent attempt to reach the database failed.
+    pub degraded: bool,
+
+    /// Failed attempts since the last success.
+    pub consecutive_failures: u32,
+
+    /// Total number of failed attempts.
+    pub failures: u64,
+
+    /// How often the connection was restored after a failure.
+    pub recoveries: u64,
+
+    /// The error of the most recent failure, cleared once the connection is restored.
+    pub last_error: Option<String>,
+}
+
+/// Tracks failures of a datasource and throttles reconnects accordingly.
+#[derive(Debug)]
+pub struct HealthMonitor {
+    backoff: Backoff,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    status: HealthStatus,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    /// The delay after a number of consecutive failures.
+    pub fn delay(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::from_secs(0);
+        }
+
+        let factor = 1u32.checked_shl(failures - 1).unwrap_or(u32::MAX);
+        self.initial
+            .checked_mul(factor)
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+impl HealthMonitor {
+    /// A monitor of a healthy datasource.
+    pub fn new(backoff: Backoff) -> Self {
+        HealthMonitor {
+            backoff,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Check whether the database may be contacted now.
+    ///
+    /// Returns the remaining time of the backoff otherwise.
+    pub fn admit(&self) -> Result<(), Duration> {
+        let state = self.state.lock().unwrap();
+        match state.retry_at {
+            Some(retry_at) => match retry_at.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::from_secs(0) => Err(remaining),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Record a successful operation.
+    pub fn success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.status.degraded {
+            log::info!("Connection to the database has been restored");
+            state.status.recoveries += 1;
+        }
+
+        state.status.degraded = false;
+        state.status.consecutive_failures = 0;
+        state.status.last_error = None;
+        state.retry_at = None;
+    }
+
+    /// Record a failure to reach the database.
+    pub fn failure(&self, err: &dyn fmt::Display) {
+        let mut state = self.state.lock().unwrap();
+        let status = &mut state.status;
+        if !status.degraded {
+            log::warn!("Lost connection to the database: {}", err);
+        }
+
+        status.degraded = true;
+        status.consecutive_failures = status.consecutive_failures.saturating_add(1);
+        status.failures += 1;
+        status.last_error = Some(err.to_string());
+        let delay = self.backoff.delay(status.consecutive_failures);
+        state.retry_at = Some(Instant::now() + delay);
+    }
+
+    /// The current health.
+    pub fn status(&self) -> HealthStatus {
+        self.state.lock().unwrap().status.clone()
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        HealthMonitor::new(Backoff::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        assert_eq!(backoff.delay(0), Duration::from_secs(0));
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(5), Duration::from_secs(1));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn monitor_throttles_until_recovery() {
+        let monitor = HealthMonitor::new(Backoff {
+            initial: Duration::from_secs(60),
+            max: Duration::from_secs(60),
+        });
+        assert!(monitor.admit().is_ok());
+
+        monitor.failure(&"connection refused");
+        assert!(monitor.admit().is_err());
+        let status = monitor.status();
+        assert!(status.degraded);
+        assert_eq!(status.consecutive_failures, 1);
+        assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+
+        monitor.success();
+        assert!(monitor.admit().is_ok());
+        let status = monitor.status();
+        assert!(!status.degraded);
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.recoveries, 1);
+        assert_eq!(status.last_error, None);
+    }
+}
@@ -0,0 +1,410 @@
+//! Versioned schema migrations shared by all SQL backends.
+//!
+//! The migrations are embedded in the crate and applied in order by the `migrate` method of each
+//! store. Every applied migration is recorded in the `oauth_schema_migrations` table. A store
+//! should additionally call its `verify_schema` at startup, which refuses to operate on a schema
+//! that is older than expected or that was created by a newer version of this crate.
+//!
+//! Replicas starting at the same time may all call `migrate`. On PostgreSQL and MySQL the stores
+//! hold the lock of [`Dialect::migration_lock`] while migrating, so that one replica applies the
+//! migrations and the others find them applied. SQLite has no such lock, migrate its databases
+//! from a single process.
+use std::fmt;
+
+/// The SQL dialect spoken by the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    /// PostgreSQL, with numbered `$n` placeholders.
+    Postgres,
+
+    /// MySQL or MariaDB.
+    MySql,
+
+    /// SQLite.
+    Sqlite,
+}
+
+/// A single step in the evolution of the schema.
+#[derive(Clone, Copy, Debug)]
+pub struct Migration {
+    /// The schema version after this migration, starting at 1.
+    pub version: i64,
+
+    /// A short human readable description.
+    pub description: &'static str,
+
+    /// The statements to execute, in order.
+    pub statements: &'static [&'static str],
+}
+
+/// The schema version does not match the one expected by this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    /// Some migrations have not been applied yet.
+    Outdated {
+        /// The version found in the database, 0 for an empty database.
+        found: i64,
+
+        /// The version this crate works with.
+        expected: i64,
+    },
+
+    /// The database was migrated by a newer version of this crate.
+    Newer {
+        /// The version found in the database.
+        found: i64,
+
+        /// The version this crate works with.
+        expected: i64,
+    },
+}
+
+/// Creates the table recording applied migrations.
+pub const CREATE_VERSION_TABLE: &str = "CREATE TABLE IF NOT EXISTS oauth_schema_migrations (
+    version BIGINT PRIMARY KEY,
+    description TEXT NOT NULL)";
+
+/// Selects the current version, if any migration has been applied.
+pub const SELECT_VERSION: &str =
+    "SELECT version FROM oauth_schema_migrations ORDER BY version DESC LIMIT 1";
+
+/// Records an applied migration, parameters are version and description.
+pub const INSERT_VERSION: &str =
+    "INSERT INTO oauth_schema_migrations (version, description) VALUES (?, ?)";
+
+/// The statements taking and releasing the migration lock of PostgreSQL, keyed by `oauth` in ASCII.
+const LOCK_POSTGRES: (&str, &str) = (
+    "SELECT pg_advisory_lock(478376457320)",
+    "SELECT pg_advisory_unlock(478376457320)",
+);
+
+/// The statements taking and releasing the migration lock of MySQL, waiting without a timeout.
+const LOCK_MYSQL: (&str, &str) = (
+    "SELECT GET_LOCK('oauth_schema_migrations', -1)",
+    "SELECT RELEASE_LOCK('oauth_schema_migrations')",
+);
+
+const MIGRATIONS_MYSQL: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create clients, grants and tokens",
+        statements: &[
+            "CREATE TABLE oauth_clients (
+                client_id VARCHAR(255) PRIMARY KEY,
+                redirect_uri TEXT NOT NULL,
+                additional_redirect_uris TEXT NOT NULL,
+                default_scope TEXT NOT NULL,
+                client_secret TEXT NULL)",
+            "CREATE TABLE oauth_grants (
+                code VARCHAR(255) PRIMARY KEY,
+                grant_data TEXT NOT NULL,
+                expires_at BIGINT NOT NULL)",
+            "CREATE TABLE oauth_tokens (
+                access_token VARCHAR(255) PRIMARY KEY,
+                refresh_token VARCHAR(255) NULL UNIQUE,
+                grant_data TEXT NOT NULL,
+                expires_at BIGINT NOT NULL)",
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "Index expiration dates",
+        statements: &[
+            "CREATE INDEX oauth_grants_expires_at ON oauth_grants (expires_at)",
+            "CREATE INDEX oauth_tokens_expires_at ON oauth_tokens (expires_at)",
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "Create the audit log",
+        statements: &[
+            "CREATE TABLE oauth_audit_log (
+                occurred_at BIGINT NOT NULL,
+                event VARCHAR(16) NOT NULL,
+                outcome VARCHAR(16) NOT NULL,
+                client_id VARCHAR(255) NULL,
+                owner_id VARCHAR(255) NULL,
+                scope TEXT NULL,
+                remote_addr VARCHAR(64) NULL)",
+            "CREATE INDEX oauth_audit_log_occurred_at ON oauth_audit_log (occurred_at)",
+            "CREATE INDEX oauth_audit_log_client_id ON oauth_audit_log (client_id)",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "Partition all tables by tenant",
+        statements: &[
+            "ALTER TABLE oauth_clients
+                ADD COLUMN tenant_id VARCHAR(255) NOT NULL DEFAULT '' FIRST,
+                DROP PRIMARY KEY,
+                ADD PRIMARY KEY (tenant_id, client_id)",
+            "ALTER TABLE oauth_grants ADD COLUMN tenant_id VARCHAR(255) NOT NULL DEFAULT ''",
+            "ALTER TABLE oauth_tokens ADD COLUMN tenant_id VARCHAR(255) NOT NULL DEFAULT ''",
+            "ALTER TABLE oauth_audit_log ADD COLUMN tenant_id VARCHAR(255) NOT NULL DEFAULT ''",
+            "CREATE INDEX oauth_audit_log_tenant_id ON oauth_audit_log (tenant_id, occurred_at)",
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "Create the claims of used codes",
+        statements: &[
+            "CREATE TABLE oauth_used_codes (
+                tenant_id VARCHAR(255) NOT NULL,
+                code VARCHAR(255) NOT NULL,
+                expires_at BIGINT NOT NULL,
+                PRIMARY KEY (tenant_id, code))",
+            "CREATE INDEX oauth_used_codes_expires_at ON oauth_used_codes (expires_at)",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "Store the registration settings of clients",
+        statements: &["ALTER TABLE oauth_clients ADD COLUMN settings TEXT NULL"],
+    },
+    Migration {
+        version: 7,
+        description: "Store whether clients are disabled",
+        // An integer, booleans of SQLite and MySQL are read back as integers.
+        statements: &["ALTER TABLE oauth_clients ADD COLUMN disabled BIGINT NOT NULL DEFAULT 0"],
+    },
+];
+
+const MIGRATIONS_STANDARD: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create clients, grants and tokens",
+        statements: &[
+            "CREATE TABLE oauth_clients (
+                client_id TEXT PRIMARY KEY,
+                redirect_uri TEXT NOT NULL,
+                additional_redirect_uris TEXT NOT NULL,
+                default_scope TEXT NOT NULL,
+                client_secret TEXT NULL)",
+            "CREATE TABLE oauth_grants (
+                code TEXT PRIMARY KEY,
+                grant_data TEXT NOT NULL,
+                expires_at BIGINT NOT NULL)",
+            "CREATE TABLE oauth_tokens (
+                access_token TEXT PRIMARY KEY,
+                refresh_token TEXT NULL UNIQUE,
+                grant_data TEXT NOT NULL,
+                expires_at BIGINT NOT NULL)",
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "Index expiration dates",
+        statements: &[
+            "CREATE INDEX oauth_grants_expires_at ON oauth_grants (expires_at)",
+            "CREATE INDEX oauth_tokens_expires_at ON oauth_tokens (expires_at)",
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "Create the audit log",
+        statements: &[
+            "CREATE TABLE oauth_audit_log (
+                occurred_at BIGINT NOT NULL,
+                event VARCHAR(16) NOT NULL,
+                outcome VARCHAR(16) NOT NULL,
+                client_id TEXT NULL,
+                owner_id TEXT NULL,
+                scope TEXT NULL,
+                remote_addr VARCHAR(64) NULL)",
+            "CREATE INDEX oauth_audit_log_occurred_at ON oauth_audit_log (occurred_at)",
+            "CREATE INDEX oauth_audit_log_client_id ON oauth_audit_log (client_id)",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "Partition all tables by tenant",
+        statements: &[
+            // SQLite can not alter a primary key, the clients table is rebuilt instead.
+            "CREATE TABLE oauth_clients_tenant (
+                tenant_id TEXT NOT NULL DEFAULT '',
+                client_id TEXT NOT NULL,
+                redirect_uri TEXT NOT NULL,
+                additional_redirect_uris TEXT NOT NULL,
+                default_scope TEXT NOT NULL,
+                client_secret TEXT NULL,
+                PRIMARY KEY (tenant_id, client_id))",
+            "INSERT INTO oauth_clients_tenant
+                (client_id, redirect_uri, additional_redirect_uris, default_scope, client_secret)
+                SELECT client_id, redirect_uri, additional_redirect_uris, default_scope, client_secret
+                FROM oauth_clients",
+            "DROP TABLE oauth_clients",
+            "ALTER TABLE oauth_clients_tenant RENAME TO oauth_clients",
+            "ALTER TABLE oauth_grants ADD COLUMN tenant_id TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE oauth_tokens ADD COLUMN tenant_id TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE oauth_audit_log ADD COLUMN tenant_id TEXT NOT NULL DEFAULT ''",
+            "CREATE INDEX oauth_audit_log_tenant_id ON oauth_audit_log (tenant_id, occurred_at)",
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "Create the claims of used codes",
+        statements: &[
+            "CREATE TABLE oauth_used_codes (
+                tenant_id TEXT NOT NULL,
+                code TEXT NOT NULL,
+                expires_at BIGINT NOT NULL,
+                PRIMARY KEY (tenant_id, code))",
+            "CREATE INDEX oauth_used_codes_expires_at ON oauth_used_codes (expires_at)",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "Store the registration settings of clients",
+        statements: &["ALTER TABLE oauth_clients ADD COLUMN settings TEXT NULL"],
+    },
+    Migration {
+        version: 7,
+        description: "Store whether clients are disabled",
+        // An integer, booleans of SQLite and MySQL are read back as integers.
+        statements: &["ALTER TABLE oauth_clients ADD COLUMN disabled BIGINT NOT NULL DEFAULT 0"],
+    },
+];
+
+impl Dialect {
+    /// Determine the dialect from the scheme of a database url.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let scheme = url.split(':').next()?;
+        match scheme {
+            "postgres" | "postgresql" => Some(Dialect::Postgres),
+            "mysql" | "mariadb" => Some(Dialect::MySql),
+            "sqlite" => Some(Dialect::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// All migrations of the schema, in order.
+    pub fn migrations(self) -> &'static [Migration] {
+        match self {
+            Dialect::MySql => MIGRATIONS_MYSQL,
+            Dialect::Postgres | Dialect::Sqlite => MIGRATIONS_STANDARD,
+        }
+    }
+
+    /// The statements taking and releasing a lock that serializes migrations across processes.
+    ///
+    /// Both lock the session, they must run on the same connection. `None` for SQLite.
+    pub fn migration_lock(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Dialect::Postgres => Some(LOCK_POSTGRES),
+            Dialect::MySql => Some(LOCK_MYSQL),
+            Dialect::Sqlite => None,
+        }
+    }
+
+    /// Rewrite `?` placeholders into the style of the dialect.
+    pub fn placeholders(self, query: &str) -> String {
+        if self != Dialect::Postgres {
+            return query.to_owned();
+        }
+
+        let mut numbered = String::with_capacity(query.len());
+        let mut count = 0;
+        for ch in query.chars() {
+            if ch == '?' {
+                count += 1;
+                numbered.push_str(&format!("${}", count));
+            } else {
+                numbered.push(ch);
+            }
+        }
+
+        numbered
+    }
+}
+
+/// The schema version this crate works with.
+pub fn expected_version() -> i64 {
+    // Both lists evolve in lock step.
+    MIGRATIONS_STANDARD.len() as i64
+}
+
+/// Determine the migrations that still need to be applied to a database at version `found`.
+pub fn pending(dialect: Dialect, found: i64) -> Result<&'static [Migration], SchemaError> {
+    let expected = expected_version();
+    if found > expected {
+        return Err(SchemaError::Newer { found, expected });
+    }
+
+    let migrations = dialect.migrations();
+    let applied = migrations
+        .iter()
+        .take_while(|migration| migration.version <= found)
+        .count();
+    Ok(&migrations[applied..])
+}
+
+/// Check that a database at version `found` can be used as is.
+pub fn verify(found: i64) -> Result<(), SchemaError> {
+    let expected = expected_version();
+    if found < expected {
+        Err(SchemaError::Outdated { found, expected })
+    } else if found > expected {
+        Err(SchemaError::Newer { found, expected })
+    } else {
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Outdated { found, expected } => write!(
+                f,
+                "Database schema version {} is older than {}, run the migrations first",
+                found, expected
+            ),
+            SchemaError::Newer { found, expected } => write!(
+                f,
+                "Database schema version {} is newer than the supported version {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_consistent() {
+        for dialect in [Dialect::Postgres, Dialect::MySql, Dialect::Sqlite] {
+            let migrations = dialect.migrations();
+            assert_eq!(migrations.len() as i64, expected_version());
+            for (index, migration) in migrations.iter().enumerate() {
+                assert_eq!(migration.version, index as i64 + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn pending_migrations() {
+        let expected = expected_version();
+        assert_eq!(pending(Dialect::Sqlite, 0).unwrap().len() as i64, expected);
+        assert_eq!(pending(Dialect::Sqlite, 1).unwrap()[0].version, 2);
+        assert!(pending(Dialect::Sqlite, expected).unwrap().is_empty());
+        assert!(pending(Dialect::Sqlite, expected + 1).is_err());
+
+        assert!(verify(expected).is_ok());
+        assert_eq!(verify(0), Err(SchemaError::Outdated { found: 0, expected }));
+        assert!(verify(expected + 1).is_err());
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(Dialect::Postgres.placeholders("VALUES (?, ?)"), "VALUES ($1, $2)");
+        assert_eq!(Dialect::MySql.placeholders("VALUES (?, ?)"), "VALUES (?, ?)");
+        assert_eq!(
+            Dialect::from_url("postgres://localhost/db"),
+            Some(Dialect::Postgres)
+        );
+        assert_eq!(Dialect::from_url("redis://localhost"), None);
+    }
+}
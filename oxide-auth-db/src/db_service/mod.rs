@@ -1,6 +1,33 @@
+use std::time::Duration;
+
+pub mod audit;
+pub mod encryption;
+pub mod health;
+pub mod migration;
+pub mod stored;
+pub mod transfer;
+
+#[cfg(any(feature = "with-redis", feature = "with-sqlx"))]
+mod trace;
+
+#[cfg(feature = "with-sqlx")]
+pub mod sql;
+
+#[cfg(feature = "with-sea-orm")]
+pub mod orm;
+
+#[cfg(any(feature = "diesel-postgres", feature = "diesel-sqlite"))]
+pub mod diesel_store;
+
+#[cfg(feature = "with-sled")]
+pub mod sled_store;
+
 #[cfg(feature = "with-redis")]
 pub mod redis;
 
+#[cfg(feature = "with-redis")]
+pub mod revocation;
+
 #[cfg(feature = "with-redis")]
 use redis::RedisDataSource;
 
@@ -9,3 +36,72 @@ use redis::RedisDataSource;
 /// for example: pub type DataSource = MysqslDataSource;
 #[cfg(feature = "with-redis")]
 pub type DataSource = RedisDataSource;
+
+/// The tenant of datasources that were not scoped to any other.
+///
+/// Everything stored before tenants were introduced belongs to this tenant.
+pub const DEFAULT_TENANT: &str = "";
+
+/// A datasource whose data is partitioned by tenant.
+///
+/// Clients, grants and tokens of one tenant are invisible to all others, so that a single database
+/// cluster can serve many isolated authorization servers. Views of different tenants share the
+/// connections of the datasource they were derived from.
+pub trait TenantScoped: Sized {
+    /// The tenant this view reads from and writes to.
+    fn tenant(&self) -> &str;
+
+    /// A view of the same database restricted to another tenant.
+    fn for_tenant(&self, tenant: &str) -> Self;
+}
+
+/// Connection pool settings shared by all datasources.
+///
+/// Every backend keeps a pool of connections instead of a single shared one, so that concurrent
+/// requests do not serialize on one socket and a single broken connection does not take down the
+/// whole registrar. The defaults mirror those of `r2d2`.
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// Maximum number of connections managed by the pool.
+    pub max_size: u32,
+
+    /// Number of idle connections the pool tries to maintain, `None` meaning `max_size`.
+    pub min_idle: Option<u32>,
+
+    /// How long to wait for a connection before giving up.
+    pub connection_timeout: Duration,
+
+    /// Idle connections are closed after this duration, if set.
+    pub idle_timeout: Option<Duration>,
+
+    /// Connections are closed after this total lifetime, if set.
+    pub max_lifetime: Option<Duration>,
+
+    /// Check the health of each connection before handing it out.
+    ///
+    /// A failed check drops the connection and another one is tried instead.
+    pub test_on_check_out: bool,
+}
+
+impl PoolConfig {
+    /// Default settings with a specific maximum pool size.
+    pub fn with_max_size(max_size: u32) -> Self {
+        PoolConfig {
+            max_size,
+            ..PoolConfig::default()
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 10,
+            min_idle: None,
+            connection_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            test_on_check_out: true,
+        }
+    }
+}
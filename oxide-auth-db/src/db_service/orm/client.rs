@@ -0,0 +1,61 @@
+//! Entity of registered clients, in the `oauth_clients` table.
+use sea_orm::entity::prelude::*;
+// The derived `ActiveModel` relies on the 2021 prelude.
+use std::convert::TryInto;
+
+use crate::db_service::stored::StoredClient;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "oauth_clients")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub tenant_id: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub client_id: String,
+    #[sea_orm(column_type = "Text")]
+    pub redirect_uri: String,
+    #[sea_orm(column_type = "Text")]
+    pub additional_redirect_uris: String,
+    #[sea_orm(column_type = "Text")]
+    pub default_scope: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub client_secret: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub settings: Option<String>,
+    pub disabled: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl From<Model> for StoredClient {
+    fn from(model: Model) -> Self {
+        StoredClient {
+            client_id: model.client_id,
+            redirect_uri: model.redirect_uri,
+            additional_redirect_uris: model.additional_redirect_uris,
+            default_scope: model.default_scope,
+            client_secret: model.client_secret,
+            settings: model.settings,
+            disabled: model.disabled != 0,
+        }
+    }
+}
+
+impl Model {
+    /// The row of a client of `tenant`.
+    pub fn new(tenant: &str, client: StoredClient) -> Self {
+        Model {
+            tenant_id: tenant.to_owned(),
+            client_id: client.client_id,
+            redirect_uri: client.redirect_uri,
+            additional_redirect_uris: client.additional_redirect_uris,
+            default_scope: client.default_scope,
+            client_secret: client.client_secret,
+            settings: client.settings,
+            disabled: i64::from(client.disabled),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Entity of authorization codes and their grants, in the `oauth_grants` table.
+use sea_orm::entity::prelude::*;
+// The derived `ActiveModel` relies on the 2021 prelude.
+use std::convert::TryInto;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "oauth_grants")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub code: String,
+    pub tenant_id: String,
+    /// The grant, encoded as JSON.
+    #[sea_orm(column_type = "Text")]
+    pub grant_data: String,
+    /// Expiration date of the grant in milliseconds since the unix epoch.
+    pub expires_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
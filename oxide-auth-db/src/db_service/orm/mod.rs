@@ -0,0 +1,991 @@
+//! Entities and repositories for applications using `sea-orm`.
+//!
+//! The entities map onto the same tables as the `SqlStore` of the `with-sqlx` feature, created by
+//! [`migrate`]. Every
+//! repository function is generic over the connection so that it can also be called with a
+//! `DatabaseTransaction`, which lets applications commit the changes to oxide-auth state together
+//! with their own writes. All of them operate within a single tenant, see [`TenantScoped`]. With
+//! a `cipher`, client secrets and grants are sealed and tokens are stored as their lookup key, see
+//! [`ValueCipher`]. [`SeaOrmStore`] implements the async primitives on top of them.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use oxide_auth::primitives::authorizer::GuardError;
+use oxide_auth::primitives::generator::{RandomGenerator, TagGrant};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenType};
+use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
+use oxide_auth::primitives::registrar::{
+    Argon2, BoundClient, Client, ClientAuthMethod, EncodedClient, GrantType, PasswordPolicy,
+    RefreshPolicy, RegisteredClient, RegistrarError,
+};
+use oxide_auth_async::frontends::sweep::Expiring;
+use oxide_auth_async::primitives::{Authorizer, Issuer, Registrar, SingleUseGuard};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    IntoActiveModel, QueryFilter, Statement, TransactionTrait,
+};
+
+use crate::db_service::audit::{self, AuditEntry};
+use crate::db_service::encryption::{self, ValueCipher};
+use crate::db_service::migration::{self, Dialect};
+use crate::db_service::stored::{bind_redirect, StoredClient, StoredGrant};
+use crate::db_service::{TenantScoped, DEFAULT_TENANT};
+
+pub mod client;
+pub mod grant;
+pub mod token;
+pub mod used_code;
+
+/// Length in bytes of generated codes and tokens.
+const TOKEN_LENGTH: usize = 16;
+
+static DEFAULT_PASSWORD_POLICY: Lazy<Argon2> = Lazy::new(Argon2::default);
+
+/// Clients, grants and tokens stored through a `sea-orm` connection.
+pub struct SeaOrmStore<C = DatabaseConnection> {
+    db: C,
+    tenant: String,
+    password_policy: Arc<dyn PasswordPolicy>,
+    cipher: Option<Arc<dyn ValueCipher>>,
+    generator: RandomGenerator,
+    usage: u64,
+    token_duration: Duration,
+}
+
+fn custom<E: std::fmt::Display>(err: E) -> DbErr {
+    DbErr::Custom(err.to_string())
+}
+
+fn dialect<C: ConnectionTrait>(db: &C) -> Dialect {
+    match db.get_database_backend() {
+        DbBackend::Postgres => Dialect::Postgres,
+        DbBackend::MySql => Dialect::MySql,
+        DbBackend::Sqlite => Dialect::Sqlite,
+    }
+}
+
+/// Apply all pending schema migrations.
+///
+/// Fails if the database has been migrated by a newer version of this crate. All migrations run in
+/// a single transaction, which on PostgreSQL and MySQL also holds the lock of
+/// `Dialect::migration_lock` so that concurrent calls of several replicas wait for each other.
+/// MySQL commits each schema change immediately regardless.
+pub async fn migrate<C: ConnectionTrait + TransactionTrait>(db: &C) -> anyhow::Result<()> {
+    let backend = db.get_database_backend();
+    let dialect = dialect(db);
+    // The transaction pins a single connection, which the session lock requires.
+    let transaction = db.begin().await?;
+    let lock = dialect.migration_lock();
+    if let Some((lock, _)) = lock {
+        transaction.execute_unprepared(lock).await?;
+    }
+
+    transaction
+        .execute_unprepared(migration::CREATE_VERSION_TABLE)
+        .await?;
+    let insert_version = dialect.placeholders(migration::INSERT_VERSION);
+
+    // Read only now, another replica may have migrated while this one waited for the lock.
+    for step in migration::pending(dialect, schema_version(&transaction).await?)? {
+        for statement in step.statements {
+            transaction.execute_unprepared(statement).await?;
+        }
+
+        let record = Statement::from_sql_and_values(
+            backend,
+            insert_version.as_str(),
+            [step.version.into(), step.description.into()],
+        );
+        transaction.execute(record).await?;
+    }
+
+    if let Some((_, unlock)) = lock {
+        transaction.execute_unprepared(unlock).await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Refuse to work with a database whose schema version is not the expected one.
+///
+/// Call this during startup, before serving any requests.
+pub async fn verify_schema<C: ConnectionTrait>(db: &C) -> anyhow::Result<()> {
+    migration::verify(schema_version(db).await?)?;
+    Ok(())
+}
+
+/// The current version of the schema, 0 for a database without any migrations.
+pub async fn schema_version<C: ConnectionTrait>(db: &C) -> anyhow::Result<i64> {
+    let query = Statement::from_string(db.get_database_backend(), migration::SELECT_VERSION);
+    match db.query_one(query).await {
+        Ok(Some(row)) => Ok(row.try_get("", "version")?),
+        Ok(None) => Ok(0),
+        // The table of migrations does not exist yet.
+        Err(DbErr::Exec(_)) | Err(DbErr::Query(_)) => Ok(0),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Check that the database answers queries.
+pub async fn health_check<C: ConnectionTrait>(db: &C) -> Result<(), DbErr> {
+    db.execute_unprepared("SELECT 1").await?;
+    Ok(())
+}
+
+/// Find a client of a tenant by its id.
+pub async fn find_client<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, client_id: &str,
+) -> Result<Option<EncodedClient>, DbErr> {
+    let model = client::Entity::find_by_id((tenant.to_owned(), client_id.to_owned()))
+        .one(db)
+        .await?;
+    let mut stored = match model {
+        Some(model) => StoredClient::from(model),
+        None => return Ok(None),
+    };
+
+    stored.client_secret = stored
+        .client_secret
+        .map(|secret| encryption::open(cipher, secret))
+        .transpose()
+        .map_err(custom)?;
+    stored.into_encoded().map(Some).map_err(custom)
+}
+
+/// Insert or update a client of a tenant.
+pub async fn save_client<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, client: &EncodedClient,
+) -> Result<(), DbErr> {
+    let mut stored = StoredClient::from_encoded(client).map_err(custom)?;
+    stored.client_secret = stored
+        .client_secret
+        .map(|secret| encryption::seal(cipher, secret))
+        .transpose()
+        .map_err(custom)?;
+    let on_conflict = OnConflict::columns([client::Column::TenantId, client::Column::ClientId])
+        .update_columns([
+            client::Column::RedirectUri,
+            client::Column::AdditionalRedirectUris,
+            client::Column::DefaultScope,
+            client::Column::ClientSecret,
+            client::Column::Settings,
+            client::Column::Disabled,
+        ])
+        .to_owned();
+
+    client::Entity::insert(client::Model::new(tenant, stored).into_active_model())
+        .on_conflict(on_conflict)
+        .exec_without_returning(db)
+        .await?;
+    Ok(())
+}
+
+/// Store the grant of an authorization code.
+pub async fn insert_grant<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, code: &str, grant: &Grant,
+) -> Result<(), DbErr> {
+    let stored = StoredGrant::from(grant);
+    let model = grant::Model {
+        code: code.to_owned(),
+        tenant_id: tenant.to_owned(),
+        grant_data: encode_grant(cipher, &stored)?,
+        expires_at: stored.expires_at(),
+    };
+
+    model.into_active_model().insert(db).await?;
+    Ok(())
+}
+
+/// Remove an authorization code, returning its grant.
+///
+/// Returns `None` if the code does not exist or has been taken concurrently.
+pub async fn take_grant<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, code: &str,
+) -> Result<Option<Grant>, DbErr> {
+    let model = grant::Entity::find_by_id(code)
+        .filter(grant::Column::TenantId.eq(tenant))
+        .one(db)
+        .await?;
+    let model = match model {
+        Some(model) => model,
+        None => return Ok(None),
+    };
+
+    // Only the request that actually deletes the code may use it.
+    let deleted = grant::Entity::delete_by_id(code).exec(db).await?;
+    if deleted.rows_affected != 1 {
+        return Ok(None);
+    }
+
+    decode_grant(cipher, model.grant_data).map(Some)
+}
+
+/// Claim an authorization code, returning `true` only for the first claim of the tenant.
+///
+/// The claim is kept until the code expires at `until`, see [`delete_expired`].
+pub async fn claim_code<C: ConnectionTrait>(
+    db: &C, tenant: &str, code: &str, until: DateTime<Utc>,
+) -> Result<bool, DbErr> {
+    let model = used_code::Model {
+        tenant_id: tenant.to_owned(),
+        code: code.to_owned(),
+        expires_at: until.timestamp_millis(),
+    };
+    let on_conflict = OnConflict::columns([used_code::Column::TenantId, used_code::Column::Code])
+        .do_nothing()
+        .to_owned();
+
+    let inserted = used_code::Entity::insert(model.into_active_model())
+        .on_conflict(on_conflict)
+        .exec_without_returning(db)
+        .await?;
+    Ok(inserted == 1)
+}
+
+/// Store an issued token.
+pub async fn insert_token<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, access: &str, refresh: Option<&str>,
+    grant: &Grant,
+) -> Result<(), DbErr> {
+    let stored = StoredGrant::from(grant);
+    let model = token::Model {
+        access_token: lookup_key(cipher, access)?,
+        tenant_id: tenant.to_owned(),
+        refresh_token: refresh.map(|refresh| lookup_key(cipher, refresh)).transpose()?,
+        grant_data: encode_grant(cipher, &stored)?,
+        expires_at: stored.expires_at(),
+    };
+
+    model.into_active_model().insert(db).await?;
+    Ok(())
+}
+
+/// Find the grant of an access token.
+pub async fn find_access<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, access: &str,
+) -> Result<Option<Grant>, DbErr> {
+    let model = token::Entity::find_by_id(lookup_key(cipher, access)?)
+        .filter(token::Column::TenantId.eq(tenant))
+        .one(db)
+        .await?;
+    match model {
+        Some(model) => decode_grant(cipher, model.grant_data).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Find the grant of a refresh token.
+pub async fn find_refresh<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, refresh: &str,
+) -> Result<Option<Grant>, DbErr> {
+    let model = token::Entity::find()
+        .filter(token::Column::TenantId.eq(tenant))
+        .filter(token::Column::RefreshToken.eq(lookup_key(cipher, refresh)?))
+        .one(db)
+        .await?;
+    match model {
+        Some(model) => decode_grant(cipher, model.grant_data).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Delete the token with the given refresh token, returning whether it existed.
+pub async fn delete_refresh<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, refresh: &str,
+) -> Result<bool, DbErr> {
+    let deleted = token::Entity::delete_many()
+        .filter(token::Column::TenantId.eq(tenant))
+        .filter(token::Column::RefreshToken.eq(lookup_key(cipher, refresh)?))
+        .exec(db)
+        .await?;
+    Ok(deleted.rows_affected == 1)
+}
+
+/// Delete all authorization codes issued to a client, returning how many there were.
+///
+/// With an `owner_id` only the codes issued on behalf of that owner are deleted. The grants are
+/// opaque to the database, so all codes of the tenant are decoded. Codes that can not be decoded
+/// are kept.
+pub async fn delete_client_grants<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, client_id: &str, owner_id: Option<&str>,
+) -> Result<u64, DbErr> {
+    let codes = grant::Entity::find()
+        .filter(grant::Column::TenantId.eq(tenant))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|model| issued_to(cipher, &model.grant_data, client_id, owner_id))
+        .map(|model| model.code);
+
+    let deleted = grant::Entity::delete_many()
+        .filter(grant::Column::TenantId.eq(tenant))
+        .filter(grant::Column::Code.is_in(codes))
+        .exec(db)
+        .await?;
+    Ok(deleted.rows_affected)
+}
+
+/// Delete all tokens issued to a client, returning how many there were.
+///
+/// Like [`delete_client_grants`], this decodes all tokens of the tenant.
+pub async fn delete_client_tokens<C: ConnectionTrait>(
+    db: &C, tenant: &str, cipher: Option<&dyn ValueCipher>, client_id: &str, owner_id: Option<&str>,
+) -> Result<u64, DbErr> {
+    let tokens = token::Entity::find()
+        .filter(token::Column::TenantId.eq(tenant))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|model| issued_to(cipher, &model.grant_data, client_id, owner_id))
+        .map(|model| model.access_token);
+
+    let deleted = token::Entity::delete_many()
+        .filter(token::Column::TenantId.eq(tenant))
+        .filter(token::Column::AccessToken.is_in(tokens))
+        .exec(db)
+        .await?;
+    Ok(deleted.rows_affected)
+}
+
+/// Delete all codes and tokens that expired at `now`, returning how many there were.
+///
+/// Refresh tokens are deleted along with their access token, claims of codes along with the code
+/// but without being counted.
+pub async fn delete_expired<C: ConnectionTrait>(
+    db: &C, tenant: &str, now: DateTime<Utc>,
+) -> Result<u64, DbErr> {
+    let now = now.timestamp_millis();
+    let grants = grant::Entity::delete_many()
+        .filter(grant::Column::TenantId.eq(tenant))
+        .filter(grant::Column::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+    let tokens = token::Entity::delete_many()
+        .filter(token::Column::TenantId.eq(tenant))
+        .filter(token::Column::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+    used_code::Entity::delete_many()
+        .filter(used_code::Column::TenantId.eq(tenant))
+        .filter(used_code::Column::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+    Ok(grants.rows_affected + tokens.rows_affected)
+}
+
+/// Append an entry to the audit log table of a tenant.
+pub async fn append_audit<C: ConnectionTrait>(
+    db: &C, tenant: &str, entry: &AuditEntry,
+) -> Result<(), DbErr> {
+    let statement = Statement::from_sql_and_values(
+        db.get_database_backend(),
+        dialect(db).placeholders(audit::INSERT_AUDIT).as_str(),
+        [
+            tenant.into(),
+            entry.occurred_at.into(),
+            entry.event.clone().into(),
+            entry.outcome.clone().into(),
+            entry.client_id.clone().into(),
+            entry.owner_id.clone().into(),
+            entry.scope.clone().into(),
+            entry.remote_addr.clone().into(),
+        ],
+    );
+    db.execute(statement).await?;
+    Ok(())
+}
+
+fn lookup_key(cipher: Option<&dyn ValueCipher>, token: &str) -> Result<String, DbErr> {
+    encryption::lookup_key(cipher, token).map_err(custom)
+}
+
+fn encode_grant(cipher: Option<&dyn ValueCipher>, stored: &StoredGrant) -> Result<String, DbErr> {
+    let data = serde_json::to_string(stored).map_err(custom)?;
+    encryption::seal(cipher, data).map_err(custom)
+}
+
+fn open_grant(cipher: Option<&dyn ValueCipher>, data: String) -> anyhow::Result<StoredGrant> {
+    let data = encryption::open(cipher, data)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn decode_grant(cipher: Option<&dyn ValueCipher>, data: String) -> Result<Grant, DbErr> {
+    let stored = open_grant(cipher, data).map_err(custom)?;
+    stored.into_grant().map_err(custom)
+}
+
+fn issued_to(
+    cipher: Option<&dyn ValueCipher>, data: &str, client_id: &str, owner_id: Option<&str>,
+) -> bool {
+    open_grant(cipher, data.to_owned()).is_ok_and(|stored| stored.issued_to(client_id, owner_id))
+}
+
+impl<C: ConnectionTrait> SeaOrmStore<C> {
+    /// Use an established connection, in the default tenant.
+    pub fn new(db: C) -> Self {
+        SeaOrmStore {
+            db,
+            tenant: DEFAULT_TENANT.to_owned(),
+            password_policy: Arc::new(DEFAULT_PASSWORD_POLICY.clone()),
+            cipher: None,
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: Duration::hours(1),
+        }
+    }
+
+    /// The underlying connection.
+    pub fn connection(&self) -> &C {
+        &self.db
+    }
+
+    /// Change how passwords are encoded while stored.
+    pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
+        self.password_policy = Arc::new(new_policy);
+    }
+
+    /// Encrypt client secrets and grants before they are stored.
+    ///
+    /// Access and refresh tokens are stored as their `ValueCipher::lookup_key` instead, tokens
+    /// issued before the cipher was set are no longer found. Secrets and grants stored before
+    /// remain readable.
+    pub fn set_cipher<V: ValueCipher + 'static>(&mut self, cipher: V) {
+        self.cipher = Some(Arc::new(cipher));
+    }
+
+    /// Set the validity of all issued tokens, one hour by default.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.token_duration = duration;
+    }
+
+    /// Insert or update the client record.
+    pub async fn register_client(&self, client: Client) -> Result<(), RegistrarError> {
+        let encoded = client.encode(&*self.password_policy);
+        save_client(&self.db, &self.tenant, self.cipher.as_deref(), &encoded)
+            .await
+            .map_err(|_| RegistrarError::PrimitiveError)
+    }
+
+    /// Remove all expired codes and tokens of the tenant, returning how many were removed.
+    pub async fn purge_expired(&self) -> Result<u64, DbErr> {
+        delete_expired(&self.db, &self.tenant, Utc::now()).await
+    }
+
+    async fn client(&self, client_id: &str) -> Result<EncodedClient, RegistrarError> {
+        match find_client(&self.db, &self.tenant, self.cipher.as_deref(), client_id).await {
+            Ok(Some(client)) => Ok(client),
+            Ok(None) => Err(RegistrarError::Unspecified),
+            Err(_) => Err(RegistrarError::PrimitiveError),
+        }
+    }
+
+    fn tag(&mut self, grant: &Grant) -> Result<String, ()> {
+        let tag = self.generator.tag(self.usage, grant)?;
+        self.usage = self.usage.wrapping_add(1);
+        Ok(tag)
+    }
+
+    async fn new_token(&mut self, mut grant: Grant) -> Result<(String, String, Grant), ()> {
+        grant.until = Utc::now() + self.token_duration;
+        let access = self.tag(&grant)?;
+        let refresh = self.tag(&grant)?;
+        insert_token(
+            &self.db,
+            &self.tenant,
+            self.cipher.as_deref(),
+            &access,
+            Some(&refresh),
+            &grant,
+        )
+        .await
+        .map_err(|_| ())?;
+        Ok((access, refresh, grant))
+    }
+}
+
+impl<C: ConnectionTrait + Clone> Clone for SeaOrmStore<C> {
+    fn clone(&self) -> Self {
+        SeaOrmStore {
+            db: self.db.clone(),
+            tenant: self.tenant.clone(),
+            password_policy: self.password_policy.clone(),
+            cipher: self.cipher.clone(),
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: self.token_duration,
+        }
+    }
+}
+
+impl<C: ConnectionTrait + Clone> TenantScoped for SeaOrmStore<C> {
+    fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    fn for_tenant(&self, tenant: &str) -> Self {
+        SeaOrmStore {
+            tenant: tenant.to_owned(),
+            ..self.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl<C: ConnectionTrait + Send> Registrar for SeaOrmStore<C> {
+    async fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        let client = self.client(&bound.client_id).await?;
+        bind_redirect(client, bound)
+    }
+
+    async fn negotiate<'a>(
+        &self, bound: BoundClient<'a>, _scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        let client = self.client(&bound.client_id).await?;
+        Ok(PreGrant {
+            client_id: bound.client_id.into_owned(),
+            redirect_uri: bound.redirect_uri.into_owned(),
+            scope: client.default_scope,
+        })
+    }
+
+    async fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        let client = self.client(client_id).await?;
+        RegisteredClient::new(&client, &*self.password_policy).check_authentication(passphrase)
+    }
+
+    async fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.client(client_id)
+            .await
+            .map(|client| client.refresh_policy)
+            .unwrap_or_default()
+    }
+
+    async fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.client(client_id)
+            .await
+            .is_ok_and(|client| client.permits_grant(grant_type))
+    }
+
+    async fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.client(client_id).await.ok()?.auth_method
+    }
+}
+
+#[async_trait]
+impl<C: ConnectionTrait + Send> Authorizer for SeaOrmStore<C> {
+    async fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+        let code = self.tag(&grant)?;
+        insert_grant(&self.db, &self.tenant, self.cipher.as_deref(), &code, &grant)
+            .await
+            .map_err(|_| ())?;
+        Ok(code)
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        take_grant(&self.db, &self.tenant, self.cipher.as_deref(), code)
+            .await
+            .map_err(|_| ())
+    }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        let deleted =
+            delete_client_grants(&self.db, &self.tenant, self.cipher.as_deref(), client_id, None)
+                .await
+                .map_err(|_| ())?;
+        Ok(deleted as usize)
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        let deleted = delete_client_grants(
+            &self.db,
+            &self.tenant,
+            self.cipher.as_deref(),
+            client_id,
+            Some(owner_id),
+        )
+        .await
+        .map_err(|_| ())?;
+        Ok(deleted as usize)
+    }
+}
+
+/// Claims are rows of the `oauth_used_codes` table, see [`claim_code`].
+///
+/// Guards another authorizer through `SingleUse`, the codes of the store itself are already
+/// redeemed atomically.
+#[async_trait]
+impl<C: ConnectionTrait + Send + Sync> SingleUseGuard for SeaOrmStore<C> {
+    async fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        claim_code(&self.db, &self.tenant, code, until)
+            .await
+            .map_err(|_| GuardError)
+    }
+}
+
+#[async_trait]
+impl<C: ConnectionTrait + Send> Issuer for SeaOrmStore<C> {
+    async fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        let (access, refresh, grant) = self.new_token(grant).await?;
+        Ok(IssuedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        // Should only be called on valid refresh tokens.
+        if !delete_refresh(&self.db, &self.tenant, self.cipher.as_deref(), refresh)
+            .await
+            .map_err(|_| ())?
+        {
+            return Err(());
+        }
+
+        let (access, refresh, grant) = self.new_token(grant).await?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        find_access(&self.db, &self.tenant, self.cipher.as_deref(), token)
+            .await
+            .map_err(|_| ())
+    }
+
+    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        find_refresh(&self.db, &self.tenant, self.cipher.as_deref(), token)
+            .await
+            .map_err(|_| ())
+    }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        let deleted =
+            delete_client_tokens(&self.db, &self.tenant, self.cipher.as_deref(), client_id, None)
+                .await
+                .map_err(|_| ())?;
+        Ok(deleted as usize)
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        let deleted = delete_client_tokens(
+            &self.db,
+            &self.tenant,
+            self.cipher.as_deref(),
+            client_id,
+            Some(owner_id),
+        )
+        .await
+        .map_err(|_| ())?;
+        Ok(deleted as usize)
+    }
+}
+
+#[async_trait]
+impl<C: ConnectionTrait + Send> Expiring for SeaOrmStore<C> {
+    async fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        let deleted = delete_expired(&self.db, &self.tenant, now)
+            .await
+            .map_err(|_| ())?;
+        Ok(deleted as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::registrar::{ExactUrl, RegisteredUrl};
+    use sea_orm::{ConnectOptions, Database};
+
+    async fn store() -> SeaOrmStore {
+        // Every connection would see its own in-memory database.
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        options.max_connections(1);
+        let db = Database::connect(options).await.unwrap();
+        migrate(&db).await.unwrap();
+        verify_schema(&db).await.unwrap();
+        health_check(&db).await.unwrap();
+        SeaOrmStore::new(db)
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn primitives() {
+        let mut store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        store
+            .register_client(Client::public(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+            ))
+            .await
+            .unwrap();
+
+        let bound = store
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Borrowed("Client"),
+                redirect_uri: None,
+            })
+            .await
+            .unwrap();
+        store.negotiate(bound, None).await.unwrap();
+        store.check("Client", None).await.unwrap();
+
+        let code = store.authorize(grant()).await.unwrap();
+        let grant = store.extract(&code).await.unwrap().unwrap();
+        assert_eq!(store.extract(&code).await.unwrap(), None);
+
+        let issued = store.issue(grant).await.unwrap();
+        let refresh = issued.refresh.unwrap();
+        let grant = store.recover_refresh(&refresh).await.unwrap().unwrap();
+        let refreshed = store.refresh(&refresh, grant).await.unwrap();
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert!(store.recover_token(&refreshed.token).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn registration_settings() {
+        let store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::builder("Client")
+            .redirect_uri(RegisteredUrl::from(url))
+            .default_scope("default".parse().unwrap())
+            .allowed_scope("default extra".parse().unwrap())
+            .grant_types([GrantType::ClientCredentials])
+            .passphrase(b"secret")
+            .auth_method(ClientAuthMethod::ClientSecretPost)
+            .metadata("client_name", "Example")
+            .build()
+            .unwrap();
+        store.register_client(client).await.unwrap();
+
+        assert!(store.permits_grant("Client", GrantType::ClientCredentials).await);
+        assert!(!store.permits_grant("Client", GrantType::RefreshToken).await);
+        assert_eq!(
+            store.auth_method("Client").await,
+            Some(ClientAuthMethod::ClientSecretPost)
+        );
+        let client = store.client("Client").await.unwrap();
+        assert_eq!(client.allowed_scope, Some("default extra".parse().unwrap()));
+        assert_eq!(client.metadata["client_name"], "Example");
+    }
+
+    #[tokio::test]
+    async fn refresh_policy() {
+        let store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        for (id, policy) in [
+            ("Never", RefreshPolicy::Never),
+            ("Offline", RefreshPolicy::OfflineAccess),
+        ] {
+            let client =
+                Client::public(id, RegisteredUrl::from(url.clone()), "default".parse().unwrap())
+                    .with_refresh_policy(policy);
+            store.register_client(client).await.unwrap();
+            assert_eq!(store.refresh_policy(id).await, policy);
+        }
+
+        assert_eq!(store.refresh_policy("Unknown").await, RefreshPolicy::Always);
+    }
+
+    #[tokio::test]
+    async fn disabled_client() {
+        let store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::public("Client", RegisteredUrl::from(url), "default".parse().unwrap());
+        let mut encoded = client.encode(&Argon2::default());
+        encoded.disabled = true;
+        save_client(&store.db, &store.tenant, None, &encoded)
+            .await
+            .unwrap();
+
+        assert!(store.client("Client").await.unwrap().disabled);
+        let bound = store
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Borrowed("Client"),
+                redirect_uri: None,
+            })
+            .await;
+        assert!(bound.is_err());
+        assert!(store.check("Client", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn claims_race() {
+        let store = store().await;
+        let replica = store.clone();
+        let until = Utc::now() + Duration::minutes(10);
+
+        let (first, second) = tokio::join!(store.claim("code", until), replica.claim("code", until));
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert!(first != second, "exactly one claim must win");
+        assert!(!store.claim("code", until).await.unwrap());
+        assert!(store.for_tenant("other").claim("code", until).await.unwrap());
+
+        let expired = Utc::now() - Duration::minutes(1);
+        assert!(store.claim("expired", expired).await.unwrap());
+        store.purge_expired().await.unwrap();
+        assert!(store.claim("expired", expired).await.unwrap());
+        assert!(!store.claim("code", until).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn sealed_values() {
+        use crate::db_service::encryption::{is_sealed, EnvelopeCipher, StaticKeys};
+        use oxide_auth::primitives::grant::Value;
+
+        let mut store = store().await;
+        store.set_cipher(EnvelopeCipher::new(StaticKeys::new("1", [7; 32])));
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        store
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+                pass,
+            ))
+            .await
+            .unwrap();
+        let mut grant = grant();
+        grant
+            .extensions
+            .set_raw("pkce".into(), Value::private(Some("challenge".into())));
+        let code = store.authorize(grant.clone()).await.unwrap();
+        let issued = store.issue(grant.clone()).await.unwrap();
+        let refresh = issued.refresh.clone().unwrap();
+
+        let db = store.connection();
+        let client = client::Entity::find().one(db).await.unwrap().unwrap();
+        assert!(is_sealed(&client.client_secret.unwrap()));
+        let stored = grant::Entity::find().one(db).await.unwrap().unwrap();
+        assert!(!stored.grant_data.contains("challenge") && !stored.grant_data.contains("Owner"));
+        let stored = token::Entity::find().one(db).await.unwrap().unwrap();
+        assert!(stored.access_token != issued.token);
+        assert!(stored.refresh_token != Some(refresh.clone()));
+        assert!(!stored.grant_data.contains("challenge") && !stored.grant_data.contains("Owner"));
+
+        store.check("Client", Some(pass)).await.unwrap();
+        assert_eq!(
+            store.extract(&code).await.unwrap().unwrap().extensions,
+            grant.extensions
+        );
+        let recovered = store.recover_token(&issued.token).await.unwrap().unwrap();
+        assert_eq!(recovered.extensions, grant.extensions);
+        assert!(store.recover_refresh(&refresh).await.unwrap().is_some());
+        store.refresh(&refresh, recovered).await.unwrap();
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert_eq!(Issuer::revoke_client(&mut store, "Client").await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn purge_expired() {
+        let mut store = store().await;
+        let expired = Grant {
+            until: Utc::now() - Duration::minutes(1),
+            ..grant()
+        };
+        let code = store.authorize(expired).await.unwrap();
+        let kept_code = store.authorize(grant()).await.unwrap();
+        store.valid_for(Duration::minutes(-1));
+        let issued = store.issue(grant()).await.unwrap();
+
+        assert_eq!(store.purge_expired().await.unwrap(), 2);
+        assert_eq!(store.extract(&code).await.unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert!(store.extract(&kept_code).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn revoke_client() {
+        let mut store = store().await;
+        let other = Grant {
+            client_id: "Other".into(),
+            ..grant()
+        };
+        let code = store.authorize(grant()).await.unwrap();
+        let kept_code = store.authorize(other.clone()).await.unwrap();
+        let issued = store.issue(grant()).await.unwrap();
+        let kept = store.issue(other).await.unwrap();
+
+        assert_eq!(Authorizer::revoke_client(&mut store, "Client").await, Ok(1));
+        assert_eq!(Issuer::revoke_client(&mut store, "Client").await, Ok(1));
+        assert_eq!(store.extract(&code).await.unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert!(store.extract(&kept_code).await.unwrap().is_some());
+        assert!(store.recover_token(&kept.token).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn shares_transactions() {
+        let store = store().await;
+        let transaction = store.connection().begin().await.unwrap();
+        insert_grant(&transaction, "", None, "code", &grant())
+            .await
+            .unwrap();
+        transaction.rollback().await.unwrap();
+        assert_eq!(
+            take_grant(store.connection(), "", None, "code").await.unwrap(),
+            None
+        );
+
+        let transaction = store.connection().begin().await.unwrap();
+        insert_grant(&transaction, "", None, "code", &grant())
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+        assert!(take_grant(store.connection(), "", None, "code")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn tenants_are_isolated() {
+        let store = store().await;
+        let mut first = store.for_tenant("first");
+        let mut second = store.for_tenant("second");
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        first
+            .register_client(Client::public(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+            ))
+            .await
+            .unwrap();
+        first.check("Client", None).await.unwrap();
+        assert!(second.check("Client", None).await.is_err());
+
+        let code = first.authorize(grant()).await.unwrap();
+        assert_eq!(second.extract(&code).await.unwrap(), None);
+        assert!(first.extract(&code).await.unwrap().is_some());
+
+        let issued = first.issue(grant()).await.unwrap();
+        assert_eq!(second.recover_token(&issued.token).await.unwrap(), None);
+        assert!(first.recover_token(&issued.token).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,24 @@
+//! Entity of issued access and refresh tokens, in the `oauth_tokens` table.
+use sea_orm::entity::prelude::*;
+// The derived `ActiveModel` relies on the 2021 prelude.
+use std::convert::TryInto;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "oauth_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub access_token: String,
+    pub tenant_id: String,
+    #[sea_orm(unique, nullable)]
+    pub refresh_token: Option<String>,
+    /// The grant, encoded as JSON.
+    #[sea_orm(column_type = "Text")]
+    pub grant_data: String,
+    /// Expiration date of the grant in milliseconds since the unix epoch.
+    pub expires_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,20 @@
+//! Entity of claimed authorization codes, in the `oauth_used_codes` table.
+use sea_orm::entity::prelude::*;
+// The derived `ActiveModel` relies on the 2021 prelude.
+use std::convert::TryInto;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "oauth_used_codes")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub tenant_id: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub code: String,
+    /// Expiration date of the code in milliseconds since the unix epoch.
+    pub expires_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,24 +1,53 @@
+use crate::db_service::audit::{AuditEntry, AuditLog};
+use crate::db_service::encryption::{is_sealed, ValueCipher};
+use crate::db_service::health::{Backoff, HealthMonitor, HealthStatus};
+use crate::db_service::revocation::{RevocationSubscriber, DEFAULT_REVOCATION_CHANNEL};
+use crate::db_service::{PoolConfig, TenantScoped, DEFAULT_TENANT};
+use crate::db_service::stored::StoredClient;
+use crate::db_service::trace;
+use crate::db_service::transfer::{Export, Import, Record, Records};
 use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::revocation::Revocation;
 
+use oxide_auth::primitives::authorizer::{GuardError, SingleUseGuard};
 use oxide_auth::primitives::prelude::Scope;
-use oxide_auth::primitives::registrar::{ClientType, EncodedClient, RegisteredUrl, ExactUrl};
+use oxide_auth::primitives::registrar::{
+    ClientAuthMethod, ClientType, EncodedClient, GrantType, RefreshPolicy, RegisteredUrl, ExactUrl,
+};
 
 use r2d2_redis::r2d2::Pool;
-use r2d2_redis::redis::{Commands, RedisError, ErrorKind};
+use r2d2_redis::r2d2::PooledConnection;
+use r2d2_redis::redis::{Commands, Connection, RedisError, RedisResult, ErrorKind};
 use r2d2_redis::RedisConnectionManager;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use url::Url;
 
 // // TODO 参数化
 // pub const CLIENT_PREFIX: &str = "client:";
 
+/// The stream receiving audit entries unless configured otherwise.
+pub const DEFAULT_AUDIT_STREAM: &str = "oauth:audit";
+
+/// The prefix of the keys recording claimed codes unless configured otherwise.
+pub const DEFAULT_USED_CODE_PREFIX: &str = "used_code:";
+
 /// redis datasource to Client entries.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct RedisDataSource {
     url: String,
     pool: Pool<RedisConnectionManager>,
     client_prefix: String,
+    cipher: Option<Arc<dyn ValueCipher>>,
+    audit_stream: String,
+    revocation_channel: String,
+    used_code_prefix: String,
+    tenant: String,
+    health: Arc<HealthMonitor>,
 }
 
 /// A client whose credentials have been wrapped by a password policy.
@@ -45,6 +74,30 @@ pub struct StringfiedEncodedClient {
 
     /// client_secret, for authentication.
     pub client_secret: Option<String>,
+
+    /// When the client is issued refresh tokens.
+    #[serde(default)]
+    pub refresh_policy: RefreshPolicy,
+
+    /// The scope the client may request, if it may request other than its default scope.
+    #[serde(default)]
+    pub allowed_scope: Option<String>,
+
+    /// The grant types the client registered for, all if empty.
+    #[serde(default)]
+    pub grant_types: Vec<GrantType>,
+
+    /// How the client authenticates, any method fitting its type if not given.
+    #[serde(default)]
+    pub auth_method: Option<ClientAuthMethod>,
+
+    /// Further registration metadata.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+
+    /// Whether the client was disabled.
+    #[serde(default)]
+    pub disabled: bool,
 }
 
 impl StringfiedEncodedClient {
@@ -72,6 +125,12 @@ impl StringfiedEncodedClient {
             )
             .unwrap(),
             encoded_client: client_type,
+            refresh_policy: self.refresh_policy,
+            allowed_scope: self.allowed_scope.as_deref().map(Scope::from_str).transpose()?,
+            grant_types: self.grant_types.clone(),
+            auth_method: self.auth_method,
+            metadata: self.metadata.clone(),
+            disabled: self.disabled,
         })
     }
 
@@ -92,28 +151,103 @@ impl StringfiedEncodedClient {
             additional_redirect_uris,
             default_scope,
             client_secret,
+            refresh_policy: encoded_client.refresh_policy,
+            allowed_scope: encoded_client.allowed_scope.as_ref().map(Scope::to_string),
+            grant_types: encoded_client.grant_types.clone(),
+            auth_method: encoded_client.auth_method,
+            metadata: encoded_client.metadata.clone(),
+            disabled: encoded_client.disabled,
         }
     }
 }
 
 impl RedisDataSource {
     pub fn new(url: String, max_pool_size: u32, client_prefix: String) -> Result<Self, RedisError> {
+        RedisDataSource::with_config(url, PoolConfig::with_max_size(max_pool_size), client_prefix)
+    }
+
+    pub fn new_with_url(
+        url: Url, max_pool_size: u32, client_prefix: String,
+    ) -> Result<Self, RedisError> {
+        RedisDataSource::new(url.into(), max_pool_size, client_prefix)
+    }
+
+    /// Create a datasource whose connection pool is configured in detail.
+    pub fn with_config(
+        url: String, config: PoolConfig, client_prefix: String,
+    ) -> Result<Self, RedisError> {
         let manager = r2d2_redis::RedisConnectionManager::new(url.as_str())?;
-        let pool = Pool::builder().max_size(max_pool_size).build(manager);
+        let pool = Pool::builder()
+            .max_size(config.max_size)
+            .min_idle(config.min_idle)
+            .connection_timeout(config.connection_timeout)
+            .idle_timeout(config.idle_timeout)
+            .max_lifetime(config.max_lifetime)
+            .test_on_check_out(config.test_on_check_out)
+            .build(manager);
         match pool {
             Ok(pool) => Ok(RedisDataSource {
                 url,
                 pool,
                 client_prefix,
+                cipher: None,
+                audit_stream: DEFAULT_AUDIT_STREAM.to_owned(),
+                revocation_channel: DEFAULT_REVOCATION_CHANNEL.to_owned(),
+                used_code_prefix: DEFAULT_USED_CODE_PREFIX.to_owned(),
+                tenant: DEFAULT_TENANT.to_owned(),
+                health: Arc::new(HealthMonitor::default()),
             }),
             Err(_e) => Err(RedisError::from((ErrorKind::ClientError, "Build pool error."))),
         }
     }
 
-    pub fn new_with_url(
-        url: Url, max_pool_size: u32, client_prefix: String,
-    ) -> Result<Self, RedisError> {
-        RedisDataSource::new(url.into(), max_pool_size, client_prefix)
+    /// Encrypt client secrets before they are stored.
+    ///
+    /// Secrets stored before encryption was enabled remain readable, use `reencrypt` to convert
+    /// them as well as secrets sealed with a previous key version.
+    pub fn with_cipher<C: ValueCipher + 'static>(mut self, cipher: C) -> Self {
+        self.cipher = Some(Arc::new(cipher));
+        self
+    }
+
+    /// Append audit entries to another stream than `DEFAULT_AUDIT_STREAM`.
+    pub fn with_audit_stream(mut self, key: String) -> Self {
+        self.audit_stream = key;
+        self
+    }
+
+    /// Announce revocations on another channel than `DEFAULT_REVOCATION_CHANNEL`.
+    pub fn with_revocation_channel(mut self, channel: String) -> Self {
+        self.revocation_channel = channel;
+        self
+    }
+
+    /// Record claimed codes under another prefix than `DEFAULT_USED_CODE_PREFIX`.
+    pub fn with_used_code_prefix(mut self, prefix: String) -> Self {
+        self.used_code_prefix = prefix;
+        self
+    }
+
+    /// Change the delays between reconnect attempts while Redis is unreachable.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.health = Arc::new(HealthMonitor::new(backoff));
+        self
+    }
+
+    /// Ping the server.
+    ///
+    /// The check is made even while reconnects are delayed by the backoff, and a successful one
+    /// ends the backoff early.
+    pub fn health_check(&self) -> anyhow::Result<()> {
+        self.execute("PING", |conn| {
+            r2d2_redis::redis::cmd("PING").query::<String>(conn)
+        })?;
+        Ok(())
+    }
+
+    /// Whether Redis is currently reachable, with failure counts for monitoring.
+    pub fn health_status(&self) -> HealthStatus {
+        self.health.status()
     }
 
     pub fn get_url(&self) -> String {
@@ -127,30 +261,266 @@ impl RedisDataSource {
 impl RedisDataSource {
     /// users can regist to redis a custom client struct which can be Serialized and Deserialized.
     pub fn regist(&self, detail: &StringfiedEncodedClient) -> anyhow::Result<()> {
-        let mut pool = self.pool.get()?;
-        let client_str = serde_json::to_string(&detail)?;
-        pool.set::<_, _, ()>(&(self.client_prefix.to_owned() + &detail.client_id), client_str)?;
+        let client_str = serde_json::to_string(&self.seal(detail.clone())?)?;
+        let key = self.key(&(self.client_prefix.to_owned() + &detail.client_id));
+        self.run("SET", |conn| conn.set::<_, _, ()>(&key, &client_str))
+    }
+
+    /// Tell the caches of all replicas subscribed to this tenant to forget a revoked item.
+    ///
+    /// Revoke the item at its source first, otherwise the caches would simply load it again.
+    pub fn publish_revocation(&self, revocation: &Revocation) -> anyhow::Result<()> {
+        let channel = self.key(&self.revocation_channel);
+        let message = revocation.to_message();
+        self.run("PUBLISH", |conn| conn.publish::<_, _, ()>(&channel, &message))
+    }
+
+    /// A subscriber to the revocations of this tenant, including changes to its stored clients.
+    pub fn revocation_subscriber(&self) -> RevocationSubscriber {
+        RevocationSubscriber::new(
+            self.url.clone(),
+            self.key(&self.revocation_channel),
+            self.key(&self.client_prefix),
+        )
+    }
+
+    /// Encrypt all stored client secrets with the current key.
+    ///
+    /// Affects secrets stored in plain text and those sealed with an older key version. Returns
+    /// the number of rewritten clients.
+    pub fn reencrypt(&self) -> anyhow::Result<usize> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(0),
+        };
+
+        let mut rewrite = r2d2_redis::redis::pipe();
+        let mut count = 0;
+        for (key, stored) in self.stored_clients()? {
+            match &stored.client_secret {
+                Some(secret) if cipher.needs_rotation(secret) => (),
+                _ => continue,
+            }
+
+            let client = self.seal(self.open(stored)?)?;
+            rewrite.set(key, serde_json::to_string(&client)?).ignore();
+            count += 1;
+        }
+
+        if count > 0 {
+            self.run("SET", |conn| rewrite.query::<()>(conn))?;
+        }
+
+        Ok(count)
+    }
+
+    /// Prefix a key with the tenant, the keys of the default tenant are left as they are.
+    fn key(&self, key: &str) -> String {
+        if self.tenant.is_empty() {
+            key.to_owned()
+        } else {
+            format!("tenant:{}:{}", self.tenant, key)
+        }
+    }
+
+    /// Run a command, unless reconnects are currently delayed by the backoff.
+    fn run<T, F>(&self, operation: &'static str, command: F) -> anyhow::Result<T>
+    where
+        F: FnMut(&mut Connection) -> RedisResult<T>,
+    {
+        if let Err(remaining) = self.health.admit() {
+            anyhow::bail!("Redis is unavailable, next attempt in {:?}", remaining);
+        }
+
+        self.execute(operation, command)
+    }
+
+    /// Execute a command in its span, named after the Redis command.
+    fn execute<T, F>(&self, operation: &'static str, command: F) -> anyhow::Result<T>
+    where
+        F: FnMut(&mut Connection) -> RedisResult<T>,
+    {
+        trace::span("redis", operation).in_scope(|| self.retry(command))
+    }
+
+    fn retry<T, F>(&self, mut command: F) -> anyhow::Result<T>
+    where
+        F: FnMut(&mut Connection) -> RedisResult<T>,
+    {
+        let mut conn = self.connection()?;
+        let mut result = command(&mut conn);
+        if matches!(&result, Err(err) if is_disconnect(err)) {
+            // The pool discards the broken connection, a fresh one may well succeed.
+            conn = self.connection()?;
+            result = command(&mut conn);
+        }
+
+        match result {
+            Err(err) if is_disconnect(&err) => {
+                self.health.failure(&err);
+                Err(err.into())
+            }
+            result => {
+                self.health.success();
+                Ok(result?)
+            }
+        }
+    }
+
+    fn connection(&self) -> anyhow::Result<PooledConnection<RedisConnectionManager>> {
+        self.pool.get().map_err(|err| {
+            self.health.failure(&err);
+            err.into()
+        })
+    }
+
+    /// All clients of the tenant as stored, with their keys.
+    ///
+    /// The clients are fetched with a single `MGET` instead of one round trip each. Clients
+    /// deleted after their key was listed are skipped.
+    fn stored_clients(&self) -> anyhow::Result<Vec<(String, StringfiedEncodedClient)>> {
+        let pattern = self.key(&(self.client_prefix.to_owned() + "*"));
+        let keys = self.run("KEYS", |conn| conn.keys::<_, Vec<String>>(&pattern))?;
+        if keys.is_empty() {
+            // `MGET` requires at least one key.
+            return Ok(Vec::new());
+        }
+
+        let mut command = r2d2_redis::redis::cmd("MGET");
+        command.arg(&keys[..]);
+        let values = self.run("MGET", |conn| command.query::<Vec<Option<String>>>(conn))?;
+
+        let mut clients = Vec::with_capacity(keys.len());
+        for (key, value) in keys.into_iter().zip(values) {
+            if let Some(value) = value {
+                clients.push((key, serde_json::from_str(&value)?));
+            }
+        }
+        Ok(clients)
+    }
+
+    fn get_client(&self, key: &str) -> anyhow::Result<StringfiedEncodedClient> {
+        let client_str = self.run("GET", |conn| conn.get::<_, String>(key))?;
+        let stored = serde_json::from_str::<StringfiedEncodedClient>(&client_str)?;
+        self.open(stored)
+    }
+
+    fn seal(&self, mut client: StringfiedEncodedClient) -> anyhow::Result<StringfiedEncodedClient> {
+        if let (Some(cipher), Some(secret)) = (&self.cipher, &client.client_secret) {
+            client.client_secret = Some(cipher.encrypt(secret.as_bytes())?);
+        }
+
+        Ok(client)
+    }
+
+    fn open(&self, mut client: StringfiedEncodedClient) -> anyhow::Result<StringfiedEncodedClient> {
+        match (&self.cipher, &client.client_secret) {
+            (Some(cipher), Some(secret)) if is_sealed(secret) => {
+                client.client_secret = Some(String::from_utf8(cipher.decrypt(secret)?)?);
+            }
+            (None, Some(secret)) if is_sealed(secret) => {
+                return Err(anyhow::anyhow!(
+                    "Client secret is encrypted but no cipher is configured"
+                ))
+            }
+            _ => (),
+        }
+
+        Ok(client)
+    }
+}
+
+impl fmt::Debug for RedisDataSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisDataSource")
+            .field("url", &self.url)
+            .field("pool", &self.pool)
+            .field("client_prefix", &self.client_prefix)
+            .field("cipher", &self.cipher.is_some())
+            .field("audit_stream", &self.audit_stream)
+            .field("revocation_channel", &self.revocation_channel)
+            .field("used_code_prefix", &self.used_code_prefix)
+            .field("tenant", &self.tenant)
+            .field("health", &self.health.status())
+            .finish()
+    }
+}
+
+/// Keys of other tenants than the default one are prefixed with `tenant:<name>:`.
+impl TenantScoped for RedisDataSource {
+    fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    fn for_tenant(&self, tenant: &str) -> Self {
+        RedisDataSource {
+            tenant: tenant.to_owned(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Entries are added to a stream with one field per present value.
+impl AuditLog for RedisDataSource {
+    fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        let mut command = r2d2_redis::redis::cmd("XADD");
+        command
+            .arg(self.key(&self.audit_stream))
+            .arg("*")
+            .arg("occurred_at")
+            .arg(entry.occurred_at)
+            .arg("event")
+            .arg(&entry.event)
+            .arg("outcome")
+            .arg(&entry.outcome);
+        let optional = [
+            ("client_id", &entry.client_id),
+            ("owner_id", &entry.owner_id),
+            ("scope", &entry.scope),
+            ("remote_addr", &entry.remote_addr),
+        ];
+        for (field, value) in optional.iter() {
+            if let Some(value) = value {
+                command.arg(*field).arg(value);
+            }
+        }
+
+        self.run("XADD", |conn| command.query::<String>(conn))?;
         Ok(())
     }
 }
 
+/// Claims are keys set only if absent, expiring together with the code.
+impl SingleUseGuard for RedisDataSource {
+    fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        let key = self.key(&(self.used_code_prefix.to_owned() + code));
+        // Expired codes are refused by the flow anyways, their claims need not outlive them.
+        let ttl = (until - Utc::now()).num_milliseconds().max(1);
+        let mut command = r2d2_redis::redis::cmd("SET");
+        command.arg(&key).arg(1).arg("NX").arg("PX").arg(ttl);
+        let reply = self
+            .run("SET", |conn| command.query::<Option<String>>(conn))
+            .map_err(|_| GuardError)?;
+        Ok(reply.is_some())
+    }
+}
+
+/// Errors after which the connection can not be used any longer.
+fn is_disconnect(err: &RedisError) -> bool {
+    err.is_io_error() || err.is_connection_dropped() || err.is_connection_refusal() || err.is_timeout()
+}
+
 impl OauthClientDBRepository for RedisDataSource {
     fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
         let mut encoded_clients: Vec<EncodedClient> = vec![];
-        let mut r = self.pool.get()?;
-        let keys = r.keys::<&str, Vec<String>>(&self.client_prefix)?;
-        for key in keys {
-            let clients_str = r.get::<String, String>(key)?;
-            let stringfied_client = serde_json::from_str::<StringfiedEncodedClient>(&clients_str)?;
-            encoded_clients.push(stringfied_client.to_encoded_client()?);
+        for (_, stored) in self.stored_clients()? {
+            encoded_clients.push(self.open(stored)?.to_encoded_client()?);
         }
         Ok(encoded_clients)
     }
 
     fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
-        let mut r = self.pool.get()?;
-        let client_str = r.get::<&str, String>(&(self.client_prefix.to_owned() + id))?;
-        let stringfied_client = serde_json::from_str::<StringfiedEncodedClient>(&client_str)?;
+        let stringfied_client = self.get_client(&self.key(&(self.client_prefix.to_owned() + id)))?;
         stringfied_client.to_encoded_client()
     }
 
@@ -159,3 +529,78 @@ impl OauthClientDBRepository for RedisDataSource {
         self.regist(&detail)
     }
 }
+
+/// Redis only stores clients, codes and tokens of the authorization server are kept elsewhere.
+impl Export for RedisDataSource {
+    fn export(&self) -> anyhow::Result<Records<'_>> {
+        let clients = self.list()?;
+        Ok(Box::new(clients.into_iter().map(|client| {
+            StoredClient::from_encoded(&client).map(Record::Client)
+        })))
+    }
+}
+
+impl Import for RedisDataSource {
+    fn import(&mut self, record: Record) -> anyhow::Result<()> {
+        match record {
+            Record::Client(client) => self.regist_from_encoded_client(client.into_encoded()?),
+            other => anyhow::bail!("The Redis datasource can not store {}", other.key()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a Redis server on localhost"]
+    fn claims_race() {
+        let source = RedisDataSource::new("redis://localhost/3".into(), 32, "client:".into()).unwrap();
+        let replica = source.clone();
+        let until = Utc::now() + chrono::Duration::minutes(1);
+        // Claims outlive the test, a fresh code avoids those of earlier runs.
+        let code = format!("race-{}", Utc::now().timestamp_micros());
+
+        let (first, second) = std::thread::scope(|scope| {
+            let first = scope.spawn(|| source.claim(&code, until));
+            let second = scope.spawn(|| replica.claim(&code, until));
+            (first.join().unwrap(), second.join().unwrap())
+        });
+        assert!(first.unwrap() != second.unwrap(), "exactly one claim must win");
+        assert!(!source.claim(&code, until).unwrap());
+        assert!(source.for_tenant("other").claim(&code, until).unwrap());
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server on localhost"]
+    fn sealed_secrets() {
+        use crate::db_service::encryption::{EnvelopeCipher, StaticKeys};
+        use oxide_auth::primitives::registrar::{Argon2, Client};
+
+        let source = RedisDataSource::new("redis://localhost/3".into(), 32, "client:".into())
+            .unwrap()
+            .for_tenant("sealed")
+            .with_cipher(EnvelopeCipher::new(StaticKeys::new("1", [7; 32])));
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::confidential(
+            "Client",
+            RegisteredUrl::from(url),
+            "default".parse().unwrap(),
+            b"AB3fAj6GJpdxmEVeNCyPoA==",
+        )
+        .encode(&Argon2::default());
+        source.regist_from_encoded_client(client.clone()).unwrap();
+
+        let key = source.key("client:Client");
+        let raw = source.run("GET", |conn| conn.get::<_, String>(&key)).unwrap();
+        let raw: StringfiedEncodedClient = serde_json::from_str(&raw).unwrap();
+        let plaintext = StringfiedEncodedClient::from_encoded_client(&client);
+        assert!(is_sealed(raw.client_secret.as_deref().unwrap()));
+        assert_ne!(raw.client_secret, plaintext.client_secret);
+
+        let found = source.find_client_by_id("Client").unwrap();
+        let found = StringfiedEncodedClient::from_encoded_client(&found);
+        assert_eq!(found.client_secret, plaintext.client_secret);
+    }
+}
@@ -0,0 +1,185 @@
+//! Propagation of revocations between replicas through Redis.
+//!
+//! Revoked tokens are announced with `RedisDataSource::publish_revocation` on a pub/sub channel,
+//! by their digest so that subscribers to the server never see the tokens themselves.
+//! Changes to stored clients need no explicit announcement, they are picked up from the keyspace
+//! notifications of Redis. These have to be enabled on the server, for example with
+//! `CONFIG SET notify-keyspace-events Kg$x`.
+//!
+//! A [`RevocationSubscriber`] listens to both on a thread of its own and forwards everything to
+//! the invalidators of the in-process caches.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use r2d2_redis::redis::{self, Msg, RedisResult};
+
+use crate::db_service::health::Backoff;
+use crate::primitives::revocation::{Revocation, RevocationSink};
+
+/// The channel carrying revocations unless configured otherwise.
+pub const DEFAULT_REVOCATION_CHANNEL: &str = "oauth:revocations";
+
+/// How often the listening thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Forwards revocations announced through Redis to in-process caches.
+///
+/// Obtained from `RedisDataSource::revocation_subscriber`, so that it observes the same tenant.
+pub struct RevocationSubscriber {
+    url: String,
+    channel: String,
+    client_keys: String,
+    sinks: Vec<Box<dyn RevocationSink>>,
+    backoff: Backoff,
+}
+
+/// The thread of a running [`RevocationSubscriber`].
+pub struct SubscriberHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl RevocationSubscriber {
+    pub(crate) fn new(url: String, channel: String, client_keys: String) -> Self {
+        RevocationSubscriber {
+            url,
+            channel,
+            client_keys,
+            sinks: Vec::new(),
+            backoff: Backoff::default(),
+        }
+    }
+
+    /// Forward revocations to another sink, typically the invalidator of a cache.
+    pub fn with_sink<S: RevocationSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Change the delays between attempts to resubscribe after the connection was lost.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Start listening on a new thread.
+    pub fn spawn(self) -> std::io::Result<SubscriberHandle> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let thread = thread::Builder::new()
+            .name("oauth-revocations".into())
+            .spawn(move || self.run(&flag))?;
+        Ok(SubscriberHandle { stop, thread })
+    }
+
+    fn run(&self, stop: &AtomicBool) {
+        let mut failures = 0;
+        while !stop.load(Ordering::Relaxed) {
+            match self.listen(stop, &mut failures) {
+                Ok(()) => return,
+                Err(err) => {
+                    failures += 1;
+                    log::warn!("Subscription to revocations interrupted: {}", err);
+                    thread::sleep(self.backoff.delay(failures));
+                }
+            }
+        }
+    }
+
+    fn listen(&self, stop: &AtomicBool, failures: &mut u32) -> RedisResult<()> {
+        let client = redis::Client::open(self.url.as_str())?;
+        let mut conn = client.get_connection()?;
+        let mut pubsub = conn.as_pubsub();
+        pubsub.set_read_timeout(Some(POLL_INTERVAL))?;
+        pubsub.subscribe(&self.channel)?;
+        pubsub.psubscribe(keyspace_pattern(&self.client_keys))?;
+
+        // Anything revoked before the subscription, or while it was interrupted, went unnoticed.
+        *failures = 0;
+        self.sinks.iter().for_each(|sink| sink.reset());
+
+        while !stop.load(Ordering::Relaxed) {
+            let message = match pubsub.get_message() {
+                Ok(message) => message,
+                Err(err) if err.is_timeout() => continue,
+                Err(err) => return Err(err),
+            };
+
+            if let Some(revocation) = self.parse(&message) {
+                self.sinks.iter().for_each(|sink| sink.revoke(&revocation));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse(&self, message: &Msg) -> Option<Revocation> {
+        if message.from_pattern() {
+            return self.parse_keyspace(message.get_channel_name());
+        }
+
+        let payload: String = message.get_payload().ok()?;
+        Revocation::from_message(&payload)
+    }
+
+    /// Keyspace channels are named `__keyspace@<db>__:<key>`.
+    fn parse_keyspace(&self, channel: &str) -> Option<Revocation> {
+        let (_, key) = channel.split_once("__:")?;
+        let client_id = key.strip_prefix(self.client_keys.as_str())?;
+        Some(Revocation::Client(client_id.to_owned()))
+    }
+}
+
+/// The pattern of the keyspace channels of all keys starting with `prefix`.
+///
+/// Glob characters in the prefix are escaped, so that they only match themselves.
+fn keyspace_pattern(prefix: &str) -> String {
+    let mut pattern = String::from("__keyspace@*__:");
+    for ch in prefix.chars() {
+        if matches!(ch, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(ch);
+    }
+    pattern.push('*');
+    pattern
+}
+
+impl SubscriberHandle {
+    /// Stop listening and wait for the thread to finish.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyspace_channels() {
+        let subscriber = RevocationSubscriber::new(
+            "redis://localhost/3".into(),
+            DEFAULT_REVOCATION_CHANNEL.into(),
+            "tenant:first:client:".into(),
+        );
+
+        assert_eq!(
+            subscriber.parse_keyspace("__keyspace@3__:tenant:first:client:ClientId"),
+            Some(Revocation::Client("ClientId".into()))
+        );
+        assert_eq!(subscriber.parse_keyspace("__keyspace@3__:client:ClientId"), None);
+    }
+
+    #[test]
+    fn escapes_glob_characters() {
+        assert_eq!(keyspace_pattern("client:"), "__keyspace@*__:client:*");
+        assert_eq!(
+            keyspace_pattern("t*[a]?\\:client:"),
+            "__keyspace@*__:t\\*\\[a\\]\\?\\\\:client:*"
+        );
+    }
+}
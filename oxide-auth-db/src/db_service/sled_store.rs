@@ -0,0 +1,814 @@
+//! An embedded storage for clients, grants and tokens using `sled`.
+//!
+//! Meant for edge devices and appliances which need their state to survive a restart but can not
+//! run a Redis or SQL server. The store lives in a directory of the local file system and
+//! implements the primitives of `oxide-auth` directly, with synchronous access like the
+//! `DieselStore`. Every key is prefixed with the tenant, see [`TenantScoped`].
+//!
+//! Nothing expires on its own, call [`SledStore::purge_expired`] periodically or sweep the store with
+//! an `oxide_auth::frontends::sweep::Sweeper` to keep the database from growing.
+//!
+//! The database files are not encrypted by `sled`. Set a cipher with [`SledStore::set_cipher`] to
+//! seal client secrets, codes and tokens before they are written.
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use oxide_auth::frontends::sweep::Expiring;
+use oxide_auth::primitives::generator::{RandomGenerator, TagGrant};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenType};
+use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
+use oxide_auth::primitives::registrar::{
+    Argon2, BoundClient, Client, ClientAuthMethod, EncodedClient, GrantType, PasswordPolicy,
+    RefreshPolicy, RegisteredClient, Registrar, RegistrarError,
+};
+use oxide_auth::primitives::{authorizer::Authorizer, issuer::Issuer};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::db_service::encryption::{self, ValueCipher};
+use crate::db_service::stored::{bind_redirect, StoredClient, StoredGrant};
+use crate::db_service::transfer::{Export, Import, Record, Records};
+use crate::db_service::{TenantScoped, DEFAULT_TENANT};
+
+/// Length in bytes of generated codes and tokens.
+const TOKEN_LENGTH: usize = 16;
+
+static DEFAULT_PASSWORD_POLICY: Lazy<Argon2> = Lazy::new(Argon2::default);
+
+/// Clients, grants and tokens stored in an embedded `sled` database.
+pub struct SledStore {
+    db: Db,
+    clients: Tree,
+    grants: Tree,
+    tokens: Tree,
+    refresh: Tree,
+    tenant: String,
+    password_policy: Arc<dyn PasswordPolicy>,
+    cipher: Option<Arc<dyn ValueCipher>>,
+    generator: RandomGenerator,
+    usage: u64,
+    token_duration: Duration,
+}
+
+/// The value stored per access token.
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    /// The refresh token in its stored form.
+    refresh: Option<String>,
+    grant: StoredGrant,
+}
+
+impl SledStore {
+    /// Open the database in a directory, creating it if necessary.
+    pub fn open<P: AsRef<Path>>(path: P) -> sled::Result<Self> {
+        SledStore::with_db(sled::open(path)?)
+    }
+
+    /// Use an opened database, possibly shared with the rest of the application.
+    ///
+    /// The store only touches trees whose names start with `oauth_`.
+    pub fn with_db(db: Db) -> sled::Result<Self> {
+        Ok(SledStore {
+            clients: db.open_tree("oauth_clients")?,
+            grants: db.open_tree("oauth_grants")?,
+            tokens: db.open_tree("oauth_tokens")?,
+            refresh: db.open_tree("oauth_refresh")?,
+            db,
+            tenant: DEFAULT_TENANT.to_owned(),
+            password_policy: Arc::new(DEFAULT_PASSWORD_POLICY.clone()),
+            cipher: None,
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: Duration::hours(1),
+        })
+    }
+
+    /// The underlying database.
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    /// Change how passwords are encoded while stored.
+    pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
+        self.password_policy = Arc::new(new_policy);
+    }
+
+    /// Encrypt client secrets, grants and tokens before they are stored.
+    ///
+    /// Access and refresh tokens are keyed by their `ValueCipher::lookup_key` instead, tokens
+    /// issued before the cipher was set are no longer found. Secrets and grants stored before
+    /// remain readable.
+    pub fn set_cipher<V: ValueCipher + 'static>(&mut self, cipher: V) {
+        self.cipher = Some(Arc::new(cipher));
+    }
+
+    /// Set the validity of all issued tokens, one hour by default.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.token_duration = duration;
+    }
+
+    /// Insert or update the client record.
+    pub fn register_client(&self, client: Client) -> Result<(), RegistrarError> {
+        let encoded = client.encode(&*self.password_policy);
+        let stored = StoredClient::from_encoded(&encoded).map_err(|_| RegistrarError::PrimitiveError)?;
+        self.insert_client(stored)
+            .map_err(|_| RegistrarError::PrimitiveError)
+    }
+
+    /// Remove all expired codes and tokens of the tenant, returning how many were removed.
+    pub fn purge_expired(&self) -> anyhow::Result<usize> {
+        self.purge_expired_at(Utc::now())
+    }
+
+    fn purge_expired_at(&self, now: DateTime<Utc>) -> anyhow::Result<usize> {
+        let now = now.timestamp_millis();
+        let mut purged = 0;
+
+        for entry in self.grants.scan_prefix(self.key("")) {
+            let (key, value) = entry?;
+            let grant: StoredGrant = self.open_value(&value)?;
+            if grant.expires_at() <= now && self.grants.remove(&key)?.is_some() {
+                purged += 1;
+            }
+        }
+
+        for entry in self.tokens.scan_prefix(self.key("")) {
+            let (key, value) = entry?;
+            let token: StoredToken = self.open_value(&value)?;
+            if token.grant.expires_at() > now || self.tokens.remove(&key)?.is_none() {
+                continue;
+            }
+
+            if let Some(refresh) = token.refresh {
+                self.refresh.remove(self.key(&refresh))?;
+            }
+            purged += 1;
+        }
+
+        Ok(purged)
+    }
+
+    /// Revoke an access or refresh token of the tenant, along with the token issued with it.
+    ///
+    /// Returns whether any token was revoked.
+    pub fn revoke_token(&self, token: &str) -> anyhow::Result<bool> {
+        let token = encryption::lookup_key(self.cipher.as_deref(), token)?;
+        // A refresh token revokes the access token it was issued with, and the other way around.
+        let access = match self.refresh.remove(self.key(&token))? {
+            Some(access) => std::str::from_utf8(&access)?.to_owned(),
+            None => token.clone(),
+        };
+
+        let stored = match self.tokens.remove(self.key(&access))? {
+            Some(stored) => stored,
+            None => return Ok(access != token),
+        };
+        let stored: StoredToken = self.open_value(&stored)?;
+        if let Some(refresh) = stored.refresh {
+            self.refresh.remove(self.key(&refresh))?;
+        }
+        Ok(true)
+    }
+
+    /// Write all changes to disk.
+    ///
+    /// `sled` flushes on its own in short intervals, this is only required before a shutdown or
+    /// when losing the most recent changes to a power failure is not acceptable.
+    pub fn flush(&self) -> sled::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// The key of an item of the tenant.
+    fn key(&self, id: &str) -> Vec<u8> {
+        // Tenant names are not expected to contain NUL, which makes it a safe separator.
+        let mut key = Vec::with_capacity(self.tenant.len() + 1 + id.len());
+        key.extend_from_slice(self.tenant.as_bytes());
+        key.push(0);
+        key.extend_from_slice(id.as_bytes());
+        key
+    }
+
+    fn client(&self, client_id: &str) -> Result<EncodedClient, RegistrarError> {
+        let value = self
+            .clients
+            .get(self.key(client_id))
+            .map_err(|_| RegistrarError::PrimitiveError)?
+            .ok_or(RegistrarError::Unspecified)?;
+        self.open_client(&value)
+            .and_then(StoredClient::into_encoded)
+            .map_err(|_| RegistrarError::PrimitiveError)
+    }
+
+    fn insert_client(&self, mut client: StoredClient) -> anyhow::Result<()> {
+        let cipher = self.cipher.as_deref();
+        client.client_secret = client
+            .client_secret
+            .map(|secret| encryption::seal(cipher, secret))
+            .transpose()?;
+        let value = serde_json::to_vec(&client)?;
+        self.clients.insert(self.key(&client.client_id), value)?;
+        Ok(())
+    }
+
+    fn open_client(&self, value: &[u8]) -> anyhow::Result<StoredClient> {
+        let mut client: StoredClient = serde_json::from_slice(value)?;
+        let cipher = self.cipher.as_deref();
+        client.client_secret = client
+            .client_secret
+            .map(|secret| encryption::open(cipher, secret))
+            .transpose()?;
+        Ok(client)
+    }
+
+    /// Encode a grant or token, sealed if there is a cipher.
+    fn seal_value<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        let value = serde_json::to_string(value)?;
+        Ok(encryption::seal(self.cipher.as_deref(), value)?.into_bytes())
+    }
+
+    fn open_value<T: DeserializeOwned>(&self, value: &[u8]) -> anyhow::Result<T> {
+        let value = String::from_utf8(value.to_vec())?;
+        let value = encryption::open(self.cipher.as_deref(), value)?;
+        Ok(serde_json::from_str(&value)?)
+    }
+
+    /// The stored form of an access or refresh token.
+    fn lookup_key(&self, token: &str) -> Result<String, ()> {
+        encryption::lookup_key(self.cipher.as_deref(), token).map_err(|_| ())
+    }
+
+    fn decode_grant(&self, value: &[u8]) -> Result<Grant, ()> {
+        let stored: StoredGrant = self.open_value(value).map_err(|_| ())?;
+        stored.into_grant().map_err(|_| ())
+    }
+
+    fn decode_token(&self, value: &[u8]) -> Result<Grant, ()> {
+        let stored: StoredToken = self.open_value(value).map_err(|_| ())?;
+        stored.grant.into_grant().map_err(|_| ())
+    }
+
+    fn tag(&mut self, grant: &Grant) -> Result<String, ()> {
+        let tag = self.generator.tag(self.usage, grant)?;
+        self.usage = self.usage.wrapping_add(1);
+        Ok(tag)
+    }
+
+    fn insert_token(&mut self, mut grant: Grant) -> Result<(String, String, Grant), ()> {
+        grant.until = Utc::now() + self.token_duration;
+        let access = self.tag(&grant)?;
+        let refresh = self.tag(&grant)?;
+        let (access_key, refresh_key) = (self.lookup_key(&access)?, self.lookup_key(&refresh)?);
+        let token = StoredToken {
+            refresh: Some(refresh_key.clone()),
+            grant: StoredGrant::from(&grant),
+        };
+
+        let value = self.seal_value(&token).map_err(|_| ())?;
+        self.tokens.insert(self.key(&access_key), value).map_err(|_| ())?;
+        self.refresh
+            .insert(self.key(&refresh_key), access_key.as_bytes())
+            .map_err(|_| ())?;
+        Ok((access, refresh, grant))
+    }
+
+    /// The ids and values of all items of the tenant in a tree.
+    fn scan<'a>(
+        &'a self, tree: &'a Tree,
+    ) -> impl Iterator<Item = anyhow::Result<(String, sled::IVec)>> + 'a {
+        let prefix = self.key("").len();
+        tree.scan_prefix(self.key("")).map(move |entry| {
+            let (key, value) = entry?;
+            let id = std::str::from_utf8(&key[prefix..])?.to_owned();
+            Ok((id, value))
+        })
+    }
+
+    /// Find the grant of an access token in its stored form.
+    fn find_token(&self, access: &[u8]) -> Result<Option<Grant>, ()> {
+        let key = self.key(std::str::from_utf8(access).map_err(|_| ())?);
+        match self.tokens.get(key).map_err(|_| ())? {
+            Some(value) => self.decode_token(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the codes of a client, and of an owner if one is given, keeping undecodable ones.
+    fn delete_client_grants(&self, client_id: &str, owner_id: Option<&str>) -> Result<usize, ()> {
+        let mut revoked = 0;
+        for entry in self.scan(&self.grants) {
+            let (code, value) = entry.map_err(|_| ())?;
+            match self.open_value::<StoredGrant>(&value) {
+                Ok(stored) if stored.issued_to(client_id, owner_id) => (),
+                _ => continue,
+            }
+
+            if self.grants.remove(self.key(&code)).map_err(|_| ())?.is_some() {
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+
+    /// Delete the tokens of a client, and of an owner if one is given, with their refresh tokens.
+    fn delete_client_tokens(&self, client_id: &str, owner_id: Option<&str>) -> Result<usize, ()> {
+        let mut revoked = 0;
+        for entry in self.scan(&self.tokens) {
+            let (access, value) = entry.map_err(|_| ())?;
+            let token = match self.open_value::<StoredToken>(&value) {
+                Ok(token) if token.grant.issued_to(client_id, owner_id) => token,
+                _ => continue,
+            };
+
+            if let Some(refresh) = &token.refresh {
+                self.refresh.remove(self.key(refresh)).map_err(|_| ())?;
+            }
+            if self.tokens.remove(self.key(&access)).map_err(|_| ())?.is_some() {
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+}
+
+impl Clone for SledStore {
+    fn clone(&self) -> Self {
+        SledStore {
+            db: self.db.clone(),
+            clients: self.clients.clone(),
+            grants: self.grants.clone(),
+            tokens: self.tokens.clone(),
+            refresh: self.refresh.clone(),
+            tenant: self.tenant.clone(),
+            password_policy: self.password_policy.clone(),
+            cipher: self.cipher.clone(),
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: self.token_duration,
+        }
+    }
+}
+
+impl TenantScoped for SledStore {
+    fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    fn for_tenant(&self, tenant: &str) -> Self {
+        SledStore {
+            tenant: tenant.to_owned(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Expiring for SledStore {
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        self.purge_expired_at(now).map_err(|_| ())
+    }
+}
+
+impl Export for SledStore {
+    fn export(&self) -> anyhow::Result<Records<'_>> {
+        let clients = self.scan(&self.clients).map(move |entry| {
+            let (_, value) = entry?;
+            Ok(Record::Client(self.open_client(&value)?))
+        });
+        let grants = self.scan(&self.grants).map(move |entry| {
+            let (code, value) = entry?;
+            let grant = self.open_value(&value)?;
+            Ok(Record::Grant { code, grant })
+        });
+        let tokens = self.scan(&self.tokens).map(move |entry| {
+            let (access, value) = entry?;
+            let token: StoredToken = self.open_value(&value)?;
+            Ok(Record::Token {
+                access,
+                refresh: token.refresh,
+                grant: token.grant,
+            })
+        });
+
+        Ok(Box::new(clients.chain(grants).chain(tokens)))
+    }
+}
+
+impl Import for SledStore {
+    fn import(&mut self, record: Record) -> anyhow::Result<()> {
+        match self.stored_form(record)? {
+            Record::Client(client) => self.insert_client(client)?,
+            Record::Grant { code, grant } => {
+                self.grants.insert(self.key(&code), self.seal_value(&grant)?)?;
+            }
+            Record::Token {
+                access,
+                refresh,
+                grant,
+            } => {
+                if let Some(refresh) = &refresh {
+                    self.refresh.insert(self.key(refresh), access.as_bytes())?;
+                }
+                let value = self.seal_value(&StoredToken { refresh, grant })?;
+                self.tokens.insert(self.key(&access), value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stored_form(&self, record: Record) -> anyhow::Result<Record> {
+        record.with_lookup_keys(self.cipher.as_deref())
+    }
+}
+
+impl Registrar for SledStore {
+    fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        let client = self.client(&bound.client_id)?;
+        bind_redirect(client, bound)
+    }
+
+    fn negotiate<'a>(
+        &self, bound: BoundClient<'a>, _scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        let client = self.client(&bound.client_id)?;
+        Ok(PreGrant {
+            client_id: bound.client_id.into_owned(),
+            redirect_uri: bound.redirect_uri.into_owned(),
+            scope: client.default_scope,
+        })
+    }
+
+    fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        let client = self.client(client_id)?;
+        RegisteredClient::new(&client, &*self.password_policy).check_authentication(passphrase)
+    }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.client(client_id)
+            .map(|client| client.refresh_policy)
+            .unwrap_or_default()
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.client(client_id)
+            .is_ok_and(|client| client.permits_grant(grant_type))
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.client(client_id).ok()?.auth_method
+    }
+}
+
+impl Authorizer for SledStore {
+    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+        let code = self.tag(&grant)?;
+        let value = self.seal_value(&StoredGrant::from(&grant)).map_err(|_| ())?;
+        self.grants.insert(self.key(&code), value).map_err(|_| ())?;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        // Removal is atomic, only one request obtains the grant.
+        match self.grants.remove(self.key(code)).map_err(|_| ())? {
+            Some(value) => self.decode_grant(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_grants(client_id, None)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_grants(client_id, Some(owner_id))
+    }
+}
+
+impl Issuer for SledStore {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        let (access, refresh, grant) = self.insert_token(grant)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        // Should only be called on valid refresh tokens.
+        let refresh = self.lookup_key(refresh)?;
+        let access = match self.refresh.remove(self.key(&refresh)).map_err(|_| ())? {
+            Some(access) => access,
+            None => return Err(()),
+        };
+
+        let access = std::str::from_utf8(&access).map_err(|_| ())?;
+        self.tokens.remove(self.key(access)).map_err(|_| ())?;
+
+        let (access, refresh, grant) = self.insert_token(grant)?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        self.find_token(self.lookup_key(token)?.as_bytes())
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        match self
+            .refresh
+            .get(self.key(&self.lookup_key(token)?))
+            .map_err(|_| ())?
+        {
+            Some(access) => self.find_token(&access),
+            None => Ok(None),
+        }
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_tokens(client_id, None)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_tokens(client_id, Some(owner_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::registrar::{ExactUrl, RegisteredUrl};
+
+    fn store() -> SledStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        SledStore::with_db(db).unwrap()
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn primitives() {
+        let mut store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        store
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+                pass,
+            ))
+            .unwrap();
+
+        let bound = store
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Borrowed("Client"),
+                redirect_uri: None,
+            })
+            .unwrap();
+        store.negotiate(bound, None).unwrap();
+        store.check("Client", Some(pass)).unwrap();
+        assert!(store.check("Client", None).is_err());
+        assert!(store.for_tenant("other").check("Client", Some(pass)).is_err());
+
+        let code = store.authorize(grant()).unwrap();
+        let grant = store.extract(&code).unwrap().unwrap();
+        assert_eq!(store.extract(&code).unwrap(), None);
+
+        let issued = store.issue(grant).unwrap();
+        let refresh = issued.refresh.unwrap();
+        let grant = store.recover_refresh(&refresh).unwrap().unwrap();
+        let refreshed = store.refresh(&refresh, grant.clone()).unwrap();
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+        assert!(store.recover_token(&refreshed.token).unwrap().is_some());
+        assert!(store.refresh(&refresh, grant).is_err());
+    }
+
+    #[test]
+    fn revoke_client() {
+        let mut store = store();
+        let other = Grant {
+            client_id: "Other".into(),
+            ..grant()
+        };
+        let code = store.authorize(grant()).unwrap();
+        let kept_code = store.authorize(other.clone()).unwrap();
+        let issued = store.issue(grant()).unwrap();
+        let kept = store.issue(other).unwrap();
+
+        assert_eq!(Authorizer::revoke_client(&mut store, "Client"), Ok(1));
+        assert_eq!(Issuer::revoke_client(&mut store, "Client"), Ok(1));
+        assert_eq!(store.extract(&code).unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+        assert_eq!(store.recover_refresh(&issued.refresh.unwrap()).unwrap(), None);
+        assert!(store.extract(&kept_code).unwrap().is_some());
+        assert!(store.recover_token(&kept.token).unwrap().is_some());
+    }
+
+    #[test]
+    fn revoke_token() {
+        let mut store = store();
+        let by_access = store.issue(grant()).unwrap();
+        let by_refresh = store.issue(grant()).unwrap();
+        let refresh = by_access.refresh.unwrap();
+
+        assert!(store.revoke_token(&by_access.token).unwrap());
+        assert!(!store.revoke_token(&by_access.token).unwrap());
+        assert_eq!(store.recover_refresh(&refresh).unwrap(), None);
+
+        assert!(store.revoke_token(&by_refresh.refresh.unwrap()).unwrap());
+        assert_eq!(store.recover_token(&by_refresh.token).unwrap(), None);
+    }
+
+    #[test]
+    fn registration_settings() {
+        let store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::builder("Client")
+            .redirect_uri(RegisteredUrl::from(url))
+            .default_scope("default".parse().unwrap())
+            .allowed_scope("default extra".parse().unwrap())
+            .grant_types([GrantType::ClientCredentials])
+            .passphrase(b"secret")
+            .auth_method(ClientAuthMethod::ClientSecretPost)
+            .metadata("client_name", "Example")
+            .build()
+            .unwrap();
+        store.register_client(client).unwrap();
+
+        assert!(store.permits_grant("Client", GrantType::ClientCredentials));
+        assert!(!store.permits_grant("Client", GrantType::RefreshToken));
+        assert_eq!(
+            store.auth_method("Client"),
+            Some(ClientAuthMethod::ClientSecretPost)
+        );
+        let client = store.client("Client").unwrap();
+        assert_eq!(client.allowed_scope, Some("default extra".parse().unwrap()));
+        assert_eq!(client.metadata["client_name"], "Example");
+    }
+
+    #[test]
+    fn refresh_policy() {
+        let store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        for (id, policy) in [
+            ("Never", RefreshPolicy::Never),
+            ("Offline", RefreshPolicy::OfflineAccess),
+        ] {
+            let client =
+                Client::public(id, RegisteredUrl::from(url.clone()), "default".parse().unwrap())
+                    .with_refresh_policy(policy);
+            store.register_client(client).unwrap();
+            assert_eq!(store.refresh_policy(id), policy);
+        }
+
+        assert_eq!(store.refresh_policy("Unknown"), RefreshPolicy::Always);
+    }
+
+    #[test]
+    fn disabled_client() {
+        let mut store = store();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::public("Client", RegisteredUrl::from(url), "default".parse().unwrap());
+        let mut stored = StoredClient::from_encoded(&client.encode(&Argon2::default())).unwrap();
+        stored.disabled = true;
+        store.import(Record::Client(stored)).unwrap();
+
+        let bound = store.bound_redirect(ClientUrl {
+            client_id: Cow::Borrowed("Client"),
+            redirect_uri: None,
+        });
+        assert!(bound.is_err());
+        assert!(store.check("Client", None).is_err());
+    }
+
+    #[test]
+    fn transfer_from_memory() {
+        use crate::db_service::transfer::transfer;
+        use oxide_auth::primitives::issuer::TokenMap;
+
+        let mut source = TokenMap::new(RandomGenerator::new(16));
+        let issued = source.issue(grant()).unwrap();
+
+        let mut store = store();
+        let report = transfer(&source, &mut store).unwrap();
+        assert_eq!(report.tokens, 1);
+        assert!(store.recover_token(&issued.token).unwrap().is_some());
+        store.refresh(&issued.refresh.unwrap(), grant()).unwrap();
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+
+        // Other tenants are not affected.
+        let report = transfer(
+            &store.for_tenant("other"),
+            &mut TokenMap::new(RandomGenerator::new(16)),
+        );
+        assert_eq!(report.unwrap().tokens, 0);
+    }
+
+    #[test]
+    fn sealed_values() {
+        use crate::db_service::encryption::{EnvelopeCipher, StaticKeys};
+        use crate::db_service::transfer::transfer;
+        use oxide_auth::primitives::grant::Value;
+        use oxide_auth::primitives::issuer::TokenMap;
+
+        let mut grant = grant();
+        grant
+            .extensions
+            .set_raw("pkce".into(), Value::private(Some("challenge".into())));
+        let mut source = TokenMap::new(RandomGenerator::new(16));
+        let moved = source.issue(grant.clone()).unwrap();
+
+        let mut store = store();
+        store.set_cipher(EnvelopeCipher::new(StaticKeys::new("1", [7; 32])));
+        transfer(&source, &mut store).unwrap();
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        store
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+                pass,
+            ))
+            .unwrap();
+        let code = store.authorize(grant.clone()).unwrap();
+        let issued = store.issue(grant.clone()).unwrap();
+        let refresh = issued.refresh.clone().unwrap();
+
+        let plaintext = [
+            "challenge",
+            "Owner",
+            "$argon2",
+            &moved.token,
+            &issued.token,
+            &refresh,
+        ];
+        for tree in [&store.clients, &store.grants, &store.tokens, &store.refresh] {
+            for entry in tree.iter() {
+                let (key, value) = entry.unwrap();
+                let (key, value) = (String::from_utf8_lossy(&key), String::from_utf8_lossy(&value));
+                for text in plaintext.iter() {
+                    assert!(!key.contains(text) && !value.contains(text));
+                }
+            }
+        }
+
+        store.check("Client", Some(pass)).unwrap();
+        assert_eq!(
+            store.extract(&code).unwrap().unwrap().extensions,
+            grant.extensions
+        );
+        assert!(store.recover_token(&moved.token).unwrap().is_some());
+        // The stored lookup keys are no tokens themselves.
+        let (stored, _) = store.tokens.iter().next().unwrap().unwrap();
+        let stored = String::from_utf8_lossy(&stored[store.tenant.len() + 1..]).into_owned();
+        assert_eq!(store.recover_token(&stored).unwrap(), None);
+        let recovered = store.recover_token(&issued.token).unwrap().unwrap();
+        assert_eq!(recovered.extensions, grant.extensions);
+        assert!(store.recover_refresh(&refresh).unwrap().is_some());
+        store.refresh(&refresh, recovered).unwrap();
+        assert_eq!(store.recover_token(&issued.token).unwrap(), None);
+        assert!(store.revoke_token(&moved.token).unwrap());
+        assert_eq!(store.recover_token(&moved.token).unwrap(), None);
+        assert_eq!(Issuer::revoke_client(&mut store, "Client"), Ok(1));
+    }
+
+    #[test]
+    fn purges_expired() {
+        let mut store = store();
+        store.valid_for(Duration::zero());
+        let expired = Grant {
+            until: Utc::now() - Duration::minutes(1),
+            ..grant()
+        };
+
+        let code = store.authorize(expired).unwrap();
+        let live = store.authorize(grant()).unwrap();
+        let issued = store.issue(grant()).unwrap();
+        assert_eq!(store.purge_expired().unwrap(), 2);
+
+        assert_eq!(store.extract(&code).unwrap(), None);
+        assert!(store.extract(&live).unwrap().is_some());
+        assert_eq!(store.recover_refresh(&issued.refresh.unwrap()).unwrap(), None);
+    }
+}
@@ -0,0 +1,1177 @@
+//! A storage for clients, grants and tokens in any SQL database supported by `sqlx`.
+//!
+//! The store is built on the `Any` driver of `sqlx` so that one implementation serves Postgres,
+//! MySQL and SQLite alike. Only the query text differs between them, see [`Dialect`]. Note that
+//! the drivers themselves need to be enabled through the features of your own `sqlx` dependency.
+//! Create the tables with [`SqlStore::migrate`].
+//!
+//! All of the async primitives of `oxide-auth-async` are implemented by [`SqlStore`]. Clone it to
+//! obtain independent handles for the registrar, authorizer and issuer of an endpoint; all clones
+//! share the same connection pool. Likewise [`TenantScoped::for_tenant`] derives a handle that
+//! only sees the rows of one tenant.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use oxide_auth::primitives::authorizer::GuardError;
+use oxide_auth::primitives::generator::{RandomGenerator, TagGrant};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenType};
+use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
+use oxide_auth::primitives::registrar::{
+    Argon2, BoundClient, Client, ClientAuthMethod, EncodedClient, GrantType, PasswordPolicy,
+    RefreshPolicy, RegisteredClient, RegistrarError,
+};
+use oxide_auth_async::frontends::sweep::Expiring;
+use oxide_auth_async::primitives::{Authorizer, Issuer, Registrar, SingleUseGuard};
+use sqlx::any::{AnyPoolOptions, AnyRow};
+use sqlx::{AnyConnection, AnyPool, Connection, Row, TypeInfo, ValueRef};
+
+use crate::db_service::audit::{self, AuditEntry};
+use crate::db_service::encryption::{self, ValueCipher};
+use crate::db_service::migration;
+use crate::db_service::stored::{bind_redirect, StoredClient, StoredGrant};
+use crate::db_service::trace::{self, DbSpan};
+use crate::db_service::{PoolConfig, TenantScoped, DEFAULT_TENANT};
+
+pub use crate::db_service::migration::Dialect;
+
+/// Length in bytes of generated codes and tokens.
+const TOKEN_LENGTH: usize = 16;
+
+static DEFAULT_PASSWORD_POLICY: Lazy<Argon2> = Lazy::new(Argon2::default);
+
+/// Clients, grants and tokens stored in an SQL database.
+pub struct SqlStore {
+    pool: AnyPool,
+    queries: Arc<Queries>,
+    tenant: Arc<str>,
+    password_policy: Arc<dyn PasswordPolicy>,
+    cipher: Option<Arc<dyn ValueCipher>>,
+    generator: RandomGenerator,
+    usage: u64,
+    token_duration: Duration,
+}
+
+/// The query text for one dialect.
+struct Queries {
+    dialect: Dialect,
+    select_client: String,
+    upsert_client: String,
+    insert_grant: String,
+    select_grant: String,
+    delete_grant: String,
+    select_grants: String,
+    purge_grants: String,
+    insert_token: String,
+    select_access: String,
+    select_refresh: String,
+    delete_refresh: String,
+    select_tokens: String,
+    delete_access: String,
+    purge_tokens: String,
+    claim_code: String,
+    purge_claims: String,
+    insert_audit: String,
+}
+
+impl Queries {
+    fn new(dialect: Dialect) -> Self {
+        let upsert_client = match dialect {
+            Dialect::MySql => {
+                "INSERT INTO oauth_clients
+                    (tenant_id, client_id, redirect_uri, additional_redirect_uris, default_scope,
+                    client_secret, settings, disabled)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                    ON DUPLICATE KEY UPDATE
+                    redirect_uri = VALUES(redirect_uri),
+                    additional_redirect_uris = VALUES(additional_redirect_uris),
+                    default_scope = VALUES(default_scope),
+                    client_secret = VALUES(client_secret),
+                    settings = VALUES(settings),
+                    disabled = VALUES(disabled)"
+            }
+            Dialect::Postgres | Dialect::Sqlite => {
+                "INSERT INTO oauth_clients
+                    (tenant_id, client_id, redirect_uri, additional_redirect_uris, default_scope,
+                    client_secret, settings, disabled)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                    ON CONFLICT (tenant_id, client_id) DO UPDATE SET
+                    redirect_uri = excluded.redirect_uri,
+                    additional_redirect_uris = excluded.additional_redirect_uris,
+                    default_scope = excluded.default_scope,
+                    client_secret = excluded.client_secret,
+                    settings = excluded.settings,
+                    disabled = excluded.disabled"
+            }
+        };
+
+        let claim_code = match dialect {
+            Dialect::MySql => {
+                "INSERT IGNORE INTO oauth_used_codes (tenant_id, code, expires_at) VALUES (?, ?, ?)"
+            }
+            Dialect::Postgres | Dialect::Sqlite => {
+                "INSERT INTO oauth_used_codes (tenant_id, code, expires_at) VALUES (?, ?, ?)
+                    ON CONFLICT DO NOTHING"
+            }
+        };
+
+        let query = |text: &str| dialect.placeholders(text);
+        Queries {
+            dialect,
+            select_client: query(
+                "SELECT redirect_uri, additional_redirect_uris, default_scope, client_secret, settings,
+                    disabled FROM oauth_clients WHERE tenant_id = ? AND client_id = ?",
+            ),
+            upsert_client: query(upsert_client),
+            insert_grant: query(
+                "INSERT INTO oauth_grants (tenant_id, code, grant_data, expires_at)
+                    VALUES (?, ?, ?, ?)",
+            ),
+            select_grant: query("SELECT grant_data FROM oauth_grants WHERE tenant_id = ? AND code = ?"),
+            delete_grant: query("DELETE FROM oauth_grants WHERE tenant_id = ? AND code = ?"),
+            select_grants: query("SELECT code, grant_data FROM oauth_grants WHERE tenant_id = ?"),
+            purge_grants: query("DELETE FROM oauth_grants WHERE tenant_id = ? AND expires_at <= ?"),
+            insert_token: query(
+                "INSERT INTO oauth_tokens
+                    (tenant_id, access_token, refresh_token, grant_data, expires_at)
+                    VALUES (?, ?, ?, ?, ?)",
+            ),
+            select_access: query(
+                "SELECT grant_data FROM oauth_tokens WHERE tenant_id = ? AND access_token = ?",
+            ),
+            select_refresh: query(
+                "SELECT grant_data FROM oauth_tokens WHERE tenant_id = ? AND refresh_token = ?",
+            ),
+            delete_refresh: query("DELETE FROM oauth_tokens WHERE tenant_id = ? AND refresh_token = ?"),
+            select_tokens: query(
+                "SELECT access_token, grant_data FROM oauth_tokens WHERE tenant_id = ?",
+            ),
+            delete_access: query("DELETE FROM oauth_tokens WHERE tenant_id = ? AND access_token = ?"),
+            purge_tokens: query("DELETE FROM oauth_tokens WHERE tenant_id = ? AND expires_at <= ?"),
+            claim_code: query(claim_code),
+            purge_claims: query("DELETE FROM oauth_used_codes WHERE tenant_id = ? AND expires_at <= ?"),
+            insert_audit: query(audit::INSERT_AUDIT),
+        }
+    }
+}
+
+impl SqlStore {
+    /// Connect to the database at `url`, with the dialect derived from its scheme.
+    pub async fn connect(url: &str, config: PoolConfig) -> Result<Self, sqlx::Error> {
+        let dialect = Dialect::from_url(url)
+            .ok_or_else(|| sqlx::Error::Configuration("Unsupported database url".into()))?;
+        sqlx::any::install_default_drivers();
+
+        let pool = AnyPoolOptions::new()
+            .max_connections(config.max_size)
+            .min_connections(config.min_idle.unwrap_or(0))
+            .acquire_timeout(config.connection_timeout)
+            .idle_timeout(config.idle_timeout)
+            .max_lifetime(config.max_lifetime)
+            .test_before_acquire(config.test_on_check_out)
+            .connect(url)
+            .await?;
+
+        Ok(SqlStore::with_pool(pool, dialect))
+    }
+
+    /// Use an existing connection pool to a database of the given dialect.
+    pub fn with_pool(pool: AnyPool, dialect: Dialect) -> Self {
+        SqlStore {
+            pool,
+            queries: Arc::new(Queries::new(dialect)),
+            tenant: DEFAULT_TENANT.into(),
+            password_policy: Arc::new(DEFAULT_PASSWORD_POLICY.clone()),
+            cipher: None,
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: Duration::hours(1),
+        }
+    }
+
+    /// The dialect of the connected database.
+    pub fn dialect(&self) -> Dialect {
+        self.queries.dialect
+    }
+
+    /// The underlying connection pool.
+    pub fn pool(&self) -> &AnyPool {
+        &self.pool
+    }
+
+    /// Check that the database answers queries.
+    ///
+    /// The pool itself replaces broken connections, this only reports whether it currently can.
+    pub async fn health_check(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Apply all pending schema migrations.
+    ///
+    /// Fails if the database has been migrated by a newer version of this crate. Concurrent calls
+    /// of several replicas wait for each other on PostgreSQL and MySQL, see `Dialect::migration_lock`.
+    pub async fn migrate(&self) -> anyhow::Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let (lock, unlock) = match self.dialect().migration_lock() {
+            Some(lock) => lock,
+            None => return self.migrate_on(&mut conn).await,
+        };
+
+        sqlx::query(lock).execute(&mut *conn).await?;
+        let migrated = self.migrate_on(&mut conn).await;
+        if sqlx::query(unlock).execute(&mut *conn).await.is_err() {
+            // Closing the connection ends the session and with it the lock.
+            drop(conn.detach());
+        }
+
+        migrated
+    }
+
+    async fn migrate_on(&self, conn: &mut AnyConnection) -> anyhow::Result<()> {
+        sqlx::query(migration::CREATE_VERSION_TABLE)
+            .execute(&mut *conn)
+            .await?;
+        let insert_version = self.dialect().placeholders(migration::INSERT_VERSION);
+
+        // Read only now, another replica may have migrated while this one waited for the lock.
+        let found = current_version(&mut *conn).await?;
+        for step in migration::pending(self.dialect(), found)? {
+            let mut transaction = conn.begin().await?;
+            for statement in step.statements {
+                sqlx::query(statement).execute(&mut *transaction).await?;
+            }
+
+            sqlx::query(&insert_version)
+                .bind(step.version)
+                .bind(step.description)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Refuse to work with a database whose schema version is not the expected one.
+    ///
+    /// Call this during startup, before serving any requests.
+    pub async fn verify_schema(&self) -> anyhow::Result<()> {
+        migration::verify(self.schema_version().await?)?;
+        Ok(())
+    }
+
+    /// The current version of the schema, 0 for a database without any migrations.
+    pub async fn schema_version(&self) -> anyhow::Result<i64> {
+        let mut conn = self.pool.acquire().await?;
+        current_version(&mut conn).await
+    }
+
+    /// Change how passwords are encoded while stored.
+    pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
+        self.password_policy = Arc::new(new_policy);
+    }
+
+    /// Encrypt client secrets and grants before they are stored.
+    ///
+    /// Access and refresh tokens are stored as their `ValueCipher::lookup_key` instead, tokens
+    /// issued before the cipher was set are no longer found. Secrets and grants stored before
+    /// remain readable.
+    pub fn set_cipher<C: ValueCipher + 'static>(&mut self, cipher: C) {
+        self.cipher = Some(Arc::new(cipher));
+    }
+
+    /// Set the validity of all issued tokens, one hour by default.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.token_duration = duration;
+    }
+
+    /// Remove all expired codes and tokens of the tenant, returning how many were removed.
+    ///
+    /// Refresh tokens are removed along with their access token.
+    pub async fn purge_expired(&self) -> anyhow::Result<usize> {
+        self.purge_expired_at(Utc::now()).await
+    }
+
+    async fn purge_expired_at(&self, now: DateTime<Utc>) -> anyhow::Result<usize> {
+        let now = now.timestamp_millis();
+        let mut transaction = self.pool.begin().await?;
+        let mut purged = 0;
+
+        for purge in [&self.queries.purge_grants, &self.queries.purge_tokens] {
+            let deleted = self
+                .span("DELETE")
+                .instrument(
+                    sqlx::query(purge)
+                        .bind(&*self.tenant)
+                        .bind(now)
+                        .execute(&mut *transaction),
+                )
+                .await?;
+            purged += deleted.rows_affected() as usize;
+        }
+
+        // Claims are no codes of their own and are not counted.
+        self.span("DELETE")
+            .instrument(
+                sqlx::query(&self.queries.purge_claims)
+                    .bind(&*self.tenant)
+                    .bind(now)
+                    .execute(&mut *transaction),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        Ok(purged)
+    }
+
+    /// Insert or update the client record.
+    pub async fn register_client(&self, client: Client) -> Result<(), RegistrarError> {
+        let encoded = client.encode(&*self.password_policy);
+        self.store_client(encoded)
+            .await
+            .map_err(|_| RegistrarError::PrimitiveError)
+    }
+
+    /// Append an entry to the audit log table.
+    ///
+    /// The flows report synchronously, so forward the records of an `Outbox` to this method
+    /// through a channel or task of your runtime.
+    pub async fn append_audit(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        self.span("INSERT")
+            .instrument(
+                sqlx::query(&self.queries.insert_audit)
+                    .bind(&*self.tenant)
+                    .bind(entry.occurred_at)
+                    .bind(entry.event.as_str())
+                    .bind(entry.outcome.as_str())
+                    .bind(entry.client_id.as_deref())
+                    .bind(entry.owner_id.as_deref())
+                    .bind(entry.scope.as_deref())
+                    .bind(entry.remote_addr.as_deref())
+                    .execute(&self.pool),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn store_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let mut stored = StoredClient::from_encoded(&client)?;
+        stored.client_secret = stored.client_secret.map(|secret| self.seal(secret)).transpose()?;
+
+        self.span("INSERT")
+            .instrument(
+                sqlx::query(&self.queries.upsert_client)
+                    .bind(&*self.tenant)
+                    .bind(stored.client_id)
+                    .bind(stored.redirect_uri)
+                    .bind(stored.additional_redirect_uris)
+                    .bind(stored.default_scope)
+                    .bind(stored.client_secret)
+                    .bind(stored.settings)
+                    .bind(i64::from(stored.disabled))
+                    .execute(&self.pool),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find_client(&self, client_id: &str) -> anyhow::Result<Option<EncodedClient>> {
+        let row = self
+            .span("SELECT")
+            .instrument(
+                sqlx::query(&self.queries.select_client)
+                    .bind(&*self.tenant)
+                    .bind(client_id)
+                    .fetch_optional(&self.pool),
+            )
+            .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let secret = nullable_text(&row, "client_secret")?;
+        let stored = StoredClient {
+            client_id: client_id.to_owned(),
+            redirect_uri: row.try_get("redirect_uri")?,
+            additional_redirect_uris: row.try_get("additional_redirect_uris")?,
+            default_scope: row.try_get("default_scope")?,
+            client_secret: secret.map(|secret| self.open(secret)).transpose()?,
+            settings: nullable_text(&row, "settings")?,
+            disabled: row.try_get::<i64, _>("disabled")? != 0,
+        };
+
+        stored.into_encoded().map(Some)
+    }
+
+    fn seal(&self, value: String) -> anyhow::Result<String> {
+        encryption::seal(self.cipher.as_deref(), value)
+    }
+
+    fn open(&self, stored: String) -> anyhow::Result<String> {
+        encryption::open(self.cipher.as_deref(), stored)
+    }
+
+    /// The stored form of an access or refresh token.
+    fn lookup_key(&self, token: &str) -> Result<String, ()> {
+        encryption::lookup_key(self.cipher.as_deref(), token).map_err(|_| ())
+    }
+
+    fn encode_grant(&self, grant: &StoredGrant) -> Result<String, ()> {
+        let data = serde_json::to_string(grant).map_err(|_| ())?;
+        self.seal(data).map_err(|_| ())
+    }
+
+    fn decode_grant(&self, row: &AnyRow) -> Result<StoredGrant, ()> {
+        let data: String = row.try_get("grant_data").map_err(|_| ())?;
+        let data = self.open(data).map_err(|_| ())?;
+        serde_json::from_str(&data).map_err(|_| ())
+    }
+
+    /// The span of a statement, a child of the current span.
+    fn span(&self, operation: &'static str) -> DbSpan {
+        let system = match self.queries.dialect {
+            Dialect::Postgres => "postgresql",
+            Dialect::MySql => "mysql",
+            Dialect::Sqlite => "sqlite",
+        };
+        trace::span(system, operation)
+    }
+
+    fn tag(&mut self, grant: &Grant) -> Result<String, ()> {
+        let tag = self.generator.tag(self.usage, grant)?;
+        self.usage = self.usage.wrapping_add(1);
+        Ok(tag)
+    }
+
+    async fn insert_token(&mut self, mut grant: Grant) -> Result<(String, String, Grant), ()> {
+        grant.until = Utc::now() + self.token_duration;
+        let access = self.tag(&grant)?;
+        let refresh = self.tag(&grant)?;
+        let stored = StoredGrant::from(&grant);
+        let data = self.encode_grant(&stored)?;
+
+        self.span("INSERT")
+            .instrument(
+                sqlx::query(&self.queries.insert_token)
+                    .bind(&*self.tenant)
+                    .bind(self.lookup_key(&access)?)
+                    .bind(self.lookup_key(&refresh)?)
+                    .bind(data)
+                    .bind(stored.expires_at())
+                    .execute(&self.pool),
+            )
+            .await
+            .map_err(|_| ())?;
+
+        Ok((access, refresh, grant))
+    }
+
+    async fn fetch_grant(&self, query: &str, key: &str) -> Result<Option<Grant>, ()> {
+        let row = self
+            .span("SELECT")
+            .instrument(
+                sqlx::query(query)
+                    .bind(&*self.tenant)
+                    .bind(key)
+                    .fetch_optional(&self.pool),
+            )
+            .await
+            .map_err(|_| ())?;
+        row.map(|row| into_grant(self.decode_grant(&row)?)).transpose()
+    }
+
+    /// Delete the rows of the tenant whose grant was issued to a client, and to an owner if given.
+    ///
+    /// The grant data is opaque to the database, so all rows of the tenant are scanned. Rows that
+    /// can not be decoded are kept.
+    async fn delete_client_rows(
+        &self, select: &str, key: &str, delete: &str, client_id: &str, owner_id: Option<&str>,
+    ) -> Result<usize, ()> {
+        let mut transaction = self.pool.begin().await.map_err(|_| ())?;
+        let rows = self
+            .span("SELECT")
+            .instrument(
+                sqlx::query(select)
+                    .bind(&*self.tenant)
+                    .fetch_all(&mut *transaction),
+            )
+            .await
+            .map_err(|_| ())?;
+
+        let mut revoked = 0;
+        for row in rows {
+            match self.decode_grant(&row) {
+                Ok(stored) if stored.issued_to(client_id, owner_id) => (),
+                _ => continue,
+            }
+
+            let value: String = row.try_get(key).map_err(|_| ())?;
+            let deleted = self
+                .span("DELETE")
+                .instrument(
+                    sqlx::query(delete)
+                        .bind(&*self.tenant)
+                        .bind(value)
+                        .execute(&mut *transaction),
+                )
+                .await
+                .map_err(|_| ())?;
+            revoked += deleted.rows_affected() as usize;
+        }
+
+        transaction.commit().await.map_err(|_| ())?;
+        Ok(revoked)
+    }
+}
+
+/// The schema version seen by a connection, 0 without any migrations.
+async fn current_version(conn: &mut AnyConnection) -> anyhow::Result<i64> {
+    let row = match sqlx::query(migration::SELECT_VERSION)
+        .fetch_optional(&mut *conn)
+        .await
+    {
+        Ok(row) => row,
+        // The table of migrations does not exist yet.
+        Err(sqlx::Error::Database(_)) => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+
+    match row {
+        Some(row) => Ok(row.try_get("version")?),
+        None => Ok(0),
+    }
+}
+
+/// Read a text column that may be `NULL`.
+///
+/// The `Any` driver reports `NULL` values with a type of their own, which `Option<String>` refuses
+/// to decode.
+fn nullable_text(row: &AnyRow, column: &str) -> anyhow::Result<Option<String>> {
+    if row.try_get_raw(column)?.type_info().name() == "NULL" {
+        return Ok(None);
+    }
+
+    Ok(Some(row.try_get(column)?))
+}
+
+fn into_grant(stored: StoredGrant) -> Result<Grant, ()> {
+    stored.into_grant().map_err(|_| ())
+}
+
+impl Clone for SqlStore {
+    fn clone(&self) -> Self {
+        SqlStore {
+            pool: self.pool.clone(),
+            queries: self.queries.clone(),
+            tenant: self.tenant.clone(),
+            password_policy: self.password_policy.clone(),
+            cipher: self.cipher.clone(),
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: self.token_duration,
+        }
+    }
+}
+
+impl TenantScoped for SqlStore {
+    fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    fn for_tenant(&self, tenant: &str) -> Self {
+        SqlStore {
+            tenant: tenant.into(),
+            ..self.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl Registrar for SqlStore {
+    async fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        let client = match self.find_client(&bound.client_id).await {
+            Ok(Some(client)) => client,
+            Ok(None) => return Err(RegistrarError::Unspecified),
+            Err(_) => return Err(RegistrarError::PrimitiveError),
+        };
+
+        bind_redirect(client, bound)
+    }
+
+    async fn negotiate<'a>(
+        &self, bound: BoundClient<'a>, _scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        let client = match self.find_client(&bound.client_id).await {
+            Ok(Some(client)) => client,
+            Ok(None) => return Err(RegistrarError::Unspecified),
+            Err(_) => return Err(RegistrarError::PrimitiveError),
+        };
+
+        Ok(PreGrant {
+            client_id: bound.client_id.into_owned(),
+            redirect_uri: bound.redirect_uri.into_owned(),
+            scope: client.default_scope,
+        })
+    }
+
+    async fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        let client = match self.find_client(client_id).await {
+            Ok(Some(client)) => client,
+            Ok(None) => return Err(RegistrarError::Unspecified),
+            Err(_) => return Err(RegistrarError::PrimitiveError),
+        };
+
+        RegisteredClient::new(&client, &*self.password_policy).check_authentication(passphrase)
+    }
+
+    async fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        match self.find_client(client_id).await {
+            Ok(Some(client)) => client.refresh_policy,
+            _ => RefreshPolicy::default(),
+        }
+    }
+
+    async fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        match self.find_client(client_id).await {
+            Ok(Some(client)) => client.permits_grant(grant_type),
+            _ => false,
+        }
+    }
+
+    async fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.find_client(client_id).await.ok()??.auth_method
+    }
+}
+
+#[async_trait]
+impl Authorizer for SqlStore {
+    async fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+        let code = self.tag(&grant)?;
+        let stored = StoredGrant::from(&grant);
+        let data = self.encode_grant(&stored)?;
+
+        self.span("INSERT")
+            .instrument(
+                sqlx::query(&self.queries.insert_grant)
+                    .bind(&*self.tenant)
+                    .bind(code.clone())
+                    .bind(data)
+                    .bind(stored.expires_at())
+                    .execute(&self.pool),
+            )
+            .await
+            .map_err(|_| ())?;
+
+        Ok(code)
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        let mut transaction = self.pool.begin().await.map_err(|_| ())?;
+        let row = self
+            .span("SELECT")
+            .instrument(
+                sqlx::query(&self.queries.select_grant)
+                    .bind(&*self.tenant)
+                    .bind(code)
+                    .fetch_optional(&mut *transaction),
+            )
+            .await
+            .map_err(|_| ())?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        // Only the request that actually deletes the code may use it.
+        let deleted = self
+            .span("DELETE")
+            .instrument(
+                sqlx::query(&self.queries.delete_grant)
+                    .bind(&*self.tenant)
+                    .bind(code)
+                    .execute(&mut *transaction),
+            )
+            .await
+            .map_err(|_| ())?;
+        transaction.commit().await.map_err(|_| ())?;
+
+        if deleted.rows_affected() != 1 {
+            return Ok(None);
+        }
+
+        into_grant(self.decode_grant(&row)?).map(Some)
+    }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        let queries = &self.queries;
+        self.delete_client_rows(
+            &queries.select_grants,
+            "code",
+            &queries.delete_grant,
+            client_id,
+            None,
+        )
+        .await
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        let queries = &self.queries;
+        self.delete_client_rows(
+            &queries.select_grants,
+            "code",
+            &queries.delete_grant,
+            client_id,
+            Some(owner_id),
+        )
+        .await
+    }
+}
+
+/// Claims are rows of the `oauth_used_codes` table, inserted only if absent.
+///
+/// Guards another authorizer through `SingleUse`, the codes of the store itself are already
+/// redeemed atomically. Claims are removed by `purge_expired` once their code expired.
+#[async_trait]
+impl SingleUseGuard for SqlStore {
+    async fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        let inserted = self
+            .span("INSERT")
+            .instrument(
+                sqlx::query(&self.queries.claim_code)
+                    .bind(&*self.tenant)
+                    .bind(code)
+                    .bind(until.timestamp_millis())
+                    .execute(&self.pool),
+            )
+            .await
+            .map_err(|_| GuardError)?;
+        Ok(inserted.rows_affected() == 1)
+    }
+}
+
+#[async_trait]
+impl Issuer for SqlStore {
+    async fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        let (access, refresh, grant) = self.insert_token(grant).await?;
+        Ok(IssuedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        let deleted = self
+            .span("DELETE")
+            .instrument(
+                sqlx::query(&self.queries.delete_refresh)
+                    .bind(&*self.tenant)
+                    .bind(self.lookup_key(refresh)?)
+                    .execute(&self.pool),
+            )
+            .await
+            .map_err(|_| ())?;
+
+        // Should only be called on valid refresh tokens.
+        if deleted.rows_affected() != 1 {
+            return Err(());
+        }
+
+        let (access, refresh, grant) = self.insert_token(grant).await?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        let key = self.lookup_key(token)?;
+        self.fetch_grant(&self.queries.select_access, &key).await
+    }
+
+    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        let key = self.lookup_key(token)?;
+        self.fetch_grant(&self.queries.select_refresh, &key).await
+    }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        let queries = &self.queries;
+        self.delete_client_rows(
+            &queries.select_tokens,
+            "access_token",
+            &queries.delete_access,
+            client_id,
+            None,
+        )
+        .await
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        let queries = &self.queries;
+        self.delete_client_rows(
+            &queries.select_tokens,
+            "access_token",
+            &queries.delete_access,
+            client_id,
+            Some(owner_id),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl Expiring for SqlStore {
+    async fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        self.purge_expired_at(now).await.map_err(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use oxide_auth::primitives::registrar::{ExactUrl, RegisteredUrl};
+    use oxide_auth::primitives::grant::{Extensions, Value};
+    use oxide_auth_async::frontends::sweep::Sweeper;
+    use crate::db_service::encryption::{EnvelopeCipher, StaticKeys};
+
+    async fn store() -> SqlStore {
+        let config = PoolConfig::with_max_size(1);
+        let store = SqlStore::connect("sqlite::memory:", config).await.unwrap();
+        assert!(store.verify_schema().await.is_err());
+        store.migrate().await.unwrap();
+        store.migrate().await.unwrap();
+        store.verify_schema().await.unwrap();
+        store.health_check().await.unwrap();
+        store
+    }
+
+    fn grant() -> Grant {
+        let mut extensions = Extensions::new();
+        extensions.set_raw("pkce".into(), Value::private(Some("challenge".into())));
+        extensions.set_raw("note".into(), Value::public(None));
+
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions,
+        }
+    }
+
+    #[tokio::test]
+    async fn registrar() {
+        let store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let pass = b"WOJJCcS8WyS2aGmJK6ZADg==";
+        store
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::from(url.clone()),
+                "default".parse().unwrap(),
+                pass,
+            ))
+            .await
+            .unwrap();
+
+        let bound = store
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Borrowed("Client"),
+                redirect_uri: Some(Cow::Owned(url)),
+            })
+            .await
+            .unwrap();
+        let pre_grant = store.negotiate(bound, None).await.unwrap();
+        assert_eq!(pre_grant.scope, "default".parse().unwrap());
+
+        store.check("Client", Some(pass)).await.unwrap();
+        assert!(store.check("Client", Some(b"wrong")).await.is_err());
+        assert!(store.check("Unknown", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn registration_settings() {
+        let store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::builder("Client")
+            .redirect_uri(RegisteredUrl::from(url))
+            .default_scope("default".parse().unwrap())
+            .allowed_scope("default extra".parse().unwrap())
+            .grant_types([GrantType::ClientCredentials])
+            .passphrase(b"secret")
+            .auth_method(ClientAuthMethod::ClientSecretPost)
+            .metadata("client_name", "Example")
+            .build()
+            .unwrap();
+        store.register_client(client).await.unwrap();
+
+        assert!(store.permits_grant("Client", GrantType::ClientCredentials).await);
+        assert!(!store.permits_grant("Client", GrantType::RefreshToken).await);
+        assert_eq!(
+            store.auth_method("Client").await,
+            Some(ClientAuthMethod::ClientSecretPost)
+        );
+        let client = store.find_client("Client").await.unwrap().unwrap();
+        assert_eq!(client.allowed_scope, Some("default extra".parse().unwrap()));
+        assert_eq!(client.metadata["client_name"], "Example");
+    }
+
+    #[tokio::test]
+    async fn refresh_policy() {
+        let store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        for (id, policy) in [
+            ("Never", RefreshPolicy::Never),
+            ("Offline", RefreshPolicy::OfflineAccess),
+        ] {
+            let client =
+                Client::public(id, RegisteredUrl::from(url.clone()), "default".parse().unwrap())
+                    .with_refresh_policy(policy);
+            store.register_client(client).await.unwrap();
+            assert_eq!(store.refresh_policy(id).await, policy);
+        }
+
+        assert_eq!(store.refresh_policy("Unknown").await, RefreshPolicy::Always);
+    }
+
+    #[tokio::test]
+    async fn disabled_client() {
+        let store = store().await;
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        let client = Client::public("Client", RegisteredUrl::from(url), "default".parse().unwrap());
+        let mut encoded = client.encode(&Argon2::default());
+        encoded.disabled = true;
+        store.store_client(encoded).await.unwrap();
+
+        assert!(store.find_client("Client").await.unwrap().unwrap().disabled);
+        let bound = store
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Borrowed("Client"),
+                redirect_uri: None,
+            })
+            .await;
+        assert!(bound.is_err());
+        assert!(store.check("Client", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn authorizer() {
+        let mut store = store().await;
+        let grant = grant();
+        let code = store.authorize(grant.clone()).await.unwrap();
+
+        let until = grant.until.timestamp_millis();
+        let extracted = store.extract(&code).await.unwrap().unwrap();
+        assert_eq!(extracted.until.timestamp_millis(), until);
+        assert_eq!(extracted.extensions, grant.extensions);
+        assert_eq!(store.extract(&code).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn claims_race() {
+        let store = store().await;
+        let replica = store.clone();
+        let until = Utc::now() + Duration::minutes(10);
+
+        let (first, second) = tokio::join!(store.claim("code", until), replica.claim("code", until));
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert!(first != second, "exactly one claim must win");
+        assert!(!store.claim("code", until).await.unwrap());
+        assert!(store.for_tenant("other").claim("code", until).await.unwrap());
+
+        let expired = Utc::now() - Duration::minutes(1);
+        assert!(store.claim("expired", expired).await.unwrap());
+        store.purge_expired().await.unwrap();
+        assert!(store.claim("expired", expired).await.unwrap());
+        assert!(!store.claim("code", until).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn issuer() {
+        let mut store = store().await;
+        let issued = store.issue(grant()).await.unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let recovered = store.recover_token(&issued.token).await.unwrap().unwrap();
+        assert_eq!(recovered.owner_id, "Owner");
+        assert!(store.recover_refresh(&refresh).await.unwrap().is_some());
+
+        let refreshed = store.refresh(&refresh, recovered).await.unwrap();
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert_eq!(store.recover_refresh(&refresh).await.unwrap(), None);
+        assert!(store.recover_token(&refreshed.token).await.unwrap().is_some());
+        assert!(store.refresh(&refresh, grant()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn purge_expired() {
+        let mut store = store().await;
+        let expired = Grant {
+            until: Utc::now() - Duration::minutes(1),
+            ..grant()
+        };
+        let code = store.authorize(expired).await.unwrap();
+        let kept_code = store.authorize(grant()).await.unwrap();
+        store.valid_for(Duration::minutes(-1));
+        let issued = store.issue(grant()).await.unwrap();
+        store.valid_for(Duration::hours(1));
+        let kept = store.issue(grant()).await.unwrap();
+
+        assert_eq!(store.purge_expired().await.unwrap(), 2);
+        assert_eq!(store.extract(&code).await.unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert!(store.extract(&kept_code).await.unwrap().is_some());
+        assert!(store.recover_token(&kept.token).await.unwrap().is_some());
+
+        let mut sweeper = Sweeper::new().store("sql", store.clone());
+        assert_eq!(sweeper.sweep_at(Utc::now() + Duration::hours(2)).await, 1);
+        assert_eq!(store.recover_token(&kept.token).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn revoke_client() {
+        let mut store = store().await;
+        let other = Grant {
+            client_id: "Other".into(),
+            ..grant()
+        };
+        let code = store.authorize(grant()).await.unwrap();
+        let kept_code = store.authorize(other.clone()).await.unwrap();
+        let issued = store.issue(grant()).await.unwrap();
+        let kept = store.issue(other).await.unwrap();
+
+        assert_eq!(Authorizer::revoke_client(&mut store, "Client").await, Ok(1));
+        assert_eq!(Issuer::revoke_client(&mut store, "Client").await, Ok(1));
+        assert_eq!(store.extract(&code).await.unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert_eq!(
+            store.recover_refresh(&issued.refresh.unwrap()).await.unwrap(),
+            None
+        );
+        assert!(store.extract(&kept_code).await.unwrap().is_some());
+        assert!(store.recover_token(&kept.token).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn revoke_grant() {
+        let mut store = store().await;
+        let other = Grant {
+            owner_id: "Other".into(),
+            ..grant()
+        };
+        let code = store.authorize(grant()).await.unwrap();
+        let issued = store.issue(grant()).await.unwrap();
+        let kept = store.issue(other).await.unwrap();
+
+        assert_eq!(
+            Authorizer::revoke_grant(&mut store, "Owner", "Client").await,
+            Ok(1)
+        );
+        assert_eq!(Issuer::revoke_grant(&mut store, "Owner", "Client").await, Ok(1));
+        assert_eq!(store.extract(&code).await.unwrap(), None);
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert!(store.recover_token(&kept.token).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn tenants_are_isolated() {
+        let store = store().await;
+        let mut first = store.for_tenant("first");
+        let mut second = store.for_tenant("second");
+        assert_eq!(second.tenant(), "second");
+
+        let url: ExactUrl = "https://client.example/endpoint".parse().unwrap();
+        first
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::from(url),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .await
+            .unwrap();
+        first.check("Client", Some(b"secret")).await.unwrap();
+        assert!(second.check("Client", Some(b"secret")).await.is_err());
+        assert!(store.check("Client", Some(b"secret")).await.is_err());
+
+        let code = first.authorize(grant()).await.unwrap();
+        assert_eq!(second.extract(&code).await.unwrap(), None);
+        assert!(first.extract(&code).await.unwrap().is_some());
+
+        let issued = first.issue(grant()).await.unwrap();
+        let refresh = issued.refresh.unwrap();
+        assert_eq!(second.recover_token(&issued.token).await.unwrap(), None);
+        assert!(second.refresh(&refresh, grant()).await.is_err());
+        assert!(first.recover_refresh(&refresh).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn sealed_values() {
+        let mut store = store().await;
+        store.set_cipher(EnvelopeCipher::new(StaticKeys::new("1", [7; 32])));
+        let code = store.authorize(grant()).await.unwrap();
+        let issued = store.issue(grant()).await.unwrap();
+        let refresh = issued.refresh.clone().unwrap();
+
+        let stored = sqlx::query("SELECT grant_data FROM oauth_grants")
+            .fetch_one(store.pool())
+            .await
+            .unwrap();
+        let data: String = stored.get("grant_data");
+        assert!(!data.contains("challenge") && !data.contains("Owner"));
+
+        let stored = sqlx::query("SELECT access_token, refresh_token, grant_data FROM oauth_tokens")
+            .fetch_one(store.pool())
+            .await
+            .unwrap();
+        let access: String = stored.get("access_token");
+        let stored_refresh: String = stored.get("refresh_token");
+        let data: String = stored.get("grant_data");
+        assert!(access != issued.token && stored_refresh != refresh);
+        assert!(!data.contains("challenge") && !data.contains("Owner"));
+
+        assert_eq!(
+            store.extract(&code).await.unwrap().unwrap().extensions,
+            grant().extensions
+        );
+        let recovered = store.recover_token(&issued.token).await.unwrap().unwrap();
+        assert_eq!(recovered.extensions, grant().extensions);
+        assert!(store.recover_refresh(&refresh).await.unwrap().is_some());
+        store.refresh(&refresh, recovered).await.unwrap();
+        assert_eq!(store.recover_token(&issued.token).await.unwrap(), None);
+        assert_eq!(Issuer::revoke_client(&mut store, "Client").await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn audit_log() {
+        use oxide_auth::endpoint::{GrantEvent, GrantOutcome, GrantRecord};
+
+        let store = store().await;
+        let record = GrantRecord {
+            client_id: Some("Client".into()),
+            ..GrantRecord::new(GrantEvent::Token, GrantOutcome::Denied)
+        };
+        store.append_audit(&AuditEntry::new(&record, None)).await.unwrap();
+
+        let row = sqlx::query("SELECT event, outcome, client_id FROM oauth_audit_log")
+            .fetch_one(store.pool())
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>(0), "token");
+        assert_eq!(row.get::<String, _>(1), "denied");
+        assert_eq!(row.get::<String, _>(2), "Client");
+    }
+}
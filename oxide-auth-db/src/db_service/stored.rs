@@ -0,0 +1,281 @@
+//! Serializable forms of primitives shared by the datasources.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use chrono::{TimeZone, Utc};
+use oxide_auth::primitives::grant::{Extensions, Grant, Value};
+use oxide_auth::primitives::registrar::{
+    BoundClient, ClientAuthMethod, ClientType, ClientUrl, EncodedClient, ExactUrl, GrantType,
+    RefreshPolicy, RegisteredUrl, RegistrarError,
+};
+use oxide_auth::primitives::scope::Scope;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A client as it is written to a datasource, one field per column.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredClient {
+    /// The id of the client.
+    pub client_id: String,
+
+    /// The default redirect uri.
+    pub redirect_uri: String,
+
+    /// Additional redirect uris, as a JSON array of strings.
+    pub additional_redirect_uris: String,
+
+    /// The scope the client gets if none was given.
+    pub default_scope: String,
+
+    /// The encoded passphrase of a confidential client.
+    pub client_secret: Option<String>,
+
+    /// Further registration settings, as a JSON document of `ClientSettings`.
+    ///
+    /// Clients stored before the column existed have none and keep the defaults.
+    #[serde(default)]
+    pub settings: Option<String>,
+
+    /// Whether the client was disabled.
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+/// The registration settings of a client that have no column of their own.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientSettings {
+    /// The scope the client may request, if it may request other than its default scope.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_scope: Option<String>,
+
+    /// The grant types the client registered for, all if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grant_types: Vec<GrantType>,
+
+    /// How the client authenticates, any method fitting its type if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_method: Option<ClientAuthMethod>,
+
+    /// When the client is issued refresh tokens.
+    #[serde(default, skip_serializing_if = "is_default_policy")]
+    pub refresh_policy: RefreshPolicy,
+
+    /// Further registration metadata.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+fn is_default_policy(policy: &RefreshPolicy) -> bool {
+    *policy == RefreshPolicy::default()
+}
+
+/// A grant as it is written to a datasource.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredGrant {
+    /// Identifies the owner of the resource.
+    pub owner_id: String,
+
+    /// Identifies the client to which the grant was issued.
+    pub client_id: String,
+
+    /// The scope granted to the client.
+    pub scope: Scope,
+
+    /// The redirection uri under which the client resides.
+    pub redirect_uri: Url,
+
+    /// Expiration date of the grant, in milliseconds since the unix epoch.
+    pub until: i64,
+
+    /// Encoded extensions of the grant.
+    pub extensions: Vec<StoredExtension>,
+}
+
+/// A single grant extension.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredExtension {
+    /// Identifier of the extension.
+    pub name: String,
+
+    /// Whether the content may be shown to the client.
+    pub public: bool,
+
+    /// The content of the extension, if any.
+    pub value: Option<String>,
+}
+
+impl StoredClient {
+    /// Prepare an encoded client for storage.
+    pub fn from_encoded(client: &EncodedClient) -> anyhow::Result<Self> {
+        let additional: Vec<&str> = client
+            .additional_redirect_uris
+            .iter()
+            .map(RegisteredUrl::as_str)
+            .collect();
+        let client_secret = match &client.encoded_client {
+            ClientType::Public => None,
+            ClientType::Confidential { passdata } => Some(String::from_utf8(passdata.clone())?),
+        };
+        let settings = ClientSettings {
+            allowed_scope: client.allowed_scope.as_ref().map(Scope::to_string),
+            grant_types: client.grant_types.clone(),
+            auth_method: client.auth_method,
+            refresh_policy: client.refresh_policy,
+            metadata: client.metadata.clone(),
+        };
+
+        Ok(StoredClient {
+            client_id: client.client_id.clone(),
+            redirect_uri: client.redirect_uri.as_str().to_owned(),
+            additional_redirect_uris: serde_json::to_string(&additional)?,
+            default_scope: client.default_scope.to_string(),
+            client_secret,
+            settings: Some(serde_json::to_string(&settings)?),
+            disabled: client.disabled,
+        })
+    }
+
+    /// The registration settings, the defaults if none were stored.
+    pub fn settings(&self) -> anyhow::Result<ClientSettings> {
+        match &self.settings {
+            None => Ok(ClientSettings::default()),
+            Some(settings) => Ok(serde_json::from_str(settings)?),
+        }
+    }
+
+    /// Restore the encoded client.
+    pub fn into_encoded(self) -> anyhow::Result<EncodedClient> {
+        let settings = self.settings()?;
+        let additional_redirect_uris =
+            serde_json::from_str::<Vec<String>>(&self.additional_redirect_uris)?
+                .iter()
+                .map(|uri| uri.parse::<ExactUrl>().map(RegisteredUrl::from))
+                .collect::<Result<_, _>>()?;
+        let encoded_client = match self.client_secret {
+            None => ClientType::Public,
+            Some(secret) => ClientType::Confidential {
+                passdata: secret.into_bytes(),
+            },
+        };
+
+        Ok(EncodedClient {
+            client_id: self.client_id,
+            redirect_uri: RegisteredUrl::from(self.redirect_uri.parse::<ExactUrl>()?),
+            additional_redirect_uris,
+            default_scope: self
+                .default_scope
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid stored scope"))?,
+            encoded_client,
+            refresh_policy: settings.refresh_policy,
+            allowed_scope: settings
+                .allowed_scope
+                .map(|scope| scope.parse())
+                .transpose()
+                .map_err(|_| anyhow::anyhow!("Invalid stored scope"))?,
+            grant_types: settings.grant_types,
+            auth_method: settings.auth_method,
+            metadata: settings.metadata,
+            disabled: self.disabled,
+        })
+    }
+}
+
+/// Bind a request to one of the redirect uris of a stored client.
+///
+/// Performs exact matching as motivated in the rfc. Disabled clients bind no redirect uri.
+pub fn bind_redirect<'a>(
+    client: EncodedClient, bound: ClientUrl<'a>,
+) -> Result<BoundClient<'a>, RegistrarError> {
+    if client.disabled {
+        return Err(RegistrarError::Unspecified);
+    }
+
+    let registered_url = match bound.redirect_uri {
+        None => client.redirect_uri,
+        Some(ref url) => {
+            let original = std::iter::once(client.redirect_uri);
+            let alternatives = client.additional_redirect_uris.into_iter();
+            match original
+                .chain(alternatives)
+                .find(|registered| *registered == *url.as_ref())
+            {
+                Some(registered) => registered,
+                None => return Err(RegistrarError::Unspecified),
+            }
+        }
+    };
+
+    Ok(BoundClient {
+        client_id: bound.client_id,
+        redirect_uri: Cow::Owned(registered_url),
+    })
+}
+
+impl StoredGrant {
+    /// Expiration date of the grant in milliseconds, to be stored in its own column for queries.
+    pub fn expires_at(&self) -> i64 {
+        self.until
+    }
+
+    /// Whether the grant was issued to the client, and on behalf of the owner if one is given.
+    ///
+    /// Selects the grants to delete when a client, or the authorization of one owner, is revoked.
+    pub fn issued_to(&self, client_id: &str, owner_id: Option<&str>) -> bool {
+        self.client_id == client_id && owner_id.is_none_or(|owner_id| self.owner_id == owner_id)
+    }
+
+    /// Restore the original grant.
+    pub fn into_grant(self) -> anyhow::Result<Grant> {
+        let until = Utc
+            .timestamp_millis_opt(self.until)
+            .single()
+            .ok_or_else(|| anyhow::anyhow!("Invalid expiration date of grant"))?;
+
+        let mut extensions = Extensions::new();
+        for extension in self.extensions {
+            let value = if extension.public {
+                Value::public(extension.value)
+            } else {
+                Value::private(extension.value)
+            };
+            extensions.set_raw(extension.name, value);
+        }
+
+        Ok(Grant {
+            owner_id: self.owner_id,
+            client_id: self.client_id,
+            scope: self.scope,
+            redirect_uri: self.redirect_uri,
+            until,
+            extensions,
+        })
+    }
+}
+
+impl From<&Grant> for StoredGrant {
+    fn from(grant: &Grant) -> Self {
+        let public = grant.extensions.public().map(|(name, value)| (name, true, value));
+        let private = grant
+            .extensions
+            .private()
+            .map(|(name, value)| (name, false, value));
+        let extensions = public
+            .chain(private)
+            .map(|(name, public, value)| StoredExtension {
+                name: name.to_owned(),
+                public,
+                value: value.map(str::to_owned),
+            })
+            .collect();
+
+        StoredGrant {
+            owner_id: grant.owner_id.clone(),
+            client_id: grant.client_id.clone(),
+            scope: grant.scope.clone(),
+            redirect_uri: grant.redirect_uri.clone(),
+            until: grant.until.timestamp_millis(),
+            extensions,
+        }
+    }
+}
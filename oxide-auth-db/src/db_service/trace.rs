@@ -0,0 +1,79 @@
+//! Spans of database calls, with the `tracing` feature.
+//!
+//! Each command sent to Redis and each SQL statement executes in a span named `db`, with the target
+//! `oxide_auth_db` and the OpenTelemetry attributes `db.system`, `db.operation`, `otel.kind` and
+//! `otel.status_code`. The spans are children of the current span, which inside a flow of
+//! `oxide-auth` is the span of the flow. Without the feature all of this compiles to nothing.
+#[cfg(feature = "tracing")]
+use tracing::{field::Empty, Span};
+
+/// The span of a single database call.
+pub(crate) struct DbSpan {
+    #[cfg(feature = "tracing")]
+    span: Span,
+}
+
+/// A span for an operation of a database system, named as in the OpenTelemetry conventions.
+#[cfg(feature = "tracing")]
+pub(crate) fn span(system: &'static str, operation: &'static str) -> DbSpan {
+    DbSpan {
+        span: tracing::info_span!(
+            target: "oxide_auth_db",
+            "db",
+            db.system = system,
+            db.operation = operation,
+            otel.name = operation,
+            otel.kind = "client",
+            otel.status_code = Empty,
+        ),
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn span(_: &'static str, _: &'static str) -> DbSpan {
+    DbSpan {}
+}
+
+impl DbSpan {
+    /// Run a call within the span, marking the span as failed on errors.
+    #[cfg(feature = "with-redis")]
+    pub(crate) fn in_scope<T, E, F>(&self, call: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        #[cfg(feature = "tracing")]
+        {
+            let result = self.span.in_scope(call);
+            self.failed(result.is_err());
+            result
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        call()
+    }
+
+    /// Await a call within the span, marking the span as failed on errors.
+    #[cfg(feature = "with-sqlx")]
+    pub(crate) async fn instrument<T, E, F>(self, call: F) -> Result<T, E>
+    where
+        F: std::future::Future<Output = Result<T, E>>,
+    {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            let result = call.instrument(self.span.clone()).await;
+            self.failed(result.is_err());
+            result
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        call.await
+    }
+
+    #[cfg(feature = "tracing")]
+    fn failed(&self, failed: bool) {
+        if failed {
+            self.span.record("otel.status_code", "ERROR");
+        }
+    }
+}
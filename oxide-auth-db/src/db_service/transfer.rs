@@ -0,0 +1,412 @@
+//! Moving state from one storage backend to another.
+//!
+//! Changing the storage of a running authorization server should not log out every user. A
+//! [`transfer`] streams the clients, pending authorization codes and tokens out of any backend
+//! implementing [`Export`] and writes them into one implementing [`Import`], for example from the
+//! in-memory `TokenMap` or Redis into Postgres. Afterwards the target is read back and compared
+//! record by record against a digest of everything that was written, so that a lossy or partial
+//! migration fails loudly instead of surfacing later as rejected tokens.
+//!
+//! Client passphrases are moved in their encoded form. The target must therefore use the same
+//! `PasswordPolicy` as the source, which holds for the default `Argon2` policy of all backends.
+use std::collections::HashMap;
+
+use chrono::Utc;
+use oxide_auth::primitives::authorizer::AuthMap;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::issuer::TokenMap;
+use oxide_auth::primitives::registrar::ClientMap;
+use serde::{Deserialize, Serialize};
+
+use crate::db_service::encryption::{self, ValueCipher};
+use crate::db_service::stored::{ClientSettings, StoredClient, StoredGrant};
+
+/// A single item of the state of an authorization server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    /// A registered client, with its passphrase still encoded.
+    Client(StoredClient),
+
+    /// An authorization code that has not been exchanged yet.
+    Grant {
+        /// The code handed to the client.
+        code: String,
+
+        /// The grant represented by the code.
+        grant: StoredGrant,
+    },
+
+    /// An issued access token, possibly with a refresh token.
+    Token {
+        /// The access token.
+        access: String,
+
+        /// The refresh token issued along with the access token.
+        refresh: Option<String>,
+
+        /// The grant represented by both tokens.
+        grant: StoredGrant,
+    },
+}
+
+/// The records of a backend, in no particular order.
+pub type Records<'a> = Box<dyn Iterator<Item = anyhow::Result<Record>> + 'a>;
+
+/// A backend whose state can be read in full.
+pub trait Export {
+    /// Iterate over all clients, grants and tokens of the backend.
+    ///
+    /// Tenanted backends only return the records of their tenant.
+    fn export(&self) -> anyhow::Result<Records<'_>>;
+}
+
+/// A backend which can take over records exported elsewhere.
+pub trait Import {
+    /// Store a record exactly as it is, replacing an existing one with the same key.
+    ///
+    /// Fails if the backend can not store this kind of record.
+    fn import(&mut self, record: Record) -> anyhow::Result<()>;
+
+    /// The record as the backend would store and later export it.
+    ///
+    /// Backends that keep tokens only as lookup keys, see `ValueCipher::lookup_key`, replace them
+    /// here. The default keeps the record as it is.
+    fn stored_form(&self, record: Record) -> anyhow::Result<Record> {
+        Ok(record)
+    }
+}
+
+/// Counts of the records moved by a [`transfer`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Clients written to the target.
+    pub clients: usize,
+
+    /// Authorization codes written to the target.
+    pub grants: usize,
+
+    /// Tokens written to the target.
+    pub tokens: usize,
+
+    /// Codes and tokens skipped because they could no longer be used.
+    pub expired: usize,
+}
+
+/// Copy all records of `source` into `target` and verify the result.
+///
+/// Authorization codes past their expiry are skipped, as are expired access tokens without a
+/// refresh token. Fails on the first record that can not be read or written, and after the copy
+/// if any written record is missing from the target or differs from the original.
+pub fn transfer<S, T>(source: &S, target: &mut T) -> anyhow::Result<Report>
+where
+    S: Export + ?Sized,
+    T: Export + Import + ?Sized,
+{
+    let now = Utc::now().timestamp_millis();
+    let mut report = Report::default();
+    let mut written = HashMap::new();
+
+    for record in source.export()? {
+        let record = target.stored_form(record?)?;
+        match &record {
+            Record::Client(_) => report.clients += 1,
+            Record::Grant { grant, .. } if grant.until <= now => {
+                report.expired += 1;
+                continue;
+            }
+            Record::Grant { .. } => report.grants += 1,
+            Record::Token {
+                refresh: None, grant, ..
+            } if grant.until <= now => {
+                report.expired += 1;
+                continue;
+            }
+            Record::Token { .. } => report.tokens += 1,
+        }
+
+        written.insert(record.key(), record.digest());
+        target.import(record)?;
+    }
+
+    verify(target, written)?;
+    Ok(report)
+}
+
+/// Check that the backend contains all records with the expected digests.
+fn verify<T: Export + ?Sized>(target: &T, mut expected: HashMap<String, u64>) -> anyhow::Result<()> {
+    for record in target.export()? {
+        let record = record?;
+        let key = record.key();
+        match expected.remove(&key) {
+            Some(digest) if digest != record.digest() => {
+                anyhow::bail!("Record {} was altered during the transfer", key)
+            }
+            // Also records that existed in the target beforehand.
+            _ => (),
+        }
+    }
+
+    match expected.keys().next() {
+        Some(key) => anyhow::bail!(
+            "{} records are missing after the transfer, such as {}",
+            expected.len(),
+            key
+        ),
+        None => Ok(()),
+    }
+}
+
+impl Record {
+    /// Identifies the record within its backend.
+    pub fn key(&self) -> String {
+        match self {
+            Record::Client(client) => format!("client:{}", client.client_id),
+            Record::Grant { code, .. } => format!("grant:{}", code),
+            Record::Token { access, .. } => format!("token:{}", access),
+        }
+    }
+
+    /// Replace the tokens with their lookup keys, if there is a cipher.
+    ///
+    /// Tokens that already are lookup keys are kept, see `encryption::import_lookup_key`.
+    pub fn with_lookup_keys(self, cipher: Option<&dyn ValueCipher>) -> anyhow::Result<Self> {
+        match self {
+            Record::Token {
+                access,
+                refresh,
+                grant,
+            } => Ok(Record::Token {
+                access: encryption::import_lookup_key(cipher, &access)?,
+                refresh: refresh
+                    .map(|refresh| encryption::import_lookup_key(cipher, &refresh))
+                    .transpose()?,
+                grant,
+            }),
+            other => Ok(other),
+        }
+    }
+
+    /// A checksum of the content, independent of the representation chosen by the backend.
+    fn digest(&self) -> u64 {
+        let mut digest = Fnv::default();
+        match self {
+            Record::Client(client) => {
+                digest.write(&client.client_id);
+                digest.write(&client.redirect_uri);
+                let mut additional: Vec<String> =
+                    serde_json::from_str(&client.additional_redirect_uris).unwrap_or_default();
+                additional.sort();
+                additional.iter().for_each(|uri| digest.write(uri));
+                let mut scope: Vec<&str> = client.default_scope.split_whitespace().collect();
+                scope.sort_unstable();
+                scope.iter().for_each(|token| digest.write(token));
+                digest.write(client.client_secret.as_deref().unwrap_or(""));
+                // Missing and default settings are the same content.
+                let settings = client.settings().unwrap_or_default();
+                if settings != ClientSettings::default() {
+                    digest.write(&serde_json::to_string(&settings).unwrap_or_default());
+                }
+                // Only disabled clients add to the digest, so a target dropping the flag is noticed.
+                if client.disabled {
+                    digest.write("disabled");
+                }
+            }
+            Record::Grant { code, grant } => {
+                digest.write(code);
+                digest.grant(grant);
+            }
+            Record::Token {
+                access,
+                refresh,
+                grant,
+            } => {
+                digest.write(access);
+                digest.write(refresh.as_deref().unwrap_or(""));
+                digest.grant(grant);
+            }
+        }
+        digest.0
+    }
+}
+
+/// The 64-bit FNV-1a hash, stable across processes unlike the default hasher of the standard library.
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, value: &str) {
+        // The length separates consecutive fields.
+        for byte in (value.len() as u64).to_le_bytes().iter().chain(value.as_bytes()) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn grant(&mut self, grant: &StoredGrant) {
+        self.write(&grant.owner_id);
+        self.write(&grant.client_id);
+        let mut scope: Vec<&str> = grant.scope.iter().collect();
+        scope.sort_unstable();
+        scope.iter().for_each(|token| self.write(token));
+        self.write(grant.redirect_uri.as_str());
+        self.write(&grant.until.to_string());
+        let mut extensions: Vec<_> = grant.extensions.iter().collect();
+        extensions.sort_by(|a, b| a.name.cmp(&b.name));
+        for extension in extensions {
+            self.write(&extension.name);
+            self.write(if extension.public { "public" } else { "private" });
+            self.write(extension.value.as_deref().unwrap_or(""));
+        }
+    }
+}
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Export for ClientMap {
+    fn export(&self) -> anyhow::Result<Records<'_>> {
+        Ok(Box::new(self.clients().map(|client| {
+            StoredClient::from_encoded(client).map(Record::Client)
+        })))
+    }
+}
+
+impl Import for ClientMap {
+    fn import(&mut self, record: Record) -> anyhow::Result<()> {
+        match record {
+            Record::Client(client) => self.import_client(client.into_encoded()?),
+            other => anyhow::bail!("A client map can not store {}", other.key()),
+        }
+        Ok(())
+    }
+}
+
+impl<I: TagGrant> Export for AuthMap<I> {
+    fn export(&self) -> anyhow::Result<Records<'_>> {
+        Ok(Box::new(self.grants().map(|(code, grant)| {
+            Ok(Record::Grant {
+                code: code.to_owned(),
+                grant: StoredGrant::from(grant),
+            })
+        })))
+    }
+}
+
+impl<I: TagGrant> Import for AuthMap<I> {
+    fn import(&mut self, record: Record) -> anyhow::Result<()> {
+        match record {
+            Record::Grant { code, grant } => self.import_grant(code, grant.into_grant()?),
+            other => anyhow::bail!("An authorization map can not store {}", other.key()),
+        }
+        Ok(())
+    }
+}
+
+impl<G: TagGrant> Export for TokenMap<G> {
+    fn export(&self) -> anyhow::Result<Records<'_>> {
+        Ok(Box::new(self.tokens().map(|(access, refresh, grant)| {
+            Ok(Record::Token {
+                access: access.to_owned(),
+                refresh: refresh.map(str::to_owned),
+                grant: StoredGrant::from(grant),
+            })
+        })))
+    }
+}
+
+impl<G: TagGrant> Import for TokenMap<G> {
+    fn import(&mut self, record: Record) -> anyhow::Result<()> {
+        match record {
+            Record::Token {
+                access,
+                refresh,
+                grant,
+            } => self.import_token(access, refresh, grant.into_grant()?),
+            other => anyhow::bail!("A token map can not store {}", other.key()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, SubsecRound};
+    use oxide_auth::primitives::authorizer::Authorizer;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::{Extensions, Grant, Value};
+    use oxide_auth::primitives::issuer::Issuer;
+
+    fn grant(valid: Duration) -> Grant {
+        let mut extensions = Extensions::new();
+        extensions.set_raw("pkce".into(), Value::private(Some("challenge".into())));
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "read write".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            // Backends store the expiry with millisecond precision.
+            until: (Utc::now() + valid).trunc_subsecs(3),
+            extensions,
+        }
+    }
+
+    #[test]
+    fn moves_live_grants() {
+        let mut source = AuthMap::new(RandomGenerator::new(16));
+        let live = source.authorize(grant(Duration::minutes(10))).unwrap();
+        source.authorize(grant(Duration::minutes(-10))).unwrap();
+
+        let mut target = AuthMap::new(RandomGenerator::new(16));
+        let report = transfer(&source, &mut target).unwrap();
+        assert_eq!(report.grants, 1);
+        assert_eq!(report.expired, 1);
+        assert_eq!(target.extract(&live).unwrap(), source.extract(&live).unwrap());
+    }
+
+    #[test]
+    fn moves_tokens() {
+        let mut source = TokenMap::new(RandomGenerator::new(16));
+        let issued = source.issue(grant(Duration::minutes(10))).unwrap();
+
+        let mut target = TokenMap::new(RandomGenerator::new(16));
+        let report = transfer(&source, &mut target).unwrap();
+        assert_eq!(report.tokens, 1);
+        assert_eq!(
+            target.recover_token(&issued.token).unwrap(),
+            source.recover_token(&issued.token).unwrap()
+        );
+        assert!(target
+            .recover_refresh(&issued.refresh.unwrap())
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn detects_lossy_targets() {
+        /// Forgets every record it is given.
+        struct Lossy;
+
+        impl Export for Lossy {
+            fn export(&self) -> anyhow::Result<Records<'_>> {
+                Ok(Box::new(std::iter::empty()))
+            }
+        }
+
+        impl Import for Lossy {
+            fn import(&mut self, _: Record) -> anyhow::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut source = TokenMap::new(RandomGenerator::new(16));
+        source.issue(grant(Duration::minutes(10))).unwrap();
+        assert!(transfer(&source, &mut Lossy).is_err());
+
+        let mut clients = ClientMap::new();
+        assert!(transfer(&source, &mut clients).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use lru::LruCache;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, Issuer, RefreshedToken};
+
+use crate::primitives::revocation::{token_digest, Revocation, RevocationSink};
+
+/// A read-through cache of access tokens in front of another issuer.
+///
+/// Resource servers recover the grant of the bearer token on every request. This wrapper remembers
+/// the grants of valid access tokens in a bounded LRU cache for at most a fixed time to live, and
+/// never beyond the expiry of the token itself. Unknown tokens and failures are never cached.
+///
+/// A token revoked at the inner issuer remains usable through this cache until its entry expires.
+/// Hand an `invalidator` to a subscriber of revocation notifications, such as the one of the Redis
+/// datasource, to close that window on every replica within moments.
+///
+/// Entries are keyed by the `token_digest` of their token, which is also how revocations name
+/// tokens between replicas. A grant looked up in the inner issuer while a revocation arrived is
+/// returned but not cached, it may already be revoked.
+pub struct CachedIssuer<I> {
+    inner: I,
+    ttl: Duration,
+    cache: Arc<Mutex<LruCache<String, CachedGrant>>>,
+    epoch: Arc<AtomicU64>,
+}
+
+/// Drops tokens from the cache of a `CachedIssuer`, usable from other threads.
+#[derive(Clone)]
+pub struct IssuerInvalidator {
+    cache: Arc<Mutex<LruCache<String, CachedGrant>>>,
+    epoch: Arc<AtomicU64>,
+}
+
+struct CachedGrant {
+    created: Instant,
+    grant: Grant,
+}
+
+impl<I: Issuer> CachedIssuer<I> {
+    /// Cache up to `capacity` access tokens of the inner issuer, each for at most `ttl`.
+    ///
+    /// ## Panics
+    ///
+    /// When `capacity` is zero.
+    pub fn new(inner: I, capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).expect("Cache capacity must not be zero");
+        CachedIssuer {
+            inner,
+            ttl,
+            cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            epoch: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Forget a cached access token.
+    pub fn invalidate(&self, token: &str) {
+        invalidated(&self.cache, &self.epoch).pop(&token_digest(token));
+    }
+
+    /// Forget all cached tokens.
+    pub fn clear(&self) {
+        invalidated(&self.cache, &self.epoch).clear();
+    }
+
+    /// A handle to invalidate entries of this cache when tokens are revoked elsewhere.
+    pub fn invalidator(&self) -> IssuerInvalidator {
+        IssuerInvalidator {
+            cache: self.cache.clone(),
+            epoch: self.epoch.clone(),
+        }
+    }
+
+    /// Get a reference to the inner issuer.
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner issuer.
+    ///
+    /// The cache is cleared since modifications can not be tracked.
+    pub fn inner_mut(&mut self) -> &mut I {
+        self.clear();
+        &mut self.inner
+    }
+
+    /// Remove the cache, returning the inner issuer.
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+
+    /// Forget all cached tokens whose grant matches.
+    fn forget(&self, matches: impl Fn(&Grant) -> bool) {
+        let mut cache = invalidated(&self.cache, &self.epoch);
+        let stale: Vec<String> = cache
+            .iter()
+            .filter(|(_, entry)| matches(&entry.grant))
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in stale {
+            cache.pop(&token);
+        }
+    }
+
+    fn cached(&self, digest: &str) -> Option<Grant> {
+        let mut cache = lock(&self.cache);
+        let live = match cache.peek(digest) {
+            Some(entry) => entry.created.elapsed() < self.ttl && entry.grant.until > Utc::now(),
+            None => return None,
+        };
+
+        if !live {
+            cache.pop(digest);
+            return None;
+        }
+
+        cache.get(digest).map(|entry| entry.grant.clone())
+    }
+}
+
+fn lock<V>(cache: &Mutex<LruCache<String, V>>) -> MutexGuard<'_, LruCache<String, V>> {
+    // The cache is always consistent, even if another thread panicked while holding the lock.
+    cache.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+/// Lock the cache to drop entries, starting a new epoch.
+///
+/// The epoch is advanced while holding the lock, so a lookup that began before can not put its
+/// grant into the cache afterwards.
+fn invalidated<'a, V>(
+    cache: &'a Mutex<LruCache<String, V>>, epoch: &AtomicU64,
+) -> MutexGuard<'a, LruCache<String, V>> {
+    let cache = lock(cache);
+    epoch.fetch_add(1, Ordering::SeqCst);
+    cache
+}
+
+impl RevocationSink for IssuerInvalidator {
+    fn revoke(&self, revocation: &Revocation) {
+        let mut cache = invalidated(&self.cache, &self.epoch);
+        if let Some(digest) = revocation.token_digest() {
+            cache.pop(&digest);
+        }
+    }
+
+    fn reset(&self) {
+        invalidated(&self.cache, &self.epoch).clear();
+    }
+}
+
+impl<I: Issuer> Issuer for CachedIssuer<I> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        self.inner.issue(grant)
+    }
+
+    fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        self.inner.refresh(refresh, grant)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        let digest = token_digest(token);
+        if let Some(grant) = self.cached(&digest) {
+            return Ok(Some(grant));
+        }
+
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let grant = self.inner.recover_token(token)?;
+        if let Some(grant) = &grant {
+            let entry = CachedGrant {
+                created: Instant::now(),
+                grant: grant.clone(),
+            };
+            let mut cache = lock(&self.cache);
+            // A revocation during the lookup may have concerned this grant.
+            if self.epoch.load(Ordering::SeqCst) == epoch {
+                cache.put(digest, entry);
+            }
+        }
+
+        Ok(grant)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        // Refresh tokens are only presented to the authorization server, and only once.
+        self.inner.recover_refresh(token)
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        let revoked = self.inner.revoke_client(client_id)?;
+        self.forget(|grant| grant.client_id == client_id);
+        Ok(revoked)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        let revoked = self.inner.revoke_grant(owner_id, client_id)?;
+        self.forget(|grant| grant.owner_id == owner_id && grant.client_id == client_id);
+        Ok(revoked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::issuer::TokenMap;
+    use oxide_auth::primitives::generator::RandomGenerator;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + chrono::Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn serves_revocations_from_cache_until_invalidated() {
+        let mut issuer = CachedIssuer::new(
+            TokenMap::new(RandomGenerator::new(16)),
+            8,
+            Duration::from_secs(60),
+        );
+        let token = issuer.issue(grant()).unwrap().token;
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+        assert_eq!(issuer.recover_token("unknown").unwrap(), None);
+
+        // Revoked behind the back of the cache.
+        issuer.inner.revoke(&token);
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+
+        let invalidator = issuer.invalidator();
+        invalidator.revoke(&Revocation::Client(token.clone()));
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+        invalidator.revoke(&Revocation::Token(token.clone()));
+        assert_eq!(issuer.recover_token(&token).unwrap(), None);
+
+        // Other replicas learn about revocations by the digest of the token.
+        let token = issuer.issue(grant()).unwrap().token;
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+        issuer.inner.revoke(&token);
+        let message = Revocation::Token(token.clone()).to_message();
+        invalidator.revoke(&Revocation::from_message(&message).unwrap());
+        assert_eq!(issuer.recover_token(&token).unwrap(), None);
+    }
+
+    #[test]
+    fn revoke_client_drops_cached_tokens() {
+        let mut issuer = CachedIssuer::new(
+            TokenMap::new(RandomGenerator::new(16)),
+            8,
+            Duration::from_secs(60),
+        );
+        let token = issuer.issue(grant()).unwrap().token;
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+
+        assert_eq!(issuer.revoke_client("Client"), Ok(1));
+        assert_eq!(issuer.recover_token(&token).unwrap(), None);
+    }
+
+    #[test]
+    fn expires_entries() {
+        let mut issuer =
+            CachedIssuer::new(TokenMap::new(RandomGenerator::new(16)), 8, Duration::from_secs(0));
+        let token = issuer.issue(grant()).unwrap().token;
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+
+        issuer.inner.revoke(&token);
+        assert_eq!(issuer.recover_token(&token).unwrap(), None);
+    }
+
+    /// Revokes its token through the invalidator while looking it up.
+    struct Racing {
+        tokens: TokenMap<RandomGenerator>,
+        invalidator: Option<IssuerInvalidator>,
+    }
+
+    impl Issuer for Racing {
+        fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+            self.tokens.issue(grant)
+        }
+
+        fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+            self.tokens.refresh(refresh, grant)
+        }
+
+        fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+            let grant = self.tokens.recover_token(token);
+            if let Some(invalidator) = &self.invalidator {
+                invalidator.revoke(&Revocation::Token(token.to_owned()));
+            }
+            grant
+        }
+
+        fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+            self.tokens.recover_refresh(token)
+        }
+    }
+
+    #[test]
+    fn skips_caching_grants_revoked_during_lookup() {
+        let racing = Racing {
+            tokens: TokenMap::new(RandomGenerator::new(16)),
+            invalidator: None,
+        };
+        let mut issuer = CachedIssuer::new(racing, 8, Duration::from_secs(60));
+        let token = issuer.issue(grant()).unwrap().token;
+        issuer.inner_mut().invalidator = Some(issuer.invalidator());
+
+        // The revocation arrived after the grant was read, it is still answered once.
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+        assert!(issuer.cached(&token_digest(&token)).is_none());
+
+        issuer.inner_mut().invalidator = None;
+        assert!(issuer.recover_token(&token).unwrap().is_some());
+        assert!(issuer.cached(&token_digest(&token)).is_some());
+    }
+}
@@ -0,0 +1,492 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::iter::Extend;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use oxide_auth::primitives::registrar::{
+    BoundClient, Client, ClientAuthMethod, ClientUrl, GrantType, PreGrant, RefreshPolicy, RegisteredUrl,
+    Registrar, RegistrarError,
+};
+use oxide_auth::primitives::scope::Scope;
+
+use crate::primitives::revocation::{Revocation, RevocationSink};
+
+/// Number of distinct redirect uris or scopes remembered per client.
+///
+/// Requests can choose these freely, this only guards against a single client filling memory.
+const MAX_VARIANTS_PER_CLIENT: usize = 16;
+
+/// A read-through cache in front of another registrar.
+///
+/// Client lookups happen on every authorization and token request, which for database registrars
+/// means a round trip each time. This wrapper remembers successful `bound_redirect` and
+/// `negotiate` results per client in a bounded LRU cache, each entry expiring after a fixed time
+/// to live. Failures are not cached, unless remembering unknown clients is enabled with
+/// `with_unknown_clients`.
+///
+/// `check` of known clients is always forwarded to the inner registrar so that the authentication
+/// of clients is not weakened by stale data.
+///
+/// Changes to clients made outside of this wrapper become visible after the time to live at the
+/// latest. Call `invalidate` or `clear` to make them visible immediately, or let the notifications
+/// of the database do so through an `invalidator`.
+pub struct CachedRegistrar<R> {
+    inner: R,
+    ttl: Duration,
+    cache: Arc<Mutex<LruCache<String, CacheEntry>>>,
+    unknown_ttl: Duration,
+    unknown: Arc<Mutex<LruCache<String, Instant>>>,
+}
+
+/// Drops clients from the cache of a `CachedRegistrar`, usable from other threads.
+#[derive(Clone)]
+pub struct RegistrarInvalidator {
+    cache: Arc<Mutex<LruCache<String, CacheEntry>>>,
+    unknown: Arc<Mutex<LruCache<String, Instant>>>,
+}
+
+struct CacheEntry {
+    created: Instant,
+    bound: HashMap<Option<String>, RegisteredUrl>,
+    negotiated: HashMap<(String, Option<String>), PreGrant>,
+}
+
+impl<R: Registrar> CachedRegistrar<R> {
+    /// Cache up to `capacity` clients of the inner registrar, each for at most `ttl`.
+    ///
+    /// ## Panics
+    ///
+    /// When `capacity` is zero.
+    pub fn new(inner: R, capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).expect("Cache capacity must not be zero");
+        CachedRegistrar {
+            inner,
+            ttl,
+            cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            unknown_ttl: Duration::from_secs(0),
+            unknown: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::MIN))),
+        }
+    }
+
+    /// Also remember up to `capacity` client ids that the inner registrar does not know, each for
+    /// at most `ttl`.
+    ///
+    /// Requests naming such a client are refused without asking the inner registrar, which shields
+    /// the database from clients hammering the token endpoint with invalid ids and from attempts
+    /// to enumerate the registered ids. Unknown ids are kept apart from the cached clients so that
+    /// they can not evict them. Keep the time to live short, a few seconds suffice, since clients
+    /// registered elsewhere are refused until it ends or they are invalidated.
+    ///
+    /// A client is marked as unknown when binding it to its default redirect uri fails with
+    /// `RegistrarError::Unspecified`. Before checking a client or binding it to another redirect
+    /// uri, this default binding is looked up first, and cached along with the other results. An
+    /// unknown id thus costs a single request to the inner registrar, while a wrong passphrase or
+    /// redirect uri of a known client never marks it as unknown.
+    ///
+    /// ## Panics
+    ///
+    /// When `capacity` is zero.
+    pub fn with_unknown_clients(mut self, capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).expect("Cache capacity must not be zero");
+        self.unknown_ttl = ttl;
+        self.unknown = Arc::new(Mutex::new(LruCache::new(capacity)));
+        self
+    }
+
+    /// Forget everything cached about a client.
+    pub fn invalidate(&self, client_id: &str) {
+        self.lock().pop(client_id);
+        lock(&self.unknown).pop(client_id);
+    }
+
+    /// Forget all cached clients.
+    pub fn clear(&self) {
+        self.lock().clear();
+        lock(&self.unknown).clear();
+    }
+
+    /// A handle to invalidate entries of this cache when clients are revoked elsewhere.
+    pub fn invalidator(&self) -> RegistrarInvalidator {
+        RegistrarInvalidator {
+            cache: self.cache.clone(),
+            unknown: self.unknown.clone(),
+        }
+    }
+
+    /// Get a reference to the inner registrar.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner registrar.
+    ///
+    /// The cache is cleared since modifications can not be tracked.
+    pub fn inner_mut(&mut self) -> &mut R {
+        self.clear();
+        &mut self.inner
+    }
+
+    /// Remove the cache, returning the inner registrar.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LruCache<String, CacheEntry>> {
+        lock(&self.cache)
+    }
+
+    /// Run a closure on the live entry of a client, creating or replacing it as necessary.
+    fn with_entry<T>(&self, client_id: &str, now: Instant, f: impl FnOnce(&mut CacheEntry) -> T) -> T {
+        let mut cache = self.lock();
+        let expired = match cache.peek(client_id) {
+            Some(entry) => now.duration_since(entry.created) >= self.ttl,
+            None => true,
+        };
+
+        if expired {
+            cache.put(client_id.to_owned(), CacheEntry::new(now));
+        }
+
+        f(cache.get_mut(client_id).unwrap())
+    }
+
+    /// Whether the client was recently found to be unknown.
+    fn is_unknown(&self, client_id: &str, now: Instant) -> bool {
+        let mut unknown = lock(&self.unknown);
+        match unknown.get(client_id) {
+            Some(since) if now.saturating_duration_since(*since) < self.unknown_ttl => true,
+            Some(_) => {
+                unknown.pop(client_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the inner registrar knows the client, by its binding to the default redirect uri.
+    ///
+    /// Always true when unknown clients are not remembered.
+    fn is_known(&self, client_id: &str, now: Instant) -> Result<bool, RegistrarError> {
+        if self.unknown_ttl.is_zero() {
+            return Ok(true);
+        }
+
+        let default = ClientUrl {
+            client_id: Cow::Borrowed(client_id),
+            redirect_uri: None,
+        };
+        match self.bound_redirect_at(default, now) {
+            Ok(_) => Ok(true),
+            Err(RegistrarError::Unspecified) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn bound_redirect_at<'a>(
+        &self, bound: ClientUrl<'a>, now: Instant,
+    ) -> Result<BoundClient<'a>, RegistrarError> {
+        if self.is_unknown(&bound.client_id, now) {
+            return Err(RegistrarError::Unspecified);
+        }
+
+        let key = bound.redirect_uri.as_ref().map(|url| url.as_str().to_owned());
+        let cached = self.with_entry(&bound.client_id, now, |entry| entry.bound.get(&key).cloned());
+
+        if let Some(redirect_uri) = cached {
+            return Ok(BoundClient {
+                client_id: bound.client_id,
+                redirect_uri: Cow::Owned(redirect_uri),
+            });
+        }
+
+        // Another redirect uri may be refused for a known client, only the default one tells.
+        if key.is_some() && !self.is_known(&bound.client_id, now)? {
+            return Err(RegistrarError::Unspecified);
+        }
+
+        let client_id = bound.client_id.clone().into_owned();
+        let result = match self.inner.bound_redirect(bound) {
+            Ok(result) => result,
+            Err(RegistrarError::Unspecified) if key.is_none() && !self.unknown_ttl.is_zero() => {
+                lock(&self.unknown).put(client_id, now);
+                return Err(RegistrarError::Unspecified);
+            }
+            Err(err) => return Err(err),
+        };
+        let redirect_uri = result.redirect_uri.clone().into_owned();
+        self.with_entry(&client_id, now, |entry| {
+            insert_bounded(&mut entry.bound, key, redirect_uri)
+        });
+
+        Ok(result)
+    }
+
+    fn check_at(
+        &self, client_id: &str, passphrase: Option<&[u8]>, now: Instant,
+    ) -> Result<(), RegistrarError> {
+        if !self.is_known(client_id, now)? {
+            return Err(RegistrarError::Unspecified);
+        }
+
+        self.inner.check(client_id, passphrase)
+    }
+}
+
+fn lock<V>(cache: &Mutex<LruCache<String, V>>) -> MutexGuard<'_, LruCache<String, V>> {
+    // The cache is always consistent, even if another thread panicked while holding the lock.
+    cache.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+impl RevocationSink for RegistrarInvalidator {
+    fn revoke(&self, revocation: &Revocation) {
+        if let Revocation::Client(client_id) = revocation {
+            lock(&self.cache).pop(client_id);
+            lock(&self.unknown).pop(client_id);
+        }
+    }
+
+    fn reset(&self) {
+        lock(&self.cache).clear();
+        lock(&self.unknown).clear();
+    }
+}
+
+impl CacheEntry {
+    fn new(created: Instant) -> Self {
+        CacheEntry {
+            created,
+            bound: HashMap::new(),
+            negotiated: HashMap::new(),
+        }
+    }
+}
+
+fn insert_bounded<K: std::hash::Hash + Eq, V>(map: &mut HashMap<K, V>, key: K, value: V) {
+    if map.len() >= MAX_VARIANTS_PER_CLIENT {
+        map.clear();
+    }
+
+    map.insert(key, value);
+}
+
+impl<R: Registrar> Registrar for CachedRegistrar<R> {
+    fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        self.bound_redirect_at(bound, Instant::now())
+    }
+
+    fn negotiate<'a>(
+        &self, bound: BoundClient<'a>, scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        let key = (
+            bound.redirect_uri.as_str().to_owned(),
+            scope.as_ref().map(Scope::to_string),
+        );
+        let client_id = bound.client_id.clone().into_owned();
+        let now = Instant::now();
+        let cached = self.with_entry(&client_id, now, |entry| entry.negotiated.get(&key).cloned());
+
+        if let Some(pre_grant) = cached {
+            return Ok(pre_grant);
+        }
+
+        let pre_grant = self.inner.negotiate(bound, scope)?;
+        let value = pre_grant.clone();
+        self.with_entry(&client_id, now, |entry| {
+            insert_bounded(&mut entry.negotiated, key, value)
+        });
+
+        Ok(pre_grant)
+    }
+
+    fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        self.check_at(client_id, passphrase, Instant::now())
+    }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.inner.refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.inner.permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.inner.auth_method(client_id)
+    }
+}
+
+impl<R: Registrar + Extend<Client>> Extend<Client> for CachedRegistrar<R> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = Client>,
+    {
+        self.inner_mut().extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::str::FromStr;
+    use oxide_auth::primitives::registrar::{ClientMap, ExactUrl};
+
+    /// Counts the requests reaching the underlying registrar.
+    struct Counting {
+        inner: ClientMap,
+        calls: Cell<usize>,
+    }
+
+    impl Registrar for Counting {
+        fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+            self.calls.set(self.calls.get() + 1);
+            self.inner.bound_redirect(bound)
+        }
+
+        fn negotiate<'a>(
+            &self, bound: BoundClient<'a>, scope: Option<Scope>,
+        ) -> Result<PreGrant, RegistrarError> {
+            self.calls.set(self.calls.get() + 1);
+            self.inner.negotiate(bound, scope)
+        }
+
+        fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+            self.calls.set(self.calls.get() + 1);
+            self.inner.check(client_id, passphrase)
+        }
+    }
+
+    fn registrar(ttl: Duration) -> CachedRegistrar<Counting> {
+        let mut inner = ClientMap::new();
+        inner.register_client(Client::public(
+            "ClientId",
+            RegisteredUrl::Exact(ExactUrl::from_str("https://example.com").unwrap()),
+            "default".parse().unwrap(),
+        ));
+
+        let counting = Counting {
+            inner,
+            calls: Cell::new(0),
+        };
+
+        CachedRegistrar::new(counting, 8, ttl)
+    }
+
+    fn client_url() -> ClientUrl<'static> {
+        ClientUrl {
+            client_id: Cow::Borrowed("ClientId"),
+            redirect_uri: None,
+        }
+    }
+
+    #[test]
+    fn caches_lookups() {
+        let registrar = registrar(Duration::from_secs(60));
+
+        for _ in 0..3 {
+            let bound = registrar.bound_redirect(client_url()).unwrap();
+            registrar.negotiate(bound, None).unwrap();
+        }
+
+        assert_eq!(registrar.inner().calls.get(), 2);
+
+        registrar.invalidate("ClientId");
+        registrar.bound_redirect(client_url()).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 3);
+
+        let invalidator = registrar.invalidator();
+        invalidator.revoke(&Revocation::Token("ClientId".into()));
+        registrar.bound_redirect(client_url()).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 3);
+        invalidator.revoke(&Revocation::Client("ClientId".into()));
+        registrar.bound_redirect(client_url()).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 4);
+    }
+
+    #[test]
+    fn expires_entries() {
+        let registrar = registrar(Duration::from_secs(0));
+
+        registrar.bound_redirect(client_url()).unwrap();
+        registrar.bound_redirect(client_url()).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 2);
+    }
+
+    #[test]
+    fn never_caches_failures_or_checks() {
+        let registrar = registrar(Duration::from_secs(60));
+        let unknown = ClientUrl {
+            client_id: Cow::Borrowed("Unknown"),
+            redirect_uri: None,
+        };
+
+        assert!(registrar.bound_redirect(unknown.clone()).is_err());
+        assert!(registrar.bound_redirect(unknown).is_err());
+        registrar.check("ClientId", None).unwrap();
+        registrar.check("ClientId", None).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 4);
+    }
+
+    #[test]
+    fn caches_unknown_clients() {
+        let registrar =
+            registrar(Duration::from_secs(60)).with_unknown_clients(8, Duration::from_secs(60));
+        let unknown = ClientUrl {
+            client_id: Cow::Borrowed("Unknown"),
+            redirect_uri: None,
+        };
+
+        assert!(registrar.bound_redirect(unknown.clone()).is_err());
+        assert!(registrar.bound_redirect(unknown.clone()).is_err());
+        assert!(registrar.check("Unknown", None).is_err());
+        assert_eq!(registrar.inner().calls.get(), 1);
+
+        // Known clients are looked up once, their failed authentication marks nothing.
+        assert!(registrar.check("ClientId", Some(b"guess")).is_err());
+        assert_eq!(registrar.inner().calls.get(), 3);
+        registrar.check("ClientId", None).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 4);
+        let other = ClientUrl {
+            client_id: Cow::Borrowed("ClientId"),
+            redirect_uri: Some(Cow::Owned(ExactUrl::from_str("https://other.example").unwrap())),
+        };
+        assert!(registrar.bound_redirect(other).is_err());
+        assert_eq!(registrar.inner().calls.get(), 5);
+        registrar.bound_redirect(client_url()).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 5);
+
+        registrar
+            .invalidator()
+            .revoke(&Revocation::Client("Unknown".into()));
+        assert!(registrar.bound_redirect(unknown).is_err());
+        assert_eq!(registrar.inner().calls.get(), 6);
+    }
+
+    #[test]
+    fn expires_unknown_clients() {
+        let mut registrar =
+            registrar(Duration::from_secs(60)).with_unknown_clients(8, Duration::from_secs(5));
+        let now = Instant::now();
+
+        assert!(registrar.check_at("Later", None, now).is_err());
+        assert_eq!(registrar.inner().calls.get(), 1);
+
+        // Registered elsewhere, without invalidating the cache.
+        registrar.inner.inner.register_client(Client::public(
+            "Later",
+            RegisteredUrl::Exact(ExactUrl::from_str("https://later.example").unwrap()),
+            "default".parse().unwrap(),
+        ));
+
+        let before = now + Duration::from_secs(4);
+        assert!(registrar.check_at("Later", None, before).is_err());
+        assert_eq!(registrar.inner().calls.get(), 1);
+
+        let after = now + Duration::from_secs(5);
+        registrar.check_at("Later", None, after).unwrap();
+        assert_eq!(registrar.inner().calls.get(), 3);
+    }
+}
@@ -1,12 +1,13 @@
 use std::borrow::Cow;
 use std::iter::Extend;
+use std::sync::Arc;
 use once_cell::sync::Lazy;
 use oxide_auth::primitives::registrar::{
-    Argon2, BoundClient, Client, EncodedClient, PasswordPolicy, RegisteredClient, Registrar,
-    RegistrarError,
+    Argon2, BoundClient, Client, ClientAuthMethod, EncodedClient, GrantType, PasswordPolicy,
+    RefreshPolicy, RegisteredClient, Registrar, RegistrarError,
 };
 use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
-use crate::db_service::DataSource;
+use crate::db_service::{DataSource, PoolConfig, TenantScoped};
 use r2d2_redis::redis::RedisError;
 
 /// A database client service which implemented Registrar.
@@ -14,7 +15,7 @@ use r2d2_redis::redis::RedisError;
 /// password_policy: to encode client_secret.
 pub struct DBRegistrar {
     pub repo: DataSource,
-    password_policy: Option<Box<dyn PasswordPolicy>>,
+    password_policy: Option<Arc<dyn PasswordPolicy>>,
 }
 
 /// methods to search and regist clients from DataSource.
@@ -43,6 +44,17 @@ impl DBRegistrar {
         })
     }
 
+    /// Create an DB connection with detailed connection pool settings.
+    pub fn with_config(
+        url: String, config: PoolConfig, client_prefix: String,
+    ) -> Result<Self, RedisError> {
+        let repo = DataSource::with_config(url, config, client_prefix)?;
+        Ok(DBRegistrar {
+            repo,
+            password_policy: None,
+        })
+    }
+
     /// Insert or update the client record.
     pub fn register_client(&mut self, client: Client) -> Result<(), RegistrarError> {
         let password_policy = Self::current_policy(&self.password_policy);
@@ -55,11 +67,11 @@ impl DBRegistrar {
 
     /// Change how passwords are encoded while stored.
     pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
-        self.password_policy = Some(Box::new(new_policy))
+        self.password_policy = Some(Arc::new(new_policy))
     }
 
     // This is not an instance method because it needs to borrow the box but register needs &mut
-    fn current_policy(policy: &Option<Box<dyn PasswordPolicy>>) -> &dyn PasswordPolicy {
+    fn current_policy(policy: &Option<Arc<dyn PasswordPolicy>>) -> &dyn PasswordPolicy {
         policy
             .as_ref()
             .map(|boxed| &**boxed)
@@ -67,6 +79,20 @@ impl DBRegistrar {
     }
 }
 
+/// The password policy is shared with the scoped registrar.
+impl TenantScoped for DBRegistrar {
+    fn tenant(&self) -> &str {
+        self.repo.tenant()
+    }
+
+    fn for_tenant(&self, tenant: &str) -> Self {
+        DBRegistrar {
+            repo: self.repo.for_tenant(tenant),
+            password_policy: self.password_policy.clone(),
+        }
+    }
+}
+
 impl Extend<Client> for DBRegistrar {
     fn extend<I>(&mut self, iter: I)
     where
@@ -81,7 +107,7 @@ impl Extend<Client> for DBRegistrar {
 impl Registrar for DBRegistrar {
     fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
         let client = match self.repo.find_client_by_id(bound.client_id.as_ref()) {
-            Ok(detail) => detail,
+            Ok(detail) if !detail.disabled => detail,
             _ => return Err(RegistrarError::Unspecified),
         };
         // Perform exact matching as motivated in the rfc
@@ -132,6 +158,23 @@ impl Registrar for DBRegistrar {
         })?;
         Ok(())
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.repo
+            .find_client_by_id(client_id)
+            .map(|client| client.refresh_policy)
+            .unwrap_or_default()
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.repo
+            .find_client_by_id(client_id)
+            .is_ok_and(|client| client.permits_grant(grant_type))
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.repo.find_client_by_id(client_id).ok()?.auth_method
+    }
 }
 
 #[cfg(test)]
@@ -280,4 +323,68 @@ mod tests {
             .check(private_id, Some(b"Not the private passphrase"))
             .expect_err("Authorization succeed with wrong password");
     }
+
+    #[test]
+    fn tenants_are_isolated() {
+        if crate::requires_redis_and_should_skip() {
+            return;
+        }
+
+        let registrar = DBRegistrar::new(
+            "redis://localhost/3".parse().unwrap(),
+            32,
+            "client:".parse().unwrap(),
+        )
+        .unwrap();
+        let mut first = registrar.for_tenant("first");
+        let second = registrar.for_tenant("second");
+        let client_id = "TenantClientId";
+
+        first
+            .register_client(Client::public(
+                client_id,
+                RegisteredUrl::Exact(ExactUrl::new("https://example.com".parse().unwrap()).unwrap()),
+                "default".parse().unwrap(),
+            ))
+            .unwrap();
+        assert_eq!(first.tenant(), "first");
+        first.check(client_id, None).unwrap();
+        second
+            .check(client_id, None)
+            .expect_err("Client of another tenant was found");
+    }
+
+    #[test]
+    fn lists_clients() {
+        if crate::requires_redis_and_should_skip() {
+            return;
+        }
+
+        let registrar = DBRegistrar::new(
+            "redis://localhost/3".parse().unwrap(),
+            32,
+            "client:".parse().unwrap(),
+        )
+        .unwrap();
+        let mut registrar = registrar.for_tenant("listing");
+        for client_id in ["FirstListedId", "SecondListedId"] {
+            registrar
+                .register_client(Client::public(
+                    client_id,
+                    RegisteredUrl::Exact(ExactUrl::new("https://example.com".parse().unwrap()).unwrap()),
+                    "default".parse().unwrap(),
+                ))
+                .unwrap();
+        }
+
+        let mut listed: Vec<_> = registrar
+            .repo
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|client| client.client_id)
+            .collect();
+        listed.sort();
+        assert_eq!(listed, ["FirstListedId", "SecondListedId"]);
+    }
 }
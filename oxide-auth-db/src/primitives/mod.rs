@@ -1 +1,5 @@
+pub mod cached_issuer;
+pub mod cached_registrar;
+#[cfg(feature = "with-redis")]
 pub mod db_registrar;
+pub mod revocation;
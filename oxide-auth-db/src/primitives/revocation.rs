@@ -0,0 +1,109 @@
+//! Revocations pushed to in-process caches.
+//!
+//! Caches such as `CachedRegistrar` and `CachedIssuer` make every replica of a server remember
+//! clients and tokens for a while. When one replica revokes a token or changes a client, the others
+//! learn about it through a notification mechanism of the database, for example the
+//! `RevocationSubscriber` of the Redis datasource, which forwards it to a [`RevocationSink`].
+//!
+//! Notifications may be seen by anyone able to subscribe to them, so revoked tokens are announced
+//! by their [`token_digest`] only. Replicas receive them as a [`Revocation::TokenDigest`].
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+/// Something that stopped being valid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Revocation {
+    /// An access token was revoked.
+    Token(String),
+
+    /// An access token was revoked, named by its `token_digest`.
+    TokenDigest(String),
+
+    /// A client was changed or removed.
+    Client(String),
+}
+
+/// Receives revocations, usually to drop entries from a cache.
+pub trait RevocationSink: Send + Sync {
+    /// Forget everything derived from the revoked item.
+    fn revoke(&self, revocation: &Revocation);
+
+    /// Forget everything.
+    ///
+    /// Called whenever revocations may have been missed, such as after the subscription to the
+    /// notifications of the database was interrupted.
+    fn reset(&self);
+}
+
+/// The SHA-256 digest of a token in unpadded base64url, naming it without revealing it.
+pub fn token_digest(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+impl Revocation {
+    /// Encode as the payload of a notification.
+    ///
+    /// Tokens are encoded by their digest, they are decoded as a `Revocation::TokenDigest`.
+    pub fn to_message(&self) -> String {
+        match self {
+            Revocation::Token(token) => format!("token:{}", token_digest(token)),
+            Revocation::TokenDigest(digest) => format!("token:{}", digest),
+            Revocation::Client(client_id) => format!("client:{}", client_id),
+        }
+    }
+
+    /// The digest of the revoked token, if a token was revoked.
+    pub fn token_digest(&self) -> Option<String> {
+        match self {
+            Revocation::Token(token) => Some(token_digest(token)),
+            Revocation::TokenDigest(digest) => Some(digest.clone()),
+            Revocation::Client(_) => None,
+        }
+    }
+
+    /// Decode the payload of a notification.
+    pub fn from_message(message: &str) -> Option<Self> {
+        let (kind, value) = message.split_once(':')?;
+        match kind {
+            "token" => Some(Revocation::TokenDigest(value.to_owned())),
+            "client" => Some(Revocation::Client(value.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+impl<S: RevocationSink + ?Sized> RevocationSink for std::sync::Arc<S> {
+    fn revoke(&self, revocation: &Revocation) {
+        (**self).revoke(revocation)
+    }
+
+    fn reset(&self) {
+        (**self).reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_roundtrip() {
+        for revocation in [
+            Revocation::TokenDigest(token_digest("a:b")),
+            Revocation::Client("client".into()),
+        ] {
+            let message = revocation.to_message();
+            assert_eq!(Revocation::from_message(&message), Some(revocation));
+        }
+
+        let token = Revocation::Token("SecretToken".into());
+        let message = token.to_message();
+        assert!(!message.contains("SecretToken"));
+        let received = Revocation::from_message(&message).unwrap();
+        assert_eq!(received.token_digest(), token.token_digest());
+
+        assert_eq!(Revocation::from_message("owner:someone"), None);
+        assert_eq!(Revocation::from_message("token"), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! A gRPC service validating the tokens of an `Issuer`.
+//!
+//! Internal services that do not speak HTTP, or that should not share the storage of the
+//! authorization server, ask the [`TokenService`] about the tokens presented to them. The service
+//! is described by `proto/token.proto` and built on `tonic`.
+//!
+//! Since the answers reveal the grants of tokens, only trusted services should be able to call it.
+//! [`mutual_tls`] configures the server to require client certificates signed by your own
+//! certificate authority.
+//!
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//! use oxide_auth::primitives::{generator::RandomGenerator, issuer::TokenMap};
+//! use oxide_auth_grpc::{mutual_tls, TokenService};
+//! use tonic::transport::{Certificate, Identity, Server};
+//!
+//! # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+//! let issuer = Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16))));
+//! let identity = Identity::from_pem(std::fs::read("server.pem")?, std::fs::read("server.key")?);
+//! let client_ca = Certificate::from_pem(std::fs::read("clients-ca.pem")?);
+//!
+//! Server::builder()
+//!     .tls_config(mutual_tls(identity, client_ca))?
+//!     .add_service(TokenService::new(issuer).into_server())
+//!     .serve("[::1]:50051".parse()?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+#![warn(missing_docs)]
+
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use oxide_auth::primitives::{grant::Grant, issuer::Issuer, scope::Scope};
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+
+/// Types generated from `proto/token.proto`.
+#[allow(missing_docs)]
+pub mod proto {
+    tonic::include_proto!("oxide_auth.v1");
+}
+
+use proto::token_validation_server::{TokenValidation, TokenValidationServer};
+use proto::{IntrospectRequest, IntrospectResponse, ValidateRequest, ValidateResponse, Validity};
+
+/// Answers validation requests with the grants of an issuer.
+///
+/// The issuer is usually shared with the token endpoint of the authorization server.
+pub struct TokenService {
+    issuer: Arc<Mutex<dyn Issuer + Send>>,
+}
+
+impl TokenService {
+    /// Validate the tokens of the issuer.
+    pub fn new<I: Issuer + Send + 'static>(issuer: Arc<Mutex<I>>) -> Self {
+        TokenService { issuer }
+    }
+
+    /// The server to add to a `tonic` router.
+    pub fn into_server(self) -> TokenValidationServer<Self> {
+        TokenValidationServer::new(self)
+    }
+
+    fn recover(&self, token: &str) -> Result<Option<Grant>, Status> {
+        let issuer = self
+            .issuer
+            .lock()
+            .map_err(|_| Status::internal("Issuer lock poisoned"))?;
+        issuer
+            .recover_token(token)
+            .map_err(|()| Status::internal("Issuer failed to recover the token"))
+    }
+}
+
+/// Require clients to present a certificate signed by the authority.
+pub fn mutual_tls(identity: Identity, client_ca: Certificate) -> ServerTlsConfig {
+    ServerTlsConfig::new()
+        .identity(identity)
+        .client_ca_root(client_ca)
+}
+
+fn describe(grant: &Grant) -> IntrospectResponse {
+    IntrospectResponse {
+        active: true,
+        client_id: grant.client_id.clone(),
+        owner_id: grant.owner_id.clone(),
+        scope: grant.scope.to_string(),
+        expires_at: grant.until.timestamp(),
+    }
+}
+
+#[tonic::async_trait]
+impl TokenValidation for TokenService {
+    async fn introspect(
+        &self, request: Request<IntrospectRequest>,
+    ) -> Result<Response<IntrospectResponse>, Status> {
+        let response = match self.recover(&request.get_ref().token)? {
+            Some(grant) if grant.until >= Utc::now() => describe(&grant),
+            _ => IntrospectResponse::default(),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn validate(
+        &self, request: Request<ValidateRequest>,
+    ) -> Result<Response<ValidateResponse>, Status> {
+        let request = request.into_inner();
+        let scopes = request
+            .scopes
+            .iter()
+            .map(|scope| scope.parse::<Scope>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| Status::invalid_argument("Malformed scope"))?;
+
+        let grant = match self.recover(&request.token)? {
+            Some(grant) => grant,
+            None => return Ok(Response::new(invalid(Validity::InvalidToken))),
+        };
+
+        if grant.until < Utc::now() {
+            return Ok(Response::new(invalid(Validity::Expired)));
+        }
+
+        if !scopes.iter().any(|scope| scope.allow_access(&grant.scope)) {
+            return Ok(Response::new(invalid(Validity::InsufficientScope)));
+        }
+
+        Ok(Response::new(ValidateResponse {
+            validity: Validity::Valid.into(),
+            grant: Some(describe(&grant)),
+        }))
+    }
+}
+
+fn invalid(validity: Validity) -> ValidateResponse {
+    ValidateResponse {
+        validity: validity.into(),
+        grant: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::issuer::TokenMap;
+
+    fn service() -> (TokenService, String) {
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        let token = issuer
+            .issue(Grant {
+                owner_id: "Owner".into(),
+                client_id: "Client".into(),
+                scope: "api.read".parse().unwrap(),
+                redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+                until: Utc::now() + Duration::minutes(10),
+                extensions: Extensions::new(),
+            })
+            .unwrap()
+            .token;
+        (TokenService::new(Arc::new(Mutex::new(issuer))), token)
+    }
+
+    fn validate(token: &str, scope: &str) -> Request<ValidateRequest> {
+        Request::new(ValidateRequest {
+            token: token.to_owned(),
+            scopes: vec![scope.to_owned()],
+        })
+    }
+
+    #[tokio::test]
+    async fn validates_tokens() {
+        let (service, token) = service();
+
+        let response = service.validate(validate(&token, "api.read")).await.unwrap();
+        let response = response.into_inner();
+        assert_eq!(response.validity(), Validity::Valid);
+        assert_eq!(response.grant.unwrap().owner_id, "Owner");
+
+        let response = service.validate(validate(&token, "api.write")).await.unwrap();
+        assert_eq!(response.into_inner().validity(), Validity::InsufficientScope);
+
+        let response = service.validate(validate("unknown", "api.read")).await.unwrap();
+        assert_eq!(response.into_inner().validity(), Validity::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn introspects_tokens() {
+        let (service, token) = service();
+
+        let request = Request::new(IntrospectRequest { token });
+        let response = service.introspect(request).await.unwrap().into_inner();
+        assert!(response.active);
+        assert_eq!(response.scope, "api.read");
+
+        let request = Request::new(IntrospectRequest {
+            token: "unknown".into(),
+        });
+        let response = service.introspect(request).await.unwrap().into_inner();
+        assert_eq!(response, IntrospectResponse::default());
+    }
+}
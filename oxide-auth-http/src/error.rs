@@ -0,0 +1,76 @@
+use crate::OAuthRequest;
+use http::header::{InvalidHeaderName, InvalidHeaderValue};
+use oxide_auth::frontends::{dev::OAuthError, simple::endpoint::Error};
+
+#[derive(Debug)]
+/// The error type for Oxide Auth operations
+pub enum WebError {
+    /// Errors occuring in Endpoint operations
+    Endpoint(OAuthError),
+
+    /// Errors occuring when setting a header
+    Header(InvalidHeaderValue),
+
+    /// Errors occuring when naming a header
+    HeaderName(InvalidHeaderName),
+
+    /// Request query was absent or could not be parsed
+    Query,
+
+    /// Request body was absent or not a form
+    Body,
+
+    /// The Authorization header was invalid
+    Authorization,
+}
+
+impl std::fmt::Display for WebError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WebError::Endpoint(ref e) => write!(f, "Endpoint, {}", e),
+            WebError::Header(ref e) => write!(f, "Couldn't set header, {}", e),
+            WebError::HeaderName(ref e) => write!(f, "Couldn't name header, {}", e),
+            WebError::Query => write!(f, "No query present"),
+            WebError::Body => write!(f, "No form body present"),
+            WebError::Authorization => write!(f, "Request has invalid Authorization headers"),
+        }
+    }
+}
+
+impl std::error::Error for WebError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            WebError::Endpoint(ref e) => e.source(),
+            WebError::Header(ref e) => e.source(),
+            WebError::HeaderName(ref e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error<OAuthRequest>> for WebError {
+    fn from(e: Error<OAuthRequest>) -> Self {
+        match e {
+            Error::Web(e) => e,
+            Error::OAuth(e) => e.into(),
+        }
+    }
+}
+
+impl From<OAuthError> for WebError {
+    fn from(e: OAuthError) -> Self {
+        WebError::Endpoint(e)
+    }
+}
+
+impl From<InvalidHeaderValue> for WebError {
+    fn from(e: InvalidHeaderValue) -> Self {
+        Self::Header(e)
+    }
+}
+
+impl From<InvalidHeaderName> for WebError {
+    fn from(e: InvalidHeaderName) -> Self {
+        Self::HeaderName(e)
+    }
+}
@@ -0,0 +1,31 @@
+//! Adaptations and integration for the types of the `http` crate.
+//!
+//! This does not depend on any particular server. Buffer the body of an `http::Request`, convert
+//! it into an [`OAuthRequest`] and turn the [`OAuthResponse`] of a flow back into an
+//! `http::Response` with any body type constructible from `Bytes`.
+//!
+//! ```
+//! use bytes::Bytes;
+//! use oxide_auth::endpoint::WebRequest;
+//! use oxide_auth_http::{OAuthRequest, OAuthResponse};
+//!
+//! let request = http::Request::post("/token")
+//!     .header("content-type", "application/x-www-form-urlencoded")
+//!     .body(Bytes::from_static(b"grant_type=authorization_code&code=abc"))
+//!     .unwrap();
+//! let mut request = OAuthRequest::from_request(&request).unwrap();
+//! assert!(request.urlbody().is_ok());
+//!
+//! let response: http::Response<Bytes> = OAuthResponse::default().into();
+//! assert_eq!(response.status(), http::StatusCode::OK);
+//! ```
+#![warn(missing_docs)]
+
+mod error;
+pub use error::WebError;
+
+mod request;
+pub use request::OAuthRequest;
+
+mod response;
+pub use response::OAuthResponse;
@@ -0,0 +1,112 @@
+use crate::WebError;
+use bytes::Bytes;
+use http::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Response, StatusCode,
+};
+use oxide_auth::frontends::dev::{WebResponse, Url};
+
+#[derive(Default, Clone, Debug)]
+/// Type implementing `WebResponse`, convertible into an `http::Response`
+pub struct OAuthResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl OAuthResponse {
+    /// Set the `ContentType` header on a response
+    pub fn content_type(mut self, content_type: &str) -> Result<Self, WebError> {
+        self.headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_str(content_type)?);
+        Ok(self)
+    }
+
+    /// Set the body for the response
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Bytes::copy_from_slice(body.as_bytes());
+        self
+    }
+
+    /// The status of the response
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+impl WebResponse for OAuthResponse {
+    type Error = WebError;
+
+    fn ok(&mut self) -> Result<(), Self::Error> {
+        self.status = StatusCode::OK;
+        Ok(())
+    }
+
+    fn redirect(&mut self, url: Url) -> Result<(), Self::Error> {
+        self.status = StatusCode::FOUND;
+        self.headers
+            .insert(header::LOCATION, HeaderValue::from_str(url.as_ref())?);
+        Ok(())
+    }
+
+    fn client_error(&mut self) -> Result<(), Self::Error> {
+        self.status = StatusCode::BAD_REQUEST;
+        Ok(())
+    }
+
+    fn unauthorized(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = StatusCode::UNAUTHORIZED;
+        self.headers
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_str(kind)?);
+        Ok(())
+    }
+
+    fn forbidden(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = StatusCode::FORBIDDEN;
+        self.headers
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_str(kind)?);
+        Ok(())
+    }
+
+    fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        self.body = Bytes::copy_from_slice(text.as_bytes());
+        self.headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        Ok(())
+    }
+
+    fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
+        self.body = Bytes::copy_from_slice(json.as_bytes());
+        self.headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        Ok(())
+    }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())?;
+        self.headers.insert(name, HeaderValue::from_str(value)?);
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.status = StatusCode::TOO_MANY_REQUESTS;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: From<Bytes>> From<OAuthResponse> for Response<B> {
+    fn from(response: OAuthResponse) -> Self {
+        let mut converted = Response::new(B::from(response.body));
+        *converted.status_mut() = response.status;
+        *converted.headers_mut() = response.headers;
+        converted
+    }
+}
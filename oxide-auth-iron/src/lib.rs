@@ -168,6 +168,13 @@ impl WebResponse for OAuthResponse {
         Ok(())
     }
 
+    fn forbidden(&mut self, header_value: &str) -> Result<(), Self::Error> {
+        self.set_status(Status::Forbidden);
+        let value_owned = header_value.as_bytes().to_vec();
+        self.set_raw_header("WWW-Authenticate".into(), vec![value_owned]);
+        Ok(())
+    }
+
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.set_header(headers::ContentType::plaintext());
         self.set_body(text);
@@ -179,6 +186,20 @@ impl WebResponse for OAuthResponse {
         self.set_body(data);
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        let value_owned = value.as_bytes().to_vec();
+        self.set_raw_header(name.to_owned().into(), vec![value_owned]);
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.set_status(Status::TooManyRequests);
+        if let Some(seconds) = retry_after {
+            self.set_raw_header("Retry-After".into(), vec![seconds.to_string().into_bytes()]);
+        }
+        Ok(())
+    }
 }
 
 impl<'a, 'b, 'c: 'b> From<&'a mut Request<'b, 'c>> for OAuthRequest<'a, 'b, 'c> {
@@ -0,0 +1,119 @@
+//! The authorization and token endpoints as an AWS Lambda function behind API Gateway.
+//!
+//! Functions keep no state between invocations, so clients, grants and tokens are stored in the
+//! Postgres database at `DATABASE_URL` through a `DieselStore`. Deploy the same binary for the
+//! routes `/authorize`, `/token` and `/refresh`, or as separate functions per route.
+//!
+//! The consent page authorizes a fixed owner. A real deployment identifies the resource owner,
+//! for example with a session cookie or an API Gateway authorizer, before granting anything.
+use std::env;
+
+use diesel::PgConnection;
+use lambda_http::{http::Method, service_fn, Body, Error, Request, Response};
+use oxide_auth::endpoint::{OwnerConsent, QueryParameter, Solicitation};
+use oxide_auth::frontends::simple::endpoint::{FnSolicitor, Generic, Vacant};
+use oxide_auth::primitives::scope::Scope;
+use oxide_auth_db::db_service::{diesel_store::DieselStore, PoolConfig};
+use oxide_auth_lambda::{into_response, oauth_request, OAuthRequest, OAuthResponse, WebError};
+
+type Store = DieselStore<PgConnection>;
+type LambdaEndpoint<S> = Generic<Store, Store, Store, S, Vec<Scope>, fn() -> OAuthResponse>;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Error> {
+    let url = env::var("DATABASE_URL").expect("DATABASE_URL should be set");
+    // Concurrent invocations run in separate instances, each needs only a single connection.
+    let store = Store::new(&url, PoolConfig::with_max_size(1))?;
+    store.migrate()?;
+
+    lambda_http::run(service_fn(move |event| {
+        let store = store.clone();
+        async move { handle(store, event) }
+    }))
+    .await
+}
+
+fn handle(store: Store, event: Request) -> Result<Response<Body>, Error> {
+    let request = oauth_request(&event)?;
+    let scopes: Vec<Scope> = vec!["default-scope".parse().unwrap()];
+
+    let response = match (event.method(), event.uri().path()) {
+        (&Method::GET, "/authorize") => endpoint(&store, scopes, FnSolicitor(consent_page))
+            .authorization_flow()
+            .execute(request),
+        (&Method::POST, "/authorize") => endpoint(&store, scopes, FnSolicitor(consent_decision))
+            .authorization_flow()
+            .execute(request),
+        (&Method::POST, "/token") => endpoint(&store, scopes, Vacant)
+            .access_token_flow()
+            .execute(request),
+        (&Method::POST, "/refresh") => endpoint(&store, scopes, Vacant).refresh_flow().execute(request),
+        _ => {
+            let mut response = Response::new(Body::Empty);
+            *response.status_mut() = lambda_http::http::StatusCode::NOT_FOUND;
+            return Ok(response);
+        }
+    };
+
+    Ok(into_response(response.map_err(WebError::from)?))
+}
+
+fn endpoint<S>(store: &Store, scopes: Vec<Scope>, solicitor: S) -> LambdaEndpoint<S> {
+    Generic {
+        registrar: store.clone(),
+        authorizer: store.clone(),
+        issuer: store.clone(),
+        solicitor,
+        scopes,
+        response: OAuthResponse::default,
+    }
+}
+
+/// Show the pending grant and ask the owner to confirm it.
+fn consent_page(_: &mut OAuthRequest, solicitation: Solicitation) -> OwnerConsent<OAuthResponse> {
+    let grant = solicitation.pre_grant();
+    let page = format!(
+        "<html>'{}' (at {}) is requesting permission for '{}'\
+         <form method=\"post\">\
+         <input type=\"submit\" value=\"Accept\" formaction=\"/authorize?{}&allow=true\">\
+         <input type=\"submit\" value=\"Deny\" formaction=\"/authorize?{}\">\
+         </form></html>",
+        grant.client_id,
+        grant.redirect_uri,
+        grant.scope,
+        query_of(&solicitation),
+        query_of(&solicitation),
+    );
+
+    match OAuthResponse::default().content_type("text/html") {
+        Ok(response) => OwnerConsent::InProgress(response.body(&page)),
+        Err(err) => OwnerConsent::Error(err),
+    }
+}
+
+/// Complete the flow with the choice submitted from the consent page.
+fn consent_decision(request: &mut OAuthRequest, _: Solicitation) -> OwnerConsent<OAuthResponse> {
+    let allowed = request
+        .query()
+        .and_then(|query| query.unique_value("allow"))
+        .is_some();
+    if allowed {
+        OwnerConsent::Authorized("dummy user".to_owned())
+    } else {
+        OwnerConsent::Denied
+    }
+}
+
+/// The query of the original authorization request, to submit it again with the decision.
+fn query_of(solicitation: &Solicitation) -> String {
+    let grant = solicitation.pre_grant();
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    query
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &grant.client_id)
+        .append_pair("redirect_uri", grant.redirect_uri.as_str());
+    if let Some(state) = solicitation.state() {
+        query.append_pair("state", state);
+    }
+    query.finish()
+}
@@ -0,0 +1,100 @@
+//! Adaptations and integration for AWS Lambda functions invoked through API Gateway.
+//!
+//! The events of `lambda_http` are `http` requests with a buffered body, so this builds on the
+//! types of `oxide-auth-http`. Convert an event with [`oauth_request`] and turn the response of a
+//! flow into the reply of the function with [`into_response`].
+//!
+//! ```no_run
+//! use lambda_http::{Body, Error, Request, Response};
+//! use oxide_auth::frontends::simple::endpoint::resource_flow;
+//! use oxide_auth::primitives::{issuer::Issuer, scope::Scope};
+//! use oxide_auth_lambda::{into_response, oauth_request, WebError};
+//!
+//! fn profile(issuer: &mut dyn Issuer, event: Request) -> Result<Response<Body>, Error> {
+//!     let scopes: Vec<Scope> = vec!["profile".parse().unwrap()];
+//!     match resource_flow(issuer, &scopes).execute(oauth_request(&event)?) {
+//!         Ok(grant) => Ok(Response::new(grant.owner_id.into())),
+//!         Err(Ok(response)) => Ok(into_response(response)),
+//!         Err(Err(err)) => Err(WebError::from(err).into()),
+//!     }
+//! }
+//! ```
+//!
+//! The example `lambda-endpoints` deploys the authorization and token endpoints as functions.
+#![warn(missing_docs)]
+
+use lambda_http::{Body, Request, RequestExt, Response};
+use oxide_auth::frontends::dev::NormalizedParameter;
+
+pub use oxide_auth_http::{OAuthRequest, OAuthResponse, WebError};
+
+/// Read the authorization, query and form body of a Lambda event.
+///
+/// Depending on the integration, API Gateway delivers the query string apart from the path of the
+/// request. Those parameters are used whenever the uri of the event has no query of its own. Base64
+/// encoded bodies have already been decoded by the runtime.
+pub fn oauth_request(event: &Request) -> Result<OAuthRequest, WebError> {
+    let request = OAuthRequest::from_request(event)?;
+    if request.query().is_some() {
+        return Ok(request);
+    }
+
+    let parameters = event.query_string_parameters();
+    if parameters.is_empty() {
+        return Ok(request);
+    }
+
+    let query: NormalizedParameter = parameters
+        .iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect();
+    Ok(request.with_query(query))
+}
+
+/// Turn the response of a flow into the reply of a Lambda function.
+///
+/// Bodies that are valid UTF-8 are returned as text, others are binary and get base64 encoded for
+/// API Gateway by the runtime.
+pub fn into_response(response: OAuthResponse) -> Response<Body> {
+    let response: Response<Vec<u8>> = response.into();
+    response.map(|body| {
+        if body.is_empty() {
+            return Body::Empty;
+        }
+
+        match String::from_utf8(body) {
+            Ok(text) => Body::Text(text),
+            Err(err) => Body::Binary(err.into_bytes()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::endpoint::{QueryParameter, WebResponse};
+    use std::collections::HashMap;
+
+    #[test]
+    fn reads_separate_query() {
+        let event = Request::new(Body::Empty).with_query_string_parameters(HashMap::from([(
+            "client_id".to_owned(),
+            "LocalClient".to_owned(),
+        )]));
+        let request = oauth_request(&event).unwrap();
+        let query = request.query().unwrap();
+        assert_eq!(query.unique_value("client_id").as_deref(), Some("LocalClient"));
+    }
+
+    #[test]
+    fn text_response() {
+        let mut response = OAuthResponse::default();
+        response.body_json("{\"access_token\":\"abc\"}").unwrap();
+        let response = into_response(response);
+        assert!(matches!(response.body(), Body::Text(text) if text.contains("abc")));
+        assert!(matches!(
+            into_response(OAuthResponse::default()).body(),
+            Body::Empty
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use poem::{get, handler, post, EndpointExt, Route, Server};
 use poem::listener::TcpListener;
 use poem::middleware::AddDataEndpoint;
@@ -18,9 +18,9 @@ use oxide_auth_poem::response::OAuthResponse;
 mod support;
 
 struct EndpointState {
-    registrar: Mutex<ClientMap>,
-    authorizer: Mutex<AuthMap<RandomGenerator>>,
-    issuer: Mutex<TokenMap<RandomGenerator>>,
+    registrar: Arc<RwLock<ClientMap>>,
+    authorizer: Arc<Mutex<AuthMap<RandomGenerator>>>,
+    issuer: Arc<RwLock<TokenMap<RandomGenerator>>>,
 }
 
 impl EndpointState {
@@ -33,7 +33,7 @@ here</a> to begin the authorization process.
 
     fn preconfigured(client_port: u16) -> Self {
         EndpointState {
-            registrar: Mutex::new(
+            registrar: Arc::new(RwLock::new(
                 vec![Client::confidential(
                     "LocalClient",
                     format!("http://localhost:{client_port}/endpoint")
@@ -45,18 +45,21 @@ here</a> to begin the authorization process.
                 )]
                 .into_iter()
                 .collect(),
-            ),
-            authorizer: Mutex::new(AuthMap::new(RandomGenerator::new(16))),
-            issuer: Mutex::new(TokenMap::new(RandomGenerator::new(16))),
+            )),
+            authorizer: Arc::new(Mutex::new(AuthMap::new(RandomGenerator::new(16)))),
+            issuer: Arc::new(RwLock::new(TokenMap::new(RandomGenerator::new(16)))),
         }
     }
 
     /// In larger app, you'd likey wrap it in your own Endpoint instead of `Generic`.
-    pub fn endpoint(&self) -> Generic<impl Registrar + '_, impl Authorizer + '_, impl Issuer + '_> {
+    ///
+    /// The primitives are locked for each operation only, so requests do not wait for each other
+    /// for the duration of their flows.
+    pub fn endpoint(&self) -> Generic<impl Registrar, impl Authorizer, impl Issuer> {
         Generic {
-            registrar: self.registrar.lock().unwrap(),
-            authorizer: self.authorizer.lock().unwrap(),
-            issuer: self.issuer.lock().unwrap(),
+            registrar: self.registrar.clone(),
+            authorizer: self.authorizer.clone(),
+            issuer: self.issuer.clone(),
             // Solicitor configured later.
             solicitor: Vacant,
             // Scope configured later.
@@ -2,18 +2,24 @@ use super::error::OxidePoemError;
 use oxide_auth::{endpoint::WebResponse, frontends::dev::Url};
 use poem::{
     http::{
-        header::{InvalidHeaderValue, CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE},
+        header::{
+            HeaderName, IntoHeaderName, InvalidHeaderValue, CONTENT_TYPE, LOCATION, SET_COOKIE, WWW_AUTHENTICATE,
+        },
         Extensions, HeaderMap, HeaderValue, StatusCode, Version,
     },
     Body, IntoResponse, Response, ResponseParts,
 };
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Debug)]
 /// Type implementing `WebResponse` and `IntoResponse` for use in route handlers
+///
+/// Solicitors can add arbitrary headers and cookies, or stream the body, for example to serve a
+/// rendered consent page while starting a session.
 pub struct OAuthResponse {
     status: StatusCode,
     headers: HeaderMap,
     body: Option<String>,
+    stream: Option<Body>,
 }
 
 impl OAuthResponse {
@@ -35,10 +41,43 @@ impl OAuthResponse {
     #[must_use]
     pub fn body(mut self, body: &str) -> Self {
         self.body = Some(body.to_owned());
+        self.stream = None;
+        self
+    }
+
+    /// Set a header on the response, replacing previous values of the same name
+    /// # Errors
+    /// In case the `value` cannot be parsed, this will return an [`OxidePoemError::Header(_)`]
+    pub fn header<K: IntoHeaderName>(mut self, name: K, value: &str) -> Result<Self, OxidePoemError> {
+        self.headers.insert(name, header_value(value)?);
+        Ok(self)
+    }
+
+    /// Add a `Set-Cookie` header to the response, keeping previously added cookies
+    /// # Errors
+    /// In case the `cookie` cannot be parsed, this will return an [`OxidePoemError::Header(_)`]
+    pub fn cookie(mut self, cookie: &str) -> Result<Self, OxidePoemError> {
+        self.headers.append(SET_COOKIE, header_value(cookie)?);
+        Ok(self)
+    }
+
+    /// Stream the body of the response, such as one created with `Body::from_bytes_stream`
+    ///
+    /// Replaces a body set previously. Bodies set by the flows in turn replace the stream.
+    #[must_use]
+    pub fn streaming_body(mut self, body: Body) -> Self {
+        self.body = None;
+        self.stream = Some(body);
         self
     }
 }
 
+fn header_value(value: &str) -> Result<HeaderValue, OxidePoemError> {
+    value
+        .parse()
+        .map_err(|err: InvalidHeaderValue| OxidePoemError::Header(err.to_string()))
+}
+
 impl WebResponse for OAuthResponse {
     type Error = OxidePoemError;
 
@@ -73,8 +112,15 @@ impl WebResponse for OAuthResponse {
         Ok(())
     }
 
+    fn forbidden(&mut self, header_value: &str) -> Result<(), Self::Error> {
+        self.unauthorized(header_value)?;
+        self.status = StatusCode::FORBIDDEN;
+        Ok(())
+    }
+
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.body = Some(text.to_owned());
+        self.stream = None;
         self.headers
             .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
         Ok(())
@@ -82,10 +128,26 @@ impl WebResponse for OAuthResponse {
 
     fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
         self.body = Some(json.to_owned());
+        self.stream = None;
         self.headers
             .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| OxidePoemError::Header(err.to_string()))?;
+        self.headers.insert(name, header_value(value)?);
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.status = StatusCode::TOO_MANY_REQUESTS;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
 }
 
 impl IntoResponse for OAuthResponse {
@@ -97,9 +159,10 @@ impl IntoResponse for OAuthResponse {
                 headers: self.headers,
                 extensions: Extensions::default(),
             },
-            match self.body {
-                Some(content) => Body::from(content),
-                None => Body::empty(),
+            match (self.stream, self.body) {
+                (Some(stream), _) => stream,
+                (None, Some(content)) => Body::from(content),
+                (None, None) => Body::empty(),
             },
         )
     }
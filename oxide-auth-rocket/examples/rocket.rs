@@ -1,7 +1,3 @@
-#![feature(proc_macro_hygiene, decl_macro)]
-
-extern crate oxide_auth;
-extern crate oxide_auth_rocket;
 #[macro_use]
 extern crate rocket;
 
@@ -11,15 +7,14 @@ mod support;
 use std::io;
 use std::sync::Mutex;
 
-use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+use oxide_auth::endpoint::{OwnerConsent, Solicitation, WebResponse};
 use oxide_auth::frontends::simple::endpoint::{FnSolicitor, Generic, Vacant};
 use oxide_auth::primitives::prelude::*;
 use oxide_auth::primitives::registrar::RegisteredUrl;
 use oxide_auth_rocket::{OAuthResponse, OAuthRequest, OAuthFailure};
 
-use rocket::{Data, State, Response, http};
+use rocket::{State, Response, http};
 use rocket::http::ContentType;
-use rocket::response::Responder;
 
 struct MyState {
     registrar: Mutex<ClientMap>,
@@ -28,9 +23,7 @@ struct MyState {
 }
 
 #[get("/authorize")]
-fn authorize<'r>(
-    oauth: OAuthRequest<'r>, state: State<MyState>,
-) -> Result<OAuthResponse<'r>, OAuthFailure> {
+fn authorize(oauth: OAuthRequest, state: &State<MyState>) -> Result<OAuthResponse, OAuthFailure> {
     state
         .endpoint()
         .with_solicitor(FnSolicitor(consent_form))
@@ -40,9 +33,9 @@ fn authorize<'r>(
 }
 
 #[post("/authorize?<allow>")]
-fn authorize_consent<'r>(
-    oauth: OAuthRequest<'r>, allow: Option<bool>, state: State<MyState>,
-) -> Result<OAuthResponse<'r>, OAuthFailure> {
+fn authorize_consent(
+    oauth: OAuthRequest, allow: Option<bool>, state: &State<MyState>,
+) -> Result<OAuthResponse, OAuthFailure> {
     let allowed = allow.unwrap_or(false);
     state
         .endpoint()
@@ -54,11 +47,9 @@ fn authorize_consent<'r>(
         .map_err(|err| err.pack::<OAuthFailure>())
 }
 
-#[post("/token", data = "<body>")]
-fn token<'r>(
-    mut oauth: OAuthRequest<'r>, body: Data, state: State<MyState>,
-) -> Result<OAuthResponse<'r>, OAuthFailure> {
-    oauth.add_body(body);
+// The body of the token requests is read by using the request as a data guard.
+#[post("/token", data = "<oauth>")]
+fn token(oauth: OAuthRequest, state: &State<MyState>) -> Result<OAuthResponse, OAuthFailure> {
     state
         .endpoint()
         .access_token_flow()
@@ -66,11 +57,8 @@ fn token<'r>(
         .map_err(|err| err.pack::<OAuthFailure>())
 }
 
-#[post("/refresh", data = "<body>")]
-fn refresh<'r>(
-    mut oauth: OAuthRequest<'r>, body: Data, state: State<MyState>,
-) -> Result<OAuthResponse<'r>, OAuthFailure> {
-    oauth.add_body(body);
+#[post("/refresh", data = "<oauth>")]
+fn refresh(oauth: OAuthRequest, state: &State<MyState>) -> Result<OAuthResponse, OAuthFailure> {
     state
         .endpoint()
         .refresh_flow()
@@ -79,7 +67,9 @@ fn refresh<'r>(
 }
 
 #[get("/")]
-fn protected_resource<'r>(oauth: OAuthRequest<'r>, state: State<MyState>) -> impl Responder<'r> {
+fn protected_resource(
+    oauth: OAuthRequest, state: &State<MyState>,
+) -> Result<OAuthResponse, OAuthFailure> {
     const DENY_TEXT: &str = "<html>
 This page should be accessed via an oauth token from the client in the example. Click
 <a href=\"/authorize?response_type=code&client_id=LocalClient\">
@@ -93,21 +83,24 @@ here</a> to begin the authorization process.
         .resource_flow()
         .execute(oauth);
     match protect {
-        Ok(_grant) => Ok("Hello, world"),
-        Err(Ok(response)) => {
-            let error: OAuthResponse = Response::build_from(response.into())
-                .header(ContentType::HTML)
-                .sized_body(io::Cursor::new(DENY_TEXT))
-                .finalize()
-                .into();
-            Err(Ok(error))
+        Ok(_grant) => {
+            let mut response = OAuthResponse::new();
+            response.body_text("Hello, world")?;
+            Ok(response)
         }
-        Err(Err(err)) => Err(Err(err.pack::<OAuthFailure>())),
+        Err(Ok(response)) => Ok(Response::build_from(response.into())
+            .header(ContentType::HTML)
+            .sized_body(DENY_TEXT.len(), io::Cursor::new(DENY_TEXT))
+            .finalize()
+            .into()),
+        Err(Err(err)) => Err(err.pack::<OAuthFailure>()),
     }
 }
 
-fn main() {
-    rocket::ignite()
+// The `OAuthFairing` mounts the same flows for an endpoint without writing the routes by hand.
+#[launch]
+fn rocket() -> _ {
+    rocket::build()
         .mount(
             "/",
             routes![authorize, authorize_consent, token, protected_resource, refresh,],
@@ -115,7 +108,6 @@ fn main() {
         // We only attach the test client here because there can only be one rocket.
         .attach(support::ClientFairing)
         .manage(MyState::preconfigured())
-        .launch();
 }
 
 impl MyState {
@@ -159,23 +151,19 @@ impl MyState {
     }
 }
 
-fn consent_form<'r>(
-    _: &mut OAuthRequest<'r>, solicitation: Solicitation,
-) -> OwnerConsent<OAuthResponse<'r>> {
+fn consent_form(_: &mut OAuthRequest, solicitation: Solicitation) -> OwnerConsent<OAuthResponse> {
+    let page = support::consent_page_html("/authorize", solicitation);
     OwnerConsent::InProgress(
         Response::build()
             .status(http::Status::Ok)
             .header(http::ContentType::HTML)
-            .sized_body(io::Cursor::new(support::consent_page_html(
-                "/authorize",
-                solicitation,
-            )))
+            .sized_body(page.len(), io::Cursor::new(page))
             .finalize()
             .into(),
     )
 }
 
-fn consent_decision<'r>(allowed: bool, _: Solicitation) -> OwnerConsent<OAuthResponse<'r>> {
+fn consent_decision(allowed: bool, _: Solicitation) -> OwnerConsent<OAuthResponse> {
     if allowed {
         OwnerConsent::Authorized("dummy user".into())
     } else {
@@ -1,5 +1,6 @@
-use super::WebError;
+use super::{OAuthRequest, WebError};
 use oxide_auth::endpoint::OAuthError;
+use oxide_auth::frontends::simple::endpoint::Error;
 
 use rocket::Request;
 use rocket::http::Status;
@@ -42,10 +43,11 @@ enum Kind {
     OAuth(OAuthError),
 }
 
-impl<'r> Responder<'r> for OAuthFailure {
-    fn respond_to(self, _: &Request) -> Result<'r> {
+impl<'r> Responder<'r, 'static> for OAuthFailure {
+    fn respond_to(self, _: &'r Request<'_>) -> Result<'static> {
         match self.inner {
-            Web(_) | OAuth(DenySilently) | OAuth(BadRequest) => Err(Status::BadRequest),
+            Web(err) => Err(err.status()),
+            OAuth(DenySilently) | OAuth(BadRequest) => Err(Status::BadRequest),
             OAuth(PrimitiveError) => Err(Status::InternalServerError),
         }
     }
@@ -62,3 +64,9 @@ impl From<WebError> for OAuthFailure {
         OAuthFailure { inner: Web(err) }
     }
 }
+
+impl From<Error<OAuthRequest>> for OAuthFailure {
+    fn from(err: Error<OAuthRequest>) -> Self {
+        err.pack()
+    }
+}
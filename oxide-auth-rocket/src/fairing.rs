@@ -0,0 +1,250 @@
+use std::sync::{Arc, Mutex};
+
+use rocket::{Build, Data, Request, Rocket, Route};
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::route::{self, Handler};
+
+use oxide_auth::endpoint::{
+    AccessTokenFlow, AuthorizationFlow, ClientCredentialsFlow, Endpoint, QueryParameter, RefreshFlow,
+};
+
+use super::{OAuthFailure, OAuthRequest, OAuthResponse};
+
+/// Fairing mounting the standard routes of an endpoint.
+///
+/// Mounts `GET` and `POST` on `authorize` for the authorization flow, and `POST` on `token` for the
+/// token flows. The token route dispatches on the `grant_type` of the request to the access token,
+/// refresh or client credentials flow. Consent is asked for by the `OwnerSolicitor` of the endpoint,
+/// which sees both the initial request and the submitted consent form.
+///
+/// ```no_run
+/// use oxide_auth::frontends::simple::endpoint::{FnSolicitor, Generic, Vacant};
+/// use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+/// use oxide_auth::primitives::prelude::*;
+/// use oxide_auth_rocket::{OAuthFairing, OAuthRequest, OAuthResponse};
+///
+/// fn consent(_: &mut OAuthRequest, _: Solicitation) -> OwnerConsent<OAuthResponse> {
+///     OwnerConsent::Denied
+/// }
+///
+/// let endpoint = Generic {
+///     registrar: ClientMap::new(),
+///     authorizer: AuthMap::new(RandomGenerator::new(16)),
+///     issuer: TokenMap::new(RandomGenerator::new(16)),
+///     solicitor: FnSolicitor(consent),
+///     scopes: Vacant,
+///     response: Vacant,
+/// };
+///
+/// let rocket = rocket::build().attach(OAuthFairing::new(endpoint).mount_at("/oauth"));
+/// ```
+///
+/// The flows of the endpoint run synchronously while holding a lock, so its primitives should
+/// answer quickly. Mount routes by hand for primitives which block on the network.
+pub struct OAuthFairing<E> {
+    base: String,
+    endpoint: Arc<Mutex<E>>,
+}
+
+#[derive(Clone, Copy)]
+enum Flow {
+    Authorize,
+    Token,
+}
+
+struct FlowHandler<E> {
+    flow: Flow,
+    endpoint: Arc<Mutex<E>>,
+}
+
+impl<E> OAuthFairing<E>
+where
+    E: Endpoint<OAuthRequest> + Send + 'static,
+    OAuthFailure: From<E::Error>,
+{
+    /// Serve the flows of the endpoint, mounted at the root by default.
+    pub fn new(endpoint: E) -> Self {
+        OAuthFairing {
+            base: "/".into(),
+            endpoint: Arc::new(Mutex::new(endpoint)),
+        }
+    }
+
+    /// Mount the routes below another base path.
+    pub fn mount_at(mut self, base: &str) -> Self {
+        self.base = base.into();
+        self
+    }
+
+    /// The routes mounted by the fairing.
+    pub fn routes(&self) -> Vec<Route> {
+        let handler = |flow| FlowHandler {
+            flow,
+            endpoint: self.endpoint.clone(),
+        };
+
+        vec![
+            Route::new(Method::Get, "/authorize", handler(Flow::Authorize)),
+            Route::new(Method::Post, "/authorize", handler(Flow::Authorize)),
+            Route::new(Method::Post, "/token", handler(Flow::Token)),
+        ]
+    }
+}
+
+#[rocket::async_trait]
+impl<E> Fairing for OAuthFairing<E>
+where
+    E: Endpoint<OAuthRequest> + Send + 'static,
+    OAuthFailure: From<E::Error>,
+{
+    fn info(&self) -> Info {
+        Info {
+            name: "OAuth endpoint routes",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.mount(self.base.as_str(), self.routes()))
+    }
+}
+
+impl<E> Clone for FlowHandler<E> {
+    fn clone(&self) -> Self {
+        FlowHandler {
+            flow: self.flow,
+            endpoint: self.endpoint.clone(),
+        }
+    }
+}
+
+impl<E> FlowHandler<E>
+where
+    E: Endpoint<OAuthRequest>,
+    OAuthFailure: From<E::Error>,
+{
+    fn execute(&self, request: OAuthRequest) -> Result<OAuthResponse, OAuthFailure> {
+        let mut endpoint = match self.endpoint.lock() {
+            Ok(endpoint) => endpoint,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let endpoint = &mut *endpoint;
+
+        let grant_type = request
+            .body()
+            .and_then(|body| body.unique_value("grant_type"))
+            .map(|grant_type| grant_type.into_owned());
+
+        let response = match (self.flow, grant_type.as_deref()) {
+            (Flow::Authorize, _) => AuthorizationFlow::prepare(endpoint)?.execute(request),
+            (Flow::Token, Some("refresh_token")) => RefreshFlow::prepare(endpoint)?.execute(request),
+            (Flow::Token, Some("client_credentials")) => {
+                ClientCredentialsFlow::prepare(endpoint)?.execute(request)
+            }
+            // Also answers unsupported grant types with the appropriate error.
+            (Flow::Token, _) => AccessTokenFlow::prepare(endpoint)?.execute(request),
+        };
+
+        Ok(response?)
+    }
+}
+
+#[rocket::async_trait]
+impl<E> Handler for FlowHandler<E>
+where
+    E: Endpoint<OAuthRequest> + Send + 'static,
+    OAuthFailure: From<E::Error>,
+{
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        let oauth = match OAuthRequest::with_body(request, data).await {
+            Ok(oauth) => oauth,
+            Err(err) => return route::Outcome::error(err.status()),
+        };
+
+        route::Outcome::from(request, self.execute(oauth))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+    use oxide_auth::frontends::dev::Url;
+    use oxide_auth::frontends::simple::endpoint::{FnSolicitor, Generic, Vacant};
+    use oxide_auth::primitives::prelude::*;
+    use oxide_auth::primitives::registrar::RegisteredUrl;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::Client as LocalClient;
+
+    fn consent(_: &mut OAuthRequest, _: Solicitation) -> OwnerConsent<OAuthResponse> {
+        OwnerConsent::Authorized("owner".into())
+    }
+
+    #[rocket::async_test]
+    async fn mounts_flows() {
+        let client = Client::confidential(
+            "LocalClient",
+            RegisteredUrl::Semantic("https://client.example/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+            b"SecretSecret",
+        );
+        let endpoint = Generic {
+            registrar: vec![client].into_iter().collect::<ClientMap>(),
+            authorizer: AuthMap::new(RandomGenerator::new(16)),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            solicitor: FnSolicitor(consent),
+            scopes: Vacant,
+            response: Vacant,
+        };
+
+        let rocket = rocket::build().attach(OAuthFairing::new(endpoint).mount_at("/oauth"));
+        let client = LocalClient::untracked(rocket).await.unwrap();
+
+        let response = client
+            .get("/oauth/authorize?response_type=code&client_id=LocalClient")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Found);
+        let location = response.headers().get_one("Location").unwrap();
+        let location: Url = location.parse().unwrap();
+        let code = location
+            .query_pairs()
+            .find(|(key, _)| key == "code")
+            .map(|(_, code)| code.into_owned())
+            .unwrap();
+
+        let response = client
+            .post("/oauth/token")
+            .header(ContentType::Form)
+            .header(Header::new(
+                "Authorization",
+                "Basic TG9jYWxDbGllbnQ6U2VjcmV0U2VjcmV0",
+            ))
+            .body(
+                Url::parse("http://form")
+                    .unwrap()
+                    .query_pairs_mut()
+                    .append_pair("grant_type", "authorization_code")
+                    .append_pair("code", &code)
+                    .append_pair("redirect_uri", "https://client.example/endpoint")
+                    .finish()
+                    .query()
+                    .unwrap(),
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains("access_token"));
+        assert!(body.contains("refresh_token"));
+
+        let response = client
+            .post("/oauth/token")
+            .header(ContentType::Form)
+            .body("grant_type=password")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
@@ -1,17 +1,22 @@
 //! Adaptions and integration for rocket.
+//!
+//! `OAuthRequest` is a request guard for flows reading only the query, such as the authorization
+//! and resource flows, and a data guard reading the urlencoded or JSON body for the token flows.
+//! The `OAuthFairing` mounts the standard routes for an endpoint in one go.
 #![warn(missing_docs)]
 
 mod failure;
+mod fairing;
 
 use std::io::Cursor;
-use std::marker::PhantomData;
 
 use rocket::{Data, Request, Response};
-use rocket::http::{ContentType, Status};
-use rocket::http::hyper::header;
-use rocket::request::FromRequest;
+use rocket::data::{self, FromData, Limits as DataLimits};
+use rocket::http::{ContentType, Cookie, Header, Status};
+use rocket::request::{self, FromRequest};
 use rocket::response::{self, Responder};
 use rocket::outcome::Outcome;
+use rocket::tokio::io::AsyncRead;
 
 use oxide_auth::endpoint::{NormalizedParameter, WebRequest, WebResponse};
 use oxide_auth::frontends::dev::*;
@@ -19,20 +24,26 @@ use oxide_auth::frontends::dev::*;
 pub use oxide_auth::frontends::simple::endpoint::Generic;
 pub use oxide_auth::frontends::simple::request::NoError;
 pub use self::failure::OAuthFailure;
+pub use self::fairing::OAuthFairing;
 
-/// Request guard that also buffers OAuth data internally.
-pub struct OAuthRequest<'r> {
+/// Request and data guard buffering OAuth data internally.
+///
+/// As a request guard it reads the query and authorization header. Used as the data guard of a
+/// route, with `data = "<oauth>"`, it additionally reads the body as a urlencoded form or, with
+/// a `Content-Type` of `application/json`, as a JSON object. The body is capped by the `form` and
+/// `json` limits of the rocket configuration respectively.
+#[derive(Clone, Debug)]
+pub struct OAuthRequest {
     auth: Option<String>,
     query: Result<NormalizedParameter, WebError>,
     body: Result<Option<NormalizedParameter>, WebError>,
-    lifetime: PhantomData<&'r ()>,
 }
 
 /// Response type for Rocket OAuth requests
 ///
-/// A simple wrapper type around a simple `rocket::Response<'r>` that implements `WebResponse`.
-#[derive(Debug)]
-pub struct OAuthResponse<'r>(Response<'r>);
+/// A simple wrapper type around a simple `rocket::Response` that implements `WebResponse`.
+#[derive(Debug, Default)]
+pub struct OAuthResponse(Response<'static>);
 
 /// Request error at the http layer.
 ///
@@ -52,21 +63,28 @@ pub enum WebError {
 
     /// Form data was requested but the request was not a form.
     NotAForm,
+
+    /// The body exceeded the configured limit.
+    TooLarge,
+
+    /// The body could not be read from the connection.
+    Io,
 }
 
-impl<'r> OAuthRequest<'r> {
+impl OAuthRequest {
     /// Create the request data from request headers.
     ///
-    /// Some oauth methods need additionally the body data which you can attach later.
-    pub fn new<'a>(request: &'a Request<'r>) -> Self {
-        let query = request.uri().query().unwrap_or("");
+    /// Operations which need the body of the request fail with `WebError::BodyNeeded`, use the
+    /// request as a data guard instead.
+    pub fn new(request: &Request<'_>) -> Self {
+        let query = request.uri().query().map_or("", |query| query.as_str());
         let query = match serde_urlencoded::from_str(query) {
             Ok(query) => Ok(query),
             Err(_) => Err(WebError::Encoding),
         };
 
         let body = match request.content_type() {
-            Some(ct) if *ct == ContentType::Form => Ok(None),
+            Some(ct) if ct.is_form() || ct.is_json() => Ok(None),
             _ => Err(WebError::NotAForm),
         };
 
@@ -74,63 +92,102 @@ impl<'r> OAuthRequest<'r> {
         let optional = all_auth.next();
 
         // Duplicate auth header, just treat it as no authorization.
-        let auth = if let Some(_) = all_auth.next() {
+        let auth = if all_auth.next().is_some() {
             None
         } else {
             optional.map(str::to_owned)
         };
 
-        OAuthRequest {
-            auth,
-            query,
-            body,
-            lifetime: PhantomData,
-        }
+        OAuthRequest { auth, query, body }
     }
 
-    /// Provide the body of the request.
+    /// Create the request data including the body of the request.
     ///
-    /// Some, but not all operations, require reading their data from a urlencoded POST body. To
-    /// simplify the implementation of primitives and handlers, this type is the central request
-    /// type for both these use cases. When you forget to provide the body to a request, the oauth
-    /// system will return an error the moment the request is used.
-    pub fn add_body(&mut self, data: Data) {
-        // Nothing to do if we already have a body, or already generated an error. This includes
-        // the case where the content type does not indicate a form, as the error is silent until a
-        // body is explicitely requested.
-        if let Ok(None) = self.body {
-            match serde_urlencoded::from_reader(data.open()) {
-                Ok(query) => self.body = Ok(Some(query)),
-                Err(_) => self.body = Err(WebError::Encoding),
-            }
+    /// Bodies exceeding the limits of the rocket configuration are rejected.
+    pub async fn with_body(request: &Request<'_>, data: Data<'_>) -> Result<Self, WebError> {
+        let mut oauth = OAuthRequest::new(request);
+        // Nothing to do if the content type does not indicate a form, as the error is silent
+        // until a body is explicitely requested.
+        if oauth.body.is_err() {
+            return Ok(oauth);
         }
+
+        let is_json = request.content_type().is_some_and(|ct| ct.is_json());
+        let limit = if is_json {
+            request.limits().get("json").unwrap_or(DataLimits::JSON)
+        } else {
+            request.limits().get("form").unwrap_or(DataLimits::FORM)
+        };
+
+        let bytes = match data.open(limit).into_bytes().await {
+            Ok(bytes) if bytes.is_complete() => bytes.into_inner(),
+            Ok(_) => return Err(WebError::TooLarge),
+            Err(_) => return Err(WebError::Io),
+        };
+
+        oauth.body = if is_json {
+            NormalizedParameter::from_json(&bytes)
+                .map(Some)
+                .ok_or(WebError::Encoding)
+        } else {
+            serde_urlencoded::from_bytes(&bytes)
+                .map(Some)
+                .map_err(|_| WebError::Encoding)
+        };
+
+        Ok(oauth)
+    }
+
+    /// Fetch the body of the request, if it was read.
+    pub fn body(&self) -> Option<&NormalizedParameter> {
+        self.body.as_ref().ok().and_then(Option::as_ref)
     }
 }
 
-impl<'r> OAuthResponse<'r> {
-    /// Create a new `OAuthResponse<'r>`
+impl OAuthResponse {
+    /// Create a new `OAuthResponse`
     pub fn new() -> Self {
         Default::default()
     }
 
-    /// Create a new `OAuthResponse<'r>` from an existing `rocket::Response<'r>`
-    pub fn from_response(response: Response<'r>) -> Self {
+    /// Create a new `OAuthResponse` from an existing `rocket::Response`
+    pub fn from_response(response: Response<'static>) -> Self {
         OAuthResponse(response)
     }
+
+    /// Set a header, replacing previous values of the same name.
+    pub fn header(mut self, header: impl Into<Header<'static>>) -> Self {
+        self.0.set_header(header);
+        self
+    }
+
+    /// Add a `Set-Cookie` header, keeping previously added cookies.
+    pub fn cookie(mut self, cookie: Cookie<'_>) -> Self {
+        self.0.adjoin_raw_header("Set-Cookie", cookie.to_string());
+        self
+    }
+
+    /// Stream the body, such as a template rendered into a pipe.
+    ///
+    /// Bodies set by the flows replace the stream.
+    pub fn streaming_body(mut self, body: impl AsyncRead + Send + 'static) -> Self {
+        self.0.set_streamed_body(body);
+        self
+    }
 }
 
-impl<'r> WebRequest for OAuthRequest<'r> {
+impl WebRequest for OAuthRequest {
     type Error = WebError;
-    type Response = OAuthResponse<'r>;
+    type Response = OAuthResponse;
 
-    fn query(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+    fn query(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
         match self.query.as_ref() {
             Ok(query) => Ok(Cow::Borrowed(query as &dyn QueryParameter)),
             Err(err) => Err(*err),
         }
     }
 
-    fn urlbody(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+    fn urlbody(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
         match self.body.as_ref() {
             Ok(None) => Err(WebError::BodyNeeded),
             Ok(Some(body)) => Ok(Cow::Borrowed(body as &dyn QueryParameter)),
@@ -138,12 +195,12 @@ impl<'r> WebRequest for OAuthRequest<'r> {
         }
     }
 
-    fn authheader(&mut self) -> Result<Option<Cow<str>>, Self::Error> {
-        Ok(self.auth.as_ref().map(String::as_str).map(Cow::Borrowed))
+    fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
+        Ok(self.auth.as_deref().map(Cow::Borrowed))
     }
 }
 
-impl<'r> WebResponse for OAuthResponse<'r> {
+impl WebResponse for OAuthResponse {
     type Error = WebError;
 
     fn ok(&mut self) -> Result<(), Self::Error> {
@@ -153,7 +210,7 @@ impl<'r> WebResponse for OAuthResponse<'r> {
 
     fn redirect(&mut self, url: Url) -> Result<(), Self::Error> {
         self.0.set_status(Status::Found);
-        self.0.set_header(header::Location(url.into()));
+        self.0.set_header(Header::new("Location", String::from(url)));
         Ok(())
     }
 
@@ -168,57 +225,90 @@ impl<'r> WebResponse for OAuthResponse<'r> {
         Ok(())
     }
 
+    fn forbidden(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.0.set_status(Status::Forbidden);
+        self.0.set_raw_header("WWW-Authenticate", kind.to_owned());
+        Ok(())
+    }
+
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
-        self.0.set_sized_body(Cursor::new(text.to_owned()));
+        self.0.set_sized_body(text.len(), Cursor::new(text.to_owned()));
         self.0.set_header(ContentType::Plain);
         Ok(())
     }
 
     fn body_json(&mut self, data: &str) -> Result<(), Self::Error> {
-        self.0.set_sized_body(Cursor::new(data.to_owned()));
+        self.0.set_sized_body(data.len(), Cursor::new(data.to_owned()));
         self.0.set_header(ContentType::JSON);
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        self.0.set_raw_header(name.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.0.set_status(Status::TooManyRequests);
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
 }
 
-impl<'a, 'r> FromRequest<'a, 'r> for OAuthRequest<'r> {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OAuthRequest {
     type Error = NoError;
 
-    fn from_request(request: &'a Request<'r>) -> Outcome<Self, (Status, Self::Error), ()> {
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
         Outcome::Success(Self::new(request))
     }
 }
 
-impl<'r> Responder<'r> for OAuthResponse<'r> {
-    fn respond_to(self, _: &Request) -> response::Result<'r> {
-        Ok(self.0)
+#[rocket::async_trait]
+impl<'r> FromData<'r> for OAuthRequest {
+    type Error = WebError;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        match Self::with_body(request, data).await {
+            Ok(oauth) => Outcome::Success(oauth),
+            Err(err) => Outcome::Error((err.status(), err)),
+        }
     }
 }
 
-impl<'r> Responder<'r> for WebError {
-    fn respond_to(self, _: &Request) -> response::Result<'r> {
+impl WebError {
+    /// The status of the response answering a request failing with this error.
+    pub fn status(self) -> Status {
         match self {
-            WebError::Encoding => Err(Status::BadRequest),
-            WebError::NotAForm => Err(Status::BadRequest),
-            WebError::BodyNeeded => Err(Status::InternalServerError),
+            WebError::Encoding | WebError::NotAForm => Status::BadRequest,
+            WebError::TooLarge => Status::PayloadTooLarge,
+            WebError::BodyNeeded | WebError::Io => Status::InternalServerError,
         }
     }
 }
 
-impl<'r> Default for OAuthResponse<'r> {
-    fn default() -> Self {
-        OAuthResponse(Default::default())
+impl<'r> Responder<'r, 'static> for OAuthResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Ok(self.0)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for WebError {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Err(self.status())
     }
 }
 
-impl<'r> From<Response<'r>> for OAuthResponse<'r> {
-    fn from(r: Response<'r>) -> Self {
+impl From<Response<'static>> for OAuthResponse {
+    fn from(r: Response<'static>) -> Self {
         OAuthResponse::from_response(r)
     }
 }
 
-impl<'r> Into<Response<'r>> for OAuthResponse<'r> {
-    fn into(self) -> Response<'r> {
-        self.0
+impl From<OAuthResponse> for Response<'static> {
+    fn from(response: OAuthResponse) -> Self {
+        response.0
     }
 }
@@ -7,7 +7,7 @@
 use core::ops::Deref;
 use std::borrow::Cow;
 
-use oxide_auth::endpoint::{QueryParameter, WebRequest, WebResponse};
+use oxide_auth::endpoint::{NormalizedParameter, QueryParameter, WebRequest, WebResponse};
 
 use url::Url;
 
@@ -15,6 +15,9 @@ use url::Url;
 // allow efficient and intuitive usage, we simply re-export common structures.
 pub use oxide_auth::frontends::simple::endpoint::{FnSolicitor, Generic as GenericEndpoint, Vacant};
 
+#[cfg(feature = "test-helpers")]
+pub mod mock;
+
 /// Something went wrong with the rouille http request or response.
 #[derive(Debug)]
 pub enum WebError {
@@ -29,6 +32,8 @@ pub enum WebError {
 /// The Request type used by Oxide Auth to extract required information
 pub struct Request<'a> {
     inner: &'a rouille::Request,
+    /// The body, which rouille lets us read only once.
+    body: Option<NormalizedParameter>,
 }
 
 #[derive(Debug)]
@@ -40,7 +45,7 @@ pub struct Response {
 impl<'a> Request<'a> {
     /// Create a new Request from a `rouille::Request`
     pub fn new(inner: &'a rouille::Request) -> Self {
-        Request { inner }
+        Request { inner, body: None }
     }
 }
 
@@ -79,9 +84,13 @@ impl<'a> WebRequest for Request<'a> {
             _ => return Err(WebError::Encoding),
         }
 
-        let body = self.inner.data().ok_or(WebError::Encoding)?;
-        let data = serde_urlencoded::from_reader(body).map_err(|_| WebError::Encoding)?;
-        Ok(Cow::Owned(data))
+        if self.body.is_none() {
+            let body = self.inner.data().ok_or(WebError::Encoding)?;
+            let data = serde_urlencoded::from_reader(body).map_err(|_| WebError::Encoding)?;
+            self.body = Some(data);
+        }
+
+        Ok(Cow::Borrowed(self.body.as_ref().unwrap()))
     }
 
     fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
@@ -124,6 +133,12 @@ impl WebResponse for Response {
         Ok(())
     }
 
+    fn forbidden(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.unauthorized(kind)?;
+        self.inner.status_code = 403;
+        Ok(())
+    }
+
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.inner
             .headers
@@ -145,6 +160,24 @@ impl WebResponse for Response {
         self.inner.data = rouille::ResponseBody::from_string(data);
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        self.inner
+            .headers
+            .retain(|header| !header.0.eq_ignore_ascii_case(name));
+        self.inner
+            .headers
+            .push((name.to_owned().into(), value.to_owned().into()));
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.inner.status_code = 429;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
 }
 
 impl Deref for Request<'_> {
@@ -0,0 +1,314 @@
+//! An authorization server for the integration tests of applications.
+//!
+//! Applications protecting their resources with `oxide-auth` need tokens to test with, and clients
+//! need a server to authorize against. The [`MockAuthServer`] runs the authorization and token
+//! endpoints on an ephemeral local port, approves all authorization requests without asking and
+//! mints tokens for any owner and scope on request.
+//!
+//! ```no_run
+//! use oxide_auth_rouille::mock::MockAuthServer;
+//!
+//! let server = MockAuthServer::start().unwrap();
+//! let token = server.mint_token("alice", "read".parse().unwrap());
+//!
+//! // The resource server of the application validates with the same tokens.
+//! let issuer = server.issuer();
+//! # let _ = (token, issuer);
+//! ```
+//!
+//! [`MockAuthServer`]: struct.MockAuthServer.html
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+
+use chrono::{Duration, Utc};
+use oxide_auth::endpoint::{AuthorizationFlow, OwnerConsent, Scope, Solicitation};
+use oxide_auth::frontends::simple::router::FlowRouter;
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::primitives::issuer::{IssuedToken, Issuer, RefreshedToken};
+use oxide_auth::primitives::prelude::*;
+use rouille::router;
+use url::Url;
+
+use crate::{FnSolicitor, GenericEndpoint, Request, Response, Vacant};
+
+/// The client registered with every mock server, and the client of minted tokens.
+pub const MOCK_CLIENT_ID: &str = "mock-client";
+
+/// The passphrase of the [`MOCK_CLIENT_ID`].
+///
+/// [`MOCK_CLIENT_ID`]: constant.MOCK_CLIENT_ID.html
+pub const MOCK_CLIENT_SECRET: &str = "mock-secret";
+
+/// The owner approving authorization requests, unless changed with `approve_as`.
+pub const MOCK_OWNER_ID: &str = "mock-owner";
+
+/// An authorization server on an ephemeral port of `localhost`.
+///
+/// It serves:
+///
+/// * `GET /authorize`, approving every valid request on behalf of the current owner.
+/// * `POST /token`, for authorization codes, refresh tokens and client credentials.
+///
+/// The [`MOCK_CLIENT_ID`] is registered as a confidential client with the [`MOCK_CLIENT_SECRET`],
+/// redirecting to `http://localhost/callback` and with the scope `default`. Register other clients
+/// with [`register_client`]. The server stops when dropped.
+///
+/// This is meant for tests only. Never use it in production, it authorizes anyone.
+///
+/// [`MOCK_CLIENT_ID`]: constant.MOCK_CLIENT_ID.html
+/// [`MOCK_CLIENT_SECRET`]: constant.MOCK_CLIENT_SECRET.html
+/// [`register_client`]: #method.register_client
+pub struct MockAuthServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stop: mpsc::Sender<()>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+/// A handle to the issuer of a [`MockAuthServer`].
+///
+/// Use it as the issuer of the resource endpoints under test, so that they accept the tokens
+/// minted by or requested from the server.
+///
+/// [`MockAuthServer`]: struct.MockAuthServer.html
+#[derive(Clone)]
+pub struct MockIssuer {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    registrar: ClientMap,
+    authorizer: AuthMap<RandomGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    owner_id: String,
+}
+
+impl MockAuthServer {
+    /// Bind a new server to an ephemeral port and start serving in a background thread.
+    pub fn start() -> Result<Self, Box<dyn Error + Send + Sync + 'static>> {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            MOCK_CLIENT_ID,
+            "http://localhost/callback".parse::<Url>().unwrap().into(),
+            "default".parse().unwrap(),
+            MOCK_CLIENT_SECRET.as_bytes(),
+        ));
+
+        let state = Arc::new(Mutex::new(State {
+            registrar,
+            authorizer: AuthMap::new(RandomGenerator::new(16)),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            owner_id: MOCK_OWNER_ID.to_owned(),
+        }));
+
+        let handler = {
+            let state = Arc::clone(&state);
+            move |request: &rouille::Request| serve(&state, request)
+        };
+        let server = rouille::Server::new(("localhost", 0), handler)?;
+        let addr = server.server_addr();
+        let (thread, stop) = server.stoppable();
+
+        Ok(MockAuthServer {
+            addr,
+            state,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server is bound to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The url of the authorization endpoint.
+    pub fn authorize_url(&self) -> Url {
+        self.url("/authorize")
+    }
+
+    /// The url of the token endpoint.
+    pub fn token_url(&self) -> Url {
+        self.url("/token")
+    }
+
+    /// Register an additional client.
+    pub fn register_client(&self, client: Client) {
+        self.lock().registrar.register_client(client);
+    }
+
+    /// Approve all following authorization requests on behalf of the owner.
+    pub fn approve_as(&self, owner_id: &str) {
+        self.lock().owner_id = owner_id.to_owned();
+    }
+
+    /// Issue a bearer token of the [`MOCK_CLIENT_ID`] for the owner and scope.
+    ///
+    /// [`MOCK_CLIENT_ID`]: constant.MOCK_CLIENT_ID.html
+    pub fn mint_token(&self, owner_id: &str, scope: Scope) -> String {
+        let grant = Grant {
+            owner_id: owner_id.to_owned(),
+            client_id: MOCK_CLIENT_ID.to_owned(),
+            scope,
+            redirect_uri: self.url("/"),
+            until: Utc::now() + Duration::hours(1),
+            extensions: Extensions::new(),
+        };
+        self.lock()
+            .issuer
+            .issue(grant)
+            .expect("The token map always issues tokens")
+            .token
+    }
+
+    /// A handle to the issuer of all tokens of this server.
+    pub fn issuer(&self) -> MockIssuer {
+        MockIssuer {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    fn url(&self, path: &str) -> Url {
+        format!("http://{}{}", self.addr, path).parse().unwrap()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Drop for MockAuthServer {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl MockIssuer {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Issuer for MockIssuer {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        self.lock().issuer.issue(grant)
+    }
+
+    fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        self.lock().issuer.refresh(refresh, grant)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        self.lock().issuer.recover_token(token)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        self.lock().issuer.recover_refresh(token)
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.lock().issuer.revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.lock().issuer.revoke_grant(owner_id, client_id)
+    }
+}
+
+fn serve(state: &Mutex<State>, request: &rouille::Request) -> rouille::Response {
+    let mut state = state.lock().unwrap();
+    let State {
+        registrar,
+        authorizer,
+        issuer,
+        owner_id,
+    } = &mut *state;
+    let owner_id = owner_id.clone();
+
+    let mut endpoint = GenericEndpoint {
+        registrar: &*registrar,
+        authorizer,
+        issuer,
+        solicitor: FnSolicitor(move |_: &mut Request, _: Solicitation| {
+            OwnerConsent::Authorized(owner_id.clone())
+        }),
+        scopes: Vacant,
+        response: || Response::from(rouille::Response::text("")),
+    };
+
+    let response = router!(request,
+        (GET) ["/authorize"] => {
+            AuthorizationFlow::prepare(&mut endpoint)
+                .and_then(|mut flow| flow.execute(Request::new(request)))
+        },
+        (POST) ["/token"] => {
+            FlowRouter::new().token(&mut endpoint, Request::new(request))
+        },
+        _ => return rouille::Response::empty_404()
+    );
+
+    response
+        .map(Response::into_inner)
+        .unwrap_or_else(|_| rouille::Response::empty_400())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::blocking::Client as HttpClient;
+    use reqwest::redirect::Policy;
+
+    #[test]
+    fn minted_token_is_recovered() {
+        let server = MockAuthServer::start().unwrap();
+        let token = server.mint_token("alice", "read write".parse().unwrap());
+
+        let grant = server.issuer().recover_token(&token).unwrap().unwrap();
+        assert_eq!(grant.owner_id, "alice");
+        assert_eq!(grant.client_id, MOCK_CLIENT_ID);
+        assert_eq!(grant.scope, "read write".parse().unwrap());
+    }
+
+    #[test]
+    fn code_flow_is_approved() {
+        let server = MockAuthServer::start().unwrap();
+        server.approve_as("bob");
+        let http = HttpClient::builder().redirect(Policy::none()).build().unwrap();
+
+        let mut authorize = server.authorize_url();
+        authorize
+            .query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", MOCK_CLIENT_ID);
+        let response = http.get(authorize).send().unwrap();
+        assert_eq!(response.status(), 302);
+        let location: Url = response.headers()["Location"].to_str().unwrap().parse().unwrap();
+        let code = location
+            .query_pairs()
+            .find(|(name, _)| name == "code")
+            .map(|(_, code)| code.into_owned())
+            .unwrap();
+
+        let response = http
+            .post(server.token_url())
+            .basic_auth(MOCK_CLIENT_ID, Some(MOCK_CLIENT_SECRET))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", &code),
+                ("redirect_uri", "http://localhost/callback"),
+            ])
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_str(&response.text().unwrap()).unwrap();
+        let token = body["access_token"].as_str().unwrap();
+
+        let grant = server.issuer().recover_token(token).unwrap().unwrap();
+        assert_eq!(grant.owner_id, "bob");
+    }
+}
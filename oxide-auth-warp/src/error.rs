@@ -0,0 +1,97 @@
+use crate::OAuthRequest;
+use oxide_auth::frontends::{dev::OAuthError, simple::endpoint::Error};
+use warp::{
+    http::{header::InvalidHeaderValue, StatusCode},
+    reject::{Reject, Rejection},
+    reply::{self, Reply, Response},
+};
+
+#[derive(Debug)]
+/// The error type for Oxide Auth operations
+pub enum WebError {
+    /// Errors occuring in Endpoint operations
+    Endpoint(OAuthError),
+
+    /// Errors occuring when setting a header
+    Header(InvalidHeaderValue),
+
+    /// Request query was absent or could not be parsed
+    Query,
+
+    /// Request body was absent or could not be parsed as a form
+    Body,
+
+    /// The Authorization header was invalid
+    Authorization,
+
+    /// General internal server error
+    InternalError(Option<String>),
+}
+
+impl std::fmt::Display for WebError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WebError::Endpoint(ref e) => write!(f, "Endpoint, {}", e),
+            WebError::Header(ref e) => write!(f, "Couldn't set header, {}", e),
+            WebError::Query => write!(f, "No query present"),
+            WebError::Body => write!(f, "No body present"),
+            WebError::Authorization => write!(f, "Request has invalid Authorization headers"),
+            WebError::InternalError(None) => write!(f, "An internal server error occured"),
+            WebError::InternalError(Some(ref e)) => write!(f, "An internal server error occured: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            WebError::Endpoint(ref e) => e.source(),
+            WebError::Header(ref e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl Reject for WebError {}
+
+impl Reply for WebError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            WebError::Authorization => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        reply::with_status(self.to_string(), status).into_response()
+    }
+}
+
+impl From<Error<OAuthRequest>> for WebError {
+    fn from(e: Error<OAuthRequest>) -> Self {
+        match e {
+            Error::Web(e) => e,
+            Error::OAuth(e) => e.into(),
+        }
+    }
+}
+
+impl From<OAuthError> for WebError {
+    fn from(e: OAuthError) -> Self {
+        WebError::Endpoint(e)
+    }
+}
+
+impl From<InvalidHeaderValue> for WebError {
+    fn from(e: InvalidHeaderValue) -> Self {
+        Self::Header(e)
+    }
+}
+
+/// Turn rejections with a [`WebError`] into replies, for use with `Filter::recover`.
+///
+/// Other rejections are passed on unchanged.
+pub async fn recover(rejection: Rejection) -> Result<Response, Rejection> {
+    match rejection.find::<WebError>() {
+        Some(WebError::Authorization) => Ok(WebError::Authorization.into_response()),
+        Some(err) => Ok(WebError::InternalError(Some(err.to_string())).into_response()),
+        None => Err(rejection),
+    }
+}
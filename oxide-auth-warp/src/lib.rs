@@ -0,0 +1,36 @@
+//! Adaptations and integration for warp.
+//!
+//! Requests are obtained through the filters [`oauth_request`] and [`oauth_resource`], responses of
+//! the flows are replies themselves.
+//!
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//! use oxide_auth::frontends::simple::endpoint::resource_flow;
+//! use oxide_auth::primitives::{generator::RandomGenerator, issuer::TokenMap, scope::Scope};
+//! use oxide_auth_warp::{oauth_resource, OAuthRequest, OAuthResponse, WebError};
+//! use warp::{Filter, Reply};
+//!
+//! # async fn serve() {
+//! let issuer = Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16))));
+//! let scopes: Vec<Scope> = vec!["profile".parse().unwrap()];
+//! let me = warp::path("me").and(oauth_resource()).map(move |request| {
+//!     let mut issuer = issuer.lock().unwrap();
+//!     match resource_flow(&mut *issuer, &scopes).execute(OAuthRequest::from(request)) {
+//!         Ok(grant) => grant.owner_id.into_response(),
+//!         Err(Ok(response)) => response.into_response(),
+//!         Err(Err(err)) => WebError::from(err).into_response(),
+//!     }
+//! });
+//! warp::serve(me.recover(oxide_auth_warp::recover)).run(([127, 0, 0, 1], 8020)).await;
+//! # }
+//! ```
+#![warn(missing_docs)]
+
+mod error;
+pub use error::{recover, WebError};
+
+mod request;
+pub use request::{oauth_request, oauth_resource, OAuthRequest, OAuthResource};
+
+mod response;
+pub use response::OAuthResponse;
@@ -0,0 +1,105 @@
+use crate::WebError;
+use warp::{
+    http::{
+        StatusCode,
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+    },
+    reply::{Reply, Response},
+};
+use oxide_auth::frontends::dev::{WebResponse, Url};
+
+#[derive(Default, Clone, Debug)]
+/// Type implementing `WebResponse` and `Reply` for use in route handlers
+pub struct OAuthResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Option<String>,
+}
+
+impl OAuthResponse {
+    /// Set the `ContentType` header on a response
+    pub fn content_type(mut self, content_type: &str) -> Result<Self, WebError> {
+        self.headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_str(content_type)?);
+        Ok(self)
+    }
+
+    /// Set the body for the response
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_owned());
+        self
+    }
+}
+
+impl WebResponse for OAuthResponse {
+    type Error = WebError;
+
+    fn ok(&mut self) -> Result<(), Self::Error> {
+        self.status = StatusCode::OK;
+        Ok(())
+    }
+
+    fn redirect(&mut self, url: Url) -> Result<(), Self::Error> {
+        self.status = StatusCode::FOUND;
+        self.headers
+            .insert(header::LOCATION, HeaderValue::from_str(url.as_ref())?);
+        Ok(())
+    }
+
+    fn client_error(&mut self) -> Result<(), Self::Error> {
+        self.status = StatusCode::BAD_REQUEST;
+        Ok(())
+    }
+
+    fn unauthorized(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = StatusCode::UNAUTHORIZED;
+        self.headers
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_str(kind)?);
+        Ok(())
+    }
+
+    fn forbidden(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = StatusCode::FORBIDDEN;
+        self.headers
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_str(kind)?);
+        Ok(())
+    }
+
+    fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        self.body = Some(text.to_owned());
+        self.headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        Ok(())
+    }
+
+    fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
+        self.body = Some(json.to_owned());
+        self.headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        Ok(())
+    }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| WebError::InternalError(Some(err.to_string())))?;
+        self.headers.insert(name, HeaderValue::from_str(value)?);
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.status = StatusCode::TOO_MANY_REQUESTS;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Reply for OAuthResponse {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(self.body.unwrap_or_default().into());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
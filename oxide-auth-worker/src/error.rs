@@ -0,0 +1,85 @@
+use crate::OAuthRequest;
+use oxide_auth::frontends::{dev::OAuthError, simple::endpoint::Error};
+use worker::Response;
+
+#[derive(Debug)]
+/// The error type for Oxide Auth operations
+pub enum WebError {
+    /// Errors occuring in Endpoint operations
+    Endpoint(OAuthError),
+
+    /// Errors of the Workers runtime, such as reading the request or building the response
+    Worker(worker::Error),
+
+    /// Request query was absent or could not be parsed
+    Query,
+
+    /// Request body was absent or not a form
+    Body,
+
+    /// The Authorization header was invalid
+    Authorization,
+}
+
+impl WebError {
+    /// Answer the request with this error.
+    pub fn into_response(self) -> worker::Result<Response> {
+        let status = match self {
+            WebError::Authorization => 400,
+            _ => 500,
+        };
+        Response::error(self.to_string(), status)
+    }
+}
+
+impl std::fmt::Display for WebError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WebError::Endpoint(ref e) => write!(f, "Endpoint, {}", e),
+            WebError::Worker(ref e) => write!(f, "Worker, {}", e),
+            WebError::Query => write!(f, "No query present"),
+            WebError::Body => write!(f, "No form body present"),
+            WebError::Authorization => write!(f, "Request has invalid Authorization headers"),
+        }
+    }
+}
+
+impl std::error::Error for WebError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            WebError::Endpoint(ref e) => e.source(),
+            WebError::Worker(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error<OAuthRequest>> for WebError {
+    fn from(e: Error<OAuthRequest>) -> Self {
+        match e {
+            Error::Web(e) => e,
+            Error::OAuth(e) => e.into(),
+        }
+    }
+}
+
+impl From<OAuthError> for WebError {
+    fn from(e: OAuthError) -> Self {
+        WebError::Endpoint(e)
+    }
+}
+
+impl From<worker::Error> for WebError {
+    fn from(e: worker::Error) -> Self {
+        WebError::Worker(e)
+    }
+}
+
+impl From<WebError> for worker::Error {
+    fn from(e: WebError) -> Self {
+        match e {
+            WebError::Worker(e) => e,
+            other => worker::Error::RustError(other.to_string()),
+        }
+    }
+}
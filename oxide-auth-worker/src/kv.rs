@@ -0,0 +1,386 @@
+//! Clients, grants and tokens stored in a Workers KV namespace.
+//!
+//! KV is eventually consistent between the locations of the edge network. A code that is redeemed
+//! at one location may still be readable for up to a minute at others, so the single use of
+//! authorization codes is only guaranteed for requests that reach the same location. Bind separate
+//! namespaces to keep the data of several authorization servers apart.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use once_cell::sync::Lazy;
+use oxide_auth::primitives::generator::{RandomGenerator, TagGrant};
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenType};
+use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
+use oxide_auth::primitives::registrar::{
+    Argon2, BoundClient, Client, ClientAuthMethod, EncodedClient, GrantType, PasswordPolicy,
+    RefreshPolicy, RegisteredClient, RegistrarError,
+};
+use oxide_auth_async::primitives::{Authorizer, Issuer, Registrar};
+use oxide_auth_db::db_service::stored::{bind_redirect, StoredClient, StoredGrant};
+use serde::{Deserialize, Serialize};
+use worker::kv::{KvError, KvStore as Namespace};
+use worker::send::{SendFuture, SendWrapper};
+
+/// Length in bytes of generated codes and tokens.
+const TOKEN_LENGTH: usize = 16;
+
+/// The shortest expiration accepted by KV, in seconds.
+const MIN_TTL: i64 = 60;
+
+static DEFAULT_PASSWORD_POLICY: Lazy<Argon2> = Lazy::new(Argon2::default);
+
+/// Clients, grants and tokens stored in a KV namespace.
+///
+/// Implements all of the async primitives of `oxide-auth-async`. Clone it to obtain independent
+/// handles for the registrar, authorizer and issuer of an endpoint.
+pub struct KvStore {
+    namespace: SendWrapper<Namespace>,
+    password_policy: Arc<dyn PasswordPolicy>,
+    generator: RandomGenerator,
+    usage: u64,
+    token_duration: Duration,
+    refresh_duration: Duration,
+}
+
+/// The record of an access token, also found through its refresh token.
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    refresh: Option<String>,
+    grant: StoredGrant,
+}
+
+impl KvStore {
+    /// Use a namespace bound to the worker, see `Env::kv`.
+    pub fn new(namespace: Namespace) -> Self {
+        KvStore {
+            namespace: SendWrapper::new(namespace),
+            password_policy: Arc::new(DEFAULT_PASSWORD_POLICY.clone()),
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: Duration::hours(1),
+            refresh_duration: Duration::days(30),
+        }
+    }
+
+    /// Change how passwords are encoded while stored.
+    pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
+        self.password_policy = Arc::new(new_policy);
+    }
+
+    /// Set the validity of all issued access tokens, one hour by default.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.token_duration = duration;
+    }
+
+    /// Set how long refresh tokens can be used, 30 days by default.
+    ///
+    /// Tokens are removed from the namespace once their refresh token has expired.
+    pub fn refresh_valid_for(&mut self, duration: Duration) {
+        self.refresh_duration = duration;
+    }
+
+    /// Insert or update the client record.
+    pub async fn register_client(&self, client: Client) -> Result<(), RegistrarError> {
+        let encoded = client.encode(&*self.password_policy);
+        let stored = StoredClient::from_encoded(&encoded).map_err(|_| RegistrarError::PrimitiveError)?;
+        let data = serde_json::to_string(&stored).map_err(|_| RegistrarError::PrimitiveError)?;
+
+        self.write(&client_key(&encoded.client_id), data, None)
+            .await
+            .map_err(|_| RegistrarError::PrimitiveError)
+    }
+
+    async fn find_client(&self, client_id: &str) -> Result<EncodedClient, RegistrarError> {
+        let data = match self.read(&client_key(client_id)).await {
+            Ok(Some(data)) => data,
+            Ok(None) => return Err(RegistrarError::Unspecified),
+            Err(_) => return Err(RegistrarError::PrimitiveError),
+        };
+
+        serde_json::from_str::<StoredClient>(&data)
+            .ok()
+            .and_then(|stored| stored.into_encoded().ok())
+            .ok_or(RegistrarError::PrimitiveError)
+    }
+
+    async fn read(&self, key: &str) -> Result<Option<String>, KvError> {
+        SendFuture::new(async { self.namespace.get(key).text().await }).await
+    }
+
+    /// Write a value, expiring after the duration if one is given.
+    async fn write(&self, key: &str, value: String, ttl: Option<Duration>) -> Result<(), KvError> {
+        SendFuture::new(async {
+            let mut put = self.namespace.put(key, value)?;
+            if let Some(ttl) = ttl {
+                put = put.expiration_ttl(ttl.num_seconds().max(MIN_TTL) as u64);
+            }
+            put.execute().await
+        })
+        .await
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), KvError> {
+        SendFuture::new(async { self.namespace.delete(key).await }).await
+    }
+
+    /// All keys starting with the prefix, fetched page by page.
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>, KvError> {
+        SendFuture::new(async {
+            let mut keys = Vec::new();
+            let mut cursor = None;
+            loop {
+                let mut list = self.namespace.list().prefix(prefix.to_owned());
+                if let Some(cursor) = cursor.take() {
+                    list = list.cursor(cursor);
+                }
+
+                let page = list.execute().await?;
+                keys.extend(page.keys.into_iter().map(|key| key.name));
+                match page.cursor {
+                    Some(next) if !page.list_complete => cursor = Some(next),
+                    _ => return Ok(keys),
+                }
+            }
+        })
+        .await
+    }
+
+    fn tag(&mut self, grant: &Grant) -> Result<String, ()> {
+        let tag = self.generator.tag(self.usage, grant)?;
+        self.usage = self.usage.wrapping_add(1);
+        Ok(tag)
+    }
+
+    async fn insert_token(&mut self, mut grant: Grant) -> Result<(String, String, Grant), ()> {
+        grant.until = Utc::now() + self.token_duration;
+        let access = self.tag(&grant)?;
+        let refresh = self.tag(&grant)?;
+        let token = StoredToken {
+            refresh: Some(refresh.clone()),
+            grant: StoredGrant::from(&grant),
+        };
+        let data = serde_json::to_string(&token).map_err(|_| ())?;
+
+        let ttl = Some(self.refresh_duration);
+        self.write(&token_key(&access), data, ttl).await.map_err(|_| ())?;
+        self.write(&refresh_key(&refresh), access.clone(), ttl)
+            .await
+            .map_err(|_| ())?;
+
+        Ok((access, refresh, grant))
+    }
+
+    async fn find_token(&self, access: &str) -> Result<Option<StoredToken>, ()> {
+        match self.read(&token_key(access)).await.map_err(|_| ())? {
+            Some(data) => serde_json::from_str(&data).map(Some).map_err(|_| ()),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the codes of a client, and of an owner if one is given.
+    async fn delete_client_grants(&self, client_id: &str, owner_id: Option<&str>) -> Result<usize, ()> {
+        // Grants are opaque to KV, every code of the namespace is read.
+        let mut revoked = 0;
+        for key in self.keys(grant_key("").as_str()).await.map_err(|_| ())? {
+            let data = match self.read(&key).await.map_err(|_| ())? {
+                Some(data) => data,
+                None => continue,
+            };
+
+            match serde_json::from_str::<StoredGrant>(&data) {
+                Ok(stored) if stored.issued_to(client_id, owner_id) => (),
+                _ => continue,
+            }
+
+            self.remove(&key).await.map_err(|_| ())?;
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+
+    /// Delete the tokens of a client, and of an owner if one is given, with their refresh tokens.
+    async fn delete_client_tokens(&self, client_id: &str, owner_id: Option<&str>) -> Result<usize, ()> {
+        let mut revoked = 0;
+        for key in self.keys(token_key("").as_str()).await.map_err(|_| ())? {
+            let access = &key[token_key("").len()..];
+            let stored = match self.find_token(access).await {
+                Ok(Some(stored)) if stored.grant.issued_to(client_id, owner_id) => stored,
+                _ => continue,
+            };
+
+            if let Some(refresh) = &stored.refresh {
+                self.remove(&refresh_key(refresh)).await.map_err(|_| ())?;
+            }
+            self.remove(&key).await.map_err(|_| ())?;
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+}
+
+fn client_key(client_id: &str) -> String {
+    format!("client:{}", client_id)
+}
+
+fn grant_key(code: &str) -> String {
+    format!("grant:{}", code)
+}
+
+fn token_key(access: &str) -> String {
+    format!("token:{}", access)
+}
+
+fn refresh_key(refresh: &str) -> String {
+    format!("refresh:{}", refresh)
+}
+
+impl Clone for KvStore {
+    fn clone(&self) -> Self {
+        KvStore {
+            namespace: self.namespace.clone(),
+            password_policy: self.password_policy.clone(),
+            generator: RandomGenerator::new(TOKEN_LENGTH),
+            usage: 0,
+            token_duration: self.token_duration,
+            refresh_duration: self.refresh_duration,
+        }
+    }
+}
+
+#[async_trait]
+impl Registrar for KvStore {
+    async fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        let client = self.find_client(&bound.client_id).await?;
+        bind_redirect(client, bound)
+    }
+
+    async fn negotiate<'a>(
+        &self, bound: BoundClient<'a>, _scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        let client = self.find_client(&bound.client_id).await?;
+        Ok(PreGrant {
+            client_id: bound.client_id.into_owned(),
+            redirect_uri: bound.redirect_uri.into_owned(),
+            scope: client.default_scope,
+        })
+    }
+
+    async fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        let client = self.find_client(client_id).await?;
+        RegisteredClient::new(&client, &*self.password_policy).check_authentication(passphrase)
+    }
+
+    async fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.find_client(client_id)
+            .await
+            .map(|client| client.refresh_policy)
+            .unwrap_or_default()
+    }
+
+    async fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.find_client(client_id)
+            .await
+            .is_ok_and(|client| client.permits_grant(grant_type))
+    }
+
+    async fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.find_client(client_id).await.ok()?.auth_method
+    }
+}
+
+#[async_trait]
+impl Authorizer for KvStore {
+    async fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+        let code = self.tag(&grant)?;
+        let data = serde_json::to_string(&StoredGrant::from(&grant)).map_err(|_| ())?;
+        let ttl = grant.until - Utc::now();
+
+        self.write(&grant_key(&code), data, Some(ttl))
+            .await
+            .map_err(|_| ())?;
+        Ok(code)
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        let key = grant_key(code);
+        let data = match self.read(&key).await.map_err(|_| ())? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+
+        self.remove(&key).await.map_err(|_| ())?;
+        let stored: StoredGrant = serde_json::from_str(&data).map_err(|_| ())?;
+        stored.into_grant().map(Some).map_err(|_| ())
+    }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_grants(client_id, None).await
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_grants(client_id, Some(owner_id)).await
+    }
+}
+
+#[async_trait]
+impl Issuer for KvStore {
+    async fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        let (access, refresh, grant) = self.insert_token(grant).await?;
+        Ok(IssuedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        // Should only be called on valid refresh tokens.
+        let access = match self.read(&refresh_key(refresh)).await.map_err(|_| ())? {
+            Some(access) => access,
+            None => return Err(()),
+        };
+
+        self.remove(&refresh_key(refresh)).await.map_err(|_| ())?;
+        self.remove(&token_key(&access)).await.map_err(|_| ())?;
+
+        let (access, refresh, grant) = self.insert_token(grant).await?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh: Some(refresh),
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        match self.find_token(token).await? {
+            Some(token) => token.grant.into_grant().map(Some).map_err(|_| ()),
+            None => Ok(None),
+        }
+    }
+
+    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+        let access = match self.read(&refresh_key(token)).await.map_err(|_| ())? {
+            Some(access) => access,
+            None => return Ok(None),
+        };
+
+        match self.find_token(&access).await? {
+            Some(stored) if stored.refresh.as_deref() == Some(token) => {
+                stored.grant.into_grant().map(Some).map_err(|_| ())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_tokens(client_id, None).await
+    }
+
+    async fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.delete_client_tokens(client_id, Some(owner_id)).await
+    }
+}
@@ -0,0 +1,36 @@
+//! Adaptations and integration for Cloudflare Workers.
+//!
+//! Read a `worker::Request` into an [`OAuthRequest`] and convert the [`OAuthResponse`] of a flow
+//! into a `worker::Response` with `TryFrom`. Workers have no state that outlives a request, so
+//! the primitives are kept in a KV namespace by [`kv::KvStore`]. It implements the primitives of
+//! `oxide-auth-async` whose flows are then run by an endpoint of your own.
+//!
+//! The crate only targets `wasm32-unknown-unknown`. It enables the `wasm` feature of `oxide-auth`
+//! so that generated tokens and the clock come from the JavaScript runtime.
+//!
+//! ```no_run
+//! use oxide_auth_worker::{kv::KvStore, OAuthRequest, OAuthResponse};
+//! use worker::{Env, Request, Response};
+//!
+//! async fn token(mut req: Request, env: Env) -> worker::Result<Response> {
+//!     let store = KvStore::new(env.kv("OAUTH")?);
+//!     let request = OAuthRequest::from_request(&mut req).await?;
+//!     let response: OAuthResponse = issue_token(store, request).await?;
+//!     Response::try_from(response)
+//! }
+//! # async fn issue_token(_: KvStore, _: OAuthRequest) -> Result<OAuthResponse, oxide_auth_worker::WebError> {
+//! #     unimplemented!()
+//! # }
+//! ```
+#![warn(missing_docs)]
+
+mod error;
+pub use error::WebError;
+
+pub mod kv;
+
+mod request;
+pub use request::OAuthRequest;
+
+mod response;
+pub use response::OAuthResponse;
@@ -0,0 +1,106 @@
+use oxide_auth::frontends::dev::{NormalizedParameter, QueryParameter, WebRequest};
+use worker::{Headers, Request};
+use crate::{OAuthResponse, WebError};
+use std::borrow::Cow;
+
+#[derive(Clone, Debug, Default)]
+/// Type implementing `WebRequest` over the parts and buffered body of a `worker::Request`
+pub struct OAuthRequest {
+    auth: Option<String>,
+    query: Option<NormalizedParameter>,
+    body: Option<NormalizedParameter>,
+}
+
+impl OAuthRequest {
+    /// Read the authorization, query and form body of a request
+    ///
+    /// This consumes the body of the request. It is only read if the request declares it as
+    /// `application/x-www-form-urlencoded`.
+    pub async fn from_request(request: &mut Request) -> Result<Self, WebError> {
+        let auth = authorization(request.headers())?;
+        let query = request
+            .url()?
+            .query()
+            .map(|query| NormalizedParameter::from_urlencoded(query.as_bytes()));
+        let body = if is_form(request.headers())? {
+            Some(NormalizedParameter::from_urlencoded(
+                request.text().await?.as_bytes(),
+            ))
+        } else {
+            None
+        };
+
+        Ok(OAuthRequest { auth, query, body })
+    }
+
+    /// Read only the authorization, enough for guarding resources
+    pub fn from_headers(headers: &Headers) -> Result<Self, WebError> {
+        Ok(OAuthRequest {
+            auth: authorization(headers)?,
+            ..Default::default()
+        })
+    }
+
+    /// Fetch the authorization header from the request
+    pub fn authorization_header(&self) -> Option<&str> {
+        self.auth.as_deref()
+    }
+
+    /// Fetch the query for this request
+    pub fn query(&self) -> Option<&NormalizedParameter> {
+        self.query.as_ref()
+    }
+
+    /// Fetch the query mutably
+    pub fn query_mut(&mut self) -> Option<&mut NormalizedParameter> {
+        self.query.as_mut()
+    }
+
+    /// Fetch the body of the request
+    pub fn body(&self) -> Option<&NormalizedParameter> {
+        self.body.as_ref()
+    }
+}
+
+/// The Fetch API joins repeated headers with a comma, which neither of the `Basic` nor the
+/// `Bearer` credentials may contain.
+fn authorization(headers: &Headers) -> Result<Option<String>, WebError> {
+    match headers.get("authorization")? {
+        Some(auth) if auth.contains(',') => Err(WebError::Authorization),
+        auth => Ok(auth),
+    }
+}
+
+fn is_form(headers: &Headers) -> Result<bool, WebError> {
+    let content_type = headers.get("content-type")?;
+    Ok(content_type
+        .as_deref()
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            mime.trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        }))
+}
+
+impl WebRequest for OAuthRequest {
+    type Error = WebError;
+    type Response = OAuthResponse;
+
+    fn query(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
+        self.query
+            .as_ref()
+            .map(|q| Cow::Borrowed(q as &dyn QueryParameter))
+            .ok_or(WebError::Query)
+    }
+
+    fn urlbody(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
+        self.body
+            .as_ref()
+            .map(|b| Cow::Borrowed(b as &dyn QueryParameter))
+            .ok_or(WebError::Body)
+    }
+
+    fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
+        Ok(self.auth.as_deref().map(Cow::Borrowed))
+    }
+}
@@ -0,0 +1,128 @@
+use crate::WebError;
+use oxide_auth::frontends::dev::{WebResponse, Url};
+use worker::{Headers, Response};
+
+#[derive(Clone, Debug)]
+/// Type implementing `WebResponse`, convertible into a `worker::Response`
+pub struct OAuthResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+}
+
+impl OAuthResponse {
+    /// Set the `ContentType` header on a response
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.insert_header("content-type", content_type.to_owned());
+        self
+    }
+
+    /// Set the body for the response
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_owned());
+        self
+    }
+
+    /// The status of the response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The value of a header set by the flow, by lowercase name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn insert_header(&mut self, name: &str, value: String) {
+        let name = name.to_ascii_lowercase();
+        self.headers.retain(|(key, _)| *key != name);
+        self.headers.push((name, value));
+    }
+}
+
+impl Default for OAuthResponse {
+    fn default() -> Self {
+        OAuthResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+}
+
+impl WebResponse for OAuthResponse {
+    type Error = WebError;
+
+    fn ok(&mut self) -> Result<(), Self::Error> {
+        self.status = 200;
+        Ok(())
+    }
+
+    fn redirect(&mut self, url: Url) -> Result<(), Self::Error> {
+        self.status = 302;
+        self.insert_header("location", url.into());
+        Ok(())
+    }
+
+    fn client_error(&mut self) -> Result<(), Self::Error> {
+        self.status = 400;
+        Ok(())
+    }
+
+    fn unauthorized(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = 401;
+        self.insert_header("www-authenticate", kind.to_owned());
+        Ok(())
+    }
+
+    fn forbidden(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = 403;
+        self.insert_header("www-authenticate", kind.to_owned());
+        Ok(())
+    }
+
+    fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        self.body = Some(text.to_owned());
+        self.insert_header("content-type", "text/plain".to_owned());
+        Ok(())
+    }
+
+    fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
+        self.body = Some(json.to_owned());
+        self.insert_header("content-type", "application/json".to_owned());
+        Ok(())
+    }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        self.insert_header(name, value.to_owned());
+        Ok(())
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.status = 429;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<OAuthResponse> for Response {
+    type Error = worker::Error;
+
+    fn try_from(response: OAuthResponse) -> worker::Result<Response> {
+        let mut headers = Headers::new();
+        for (name, value) in &response.headers {
+            headers.set(name, value)?;
+        }
+
+        let body = match response.body {
+            Some(body) => Response::ok(body)?,
+            None => Response::empty()?,
+        };
+        Ok(body.with_status(response.status).with_headers(headers))
+    }
+}
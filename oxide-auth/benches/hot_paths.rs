@@ -0,0 +1,217 @@
+//! Benchmarks of the operations executed on every request.
+//!
+//! Run with `cargo bench -p oxide-auth`. To compare a change against a baseline, save one on the
+//! base revision with `cargo bench -p oxide-auth -- --save-baseline base` and compare the change
+//! with `cargo bench -p oxide-auth -- --baseline base`.
+use std::time::Instant;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use chrono::{Duration, Utc};
+use oxide_auth::endpoint::{Issuer, NormalizedParameter, QueryParameter};
+use oxide_auth::frontends::simple::endpoint::access_token_flow;
+use oxide_auth::frontends::simple::request::{Request, Status};
+use oxide_auth::primitives::authorizer::{AuthMap, Authorizer};
+use oxide_auth::primitives::generator::RandomGenerator;
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::primitives::issuer::{TokenMap, TokenSigner};
+use oxide_auth::primitives::registrar::{Argon2, Client, ClientMap, Prechecked, Registrar, RegisteredUrl};
+use oxide_auth::primitives::scope::{Scope, ScopeMatching};
+
+const CLIENT_ID: &str = "LocalClient";
+const REDIRECT_URI: &str = "https://client.example/endpoint";
+const PASSPHRASE: &[u8] = b"VGhpcyBpcyBhIHZlcnkgc2VjdXJlIHBhc3NwaHJhc2UK";
+
+fn grant() -> Grant {
+    Grant {
+        owner_id: "owner".into(),
+        client_id: CLIENT_ID.into(),
+        scope: "read write profile".parse().unwrap(),
+        redirect_uri: REDIRECT_URI.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    }
+}
+
+fn token_issuance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("issue");
+    let grant = grant();
+
+    group.bench_function("token_map", |b| {
+        b.iter_batched(
+            || TokenMap::new(RandomGenerator::new(16)),
+            |mut issuer| issuer.issue(grant.clone()).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let mut signer = TokenSigner::ephemeral();
+    group.bench_function("token_signer", |b| {
+        b.iter(|| signer.issue(grant.clone()).unwrap())
+    });
+
+    group.finish();
+}
+
+fn token_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("recover_token");
+    let grant = grant();
+
+    for size in [1, 10_000] {
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        let tokens: Vec<_> = (0..size)
+            .map(|_| issuer.issue(grant.clone()).unwrap().token)
+            .collect();
+        let known = &tokens[size / 2];
+
+        group.bench_with_input(BenchmarkId::new("token_map", size), known, |b, token| {
+            b.iter(|| issuer.recover_token(token).unwrap().unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("token_map_unknown", size), &size, |b, _| {
+            b.iter(|| issuer.recover_token("unknown").unwrap())
+        });
+    }
+
+    let mut signer = TokenSigner::ephemeral();
+    let token = signer.issue(grant).unwrap().token;
+    group.bench_function("token_signer", |b| {
+        b.iter(|| signer.recover_token(&token).unwrap().unwrap())
+    });
+
+    group.finish();
+}
+
+fn scopes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scope");
+    let granted: Scope = "repo user:email read:org admin:* profile".parse().unwrap();
+    let required: Scope = "repo:status admin:org".parse().unwrap();
+
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            "repo user:email read:org admin:* profile"
+                .parse::<Scope>()
+                .unwrap()
+        })
+    });
+    group.bench_function("allow_access", |b| {
+        b.iter(|| required.allow_access_with(&granted, ScopeMatching::Exact))
+    });
+    group.bench_function("allow_access_hierarchical", |b| {
+        b.iter(|| required.allow_access_with(&granted, ScopeMatching::Hierarchical))
+    });
+
+    group.finish();
+}
+
+fn parameters(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parameters");
+    let body = b"grant_type=authorization_code&code=c2VjcmV0&client_id=LocalClient\
+        &redirect_uri=https%3A%2F%2Fclient.example%2Fendpoint";
+
+    group.bench_function("from_urlencoded", |b| {
+        b.iter(|| NormalizedParameter::from_urlencoded(body))
+    });
+    let params = NormalizedParameter::from_urlencoded(body);
+    group.bench_function("normalize", |b| b.iter(|| params.normalize()));
+    group.bench_function("unique_value", |b| {
+        b.iter(|| params.unique_value("redirect_uri").unwrap().len())
+    });
+
+    group.finish();
+}
+
+fn client_secret(c: &mut Criterion) {
+    let mut group = c.benchmark_group("client_secret");
+    // Each check derives an Argon2 hash on purpose, which takes far longer than the other paths.
+    group.sample_size(10);
+
+    let mut registrar = ClientMap::new();
+    registrar.register_client(confidential_client());
+    group.bench_function("argon2", |b| {
+        b.iter(|| registrar.check(CLIENT_ID, Some(PASSPHRASE)).unwrap())
+    });
+    group.bench_function("argon2_wrong", |b| {
+        b.iter(|| registrar.check(CLIENT_ID, Some(b"wrong")).unwrap_err())
+    });
+
+    let mut prechecked = ClientMap::new();
+    prechecked.set_password_policy(
+        Prechecked::new(Argon2::default())
+            .length(32, 128)
+            .cache_verified(16, std::time::Duration::from_secs(60)),
+    );
+    prechecked.register_client(confidential_client());
+    group.bench_function("prechecked_cached", |b| {
+        b.iter(|| prechecked.check(CLIENT_ID, Some(PASSPHRASE)).unwrap())
+    });
+    group.bench_function("prechecked_malformed", |b| {
+        b.iter(|| prechecked.check(CLIENT_ID, Some(b"wrong")).unwrap_err())
+    });
+
+    group.finish();
+}
+
+fn access_token(c: &mut Criterion) {
+    // A public client, so that the flow is not dominated by hashing the passphrase.
+    let mut registrar = ClientMap::new();
+    registrar.register_client(Client::public(
+        CLIENT_ID,
+        RegisteredUrl::Semantic(REDIRECT_URI.parse().unwrap()),
+        "read write profile".parse().unwrap(),
+    ));
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    let grant = grant();
+
+    c.bench_function("access_token_flow", |b| {
+        // Codes are redeemed only once, so each iteration gets its own.
+        b.iter_custom(|iters| {
+            let requests: Vec<_> = (0..iters)
+                .map(|_| request(authorizer.authorize(grant.clone()).unwrap()))
+                .collect();
+
+            let start = Instant::now();
+            for request in requests {
+                let response = access_token_flow(&registrar, &mut authorizer, &mut issuer)
+                    .execute(request)
+                    .unwrap();
+                assert_eq!(response.status, Status::Ok);
+            }
+            start.elapsed()
+        })
+    });
+}
+
+fn confidential_client() -> Client {
+    Client::confidential(
+        CLIENT_ID,
+        RegisteredUrl::Semantic(REDIRECT_URI.parse().unwrap()),
+        "read write profile".parse().unwrap(),
+        PASSPHRASE,
+    )
+}
+
+fn request(code: String) -> Request {
+    let mut request = Request::default();
+    let body = [
+        ("grant_type", "authorization_code"),
+        ("client_id", CLIENT_ID),
+        ("code", &code),
+        ("redirect_uri", REDIRECT_URI),
+    ];
+    for (key, value) in body {
+        request.urlbody.insert(key.into(), value.into());
+    }
+    request
+}
+
+criterion_group!(
+    hot_paths,
+    token_issuance,
+    token_lookup,
+    scopes,
+    parameters,
+    client_secret,
+    access_token
+);
+criterion_main!(hot_paths);
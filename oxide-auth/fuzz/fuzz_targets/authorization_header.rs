@@ -0,0 +1,41 @@
+//! `Authorization` headers of resource requests and of client authentication.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use oxide_auth::endpoint::{AccessTokenFlow, ResourceFlow};
+use oxide_auth::frontends::simple::endpoint::{Generic, Vacant};
+use oxide_auth::frontends::simple::request::{Request, Status};
+use oxide_auth::primitives::prelude::*;
+
+fuzz_target!(|data: &str| {
+    let mut endpoint = Generic {
+        registrar: ClientMap::new(),
+        authorizer: AuthMap::new(RandomGenerator::new(16)),
+        issuer: TokenMap::new(RandomGenerator::new(16)),
+        solicitor: Vacant,
+        scopes: vec!["default".parse::<Scope>().unwrap()],
+        response: Vacant,
+    };
+
+    let request = Request {
+        auth: Some(data.to_owned()),
+        ..Request::default()
+    };
+    // No token was issued, so no header can grant access.
+    assert!(ResourceFlow::prepare(&mut endpoint)
+        .unwrap()
+        .execute(request)
+        .is_err());
+
+    let request = Request {
+        auth: Some(data.to_owned()),
+        urlbody: vec![("grant_type", "authorization_code"), ("code", "code")]
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect(),
+        ..Request::default()
+    };
+    // Neither a client nor a code is known.
+    if let Ok(response) = AccessTokenFlow::prepare(&mut endpoint).unwrap().execute(request) {
+        assert!(response.status != Status::Ok);
+    }
+});
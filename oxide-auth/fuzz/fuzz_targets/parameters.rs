@@ -0,0 +1,23 @@
+//! Query and body parameters, urlencoded or as JSON.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use oxide_auth::endpoint::{NormalizedParameter, QueryParameter};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(params) = serde_urlencoded::from_bytes::<NormalizedParameter>(data) {
+        check(&params);
+    }
+
+    check(&NormalizedParameter::from_urlencoded(data));
+
+    if let Some(params) = NormalizedParameter::from_json(data) {
+        check(&params);
+    }
+});
+
+fn check(params: &NormalizedParameter) {
+    for (key, value) in params.iter() {
+        assert_eq!(params.unique_value(key).as_deref(), Some(value));
+    }
+    assert!(params.iter().count() <= params.len());
+}
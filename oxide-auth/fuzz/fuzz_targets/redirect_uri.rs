@@ -0,0 +1,26 @@
+//! Redirect uris as sent in requests, compared with each kind of registered url.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use oxide_auth::primitives::registrar::{ExactUrl, IgnoreLocalPortUrl, RegisteredUrl};
+
+fuzz_target!(|data: &str| {
+    let exact = match data.parse::<ExactUrl>() {
+        Ok(exact) => exact,
+        Err(_) => return,
+    };
+    let ignore_port = IgnoreLocalPortUrl::new(data).expect("Parsed as exact url");
+    let semantic = exact.to_url();
+
+    let registered = [
+        RegisteredUrl::Exact(exact.clone()),
+        RegisteredUrl::Semantic(semantic.clone()),
+        RegisteredUrl::IgnorePortOnLocalhost(ignore_port.clone()),
+    ];
+    for url in &registered {
+        assert!(*url == exact || *url != exact);
+        assert!(*url == ignore_port || *url != ignore_port);
+        assert!(*url == semantic || *url != semantic);
+        let _ = url.to_string();
+    }
+    assert_eq!(RegisteredUrl::Exact(exact.clone()), exact);
+});
@@ -0,0 +1,15 @@
+//! Scopes, which must survive formatting and parsing again.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use oxide_auth::primitives::scope::Scope;
+
+fuzz_target!(|data: &str| {
+    let scope = match data.parse::<Scope>() {
+        Ok(scope) => scope,
+        Err(_) => return,
+    };
+
+    let formatted = scope.to_string();
+    assert_eq!(formatted.parse::<Scope>().ok(), Some(scope.clone()));
+    assert!(scope <= scope.union(&scope));
+});
@@ -6,12 +6,14 @@ use std::collections::HashMap;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use zeroize::Zeroizing;
 
 use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::endpoint::{GrantDecision, GrantEvent, GrantPolicy};
 use crate::primitives::authorizer::Authorizer;
 use crate::primitives::issuer::{IssuedToken, Issuer};
 use crate::primitives::grant::{Extensions, Grant};
-use crate::primitives::registrar::{Registrar, RegistrarError};
+use crate::primitives::registrar::{ClientAuthMethod, GrantType, Registrar, RegistrarError};
 use crate::primitives::scope::Scope;
 
 /// Token Response
@@ -40,6 +42,22 @@ pub struct TokenResponse {
     /// Error code
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+
+    /// Further members of the response, such as those published by extensions.
+    #[serde(flatten)]
+    pub additional: HashMap<String, serde_json::Value>,
+}
+
+impl TokenResponse {
+    /// Names of the members defined by the response itself.
+    pub const MEMBERS: [&'static str; 6] = [
+        "access_token",
+        "refresh_token",
+        "token_type",
+        "expires_in",
+        "scope",
+        "error",
+    ];
 }
 
 /// Authorization information from the request
@@ -128,6 +146,18 @@ pub trait Endpoint {
     ///
     /// It is possible to use `&mut ()`.
     fn extension(&mut self) -> &mut dyn Extension;
+
+    /// Decides over the grant just before the access token is issued.
+    ///
+    /// The default implementation has no policy and issues all grants.
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        None
+    }
+
+    /// Binds the grant to the context of the request, just before the access token is issued.
+    ///
+    /// The default implementation leaves the grant unbound.
+    fn bind(&mut self, _grant: &mut Grant) {}
 }
 
 enum Credentials<'a> {
@@ -137,6 +167,7 @@ enum Credentials<'a> {
     Authenticated {
         client_id: &'a str,
         passphrase: &'a [u8],
+        auth_method: ClientAuthMethod,
     },
     /// No password but name was offered.
     ///
@@ -185,7 +216,8 @@ enum AccessTokenState {
     /// State after the request has been validated.
     Authenticate {
         client: String,
-        passdata: Option<Vec<u8>>,
+        passdata: Option<Zeroizing<Vec<u8>>>,
+        auth_method: ClientAuthMethod,
         code: String,
         // TODO: parsing here is unnecessary if we compare a string representation.
         redirect_uri: url::Url,
@@ -239,6 +271,8 @@ pub enum Output<'machine> {
         client: &'machine str,
         /// The supplied passdata/password.
         passdata: Option<&'machine [u8]>,
+        /// How the client authenticated.
+        auth_method: ClientAuthMethod,
     },
     /// The issuer should try to recover the grant for this `code`
     ///
@@ -256,10 +290,11 @@ pub enum Output<'machine> {
     },
     /// The issue should issue a new access token
     ///
-    /// Fullfilled by `Input::Issued`
+    /// Fullfilled by `Input::Issued`. A grant policy may change the grant before, the response
+    /// then reports the changed grant.
     Issue {
         /// The grant to be used in the token generation
-        grant: &'machine Grant,
+        grant: &'machine mut Grant,
     },
     /// The state machine finished and a new bearer token was generated
     ///
@@ -315,9 +350,15 @@ impl AccessToken {
     fn output(&mut self) -> Output<'_> {
         match &mut self.state {
             AccessTokenState::Err(err) => Output::Err(Box::new(err.clone())),
-            AccessTokenState::Authenticate { client, passdata, .. } => Output::Authenticate {
+            AccessTokenState::Authenticate {
+                client,
+                passdata,
+                auth_method,
+                ..
+            } => Output::Authenticate {
                 client,
-                passdata: passdata.as_ref().map(Vec::as_slice),
+                passdata: passdata.as_ref().map(|passdata| passdata.as_slice()),
+                auth_method: *auth_method,
             },
             AccessTokenState::Recover { code, .. } => Output::Recover { code },
             AccessTokenState::Extend { extensions, .. } => Output::Extend { extensions },
@@ -347,15 +388,17 @@ impl AccessToken {
             Authorization::None => {}
             Authorization::Username(username) => credentials.unauthenticated(username),
             Authorization::UsernamePassword(username, password) => {
-                credentials.authenticate(username, password)
+                credentials.authenticate(username, password, ClientAuthMethod::ClientSecretBasic)
             }
         }
 
         if let Some(client_id) = &client_id {
             match &client_secret {
-                Some(auth) if request.allow_credentials_in_body() => {
-                    credentials.authenticate(client_id.as_ref(), auth.as_ref().as_bytes())
-                }
+                Some(auth) if request.allow_credentials_in_body() => credentials.authenticate(
+                    client_id.as_ref(),
+                    auth.as_ref().as_bytes(),
+                    ClientAuthMethod::ClientSecretPost,
+                ),
                 // Ignore parameter if not allowed.
                 Some(_) | None => credentials.unauthenticated(client_id.as_ref()),
             }
@@ -367,7 +410,7 @@ impl AccessToken {
             Some(_) => return Err(Error::invalid_with(AccessTokenErrorType::UnsupportedGrantType)),
         };
 
-        let (client_id, passdata) = credentials.into_client().ok_or_else(Error::invalid)?;
+        let (client_id, passdata, auth_method) = credentials.into_client().ok_or_else(Error::invalid)?;
 
         let redirect_uri = request
             .redirect_uri()
@@ -379,7 +422,8 @@ impl AccessToken {
 
         Ok(AccessTokenState::Authenticate {
             client: client_id.to_string(),
-            passdata: passdata.map(Vec::from),
+            passdata: passdata.map(|passdata| Zeroizing::new(Vec::from(passdata))),
+            auth_method,
             redirect_uri,
             code: code.into_owned(),
         })
@@ -416,14 +460,13 @@ impl AccessToken {
         })
     }
 
-    fn issue(grant: Box<Grant>, extensions: Extensions) -> AccessTokenState {
-        AccessTokenState::Issue {
-            grant: Box::new(Grant { extensions, ..*grant }),
-        }
+    fn issue(mut grant: Box<Grant>, extensions: Extensions) -> AccessTokenState {
+        grant.extensions = extensions;
+        AccessTokenState::Issue { grant }
     }
 
     fn finish(grant: Box<Grant>, token: IssuedToken) -> BearerToken {
-        BearerToken(token, grant.scope.clone())
+        BearerToken(token, grant.scope.clone(), Parties::of(&grant), grant.extensions)
     }
 }
 
@@ -435,13 +478,14 @@ pub fn access_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result
         Authenticate {
             client: &'a str,
             passdata: Option<&'a [u8]>,
+            auth_method: ClientAuthMethod,
         },
         Recover(&'a str),
         Extend {
             extensions: &'a mut Extensions,
         },
         Issue {
-            grant: &'a Grant,
+            grant: &'a mut Grant,
         },
     }
 
@@ -451,17 +495,28 @@ pub fn access_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result
     loop {
         let input = match requested {
             Requested::None => Input::None,
-            Requested::Authenticate { client, passdata } => {
-                handler
-                    .registrar()
-                    .check(client, passdata)
-                    .map_err(|err| match err {
-                        RegistrarError::Unspecified => Error::unauthorized("basic"),
-                        RegistrarError::PrimitiveError => Error::Primitive(Box::new(PrimitiveError {
-                            grant: None,
-                            extensions: None,
-                        })),
-                    })?;
+            Requested::Authenticate {
+                client,
+                passdata,
+                auth_method,
+            } => {
+                let registrar = handler.registrar();
+                registrar.check(client, passdata).map_err(|err| match err {
+                    RegistrarError::Unspecified => Error::unauthorized("basic"),
+                    RegistrarError::PrimitiveError => Error::Primitive(Box::new(PrimitiveError {
+                        grant: None,
+                        extensions: None,
+                    })),
+                })?;
+                if registrar
+                    .auth_method(client)
+                    .is_some_and(|registered| registered != auth_method)
+                {
+                    return Err(Error::unauthorized("basic"));
+                }
+                if !registrar.permits_grant(client, GrantType::AuthorizationCode) {
+                    return Err(Error::invalid_with(AccessTokenErrorType::UnauthorizedClient));
+                }
                 Input::Authenticated
             }
             Requested::Recover(code) => {
@@ -481,19 +536,44 @@ pub fn access_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result
                 Input::Extended { access_extensions }
             }
             Requested::Issue { grant } => {
-                let token = handler.issuer().issue(grant.clone()).map_err(|_| {
+                match super::police(handler.grant_policy(), GrantEvent::Token, grant) {
+                    GrantDecision::Allow => (),
+                    GrantDecision::Deny => {
+                        return Err(Error::invalid_with(AccessTokenErrorType::InvalidGrant))
+                    }
+                    GrantDecision::Error => {
+                        return Err(Error::Primitive(Box::new(PrimitiveError::empty())))
+                    }
+                }
+
+                handler.bind(grant);
+
+                let policy = handler.registrar().refresh_policy(&grant.client_id);
+                let refreshable = policy.narrow(&mut grant.scope);
+                let mut token = handler.issuer().issue(grant.clone()).map_err(|_| {
                     Error::Primitive(Box::new(PrimitiveError {
                         // FIXME: endpoint should get and handle these.
                         grant: None,
                         extensions: None,
                     }))
                 })?;
+                if !refreshable {
+                    token.refresh = None;
+                }
                 Input::Issued(token)
             }
         };
 
         requested = match access_token.advance(input) {
-            Output::Authenticate { client, passdata } => Requested::Authenticate { client, passdata },
+            Output::Authenticate {
+                client,
+                passdata,
+                auth_method,
+            } => Requested::Authenticate {
+                client,
+                passdata,
+                auth_method,
+            },
             Output::Recover { code } => Requested::Recover(code),
             Output::Extend { extensions } => Requested::Extend { extensions },
             Output::Issue { grant } => Requested::Issue { grant },
@@ -504,10 +584,13 @@ pub fn access_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result
 }
 
 impl<'a> Credentials<'a> {
-    pub fn authenticate(&mut self, client_id: &'a str, passphrase: &'a [u8]) {
+    pub fn authenticate(
+        &mut self, client_id: &'a str, passphrase: &'a [u8], auth_method: ClientAuthMethod,
+    ) {
         self.add(Credentials::Authenticated {
             client_id,
             passphrase,
+            auth_method,
         })
     }
 
@@ -515,13 +598,16 @@ impl<'a> Credentials<'a> {
         self.add(Credentials::Unauthenticated { client_id })
     }
 
-    pub fn into_client(self) -> Option<(&'a str, Option<&'a [u8]>)> {
+    pub fn into_client(self) -> Option<(&'a str, Option<&'a [u8]>, ClientAuthMethod)> {
         match self {
             Credentials::Authenticated {
                 client_id,
                 passphrase,
-            } => Some((client_id, Some(passphrase))),
-            Credentials::Unauthenticated { client_id } => Some((client_id, None)),
+                auth_method,
+            } => Some((client_id, Some(passphrase), auth_method)),
+            Credentials::Unauthenticated { client_id } => {
+                Some((client_id, None, ClientAuthMethod::None))
+            }
             _ => None,
         }
     }
@@ -586,7 +672,33 @@ pub struct ErrorDescription {
 type Result<T> = std::result::Result<T, Error>;
 
 /// Represents an access token, a refresh token and the associated scope for serialization.
-pub struct BearerToken(pub(crate) IssuedToken, pub(crate) Scope);
+///
+/// Public extension data of the grant is published as additional members of the response, keyed
+/// by the identifier of the extension. This is how an `AccessTokenExtension` can, for example,
+/// return an `id_token` to the client. Members defined by the token response itself take
+/// precedence, and private extension data is never published.
+pub struct BearerToken(
+    pub(crate) IssuedToken,
+    pub(crate) Scope,
+    pub(crate) Parties,
+    pub(crate) Extensions,
+);
+
+/// The client and, if known, the resource owner of an issued token.
+#[derive(Clone, Debug)]
+pub(crate) struct Parties {
+    pub(crate) client_id: String,
+    pub(crate) owner_id: Option<String>,
+}
+
+impl Parties {
+    pub(crate) fn of(grant: &Grant) -> Self {
+        Parties {
+            client_id: grant.client_id.clone(),
+            owner_id: Some(grant.owner_id.clone()),
+        }
+    }
+}
 
 impl Error {
     /// Create invalid error type
@@ -596,7 +708,8 @@ impl Error {
         })
     }
 
-    pub(crate) fn invalid_with(with_type: AccessTokenErrorType) -> Self {
+    /// Create invalid error with a specific error type.
+    pub fn invalid_with(with_type: AccessTokenErrorType) -> Self {
         Error::Invalid(ErrorDescription {
             error: {
                 let mut error = AccessTokenError::default();
@@ -631,6 +744,15 @@ impl Error {
             Error::Primitive(_) => None,
         }
     }
+
+    /// The error code sent to the client, if any.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            Error::Invalid(description) => Some(description.error.code()),
+            Error::Unauthorized(description, _) => Some(description.error.code()),
+            Error::Primitive(_) => None,
+        }
+    }
 }
 
 impl PrimitiveError {
@@ -667,20 +789,44 @@ impl ErrorDescription {
 }
 
 impl BearerToken {
+    /// The client to which the token was issued.
+    pub fn client_id(&self) -> &str {
+        &self.2.client_id
+    }
+
+    /// The resource owner who authorized the grant, if known.
+    pub fn owner_id(&self) -> Option<&str> {
+        self.2.owner_id.as_deref()
+    }
+
+    /// The scope of the issued token.
+    pub fn scope(&self) -> &Scope {
+        &self.1
+    }
+
     /// Convert the token into a json string, viable for being sent over a network with
     /// `application/json` encoding.
     pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.to_response()).unwrap()
+    }
+
+    /// The response describing the token, to be encoded as json.
+    pub fn to_response(&self) -> TokenResponse {
         let remaining = self.0.until.signed_duration_since(Utc::now());
-        let token_response = TokenResponse {
+        TokenResponse {
             access_token: Some(self.0.token.clone()),
             refresh_token: self.0.refresh.clone(),
             token_type: Some("bearer".to_owned()),
             expires_in: Some(remaining.num_seconds()),
             scope: Some(self.1.to_string()),
             error: None,
-        };
-
-        serde_json::to_string(&token_response).unwrap()
+            additional: self
+                .3
+                .public()
+                .filter(|(key, _)| !TokenResponse::MEMBERS.contains(key))
+                .filter_map(|(key, value)| Some((key.to_owned(), value?.into())))
+                .collect(),
+        }
     }
 }
 
@@ -699,6 +845,11 @@ mod tests {
                 token_type: TokenType::Bearer,
             },
             "scope".parse().unwrap(),
+            Parties {
+                client_id: "client".into(),
+                owner_id: Some("owner".into()),
+            },
+            Extensions::new(),
         );
 
         let json = token.to_json();
@@ -716,6 +867,11 @@ mod tests {
         let token = BearerToken(
             IssuedToken::without_refresh("access".into(), Utc::now()),
             "scope".parse().unwrap(),
+            Parties {
+                client_id: "client".into(),
+                owner_id: Some("owner".into()),
+            },
+            Extensions::new(),
         );
 
         let json = token.to_json();
@@ -727,4 +883,34 @@ mod tests {
         assert_eq!(token.token_type, Some("bearer".to_owned()));
         assert!(token.expires_in.is_some());
     }
+
+    #[test]
+    fn publishes_public_extensions() {
+        use crate::primitives::grant::Value;
+
+        let mut extensions = Extensions::new();
+        extensions.set_raw("id_token".into(), Value::public(Some("header.claims.sig".into())));
+        extensions.set_raw("nonce".into(), Value::private(Some("secret".into())));
+        extensions.set_raw("scope".into(), Value::public(Some("overridden".into())));
+        let token = BearerToken(
+            IssuedToken::without_refresh("access".into(), Utc::now()),
+            "scope".parse().unwrap(),
+            Parties {
+                client_id: "client".into(),
+                owner_id: Some("owner".into()),
+            },
+            extensions,
+        );
+
+        let json = token.to_json();
+        let token = serde_json::from_str::<TokenResponse>(&json).unwrap();
+
+        assert_eq!(token.scope, Some("scope".to_owned()));
+        assert_eq!(
+            token.additional.get("id_token"),
+            Some(&"header.claims.sig".into())
+        );
+        assert!(!token.additional.contains_key("nonce"));
+        assert!(!json.contains("overridden"));
+    }
 }
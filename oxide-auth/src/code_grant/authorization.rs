@@ -9,7 +9,8 @@ use crate::code_grant::error::{AuthorizationError, AuthorizationErrorType};
 use crate::primitives::authorizer::Authorizer;
 use crate::primitives::registrar::{ClientUrl, ExactUrl, Registrar, RegistrarError, PreGrant};
 use crate::primitives::grant::{Extensions, Grant};
-use crate::{endpoint::Scope, endpoint::Solicitation, primitives::registrar::BoundClient};
+use crate::endpoint::{GrantDecision, GrantEvent, GrantPolicy, Scope, Solicitation};
+use crate::primitives::registrar::BoundClient;
 
 /// Interface required from a request to determine the handling in the backend.
 pub trait Request {
@@ -68,6 +69,13 @@ pub trait Endpoint {
     ///
     /// It is possible to use `&mut ()`.
     fn extension(&mut self) -> &mut dyn Extension;
+
+    /// Decides over the grant just before its code is generated.
+    ///
+    /// The default implementation has no policy and generates codes for all grants.
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        None
+    }
 }
 
 /// The result will indicate wether the authorization succeed or not.
@@ -441,16 +449,38 @@ impl Pending {
         Solicitation {
             grant: Cow::Borrowed(&self.pre_grant),
             state: self.state.as_ref().map(|s| Cow::Borrowed(&**s)),
+            extensions: Some(Cow::Borrowed(&self.extensions)),
+            parameters: None,
+            context: None,
+        }
+    }
+
+    /// Restrict the grant to the part of its scope approved by the resource owner.
+    ///
+    /// Returns `false` and leaves the grant unchanged if the scope exceeds the negotiated scope.
+    pub fn restrict(&mut self, scope: Scope) -> bool {
+        if !self.pre_grant.scope.priviledged_to(&scope) {
+            return false;
         }
+
+        self.pre_grant.scope = scope;
+        true
     }
 
     /// Denies the request, which redirects to the client for which the request originated.
     pub fn deny(self) -> Result<Url> {
-        let url = self.pre_grant.redirect_uri;
+        Err(access_denied(self.pre_grant.redirect_uri.into_url(), self.state))
+    }
+
+    /// Refuses the request with an error, which redirects to the client.
+    ///
+    /// Use this for refusals other than a denial by the owner, such as `login_required` for a
+    /// request that does not permit to interact with the owner.
+    pub fn refuse(self, kind: AuthorizationErrorType) -> Result<Url> {
         let mut error = AuthorizationError::default();
-        error.set_type(AuthorizationErrorType::AccessDenied);
-        let error = ErrorUrl::new_generic(url.into_url(), self.state, error);
-        Err(Error::Redirect(error))
+        error.set_type(kind);
+        let url = self.pre_grant.redirect_uri.into_url();
+        Err(Error::Redirect(ErrorUrl::new_generic(url, self.state, error)))
     }
 
     /// Inform the backend about consent from a resource owner.
@@ -459,17 +489,24 @@ impl Pending {
     /// same endpoint as was used to create the pending request.
     pub fn authorize(self, handler: &mut dyn Endpoint, owner_id: Cow<str>) -> Result<Url> {
         let mut url = self.pre_grant.redirect_uri.to_url();
+        let mut grant = Grant {
+            owner_id: owner_id.into_owned(),
+            client_id: self.pre_grant.client_id,
+            redirect_uri: self.pre_grant.redirect_uri.into_url(),
+            scope: self.pre_grant.scope,
+            until: Utc::now() + Duration::minutes(10),
+            extensions: self.extensions,
+        };
+
+        match super::police(handler.grant_policy(), GrantEvent::Code, &mut grant) {
+            GrantDecision::Allow => (),
+            GrantDecision::Deny => return Err(access_denied(url, self.state)),
+            GrantDecision::Error => return Err(Error::PrimitiveError),
+        }
 
         let grant = handler
             .authorizer()
-            .authorize(Grant {
-                owner_id: owner_id.into_owned(),
-                client_id: self.pre_grant.client_id,
-                redirect_uri: self.pre_grant.redirect_uri.into_url(),
-                scope: self.pre_grant.scope,
-                until: Utc::now() + Duration::minutes(10),
-                extensions: self.extensions,
-            })
+            .authorize(grant)
             .map_err(|()| Error::PrimitiveError)?;
 
         url.query_pairs_mut()
@@ -486,6 +523,12 @@ impl Pending {
     }
 }
 
+fn access_denied(url: Url, state: Option<String>) -> Error {
+    let mut error = AuthorizationError::default();
+    error.set_type(AuthorizationErrorType::AccessDenied);
+    Error::Redirect(ErrorUrl::new_generic(url, state, error))
+}
+
 /// Defines the correct treatment of the error.
 /// Not all errors are signalled to the requesting party, especially when impersonation is possible
 /// it is integral for security to resolve the error internally instead of redirecting the user
@@ -563,6 +606,14 @@ impl Error {
             Error::PrimitiveError => None,
         }
     }
+
+    /// The error code sent to the client, if any.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            Error::Redirect(inner) => Some(inner.error.code()),
+            Error::Ignore | Error::PrimitiveError => None,
+        }
+    }
 }
 
 impl From<ErrorUrl> for Url {
@@ -3,15 +3,18 @@ use std::mem;
 use std::borrow::Cow;
 
 use chrono::{Utc, Duration};
+use zeroize::Zeroizing;
 
 use crate::code_grant::accesstoken::BearerToken;
 use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
-use crate::endpoint::{Scope, Solicitation};
+use crate::endpoint::{GrantDecision, GrantEvent, GrantPolicy, Scope, Solicitation};
 use crate::primitives::issuer::Issuer;
 use crate::primitives::grant::{Extensions, Grant};
-use crate::primitives::registrar::{Registrar, RegistrarError, BoundClient, PreGrant, ClientUrl};
+use crate::primitives::registrar::{
+    Registrar, RegistrarError, BoundClient, PreGrant, ClientUrl, ClientAuthMethod, GrantType,
+};
 
-use super::accesstoken::{ErrorDescription, PrimitiveError};
+use super::accesstoken::{ErrorDescription, Parties, PrimitiveError};
 
 /// Required content of a client credentials request.
 pub trait Request {
@@ -89,6 +92,18 @@ pub trait Endpoint {
     ///
     /// It is possible to use `&mut ()`.
     fn extension(&mut self) -> &mut dyn Extension;
+
+    /// Decides over the grant just before the access token is issued.
+    ///
+    /// The default implementation has no policy and issues all grants.
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        None
+    }
+
+    /// Binds the grant to the context of the request, just before the access token is issued.
+    ///
+    /// The default implementation leaves the grant unbound.
+    fn bind(&mut self, _grant: &mut Grant) {}
 }
 
 enum Credentials<'a> {
@@ -98,6 +113,7 @@ enum Credentials<'a> {
     Authenticated {
         client_id: &'a str,
         passphrase: &'a [u8],
+        auth_method: ClientAuthMethod,
     },
     /// No password but name was offered.
     ///
@@ -143,7 +159,8 @@ pub struct ClientCredentials {
 enum ClientCredentialsState {
     Authenticate {
         client: String,
-        passdata: Vec<u8>,
+        passdata: Zeroizing<Vec<u8>>,
+        auth_method: ClientAuthMethod,
     },
     Binding {
         client_id: String,
@@ -200,6 +217,8 @@ pub enum Output<'machine> {
         client: &'machine str,
         /// The supplied passdata/password.
         passdata: &'machine [u8],
+        /// How the client authenticated.
+        auth_method: ClientAuthMethod,
     },
     /// Ask registrar to bind the client. There is no redirect URI provided from the request,
     /// so the registrar will have to pick one arbitrarily (or return an invalid one). Ths
@@ -274,9 +293,14 @@ impl ClientCredentials {
     fn output(&self) -> Output<'_> {
         match &self.state {
             ClientCredentialsState::Err(err) => Output::Err(Box::new(err.clone())),
-            ClientCredentialsState::Authenticate { client, passdata, .. } => Output::Authenticate {
+            ClientCredentialsState::Authenticate {
+                client,
+                passdata,
+                auth_method,
+            } => Output::Authenticate {
                 client,
                 passdata: passdata.as_slice(),
+                auth_method: *auth_method,
             },
             ClientCredentialsState::Binding { client_id } => Output::Binding { client_id },
             ClientCredentialsState::Extend { .. } => Output::Extend,
@@ -312,13 +336,20 @@ impl ClientCredentials {
 
         let mut credentials = Credentials::None;
         if let Some((client_id, auth)) = &authorization {
-            credentials.authenticate(client_id.as_ref(), auth.as_ref());
+            credentials.authenticate(
+                client_id.as_ref(),
+                auth.as_ref(),
+                ClientAuthMethod::ClientSecretBasic,
+            );
         }
 
         match (&client_id, &client_secret) {
-            (Some(client_id), Some(client_secret)) if request.allow_credentials_in_body() => {
-                credentials.authenticate(client_id.as_ref(), client_secret.as_ref().as_bytes())
-            }
+            (Some(client_id), Some(client_secret)) if request.allow_credentials_in_body() => credentials
+                .authenticate(
+                    client_id.as_ref(),
+                    client_secret.as_ref().as_bytes(),
+                    ClientAuthMethod::ClientSecretPost,
+                ),
             (None, None) => {}
             _ => credentials.unauthenticated(),
         }
@@ -335,12 +366,13 @@ impl ClientCredentials {
             Some(_) => return Err(Error::invalid_with(AccessTokenErrorType::UnsupportedGrantType)),
         };
 
-        let (client_id, passdata) = credentials.into_client().ok_or_else(Error::invalid)?;
+        let (client_id, passdata, auth_method) = credentials.into_client().ok_or_else(Error::invalid)?;
 
         Ok((
             ClientCredentialsState::Authenticate {
                 client: client_id.to_string(),
-                passdata: Vec::from(passdata),
+                passdata: Zeroizing::new(Vec::from(passdata)),
+                auth_method,
             },
             scope,
         ))
@@ -388,9 +420,24 @@ impl Pending {
         Solicitation {
             grant: Cow::Borrowed(&self.pre_grant),
             state: None,
+            extensions: Some(Cow::Borrowed(&self.extensions)),
+            parameters: None,
+            context: None,
         }
     }
 
+    /// Restrict the grant to the part of its scope approved by the resource owner.
+    ///
+    /// Returns `false` and leaves the grant unchanged if the scope exceeds the negotiated scope.
+    pub fn restrict(&mut self, scope: Scope) -> bool {
+        if !self.pre_grant.scope.priviledged_to(&scope) {
+            return false;
+        }
+
+        self.pre_grant.scope = scope;
+        true
+    }
+
     /// Inform the backend about consent from a resource owner.
     ///
     /// Use negotiated parameters to authorize a client for an owner. The endpoint SHOULD be the
@@ -398,23 +445,38 @@ impl Pending {
     pub fn issue(
         self, handler: &mut dyn Endpoint, owner_id: String, allow_refresh_token: bool,
     ) -> Result<BearerToken> {
+        let mut grant = Grant {
+            owner_id,
+            client_id: self.pre_grant.client_id,
+            redirect_uri: self.pre_grant.redirect_uri.into_url(),
+            scope: self.pre_grant.scope,
+            until: Utc::now() + Duration::minutes(10),
+            extensions: self.extensions,
+        };
+
+        match super::police(handler.grant_policy(), GrantEvent::Token, &mut grant) {
+            GrantDecision::Allow => (),
+            GrantDecision::Deny => return Err(Error::invalid_with(AccessTokenErrorType::InvalidGrant)),
+            GrantDecision::Error => return Err(Error::Primitive(Box::new(PrimitiveError::empty()))),
+        }
+
+        handler.bind(&mut grant);
+
+        let parties = Parties {
+            client_id: grant.client_id.clone(),
+            owner_id: Some(grant.owner_id.clone()),
+        };
+        let scope = grant.scope.clone();
         let mut token = handler
             .issuer()
-            .issue(Grant {
-                owner_id,
-                client_id: self.pre_grant.client_id,
-                redirect_uri: self.pre_grant.redirect_uri.into_url(),
-                scope: self.pre_grant.scope.clone(),
-                until: Utc::now() + Duration::minutes(10),
-                extensions: self.extensions,
-            })
+            .issue(grant)
             .map_err(|()| Error::Primitive(Box::new(PrimitiveError::empty())))?;
 
         if !allow_refresh_token {
             token.refresh = None;
         }
 
-        Ok(BearerToken(token, self.pre_grant.scope.clone()))
+        Ok(BearerToken(token, scope, parties, Extensions::new()))
     }
 }
 
@@ -425,7 +487,8 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
         None,
         Authenticate {
             client: String,
-            passdata: Vec<u8>,
+            passdata: Zeroizing<Vec<u8>>,
+            auth_method: ClientAuthMethod,
         },
         Bind {
             client_id: String,
@@ -443,9 +506,13 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
     loop {
         let input = match requested {
             Requested::None => Input::None,
-            Requested::Authenticate { client, passdata } => {
-                handler
-                    .registrar()
+            Requested::Authenticate {
+                client,
+                passdata,
+                auth_method,
+            } => {
+                let registrar = handler.registrar();
+                registrar
                     .check(&client, Some(passdata.as_slice()))
                     .map_err(|err| match err {
                         RegistrarError::Unspecified => Error::unauthorized("basic"),
@@ -454,6 +521,15 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
                             extensions: None,
                         })),
                     })?;
+                if registrar
+                    .auth_method(&client)
+                    .is_some_and(|registered| registered != auth_method)
+                {
+                    return Err(Error::unauthorized("basic"));
+                }
+                if !registrar.permits_grant(&client, GrantType::ClientCredentials) {
+                    return Err(Error::invalid_with(AccessTokenErrorType::UnauthorizedClient));
+                }
                 Input::Authenticated
             }
             Requested::Bind { client_id } => {
@@ -496,9 +572,14 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
         };
 
         requested = match client_credentials.advance(input) {
-            Output::Authenticate { client, passdata } => Requested::Authenticate {
+            Output::Authenticate {
+                client,
+                passdata,
+                auth_method,
+            } => Requested::Authenticate {
                 client: client.to_owned(),
-                passdata: passdata.to_vec(),
+                passdata: Zeroizing::new(passdata.to_vec()),
+                auth_method,
             },
             Output::Binding { client_id } => Requested::Bind {
                 client_id: client_id.to_owned(),
@@ -523,10 +604,13 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
 }
 
 impl<'a> Credentials<'a> {
-    pub fn authenticate(&mut self, client_id: &'a str, passphrase: &'a [u8]) {
+    pub fn authenticate(
+        &mut self, client_id: &'a str, passphrase: &'a [u8], auth_method: ClientAuthMethod,
+    ) {
         self.add(Credentials::Authenticated {
             client_id,
             passphrase,
+            auth_method,
         })
     }
 
@@ -534,12 +618,13 @@ impl<'a> Credentials<'a> {
         self.add(Credentials::Unauthenticated)
     }
 
-    pub fn into_client(self) -> Option<(&'a str, &'a [u8])> {
+    pub fn into_client(self) -> Option<(&'a str, &'a [u8], ClientAuthMethod)> {
         match self {
             Credentials::Authenticated {
                 client_id,
                 passphrase,
-            } => Some((client_id, passphrase)),
+                auth_method,
+            } => Some((client_id, passphrase, auth_method)),
             Credentials::Unauthenticated { .. } => None,
             _ => None,
         }
@@ -582,7 +667,8 @@ impl Error {
         })
     }
 
-    fn invalid_with(with_type: AccessTokenErrorType) -> Self {
+    /// Create invalid error with a specific error type.
+    pub fn invalid_with(with_type: AccessTokenErrorType) -> Self {
         Error::Invalid(ErrorDescription {
             error: {
                 let mut error = AccessTokenError::default();
@@ -618,4 +704,13 @@ impl Error {
             Error::Primitive(_) => None,
         }
     }
+
+    /// The error code sent to the client, if any.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            Error::Invalid(description) => Some(description.error.code()),
+            Error::Unauthorized(description, _) => Some(description.error.code()),
+            Error::Ignore | Error::Primitive(_) => None,
+        }
+    }
 }
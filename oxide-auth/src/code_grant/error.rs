@@ -35,10 +35,18 @@ pub enum AuthorizationErrorType {
     /// overloading or maintenance of the server.  (This error code is needed because a 503 Service
     /// Unavailable HTTP status code cannot be returned to the client via an HTTP redirect.)
     TemporarilyUnavailable,
+
+    /// The request asked not to interact with the resource owner, or for a recent authentication,
+    /// but the owner would need to log in.
+    LoginRequired,
+
+    /// The request asked not to interact with the resource owner, who has not yet consented to the
+    /// client.
+    ConsentRequired,
 }
 
 impl AuthorizationErrorType {
-    fn description(self) -> &'static str {
+    pub(crate) fn description(self) -> &'static str {
         match self {
             AuthorizationErrorType::InvalidRequest => "invalid_request",
             AuthorizationErrorType::UnauthorizedClient => "unauthorized_client",
@@ -47,6 +55,8 @@ impl AuthorizationErrorType {
             AuthorizationErrorType::InvalidScope => "invalid_scope",
             AuthorizationErrorType::ServerError => "server_error",
             AuthorizationErrorType::TemporarilyUnavailable => "temporarily_unavailable",
+            AuthorizationErrorType::LoginRequired => "login_required",
+            AuthorizationErrorType::ConsentRequired => "consent_required",
         }
     }
 }
@@ -59,6 +69,7 @@ pub struct AuthorizationError {
     error: AuthorizationErrorType,
     description: Option<Cow<'static, str>>,
     uri: Option<Cow<'static, str>>,
+    members: Vec<(&'static str, Cow<'static, str>)>,
 }
 
 impl AuthorizationError {
@@ -68,6 +79,7 @@ impl AuthorizationError {
             error,
             description: None,
             uri: None,
+            members: Vec::new(),
         }
     }
 
@@ -93,6 +105,24 @@ impl AuthorizationError {
         self.uri = Some(String::from(uri).into())
     }
 
+    /// The short text explanation of the error, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Add a member to the error, for example a support or trace identifier.
+    ///
+    /// Setting a member again replaces its value. Members of the standard error response can not
+    /// be set this way, in which case this returns `false` and the error is left unchanged.
+    pub fn add_member<V: Into<Cow<'static, str>>>(&mut self, key: &'static str, value: V) -> bool {
+        add_member(&mut self.members, &AUTHORIZATION_MEMBERS, key, value.into())
+    }
+
+    /// The error code, as sent to the client.
+    pub(crate) fn code(&self) -> &'static str {
+        self.error.description()
+    }
+
     /// Iterate over the key value pairs that describe this error.
     ///
     /// These pairs must be added to the detailed description of an error. To this end the pairs
@@ -136,6 +166,14 @@ pub enum AccessTokenErrorType {
     /// The requested scope is invalid, unknown, malformed, or exceeds the scope granted by the
     /// resource owner.
     InvalidScope,
+
+    /// The client is polling or requesting too frequently and should slow down, as defined for
+    /// the device authorization grant in RFC 8628.
+    SlowDown,
+
+    /// The DPoP proof did not contain a nonce provided by the server, as defined in RFC 9449. The
+    /// response carries the nonce to retry with.
+    UseDpopNonce,
 }
 
 impl AccessTokenErrorType {
@@ -147,6 +185,8 @@ impl AccessTokenErrorType {
             AccessTokenErrorType::UnauthorizedClient => "unauthorized_client",
             AccessTokenErrorType::UnsupportedGrantType => "unsupported_grant_type",
             AccessTokenErrorType::InvalidScope => "invalid_scope",
+            AccessTokenErrorType::SlowDown => "slow_down",
+            AccessTokenErrorType::UseDpopNonce => "use_dpop_nonce",
         }
     }
 }
@@ -163,6 +203,7 @@ pub struct AccessTokenError {
     error: AccessTokenErrorType,
     description: Option<Cow<'static, str>>,
     uri: Option<Cow<'static, str>>,
+    members: Vec<(&'static str, Cow<'static, str>)>,
 }
 
 impl AccessTokenError {
@@ -171,6 +212,7 @@ impl AccessTokenError {
             error,
             description: None,
             uri: None,
+            members: Vec::new(),
         }
     }
 
@@ -196,6 +238,24 @@ impl AccessTokenError {
         self.uri = Some(String::from(uri).into())
     }
 
+    /// The short text explanation of the error, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Add a member to the error, for example a support or trace identifier.
+    ///
+    /// Setting a member again replaces its value. Members of the standard error response can not
+    /// be set this way, in which case this returns `false` and the error is left unchanged.
+    pub fn add_member<V: Into<Cow<'static, str>>>(&mut self, key: &'static str, value: V) -> bool {
+        add_member(&mut self.members, &ACCESS_TOKEN_MEMBERS, key, value.into())
+    }
+
+    /// The error code, as sent to the client.
+    pub(crate) fn code(&self) -> &'static str {
+        self.error.description()
+    }
+
     /// Iterate over the key value pairs that describe this error.
     ///
     /// These pairs must be added to the detailed description of an error. The pairs will be
@@ -205,6 +265,25 @@ impl AccessTokenError {
     }
 }
 
+/// Members of an authorization error, including the `state` appended to the redirect.
+const AUTHORIZATION_MEMBERS: [&str; 4] = ["error", "error_description", "error_uri", "state"];
+
+/// Members of an access token error response.
+const ACCESS_TOKEN_MEMBERS: [&str; 3] = ["error", "error_description", "error_uri"];
+
+fn add_member(
+    members: &mut Vec<(&'static str, Cow<'static, str>)>, reserved: &[&str], key: &'static str,
+    value: Cow<'static, str>,
+) -> bool {
+    if reserved.contains(&key) {
+        return false;
+    }
+
+    members.retain(|(existing, _)| *existing != key);
+    members.push((key, value));
+    true
+}
+
 impl Default for AuthorizationError {
     /// Construct a `AuthorizationError` with no extra information.
     ///
@@ -215,6 +294,7 @@ impl Default for AuthorizationError {
             error: AuthorizationErrorType::InvalidRequest,
             description: None,
             uri: None,
+            members: Vec::new(),
         }
     }
 }
@@ -241,6 +321,7 @@ impl Default for AccessTokenError {
             error: AccessTokenErrorType::InvalidRequest,
             description: None,
             uri: None,
+            members: Vec::new(),
         }
     }
 }
@@ -265,11 +346,12 @@ impl IntoIterator for AuthorizationError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = self.description {
-            vec.push(("description", description));
+            vec.push(("error_description", description));
         }
         if let Some(uri) = self.uri {
-            vec.push(("uri", uri));
+            vec.push(("error_uri", uri));
         }
+        vec.extend(self.members);
         vec.into_iter()
     }
 }
@@ -281,11 +363,12 @@ impl IntoIterator for &'_ AuthorizationError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = &self.description {
-            vec.push(("description", description.clone()));
+            vec.push(("error_description", description.clone()));
         }
         if let Some(uri) = &self.uri {
-            vec.push(("uri", uri.clone()));
+            vec.push(("error_uri", uri.clone()));
         }
+        vec.extend(self.members.iter().cloned());
         vec.into_iter()
     }
 }
@@ -298,11 +381,12 @@ impl IntoIterator for AccessTokenError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = self.description {
-            vec.push(("description", description));
+            vec.push(("error_description", description));
         }
         if let Some(uri) = self.uri {
-            vec.push(("uri", uri));
+            vec.push(("error_uri", uri));
         }
+        vec.extend(self.members);
         vec.into_iter()
     }
 }
@@ -314,11 +398,12 @@ impl IntoIterator for &'_ AccessTokenError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = &self.description {
-            vec.push(("description", description.clone()));
+            vec.push(("error_description", description.clone()));
         }
         if let Some(uri) = &self.uri {
-            vec.push(("uri", uri.clone()));
+            vec.push(("error_uri", uri.clone()));
         }
+        vec.extend(self.members.iter().cloned());
         vec.into_iter()
     }
 }
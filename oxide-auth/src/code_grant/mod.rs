@@ -27,10 +27,43 @@
 //! [`endpoint`]: ../endpoint/index.html
 //! [`Endpoint`]: ../endpoint/trait.Endpoint.html
 
+#[cfg(feature = "std")]
 pub mod accesstoken;
+#[cfg(feature = "std")]
 pub mod authorization;
+#[cfg(feature = "std")]
 pub mod client_credentials;
+#[cfg(feature = "std")]
 pub mod error;
+#[cfg(feature = "std")]
 pub mod extensions;
+#[cfg(feature = "std")]
 pub mod refresh;
 pub mod resource;
+
+#[cfg(feature = "std")]
+use crate::endpoint::{GrantDecision, GrantEvent, GrantPolicy};
+#[cfg(feature = "std")]
+use crate::primitives::grant::Grant;
+
+/// Check if the header is an authorization method
+pub fn is_authorization_method<'h>(header: &'h str, method: &'static str) -> Option<&'h str> {
+    let header_method = header.get(..method.len())?;
+    if header_method.eq_ignore_ascii_case(method) {
+        Some(&header[method.len()..])
+    } else {
+        None
+    }
+}
+
+/// Consult the grant policy of an endpoint, if any, over a grant about to be issued.
+#[cfg(feature = "std")]
+fn police(policy: Option<&mut dyn GrantPolicy>, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+    match policy {
+        Some(policy) => {
+            let original = grant.clone();
+            policy.decide(event, grant).check(&original, grant)
+        }
+        None => GrantDecision::Allow,
+    }
+}
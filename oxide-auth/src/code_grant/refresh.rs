@@ -3,14 +3,17 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 
 use chrono::{Duration, Utc};
+use zeroize::Zeroizing;
 
 use crate::code_grant::{
-    accesstoken::TokenResponse,
+    accesstoken::{Parties, TokenResponse},
     error::{AccessTokenError, AccessTokenErrorType},
 };
+use crate::endpoint::{GrantDecision, GrantEvent, GrantPolicy};
 use crate::primitives::grant::Grant;
 use crate::primitives::issuer::{RefreshedToken, Issuer};
-use crate::primitives::registrar::{Registrar, RegistrarError};
+use crate::primitives::registrar::{ClientAuthMethod, GrantType, Registrar, RegistrarError};
+use crate::primitives::scope::Scope;
 
 /// Required content of a refresh request.
 ///
@@ -57,11 +60,25 @@ pub trait Endpoint {
 
     /// Recover and test the provided refresh token then issue new tokens.
     fn issuer(&mut self) -> &mut dyn Issuer;
+
+    /// Decides over the grant just before the refreshed token is issued.
+    ///
+    /// The default implementation has no policy and refreshes all grants.
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        None
+    }
+
+    /// Binds the grant to the context of the request, just before the refreshed token is issued.
+    ///
+    /// The default implementation leaves the grant unbound.
+    fn bind(&mut self, _grant: &mut Grant) {}
 }
 
 /// Represents a bearer token, optional refresh token and the associated scope for serialization.
+///
+/// Also remembers the scope the client explicitly requested, if any.
 #[derive(Debug)]
-pub struct BearerToken(RefreshedToken, String);
+pub struct BearerToken(RefreshedToken, Scope, Parties, Option<Scope>);
 
 /// An ongoing refresh request.
 ///
@@ -99,7 +116,7 @@ enum RefreshState {
     /// Next, the registrar must verify the authentication (authorization header).
     Authenticating {
         client: String,
-        passdata: Option<Vec<u8>>,
+        passdata: Option<Zeroizing<Vec<u8>>>,
         token: String,
     },
     /// State after authorization has passed, waiting on recovering the refresh token.
@@ -122,6 +139,8 @@ enum RefreshState {
         grant: Box<Grant>,
         /// The refresh token of the grant.
         token: String,
+        /// The scope explicitly requested by the client.
+        requested: Option<Scope>,
     },
     /// State after an error occurred.
     Err(Error),
@@ -177,12 +196,13 @@ pub enum Output<'a> {
     },
     /// The issuer should issue a refreshed code grant token.
     ///
-    /// Fulfilled by `Input::Refreshed`.
+    /// Fulfilled by `Input::Refreshed`. A grant policy may change the grant before, the response
+    /// then reports the changed grant.
     Refresh {
         /// The refresh token that has been used.
         token: &'a str,
         /// The grant that should be issued as determined.
-        grant: Box<Grant>,
+        grant: &'a mut Grant,
     },
     /// The state machine finished and a new bearer token was generated.
     ///
@@ -265,10 +285,10 @@ impl Refresh {
                 self.state = co_authenticated(scope, grant, token).unwrap_or_else(RefreshState::Err);
                 self.output()
             }
-            (RefreshState::Issuing { grant, token: _ }, Input::Refreshed(token)) => {
+            (RefreshState::Issuing { grant, requested, .. }, Input::Refreshed(token)) => {
                 // Ensure that this result is not duplicated.
                 self.state = RefreshState::Err(Error::Primitive);
-                Output::Ok(issued(grant, token))
+                Output::Ok(issued(grant, token, requested))
             }
             (current, Input::None) => {
                 match current {
@@ -291,8 +311,8 @@ impl Refresh {
         core::mem::replace(&mut self.state, RefreshState::Err(Error::Primitive))
     }
 
-    fn output(&self) -> Output<'_> {
-        match &self.state {
+    fn output(&mut self) -> Output<'_> {
+        match &mut self.state {
             RefreshState::Authenticating { client, passdata, .. } => Output::Unauthenticated {
                 client,
                 pass: passdata.as_ref().map(|vec| vec.as_slice()),
@@ -302,10 +322,7 @@ impl Refresh {
                 pass: None,
             },
             RefreshState::Recovering { token, .. } => Output::RecoverRefresh { token },
-            RefreshState::Issuing { token, grant, .. } => Output::Refresh {
-                token,
-                grant: grant.clone(),
-            },
+            RefreshState::Issuing { token, grant, .. } => Output::Refresh { token, grant },
             RefreshState::Err(error) => Output::Err(error.clone()),
         }
     }
@@ -331,9 +348,17 @@ impl<'req> Input<'req> {
 pub fn refresh(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<BearerToken> {
     enum Requested {
         None,
-        Refresh { token: String, grant: Box<Grant> },
-        RecoverRefresh { token: String },
-        Authenticate { client: String, pass: Option<Vec<u8>> },
+        Refresh {
+            token: String,
+            grant: Box<Grant>,
+        },
+        RecoverRefresh {
+            token: String,
+        },
+        Authenticate {
+            client: String,
+            pass: Option<Zeroizing<Vec<u8>>>,
+        },
     }
     let mut refresh = Refresh::new(request);
     let mut requested = Requested::None;
@@ -358,14 +383,27 @@ pub fn refresh(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<Bear
                 }
             }
             Requested::Authenticate { client, pass } => {
-                let _: () =
-                    handler
-                        .registrar()
-                        .check(&client, pass.as_deref())
-                        .map_err(|err| match err {
-                            RegistrarError::PrimitiveError => Error::Primitive,
-                            RegistrarError::Unspecified => Error::unauthorized("basic"),
-                        })?;
+                let registrar = handler.registrar();
+                let _: () = registrar
+                    .check(&client, pass.as_ref().map(|pass| pass.as_slice()))
+                    .map_err(|err| match err {
+                        RegistrarError::PrimitiveError => Error::Primitive,
+                        RegistrarError::Unspecified => Error::unauthorized("basic"),
+                    })?;
+                // Refresh requests only authenticate with the authorization header.
+                let auth_method = match pass {
+                    Some(_) => ClientAuthMethod::ClientSecretBasic,
+                    None => ClientAuthMethod::None,
+                };
+                if registrar
+                    .auth_method(&client)
+                    .is_some_and(|registered| registered != auth_method)
+                {
+                    return Err(Error::unauthorized("basic"));
+                }
+                if !registrar.permits_grant(&client, GrantType::RefreshToken) {
+                    return Err(Error::invalid(AccessTokenErrorType::UnauthorizedClient));
+                }
                 Input::Authenticated {
                     scope: request.scope(),
                 }
@@ -375,16 +413,28 @@ pub fn refresh(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<Bear
         requested = match refresh.advance(input) {
             Output::Err(error) => return Err(error),
             Output::Ok(token) => return Ok(token),
-            Output::Refresh { token, grant } => Requested::Refresh {
-                token: token.to_string(),
-                grant,
-            },
+            Output::Refresh { token, grant } => {
+                match super::police(handler.grant_policy(), GrantEvent::Refresh, grant) {
+                    GrantDecision::Allow => (),
+                    GrantDecision::Deny => {
+                        return Err(Error::invalid(AccessTokenErrorType::InvalidGrant))
+                    }
+                    GrantDecision::Error => return Err(Error::Primitive),
+                }
+
+                handler.bind(grant);
+
+                Requested::Refresh {
+                    token: token.to_string(),
+                    grant: Box::new(grant.clone()),
+                }
+            }
             Output::RecoverRefresh { token } => Requested::RecoverRefresh {
                 token: token.to_string(),
             },
             Output::Unauthenticated { client, pass } => Requested::Authenticate {
                 client: client.to_string(),
-                pass: pass.map(|p| p.to_vec()),
+                pass: pass.map(|p| Zeroizing::new(p.to_vec())),
             },
         };
     }
@@ -409,7 +459,7 @@ fn initialize(request: &dyn Request) -> Result<RefreshState> {
     match request.authorization() {
         Some((client, passdata)) => Ok(RefreshState::Authenticating {
             client: client.into_owned(),
-            passdata: Some(passdata.to_vec()),
+            passdata: Some(Zeroizing::new(passdata.to_vec())),
             token: token.into_owned(),
         }),
         None => Ok(RefreshState::Recovering {
@@ -475,14 +525,22 @@ fn validate(scope: Option<Cow<str>>, grant: Box<Grant>, token: String) -> Result
         None => None,
     };
 
-    let scope = match scope {
+    let requested = scope;
+    let scope = match &requested {
         Some(scope) => {
             // ... MUST NOT include any scope not originally granted.
-            if !grant.scope.priviledged_to(&scope) {
+            if !grant.scope.priviledged_to(scope) {
                 // ... or exceeds the scope grant (Section 5.2)
-                return Err(Error::invalid(AccessTokenErrorType::InvalidScope));
+                let mut error = Error::invalid(AccessTokenErrorType::InvalidScope);
+                if let Some(description) = error.description() {
+                    description.explain(format!(
+                        "The requested scope was not originally granted: {}",
+                        scope.difference(&grant.scope)
+                    ));
+                }
+                return Err(error);
             }
-            scope
+            scope.clone()
         }
         // ... if omitted is treated as equal to the scope originally granted
         None => grant.scope.clone(),
@@ -493,15 +551,20 @@ fn validate(scope: Option<Cow<str>>, grant: Box<Grant>, token: String) -> Result
     grant.scope = scope;
     grant.until = Utc::now() + Duration::hours(1);
 
-    Ok(RefreshState::Issuing { grant, token })
+    Ok(RefreshState::Issuing {
+        grant,
+        token,
+        requested,
+    })
 }
 
-fn issued(grant: Box<Grant>, token: RefreshedToken) -> BearerToken {
-    BearerToken(token, grant.scope.to_string())
+fn issued(grant: Box<Grant>, token: RefreshedToken, requested: Option<Scope>) -> BearerToken {
+    BearerToken(token, grant.scope.clone(), Parties::of(&grant), requested)
 }
 
 impl Error {
-    fn invalid(kind: AccessTokenErrorType) -> Self {
+    /// Create invalid error with a specific error type.
+    pub fn invalid(kind: AccessTokenErrorType) -> Self {
         Error::Invalid(ErrorDescription {
             error: AccessTokenError::new(kind),
         })
@@ -529,6 +592,15 @@ impl Error {
             Error::Primitive => None,
         }
     }
+
+    /// The error code sent to the client, if any.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            Error::Invalid(description) => Some(description.error.code()),
+            Error::Unauthorized(description, _) => Some(description.error.code()),
+            Error::Primitive => None,
+        }
+    }
 }
 
 impl ErrorDescription {
@@ -551,19 +623,55 @@ impl ErrorDescription {
 }
 
 impl BearerToken {
+    /// The client to which the token was issued.
+    pub fn client_id(&self) -> &str {
+        &self.2.client_id
+    }
+
+    /// The resource owner who authorized the original grant.
+    pub fn owner_id(&self) -> Option<&str> {
+        self.2.owner_id.as_deref()
+    }
+
+    /// The scope of the refreshed token.
+    ///
+    /// This is the scope requested by the client or, if it did not request one, the scope
+    /// originally granted, as further narrowed by a grant policy.
+    pub fn scope(&self) -> &Scope {
+        &self.1
+    }
+
+    /// Whether the token has exactly the scope that the client explicitly requested.
+    ///
+    /// The response may then omit the `scope`, as the client already knows it (Section 5.1).
+    pub fn scope_as_requested(&self) -> bool {
+        self.3.as_ref() == Some(&self.1)
+    }
+
     /// Convert the token into a json string, viable for being sent over a network with
     /// `application/json` encoding.
     pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.to_response()).unwrap()
+    }
+
+    /// The response describing the token, to be encoded as json.
+    ///
+    /// The `scope` is omitted when it is exactly the scope the client requested.
+    pub fn to_response(&self) -> TokenResponse {
         let remaining = self.0.until.signed_duration_since(Utc::now());
-        let token_response = TokenResponse {
+        let scope = if self.scope_as_requested() {
+            None
+        } else {
+            Some(self.1.to_string())
+        };
+        TokenResponse {
             access_token: Some(self.0.token.clone()),
             refresh_token: self.0.refresh.clone(),
             token_type: Some("bearer".to_owned()),
             expires_in: Some(remaining.num_seconds()),
-            scope: Some(self.1.clone()),
+            scope,
             error: None,
-        };
-
-        serde_json::to_string(&token_response).unwrap()
+            additional: HashMap::new(),
+        }
     }
 }
@@ -1,12 +1,19 @@
 //! Provides the handling for Resource Requests.
-use std::{fmt, mem};
-use std::borrow::Cow;
-
-use chrono::Utc;
-
+use core::{fmt, mem};
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use chrono::{DateTime, Utc};
+
+use crate::code_grant::is_authorization_method;
+#[cfg(feature = "std")]
 use crate::primitives::issuer::Issuer;
 use crate::primitives::grant::Grant;
-use crate::primitives::scope::Scope;
+use crate::primitives::scope::{Scope, ScopeMatching};
 
 /// Gives additional information about the reason for an access failure.
 ///
@@ -43,6 +50,45 @@ pub struct Authenticate {
     pub scope: Option<Scope>,
 }
 
+/// A bearer challenge of a `WWW-Authenticate` header, as defined in [rfc6750].
+///
+/// Constructed from a resource `Error` with `Error::challenge`, and rendered into the value of the
+/// header with its `Display` implementation. Challenges with an `insufficient_scope` error are
+/// answered with status `403 Forbidden`, all others with `401 Unauthorized`.
+///
+/// [rfc6750]: https://tools.ietf.org/html/rfc6750#section-3
+#[derive(Clone, Debug, Default)]
+pub struct Challenge {
+    /// The protection space of the resource.
+    pub realm: Option<String>,
+
+    /// The scope necessary to access the resource.
+    pub scope: Option<Scope>,
+
+    /// The reason for denying access.
+    pub error: Option<ErrorCode>,
+
+    /// A human readable explanation of the error.
+    pub error_description: Option<String>,
+}
+
+/// The locations from which the access token of a request is accepted.
+///
+/// The `Authorization` header is always accepted. The `access_token` parameter of a form encoded
+/// body, as described in [rfc6750], is accepted by default. The `access_token` query parameter is
+/// off by default, as the token then leaks into logs and browser histories. Requests with a token in
+/// a location that is not accepted, or with tokens in more than one location, are malformed.
+///
+/// [rfc6750]: https://tools.ietf.org/html/rfc6750#section-2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenLocations {
+    /// Accept the `access_token` parameter of a form encoded body.
+    pub form: bool,
+
+    /// Accept the `access_token` query parameter.
+    pub query: bool,
+}
+
 /// An error signalling the resource access was not permitted.
 #[derive(Clone, Debug)]
 pub enum Error {
@@ -73,7 +119,7 @@ pub enum Error {
 
 const BEARER_START: &str = "Bearer ";
 
-type Result<T> = std::result::Result<T, Error>;
+type Result<T> = core::result::Result<T, Error>;
 
 /// Required request methods for deciding on the rights to access a protected resource.
 pub trait Request {
@@ -96,17 +142,27 @@ pub trait Request {
 /// Each method will only be invoked exactly once when processing a correct and authorized request,
 /// and potentially less than once when the request is faulty.  These methods should be implemented
 /// by internally using `primitives`, as it is implemented in the `frontend` module.
+#[cfg(feature = "std")]
 pub trait Endpoint {
     /// The list of possible scopes required by the resource endpoint.
     fn scopes(&mut self) -> &[Scope];
 
     /// Issuer which provides the tokens used for authorization by the client.
     fn issuer(&mut self) -> &dyn Issuer;
+
+    /// How the scope of a grant is matched against the scopes of the resource.
+    ///
+    /// Returns `ScopeMatching::Exact` by default.
+    fn scope_matching(&mut self) -> ScopeMatching {
+        ScopeMatching::Exact
+    }
 }
 
 /// The result will indicate whether the resource access should be allowed or not.
 pub struct Resource {
     state: ResourceState,
+    matching: ScopeMatching,
+    now: DateTime<Utc>,
 }
 
 enum ResourceState {
@@ -175,9 +231,25 @@ pub enum Output<'machine> {
 
 impl Resource {
     /// Create a Resource state machine at `ResourceState::New` state
+    #[cfg(feature = "std")]
     pub fn new() -> Self {
+        Resource::with_matching(ScopeMatching::Exact)
+    }
+
+    /// Create a Resource state machine matching scopes in the given mode.
+    #[cfg(feature = "std")]
+    pub fn with_matching(matching: ScopeMatching) -> Self {
+        Resource::at(matching, Utc::now())
+    }
+
+    /// Create a Resource state machine judging the expiry of grants at the given time.
+    ///
+    /// This is the only constructor without the `std` feature, where there is no system clock.
+    pub fn at(matching: ScopeMatching, now: DateTime<Utc>) -> Self {
         Resource {
             state: ResourceState::New,
+            matching,
+            now,
         }
     }
 
@@ -190,7 +262,7 @@ impl Resource {
             }
             (ResourceState::Internalized { token }, Input::Scopes(scopes)) => get_scopes(token, scopes),
             (ResourceState::Recovering { token: _, scopes }, Input::Recovered(grant)) => {
-                match recovered(grant, scopes) {
+                match recovered(grant, scopes, self.matching, self.now) {
                     Ok(grant) => return Output::Ok(Box::new(grant)),
                     Err(err) => ResourceState::Err(err),
                 }
@@ -215,6 +287,7 @@ impl Resource {
     }
 }
 
+#[cfg(feature = "std")]
 impl Default for Resource {
     fn default() -> Self {
         Self::new()
@@ -222,6 +295,7 @@ impl Default for Resource {
 }
 
 /// Do needed verification before granting access to the resource
+#[cfg(feature = "std")]
 pub fn protect(handler: &mut dyn Endpoint, req: &dyn Request) -> Result<Grant> {
     enum Requested {
         None,
@@ -230,7 +304,7 @@ pub fn protect(handler: &mut dyn Endpoint, req: &dyn Request) -> Result<Grant> {
         Grant(String),
     }
 
-    let mut resource = Resource::new();
+    let mut resource = Resource::with_matching(handler.scope_matching());
     let mut requested = Requested::None;
     loop {
         let input = match requested {
@@ -272,21 +346,15 @@ fn validate(request: &'_ dyn Request) -> Result<ResourceState> {
         }
     };
 
-    if !client_token
-        .to_uppercase()
-        .starts_with(&BEARER_START.to_uppercase())
-    {
-        return Err(Error::InvalidRequest {
+    // Compare the scheme on bytes, changing the case of arbitrary text can change its length.
+    match is_authorization_method(&client_token, BEARER_START) {
+        Some(token) => Ok(ResourceState::Internalized {
+            token: token.to_string(),
+        }),
+        None => Err(Error::InvalidRequest {
             authenticate: Authenticate::empty(),
-        });
+        }),
     }
-
-    let token = match client_token {
-        Cow::Borrowed(token) => token[BEARER_START.len()..].to_string(),
-        Cow::Owned(mut token) => token.split_off(BEARER_START.len()),
-    };
-
-    Ok(ResourceState::Internalized { token })
 }
 
 fn get_scopes(token: String, scopes: &'_ [Scope]) -> ResourceState {
@@ -296,13 +364,15 @@ fn get_scopes(token: String, scopes: &'_ [Scope]) -> ResourceState {
     }
 }
 
-fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>) -> Result<Grant> {
+fn recovered(
+    grant: Option<Grant>, mut scopes: Vec<Scope>, matching: ScopeMatching, now: DateTime<Utc>,
+) -> Result<Grant> {
     let grant = match grant {
         Some(grant) => grant,
         None => {
             return Err(Error::AccessDenied {
                 failure: AccessFailure {
-                    code: Some(ErrorCode::InvalidRequest),
+                    code: Some(ErrorCode::InvalidToken),
                 },
                 authenticate: Authenticate {
                     realm: None,
@@ -313,7 +383,7 @@ fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>) -> Result<Grant> {
         }
     };
 
-    if grant.until < Utc::now() {
+    if grant.until < now {
         return Err(Error::AccessDenied {
             failure: AccessFailure {
                 code: Some(ErrorCode::InvalidToken),
@@ -324,7 +394,7 @@ fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>) -> Result<Grant> {
 
     let allowing = scopes
         .iter()
-        .find(|resource_scope| resource_scope.allow_access(&grant.scope));
+        .find(|resource_scope| resource_scope.allow_access_with(&grant.scope, matching));
 
     if allowing.is_none() {
         return Err(Error::AccessDenied {
@@ -343,45 +413,124 @@ fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>) -> Result<Grant> {
 }
 
 impl ErrorCode {
-    fn description(self) -> &'static str {
+    /// The standard error code of the failure.
+    pub fn description(self) -> &'static str {
         match self {
             ErrorCode::InvalidRequest => "invalid_request",
             ErrorCode::InsufficientScope => "insufficient_scope",
             ErrorCode::InvalidToken => "invalid_token",
         }
     }
+
+    /// A default human readable explanation of the failure.
+    fn explanation(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "The request is malformed",
+            ErrorCode::InsufficientScope => "The access token does not grant the required scope",
+            ErrorCode::InvalidToken => "The access token is unknown, revoked or expired",
+        }
+    }
 }
 
-struct BearerHeader {
-    content: String,
-    first_option: bool,
+impl Challenge {
+    /// Set the protection space of the resource.
+    pub fn with_realm(self, realm: &str) -> Self {
+        Challenge {
+            realm: Some(realm.to_owned()),
+            ..self
+        }
+    }
+
+    /// Do not reveal the scope necessary to access the resource.
+    pub fn without_scope(self) -> Self {
+        Challenge { scope: None, ..self }
+    }
+
+    /// Whether the client should be answered with `403 Forbidden`.
+    ///
+    /// This is the case when the token is valid but lacks the necessary scope. Re-authenticating
+    /// with the same grant would not help the client.
+    pub fn is_forbidden(&self) -> bool {
+        matches!(self.error, Some(ErrorCode::InsufficientScope))
+    }
 }
 
-impl BearerHeader {
-    fn new() -> Self {
-        BearerHeader {
-            content: "Bearer".to_string(),
-            first_option: true,
+impl fmt::Display for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params = [
+            ("realm", self.realm.clone()),
+            ("scope", self.scope.as_ref().map(Scope::to_string)),
+            ("error", self.error.map(|code| code.description().to_owned())),
+            ("error_description", self.error_description.clone()),
+        ];
+
+        f.write_str("Bearer")?;
+        let mut separator = " ";
+        for (key, value) in params.iter() {
+            if let Some(value) = value {
+                write!(f, "{}{}=\"", separator, key)?;
+                // Values are quoted strings, which escape quotes and backslashes.
+                for ch in value.chars() {
+                    match ch {
+                        '"' | '\\' => write!(f, "\\{}", ch)?,
+                        _ => write!(f, "{}", ch)?,
+                    }
+                }
+                f.write_str("\"")?;
+                separator = ", ";
+            }
         }
+
+        Ok(())
     }
+}
 
-    fn add_option(&mut self, args: fmt::Arguments) {
-        if self.first_option {
-            self.content.push(' ');
-        } else {
-            self.content.push(',');
+impl TokenLocations {
+    /// Select the token of a request from all locations in which one was found.
+    ///
+    /// The header is the complete value of the `Authorization` header while the others are the
+    /// values of the `access_token` parameters. Returns the token in the form of an `Authorization`
+    /// header, as expected by `Request::token`, or a description of why the request is malformed.
+    pub fn select(
+        &self, header: Option<String>, form: Option<String>, query: Option<String>,
+    ) -> core::result::Result<Option<String>, String> {
+        let found = [header.is_some(), form.is_some(), query.is_some()];
+        if found.iter().filter(|found| **found).count() > 1 {
+            return Err("The access token must be sent in exactly one location".to_owned());
+        }
+
+        if (form.is_some() && !self.form) || (query.is_some() && !self.query) {
+            return Err(self.description());
         }
-        fmt::write(&mut self.content, args).unwrap();
+
+        Ok(header.or_else(|| form.or(query).map(|token| format!("{}{}", BEARER_START, token))))
     }
 
-    fn add_kvp(&mut self, key: &'static str, value: Option<impl fmt::Display>) {
-        if let Some(value) = value {
-            self.add_option(format_args!("{}=\"{}\"", key, value));
+    /// Explain where clients should send their access token.
+    pub fn description(&self) -> String {
+        let mut accepted = vec!["the Authorization header"];
+        if self.form {
+            accepted.push("the access_token form parameter");
+        }
+        if self.query {
+            accepted.push("the access_token query parameter");
+        }
+
+        let last = accepted.pop().unwrap();
+        if accepted.is_empty() {
+            format!("Send the access token in {}", last)
+        } else {
+            format!("Send the access token in {} or {}", accepted.join(", "), last)
         }
     }
+}
 
-    fn finalize(self) -> String {
-        self.content
+impl Default for TokenLocations {
+    fn default() -> Self {
+        TokenLocations {
+            form: true,
+            query: false,
+        }
     }
 }
 
@@ -392,39 +541,142 @@ impl Authenticate {
             scope: None,
         }
     }
-
-    fn extend_header(self, header: &mut BearerHeader) {
-        header.add_kvp("realm", self.realm);
-        header.add_kvp("scope", self.scope);
-    }
-}
-
-impl AccessFailure {
-    fn extend_header(self, header: &mut BearerHeader) {
-        header.add_kvp("error", self.code.map(ErrorCode::description));
-    }
 }
 
 impl Error {
-    /// Convert the guard error into the content used in an WWW-Authenticate header.
-    pub fn www_authenticate(self) -> String {
-        let mut header = BearerHeader::new();
+    /// The error code sent to the client in the WWW-Authenticate header, if any.
+    pub fn code(&self) -> Option<&'static str> {
         match self {
+            Error::AccessDenied { failure, .. } => failure.code.map(ErrorCode::description),
+            Error::InvalidRequest { .. } => Some(ErrorCode::InvalidRequest.description()),
+            _ => None,
+        }
+    }
+
+    /// The challenge to send to the client, or `None` for a primitive error.
+    ///
+    /// Requests without any authentication receive a challenge without an error, as recommended
+    /// by [rfc6750].
+    ///
+    /// [rfc6750]: https://tools.ietf.org/html/rfc6750#section-3.1
+    pub fn challenge(&self) -> Option<Challenge> {
+        let (code, authenticate) = match self {
             Error::AccessDenied {
                 failure,
                 authenticate,
-            } => {
-                failure.extend_header(&mut header);
-                authenticate.extend_header(&mut header);
-            }
-            Error::NoAuthentication { authenticate } => {
-                authenticate.extend_header(&mut header);
-            }
-            Error::InvalidRequest { authenticate } => {
-                authenticate.extend_header(&mut header);
-            }
-            Error::PrimitiveError => (),
+            } => (failure.code, authenticate),
+            Error::NoAuthentication { authenticate } => (None, authenticate),
+            Error::InvalidRequest { authenticate } => (Some(ErrorCode::InvalidRequest), authenticate),
+            Error::PrimitiveError => return None,
+        };
+
+        Some(Challenge {
+            realm: authenticate.realm.clone(),
+            scope: authenticate.scope.clone(),
+            error: code,
+            error_description: code.map(|code| code.explanation().to_owned()),
+        })
+    }
+
+    /// Convert the guard error into the content used in an WWW-Authenticate header.
+    pub fn www_authenticate(self) -> String {
+        match self.challenge() {
+            Some(challenge) => challenge.to_string(),
+            None => "Bearer".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_header() {
+        let challenge = Challenge {
+            scope: Some("email".parse().unwrap()),
+            error: Some(ErrorCode::InsufficientScope),
+            error_description: Some("Ask for \"email\"".to_owned()),
+            ..Challenge::default()
+        }
+        .with_realm("example");
+
+        assert!(challenge.is_forbidden());
+        assert_eq!(
+            challenge.to_string(),
+            "Bearer realm=\"example\", scope=\"email\", error=\"insufficient_scope\", \
+             error_description=\"Ask for \\\"email\\\"\""
+        );
+        assert_eq!(challenge.without_scope().scope, None);
+        assert_eq!(Challenge::default().to_string(), "Bearer");
+    }
+
+    #[test]
+    fn challenge_without_authentication() {
+        let error = Error::NoAuthentication {
+            authenticate: Authenticate::empty(),
+        };
+        let challenge = error.challenge().unwrap();
+        assert!(challenge.error.is_none());
+        assert!(!challenge.is_forbidden());
+        assert!(Error::PrimitiveError.challenge().is_none());
+    }
+
+    struct Header(&'static str);
+
+    impl Request for Header {
+        fn valid(&self) -> bool {
+            true
         }
-        header.finalize()
+
+        fn token(&self) -> Option<Cow<'_, str>> {
+            Some(self.0.into())
+        }
+    }
+
+    #[test]
+    fn bearer_scheme() {
+        let token = |header| match validate(&Header(header)) {
+            Ok(ResourceState::Internalized { token }) => Some(token),
+            _ => None,
+        };
+
+        assert_eq!(token("Bearer abc").as_deref(), Some("abc"));
+        assert_eq!(token("bEaReR abc").as_deref(), Some("abc"));
+        assert_eq!(token("Basic abc"), None);
+        assert_eq!(token("Bear"), None);
+        // Multi-byte characters within the length of the scheme.
+        assert_eq!(token("Beare\u{df}bc"), None);
+        assert_eq!(token("\u{1f600}\u{1f600}"), None);
+    }
+
+    #[test]
+    fn expiry_at_given_time() {
+        use chrono::Duration;
+
+        let now = Utc::now();
+        let grant = Grant {
+            owner_id: "owner".into(),
+            client_id: "client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: now,
+            extensions: Default::default(),
+        };
+        let scopes = ["default".parse().unwrap()];
+        let access = |time| {
+            let mut resource = Resource::at(ScopeMatching::Exact, time);
+            resource.advance(Input::Request {
+                request: &Header("Bearer token"),
+            });
+            resource.advance(Input::Scopes(&scopes));
+            matches!(
+                resource.advance(Input::Recovered(Some(grant.clone()))),
+                Output::Ok(_)
+            )
+        };
+
+        assert!(access(now - Duration::seconds(1)));
+        assert!(!access(now + Duration::seconds(1)));
     }
 }
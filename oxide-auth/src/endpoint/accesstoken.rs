@@ -4,15 +4,19 @@ use std::marker::PhantomData;
 
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
+use zeroize::Zeroizing;
 
 use crate::code_grant::accesstoken::{
     access_token, Error as TokenError, Extension, Endpoint as TokenEndpoint, Request as TokenRequest,
     Authorization as TokenAuthorization,
 };
-use crate::primitives::{authorizer::Authorizer, registrar::Registrar, issuer::Issuer};
+use crate::code_grant::error::AccessTokenErrorType;
+use crate::primitives::{authorizer::Authorizer, grant::Grant, registrar::Registrar, issuer::Issuer};
 use super::{
-    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
-    is_authorization_method,
+    bind_grant, context_binding, Endpoint, GrantEvent, GrantPolicy, GrantOutcome, GrantRecord,
+    IdempotentResponse, InnerTemplate, LimitedRequest, OAuthError, QueryParameter, WebRequest,
+    WebResponse, is_authorization_method, explain_access_token_error, rate_limit, record,
+    request_fingerprint, token_json, trace,
 };
 
 /// Offers access tokens to authenticated third parties.
@@ -38,6 +42,7 @@ where
 
 struct WrappedToken<E: Endpoint<R>, R: WebRequest> {
     inner: E,
+    binding: Option<String>,
     extension_fallback: (),
     r_type: PhantomData<R>,
 }
@@ -68,7 +73,7 @@ enum FailParse<E> {
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct Authorization(String, Option<Vec<u8>>);
+struct Authorization(String, Option<Zeroizing<Vec<u8>>>);
 
 impl<E, R> AccessTokenFlow<E, R>
 where
@@ -101,6 +106,7 @@ where
         Ok(AccessTokenFlow {
             endpoint: WrappedToken {
                 inner: endpoint,
+                binding: None,
                 extension_fallback: (),
                 r_type: PhantomData,
             },
@@ -126,32 +132,125 @@ where
     /// When the registrar, authorizer, or issuer returned by the endpoint is suddenly
     /// `None` when previously it was `Some(_)`.
     pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let issued = access_token(
-            &mut self.endpoint,
-            &WrappedRequest::new(&mut request, self.allow_credentials_in_body),
-        );
+        let span = trace::access_token();
+        let _entered = span.enter();
+
+        if let Some(limited) = self.rate_limited(&mut request) {
+            return limited;
+        }
+
+        let idempotency = self.idempotency(&mut request);
+        if let Some((key, fingerprint)) = &idempotency {
+            if let Some(replayed) = self.replay(&mut request, key, fingerprint) {
+                return replayed;
+            }
+        }
+
+        self.endpoint.binding = context_binding(&mut self.endpoint.inner, &mut request);
+        let (issued, client_id) = {
+            let wrapped = WrappedRequest::new(&mut request, self.allow_credentials_in_body);
+            let issued = access_token(&mut self.endpoint, &wrapped);
+            (issued, wrapped.requesting_client())
+        };
 
         let token = match issued {
-            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Err(error) => {
+                let outcome = match error {
+                    TokenError::Primitive(_) => GrantOutcome::Failed,
+                    _ => GrantOutcome::Denied,
+                };
+                let refused = GrantRecord {
+                    client_id,
+                    error: error.code(),
+                    ..GrantRecord::new(GrantEvent::Token, outcome)
+                };
+                record(&mut self.endpoint.inner, &mut request, refused);
+                return token_error(&mut self.endpoint.inner, &mut request, error);
+            }
             Ok(token) => token,
         };
 
-        let mut response = self
-            .endpoint
-            .inner
-            .response(&mut request, InnerTemplate::Ok.into())?;
-        response
-            .body_json(&token.to_json())
-            .map_err(|err| self.endpoint.inner.web_error(err))?;
-        Ok(response)
+        let issued = GrantRecord {
+            client_id: Some(token.client_id().to_owned()),
+            owner_id: token.owner_id().map(str::to_owned),
+            scope: Some(token.scope().clone()),
+            ..GrantRecord::new(GrantEvent::Token, GrantOutcome::Issued)
+        };
+        let body = token_json(
+            &mut self.endpoint.inner,
+            &mut request,
+            token.to_response(),
+            &issued,
+        );
+        record(&mut self.endpoint.inner, &mut request, issued);
+
+        if let Some((key, fingerprint)) = idempotency {
+            if let Some(store) = self.endpoint.inner.idempotency_store() {
+                let body = body.clone();
+                store.remember(&key, IdempotentResponse { fingerprint, body });
+            }
+        }
+
+        token_response(&mut self.endpoint.inner, &mut request, &body)
+    }
+
+    /// The idempotency key and fingerprint of the request, if the endpoint has a store.
+    fn idempotency(&mut self, request: &mut R) -> Option<(String, String)> {
+        self.endpoint.inner.idempotency_store()?;
+        let key = request.context()?.idempotency_key.clone()?;
+        let fingerprint = request_fingerprint(request)?;
+        Some((key, fingerprint))
+    }
+
+    /// Answer a retried request with the remembered response, if there is one.
+    fn replay(
+        &mut self, request: &mut R, key: &str, fingerprint: &str,
+    ) -> Option<Result<R::Response, E::Error>> {
+        let remembered = self.endpoint.inner.idempotency_store()?.replay(key)?;
+        if remembered.fingerprint != fingerprint {
+            let mut error = TokenError::invalid_with(AccessTokenErrorType::InvalidRequest);
+            if let TokenError::Invalid(json) = &mut error {
+                json.description()
+                    .explain("The idempotency key was used for a different request");
+            }
+            return Some(token_error(&mut self.endpoint.inner, request, error));
+        }
+
+        Some(token_response(
+            &mut self.endpoint.inner,
+            request,
+            &remembered.body,
+        ))
+    }
+
+    /// Consult the rate limiter of the endpoint, if there is one.
+    fn rate_limited(&mut self, request: &mut R) -> Option<Result<R::Response, E::Error>> {
+        // Only parse the client from the request when there is a limiter to consult.
+        self.endpoint.inner.rate_limiter()?;
+        let client_id = WrappedRequest::new(request, self.allow_credentials_in_body).requesting_client();
+        let limited = LimitedRequest {
+            kind: "authorization_code",
+            client_id: client_id.as_deref(),
+        };
+        rate_limit(&mut self.endpoint.inner, request, limited)
     }
 }
 
+fn token_response<E: Endpoint<R>, R: WebRequest>(
+    endpoint: &mut E, request: &mut R, body: &str,
+) -> Result<R::Response, E::Error> {
+    let mut response = endpoint.response(request, InnerTemplate::Ok.into())?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response.body_json(body).map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
+
 fn token_error<E: Endpoint<R>, R: WebRequest>(
     endpoint: &mut E, request: &mut R, error: TokenError,
 ) -> Result<R::Response, E::Error> {
     Ok(match error {
         TokenError::Invalid(mut json) => {
+            explain_access_token_error(endpoint, request, json.description());
             let mut response = endpoint.response(
                 request,
                 InnerTemplate::BadRequest {
@@ -160,12 +259,14 @@ fn token_error<E: Endpoint<R>, R: WebRequest>(
                 .into(),
             )?;
             response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
         TokenError::Unauthorized(mut json, scheme) => {
+            explain_access_token_error(endpoint, request, json.description());
             let mut response = endpoint.response(
                 request,
                 InnerTemplate::Unauthorized {
@@ -177,6 +278,7 @@ fn token_error<E: Endpoint<R>, R: WebRequest>(
             response
                 .unauthorized(&scheme)
                 .map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
@@ -208,6 +310,16 @@ impl<E: Endpoint<R>, R: WebRequest> TokenEndpoint for WrappedToken<E, R> {
             .and_then(super::Extension::access_token)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn bind(&mut self, grant: &mut Grant) {
+        if let Some(binding) = &self.binding {
+            bind_grant(grant, binding);
+        }
+    }
 }
 
 impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
@@ -242,6 +354,14 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
         }
     }
 
+    /// The client as claimed by the request, whether it could be authenticated or not.
+    fn requesting_client(&self) -> Option<String> {
+        match &self.authorization {
+            Some(Authorization(username, _)) => Some(username.clone()),
+            None => self.client_id().map(Cow::into_owned),
+        }
+    }
+
     fn parse_header(header: Cow<str>) -> Result<Authorization, Invalid> {
         let authorization = {
             let auth_data = match is_authorization_method(&header, "Basic ") {
@@ -251,7 +371,7 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
 
             let combined = match STANDARD.decode(auth_data) {
                 Err(_) => return Err(Invalid),
-                Ok(vec) => vec,
+                Ok(vec) => Zeroizing::new(vec),
             };
 
             let mut split = combined.splitn(2, |&c| c == b':');
@@ -270,7 +390,10 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
                 Ok(client) => client,
             };
 
-            Authorization(client.to_string(), passwd.map(|passwd| passwd.to_vec()))
+            Authorization(
+                client.to_string(),
+                passwd.map(|passwd| Zeroizing::new(passwd.to_vec())),
+            )
         };
 
         Ok(authorization)
@@ -291,7 +414,7 @@ impl<'a, R: WebRequest> TokenRequest for WrappedRequest<'a, R> {
             None => TokenAuthorization::None,
             Some(Authorization(username, None)) => TokenAuthorization::Username(username.into()),
             Some(Authorization(username, Some(password))) => {
-                TokenAuthorization::UsernamePassword(username.into(), password.into())
+                TokenAuthorization::UsernamePassword(username.into(), password.as_slice().into())
             }
         }
     }
@@ -342,6 +465,9 @@ mod test {
         let result = WrappedRequest::<Request>::parse_header("Basic Zm9vOmJhcg==".into());
         assert!(result.is_ok());
         let result = result.unwrap();
-        assert_eq!(result, Authorization("foo".into(), Some("bar".into())));
+        assert_eq!(
+            result,
+            Authorization("foo".into(), Some(Zeroizing::new(b"bar".to_vec())))
+        );
     }
 }
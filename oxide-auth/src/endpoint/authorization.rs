@@ -2,6 +2,8 @@ use crate::code_grant::authorization::{
     authorization_code, Error as AuthorizationError, Extension, Endpoint as AuthorizationEndpoint,
     Request as AuthorizationRequest, Pending,
 };
+use crate::code_grant::error::AuthorizationErrorType;
+use crate::primitives::session::Session;
 
 use super::*;
 
@@ -27,6 +29,9 @@ struct WrappedRequest<'a, R: WebRequest + 'a> {
     /// The query in the url.
     query: Cow<'a, dyn QueryParameter + 'static>,
 
+    /// The request uri of the pushed parameters in place of the query.
+    request_uri: Option<String>,
+
     /// An error if one occurred.
     error: Option<R::Error>,
 }
@@ -38,6 +43,9 @@ where
 {
     endpoint: &'a mut WrappedAuthorization<E, R>,
     pending: Pending,
+    parameters: NormalizedParameter,
+    request_uri: Option<String>,
+    context: Option<RequestContext>,
     request: R,
 }
 
@@ -130,17 +138,46 @@ where
     /// When the registrar or the authorizer returned by the endpoint is suddenly `None` when
     /// previously it was `Some(_)`.
     pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let negotiated = authorization_code(&mut self.endpoint, &WrappedRequest::new(&mut request));
+        let span = trace::authorization();
+        let _entered = span.enter();
+
+        let (negotiated, client_id, parameters, request_uri) = {
+            let wrapped = match self.endpoint.inner.pushed_requests() {
+                Some(store) => WrappedRequest::pushed(&mut request, store),
+                None => WrappedRequest::new(&mut request),
+            };
+            let negotiated = authorization_code(&mut self.endpoint, &wrapped);
+            let client_id = wrapped.client_id().map(Cow::into_owned);
+            (
+                negotiated,
+                client_id,
+                wrapped.query.into_owned(),
+                wrapped.request_uri,
+            )
+        };
+        let context = request.context().map(Cow::into_owned);
 
         let inner = match negotiated {
-            Err(err) => match authorization_error(&mut self.endpoint.inner, &mut request, err) {
-                Ok(response) => AuthorizationPartialInner::Failed { request, response },
-                Err(error) => AuthorizationPartialInner::Error { request, error },
-            },
+            Err(err) => {
+                consume(&mut self.endpoint.inner, request_uri);
+                let refused = GrantRecord {
+                    client_id,
+                    error: err.code(),
+                    ..GrantRecord::new(GrantEvent::Code, error_outcome(&err))
+                };
+                record(&mut self.endpoint.inner, &mut request, refused);
+                match authorization_error(&mut self.endpoint.inner, &mut request, err) {
+                    Ok(response) => AuthorizationPartialInner::Failed { request, response },
+                    Err(error) => AuthorizationPartialInner::Error { request, error },
+                }
+            }
             Ok(negotiated) => AuthorizationPartialInner::Pending {
                 pending: AuthorizationPending {
                     endpoint: &mut self.endpoint,
                     pending: negotiated,
+                    parameters,
+                    request_uri,
+                    context,
                     request,
                 },
             },
@@ -168,12 +205,31 @@ impl<'a, E: Endpoint<R>, R: WebRequest> AuthorizationPartial<'a, E, R> {
     }
 }
 
+/// The solicitation of a pending request, with its parameters and context.
+fn solicitation<'p>(
+    pending: &'p Pending, parameters: &'p NormalizedParameter, context: Option<&'p RequestContext>,
+) -> Solicitation<'p> {
+    let solicitation = pending.as_solicitation().with_parameters(parameters);
+    match context {
+        Some(context) => solicitation.with_context(context),
+        None => solicitation,
+    }
+}
+
+fn error_outcome(error: &AuthorizationError) -> GrantOutcome {
+    match error {
+        AuthorizationError::PrimitiveError => GrantOutcome::Failed,
+        _ => GrantOutcome::Denied,
+    }
+}
+
 fn authorization_error<E: Endpoint<R>, R: WebRequest>(
     endpoint: &mut E, request: &mut R, error: AuthorizationError,
 ) -> Result<R::Response, E::Error> {
     match error {
         AuthorizationError::Ignore => Err(endpoint.error(OAuthError::DenySilently)),
         AuthorizationError::Redirect(mut target) => {
+            explain_authorization_error(endpoint, request, target.description());
             let mut response = endpoint.response(
                 request,
                 InnerTemplate::Redirect {
@@ -181,28 +237,181 @@ fn authorization_error<E: Endpoint<R>, R: WebRequest>(
                 }
                 .into(),
             )?;
-            response
-                .redirect(target.into())
-                .map_err(|err| endpoint.web_error(err))?;
+            let url = with_issuer(endpoint, target.into());
+            response.redirect(url).map_err(|err| endpoint.web_error(err))?;
             Ok(response)
         }
         AuthorizationError::PrimitiveError => Err(endpoint.error(OAuthError::PrimitiveError)),
     }
 }
 
+/// Remove the pushed parameters of a decided request from the store of the endpoint.
+fn consume<E: Endpoint<R>, R: WebRequest>(endpoint: &mut E, request_uri: Option<String>) {
+    if let (Some(request_uri), Some(store)) = (request_uri, endpoint.pushed_requests()) {
+        store.consume(&request_uri);
+    }
+}
+
+/// Add the issuer identifier of the endpoint to an authorization response.
+fn with_issuer<E: Endpoint<R>, R: WebRequest>(endpoint: &E, mut url: Url) -> Url {
+    if let Some(issuer) = endpoint.issuer_identifier() {
+        url.query_pairs_mut().append_pair("iss", issuer);
+    }
+    url
+}
+
 impl<'a, E: Endpoint<R>, R: WebRequest> AuthorizationPending<'a, E, R> {
     /// Resolve the pending status using the endpoint to query owner consent.
     fn finish(mut self) -> (R, Result<R::Response, E::Error>) {
-        let checked = self
-            .endpoint
-            .owner_solicitor()
-            .check_consent(&mut self.request, self.pending.as_solicitation());
+        match self.police() {
+            Some(true) => (),
+            Some(false) => return self.fail(),
+            None => return self.deny(),
+        }
+
+        if self.endpoint.inner.session_store().is_some() {
+            if self.parameters.unique_value("max_age").is_some() && self.max_age().is_none() {
+                return self.refuse(AuthorizationErrorType::InvalidRequest);
+            }
+
+            if self.prompt_none() {
+                return self.silently();
+            }
+        }
+
+        if let Some(who) = self.remembered() {
+            if !self.stale(&who) {
+                return self.authorize(who);
+            }
+        }
+
+        let checked = self.endpoint.owner_solicitor().check_consent(
+            &mut self.request,
+            solicitation(&self.pending, &self.parameters, self.context.as_ref()),
+        );
 
         match checked {
             OwnerConsent::Denied => self.deny(),
             OwnerConsent::InProgress(resp) => self.in_progress(resp),
             OwnerConsent::Authorized(who) => self.authorize(who),
-            OwnerConsent::Error(err) => (self.request, Err(self.endpoint.inner.web_error(err))),
+            OwnerConsent::AuthorizedScope(who, scope) => self.authorize_scope(who, scope),
+            OwnerConsent::Remember(who) => {
+                self.remember(&who);
+                self.authorize(who)
+            }
+            OwnerConsent::Error(err) => {
+                let failed = self.record(GrantOutcome::Failed, None);
+                self.decided(failed);
+                (self.request, Err(self.endpoint.inner.web_error(err)))
+            }
+        }
+    }
+
+    /// Narrows the scope with the scope policy of the endpoint, if any.
+    ///
+    /// Returns `None` if the policy denies the request and `Some(false)` if it exceeded the
+    /// negotiated scope.
+    fn police(&mut self) -> Option<bool> {
+        if self.endpoint.inner.scope_policy().is_none() {
+            return Some(true);
+        }
+
+        let owner_id = self.endpoint.owner_solicitor().owner_id(&mut self.request);
+        let solicitation = solicitation(&self.pending, &self.parameters, self.context.as_ref());
+        let scope = self.endpoint.inner.scope_policy()?.decide(
+            &mut self.request,
+            owner_id.as_deref(),
+            solicitation,
+        )?;
+        Some(self.pending.restrict(scope))
+    }
+
+    /// The owner of the request, if they have already approved the client for its scope.
+    fn remembered(&mut self) -> Option<String> {
+        self.endpoint.inner.consent_store()?;
+        let owner_id = self.endpoint.owner_solicitor().owner_id(&mut self.request)?;
+        if self.consented(&owner_id) {
+            Some(owner_id)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the owner has already approved the client for the scope of the request.
+    fn consented(&mut self, owner_id: &str) -> bool {
+        let pre_grant = self.pending.pre_grant();
+        let scope = self
+            .endpoint
+            .inner
+            .consent_store()
+            .and_then(|store| store.recall(owner_id, &pre_grant.client_id));
+
+        match scope {
+            Some(scope) => scope.priviledged_to(&pre_grant.scope),
+            None => false,
+        }
+    }
+
+    /// Whether the request forbids interacting with the owner.
+    fn prompt_none(&self) -> bool {
+        match self.parameters.unique_value("prompt") {
+            Some(prompt) => prompt.split(' ').any(|value| value == "none"),
+            None => false,
+        }
+    }
+
+    /// The maximum age of the authentication of the owner requested by the client.
+    fn max_age(&self) -> Option<chrono::Duration> {
+        let seconds: u32 = self.parameters.unique_value("max_age")?.parse().ok()?;
+        Some(chrono::Duration::seconds(seconds.into()))
+    }
+
+    /// The session the request was made in, if its owner authenticated recently enough.
+    fn session(&mut self) -> Option<(String, Session)> {
+        self.endpoint.inner.session_store()?;
+        let session_id = self.endpoint.owner_solicitor().session_id(&mut self.request)?;
+        let session = self.endpoint.inner.session_store()?.session(&session_id)?;
+
+        match self.max_age() {
+            Some(max_age) if !session.authenticated_within(max_age, Utc::now()) => None,
+            _ => Some((session_id, session)),
+        }
+    }
+
+    /// Whether the owner must authenticate again before the client may be granted a code.
+    fn stale(&mut self, owner_id: &str) -> bool {
+        if self.endpoint.inner.session_store().is_none() || self.max_age().is_none() {
+            return false;
+        }
+
+        !matches!(self.session(), Some((_, session)) if session.owner_id == owner_id)
+    }
+
+    /// Decides a request that forbids interacting with the owner, from their session alone.
+    fn silently(mut self) -> (R, Result<R::Response, E::Error>) {
+        let owner_id = match self.session() {
+            Some((_, session)) => session.owner_id,
+            None => return self.refuse(AuthorizationErrorType::LoginRequired),
+        };
+
+        if self.consented(&owner_id) {
+            self.authorize(owner_id)
+        } else {
+            self.refuse(AuthorizationErrorType::ConsentRequired)
+        }
+    }
+
+    /// Stores the consent of the owner, if the endpoint has a consent store.
+    fn remember(&mut self, owner_id: &str) {
+        let pre_grant = self.pending.pre_grant();
+        let consent = Consent {
+            owner_id: owner_id.to_owned(),
+            client_id: pre_grant.client_id.clone(),
+            scope: pre_grant.scope.clone(),
+        };
+
+        if let Some(store) = self.endpoint.inner.consent_store() {
+            store.remember(consent);
         }
     }
 
@@ -218,20 +427,96 @@ impl<'a, E: Endpoint<R>, R: WebRequest> AuthorizationPending<'a, E, R> {
 
     /// Denies the request, the client is not allowed access.
     fn deny(mut self) -> (R, Result<R::Response, E::Error>) {
+        let denied = GrantRecord {
+            error: Some("access_denied"),
+            ..self.record(GrantOutcome::Denied, None)
+        };
+        self.decided(denied);
         let result = self.pending.deny();
         let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
 
         (self.request, result)
     }
 
+    /// Refuses the request with an error, without the owner having denied it.
+    fn refuse(mut self, kind: AuthorizationErrorType) -> (R, Result<R::Response, E::Error>) {
+        let refused = GrantRecord {
+            error: Some(kind.description()),
+            ..self.record(GrantOutcome::Denied, None)
+        };
+        self.decided(refused);
+        let result = self.pending.refuse(kind);
+        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
+
+        (self.request, result)
+    }
+
+    /// Tells the system that the resource owner has approved only part of the grant.
+    fn authorize_scope(mut self, who: String, scope: Scope) -> (R, Result<R::Response, E::Error>) {
+        if !self.pending.restrict(scope) {
+            return self.fail();
+        }
+
+        self.authorize(who)
+    }
+
+    /// Fails the request after a scope exceeding the negotiated one was approved.
+    fn fail(mut self) -> (R, Result<R::Response, E::Error>) {
+        let failed = self.record(GrantOutcome::Failed, None);
+        self.decided(failed);
+        (
+            self.request,
+            Err(self.endpoint.inner.error(OAuthError::PrimitiveError)),
+        )
+    }
+
     /// Tells the system that the resource owner with the given id has approved the grant.
     fn authorize(mut self, who: String) -> (R, Result<R::Response, E::Error>) {
-        let result = self.pending.authorize(self.endpoint, who.into());
+        if self.stale(&who) {
+            return self.refuse(AuthorizationErrorType::LoginRequired);
+        }
+
+        let session = self.session();
+        let client_id = self.pending.pre_grant().client_id.clone();
+        let mut decided = self.record(GrantOutcome::Issued, Some(who.clone()));
+        let result = self.pending.authorize(self.endpoint, who.clone().into());
+        match (&result, session) {
+            (Err(err), _) => decided.outcome = error_outcome(err),
+            (Ok(_), Some((session_id, session))) if session.owner_id == who => {
+                if let Some(store) = self.endpoint.inner.session_store() {
+                    store.associate(&session_id, &client_id);
+                }
+            }
+            (Ok(_), _) => (),
+        }
+        consume(&mut self.endpoint.inner, self.request_uri.take());
+        record(&mut self.endpoint.inner, &mut self.request, decided);
         let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
 
         (self.request, result)
     }
 
+    /// Records the decision over the request, which consumes its pushed parameters.
+    ///
+    /// Every outcome but asking the owner for consent decides the request. The pushed parameters
+    /// stay available until then, for the request answering the consent page.
+    fn decided(&mut self, decision: GrantRecord) {
+        consume(&mut self.endpoint.inner, self.request_uri.take());
+        record(&mut self.endpoint.inner, &mut self.request, decision);
+    }
+
+    fn record(&self, outcome: GrantOutcome, owner_id: Option<String>) -> GrantRecord {
+        let pre_grant = self.pending.pre_grant();
+        GrantRecord {
+            event: GrantEvent::Code,
+            outcome,
+            client_id: Some(pre_grant.client_id.clone()),
+            owner_id,
+            scope: Some(pre_grant.scope.clone()),
+            error: None,
+        }
+    }
+
     fn convert_result(
         result: Result<Url, AuthorizationError>, endpoint: &mut E, request: &mut R,
     ) -> Result<R::Response, E::Error> {
@@ -244,6 +529,7 @@ impl<'a, E: Endpoint<R>, R: WebRequest> AuthorizationPending<'a, E, R> {
                     }
                     .into(),
                 )?;
+                let url = with_issuer(endpoint, url);
                 response.redirect(url).map_err(|err| endpoint.web_error(err))?;
                 Ok(response)
             }
@@ -273,6 +559,10 @@ impl<E: Endpoint<R>, R: WebRequest> AuthorizationEndpoint for WrappedAuthorizati
             .and_then(super::Extension::authorization)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
 }
 
 impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
@@ -284,14 +574,50 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
         Ok(WrappedRequest {
             request: PhantomData,
             query: request.query()?,
+            request_uri: None,
             error: None,
         })
     }
 
+    /// The parameters pushed under the `request_uri` of the request, in place of its query.
+    ///
+    /// Requests not referring to parameters pushed by their client are left without parameters,
+    /// which ignores them as no client can be identified.
+    fn pushed(request: &'a mut R, store: &mut dyn PushedRequests) -> Self {
+        let query = match request.query() {
+            Ok(query) => query,
+            Err(err) => return Self::from_err(err),
+        };
+
+        let found = match (query.unique_value("client_id"), query.unique_value("request_uri")) {
+            (Some(client_id), Some(request_uri)) => store
+                .find(&client_id, &request_uri)
+                .filter(|pushed| pushed.unique_value("client_id") == Some(client_id))
+                .map(|pushed| (pushed, request_uri.into_owned())),
+            _ => None,
+        };
+
+        match found {
+            Some((pushed, request_uri)) => WrappedRequest {
+                request: PhantomData,
+                query: Cow::Owned(pushed),
+                request_uri: Some(request_uri),
+                error: None,
+            },
+            None => WrappedRequest {
+                request: PhantomData,
+                query: Cow::Owned(Default::default()),
+                request_uri: None,
+                error: None,
+            },
+        }
+    }
+
     fn from_err(err: R::Error) -> Self {
         WrappedRequest {
             request: PhantomData,
             query: Cow::Owned(Default::default()),
+            request_uri: None,
             error: Some(err),
         }
     }
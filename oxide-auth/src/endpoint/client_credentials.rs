@@ -4,6 +4,7 @@ use std::marker::PhantomData;
 
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
+use zeroize::Zeroizing;
 
 use crate::code_grant::client_credentials::{
     client_credentials, Error as ClientCredentialsError, Extension,
@@ -11,10 +12,12 @@ use crate::code_grant::client_credentials::{
 };
 use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
 use crate::code_grant::refresh::ErrorDescription;
-use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::primitives::{grant::Grant, registrar::Registrar, issuer::Issuer};
 use super::{
-    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
-    is_authorization_method, OwnerConsent,
+    bind_grant, context_binding, Endpoint, GrantEvent, GrantPolicy, GrantOutcome, GrantRecord,
+    InnerTemplate, LimitedRequest, OAuthError, QueryParameter, WebRequest, WebResponse,
+    is_authorization_method, explain_access_token_error, rate_limit, record, token_json, trace,
+    OwnerConsent,
 };
 
 /// Offers access tokens to authenticated third parties.
@@ -38,6 +41,7 @@ where
 
 struct WrappedToken<E: Endpoint<R>, R: WebRequest> {
     inner: E,
+    binding: Option<String>,
     extension_fallback: (),
     r_type: PhantomData<R>,
 }
@@ -66,7 +70,7 @@ enum FailParse<E> {
     Err(E),
 }
 
-struct Authorization(String, Vec<u8>);
+struct Authorization(String, Zeroizing<Vec<u8>>);
 
 impl<E, R> ClientCredentialsFlow<E, R>
 where
@@ -95,6 +99,7 @@ where
         Ok(ClientCredentialsFlow {
             endpoint: WrappedToken {
                 inner: endpoint,
+                binding: None,
                 extension_fallback: (),
                 r_type: PhantomData,
             },
@@ -135,17 +140,42 @@ where
     /// When the registrar, authorizer, or issuer returned by the endpoint is suddenly
     /// `None` when previously it was `Some(_)`.
     pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let pending = client_credentials(
-            &mut self.endpoint,
-            &WrappedRequest::new(&mut request, self.allow_credentials_in_body),
-        );
-        let pending = match pending {
+        let span = trace::client_credentials();
+        let _entered = span.enter();
+
+        if let Some(limited) = self.rate_limited(&mut request) {
+            return limited;
+        }
+
+        self.endpoint.binding = context_binding(&mut self.endpoint.inner, &mut request);
+        let (pending, client_id) = {
+            let wrapped = WrappedRequest::new(&mut request, self.allow_credentials_in_body);
+            let pending = client_credentials(&mut self.endpoint, &wrapped);
+            (pending, wrapped.requesting_client())
+        };
+        let mut pending = match pending {
             Err(error) => {
-                return client_credentials_error(&mut self.endpoint.inner, &mut request, error)
+                let refused = GrantRecord {
+                    client_id,
+                    error: error.code(),
+                    ..GrantRecord::new(GrantEvent::Token, error_outcome(&error))
+                };
+                record(&mut self.endpoint.inner, &mut request, refused);
+                return client_credentials_error(&mut self.endpoint.inner, &mut request, error);
             }
             Ok(pending) => pending,
         };
 
+        let pre_grant = pending.as_solicitation().pre_grant().clone();
+        let decided = |outcome, owner_id| GrantRecord {
+            event: GrantEvent::Token,
+            outcome,
+            client_id: Some(pre_grant.client_id.clone()),
+            owner_id,
+            scope: Some(pre_grant.scope.clone()),
+            error: None,
+        };
+
         let consent = self
             .endpoint
             .inner
@@ -154,14 +184,34 @@ where
             .check_consent(&mut request, pending.as_solicitation());
 
         let owner_id = match consent {
-            OwnerConsent::Authorized(owner_id) => owner_id,
-            OwnerConsent::Error(error) => return Err(self.endpoint.inner.web_error(error)),
+            OwnerConsent::Authorized(owner_id) | OwnerConsent::Remember(owner_id) => owner_id,
+            OwnerConsent::AuthorizedScope(owner_id, scope) => {
+                if !pending.restrict(scope) {
+                    let failed = decided(GrantOutcome::Failed, None);
+                    record(&mut self.endpoint.inner, &mut request, failed);
+                    return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
+                }
+                owner_id
+            }
+            OwnerConsent::Error(error) => {
+                let failed = decided(GrantOutcome::Failed, None);
+                record(&mut self.endpoint.inner, &mut request, failed);
+                return Err(self.endpoint.inner.web_error(error));
+            }
             OwnerConsent::InProgress(..) => {
                 // User interaction is not permitted in the client credentials flow, so
                 // an InProgress response is invalid.
+                let failed = decided(GrantOutcome::Failed, None);
+                record(&mut self.endpoint.inner, &mut request, failed);
                 return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
             }
             OwnerConsent::Denied => {
+                let denied = GrantRecord {
+                    error: Some("invalid_client"),
+                    ..decided(GrantOutcome::Denied, None)
+                };
+                record(&mut self.endpoint.inner, &mut request, denied);
+
                 let mut error = AccessTokenError::default();
                 error.set_type(AccessTokenErrorType::InvalidClient);
                 let mut json = ErrorDescription { error };
@@ -176,6 +226,9 @@ where
                 response
                     .client_error()
                     .map_err(|err| self.endpoint.inner.web_error(err))?;
+                response
+                    .no_store()
+                    .map_err(|err| self.endpoint.inner.web_error(err))?;
                 response
                     .body_json(&json.to_json())
                     .map_err(|err| self.endpoint.inner.web_error(err))?;
@@ -183,22 +236,64 @@ where
             }
         };
 
+        let owner = Some(owner_id.clone());
+        let scope = Some(pending.as_solicitation().pre_grant().scope.clone());
         let token = match pending.issue(&mut self.endpoint, owner_id, self.allow_refresh_token) {
             Err(error) => {
-                return client_credentials_error(&mut self.endpoint.inner, &mut request, error)
+                let refused = GrantRecord {
+                    scope,
+                    error: error.code(),
+                    ..decided(error_outcome(&error), owner)
+                };
+                record(&mut self.endpoint.inner, &mut request, refused);
+                return client_credentials_error(&mut self.endpoint.inner, &mut request, error);
             }
             Ok(token) => token,
         };
 
+        let issued = GrantRecord {
+            scope,
+            ..decided(GrantOutcome::Issued, owner)
+        };
+        let body = token_json(
+            &mut self.endpoint.inner,
+            &mut request,
+            token.to_response(),
+            &issued,
+        );
+        record(&mut self.endpoint.inner, &mut request, issued);
+
         let mut response = self
             .endpoint
             .inner
             .response(&mut request, InnerTemplate::Ok.into())?;
         response
-            .body_json(&token.to_json())
+            .no_store()
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        response
+            .body_json(&body)
             .map_err(|err| self.endpoint.inner.web_error(err))?;
         Ok(response)
     }
+
+    /// Consult the rate limiter of the endpoint, if there is one.
+    fn rate_limited(&mut self, request: &mut R) -> Option<Result<R::Response, E::Error>> {
+        // Only parse the client from the request when there is a limiter to consult.
+        self.endpoint.inner.rate_limiter()?;
+        let client_id = WrappedRequest::new(request, self.allow_credentials_in_body).requesting_client();
+        let limited = LimitedRequest {
+            kind: "client_credentials",
+            client_id: client_id.as_deref(),
+        };
+        rate_limit(&mut self.endpoint.inner, request, limited)
+    }
+}
+
+fn error_outcome(error: &ClientCredentialsError) -> GrantOutcome {
+    match error {
+        ClientCredentialsError::Primitive(_) => GrantOutcome::Failed,
+        _ => GrantOutcome::Denied,
+    }
 }
 
 fn client_credentials_error<E: Endpoint<R>, R: WebRequest>(
@@ -207,6 +302,7 @@ fn client_credentials_error<E: Endpoint<R>, R: WebRequest>(
     Ok(match error {
         ClientCredentialsError::Ignore => return Err(endpoint.error(OAuthError::DenySilently)),
         ClientCredentialsError::Invalid(mut json) => {
+            explain_access_token_error(endpoint, request, json.description());
             let mut response = endpoint.response(
                 request,
                 InnerTemplate::BadRequest {
@@ -215,12 +311,14 @@ fn client_credentials_error<E: Endpoint<R>, R: WebRequest>(
                 .into(),
             )?;
             response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
         ClientCredentialsError::Unauthorized(mut json, scheme) => {
+            explain_access_token_error(endpoint, request, json.description());
             let mut response = endpoint.response(
                 request,
                 InnerTemplate::Unauthorized {
@@ -232,6 +330,7 @@ fn client_credentials_error<E: Endpoint<R>, R: WebRequest>(
             response
                 .unauthorized(&scheme)
                 .map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
@@ -259,6 +358,16 @@ impl<E: Endpoint<R>, R: WebRequest> ClientCredentialsEndpoint for WrappedToken<E
             .and_then(super::Extension::client_credentials)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn bind(&mut self, grant: &mut Grant) {
+        if let Some(binding) = &self.binding {
+            bind_grant(grant, binding);
+        }
+    }
 }
 
 impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
@@ -293,6 +402,14 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
         }
     }
 
+    /// The client as claimed by the request, whether it could be authenticated or not.
+    fn requesting_client(&self) -> Option<String> {
+        match &self.authorization {
+            Some(Authorization(username, _)) => Some(username.clone()),
+            None => self.body.unique_value("client_id").map(Cow::into_owned),
+        }
+    }
+
     fn parse_header(header: Cow<str>) -> Result<Authorization, Invalid> {
         let authorization = {
             let auth_data = match is_authorization_method(&header, "Basic ") {
@@ -302,7 +419,7 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
 
             let combined = match STANDARD.decode(auth_data) {
                 Err(_) => return Err(Invalid),
-                Ok(vec) => vec,
+                Ok(vec) => Zeroizing::new(vec),
             };
 
             let mut split = combined.splitn(2, |&c| c == b':');
@@ -320,7 +437,7 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
                 Ok(client) => client,
             };
 
-            Authorization(client.to_string(), passwd.to_vec())
+            Authorization(client.to_string(), Zeroizing::new(passwd.to_vec()))
         };
 
         Ok(authorization)
@@ -0,0 +1,236 @@
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::registrar::ClientUrl;
+
+use super::*;
+
+/// A grant request of GNAP, as sent to the grant endpoint.
+///
+/// Only the members understood by the `GnapFlow` are modelled, others are ignored when
+/// deserializing.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GnapRequest {
+    /// The single access token requested.
+    pub access_token: GnapAccessToken,
+
+    /// The client instance making the request.
+    pub client: GnapClient,
+
+    /// How the client can interact with the end user, if at all.
+    #[serde(default)]
+    pub interact: Option<JsonValue>,
+}
+
+/// The rights requested for an access token.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GnapAccessToken {
+    /// The requested access rights.
+    pub access: Vec<GnapAccess>,
+}
+
+/// A requested access right.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum GnapAccess {
+    /// A reference to a right known to the server, a scope token.
+    Reference(String),
+
+    /// A right described by an object with a `type`.
+    Object(JsonValue),
+}
+
+/// The client instance of a request.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum GnapClient {
+    /// The instance identifier, which is the id of a registered client.
+    Instance(String),
+
+    /// A client instance described by its key and display information.
+    Object(JsonValue),
+}
+
+/// An experimental grant endpoint of GNAP, RFC 9635.
+///
+/// The flow lets clients of GNAP request access tokens from the registrar and issuer used for
+/// OAuth, so that such clients can be prototyped against an existing server. It only covers a small
+/// part of the protocol:
+///
+/// * The client instance is referenced by the id of a registered client. Clients presenting their
+///   key by value are refused.
+/// * The access rights are references, interpreted as scope tokens and negotiated with the
+///   registrar like a requested scope.
+/// * No interaction with an end user. The token is issued for the client instance itself, as in
+///   the client credentials grant. Requests with `interact` are refused.
+/// * No continuation and no token management.
+///
+/// GNAP binds every request to the key of the client instance. The flow does NOT verify the key
+/// proof of the request, the frontend must do so before calling [`execute`] with the parsed body.
+/// Successful requests are answered with the token and its granted access:
+///
+/// ```json
+/// {"access_token":{"value":"OS9M2PMHKUR64TB8N6BW7OZB8CDFONP219RP1LT0","access":["read"],"expires_in":600},"instance_id":"LocalClient"}
+/// ```
+///
+/// [`execute`]: #method.execute
+pub struct GnapFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: E,
+    r_type: PhantomData<R>,
+}
+
+impl<E, R> GnapFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    /// Check that the endpoint supports the necessary operations for handling requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(GnapFlow {
+            endpoint,
+            r_type: PhantomData,
+        })
+    }
+
+    /// Answer a grant request whose key proof the frontend has verified.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or issuer returned by the endpoint is suddenly `None` when previously it
+    /// was `Some(_)`.
+    pub fn execute(
+        &mut self, mut request: R, grant_request: GnapRequest,
+    ) -> Result<R::Response, E::Error> {
+        let client_id = match grant_request.client {
+            GnapClient::Instance(client_id) => client_id,
+            GnapClient::Object(_) => {
+                return self.refuse(
+                    &mut request,
+                    "invalid_client",
+                    "Only registered client instances are supported",
+                )
+            }
+        };
+
+        if grant_request.interact.is_some() {
+            return self.refuse(
+                &mut request,
+                "invalid_interaction",
+                "Interaction with the end user is not supported",
+            );
+        }
+
+        let mut references = Vec::new();
+        for access in grant_request.access_token.access {
+            match access {
+                GnapAccess::Reference(reference) => references.push(reference),
+                GnapAccess::Object(_) => {
+                    return self.refuse(
+                        &mut request,
+                        "invalid_request",
+                        "Only access rights by reference are supported",
+                    )
+                }
+            }
+        }
+
+        let scope = match references.join(" ").parse::<Scope>() {
+            Ok(scope) if !references.is_empty() => scope,
+            _ => return self.refuse(&mut request, "invalid_request", "The requested access is invalid"),
+        };
+
+        let registrar = self.endpoint.registrar().unwrap();
+        let bound = registrar.bound_redirect(ClientUrl {
+            client_id: Cow::Borrowed(&client_id),
+            redirect_uri: None,
+        });
+        let pre_grant = match bound.and_then(|bound| registrar.negotiate(bound, Some(scope))) {
+            Ok(pre_grant) => pre_grant,
+            Err(_) => {
+                return self.refuse(&mut request, "invalid_client", "The client instance is unknown")
+            }
+        };
+
+        let grant = Grant {
+            owner_id: pre_grant.client_id.clone(),
+            client_id: pre_grant.client_id,
+            scope: pre_grant.scope,
+            redirect_uri: pre_grant.redirect_uri.to_url(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        };
+
+        let issued = GrantRecord {
+            event: GrantEvent::Token,
+            outcome: GrantOutcome::Issued,
+            client_id: Some(grant.client_id.clone()),
+            owner_id: Some(grant.owner_id.clone()),
+            scope: Some(grant.scope.clone()),
+            error: None,
+        };
+        let access: Vec<_> = grant.scope.iter().map(str::to_owned).collect();
+        let token = self
+            .endpoint
+            .issuer_mut()
+            .unwrap()
+            .issue(grant)
+            .map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?;
+        record(&mut self.endpoint, &mut request, issued);
+
+        let body = json!({
+            "access_token": {
+                "value": token.token,
+                "access": access,
+                "expires_in": token.until.signed_duration_since(Utc::now()).num_seconds(),
+            },
+            "instance_id": client_id,
+        });
+        let mut response = self.endpoint.response(&mut request, Template::new_ok())?;
+        response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_json(&body.to_string())
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+
+    /// Answer with an error object of GNAP.
+    fn refuse(
+        &mut self, request: &mut R, code: &'static str, description: &'static str,
+    ) -> Result<R::Response, E::Error> {
+        let refused = GrantRecord {
+            error: Some(code),
+            ..GrantRecord::new(GrantEvent::Token, GrantOutcome::Denied)
+        };
+        record(&mut self.endpoint, request, refused);
+
+        let body = json!({ "error": { "code": code, "description": description } });
+        let mut response = self.endpoint.response(request, Template::new_bad(None))?;
+        response
+            .client_error()
+            .map_err(|err| self.endpoint.web_error(err))?;
+        response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_json(&body.to_string())
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
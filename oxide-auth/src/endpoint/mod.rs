@@ -32,39 +32,64 @@ mod authorization;
 mod accesstoken;
 mod client_credentials;
 mod error;
+#[cfg(feature = "gnap")]
+mod gnap;
 mod refresh;
 mod resource;
+mod revocation;
 mod query;
+mod trace;
+mod uma;
 
 #[cfg(test)]
 mod tests;
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub use crate::primitives::authorizer::Authorizer;
+pub use crate::primitives::consent::ConsentStore;
 pub use crate::primitives::issuer::Issuer;
+pub use crate::primitives::nonce::NonceStore;
+pub use crate::primitives::pushed::PushedRequests;
 pub use crate::primitives::registrar::Registrar;
-pub use crate::primitives::scope::Scope;
+pub use crate::primitives::scope::{Scope, ScopeMatching};
+pub use crate::primitives::session::SessionStore;
 
+use crate::code_grant::accesstoken::{ErrorDescription, TokenResponse};
 use crate::code_grant::resource::{Error as ResourceError};
-use crate::code_grant::error::{AuthorizationError, AccessTokenError};
-
+use crate::code_grant::error::{AuthorizationError, AccessTokenError, AccessTokenErrorType};
+use crate::primitives::consent::Consent;
+use crate::primitives::grant::{Extensions, Grant, Value};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
 use url::Url;
 
 // Re-export the extension traits under prefixed names.
 pub use crate::code_grant::authorization::Extension as AuthorizationExtension;
 pub use crate::code_grant::accesstoken::Extension as AccessTokenExtension;
 pub use crate::code_grant::client_credentials::Extension as ClientCredentialsExtension;
+pub use crate::code_grant::is_authorization_method;
 
 pub use crate::primitives::registrar::PreGrant;
 pub use self::authorization::*;
 pub use self::accesstoken::*;
 pub use self::client_credentials::ClientCredentialsFlow;
 pub use self::error::OAuthError;
+#[cfg(feature = "gnap")]
+pub use self::gnap::{GnapAccess, GnapAccessToken, GnapClient, GnapFlow, GnapRequest};
 pub use self::refresh::RefreshFlow;
 pub use self::resource::*;
+pub use self::revocation::{ClientRevocationFlow, OwnerGrantsFlow};
 pub use self::query::*;
+pub use self::uma::{PermissionFlow, UmaGrantFlow, PROTECTION_SCOPE, UMA_GRANT_TYPE};
 
 /// Answer from OwnerAuthorizer to indicate the owners choice.
 pub enum OwnerConsent<Response: WebResponse> {
@@ -77,6 +102,16 @@ pub enum OwnerConsent<Response: WebResponse> {
     /// Authorization was granted by the specified user.
     Authorized(String),
 
+    /// Authorization was granted by the specified user for only part of the requested scope, for
+    /// example after deselecting some scopes on the consent page.
+    ///
+    /// The scope must not exceed the scope of the solicitation, otherwise the flow fails.
+    AuthorizedScope(String, Scope),
+
+    /// Authorization was granted by the specified user, who asked for the decision to be
+    /// remembered by the consent store of the endpoint.
+    Remember(String),
+
     /// An error occurred while checking authorization.
     Error(Response::Error),
 }
@@ -182,6 +217,9 @@ enum InnerTemplate<'a> {
 pub struct Solicitation<'flow> {
     pub(crate) grant: Cow<'flow, PreGrant>,
     pub(crate) state: Option<Cow<'flow, str>>,
+    pub(crate) extensions: Option<Cow<'flow, Extensions>>,
+    pub(crate) parameters: Option<Cow<'flow, NormalizedParameter>>,
+    pub(crate) context: Option<Cow<'flow, RequestContext>>,
 }
 
 impl<'flow> Solicitation<'flow> {
@@ -192,6 +230,9 @@ impl<'flow> Solicitation<'flow> {
         Solicitation {
             grant: Cow::Owned(self.grant.into_owned()),
             state: self.state.map(|state| Cow::Owned(state.into_owned())),
+            extensions: self.extensions.map(|ext| Cow::Owned(ext.into_owned())),
+            parameters: self.parameters.map(|params| Cow::Owned(params.into_owned())),
+            context: self.context.map(|context| Cow::Owned(context.into_owned())),
         }
     }
 
@@ -212,6 +253,30 @@ impl<'flow> Solicitation<'flow> {
         self.state.as_ref().map(|x| x as _)
     }
 
+    /// The grant extensions negotiated by the extensions of the endpoint.
+    ///
+    /// For example, the PKCE challenge or the `nonce` of an OpenID request, under the identifier
+    /// of their extension. The data is stored in the grant when the owner approves it.
+    pub fn extensions(&self) -> Option<&Extensions> {
+        self.extensions.as_deref()
+    }
+
+    /// All parameters of an authorization request, including those not understood by the flow.
+    ///
+    /// Use these to inform a consent page or a risk assessment, for example of a `prompt` or
+    /// `login_hint`. Only the `PreGrant` has been validated, any other value is untrusted input.
+    pub fn parameters(&self) -> Option<&NormalizedParameter> {
+        self.parameters.as_deref()
+    }
+
+    /// The context the frontend attached to the request, such as the remote address.
+    ///
+    /// Use it for adaptive decisions, for example to ask for a second factor from an unknown
+    /// device. Like the parameters, the context is not verified by the flow.
+    pub fn context(&self) -> Option<&RequestContext> {
+        self.context.as_deref()
+    }
+
     /// Create a new solicitation request from a pre grant.
     ///
     /// You usually wouldn't need to call this manually as it is called by the endpoint's flow and
@@ -220,6 +285,9 @@ impl<'flow> Solicitation<'flow> {
         Solicitation {
             grant: Cow::Borrowed(grant),
             state: None,
+            extensions: None,
+            parameters: None,
+            context: None,
         }
     }
 
@@ -230,6 +298,30 @@ impl<'flow> Solicitation<'flow> {
             ..self
         }
     }
+
+    /// Add the negotiated grant extensions to the solicitation.
+    pub fn with_extensions(self, extensions: &'flow Extensions) -> Self {
+        Solicitation {
+            extensions: Some(Cow::Borrowed(extensions)),
+            ..self
+        }
+    }
+
+    /// Add the parameters of the request to the solicitation.
+    pub fn with_parameters(self, parameters: &'flow NormalizedParameter) -> Self {
+        Solicitation {
+            parameters: Some(Cow::Borrowed(parameters)),
+            ..self
+        }
+    }
+
+    /// Add the context of the request to the solicitation.
+    pub fn with_context(self, context: &'flow RequestContext) -> Self {
+        Solicitation {
+            context: Some(Cow::Borrowed(context)),
+            ..self
+        }
+    }
 }
 
 /// Checks consent with the owner of a resource, identified in a request.
@@ -241,6 +333,22 @@ pub trait OwnerSolicitor<Request: WebRequest> {
     /// Ensure that a user (resource owner) is currently authenticated (for example via a session
     /// cookie) and determine if he has agreed to the presented grants.
     fn check_consent(&mut self, _: &mut Request, _: Solicitation) -> OwnerConsent<Request::Response>;
+
+    /// The resource owner already authenticated with the request, without interacting with them.
+    ///
+    /// The authorization flow uses this to look up remembered consent before checking consent.
+    /// Returning `None` is the default implementation and always checks consent.
+    fn owner_id(&mut self, _: &mut Request) -> Option<String> {
+        None
+    }
+
+    /// The id of the session the request was made in, for example read from a session cookie.
+    ///
+    /// The authorization flow looks the session up in the session store of the endpoint. Returning
+    /// `None` is the default implementation and treats every request as made outside a session.
+    fn session_id(&mut self, _: &mut Request) -> Option<String> {
+        None
+    }
 }
 
 /// Determine the scopes applying to a request of a resource.
@@ -284,6 +392,378 @@ pub trait Scopes<Request: WebRequest> {
     /// A scope is fulfilled if the set of its part is a subset of the parts in the grant. If the
     /// slice is empty, then no scope can be fulfilled and the request is always blocked.
     fn scopes(&mut self, request: &mut Request) -> &[Scope];
+
+    /// How the scope of the access token is matched against these scopes.
+    ///
+    /// Defaults to `ScopeMatching::Exact`, where every token of a scope must be granted verbatim.
+    fn matching(&mut self) -> ScopeMatching {
+        ScopeMatching::Exact
+    }
+}
+
+/// The kind of grant a flow decided over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrantEvent {
+    /// An authorization code requested by a client.
+    Code,
+
+    /// An access token, either exchanged for a code or requested with client credentials.
+    Token,
+
+    /// A refreshed access token.
+    Refresh,
+
+    /// A revoked token.
+    ///
+    /// Recorded by the revocation flows, with the client and possibly owner whose tokens were
+    /// revoked. The application can record its own revocations into the same outbox.
+    Revocation,
+}
+
+/// How a flow concluded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrantOutcome {
+    /// The grant was issued.
+    Issued,
+
+    /// The request was refused, the client or resource owner got an error response.
+    Denied,
+
+    /// An internal error, such as a failing primitive, prevented the decision.
+    Failed,
+}
+
+/// Describes the outcome of a single flow for auditing.
+///
+/// Fields are filled in as far as they are known to the flow. A denied access token request may
+/// for example not have identified the owner, and a request that failed parsing may not even have
+/// identified the client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrantRecord {
+    /// The kind of grant.
+    pub event: GrantEvent,
+
+    /// How the request concluded.
+    pub outcome: GrantOutcome,
+
+    /// The client that made the request.
+    pub client_id: Option<String>,
+
+    /// The resource owner who authorized the grant.
+    pub owner_id: Option<String>,
+
+    /// The granted or requested scope.
+    pub scope: Option<Scope>,
+
+    /// The error code sent to the client, if the request was refused with one.
+    ///
+    /// A resource owner who denied the request results in `access_denied`, a client that could not
+    /// be authenticated in `invalid_client`.
+    pub error: Option<&'static str>,
+}
+
+/// Receives a record of every grant decided by a flow.
+///
+/// The outbox is called after the decision was made but before the response is returned. It can
+/// not influence the response, so failures to persist a record must be handled by the outbox
+/// itself. The request is passed along to extract additional information, for example the remote
+/// address of the client.
+pub trait Outbox<Request: WebRequest> {
+    /// Record the outcome of a flow.
+    fn record(&mut self, request: &mut Request, record: GrantRecord);
+}
+
+/// Collects metrics of the flows and primitives, for example to be scraped by a monitoring system.
+///
+/// All methods take `&self` so that a single collector can be shared by several endpoints, for
+/// example in an `Arc`, and default to doing nothing. See [`frontends::metrics`] for a collector
+/// exposing the Prometheus text format.
+///
+/// [`frontends::metrics`]: ../frontends/metrics/index.html
+pub trait Metrics {
+    /// A flow decided over a grant.
+    fn grant(&self, _record: &GrantRecord) {}
+
+    /// An error was sent to the client, identified by its standard error code.
+    fn error(&self, _code: &str) {}
+
+    /// A call of an issuer completed after the elapsed time.
+    fn issuer_call(&self, _call: IssuerCall, _elapsed: Duration) {}
+
+    /// An access token valid until the given time was issued.
+    fn token_issued(&self, _until: DateTime<Utc>) {}
+
+    /// Expired entries were removed from a store, identified by the name it was swept under.
+    fn swept(&self, _store: &str, _removed: usize) {}
+}
+
+/// The methods of an issuer, as reported to `Metrics`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IssuerCall {
+    /// Issuing a token for a grant.
+    Issue,
+
+    /// Refreshing a token.
+    Refresh,
+
+    /// Recovering the grant of an access token.
+    RecoverToken,
+
+    /// Recovering the grant of a refresh token.
+    RecoverRefresh,
+}
+
+/// Adds members to the response of an issued token.
+///
+/// Called by the access token, client credentials and refresh flows for each token they issue,
+/// with the record of the grant. The additional members already contain any public extension data
+/// of the grant. Members defined by the token response itself, such as `access_token` or `scope`,
+/// can not be replaced and are dropped from the additional members.
+pub trait TokenResponseCustomizer<Request: WebRequest> {
+    /// Add, change or remove additional members of the token response.
+    fn customize(
+        &mut self, request: &mut Request, grant: &GrantRecord,
+        additional: &mut HashMap<String, JsonValue>,
+    );
+}
+
+/// Explains errors to the client, for example with a localized description or a support page.
+///
+/// The customizer can set the description, the `error_uri` and additional members of an error
+/// response. The error code itself and the status of the response stay under the control of the
+/// flow, changes to the kind of an error are reverted.
+pub trait ErrorCustomizer<Request: WebRequest> {
+    /// Explain an error sent to the client in the redirect of an authorization request.
+    fn authorization_error(&mut self, _request: &mut Request, _error: &mut AuthorizationError) {}
+
+    /// Explain an error sent to the client in the body of a token response.
+    fn access_token_error(&mut self, _request: &mut Request, _error: &mut AccessTokenError) {}
+}
+
+/// Decides the scope of authorization requests per client and resource owner.
+///
+/// The registrar negotiates a scope for each client. A policy can narrow it down further, depending
+/// on the owner, the requested scope or anything else in the request. For example, it may remove an
+/// `admin` scope unless the owner has an admin role. The owner is then only asked to consent to the
+/// scope that remains.
+///
+/// As the policy is consulted before any grant is issued, it can also make risk based decisions
+/// with the [`RequestContext`] of the solicitation, such as denying requests from unknown networks.
+///
+/// [`RequestContext`]: struct.RequestContext.html
+pub trait ScopePolicy<Request: WebRequest> {
+    /// The scope to grant, or `None` to deny the request.
+    ///
+    /// The `owner_id` is the owner reported by [`OwnerSolicitor::owner_id`], if any. The returned
+    /// scope must not exceed the negotiated scope of the pre grant, otherwise the request fails.
+    ///
+    /// [`OwnerSolicitor::owner_id`]: trait.OwnerSolicitor.html#method.owner_id
+    fn decide(
+        &mut self, request: &mut Request, owner_id: Option<&str>, solicitation: Solicitation,
+    ) -> Option<Scope>;
+}
+
+/// Decides over grants just before they are issued.
+///
+/// The policy sees the complete grant of each code, access token and refreshed token: its client,
+/// owner, scope, lifetime and extensions. Unlike the [`ScopePolicy`], which only narrows the scope
+/// of authorization requests before consent, it is consulted by all flows issuing grants. This
+/// makes it the place to enforce the rules of a central policy engine.
+///
+/// [`ScopePolicy`]: trait.ScopePolicy.html
+pub trait GrantPolicy {
+    /// Allow, change or deny the grant about to be issued.
+    ///
+    /// A policy may narrow the scope, change the lifetime or add extensions. It must not change
+    /// the client, the owner or the redirect uri of the grant and must not widen its scope,
+    /// otherwise the flow fails as if the policy returned `GrantDecision::Error`.
+    fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision;
+}
+
+/// The decision of a `GrantPolicy` over a grant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrantDecision {
+    /// Issue the grant, including any changes of the policy.
+    Allow,
+
+    /// Refuse the grant.
+    ///
+    /// Authorization requests are redirected with an `access_denied` error while token requests
+    /// are answered with `invalid_grant`.
+    Deny,
+
+    /// The policy could not be evaluated, for example as the policy engine was unreachable.
+    Error,
+}
+
+impl GrantDecision {
+    /// Check that a grant was changed only within the permitted bounds.
+    ///
+    /// Returns `Error` instead of allowing the `changed` grant if it differs from the `original` in
+    /// its client, owner or redirect uri, or if its scope is wider.
+    pub fn check(self, original: &Grant, changed: &Grant) -> Self {
+        if self != GrantDecision::Allow {
+            return self;
+        }
+
+        let same_parties = original.client_id == changed.client_id
+            && original.owner_id == changed.owner_id
+            && original.redirect_uri == changed.redirect_uri;
+        if same_parties && original.scope.priviledged_to(&changed.scope) {
+            GrantDecision::Allow
+        } else {
+            GrantDecision::Error
+        }
+    }
+}
+
+/// Binds issued tokens to the context of the request they were issued to.
+///
+/// A bearer token works for anyone holding it. Binding it to the network context of the client,
+/// such as the prefix of its address or a hash of its user agent, makes a leaked token useless
+/// elsewhere without the keys of DPoP or mutual TLS. The flows of the token endpoint keep the
+/// binding of the [`RequestContext`] with the grant, as a private extension, and the resource flow
+/// compares it to the context of each request presenting the token. See [`frontends::binding`] for
+/// an implementation.
+///
+/// The context is supplied by the frontend and not verified, some of it is even chosen by the
+/// client. A binding is a cheap sender constraint, not a proof of possession.
+///
+/// [`RequestContext`]: struct.RequestContext.html
+/// [`frontends::binding`]: ../frontends/binding/index.html
+pub trait BindingPolicy {
+    /// The binding of a token issued to a request with this context, or `None` to not bind it.
+    ///
+    /// Tokens issued to requests without context are not bound. Refreshed tokens are bound to the
+    /// context of the refresh request, or keep their binding if it has none.
+    fn bind(&mut self, context: &RequestContext) -> Option<String>;
+
+    /// Decide over a resource request presenting a token with a binding.
+    ///
+    /// The `context` is `None` if the frontend did not attach any to the request. Tokens without
+    /// a binding are not checked.
+    fn check(&mut self, binding: &str, context: Option<&RequestContext>) -> BindingDecision;
+}
+
+/// The decision of a `BindingPolicy` over a resource request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingDecision {
+    /// The request matches the binding of its token.
+    Allow,
+
+    /// Refuse the request with an `invalid_token` error.
+    ///
+    /// The token remains valid for requests matching its binding.
+    Reject,
+
+    /// Refuse the request and revoke the tokens of the owner issued to the client.
+    ///
+    /// The client has to ask the owner for authorization again. Fails the request with a primitive
+    /// error if the issuer can not revoke grants.
+    Reauthenticate,
+}
+
+/// Limits the rate of requests to the token endpoint.
+///
+/// The flows of the token endpoint consult the limiter before processing a request. The limiter
+/// has access to the request itself, so it can key its limits by the remote address as determined
+/// by the frontend, by the client or by both. See [`frontends::ratelimit`] for an implementation.
+///
+/// [`frontends::ratelimit`]: ../frontends/ratelimit/index.html
+pub trait RateLimiter<Request: WebRequest> {
+    /// Decide whether the request is processed.
+    fn check(&mut self, request: &mut Request, limited: &LimitedRequest) -> RateDecision;
+}
+
+/// Remembers the responses of token requests carrying an idempotency key.
+///
+/// A client retrying a token request after a network timeout would otherwise burn its one-time
+/// code on the first attempt and fail on the retry. With a store, the access token flow answers a
+/// retry with the same `RequestContext::idempotency_key` with the originally issued token, as long
+/// as the request is repeated exactly. See [`frontends::idempotency`] for an implementation.
+///
+/// The store holds issued tokens, it should only retain them for the short time in which clients
+/// retry and must be protected like the issuer itself.
+///
+/// [`frontends::idempotency`]: ../frontends/idempotency/index.html
+pub trait IdempotencyStore {
+    /// The response remembered under a key, unless it has been forgotten.
+    fn replay(&mut self, key: &str) -> Option<IdempotentResponse>;
+
+    /// Remember the response to a request with a key.
+    fn remember(&mut self, key: &str, response: IdempotentResponse);
+}
+
+/// The response to a token request, as remembered by an `IdempotencyStore`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdempotentResponse {
+    /// A digest of the parameters and credentials of the request.
+    ///
+    /// A request reusing the key with a different digest is refused instead of replayed.
+    pub fingerprint: String,
+
+    /// The json encoded token response.
+    pub body: String,
+}
+
+/// Describes a request to a rate limiter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LimitedRequest<'a> {
+    /// The grant type of a token request, such as `refresh_token`.
+    ///
+    /// Other endpoints calling [`rate_limit`] name themselves here, for example `introspection`.
+    ///
+    /// [`rate_limit`]: fn.rate_limit.html
+    pub kind: &'a str,
+
+    /// The client claimed by the request.
+    ///
+    /// Requests are limited before the client is authenticated, so this is not yet verified.
+    pub client_id: Option<&'a str>,
+}
+
+/// The decision of a rate limiter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateDecision {
+    /// Process the request.
+    Allow,
+
+    /// Answer with a `slow_down` error, as defined for polling clients of the device flow.
+    SlowDown,
+
+    /// Answer with status 429 (Too Many Requests).
+    TooManyRequests {
+        /// Seconds after which the client may retry, sent as the `Retry-After` header.
+        retry_after: Option<u64>,
+    },
+}
+
+/// Context of a request, for risk based decisions.
+///
+/// `WebRequest` exposes the parameters of the protocol. Frontends attach anything else they know
+/// about a request here, for solicitors, policies and audit logging to consult. None of it is
+/// verified by the flows and some of it, such as the user agent, is chosen by the client.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    /// The address of the requesting party.
+    pub remote_addr: Option<String>,
+
+    /// The `User-Agent` header of the request.
+    pub user_agent: Option<String>,
+
+    /// The location of the requesting party, for example a country code from a geo IP lookup.
+    pub geo: Option<String>,
+
+    /// A fingerprint of the device, for example read from a cookie.
+    pub device: Option<String>,
+
+    /// The `Idempotency-Key` header of the request.
+    ///
+    /// Token requests with a key are replayed by the `IdempotencyStore` of the endpoint.
+    pub idempotency_key: Option<String>,
+
+    /// Further signals, such as the score of a bot detection service.
+    pub attributes: HashMap<String, String>,
 }
 
 /// Abstraction of web requests with several different abstractions and constructors needed by an
@@ -310,6 +790,13 @@ pub trait WebRequest {
     /// Contents of the authorization header or none if none exists. An Err value indicates a
     /// malformed header or request.
     fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error>;
+
+    /// Context of the request beyond its parameters, such as the remote address.
+    ///
+    /// The default attaches no context.
+    fn context(&mut self) -> Option<Cow<'_, RequestContext>> {
+        None
+    }
 }
 
 /// Response representation into which the Request is transformed by the code_grant types.
@@ -337,6 +824,44 @@ pub trait WebResponse {
 
     /// Json repsonse data, with media type `aplication/json.
     fn body_json(&mut self, data: &str) -> Result<(), Self::Error>;
+
+    /// Set a header not covered by the other methods, replacing previous values of the name.
+    ///
+    /// The default ignores the header, for responses which can not represent arbitrary headers.
+    fn set_header(&mut self, _name: &str, _value: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Forbid caches from storing the response.
+    ///
+    /// Called by the flows on all responses of the token endpoint, which contain tokens or
+    /// details about them, as required by RFC 6749 section 5.1. Override it to add further
+    /// security headers to those responses.
+    fn no_store(&mut self) -> Result<(), Self::Error> {
+        self.set_header("Cache-Control", "no-store")?;
+        self.set_header("Pragma", "no-cache")
+    }
+
+    /// Set the response status to 403 and add a `WWW-Authenticate` header.
+    ///
+    /// Used by the resource flow for tokens lacking the necessary scope. The default
+    /// implementation falls back to status 401, for responses without a way to set arbitrary
+    /// status codes.
+    fn forbidden(&mut self, header_value: &str) -> Result<(), Self::Error> {
+        self.unauthorized(header_value)
+    }
+
+    /// Set the response status to 429 and add a `Retry-After` header, if the delay is known.
+    ///
+    /// The default implementation falls back to status 400, for responses without a way to set
+    /// arbitrary status codes.
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.client_error()?;
+        match retry_after {
+            Some(seconds) => self.set_header("Retry-After", &seconds.to_string()),
+            None => Ok(()),
+        }
+    }
 }
 
 /// Intermediate trait to flow specific extensions.
@@ -435,6 +960,130 @@ pub trait Endpoint<Request: WebRequest> {
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         None
     }
+
+    /// An outbox receiving a record of each decided grant.
+    ///
+    /// Returning `None` is the default implementation and disables recording.
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<Request>> {
+        None
+    }
+
+    /// Customizes the responses of issued tokens.
+    ///
+    /// Returning `None` is the default implementation and leaves responses unchanged.
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<Request>> {
+        None
+    }
+
+    /// Explains the errors reported to clients.
+    ///
+    /// Returning `None` is the default implementation and sends errors without description.
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<Request>> {
+        None
+    }
+
+    /// Remembers the consent of resource owners.
+    ///
+    /// Returning `None` is the default implementation and asks owners for consent on every request.
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        None
+    }
+
+    /// Decides the scope of authorization requests per client and owner.
+    ///
+    /// Returning `None` is the default implementation and grants the negotiated scope.
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<Request>> {
+        None
+    }
+
+    /// Collects metrics of the flows.
+    ///
+    /// Returning `None` is the default implementation and collects nothing.
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        None
+    }
+
+    /// Limits the rate of requests to the token endpoint.
+    ///
+    /// Returning `None` is the default implementation and processes all requests.
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<Request>> {
+        None
+    }
+
+    /// Decides over grants just before they are issued.
+    ///
+    /// Returning `None` is the default implementation and issues all grants unchanged.
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        None
+    }
+
+    /// Remembers token responses, to replay them to retried requests.
+    ///
+    /// Returning `None` is the default implementation and processes retries as new requests.
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        None
+    }
+
+    /// Tracks the authenticated sessions of resource owners.
+    ///
+    /// With a store the authorization flow honors the `prompt=none` and `max_age` parameters and
+    /// records the clients issued a code in each session, see [`primitives::session`]. Returning
+    /// `None` is the default implementation and leaves authentication to the owner solicitor.
+    ///
+    /// [`primitives::session`]: ../primitives/session/index.html
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        None
+    }
+
+    /// Provides the nonces that DPoP proofs must contain, checked by [`require_dpop_nonce`].
+    ///
+    /// Returning `None` is the default implementation and accepts proofs without a nonce.
+    ///
+    /// [`require_dpop_nonce`]: fn.require_dpop_nonce.html
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        None
+    }
+
+    /// Binds issued tokens to the context of requests, checked by the resource flow.
+    ///
+    /// Returning `None` is the default implementation and issues unbound tokens.
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        None
+    }
+
+    /// Holds the authorization requests pushed by clients, see [`primitives::pushed`].
+    ///
+    /// With a store the authorization flow only accepts requests referring to pushed parameters
+    /// with their `request_uri` and ignores all others. Returning `None` is the default
+    /// implementation and takes the parameters from the query of each request.
+    ///
+    /// [`primitives::pushed`]: ../primitives/pushed/index.html
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        None
+    }
+
+    /// The issuer identifier of the server, added to authorization responses as `iss`.
+    ///
+    /// Clients talking to several servers compare it to the issuer they sent the request to, which
+    /// defends against mix-up attacks (RFC 9207). Returning `None` is the default implementation
+    /// and omits the parameter.
+    fn issuer_identifier(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl GrantRecord {
+    /// A record without any information on the client, owner or scope.
+    pub fn new(event: GrantEvent, outcome: GrantOutcome) -> Self {
+        GrantRecord {
+            event,
+            outcome,
+            client_id: None,
+            owner_id: None,
+            scope: None,
+            error: None,
+        }
+    }
 }
 
 impl<'a> Template<'a> {
@@ -539,6 +1188,223 @@ fn reborrow<'a, T>(opt: &'a mut Option<&mut T>) -> Option<&'a mut T> {
     }
 }
 
+/// Pass a record to the outbox of the endpoint, if there is one.
+fn record<R: WebRequest, E: Endpoint<R>>(endpoint: &mut E, request: &mut R, record: GrantRecord) {
+    trace::grant(&record);
+    if let Some(metrics) = endpoint.metrics() {
+        metrics.grant(&record);
+    }
+    if let Some(outbox) = endpoint.outbox() {
+        outbox.record(request, record);
+    }
+}
+
+/// Encode a token response, with the members added by the customizer of the endpoint.
+fn token_json<R: WebRequest, E: Endpoint<R>>(
+    endpoint: &mut E, request: &mut R, mut response: TokenResponse, grant: &GrantRecord,
+) -> String {
+    if let Some(customizer) = endpoint.token_customizer() {
+        customizer.customize(request, grant, &mut response.additional);
+        response
+            .additional
+            .retain(|key, _| !TokenResponse::MEMBERS.contains(&key.as_str()));
+    }
+
+    serde_json::to_string(&response).unwrap()
+}
+
+/// Let the customizer of the endpoint explain an authorization error, keeping its kind.
+fn explain_authorization_error<R: WebRequest, E: Endpoint<R>>(
+    endpoint: &mut E, request: &mut R, error: &mut AuthorizationError,
+) {
+    trace::error(error.kind().as_ref());
+    if let Some(metrics) = endpoint.metrics() {
+        metrics.error(error.kind().as_ref());
+    }
+    if let Some(customizer) = endpoint.error_customizer() {
+        let kind = error.kind();
+        customizer.authorization_error(request, error);
+        error.set_type(kind);
+    }
+}
+
+/// Let the customizer of the endpoint explain an access token error, keeping its kind.
+pub(crate) fn explain_access_token_error<R: WebRequest, E: Endpoint<R>>(
+    endpoint: &mut E, request: &mut R, error: &mut AccessTokenError,
+) {
+    trace::error(error.kind().as_ref());
+    if let Some(metrics) = endpoint.metrics() {
+        metrics.error(error.kind().as_ref());
+    }
+    if let Some(customizer) = endpoint.error_customizer() {
+        let kind = error.kind();
+        customizer.access_token_error(request, error);
+        error.set_type(kind);
+    }
+}
+
+/// A digest of the credentials and body parameters of a token request.
+///
+/// Identifies the request to an `IdempotencyStore`, a retry must send the same parameters in any
+/// order with the same `Authorization` header. Returns `None` if the request is malformed.
+pub fn request_fingerprint<R: WebRequest>(request: &mut R) -> Option<String> {
+    let mut digest = Sha256::new();
+    match request.authheader().ok()? {
+        Some(header) => {
+            digest.update([1]);
+            digest_part(&mut digest, &header);
+        }
+        None => digest.update([0]),
+    }
+
+    let body = request.urlbody().ok()?.normalize();
+    let mut parameters: Vec<_> = body.iter().collect();
+    parameters.sort_unstable();
+    for (key, value) in parameters {
+        digest_part(&mut digest, key);
+        digest_part(&mut digest, value);
+    }
+
+    Some(URL_SAFE_NO_PAD.encode(digest.finalize()))
+}
+
+/// Add a length prefixed part to a digest, so that parts can not run into each other.
+fn digest_part(digest: &mut Sha256, part: &str) {
+    digest.update((part.len() as u64).to_be_bytes());
+    digest.update(part.as_bytes());
+}
+
+/// Consult the rate limiter of the endpoint, answering the request if it is limited.
+///
+/// The flows of the token endpoint call this before processing a request. Handlers of extension
+/// grants and of other endpoints, such as token introspection, can call it in the same way.
+/// Returns `None` if the request is to be processed.
+pub fn rate_limit<R: WebRequest, E: Endpoint<R>>(
+    endpoint: &mut E, request: &mut R, limited: LimitedRequest,
+) -> Option<Result<R::Response, E::Error>> {
+    let decision = endpoint.rate_limiter()?.check(request, &limited);
+    let mut error = match decision {
+        RateDecision::Allow => return None,
+        RateDecision::SlowDown => AccessTokenError::new(AccessTokenErrorType::SlowDown),
+        RateDecision::TooManyRequests { .. } => {
+            let mut error = AccessTokenError::new(AccessTokenErrorType::InvalidRequest);
+            error.explain("Too many requests");
+            error
+        }
+    };
+
+    explain_access_token_error(endpoint, request, &mut error);
+    Some(rate_limited(
+        endpoint,
+        request,
+        decision,
+        ErrorDescription::new(error),
+    ))
+}
+
+fn rate_limited<R: WebRequest, E: Endpoint<R>>(
+    endpoint: &mut E, request: &mut R, decision: RateDecision, mut json: ErrorDescription,
+) -> Result<R::Response, E::Error> {
+    let mut response = endpoint.response(
+        request,
+        InnerTemplate::BadRequest {
+            access_token_error: Some(json.description()),
+        }
+        .into(),
+    )?;
+    match decision {
+        RateDecision::TooManyRequests { retry_after } => response.too_many_requests(retry_after),
+        _ => response.client_error(),
+    }
+    .map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&json.to_json())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
+
+/// Check the nonce of a DPoP proof, answering the request if it is not current.
+///
+/// The `nonce` is the claim of a proof that the caller has already verified, this crate does not
+/// validate DPoP proofs. Requests with a missing or stale nonce are answered with a
+/// `use_dpop_nonce` error and the current nonce in the `DPoP-Nonce` header, to retry with. Returns
+/// `None` if the request is to be processed, also when the endpoint has no [`nonce_store`].
+///
+/// [`nonce_store`]: trait.Endpoint.html#method.nonce_store
+pub fn require_dpop_nonce<R: WebRequest, E: Endpoint<R>>(
+    endpoint: &mut E, request: &mut R, nonce: Option<&str>,
+) -> Option<Result<R::Response, E::Error>> {
+    let store = endpoint.nonce_store()?;
+    if nonce.is_some_and(|nonce| store.check(nonce)) {
+        return None;
+    }
+
+    let current = match store.current() {
+        Ok(current) => current,
+        Err(_) => return Some(Err(endpoint.error(OAuthError::PrimitiveError))),
+    };
+
+    let mut error = AccessTokenError::new(AccessTokenErrorType::UseDpopNonce);
+    error.explain("Authorization server requires nonce in DPoP proof");
+    explain_access_token_error(endpoint, request, &mut error);
+    Some(nonce_required(
+        endpoint,
+        request,
+        &current,
+        ErrorDescription::new(error),
+    ))
+}
+
+fn nonce_required<R: WebRequest, E: Endpoint<R>>(
+    endpoint: &mut E, request: &mut R, current: &str, mut json: ErrorDescription,
+) -> Result<R::Response, E::Error> {
+    let mut response = endpoint.response(
+        request,
+        InnerTemplate::BadRequest {
+            access_token_error: Some(json.description()),
+        }
+        .into(),
+    )?;
+    response.client_error().map_err(|err| endpoint.web_error(err))?;
+    response
+        .set_header("DPoP-Nonce", current)
+        .map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&json.to_json())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
+
+/// The identifier of the grant extension holding the binding of a token.
+const BINDING: &str = "context_binding";
+
+/// The binding of tokens issued to the request, if the endpoint has a binding policy.
+fn context_binding<R: WebRequest, E: Endpoint<R>>(endpoint: &mut E, request: &mut R) -> Option<String> {
+    let policy = endpoint.binding_policy()?;
+    let context = request.context()?;
+    policy.bind(&context)
+}
+
+/// Keep the binding of a `BindingPolicy` with the grant, replacing any previous one.
+///
+/// The flows of the token endpoint bind their grants themselves. Handlers of extension grants
+/// issuing tokens can bind them in the same way.
+pub fn bind_grant(grant: &mut Grant, binding: &str) {
+    let stored = Value::private(Some(binding.to_owned()));
+    grant.extensions.set_raw(BINDING.to_owned(), stored);
+}
+
+/// The binding of a grant, if it has one.
+pub fn grant_binding(grant: &Grant) -> Option<&str> {
+    let (_, binding) = grant
+        .extensions
+        .private()
+        .find(|(identifier, _)| *identifier == BINDING)?;
+    binding
+}
+
 impl<W: WebRequest> WebRequest for &mut W {
     type Error = W::Error;
     type Response = W::Response;
@@ -554,6 +1420,10 @@ impl<W: WebRequest> WebRequest for &mut W {
     fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
         (**self).authheader()
     }
+
+    fn context(&mut self) -> Option<Cow<'_, RequestContext>> {
+        (**self).context()
+    }
 }
 
 impl<R: WebRequest, E: Endpoint<R>> Endpoint<R> for &mut E {
@@ -594,6 +1464,62 @@ impl<R: WebRequest, E: Endpoint<R>> Endpoint<R> for &mut E {
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         (**self).extension()
     }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<R>> {
+        (**self).outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<R>> {
+        (**self).token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<R>> {
+        (**self).error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        (**self).consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<R>> {
+        (**self).scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        (**self).metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<R>> {
+        (**self).rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        (**self).grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        (**self).idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        (**self).session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        (**self).nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        (**self).binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        (**self).pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        (**self).issuer_identifier()
+    }
 }
 
 impl<R: WebRequest, E: Endpoint<R>> Endpoint<R> for Box<E> {
@@ -634,6 +1560,62 @@ impl<R: WebRequest, E: Endpoint<R>> Endpoint<R> for Box<E> {
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         (**self).extension()
     }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<R>> {
+        (**self).outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<R>> {
+        (**self).token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<R>> {
+        (**self).error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        (**self).consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<R>> {
+        (**self).scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        (**self).metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<R>> {
+        (**self).rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        (**self).grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        (**self).idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        (**self).session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        (**self).nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        (**self).binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        (**self).pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        (**self).issuer_identifier()
+    }
 }
 
 impl Extension for () {}
@@ -644,6 +1626,14 @@ impl<'a, W: WebRequest, S: OwnerSolicitor<W> + 'a + ?Sized> OwnerSolicitor<W> fo
     ) -> OwnerConsent<W::Response> {
         (**self).check_consent(request, solicitation)
     }
+
+    fn owner_id(&mut self, request: &mut W) -> Option<String> {
+        (**self).owner_id(request)
+    }
+
+    fn session_id(&mut self, request: &mut W) -> Option<String> {
+        (**self).session_id(request)
+    }
 }
 
 impl<W: WebRequest, S: OwnerSolicitor<W> + ?Sized> OwnerSolicitor<W> for Box<S> {
@@ -652,6 +1642,180 @@ impl<W: WebRequest, S: OwnerSolicitor<W> + ?Sized> OwnerSolicitor<W> for Box<S>
     ) -> OwnerConsent<W::Response> {
         (**self).check_consent(request, solicitation)
     }
+
+    fn owner_id(&mut self, request: &mut W) -> Option<String> {
+        (**self).owner_id(request)
+    }
+
+    fn session_id(&mut self, request: &mut W) -> Option<String> {
+        (**self).session_id(request)
+    }
+}
+
+impl<'a, W: WebRequest, O: Outbox<W> + 'a + ?Sized> Outbox<W> for &'a mut O {
+    fn record(&mut self, request: &mut W, record: GrantRecord) {
+        (**self).record(request, record)
+    }
+}
+
+impl<W: WebRequest, O: Outbox<W> + ?Sized> Outbox<W> for Box<O> {
+    fn record(&mut self, request: &mut W, record: GrantRecord) {
+        (**self).record(request, record)
+    }
+}
+
+impl<'a, W, C> TokenResponseCustomizer<W> for &'a mut C
+where
+    W: WebRequest,
+    C: TokenResponseCustomizer<W> + 'a + ?Sized,
+{
+    fn customize(
+        &mut self, request: &mut W, grant: &GrantRecord, additional: &mut HashMap<String, JsonValue>,
+    ) {
+        (**self).customize(request, grant, additional)
+    }
+}
+
+impl<W: WebRequest, C: TokenResponseCustomizer<W> + ?Sized> TokenResponseCustomizer<W> for Box<C> {
+    fn customize(
+        &mut self, request: &mut W, grant: &GrantRecord, additional: &mut HashMap<String, JsonValue>,
+    ) {
+        (**self).customize(request, grant, additional)
+    }
+}
+
+impl<'a, W: WebRequest, C: ErrorCustomizer<W> + 'a + ?Sized> ErrorCustomizer<W> for &'a mut C {
+    fn authorization_error(&mut self, request: &mut W, error: &mut AuthorizationError) {
+        (**self).authorization_error(request, error)
+    }
+
+    fn access_token_error(&mut self, request: &mut W, error: &mut AccessTokenError) {
+        (**self).access_token_error(request, error)
+    }
+}
+
+impl<W: WebRequest, C: ErrorCustomizer<W> + ?Sized> ErrorCustomizer<W> for Box<C> {
+    fn authorization_error(&mut self, request: &mut W, error: &mut AuthorizationError) {
+        (**self).authorization_error(request, error)
+    }
+
+    fn access_token_error(&mut self, request: &mut W, error: &mut AccessTokenError) {
+        (**self).access_token_error(request, error)
+    }
+}
+
+impl<'a, W: WebRequest, P: ScopePolicy<W> + 'a + ?Sized> ScopePolicy<W> for &'a mut P {
+    fn decide(
+        &mut self, request: &mut W, owner_id: Option<&str>, solicitation: Solicitation,
+    ) -> Option<Scope> {
+        (**self).decide(request, owner_id, solicitation)
+    }
+}
+
+impl<W: WebRequest, P: ScopePolicy<W> + ?Sized> ScopePolicy<W> for Box<P> {
+    fn decide(
+        &mut self, request: &mut W, owner_id: Option<&str>, solicitation: Solicitation,
+    ) -> Option<Scope> {
+        (**self).decide(request, owner_id, solicitation)
+    }
+}
+
+impl<'a, P: GrantPolicy + 'a + ?Sized> GrantPolicy for &'a mut P {
+    fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+        (**self).decide(event, grant)
+    }
+}
+
+impl<P: GrantPolicy + ?Sized> GrantPolicy for Box<P> {
+    fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+        (**self).decide(event, grant)
+    }
+}
+
+impl<'a, S: IdempotencyStore + 'a + ?Sized> IdempotencyStore for &'a mut S {
+    fn replay(&mut self, key: &str) -> Option<IdempotentResponse> {
+        (**self).replay(key)
+    }
+
+    fn remember(&mut self, key: &str, response: IdempotentResponse) {
+        (**self).remember(key, response)
+    }
+}
+
+impl<S: IdempotencyStore + ?Sized> IdempotencyStore for Box<S> {
+    fn replay(&mut self, key: &str) -> Option<IdempotentResponse> {
+        (**self).replay(key)
+    }
+
+    fn remember(&mut self, key: &str, response: IdempotentResponse) {
+        (**self).remember(key, response)
+    }
+}
+
+impl<M: Metrics + ?Sized> Metrics for &M {
+    fn grant(&self, record: &GrantRecord) {
+        (**self).grant(record)
+    }
+
+    fn error(&self, code: &str) {
+        (**self).error(code)
+    }
+
+    fn issuer_call(&self, call: IssuerCall, elapsed: Duration) {
+        (**self).issuer_call(call, elapsed)
+    }
+
+    fn token_issued(&self, until: DateTime<Utc>) {
+        (**self).token_issued(until)
+    }
+
+    fn swept(&self, store: &str, removed: usize) {
+        (**self).swept(store, removed)
+    }
+}
+
+impl<M: Metrics + ?Sized> Metrics for Box<M> {
+    fn grant(&self, record: &GrantRecord) {
+        (**self).grant(record)
+    }
+
+    fn error(&self, code: &str) {
+        (**self).error(code)
+    }
+
+    fn issuer_call(&self, call: IssuerCall, elapsed: Duration) {
+        (**self).issuer_call(call, elapsed)
+    }
+
+    fn token_issued(&self, until: DateTime<Utc>) {
+        (**self).token_issued(until)
+    }
+
+    fn swept(&self, store: &str, removed: usize) {
+        (**self).swept(store, removed)
+    }
+}
+
+impl<M: Metrics + ?Sized> Metrics for Arc<M> {
+    fn grant(&self, record: &GrantRecord) {
+        (**self).grant(record)
+    }
+
+    fn error(&self, code: &str) {
+        (**self).error(code)
+    }
+
+    fn issuer_call(&self, call: IssuerCall, elapsed: Duration) {
+        (**self).issuer_call(call, elapsed)
+    }
+
+    fn token_issued(&self, until: DateTime<Utc>) {
+        (**self).token_issued(until)
+    }
+
+    fn swept(&self, store: &str, removed: usize) {
+        (**self).swept(store, removed)
+    }
 }
 
 impl<W: WebRequest> Scopes<W> for [Scope] {
@@ -676,12 +1840,20 @@ impl<'a, W: WebRequest, S: Scopes<W> + 'a + ?Sized> Scopes<W> for &'a mut S {
     fn scopes(&mut self, request: &mut W) -> &[Scope] {
         (**self).scopes(request)
     }
+
+    fn matching(&mut self) -> ScopeMatching {
+        (**self).matching()
+    }
 }
 
 impl<W: WebRequest, S: Scopes<W> + ?Sized> Scopes<W> for Box<S> {
     fn scopes(&mut self, request: &mut W) -> &[Scope] {
         (**self).scopes(request)
     }
+
+    fn matching(&mut self) -> ScopeMatching {
+        (**self).matching()
+    }
 }
 
 impl<'a> From<InnerTemplate<'a>> for Template<'a> {
@@ -689,13 +1861,3 @@ impl<'a> From<InnerTemplate<'a>> for Template<'a> {
         Template { inner }
     }
 }
-
-/// Check if the header is an authorization method
-pub fn is_authorization_method<'h>(header: &'h str, method: &'static str) -> Option<&'h str> {
-    let header_method = header.get(..method.len())?;
-    if header_method.eq_ignore_ascii_case(method) {
-        Some(&header[method.len()..])
-    } else {
-        None
-    }
-}
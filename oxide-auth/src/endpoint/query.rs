@@ -48,18 +48,40 @@ pub unsafe trait QueryParameter {
 /// This gives rise to a custom `Cow<QueryParameter>` instance by requiring that normalization into
 /// memory with unrelated lifetime is always possible.
 ///
-/// Internally a hashmap but this may change due to optimizations.
-#[derive(Clone, Debug, Default)]
+/// Parsed keys and values are copied into a single shared text instead of one allocation each, so
+/// that parsing and cloning a set of parameters costs a constant number of allocations. Internally
+/// a list sorted by key but this may change due to optimizations.
+#[derive(Clone)]
 pub struct NormalizedParameter {
-    /// The value is `None` if the key appeared at least twice.
-    inner: HashMap<Cow<'static, str>, Option<Cow<'static, str>>>,
+    /// The text that `Text::Shared` ranges refer to.
+    text: Arc<str>,
+
+    /// Sorted by key. The value is `None` if the key appeared at least twice.
+    inner: Vec<(Text, Option<Text>)>,
+}
+
+/// A key or value of a `NormalizedParameter`.
+#[derive(Clone, Debug)]
+enum Text {
+    /// A range of the shared text.
+    Shared(usize, usize),
+
+    /// Inserted on its own after the shared text was completed.
+    Owned(Cow<'static, str>),
+}
+
+/// Collects the parameters into a shared text before sorting them.
+#[derive(Default)]
+struct Builder {
+    text: String,
+    pairs: Vec<(Text, Option<Text>)>,
 }
 
 unsafe impl QueryParameter for NormalizedParameter {
     fn unique_value(&self, key: &str) -> Option<Cow<'_, str>> {
-        self.inner
-            .get(key)
-            .and_then(|val| val.as_ref().map(Cow::as_ref).map(Cow::Borrowed))
+        let index = self.position(key).ok()?;
+        let value = self.inner[index].1.as_ref()?;
+        Some(Cow::Borrowed(value.resolve(&self.text)))
     }
 
     fn normalize(&self) -> NormalizedParameter {
@@ -73,16 +95,150 @@ impl NormalizedParameter {
         NormalizedParameter::default()
     }
 
+    /// Parse an `application/x-www-form-urlencoded` query or body.
+    ///
+    /// Parameters that are not valid UTF-8 are decoded lossily, with replacement characters.
+    pub fn from_urlencoded(encoded: &[u8]) -> Self {
+        let mut builder = Builder::with_capacity(encoded.len());
+        for (key, value) in url::form_urlencoded::parse(encoded) {
+            builder.push(&key, &value);
+        }
+        builder.finish()
+    }
+
     /// Insert a key-value-pair or mark key as dead if already present.
     ///
     /// Since each key must appear at most once, we do not remove it from the map but instead mark
     /// the key as having a duplicate entry.
     pub fn insert_or_poison(&mut self, key: Cow<'static, str>, val: Cow<'static, str>) {
-        let unique_val = Some(val);
+        match self.position(&key) {
+            Ok(index) => self.inner[index].1 = None,
+            Err(index) => self
+                .inner
+                .insert(index, (Text::Owned(key), Some(Text::Owned(val)))),
+        }
+    }
+
+    /// The number of distinct keys, including those that appeared more than once.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether there are no parameters at all.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Iterate over the parameters with a unique value, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        let text = &self.text;
+        self.inner
+            .iter()
+            .filter_map(move |(key, val)| val.as_ref().map(|val| (key.resolve(text), val.resolve(text))))
+    }
+
+    /// Read the members of a JSON object, as sent by some clients instead of a urlencoded form.
+    ///
+    /// Strings are taken verbatim while numbers and booleans are represented by their literal.
+    /// Arrays and objects have no urlencoded equivalent and are kept as compact JSON text, to be
+    /// parsed again by the consumer. Members with a `null` value are treated as absent. Returns
+    /// `None` if the body is not a JSON object.
+    pub fn from_json(body: &[u8]) -> Option<Self> {
+        use serde_json::Value;
+
+        let object = match serde_json::from_slice(body) {
+            Ok(Value::Object(object)) => object,
+            _ => return None,
+        };
+
+        let mut builder = Builder::with_capacity(body.len());
+        for (key, value) in object {
+            match value {
+                Value::Null => continue,
+                Value::String(value) => builder.push(&key, &value),
+                Value::Bool(_) | Value::Number(_) | Value::Array(_) | Value::Object(_) => {
+                    builder.push(&key, &value.to_string())
+                }
+            }
+        }
+
+        Some(builder.finish())
+    }
+
+    fn position(&self, key: &str) -> Result<usize, usize> {
+        let text = &self.text;
         self.inner
-            .entry(key)
-            .and_modify(|val| *val = None)
-            .or_insert(unique_val);
+            .binary_search_by(|(probe, _)| probe.resolve(text).cmp(key))
+    }
+}
+
+impl Default for NormalizedParameter {
+    fn default() -> Self {
+        NormalizedParameter {
+            text: Arc::from(""),
+            inner: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for NormalizedParameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = &self.text;
+        f.debug_map()
+            .entries(
+                self.inner
+                    .iter()
+                    .map(|(key, val)| (key.resolve(text), val.as_ref().map(|val| val.resolve(text)))),
+            )
+            .finish()
+    }
+}
+
+impl Text {
+    fn resolve<'a>(&'a self, text: &'a str) -> &'a str {
+        match self {
+            Text::Shared(start, end) => &text[*start..*end],
+            Text::Owned(owned) => owned,
+        }
+    }
+}
+
+impl Builder {
+    fn with_capacity(capacity: usize) -> Self {
+        Builder {
+            text: String::with_capacity(capacity),
+            pairs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, key: &str, val: &str) {
+        let key = self.append(key);
+        let val = self.append(val);
+        self.pairs.push((key, Some(val)));
+    }
+
+    fn append(&mut self, part: &str) -> Text {
+        let start = self.text.len();
+        self.text.push_str(part);
+        Text::Shared(start, self.text.len())
+    }
+
+    /// Sort the parameters by key and poison the keys that appeared more than once.
+    fn finish(self) -> NormalizedParameter {
+        let Builder { text, mut pairs } = self;
+        pairs.sort_by(|(a, _), (b, _)| a.resolve(&text).cmp(b.resolve(&text)));
+        pairs.dedup_by(|(key, _), (retained, val)| {
+            let duplicate = key.resolve(&text) == retained.resolve(&text);
+            if duplicate {
+                *val = None;
+            }
+            duplicate
+        });
+
+        NormalizedParameter {
+            text: Arc::from(text),
+            inner: pairs,
+        }
     }
 }
 
@@ -103,7 +259,7 @@ impl<'de> de::Deserialize<'de> for NormalizedParameter {
     where
         D: Deserializer<'de>,
     {
-        struct Visitor(NormalizedParameter);
+        struct Visitor(Builder);
 
         impl<'a> de::Visitor<'a> for Visitor {
             type Value = NormalizedParameter;
@@ -116,19 +272,80 @@ impl<'de> de::Deserialize<'de> for NormalizedParameter {
             where
                 A: de::SeqAccess<'a>,
             {
-                while let Some((key, value)) = access.next_element::<(String, String)>()? {
-                    self.0.insert_or_poison(key.into(), value.into())
+                while let Some((key, value)) = access.next_element_seed(PairSeed(&mut self.0))? {
+                    self.0.pairs.push((key, Some(value)));
                 }
 
-                Ok(self.0)
+                Ok(self.0.finish())
             }
         }
 
-        let visitor = Visitor(NormalizedParameter::default());
+        let visitor = Visitor(Builder::default());
         deserializer.deserialize_seq(visitor)
     }
 }
 
+/// Appends a key-value-pair to the shared text, without allocating borrowed strings.
+struct PairSeed<'b>(&'b mut Builder);
+
+/// Appends a string to the shared text.
+struct TextSeed<'b>(&'b mut Builder);
+
+impl<'de, 'b> de::DeserializeSeed<'de> for PairSeed<'b> {
+    type Value = (Text, Text);
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+impl<'de, 'b> de::Visitor<'de> for PairSeed<'b> {
+    type Value = (Text, Text);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a key-value-pair")
+    }
+
+    fn visit_seq<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let key = access
+            .next_element_seed(TextSeed(&mut *self.0))?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let value = access
+            .next_element_seed(TextSeed(&mut *self.0))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok((key, value))
+    }
+}
+
+impl<'de, 'b> de::DeserializeSeed<'de> for TextSeed<'b> {
+    type Value = Text;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de, 'b> de::Visitor<'de> for TextSeed<'b> {
+    type Value = Text;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(self.0.append(value))
+    }
+}
+
 impl<K, V> FromIterator<(K, V)> for NormalizedParameter
 where
     K: Into<Cow<'static, str>>,
@@ -138,10 +355,13 @@ where
     where
         T: IntoIterator<Item = (K, V)>,
     {
-        let mut target = NormalizedParameter::default();
-        iter.into_iter()
-            .for_each(|(k, v)| target.insert_or_poison(k.into(), v.into()));
-        target
+        let mut builder = Builder::default();
+        for (key, val) in iter {
+            builder
+                .pairs
+                .push((Text::Owned(key.into()), Some(Text::Owned(val.into()))));
+        }
+        builder.finish()
     }
 }
 
@@ -189,19 +409,13 @@ where
     }
 
     fn normalize(&self) -> NormalizedParameter {
-        let inner = self
-            .iter()
-            .filter_map(|(key, val)| {
-                val.get_unique().map(|value| {
-                    (
-                        Cow::Owned(key.borrow().to_string()),
-                        Some(Cow::Owned(value.to_string())),
-                    )
-                })
-            })
-            .collect();
-
-        NormalizedParameter { inner }
+        let mut builder = Builder::default();
+        for (key, val) in self.iter() {
+            if let Some(value) = val.get_unique() {
+                builder.push(key.borrow(), value);
+            }
+        }
+        builder.finish()
     }
 }
 
@@ -226,16 +440,11 @@ where
     }
 
     fn normalize(&self) -> NormalizedParameter {
-        let mut params = NormalizedParameter::default();
-        self.iter()
-            .map(|(key, val)| {
-                (
-                    Cow::Owned(key.borrow().to_string()),
-                    Cow::Owned(val.borrow().to_string()),
-                )
-            })
-            .for_each(|(key, val)| params.insert_or_poison(key, val));
-        params
+        let mut builder = Builder::default();
+        for (key, val) in self.iter() {
+            builder.push(key.borrow(), val.borrow());
+        }
+        builder.finish()
     }
 }
 
@@ -345,4 +554,51 @@ mod test {
         let _ = (&HashMap::<String, Box<String>>::new()) as &dyn QueryParameter;
         let _ = (&HashMap::<String, Box<[Cow<'static, str>]>>::new()) as &dyn QueryParameter;
     }
+
+    #[test]
+    fn json_parameters() {
+        let body = br#"{"client_id":"client","max_age":60,"redirect_uris":["https://a"],"state":null}"#;
+        let params = NormalizedParameter::from_json(body).unwrap();
+        assert_eq!(params.unique_value("client_id").as_deref(), Some("client"));
+        assert_eq!(params.unique_value("max_age").as_deref(), Some("60"));
+        assert_eq!(
+            params.unique_value("redirect_uris").as_deref(),
+            Some(r#"["https://a"]"#)
+        );
+        assert_eq!(params.unique_value("state"), None);
+
+        assert!(NormalizedParameter::from_json(b"[]").is_none());
+        assert!(NormalizedParameter::from_json(b"client_id=client").is_none());
+    }
+
+    #[test]
+    fn urlencoded_parameters() {
+        let mut params =
+            NormalizedParameter::from_urlencoded(b"scope=read+write&state=a%26b&code=1&code=2");
+        assert_eq!(params.unique_value("scope").as_deref(), Some("read write"));
+        assert_eq!(params.unique_value("state").as_deref(), Some("a&b"));
+        assert_eq!(params.unique_value("code"), None);
+        assert_eq!(params.len(), 3);
+
+        params.insert_or_poison("client_id".into(), "client".into());
+        params.insert_or_poison("state".into(), "other".into());
+        assert_eq!(params.unique_value("client_id").as_deref(), Some("client"));
+        assert_eq!(params.unique_value("state"), None);
+
+        let mut pairs: Vec<_> = params.iter().collect();
+        pairs.sort();
+        assert_eq!(pairs, [("client_id", "client"), ("scope", "read write")]);
+    }
+
+    #[test]
+    fn normalized_duplicates() {
+        let pairs = vec![("a", "1"), ("b", "2"), ("a", "3")];
+        let params = pairs.normalize();
+        assert_eq!(params.unique_value("a"), None);
+        assert_eq!(params.unique_value("b").as_deref(), Some("2"));
+
+        let params: NormalizedParameter = pairs.into_iter().collect();
+        assert_eq!(params.unique_value("a"), None);
+        assert_eq!(params.clone().unique_value("b").as_deref(), Some("2"));
+    }
 }
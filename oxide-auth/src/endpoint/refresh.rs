@@ -4,25 +4,35 @@ use std::str::from_utf8;
 
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
+use zeroize::Zeroizing;
 
 use crate::code_grant::refresh::{refresh, Error, Endpoint as RefreshEndpoint, Request};
-use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::primitives::{grant::Grant, registrar::Registrar, issuer::Issuer};
 use super::{
-    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
-    is_authorization_method,
+    bind_grant, context_binding, Endpoint, GrantEvent, GrantPolicy, GrantOutcome, GrantRecord,
+    InnerTemplate, LimitedRequest, OAuthError, QueryParameter, WebRequest, WebResponse,
+    is_authorization_method, explain_access_token_error, rate_limit, record, token_json, trace,
 };
 
 /// Takes requests from clients to refresh their access tokens.
+///
+/// A client may request a narrower scope than originally granted, but never one exceeding it. The
+/// response omits the `scope` if it is exactly the requested one, unless [`always_echo_scope`] is
+/// enabled.
+///
+/// [`always_echo_scope`]: #method.always_echo_scope
 pub struct RefreshFlow<E, R>
 where
     E: Endpoint<R>,
     R: WebRequest,
 {
     endpoint: WrappedRefresh<E, R>,
+    always_echo_scope: bool,
 }
 
 struct WrappedRefresh<E: Endpoint<R>, R: WebRequest> {
     inner: E,
+    binding: Option<String>,
     r_type: PhantomData<R>,
 }
 
@@ -45,7 +55,7 @@ enum InitError<E> {
     Internal(E),
 }
 
-struct Authorization(String, Vec<u8>);
+struct Authorization(String, Zeroizing<Vec<u8>>);
 
 impl<E, R> RefreshFlow<E, R>
 where
@@ -77,11 +87,20 @@ where
         Ok(RefreshFlow {
             endpoint: WrappedRefresh {
                 inner: endpoint,
+                binding: None,
                 r_type: PhantomData,
             },
+            always_echo_scope: false,
         })
     }
 
+    /// Include the `scope` in every response, even when it is exactly the requested scope.
+    ///
+    /// Some clients do not remember the scope they requested and rely on the response instead.
+    pub fn always_echo_scope(&mut self, always: bool) {
+        self.always_echo_scope = always;
+    }
+
     /// Use the checked endpoint to refresh a token.
     ///
     /// ## Panics
@@ -89,22 +108,74 @@ where
     /// When the registrar, authorizer, or issuer returned by the endpoint is suddenly
     /// `None` when previously it was `Some(_)`.
     pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let refreshed = refresh(&mut self.endpoint, &WrappedRequest::new(&mut request));
+        let span = trace::refresh();
+        let _entered = span.enter();
+
+        if let Some(limited) = self.rate_limited(&mut request) {
+            return limited;
+        }
+
+        self.endpoint.binding = context_binding(&mut self.endpoint.inner, &mut request);
+        let (refreshed, client_id) = {
+            let wrapped = WrappedRequest::new(&mut request);
+            let refreshed = refresh(&mut self.endpoint, &wrapped);
+            (refreshed, wrapped.requesting_client())
+        };
 
         let token = match refreshed {
-            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Err(error) => {
+                let outcome = match error {
+                    Error::Primitive => GrantOutcome::Failed,
+                    _ => GrantOutcome::Denied,
+                };
+                let refused = GrantRecord {
+                    client_id,
+                    error: error.code(),
+                    ..GrantRecord::new(GrantEvent::Refresh, outcome)
+                };
+                record(&mut self.endpoint.inner, &mut request, refused);
+                return token_error(&mut self.endpoint.inner, &mut request, error);
+            }
             Ok(token) => token,
         };
 
+        let refreshed = GrantRecord {
+            client_id: Some(token.client_id().to_owned()),
+            owner_id: token.owner_id().map(str::to_owned),
+            scope: Some(token.scope().clone()),
+            ..GrantRecord::new(GrantEvent::Refresh, GrantOutcome::Issued)
+        };
+        let mut response = token.to_response();
+        if self.always_echo_scope {
+            response.scope = Some(token.scope().to_string());
+        }
+        let body = token_json(&mut self.endpoint.inner, &mut request, response, &refreshed);
+        record(&mut self.endpoint.inner, &mut request, refreshed);
+
         let mut response = self
             .endpoint
             .inner
             .response(&mut request, InnerTemplate::Ok.into())?;
         response
-            .body_json(&token.to_json())
+            .no_store()
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        response
+            .body_json(&body)
             .map_err(|err| self.endpoint.inner.web_error(err))?;
         Ok(response)
     }
+
+    /// Consult the rate limiter of the endpoint, if there is one.
+    fn rate_limited(&mut self, request: &mut R) -> Option<Result<R::Response, E::Error>> {
+        // Only parse the client from the request when there is a limiter to consult.
+        self.endpoint.inner.rate_limiter()?;
+        let client_id = WrappedRequest::new(request).requesting_client();
+        let limited = LimitedRequest {
+            kind: "refresh_token",
+            client_id: client_id.as_deref(),
+        };
+        rate_limit(&mut self.endpoint.inner, request, limited)
+    }
 }
 
 fn token_error<E: Endpoint<R>, R: WebRequest>(
@@ -112,6 +183,7 @@ fn token_error<E: Endpoint<R>, R: WebRequest>(
 ) -> Result<R::Response, E::Error> {
     Ok(match error {
         Error::Invalid(mut json) => {
+            explain_access_token_error(endpoint, request, json.description());
             let mut response = endpoint.response(
                 request,
                 InnerTemplate::BadRequest {
@@ -120,12 +192,14 @@ fn token_error<E: Endpoint<R>, R: WebRequest>(
                 .into(),
             )?;
             response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
         Error::Unauthorized(mut json, scheme) => {
+            explain_access_token_error(endpoint, request, json.description());
             let mut response = endpoint.response(
                 request,
                 InnerTemplate::Unauthorized {
@@ -137,6 +211,7 @@ fn token_error<E: Endpoint<R>, R: WebRequest>(
             response
                 .unauthorized(&scheme)
                 .map_err(|err| endpoint.web_error(err))?;
+            response.no_store().map_err(|err| endpoint.web_error(err))?;
             response
                 .body_json(&json.to_json())
                 .map_err(|err| endpoint.web_error(err))?;
@@ -179,6 +254,14 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
         }
     }
 
+    /// The client as claimed by the request, whether it could be authenticated or not.
+    fn requesting_client(&self) -> Option<String> {
+        match &self.authorization {
+            Some(Authorization(username, _)) => Some(username.clone()),
+            None => self.body.unique_value("client_id").map(Cow::into_owned),
+        }
+    }
+
     fn parse_header(header: Cow<str>) -> Result<Authorization, InitError<R::Error>> {
         let authorization = {
             let auth_data = match is_authorization_method(&header, "Basic ") {
@@ -188,7 +271,7 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
 
             let combined = match STANDARD.decode(auth_data) {
                 Err(_) => return Err(InitError::Malformed),
-                Ok(vec) => vec,
+                Ok(vec) => Zeroizing::new(vec),
             };
 
             let mut split = combined.splitn(2, |&c| c == b':');
@@ -206,7 +289,7 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
                 Ok(client) => client,
             };
 
-            Authorization(client.to_string(), passwd.to_vec())
+            Authorization(client.to_string(), Zeroizing::new(passwd.to_vec()))
         };
 
         Ok(authorization)
@@ -221,6 +304,16 @@ impl<E: Endpoint<R>, R: WebRequest> RefreshEndpoint for WrappedRefresh<E, R> {
     fn issuer(&mut self) -> &mut dyn Issuer {
         self.inner.issuer_mut().unwrap()
     }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn bind(&mut self, grant: &mut Grant) {
+        if let Some(binding) = &self.binding {
+            bind_grant(grant, binding);
+        }
+    }
 }
 
 impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
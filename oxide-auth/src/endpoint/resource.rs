@@ -1,7 +1,8 @@
 use std::borrow::Cow;
 
 use crate::code_grant::resource::{
-    protect, Error as ResourceError, Endpoint as ResourceEndpoint, Request as ResourceRequest,
+    protect, AccessFailure, Authenticate, Error as ResourceError, ErrorCode,
+    Endpoint as ResourceEndpoint, Request as ResourceRequest, TokenLocations,
 };
 use crate::primitives::grant::Grant;
 
@@ -14,6 +15,9 @@ where
     R: WebRequest,
 {
     endpoint: WrappedResource<E, R>,
+    realm: Option<String>,
+    scope_hints: bool,
+    locations: TokenLocations,
 }
 
 struct WrappedResource<E: Endpoint<R>, R: WebRequest>(E, PhantomData<R>);
@@ -29,6 +33,9 @@ struct WrappedRequest<R: WebRequest> {
     ///
     /// Actual parsing of the authorization header is done in the lower level.
     error: Option<R::Error>,
+
+    /// Why the token locations of the request are not accepted.
+    rejection: Option<String>,
 }
 
 struct Scoped<'a, E: 'a, R: 'a> {
@@ -62,9 +69,33 @@ where
 
         Ok(ResourceFlow {
             endpoint: WrappedResource(endpoint, PhantomData),
+            realm: None,
+            scope_hints: true,
+            locations: TokenLocations::default(),
         })
     }
 
+    /// Name the protection space of the resource in the `realm` of challenges.
+    pub fn realm(&mut self, realm: &str) {
+        self.realm = Some(realm.to_owned());
+    }
+
+    /// Include the scope necessary to access the resource in challenges.
+    ///
+    /// Enabled by default, which helps clients to request a suitable token. Disable it to not
+    /// reveal the scopes of the resource to unauthorized clients.
+    pub fn scope_hints(&mut self, hints: bool) {
+        self.scope_hints = hints;
+    }
+
+    /// Choose the locations from which the access token is accepted.
+    ///
+    /// By default, the token is accepted from the `Authorization` header and the `access_token`
+    /// parameter of form encoded bodies but not from the query.
+    pub fn token_locations(&mut self, locations: TokenLocations) {
+        self.locations = locations;
+    }
+
     /// Use the checked endpoint to check for authorization for a resource.
     ///
     /// ## Panics
@@ -72,21 +103,86 @@ where
     /// When the issuer returned by the endpoint is suddenly `None` when previously it
     /// was `Some(_)`.
     pub fn execute(&mut self, mut request: R) -> Result<Grant, Result<R::Response, E::Error>> {
-        let protected = {
-            let wrapped = WrappedRequest::new(&mut request);
+        let span = trace::resource();
+        let _entered = span.enter();
+
+        let (protected, rejection) = {
+            let wrapped = WrappedRequest::new(&mut request, self.locations);
 
             let mut scoped = Scoped {
                 request: &mut request,
                 endpoint: &mut self.endpoint.0,
             };
 
-            protect(&mut scoped, &wrapped)
+            (protect(&mut scoped, &wrapped), wrapped.rejection)
+        };
+
+        match protected {
+            Ok(grant) => {
+                if let Some(refused) = self.check_binding(&mut request, &grant) {
+                    return Err(refused);
+                }
+
+                trace::client(&grant.client_id);
+                trace::owner(&grant.owner_id);
+                trace::outcome("allowed");
+                Ok(grant)
+            }
+            Err(err) => Err(self.denied(&mut request, err, rejection)),
+        }
+    }
+
+    /// Check the binding of the token against the context of the request, if it has one.
+    ///
+    /// Returns `None` if the request is to be processed.
+    fn check_binding(
+        &mut self, request: &mut R, grant: &Grant,
+    ) -> Option<Result<R::Response, E::Error>> {
+        let binding = grant_binding(grant)?;
+        let decision = {
+            let context = request.context();
+            let policy = self.endpoint.0.binding_policy()?;
+            policy.check(binding, context.as_deref())
+        };
+
+        let description = match decision {
+            BindingDecision::Allow => return None,
+            BindingDecision::Reject => "The access token is bound to a different context",
+            BindingDecision::Reauthenticate => {
+                let issuer = self.endpoint.0.issuer_mut().unwrap();
+                if issuer.revoke_grant(&grant.owner_id, &grant.client_id).is_err() {
+                    return Some(self.denied(request, ResourceError::PrimitiveError, None));
+                }
+                "The access token is bound to a different context and was revoked"
+            }
         };
 
-        protected.map_err(|err| self.denied(&mut request, err))
+        let error = ResourceError::AccessDenied {
+            failure: AccessFailure {
+                code: Some(ErrorCode::InvalidToken),
+            },
+            authenticate: Authenticate {
+                realm: None,
+                scope: None,
+            },
+        };
+        Some(self.denied(request, error, Some(description.to_owned())))
     }
 
-    fn denied(&mut self, request: &mut R, error: ResourceError) -> Result<R::Response, E::Error> {
+    fn denied(
+        &mut self, request: &mut R, error: ResourceError, rejection: Option<String>,
+    ) -> Result<R::Response, E::Error> {
+        trace::outcome(match error {
+            ResourceError::PrimitiveError => "failed",
+            _ => "denied",
+        });
+        if let Some(code) = error.code() {
+            trace::error(code);
+            if let Some(metrics) = self.endpoint.0.metrics() {
+                metrics.error(code);
+            }
+        }
+
         let template = match &error {
             ResourceError::AccessDenied { .. } => InnerTemplate::Unauthorized {
                 error: None,
@@ -104,28 +200,62 @@ where
             }
         };
 
+        let mut challenge = match error.challenge() {
+            Some(challenge) => challenge,
+            None => return Err(self.endpoint.0.error(OAuthError::PrimitiveError)),
+        };
+        if let Some(realm) = &self.realm {
+            challenge = challenge.with_realm(realm);
+        }
+        if !self.scope_hints {
+            challenge = challenge.without_scope();
+        }
+        if rejection.is_some() {
+            challenge.error_description = rejection;
+        }
+
         let mut response = self.endpoint.0.response(request, template.into())?;
-        response
-            .unauthorized(&error.www_authenticate())
-            .map_err(|err| self.endpoint.0.web_error(err))?;
+        let header = challenge.to_string();
+        let rendered = if challenge.is_forbidden() {
+            response.forbidden(&header)
+        } else {
+            response.unauthorized(&header)
+        };
+        rendered.map_err(|err| self.endpoint.0.web_error(err))?;
 
         Ok(response)
     }
 }
 
 impl<R: WebRequest> WrappedRequest<R> {
-    fn new(request: &mut R) -> Self {
-        let token = match request.authheader() {
+    fn new(request: &mut R, locations: TokenLocations) -> Self {
+        let header = match request.authheader() {
             // TODO: this is unecessarily wasteful, we always clone.
             Ok(Some(token)) => Some(token.into_owned()),
             Ok(None) => None,
             Err(error) => return Self::from_error(error),
         };
 
+        // A missing query or a body that is not a form carries no token.
+        let form = match request.urlbody() {
+            Ok(body) => body.unique_value("access_token").map(Cow::into_owned),
+            Err(_) => None,
+        };
+        let query = match request.query() {
+            Ok(query) => query.unique_value("access_token").map(Cow::into_owned),
+            Err(_) => None,
+        };
+
+        let (authorization, rejection) = match locations.select(header, form, query) {
+            Ok(token) => (token, None),
+            Err(rejection) => (None, Some(rejection)),
+        };
+
         WrappedRequest {
             request: PhantomData,
-            authorization: token,
+            authorization,
             error: None,
+            rejection,
         }
     }
 
@@ -134,6 +264,7 @@ impl<R: WebRequest> WrappedRequest<R> {
             request: PhantomData,
             authorization: None,
             error: Some(error),
+            rejection: None,
         }
     }
 }
@@ -143,6 +274,10 @@ impl<'a, E: Endpoint<R> + 'a, R: WebRequest + 'a> ResourceEndpoint for Scoped<'a
         self.endpoint.scopes().unwrap().scopes(self.request)
     }
 
+    fn scope_matching(&mut self) -> ScopeMatching {
+        self.endpoint.scopes().unwrap().matching()
+    }
+
     fn issuer(&mut self) -> &dyn Issuer {
         self.endpoint.issuer_mut().unwrap()
     }
@@ -150,7 +285,7 @@ impl<'a, E: Endpoint<R> + 'a, R: WebRequest + 'a> ResourceEndpoint for Scoped<'a
 
 impl<R: WebRequest> ResourceRequest for WrappedRequest<R> {
     fn valid(&self) -> bool {
-        self.error.is_none()
+        self.error.is_none() && self.rejection.is_none()
     }
 
     fn token(&self) -> Option<Cow<'_, str>> {
@@ -1,18 +1,36 @@
 use crate::primitives::authorizer::{AuthMap, Authorizer};
 use crate::primitives::issuer::TokenMap;
 use crate::primitives::grant::{Grant, Extensions};
-use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
-
-use crate::frontends::simple::endpoint::access_token_flow;
+use crate::primitives::registrar::{
+    Client, ClientAuthMethod, ClientBuilder, ClientMap, GrantType, RefreshPolicy, RegisteredUrl,
+};
+use crate::primitives::scope::Scope;
+
+use crate::primitives::nonce::{NonceStore, NonceWindow};
+
+use crate::endpoint::{
+    require_dpop_nonce, AccessTokenFlow, BindingDecision, GrantDecision, GrantEvent, GrantPolicy,
+    RequestContext, ResourceFlow,
+};
+use crate::frontends::binding::NetworkBinding;
+use crate::frontends::idempotency::IdempotencyMap;
+use crate::frontends::ratelimit::{client_key, WindowLimiter};
+use crate::frontends::simple::endpoint::{
+    access_token_flow, Bound, Generic, Governed, Idempotent, Limited, Nonced, Vacant,
+};
+use crate::frontends::simple::request::{Body as SimpleBody, Request, Response, Status as SimpleStatus};
 
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
 
 use base64::{self, Engine};
 use base64::engine::general_purpose::STANDARD;
 use chrono::{Utc, Duration};
 use serde_json;
 
-use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::{
+    assert_no_store, Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery,
+};
 use super::defaults::*;
 
 struct AccessTokenSetup {
@@ -143,6 +161,8 @@ impl AccessTokenSetup {
             Status::BadRequest => (),
             _ => panic!("Expected error status, got {:?}", response),
         }
+
+        assert_no_store(response);
     }
 
     fn test_simple_error(&mut self, request: CraftedRequest) {
@@ -170,6 +190,7 @@ impl AccessTokenSetup {
 
     fn assert_ok_access_token(&mut self, response: CraftedResponse) {
         assert_eq!(response.status, Status::Ok);
+        assert_no_store(&response);
     }
 }
 
@@ -697,3 +718,451 @@ fn private_duplicate_authentication() {
 
     setup.test_simple_error(valid_public);
 }
+
+#[test]
+fn rate_limited_client() {
+    let mut setup = AccessTokenSetup::private_client();
+    let limiter = WindowLimiter::new(1, Duration::minutes(1), client_key);
+    let endpoint = Generic {
+        registrar: &setup.registrar,
+        authorizer: &mut setup.authorizer,
+        issuer: &mut setup.issuer,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    };
+    let mut flow = AccessTokenFlow::prepare(Limited::new(endpoint, limiter)).unwrap();
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("code", &setup.authtoken),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    let response = flow
+        .execute(request.clone())
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+
+    // Over the limit, the default response is a client error with the delay as a header.
+    let response = flow.execute(request).expect("Expected non-error response");
+    AccessTokenSetup::assert_json_error_set(&response);
+    assert!(response.headers.contains_key("Retry-After"));
+}
+
+#[test]
+fn shared_primitives() {
+    let setup = AccessTokenSetup::private_client();
+    let registrar = Arc::new(RwLock::new(setup.registrar));
+    let authorizer = Arc::new(Mutex::new(setup.authorizer));
+    let issuer = Arc::new(RwLock::new(setup.issuer));
+
+    // Each request constructs its own endpoint from clones, no guard is held across the flow.
+    let endpoint = Generic {
+        registrar: registrar.clone(),
+        authorizer: authorizer.clone(),
+        issuer: issuer.clone(),
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    };
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("code", &setup.authtoken),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    // Reading the registrar elsewhere does not block the flow.
+    let clients = registrar.read().unwrap();
+    let response = AccessTokenFlow::prepare(endpoint)
+        .unwrap()
+        .execute(request)
+        .expect("Expected non-error response");
+    drop(clients);
+    assert_eq!(response.status, Status::Ok);
+
+    assert!(authorizer.lock().unwrap().grants().next().is_none());
+    assert_eq!(issuer.read().unwrap().tokens().count(), 1);
+}
+
+#[test]
+fn dpop_nonce_required() {
+    let mut setup = AccessTokenSetup::private_client();
+    let endpoint = Generic {
+        registrar: &setup.registrar,
+        authorizer: &mut setup.authorizer,
+        issuer: &mut setup.issuer,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    };
+    let mut endpoint = Nonced::new(endpoint, NonceWindow::new());
+    let mut request = CraftedRequest::default();
+
+    // A proof without a nonce is answered with the one to retry with.
+    let response = require_dpop_nonce(&mut endpoint, &mut request, None)
+        .expect("Expected the request to be answered")
+        .expect("Expected non-error response");
+    AccessTokenSetup::assert_json_error_set(&response);
+    assert_no_store(&response);
+    let body: serde_json::Value = match response.body {
+        Some(Body::Json(ref body)) => serde_json::from_str(body).unwrap(),
+        ref other => panic!("Expected json body, got {:?}", other),
+    };
+    assert_eq!(body["error"], "use_dpop_nonce");
+
+    let nonce = response.headers["DPoP-Nonce"].clone();
+    assert_eq!(endpoint.store.current(), Ok(nonce.clone()));
+    assert!(require_dpop_nonce(&mut endpoint, &mut request, Some(&nonce)).is_none());
+    assert!(require_dpop_nonce(&mut endpoint, &mut request, Some("stale")).is_some());
+
+    // Without a store, no nonce is required.
+    assert!(require_dpop_nonce(&mut endpoint.inner, &mut request, None).is_none());
+}
+
+/// Restricts token grants to a scope, or denies them.
+struct Restrict(Option<&'static str>);
+
+impl GrantPolicy for Restrict {
+    fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+        assert_eq!(event, GrantEvent::Token);
+        match self.0 {
+            Some(scope) => {
+                grant.scope = scope.parse().unwrap();
+                GrantDecision::Allow
+            }
+            None => GrantDecision::Deny,
+        }
+    }
+}
+
+impl AccessTokenSetup {
+    fn test_governed(&mut self, policy: Restrict) -> serde_json::Value {
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        };
+        let mut flow = AccessTokenFlow::prepare(Governed::new(endpoint, policy)).unwrap();
+
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", "authorization_code"),
+                    ("code", &self.authtoken),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: Some("Basic ".to_string() + &self.basic_authorization),
+        };
+
+        let response = flow.execute(request).expect("Expected non-error response");
+        match response.body {
+            Some(Body::Json(ref json)) => serde_json::from_str(json).unwrap(),
+            other => panic!("Expected json encoded body, got {:?}", other),
+        }
+    }
+}
+
+impl AccessTokenSetup {
+    /// Issue a token for a grant of `scope` to a client with a refresh policy.
+    fn test_refresh_policy(policy: RefreshPolicy, scope: &'static str) -> serde_json::Value {
+        let mut setup = AccessTokenSetup::private_client();
+        setup.registrar.register_client(
+            Client::confidential(
+                EXAMPLE_CLIENT_ID,
+                RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+                EXAMPLE_SCOPE.parse().unwrap(),
+                EXAMPLE_PASSPHRASE.as_bytes(),
+            )
+            .with_refresh_policy(policy),
+        );
+        setup.authtoken = setup
+            .authorizer
+            .authorize(Grant {
+                client_id: EXAMPLE_CLIENT_ID.to_string(),
+                owner_id: EXAMPLE_OWNER_ID.to_string(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                scope: scope.parse().unwrap(),
+                until: Utc::now() + Duration::hours(1),
+                extensions: Extensions::new(),
+            })
+            .unwrap();
+
+        setup.test_governed(Restrict(Some(scope)))
+    }
+}
+
+#[test]
+fn refresh_policy_offline_access() {
+    let content = AccessTokenSetup::test_refresh_policy(RefreshPolicy::OfflineAccess, "default");
+    assert!(content["access_token"].is_string());
+    assert!(content.get("refresh_token").is_none());
+
+    let content = AccessTokenSetup::test_refresh_policy(RefreshPolicy::OfflineAccess, "offline_access");
+    assert!(content["refresh_token"].is_string());
+    assert_eq!(content["scope"], "offline_access");
+}
+
+#[test]
+fn refresh_policy_never() {
+    let content = AccessTokenSetup::test_refresh_policy(RefreshPolicy::Never, "offline_access");
+    assert!(content["access_token"].is_string());
+    assert!(content.get("refresh_token").is_none());
+    assert_eq!(content["scope"], "");
+}
+
+impl AccessTokenSetup {
+    /// Register the confidential client again, restricted by its registration.
+    fn restricted(restrict: impl FnOnce(ClientBuilder) -> ClientBuilder) -> Self {
+        let mut setup = AccessTokenSetup::private_client();
+        let builder = Client::builder(EXAMPLE_CLIENT_ID)
+            .redirect_uri(RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()))
+            .default_scope(EXAMPLE_SCOPE.parse().unwrap())
+            .passphrase(EXAMPLE_PASSPHRASE.as_bytes());
+        setup
+            .registrar
+            .register_client(restrict(builder).build().unwrap());
+        setup
+    }
+
+    /// Redeem the code, with the credentials in the body instead of the authorization header.
+    fn redeem(&mut self, in_body: bool) -> CraftedResponse {
+        let mut body = vec![
+            ("grant_type", "authorization_code"),
+            ("code", &self.authtoken),
+            ("redirect_uri", EXAMPLE_REDIRECT_URI),
+        ];
+        let auth = if in_body {
+            body.extend([
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("client_secret", EXAMPLE_PASSPHRASE),
+            ]);
+            None
+        } else {
+            Some("Basic ".to_string() + &self.basic_authorization)
+        };
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(body.iter().to_single_value_query()),
+            auth,
+        };
+
+        let mut flow = access_token_flow(&self.registrar, &mut self.authorizer, &mut self.issuer);
+        flow.allow_credentials_in_body(true);
+        flow.execute(request).expect("Expected non-error response")
+    }
+}
+
+fn json_error(response: &CraftedResponse) -> String {
+    match &response.body {
+        Some(Body::Json(json)) => {
+            let content: HashMap<String, String> = serde_json::from_str(json).unwrap();
+            content["error"].clone()
+        }
+        other => panic!("Expected json encoded body, got {:?}", other),
+    }
+}
+
+#[test]
+fn restricted_grant_types() {
+    let mut setup =
+        AccessTokenSetup::restricted(|client| client.grant_types([GrantType::ClientCredentials]));
+    let response = setup.redeem(false);
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(json_error(&response), "unauthorized_client");
+
+    let mut setup =
+        AccessTokenSetup::restricted(|client| client.grant_types([GrantType::AuthorizationCode]));
+    let response = setup.redeem(false);
+    setup.assert_ok_access_token(response);
+}
+
+#[test]
+fn registered_auth_method() {
+    let mut setup =
+        AccessTokenSetup::restricted(|client| client.auth_method(ClientAuthMethod::ClientSecretPost));
+    let response = setup.redeem(false);
+    assert_eq!(response.status, Status::Unauthorized);
+    assert_eq!(json_error(&response), "invalid_client");
+
+    // The failed attempt did not redeem the code.
+    let response = setup.redeem(true);
+    setup.assert_ok_access_token(response);
+
+    let mut setup =
+        AccessTokenSetup::restricted(|client| client.auth_method(ClientAuthMethod::ClientSecretBasic));
+    let response = setup.redeem(true);
+    assert_eq!(json_error(&response), "invalid_client");
+}
+
+#[test]
+fn grant_policy_narrows_scope() {
+    let mut setup = AccessTokenSetup::private_client();
+    let content = setup.test_governed(Restrict(Some("default")));
+    assert_eq!(content["scope"], "default");
+}
+
+#[test]
+fn grant_policy_denies() {
+    let mut setup = AccessTokenSetup::private_client();
+    let content = setup.test_governed(Restrict(None));
+    assert_eq!(content["error"], "invalid_grant");
+}
+
+impl AccessTokenSetup {
+    fn keyed_request(&self, code: &str, key: Option<&str>) -> Request {
+        Request {
+            urlbody: [
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+            auth: Some("Basic ".to_string() + &self.basic_authorization),
+            context: Some(RequestContext {
+                idempotency_key: key.map(str::to_owned),
+                ..RequestContext::default()
+            }),
+            ..Request::default()
+        }
+    }
+}
+
+fn json_body(response: Response) -> serde_json::Value {
+    match response.body {
+        Some(SimpleBody::Json(ref json)) => serde_json::from_str(json).unwrap(),
+        other => panic!("Expected json encoded body, got {:?}", other),
+    }
+}
+
+#[test]
+fn idempotent_retry() {
+    let mut setup = AccessTokenSetup::private_client();
+    let code = setup.authtoken.clone();
+    let first = setup.keyed_request(&code, Some("retry"));
+    let other = setup.keyed_request("other", Some("retry"));
+    let unkeyed = setup.keyed_request(&code, None);
+
+    let endpoint = Generic {
+        registrar: &setup.registrar,
+        authorizer: &mut setup.authorizer,
+        issuer: &mut setup.issuer,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    };
+    let store = IdempotencyMap::new(Duration::minutes(5), 16);
+    let mut flow = AccessTokenFlow::prepare(Idempotent::new(endpoint, store)).unwrap();
+
+    let issued = json_body(flow.execute(first.clone()).expect("Expected non-error response"));
+    assert!(issued["access_token"].is_string());
+
+    // The retry is answered with the same token although the code was already redeemed.
+    let retried = flow.execute(first).expect("Expected non-error response");
+    assert_eq!(retried.status, SimpleStatus::Ok);
+    assert_eq!(json_body(retried), issued);
+
+    let reused = json_body(flow.execute(other).expect("Expected non-error response"));
+    assert_eq!(reused["error"], "invalid_request");
+    assert!(reused["error_description"]
+        .as_str()
+        .unwrap()
+        .contains("idempotency key"));
+
+    let unkeyed = json_body(flow.execute(unkeyed).expect("Expected non-error response"));
+    assert!(unkeyed["error"].is_string());
+    assert!(unkeyed.get("access_token").is_none());
+}
+
+fn bound_context(remote_addr: &str, user_agent: &str) -> Option<RequestContext> {
+    Some(RequestContext {
+        remote_addr: Some(remote_addr.to_owned()),
+        user_agent: Some(user_agent.to_owned()),
+        ..RequestContext::default()
+    })
+}
+
+fn resource_request(token: &str, remote_addr: &str) -> Request {
+    Request {
+        auth: Some(format!("Bearer {}", token)),
+        context: bound_context(remote_addr, "Client/1.0"),
+        ..Request::default()
+    }
+}
+
+#[test]
+fn binds_tokens_to_context() {
+    let mut setup = AccessTokenSetup::private_client();
+    let mut request = setup.keyed_request(&setup.authtoken.clone(), None);
+    request.context = bound_context("198.51.100.7:53124", "Client/1.0");
+    let scopes: [Scope; 1] = [EXAMPLE_SCOPE.parse().unwrap()];
+
+    let endpoint = Generic {
+        registrar: &setup.registrar,
+        authorizer: &mut setup.authorizer,
+        issuer: &mut setup.issuer,
+        solicitor: Vacant,
+        scopes: &scopes[..],
+        response: Vacant,
+    };
+    let mut endpoint = Bound::new(endpoint, NetworkBinding::new());
+
+    let issued = AccessTokenFlow::prepare(&mut endpoint)
+        .unwrap()
+        .execute(request)
+        .expect("Expected non-error response");
+    let issued = json_body(issued);
+    let token = issued["access_token"].as_str().unwrap().to_owned();
+
+    let mut resource = ResourceFlow::prepare(&mut endpoint).unwrap();
+    assert!(resource
+        .execute(resource_request(&token, "198.51.100.42"))
+        .is_ok());
+
+    let refused = match resource.execute(resource_request(&token, "203.0.113.7")) {
+        Err(Ok(response)) => response,
+        other => panic!("Expected a refusal, got {:?}", other.map(|grant| grant.client_id)),
+    };
+    assert_eq!(refused.status, SimpleStatus::Unauthorized);
+    assert!(refused
+        .www_authenticate
+        .unwrap()
+        .contains("error=\"invalid_token\""));
+
+    // A rejected request does not affect the token in its own context.
+    assert!(resource.execute(resource_request(&token, "198.51.100.7")).is_ok());
+    drop(resource);
+
+    endpoint.policy = NetworkBinding::new().on_mismatch(BindingDecision::Reauthenticate);
+    let mut resource = ResourceFlow::prepare(&mut endpoint).unwrap();
+    assert!(resource.execute(resource_request(&token, "203.0.113.7")).is_err());
+    assert!(resource
+        .execute(resource_request(&token, "198.51.100.7"))
+        .is_err());
+}
@@ -1,13 +1,17 @@
 use std::collections::HashMap;
 
 use crate::primitives::authorizer::AuthMap;
+use crate::primitives::grant::Grant;
 use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
 
-use crate::endpoint::{OwnerSolicitor};
+use crate::endpoint::{
+    AuthorizationFlow, GrantDecision, GrantEvent, GrantPolicy, OwnerConsent, OwnerSolicitor,
+    QueryParameter, Solicitation,
+};
 
-use crate::frontends::simple::endpoint::authorization_flow;
+use crate::frontends::simple::endpoint::{authorization_flow, EndpointBuilder, Governed};
 
-use super::{CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
+use super::{CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
 use super::{Allow, Deny};
 use super::defaults::*;
 
@@ -284,3 +288,103 @@ fn auth_request_error_malformed_scope() {
 
     AuthorizationSetup::new().test_error_redirect(malformed_scope, Allow(EXAMPLE_OWNER_ID.to_string()));
 }
+
+#[test]
+fn auth_request_parameters_solicited() {
+    struct LoginHint;
+
+    impl OwnerSolicitor<CraftedRequest> for LoginHint {
+        fn check_consent(
+            &mut self, _: &mut CraftedRequest, solicitation: Solicitation,
+        ) -> OwnerConsent<CraftedResponse> {
+            let parameters = solicitation
+                .parameters()
+                .expect("Should expose the request parameters");
+            assert_eq!(parameters.unique_value("login_hint").as_deref(), Some("alice"));
+            assert!(parameters.iter().any(|(key, _)| key == "client_id"));
+            assert!(solicitation.extensions().is_some());
+            OwnerConsent::Authorized(EXAMPLE_OWNER_ID.to_string())
+        }
+    }
+
+    let hinted = CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ("login_hint", "alice"),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+
+    let mut setup = AuthorizationSetup::new();
+    let response = authorization_flow(&setup.registrar, &mut setup.authorizer, &mut LoginHint)
+        .execute(hinted)
+        .expect("Should not error");
+    assert_eq!(response.status, Status::Redirect);
+}
+
+#[test]
+fn auth_grant_policy() {
+    /// Restricts the grants of the example owner and denies all others.
+    struct Owners;
+
+    impl GrantPolicy for Owners {
+        fn decide(&mut self, event: GrantEvent, grant: &mut Grant) -> GrantDecision {
+            assert_eq!(event, GrantEvent::Code);
+            if grant.owner_id != EXAMPLE_OWNER_ID {
+                return GrantDecision::Deny;
+            }
+
+            grant.scope = "example".parse().unwrap();
+            GrantDecision::Allow
+        }
+    }
+
+    let request = CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+
+    let mut setup = AuthorizationSetup::new();
+    let mut execute = |owner: &str| {
+        let endpoint = EndpointBuilder::new()
+            .registrar(&setup.registrar)
+            .authorizer(&mut setup.authorizer)
+            .solicitor(Allow(owner.to_string()))
+            .build();
+        AuthorizationFlow::prepare(Governed::new(endpoint, Owners))
+            .expect("Should be able to prepare")
+            .execute(request.clone())
+            .expect("Should not error")
+    };
+
+    let response = execute("Intruder");
+    let location = response.location.expect("Should redirect");
+    assert!(location.as_str().contains("error=access_denied"));
+
+    let response = execute(EXAMPLE_OWNER_ID);
+    let location = response.location.expect("Should redirect");
+    assert!(location.as_str().contains("code="));
+
+    let scopes = setup
+        .authorizer
+        .grants()
+        .map(|(_, grant)| grant.scope.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(scopes, vec!["example".parse().unwrap()]);
+}
@@ -1,7 +1,9 @@
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
 
-use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::registrar::{
+    Client, ClientAuthMethod, ClientBuilder, ClientMap, GrantType, RegisteredUrl,
+};
 use crate::primitives::issuer::TokenMap;
 
 use crate::endpoint::{OwnerSolicitor};
@@ -61,6 +63,18 @@ impl ClientCredentialsSetup {
         }
     }
 
+    fn restricted(restrict: impl FnOnce(ClientBuilder) -> ClientBuilder) -> Self {
+        let mut setup = ClientCredentialsSetup::new();
+        let builder = Client::builder(EXAMPLE_CLIENT_ID)
+            .redirect_uri(RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()))
+            .default_scope(EXAMPLE_SCOPE.parse().unwrap())
+            .passphrase(EXAMPLE_PASSPHRASE.as_bytes());
+        setup
+            .registrar
+            .register_client(restrict(builder).build().unwrap());
+        setup
+    }
+
     fn test_success<S>(&mut self, request: CraftedRequest, mut solicitor: S)
     where
         S: OwnerSolicitor<CraftedRequest>,
@@ -362,3 +376,57 @@ fn client_credentials_request_error_malformed_scope() {
 
     setup.test_bad_request(malformed_scope, Allow(EXAMPLE_OWNER_ID.to_owned()));
 }
+
+#[test]
+fn client_credentials_restricted_grant_types() {
+    let mut setup =
+        ClientCredentialsSetup::restricted(|client| client.grant_types([GrantType::AuthorizationCode]));
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [("grant_type", "client_credentials")]
+                .iter()
+                .to_single_value_query(),
+        ),
+        auth: Some(format!("Basic {}", setup.basic_authorization)),
+    };
+
+    setup.test_bad_request(request.clone(), Allow(EXAMPLE_OWNER_ID.to_owned()));
+
+    let mut setup =
+        ClientCredentialsSetup::restricted(|client| client.grant_types([GrantType::ClientCredentials]));
+    setup.test_success(request, Allow(EXAMPLE_OWNER_ID.to_owned()));
+}
+
+#[test]
+fn client_credentials_registered_auth_method() {
+    let mut setup = ClientCredentialsSetup::restricted(|client| {
+        client.auth_method(ClientAuthMethod::ClientSecretPost)
+    });
+    setup.allow_credentials_in_body = true;
+    let basic = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [("grant_type", "client_credentials")]
+                .iter()
+                .to_single_value_query(),
+        ),
+        auth: Some(format!("Basic {}", setup.basic_authorization)),
+    };
+    let body = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "client_credentials"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("client_secret", EXAMPLE_PASSPHRASE),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: None,
+    };
+
+    setup.test_unauthorized(basic, Allow(EXAMPLE_OWNER_ID.to_owned()));
+    setup.test_success(body, Allow(EXAMPLE_OWNER_ID.to_owned()));
+}
@@ -0,0 +1,313 @@
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::consent::{Consent, ConsentMap, ConsentStore};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::scope::Scope;
+
+use crate::endpoint::{
+    AuthorizationFlow, OwnerConsent, OwnerSolicitor, RequestContext, ScopePolicy, Solicitation,
+};
+use crate::frontends::simple::endpoint::{EndpointBuilder, Error, Policed, Remembering};
+use crate::frontends::simple::request::{Request, Response};
+
+use super::{CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// Asks the logged in owner, who always approves and asks to remember the decision unless they
+/// only approve part of the scope.
+struct Session {
+    asked: usize,
+    approved: Option<Scope>,
+}
+
+impl OwnerSolicitor<CraftedRequest> for Session {
+    fn check_consent(
+        &mut self, _: &mut CraftedRequest, _: Solicitation,
+    ) -> OwnerConsent<CraftedResponse> {
+        self.asked += 1;
+        match self.approved.clone() {
+            Some(scope) => OwnerConsent::AuthorizedScope(EXAMPLE_OWNER_ID.to_string(), scope),
+            None => OwnerConsent::Remember(EXAMPLE_OWNER_ID.to_string()),
+        }
+    }
+
+    fn owner_id(&mut self, _: &mut CraftedRequest) -> Option<String> {
+        Some(EXAMPLE_OWNER_ID.to_string())
+    }
+}
+
+/// Grants the `default` scope only to admins.
+enum Role {
+    Admin,
+    User,
+    Banned,
+}
+
+impl ScopePolicy<CraftedRequest> for Role {
+    fn decide(
+        &mut self, _: &mut CraftedRequest, owner_id: Option<&str>, solicitation: Solicitation,
+    ) -> Option<Scope> {
+        assert_eq!(owner_id, Some(EXAMPLE_OWNER_ID));
+        let scope = solicitation.pre_grant().scope.clone();
+        match self {
+            Role::Admin => Some(scope),
+            Role::User => Some(scope.difference(&"default".parse().unwrap())),
+            Role::Banned => None,
+        }
+    }
+}
+
+/// Asks for consent only from known devices and drops the `default` scope for unknown networks.
+struct Risk {
+    seen: Option<RequestContext>,
+}
+
+impl OwnerSolicitor<Request> for Risk {
+    fn check_consent(&mut self, _: &mut Request, solicitation: Solicitation) -> OwnerConsent<Response> {
+        self.seen = solicitation.context().cloned();
+        match solicitation
+            .context()
+            .and_then(|context| context.device.as_deref())
+        {
+            Some("known") => OwnerConsent::Authorized(EXAMPLE_OWNER_ID.to_string()),
+            _ => OwnerConsent::Denied,
+        }
+    }
+}
+
+impl ScopePolicy<Request> for Risk {
+    fn decide(&mut self, _: &mut Request, _: Option<&str>, solicitation: Solicitation) -> Option<Scope> {
+        let context = solicitation.context()?;
+        let scope = solicitation.pre_grant().scope.clone();
+        match context.remote_addr.as_deref() {
+            Some(addr) if addr.starts_with("10.") => Some(scope),
+            _ => Some(scope.difference(&"default".parse().unwrap())),
+        }
+    }
+}
+
+struct ConsentSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    store: ConsentMap,
+    session: Session,
+    role: Role,
+}
+
+impl ConsentSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        ConsentSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_string())),
+            store: ConsentMap::new(),
+            session: Session {
+                asked: 0,
+                approved: None,
+            },
+            role: Role::Admin,
+        }
+    }
+
+    fn authorize(&mut self) {
+        let response = self.execute().expect("Should not error");
+
+        assert_eq!(response.status, Status::Redirect);
+        match response.location {
+            Some(ref url) if url.as_str().contains("code=") => (),
+            other => panic!("Expected a code: {:?}", other),
+        }
+    }
+
+    fn execute(&mut self) -> Result<CraftedResponse, Error<CraftedRequest>> {
+        let request = CraftedRequest {
+            query: Some(
+                [
+                    ("response_type", "code"),
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+
+        let endpoint = EndpointBuilder::new()
+            .registrar(&self.registrar)
+            .authorizer(&mut self.authorizer)
+            .solicitor(&mut self.session)
+            .build();
+        let endpoint = Remembering::new(endpoint, &mut self.store);
+        AuthorizationFlow::prepare(Policed::new(endpoint, &mut self.role))
+            .expect("Should be able to prepare")
+            .execute(request)
+    }
+
+    /// Executes a request with a context, asking `risk` for consent and its scope.
+    fn execute_in(&mut self, risk: &mut Risk, context: Option<RequestContext>) -> Response {
+        let request = Request {
+            query: [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+            context,
+            ..Request::default()
+        };
+
+        let mut policy = Risk { seen: None };
+        let endpoint = EndpointBuilder::new()
+            .registrar(&self.registrar)
+            .authorizer(&mut self.authorizer)
+            .solicitor(risk)
+            .build();
+        AuthorizationFlow::prepare(Policed::new(endpoint, &mut policy))
+            .expect("Should be able to prepare")
+            .execute(request)
+            .expect("Should not error")
+    }
+}
+
+#[test]
+fn consent_remembered() {
+    let mut setup = ConsentSetup::new();
+
+    setup.authorize();
+    assert_eq!(setup.session.asked, 1);
+
+    // The owner has approved this scope before, no need to ask again.
+    setup.authorize();
+    assert_eq!(setup.session.asked, 1);
+
+    assert!(setup.store.revoke(EXAMPLE_OWNER_ID, EXAMPLE_CLIENT_ID));
+    setup.authorize();
+    assert_eq!(setup.session.asked, 2);
+}
+
+#[test]
+fn consent_narrower_scope_asks_again() {
+    let mut setup = ConsentSetup::new();
+    setup.store.remember(Consent {
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        scope: "example".parse().unwrap(),
+    });
+
+    // The client is granted more than the owner approved.
+    setup.authorize();
+    assert_eq!(setup.session.asked, 1);
+
+    setup.authorize();
+    assert_eq!(setup.session.asked, 1);
+}
+
+#[test]
+fn consent_partial_scope() {
+    let mut setup = ConsentSetup::new();
+    setup.session.approved = Some("example".parse().unwrap());
+    setup.authorize();
+
+    let scopes = setup
+        .authorizer
+        .grants()
+        .map(|(_, grant)| grant.scope.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(scopes, vec!["example".parse().unwrap()]);
+    assert!(setup.store.consents(EXAMPLE_OWNER_ID).is_empty());
+}
+
+#[test]
+fn consent_partial_scope_exceeding() {
+    let mut setup = ConsentSetup::new();
+    setup.session.approved = Some("example admin".parse().unwrap());
+
+    assert!(setup.execute().is_err());
+    assert_eq!(setup.authorizer.grants().count(), 0);
+}
+
+#[test]
+fn consent_policy_narrows_scope() {
+    let mut setup = ConsentSetup::new();
+    setup.role = Role::User;
+    setup.authorize();
+
+    let scopes = setup
+        .authorizer
+        .grants()
+        .map(|(_, grant)| grant.scope.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(scopes, vec!["example".parse().unwrap()]);
+
+    // Only the narrowed scope was approved and remembered.
+    let consents = setup.store.consents(EXAMPLE_OWNER_ID);
+    assert_eq!(consents[0].scope, "example".parse().unwrap());
+}
+
+#[test]
+fn consent_policy_denies() {
+    let mut setup = ConsentSetup::new();
+    setup.role = Role::Banned;
+
+    let response = setup.execute().expect("Should not error");
+    assert_eq!(response.status, Status::Redirect);
+    match response.location {
+        Some(ref url) if url.as_str().contains("error=access_denied") => (),
+        other => panic!("Expected an access_denied error: {:?}", other),
+    }
+    assert_eq!(setup.session.asked, 0);
+    assert_eq!(setup.authorizer.grants().count(), 0);
+}
+
+#[test]
+fn consent_request_context() {
+    let mut setup = ConsentSetup::new();
+    let mut risk = Risk { seen: None };
+
+    let known = RequestContext {
+        remote_addr: Some("192.0.2.1".to_string()),
+        device: Some("known".to_string()),
+        ..RequestContext::default()
+    };
+    let response = setup.execute_in(&mut risk, Some(known.clone()));
+    assert!(response.location.unwrap().as_str().contains("code="));
+    assert_eq!(risk.seen.as_ref(), Some(&known));
+
+    let unknown = RequestContext {
+        device: Some("unknown".to_string()),
+        ..known.clone()
+    };
+    let response = setup.execute_in(&mut risk, Some(unknown));
+    assert!(response
+        .location
+        .unwrap()
+        .as_str()
+        .contains("error=access_denied"));
+
+    // Without any context, the policy denies outright and the solicitor is not asked.
+    risk.seen = None;
+    let response = setup.execute_in(&mut risk, None);
+    assert!(response
+        .location
+        .unwrap()
+        .as_str()
+        .contains("error=access_denied"));
+    assert!(risk.seen.is_none());
+
+    let scopes = setup
+        .authorizer
+        .grants()
+        .map(|(_, grant)| grant.scope.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(scopes, vec!["example".parse().unwrap()]);
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType, AuthorizationError};
+use crate::endpoint::{AccessTokenFlow, AuthorizationFlow, ErrorCustomizer, GrantRecord, RefreshFlow};
+use crate::frontends::simple::endpoint::{Customized, Explained, FnCustomizer, Generic, Vacant};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::Value;
+
+use super::{Allow, Body, Deny, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// Names the tenant and owner of the grant, and tries to replace the issued token.
+fn tenant(_: &mut CraftedRequest, grant: &GrantRecord, additional: &mut HashMap<String, Value>) {
+    additional.insert("tenant".to_owned(), "example".into());
+    additional.insert("owner".to_owned(), grant.owner_id.clone().into());
+    additional.insert("access_token".to_owned(), "Forged".into());
+}
+
+/// Points clients to a support page, in the language of the client.
+struct Support;
+
+impl ErrorCustomizer<CraftedRequest> for Support {
+    fn authorization_error(&mut self, _: &mut CraftedRequest, error: &mut AuthorizationError) {
+        error.explain_uri("https://support.example/errors".parse().unwrap());
+        assert!(!error.add_member("state", "forged"));
+        assert!(error.add_member("trace_id", "42"));
+    }
+
+    fn access_token_error(&mut self, _: &mut CraftedRequest, error: &mut AccessTokenError) {
+        error.explain("Der Code ist ungültig.");
+        error.explain_uri("https://support.example/errors".parse().unwrap());
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        assert!(!error.add_member("error", "forged"));
+        assert!(error.add_member("trace_id", "42"));
+    }
+}
+
+struct CustomizerSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    basic_authorization: String,
+}
+
+impl CustomizerSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let basic_authorization =
+            STANDARD.encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        CustomizerSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_string())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            basic_authorization: format!("Basic {}", basic_authorization),
+        }
+    }
+
+    fn endpoint(
+        &mut self,
+    ) -> Generic<&ClientMap, &mut AuthMap<TestGenerator>, &mut TokenMap<RandomGenerator>> {
+        Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        }
+    }
+
+    fn access_token(&mut self) -> TokenResponse {
+        let authorization = CraftedRequest {
+            query: Some(
+                [
+                    ("response_type", "code"),
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+        let endpoint = self.endpoint().with_solicitor(Allow(EXAMPLE_OWNER_ID.to_owned()));
+        let response = AuthorizationFlow::prepare(endpoint)
+            .unwrap()
+            .execute(authorization)
+            .expect("Should not error");
+        assert_eq!(response.status, Status::Redirect);
+
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", "authorization_code"),
+                    ("code", "AuthToken"),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: Some(self.basic_authorization.clone()),
+        };
+        let endpoint = Customized::new(self.endpoint(), FnCustomizer(tenant));
+        let response = AccessTokenFlow::prepare(endpoint)
+            .unwrap()
+            .execute(request)
+            .expect("Should not error");
+        token_response(response)
+    }
+
+    fn refresh(&mut self, refresh_token: &str) -> TokenResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [("grant_type", "refresh_token"), ("refresh_token", refresh_token)]
+                    .iter()
+                    .to_single_value_query(),
+            ),
+            auth: Some(self.basic_authorization.clone()),
+        };
+        let endpoint = Customized::new(self.endpoint(), FnCustomizer(tenant));
+        let response = RefreshFlow::prepare(endpoint)
+            .unwrap()
+            .execute(request)
+            .expect("Should not error");
+        token_response(response)
+    }
+}
+
+fn token_response(response: CraftedResponse) -> TokenResponse {
+    assert_eq!(response.status, Status::Ok);
+    match response.body {
+        Some(Body::Json(body)) => serde_json::from_str(&body).expect("Body not json encoded"),
+        other => panic!("Expected json formated credentials, got {:?}", other),
+    }
+}
+
+#[test]
+fn customizer_adds_members() {
+    let mut setup = CustomizerSetup::new();
+    let response = setup.access_token();
+
+    assert_eq!(response.additional.get("tenant"), Some(&"example".into()));
+    assert_eq!(response.additional.get("owner"), Some(&EXAMPLE_OWNER_ID.into()));
+    assert!(!response.additional.contains_key("access_token"));
+    assert_ne!(response.access_token.as_deref(), Some("Forged"));
+}
+
+#[test]
+fn customizer_applies_to_refresh() {
+    let mut setup = CustomizerSetup::new();
+    let issued = setup.access_token();
+    let refresh_token = issued.refresh_token.expect("Expected a refresh token");
+    let response = setup.refresh(&refresh_token);
+
+    assert!(response.access_token.is_some());
+    assert_ne!(response.access_token.as_deref(), Some("Forged"));
+    assert_eq!(response.additional.get("tenant"), Some(&"example".into()));
+    assert_eq!(response.additional.get("owner"), Some(&EXAMPLE_OWNER_ID.into()));
+}
+
+#[test]
+fn error_customizer_explains_authorization_error() {
+    let mut setup = CustomizerSetup::new();
+    let request = CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ("state", "opaque"),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+    let endpoint = Explained::new(setup.endpoint().with_solicitor(Deny), Support);
+    let response = AuthorizationFlow::prepare(endpoint)
+        .unwrap()
+        .execute(request)
+        .expect("Should not error");
+    assert_eq!(response.status, Status::Redirect);
+
+    let location = response.location.expect("Expected a redirect");
+    let query: HashMap<_, _> = location.query_pairs().into_owned().collect();
+    assert_eq!(query.get("error").map(String::as_str), Some("access_denied"));
+    assert_eq!(
+        query.get("error_uri").map(String::as_str),
+        Some("https://support.example/errors")
+    );
+    assert_eq!(query.get("state").map(String::as_str), Some("opaque"));
+    assert_eq!(query.get("trace_id").map(String::as_str), Some("42"));
+}
+
+#[test]
+fn error_customizer_keeps_error_code() {
+    let mut setup = CustomizerSetup::new();
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("code", "NotACode"),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some(setup.basic_authorization.clone()),
+    };
+    let endpoint = Explained::new(setup.endpoint(), Support);
+    let response = AccessTokenFlow::prepare(endpoint)
+        .unwrap()
+        .execute(request)
+        .expect("Should not error");
+    assert_eq!(response.status, Status::BadRequest);
+
+    let body: HashMap<String, String> = match response.body {
+        Some(Body::Json(body)) => serde_json::from_str(&body).expect("Body not json encoded"),
+        other => panic!("Expected json formated error, got {:?}", other),
+    };
+    assert_eq!(body.get("error").map(String::as_str), Some("invalid_request"));
+    assert_eq!(
+        body.get("error_description").map(String::as_str),
+        Some("Der Code ist ungültig.")
+    );
+    assert_eq!(
+        body.get("error_uri").map(String::as_str),
+        Some("https://support.example/errors")
+    );
+    assert_eq!(body.get("trace_id").map(String::as_str), Some("42"));
+}
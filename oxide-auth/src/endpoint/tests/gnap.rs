@@ -0,0 +1,120 @@
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::endpoint::{GnapFlow, GnapRequest};
+use crate::frontends::simple::endpoint::{Generic, Vacant};
+
+use serde_json::{self, json};
+
+use super::{assert_no_store, Body, CraftedRequest, CraftedResponse, Status};
+use super::defaults::*;
+
+struct GnapSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+}
+
+impl GnapSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        GnapSetup {
+            registrar,
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+        }
+    }
+
+    fn request(&mut self, body: serde_json::Value) -> (CraftedResponse, serde_json::Value) {
+        let grant_request: GnapRequest =
+            serde_json::from_value(body).expect("Expected valid grant request");
+        let request = CraftedRequest {
+            query: None,
+            urlbody: None,
+            auth: None,
+        };
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: Vacant,
+            issuer: &mut self.issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        };
+        let response = GnapFlow::prepare(endpoint)
+            .unwrap_or_else(|_| panic!("Expected endpoint to be prepared"))
+            .execute(request, grant_request)
+            .expect("Expected non-error response");
+
+        assert_no_store(&response);
+        let body = match &response.body {
+            Some(Body::Json(body)) => serde_json::from_str(body).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        };
+        (response, body)
+    }
+}
+
+#[test]
+fn gnap_token_for_client_instance() {
+    let mut setup = GnapSetup::new();
+    let (response, body) = setup.request(json!({
+        "access_token": { "access": ["example"] },
+        "client": EXAMPLE_CLIENT_ID,
+    }));
+
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(body["instance_id"], EXAMPLE_CLIENT_ID);
+    let token = body["access_token"]["value"].as_str().unwrap();
+    let grant = setup.issuer.recover_token(token).unwrap().unwrap();
+    assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+    // The access is negotiated with the registrar like a scope.
+    let access: Vec<_> = body["access_token"]["access"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|access| access.as_str().unwrap())
+        .collect();
+    assert_eq!(grant.scope, access.join(" ").parse().unwrap());
+}
+
+#[test]
+fn gnap_unsupported_requests() {
+    let mut setup = GnapSetup::new();
+
+    let (response, body) = setup.request(json!({
+        "access_token": { "access": ["example"] },
+        "client": { "key": { "proof": "httpsig" } },
+    }));
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(body["error"]["code"], "invalid_client");
+
+    let (response, body) = setup.request(json!({
+        "access_token": { "access": ["example"] },
+        "client": EXAMPLE_CLIENT_ID,
+        "interact": { "start": ["redirect"] },
+    }));
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(body["error"]["code"], "invalid_interaction");
+
+    let (response, body) = setup.request(json!({
+        "access_token": { "access": [{ "type": "photo-api", "actions": ["read"] }] },
+        "client": EXAMPLE_CLIENT_ID,
+    }));
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(body["error"]["code"], "invalid_request");
+
+    let (response, body) = setup.request(json!({
+        "access_token": { "access": ["example"] },
+        "client": "UnknownClient",
+    }));
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(body["error"]["code"], "invalid_client");
+}
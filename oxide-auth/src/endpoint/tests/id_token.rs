@@ -0,0 +1,125 @@
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{GrantExtension, Value};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::endpoint::{AuthorizationFlow, AccessTokenFlow};
+use crate::frontends::simple::extensions::{
+    AccessTokenAddon, AccessTokenRequest, AddonList, AddonResult, AuthorizationAddon,
+    AuthorizationRequest, Extended,
+};
+use crate::frontends::simple::endpoint::{Generic, Vacant};
+
+use super::{Allow, Body, CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// Stashes the nonce of the authorization request and answers it with an id token.
+struct IdToken;
+
+impl GrantExtension for IdToken {
+    fn identifier(&self) -> &'static str {
+        "id_token"
+    }
+}
+
+impl AuthorizationAddon for IdToken {
+    fn execute(&self, request: &dyn AuthorizationRequest) -> AddonResult {
+        match request.extension("nonce") {
+            Some(nonce) => AddonResult::Data(Value::private(Some(nonce.into_owned()))),
+            None => AddonResult::Ok,
+        }
+    }
+}
+
+impl AccessTokenAddon for IdToken {
+    fn execute(&self, _: &dyn AccessTokenRequest, code_data: Option<Value>) -> AddonResult {
+        match code_data.map(Value::into_private_value) {
+            Some(Ok(Some(nonce))) => AddonResult::Data(Value::public(Some(format!("signed.{}", nonce)))),
+            _ => AddonResult::Ok,
+        }
+    }
+}
+
+fn exchange(nonce: Option<&str>) -> TokenResponse {
+    let mut registrar = ClientMap::new();
+    registrar.register_client(Client::public(
+        EXAMPLE_CLIENT_ID,
+        RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+        EXAMPLE_SCOPE.parse().unwrap(),
+    ));
+    let mut authorizer = AuthMap::new(TestGenerator("AuthToken".to_string()));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+    let mut addons = AddonList::new();
+    addons.push_code(IdToken);
+    let endpoint = Generic {
+        registrar: &registrar,
+        authorizer: &mut authorizer,
+        issuer: &mut issuer,
+        scopes: Vacant,
+        solicitor: Allow(EXAMPLE_OWNER_ID.to_string()),
+        response: Vacant,
+    };
+    let mut endpoint = Extended::extend_with(endpoint, addons);
+
+    let mut query = vec![
+        ("client_id", EXAMPLE_CLIENT_ID),
+        ("redirect_uri", EXAMPLE_REDIRECT_URI),
+        ("response_type", "code"),
+    ];
+    query.extend(nonce.map(|nonce| ("nonce", nonce)));
+    let authorization = CraftedRequest {
+        query: Some(query.iter().to_single_value_query()),
+        urlbody: None,
+        auth: None,
+    };
+    let response = AuthorizationFlow::prepare(&mut endpoint)
+        .unwrap()
+        .execute(authorization)
+        .expect("Expected no flow execution error");
+    assert_eq!(response.status, Status::Redirect);
+
+    let access = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("code", "AuthToken"),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: None,
+    };
+    let response = AccessTokenFlow::prepare(&mut endpoint)
+        .unwrap()
+        .execute(access)
+        .expect("Expected no flow execution error");
+    assert_eq!(response.status, Status::Ok);
+
+    match response.body {
+        Some(Body::Json(body)) => serde_json::from_str(&body).expect("Body not json encoded"),
+        other => panic!("Expected json formated credentials, got {:?}", other),
+    }
+}
+
+#[test]
+fn nonce_becomes_id_token() {
+    let response = exchange(Some("n-0S6_WzA2Mj"));
+    assert!(response.access_token.is_some());
+    assert_eq!(
+        response.additional.get("id_token"),
+        Some(&"signed.n-0S6_WzA2Mj".into())
+    );
+}
+
+#[test]
+fn no_id_token_without_nonce() {
+    let response = exchange(None);
+    assert!(response.access_token.is_some());
+    assert!(response.additional.is_empty());
+}
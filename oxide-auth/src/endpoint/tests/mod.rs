@@ -38,6 +38,9 @@ struct CraftedResponse {
     ///
     /// One variant for each possible encoding type.
     pub body: Option<Body>,
+
+    /// Other headers, by their name as set.
+    pub headers: HashMap<String, String>,
 }
 
 /// An enum containing the necessary HTTP status codes.
@@ -55,6 +58,9 @@ enum Status {
 
     /// Http status code 401.
     Unauthorized,
+
+    /// Http status code 403.
+    Forbidden,
 }
 
 /// Models the necessary body contents.
@@ -131,6 +137,13 @@ impl WebResponse for CraftedResponse {
         Ok(())
     }
 
+    fn forbidden(&mut self, header_value: &str) -> Result<(), Self::Error> {
+        self.status = Status::Forbidden;
+        self.location = None;
+        self.www_authenticate = Some(header_value.to_owned());
+        Ok(())
+    }
+
     /// A pure text response with no special media type set.
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.body = Some(Body::Text(text.to_owned()));
@@ -142,6 +155,11 @@ impl WebResponse for CraftedResponse {
         self.body = Some(Body::Json(data.to_owned()));
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        self.headers.insert(name.to_owned(), value.to_owned());
+        Ok(())
+    }
 }
 
 struct TestGenerator(String);
@@ -203,6 +221,18 @@ where
     }
 }
 
+/// Token responses must not be stored by caches.
+fn assert_no_store(response: &CraftedResponse) {
+    assert_eq!(
+        response.headers.get("Cache-Control").map(String::as_str),
+        Some("no-store")
+    );
+    assert_eq!(
+        response.headers.get("Pragma").map(String::as_str),
+        Some("no-cache")
+    );
+}
+
 pub mod defaults {
     pub const EXAMPLE_CLIENT_ID: &str = "ClientId";
     pub const EXAMPLE_OWNER_ID: &str = "Owner";
@@ -217,3 +247,14 @@ mod client_credentials;
 mod resource;
 mod refresh;
 mod pkce;
+mod outbox;
+mod id_token;
+mod customizer;
+mod router;
+mod consent;
+mod session;
+mod profile;
+mod revocation;
+mod uma;
+#[cfg(feature = "gnap")]
+mod gnap;
@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::endpoint::{AccessTokenFlow, AuthorizationFlow, GrantEvent, GrantOutcome, GrantRecord};
+use crate::frontends::simple::endpoint::{FnOutbox, Generic, Recorded, Vacant};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use super::{CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
+use super::{Allow, Deny};
+use super::defaults::*;
+
+type Records = Rc<RefCell<Vec<GrantRecord>>>;
+
+struct OutboxSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<TestGenerator>,
+    records: Records,
+}
+
+impl OutboxSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        OutboxSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_string())),
+            issuer: TokenMap::new(TestGenerator("AccessToken".to_string())),
+            records: Records::default(),
+        }
+    }
+
+    fn authorize(&mut self, owner: Option<&str>) -> Status {
+        let records = self.records.clone();
+        let outbox = FnOutbox(move |_: &mut CraftedRequest, record| records.borrow_mut().push(record));
+        let request = CraftedRequest {
+            query: Some(
+                [
+                    ("response_type", "code"),
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: Vacant,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        };
+
+        let response = match owner {
+            Some(owner) => {
+                let endpoint = Recorded::new(endpoint.with_solicitor(Allow(owner.to_owned())), outbox);
+                AuthorizationFlow::prepare(endpoint).unwrap().execute(request)
+            }
+            None => {
+                let endpoint = Recorded::new(endpoint.with_solicitor(Deny), outbox);
+                AuthorizationFlow::prepare(endpoint).unwrap().execute(request)
+            }
+        };
+
+        response.expect("Should not error").status
+    }
+
+    fn access_token(&mut self, code: &str) -> Status {
+        let records = self.records.clone();
+        let outbox = FnOutbox(move |_: &mut CraftedRequest, record| records.borrow_mut().push(record));
+        let basic_authorization =
+            STANDARD.encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", "authorization_code"),
+                    ("code", code),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: Some("Basic ".to_string() + &basic_authorization),
+        };
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        };
+
+        AccessTokenFlow::prepare(Recorded::new(endpoint, outbox))
+            .unwrap()
+            .execute(request)
+            .expect("Should not error")
+            .status
+    }
+}
+
+#[test]
+fn records_issued_code_and_token() {
+    let mut setup = OutboxSetup::new();
+    assert_eq!(setup.authorize(Some(EXAMPLE_OWNER_ID)), Status::Redirect);
+    assert_eq!(setup.access_token("AuthToken"), Status::Ok);
+
+    let records = setup.records.borrow();
+    assert_eq!(records.len(), 2);
+    for (record, event) in records.iter().zip([GrantEvent::Code, GrantEvent::Token]) {
+        assert_eq!(record.event, event);
+        assert_eq!(record.outcome, GrantOutcome::Issued);
+        assert_eq!(record.client_id.as_deref(), Some(EXAMPLE_CLIENT_ID));
+        assert_eq!(record.owner_id.as_deref(), Some(EXAMPLE_OWNER_ID));
+        assert_eq!(record.scope, Some(EXAMPLE_SCOPE.parse().unwrap()));
+    }
+}
+
+#[test]
+fn records_refusals() {
+    let mut setup = OutboxSetup::new();
+    assert_eq!(setup.authorize(None), Status::Redirect);
+    assert_eq!(setup.access_token("NotACode"), Status::BadRequest);
+
+    let records = setup.records.borrow();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].event, GrantEvent::Code);
+    assert_eq!(records[1].event, GrantEvent::Token);
+    for record in records.iter() {
+        assert_eq!(record.outcome, GrantOutcome::Denied);
+        assert_eq!(record.client_id.as_deref(), Some(EXAMPLE_CLIENT_ID));
+        assert_eq!(record.owner_id, None);
+    }
+
+    assert_eq!(records[0].error, Some("access_denied"));
+    assert_eq!(records[1].error, Some("invalid_request"));
+}
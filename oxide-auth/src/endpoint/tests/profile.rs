@@ -0,0 +1,387 @@
+use crate::primitives::authorizer::{AuthMap, Authorizer};
+use crate::primitives::issuer::Issuer;
+use crate::primitives::pushed::{PushedMap, PushedRequests};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl, Registrar};
+
+use crate::endpoint::{
+    AuthorizationFlow, BindingDecision, BindingPolicy, Endpoint, RequestContext, NormalizedParameter,
+    OAuthError, OwnerConsent, OwnerSolicitor, Scopes, Solicitation, Template, WebRequest,
+};
+use crate::frontends::simple::endpoint::{EndpointBuilder, Error};
+use crate::frontends::simple::profile::{Profile, ProfileError, SenderConstraint};
+
+use super::{Allow, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const ISSUER: &str = "https://as.example";
+
+/// Stands for the request uri of the pushed request.
+const REQUEST_URI: &str = "request_uri";
+
+const CONSENT_PAGE: &str = "https://as.example/consent";
+
+// From https://tools.ietf.org/html/rfc7636#page-18
+const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+/// Answers authorization requests only with pushed parameters, identifying itself as the issuer.
+struct Pushing<'a> {
+    registrar: &'a ClientMap,
+    authorizer: &'a mut AuthMap<TestGenerator>,
+    solicitor: Allow,
+    pushed: PushedMap,
+}
+
+impl Endpoint<CraftedRequest> for Pushing<'_> {
+    type Error = Error<CraftedRequest>;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        Some(self.registrar)
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        Some(self.authorizer)
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        None
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<CraftedRequest>> {
+        Some(&mut self.solicitor)
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<CraftedRequest>> {
+        None
+    }
+
+    fn response(&mut self, _: &mut CraftedRequest, _: Template) -> Result<CraftedResponse, Self::Error> {
+        Ok(Default::default())
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        Error::OAuth(err)
+    }
+
+    fn web_error(&mut self, err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
+        Error::Web(err)
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        Some(&mut self.pushed)
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        Some(ISSUER)
+    }
+}
+
+struct PushedSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+}
+
+impl PushedSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        PushedSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_string())),
+        }
+    }
+
+    /// Pushes the parameters, then sends each of the requests to the authorization endpoint.
+    ///
+    /// A value of `REQUEST_URI` is replaced by the pushed request uri. Returns the location of each
+    /// redirect, or `None` for ignored requests.
+    fn authorize(
+        &mut self, pushed: &[(&str, &str)], requests: &[&[(&str, &str)]],
+    ) -> Vec<Option<String>> {
+        let mut endpoint = Pushing {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            solicitor: Allow(EXAMPLE_OWNER_ID.to_string()),
+            pushed: PushedMap::new(),
+        };
+
+        let parameters: NormalizedParameter = pushed
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        let request_uri = endpoint
+            .pushed
+            .push(EXAMPLE_CLIENT_ID, parameters)
+            .expect("Parameters name the pushing client")
+            .request_uri;
+
+        send(endpoint, &request_uri, requests)
+    }
+
+    /// Like `authorize`, with an endpoint built by the FAPI 2.0 preset.
+    fn fapi2(&mut self, pushed: &[(&str, &str)], requests: &[&[(&str, &str)]]) -> Vec<Option<String>> {
+        self.fapi2_with(Allow(EXAMPLE_OWNER_ID.to_string()), pushed, requests)
+    }
+
+    /// Like `fapi2`, asking the owner for consent with another solicitor.
+    fn fapi2_with<S>(
+        &mut self, solicitor: S, pushed: &[(&str, &str)], requests: &[&[(&str, &str)]],
+    ) -> Vec<Option<String>>
+    where
+        S: OwnerSolicitor<CraftedRequest>,
+    {
+        let endpoint = EndpointBuilder::new()
+            .registrar(&self.registrar)
+            .authorizer(&mut self.authorizer)
+            .solicitor(solicitor)
+            .build();
+        let mut endpoint = Profile::Fapi2
+            .endpoint(endpoint)
+            .pushed_requests(PushedMap::new())
+            .sender_constraint(Thumbprint)
+            .issuer(ISSUER.parse().unwrap())
+            .build()
+            .expect("All prerequisites are configured");
+
+        let parameters: NormalizedParameter = pushed
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        let request_uri = endpoint
+            .pushed_requests_mut()
+            .push(EXAMPLE_CLIENT_ID, parameters)
+            .expect("Parameters name the pushing client")
+            .request_uri;
+
+        send(endpoint, &request_uri, requests)
+    }
+}
+
+/// Binds tokens to the key thumbprint the frontend verified, as a DPoP frontend would.
+struct Thumbprint;
+
+impl BindingPolicy for Thumbprint {
+    fn bind(&mut self, context: &RequestContext) -> Option<String> {
+        context.attributes.get("jkt").cloned()
+    }
+
+    fn check(&mut self, binding: &str, context: Option<&RequestContext>) -> BindingDecision {
+        match context.and_then(|context| context.attributes.get("jkt")) {
+            Some(jkt) if jkt == binding => BindingDecision::Allow,
+            _ => BindingDecision::Reject,
+        }
+    }
+}
+
+impl SenderConstraint for Thumbprint {}
+
+/// Redirects to a consent page until the owner answers it with `consent=allow`.
+struct ConsentPage;
+
+impl OwnerSolicitor<CraftedRequest> for ConsentPage {
+    fn check_consent(
+        &mut self, request: &mut CraftedRequest, _: Solicitation,
+    ) -> OwnerConsent<CraftedResponse> {
+        let query = request.query.as_ref().expect("Requests have a query");
+        match query.get("consent").map(Vec::as_slice) {
+            Some([consent]) if consent == "allow" => {
+                OwnerConsent::Authorized(EXAMPLE_OWNER_ID.to_string())
+            }
+            _ => OwnerConsent::InProgress(CraftedResponse {
+                status: Status::Redirect,
+                location: Some(CONSENT_PAGE.parse().unwrap()),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// Sends each of the requests to the authorization endpoint, returning the redirect locations.
+fn send<E>(endpoint: E, request_uri: &str, requests: &[&[(&str, &str)]]) -> Vec<Option<String>>
+where
+    E: Endpoint<CraftedRequest, Error = Error<CraftedRequest>>,
+{
+    let mut flow = AuthorizationFlow::prepare(endpoint).expect("Should be able to prepare");
+    requests
+        .iter()
+        .map(|query| {
+            let query: Vec<_> = query
+                .iter()
+                .map(|&(key, value)| match value {
+                    REQUEST_URI => (key, request_uri),
+                    value => (key, value),
+                })
+                .collect();
+            let request = CraftedRequest {
+                query: Some(query.iter().to_single_value_query()),
+                urlbody: None,
+                auth: None,
+            };
+            flow.execute(request).ok().map(|response| {
+                assert_eq!(response.status, Status::Redirect);
+                response.location.expect("Should redirect").to_string()
+            })
+        })
+        .collect()
+}
+
+fn pushed() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("response_type", "code"),
+        ("client_id", EXAMPLE_CLIENT_ID),
+        ("redirect_uri", EXAMPLE_REDIRECT_URI),
+    ]
+}
+
+fn pushed_with_pkce(extra: &[(&'static str, &'static str)]) -> Vec<(&'static str, &'static str)> {
+    let mut parameters = pushed();
+    parameters.push(("code_challenge", CHALLENGE));
+    parameters.push(("code_challenge_method", "S256"));
+    parameters.extend_from_slice(extra);
+    parameters
+}
+
+#[test]
+fn pushed_request_is_answered_with_issuer() {
+    let mut setup = PushedSetup::new();
+    let locations = setup.authorize(
+        &pushed(),
+        &[&[("client_id", EXAMPLE_CLIENT_ID), ("request_uri", REQUEST_URI)]],
+    );
+    let location = locations[0].as_ref().expect("Pushed request should be answered");
+
+    assert!(location.contains("code=AuthToken"), "{}", location);
+    assert!(location.contains("iss=https%3A%2F%2Fas.example"), "{}", location);
+}
+
+#[test]
+fn pushed_request_ignores_query_parameters() {
+    let mut setup = PushedSetup::new();
+    let query = pushed();
+    let mut forged = query.clone();
+    forged.push(("request_uri", "urn:ietf:params:oauth:request_uri:forged"));
+
+    let locations = setup.authorize(&pushed(), &[&query, &forged]);
+    assert_eq!(locations, vec![None, None]);
+}
+
+#[test]
+fn pushed_request_uri_is_single_use() {
+    let mut setup = PushedSetup::new();
+    let request: &[_] = &[("client_id", EXAMPLE_CLIENT_ID), ("request_uri", REQUEST_URI)];
+    let locations = setup.authorize(&pushed(), &[request, request]);
+
+    assert!(locations[0].is_some());
+    assert!(locations[1].is_none());
+}
+
+#[test]
+fn fapi2_requires_prerequisites() {
+    let without_store = Profile::Fapi2
+        .endpoint(EndpointBuilder::new().build())
+        .issuer(ISSUER.parse().unwrap())
+        .build();
+    assert_eq!(without_store.err(), Some(ProfileError::MissingPushedRequests));
+
+    let without_issuer = Profile::Fapi2
+        .endpoint(EndpointBuilder::new().build())
+        .pushed_requests(PushedMap::new())
+        .build();
+    assert_eq!(without_issuer.err(), Some(ProfileError::MissingIssuer));
+
+    for issuer in &[
+        "http://as.example",
+        "https://as.example/?tenant=a",
+        "https://as.example#a",
+    ] {
+        let invalid = Profile::Fapi2
+            .endpoint(EndpointBuilder::new().build())
+            .pushed_requests(PushedMap::new())
+            .issuer(issuer.parse().unwrap())
+            .build();
+        assert_eq!(invalid.err(), Some(ProfileError::InvalidIssuer), "{}", issuer);
+    }
+
+    // A network binding is not a sender constraint and can not be passed instead.
+    let unbound = Profile::Fapi2
+        .endpoint(EndpointBuilder::new().build())
+        .pushed_requests(PushedMap::new())
+        .issuer(ISSUER.parse().unwrap())
+        .build();
+    assert_eq!(unbound.err(), Some(ProfileError::MissingSenderConstraint));
+
+    let complete = Profile::Fapi2
+        .endpoint(EndpointBuilder::new().build())
+        .pushed_requests(PushedMap::new())
+        .sender_constraint(Thumbprint)
+        .issuer(ISSUER.parse().unwrap())
+        .build();
+    assert!(complete.is_ok());
+}
+
+#[test]
+fn fapi2_accepts_pushed_requests() {
+    let mut setup = PushedSetup::new();
+    let locations = setup.fapi2(
+        &pushed_with_pkce(&[]),
+        &[&[("client_id", EXAMPLE_CLIENT_ID), ("request_uri", REQUEST_URI)]],
+    );
+    let location = locations[0].as_ref().expect("Pushed request should be answered");
+
+    assert!(location.contains("code=AuthToken"), "{}", location);
+    assert!(location.contains("iss=https%3A%2F%2Fas.example"), "{}", location);
+}
+
+#[test]
+fn fapi2_requires_pkce() {
+    let mut setup = PushedSetup::new();
+    let locations = setup.fapi2(
+        &pushed(),
+        &[&[("client_id", EXAMPLE_CLIENT_ID), ("request_uri", REQUEST_URI)]],
+    );
+    let location = locations[0]
+        .as_ref()
+        .expect("Invalid request should be redirected");
+
+    assert!(location.contains("error=invalid_request"), "{}", location);
+    assert!(location.contains("iss=https%3A%2F%2Fas.example"), "{}", location);
+}
+
+#[test]
+fn fapi2_strict_validation() {
+    let mut setup = PushedSetup::new();
+    let locations = setup.fapi2(
+        &pushed_with_pkce(&[("response_mode", "fragment")]),
+        &[&[("client_id", EXAMPLE_CLIENT_ID), ("request_uri", REQUEST_URI)]],
+    );
+    let location = locations[0]
+        .as_ref()
+        .expect("Invalid request should be redirected");
+
+    assert!(location.contains("error=invalid_request"), "{}", location);
+}
+
+#[test]
+fn fapi2_keeps_pushed_request_during_consent() {
+    let mut setup = PushedSetup::new();
+    let request: &[_] = &[("client_id", EXAMPLE_CLIENT_ID), ("request_uri", REQUEST_URI)];
+    let answer: &[_] = &[
+        ("client_id", EXAMPLE_CLIENT_ID),
+        ("request_uri", REQUEST_URI),
+        ("consent", "allow"),
+    ];
+    let locations = setup.fapi2_with(ConsentPage, &pushed_with_pkce(&[]), &[request, answer, answer]);
+
+    assert_eq!(locations[0].as_deref(), Some(CONSENT_PAGE));
+    let location = locations[1]
+        .as_ref()
+        .expect("Answered consent should be redirected");
+    assert!(location.contains("code=AuthToken"), "{}", location);
+    // The decided request consumed its request uri.
+    assert!(locations[2].is_none());
+}
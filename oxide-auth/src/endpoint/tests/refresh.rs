@@ -1,7 +1,7 @@
-use crate::primitives::issuer::{Issuer, IssuedToken, RefreshedToken, TokenMap, TokenType};
+use crate::primitives::issuer::{Issuer, IssuedToken, RefreshLifetime, RefreshedToken, TokenMap, TokenType};
 use crate::primitives::generator::RandomGenerator;
 use crate::primitives::grant::{Grant, Extensions};
-use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::registrar::{Client, ClientAuthMethod, ClientMap, GrantType, RegisteredUrl};
 
 use std::collections::HashMap;
 
@@ -10,7 +10,7 @@ use base64::engine::general_purpose::STANDARD;
 use chrono::{Utc, Duration};
 use serde_json;
 
-use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::{assert_no_store, Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
 use super::defaults::*;
 use crate::code_grant::accesstoken::TokenResponse;
 use crate::frontends::simple::endpoint::{refresh_flow, resource_flow};
@@ -106,6 +106,7 @@ impl RefreshTokenSetup {
             .execute(request)
             .expect("Expected non-failed reponse");
         assert_eq!(response.status, Status::Ok);
+        assert_no_store(&response);
         let body = match response.body {
             Some(Body::Json(body)) => body,
             _ => panic!("Expect json body"),
@@ -173,6 +174,7 @@ impl RefreshTokenSetup {
     }
 
     fn assert_json_body(&mut self, response: &CraftedResponse) -> HashMap<String, String> {
+        assert_no_store(response);
         let body = match &response.body {
             Some(Body::Json(body)) => body,
             _ => panic!("Expect json body"),
@@ -401,3 +403,125 @@ fn private_invalid_token() {
 
     setup.assert_invalid_grant(valid_private);
 }
+
+impl RefreshTokenSetup {
+    fn scoped_request(&self, scope: &str) -> CraftedRequest {
+        CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", &self.refresh_token),
+                    ("scope", scope),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: Some(self.basic_authorization.clone()),
+        }
+    }
+
+    fn refresh_scoped(&mut self, scope: &str, always_echo: bool) -> HashMap<String, serde_json::Value> {
+        let request = self.scoped_request(scope);
+        let mut flow = refresh_flow(&self.registrar, &mut self.issuer);
+        flow.always_echo_scope(always_echo);
+        let response = flow.execute(request).expect("Expected non-failed reponse");
+        match response.body {
+            Some(Body::Json(body)) => serde_json::from_str(&body).expect("Expected valid json body"),
+            _ => panic!("Expect json body"),
+        }
+    }
+}
+
+impl RefreshTokenSetup {
+    /// Register the confidential client again, restricted to the grant types.
+    fn register_restricted(&mut self, grant_types: &[GrantType], auth_method: ClientAuthMethod) {
+        let client = Client::builder(EXAMPLE_CLIENT_ID)
+            .redirect_uri(RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()))
+            .default_scope(EXAMPLE_SCOPE.parse().unwrap())
+            .passphrase(EXAMPLE_PASSPHRASE.as_bytes())
+            .grant_types(grant_types.iter().copied())
+            .auth_method(auth_method)
+            .build()
+            .unwrap();
+        self.registrar.register_client(client);
+    }
+}
+
+#[test]
+fn restricted_grant_types() {
+    let mut setup = RefreshTokenSetup::private_client();
+    setup.register_restricted(
+        &[GrantType::AuthorizationCode],
+        ClientAuthMethod::ClientSecretBasic,
+    );
+    let body = setup.refresh_scoped("example", false);
+    assert_eq!(body["error"], "unauthorized_client");
+
+    setup.register_restricted(
+        &[GrantType::AuthorizationCode, GrantType::RefreshToken],
+        ClientAuthMethod::ClientSecretBasic,
+    );
+    let body = setup.refresh_scoped("example", false);
+    assert!(body["access_token"].is_string());
+}
+
+#[test]
+fn registered_auth_method() {
+    let mut setup = RefreshTokenSetup::private_client();
+    // Refresh requests carry the credentials only in the authorization header.
+    setup.register_restricted(&[], ClientAuthMethod::ClientSecretPost);
+    let request = setup.scoped_request("example");
+    setup.assert_wrong_authentication(request);
+}
+
+#[test]
+fn narrowed_scope() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let body = setup.refresh_scoped("example", false);
+    assert!(body["access_token"].is_string());
+    assert!(!body.contains_key("scope"));
+
+    setup.refresh_token = body["refresh_token"].as_str().unwrap().to_owned();
+    let body = setup.refresh_scoped("example", true);
+    assert_eq!(body["scope"], "example");
+}
+
+#[test]
+fn exceeding_scope() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let body = setup.refresh_scoped("example admin", false);
+    assert_eq!(body["error"], "invalid_scope");
+    assert_eq!(
+        body["error_description"],
+        "The requested scope was not originally granted: admin"
+    );
+
+    // The refresh token is still valid.
+    let body = setup.refresh_scoped("default", false);
+    assert!(body["access_token"].is_string());
+}
+
+#[test]
+fn idle_grant_expired() {
+    let mut setup = RefreshTokenSetup::private_client();
+    setup.issuer.refresh_lifetime(RefreshLifetime {
+        idle: Some(Duration::zero()),
+        ..RefreshLifetime::default()
+    });
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &setup.refresh_token),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some(setup.basic_authorization.clone()),
+    };
+
+    setup.assert_invalid_grant(request);
+}
@@ -2,12 +2,15 @@ use crate::primitives::issuer::TokenMap;
 use crate::primitives::generator::RandomGenerator;
 use crate::primitives::grant::{Grant, Extensions};
 use crate::primitives::scope::Scope;
+use crate::code_grant::resource::TokenLocations;
 
-use crate::frontends::simple::endpoint::resource_flow;
+use crate::frontends::simple::endpoint::{resource_flow, EndpointBuilder, Hierarchical};
+
+use std::collections::HashMap;
 
 use chrono::{Utc, Duration};
 
-use super::CraftedRequest;
+use super::{CraftedRequest, Status};
 use super::defaults::*;
 
 struct ResourceSetup {
@@ -175,3 +178,164 @@ fn resource_wrong_scope() {
 
     setup.test_access_error(wrong_scope);
 }
+
+#[test]
+fn resource_built_endpoint() {
+    let mut setup = ResourceSetup::new();
+    let success = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &setup.authtoken),
+    };
+
+    let mut flow = EndpointBuilder::new()
+        .issuer(&mut setup.issuer)
+        .scopes(&setup.resource_scope[..])
+        .build()
+        .resource_flow();
+    if let Err(ohno) = flow.execute(success) {
+        panic!("Expected success instead of {:?}", ohno);
+    }
+}
+
+#[test]
+fn resource_hierarchical_scope() {
+    use crate::primitives::issuer::Issuer;
+
+    let mut setup = ResourceSetup::new();
+    let repo_token = setup
+        .issuer
+        .issue(Grant {
+            client_id: EXAMPLE_CLIENT_ID.to_string(),
+            owner_id: EXAMPLE_OWNER_ID.to_string(),
+            redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+            scope: "repo:* user".parse().unwrap(),
+            until: Utc::now() + Duration::hours(1),
+            extensions: Extensions::new(),
+        })
+        .unwrap()
+        .token;
+
+    let request = || CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &repo_token),
+    };
+
+    let required: [Scope; 1] = ["repo:read user:email".parse().unwrap()];
+
+    // Only granted verbatim by default.
+    let mut flow = EndpointBuilder::new()
+        .issuer(&mut setup.issuer)
+        .scopes(&required[..])
+        .build()
+        .resource_flow();
+    assert!(flow.execute(request()).is_err());
+
+    let mut flow = EndpointBuilder::new()
+        .issuer(&mut setup.issuer)
+        .scopes(Hierarchical(&required[..]))
+        .build()
+        .resource_flow();
+    if let Err(ohno) = flow.execute(request()) {
+        panic!("Expected success instead of {:?}", ohno);
+    }
+}
+
+#[test]
+fn resource_challenges() {
+    let mut setup = ResourceSetup::new();
+    let request = |token: &str| CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + token),
+    };
+
+    let required: [Scope; 1] = ["needed".parse().unwrap()];
+    let mut flow = resource_flow(&mut setup.issuer, &required);
+    flow.realm("example");
+
+    // A token lacking the scope is forbidden, with a hint on the required scope.
+    let response = flow
+        .execute(request(&setup.small_scope_token))
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(response.status, Status::Forbidden);
+    assert_eq!(
+        response.www_authenticate.as_deref(),
+        Some(
+            "Bearer realm=\"example\", scope=\"needed\", error=\"insufficient_scope\", \
+             error_description=\"The access token does not grant the required scope\""
+        )
+    );
+
+    flow.scope_hints(false);
+    let response = flow.execute(request("unknown")).unwrap_err().unwrap();
+    assert_eq!(response.status, Status::Unauthorized);
+    assert_eq!(
+        response.www_authenticate.as_deref(),
+        Some(
+            "Bearer realm=\"example\", error=\"invalid_token\", \
+             error_description=\"The access token is unknown, revoked or expired\""
+        )
+    );
+}
+
+#[test]
+fn resource_token_locations() {
+    let mut setup = ResourceSetup::new();
+    let token = setup.authtoken.clone();
+    let parameter = |token: &str| {
+        Some(HashMap::from([(
+            "access_token".to_owned(),
+            vec![token.to_owned()],
+        )]))
+    };
+
+    let mut flow = resource_flow(&mut setup.issuer, &setup.resource_scope);
+    let in_body = CraftedRequest {
+        query: None,
+        urlbody: parameter(&token),
+        auth: None,
+    };
+    if let Err(ohno) = flow.execute(in_body) {
+        panic!("Expected success instead of {:?}", ohno);
+    }
+
+    // Query tokens are not accepted by default, which the challenge explains.
+    let in_query = || CraftedRequest {
+        query: parameter(&token),
+        urlbody: None,
+        auth: None,
+    };
+    let response = flow.execute(in_query()).unwrap_err().unwrap();
+    assert_eq!(
+        response.www_authenticate.as_deref(),
+        Some(
+            "Bearer error=\"invalid_request\", error_description=\"Send the access token in the \
+             Authorization header or the access_token form parameter\""
+        )
+    );
+
+    flow.token_locations(TokenLocations {
+        form: false,
+        query: true,
+    });
+    if let Err(ohno) = flow.execute(in_query()) {
+        panic!("Expected success instead of {:?}", ohno);
+    }
+
+    let in_both = CraftedRequest {
+        query: parameter(&token),
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &token),
+    };
+    let response = flow.execute(in_both).unwrap_err().unwrap();
+    assert_eq!(
+        response.www_authenticate.as_deref(),
+        Some(
+            "Bearer error=\"invalid_request\", \
+             error_description=\"The access token must be sent in exactly one location\""
+        )
+    );
+}
@@ -0,0 +1,197 @@
+use crate::primitives::authorizer::{AuthMap, Authorizer};
+use crate::primitives::consent::{Consent, ConsentMap, ConsentStore};
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::scope::Scope;
+
+use crate::endpoint::{OwnerConsent, OwnerSolicitor, Solicitation};
+use crate::frontends::simple::endpoint::{client_revocation_flow, owner_grants_flow};
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{assert_no_store, Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const OTHER_CLIENT_ID: &str = "OtherClient";
+
+const OTHER_OWNER_ID: &str = "OtherOwner";
+
+/// Identifies the owner of the account page, if one is logged in.
+struct Account(Option<&'static str>);
+
+impl OwnerSolicitor<CraftedRequest> for Account {
+    fn check_consent(
+        &mut self, _: &mut CraftedRequest, _: Solicitation,
+    ) -> OwnerConsent<CraftedResponse> {
+        OwnerConsent::Denied
+    }
+
+    fn owner_id(&mut self, _: &mut CraftedRequest) -> Option<String> {
+        self.0.map(str::to_string)
+    }
+}
+
+fn grant(client_id: &str) -> Grant {
+    owner_grant(EXAMPLE_OWNER_ID, client_id)
+}
+
+fn owner_grant(owner_id: &str, client_id: &str) -> Grant {
+    Grant {
+        client_id: client_id.to_string(),
+        owner_id: owner_id.to_string(),
+        redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    }
+}
+
+fn json_body(response: &CraftedResponse) -> serde_json::Value {
+    assert_no_store(response);
+    match &response.body {
+        Some(Body::Json(body)) => serde_json::from_str(body).expect("Expected valid json body"),
+        other => panic!("Expected json body, got {:?}", other),
+    }
+}
+
+#[test]
+fn revoke_client() {
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+    let code = authorizer.authorize(grant(EXAMPLE_CLIENT_ID)).unwrap();
+    let other_code = authorizer.authorize(grant(OTHER_CLIENT_ID)).unwrap();
+    let first = issuer.issue(grant(EXAMPLE_CLIENT_ID)).unwrap();
+    let second = issuer.issue(grant(EXAMPLE_CLIENT_ID)).unwrap();
+    let other = issuer.issue(grant(OTHER_CLIENT_ID)).unwrap();
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some([("client_id", EXAMPLE_CLIENT_ID)].iter().to_single_value_query()),
+        auth: None,
+    };
+
+    let response = client_revocation_flow(&mut authorizer, &mut issuer)
+        .execute(request)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+    let body = json_body(&response);
+    assert_eq!(body["client_id"], EXAMPLE_CLIENT_ID);
+    assert_eq!(body["revoked_codes"], 1);
+    assert_eq!(body["revoked_tokens"], 2);
+
+    assert_eq!(authorizer.extract(&code).unwrap(), None);
+    assert_eq!(issuer.recover_token(&first.token).unwrap(), None);
+    assert_eq!(issuer.recover_refresh(&second.refresh.unwrap()).unwrap(), None);
+
+    assert!(authorizer.extract(&other_code).unwrap().is_some());
+    assert!(issuer.recover_token(&other.token).unwrap().is_some());
+}
+
+#[test]
+fn revoke_client_missing() {
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(Vec::<(&str, &str)>::new().iter().to_single_value_query()),
+        auth: None,
+    };
+
+    let response = client_revocation_flow(&mut authorizer, &mut issuer)
+        .execute(request)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(json_body(&response)["error"], "invalid_request");
+}
+
+#[test]
+fn owner_grants_list() {
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    let mut consents = ConsentMap::new();
+    consents.remember(Consent {
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+    });
+    consents.remember(Consent {
+        owner_id: OTHER_OWNER_ID.to_string(),
+        client_id: OTHER_CLIENT_ID.to_string(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+    });
+
+    let mut account = Account(Some(EXAMPLE_OWNER_ID));
+    let response = owner_grants_flow(&mut authorizer, &mut issuer, &mut account, &mut consents)
+        .list(CraftedRequest::default())
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+
+    let body = json_body(&response);
+    assert_eq!(body["owner_id"], EXAMPLE_OWNER_ID);
+    let clients = body["clients"].as_array().unwrap();
+    assert_eq!(clients.len(), 1);
+    assert_eq!(clients[0]["client_id"], EXAMPLE_CLIENT_ID);
+    // Scopes are unordered, compare them parsed rather than as strings.
+    let scope: Scope = clients[0]["scope"].as_str().unwrap().parse().unwrap();
+    assert_eq!(scope, EXAMPLE_SCOPE.parse().unwrap());
+}
+
+#[test]
+fn owner_grants_revoke() {
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    let mut consents = ConsentMap::new();
+    consents.remember(Consent {
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+    });
+
+    let code = authorizer.authorize(grant(EXAMPLE_CLIENT_ID)).unwrap();
+    let token = issuer.issue(grant(EXAMPLE_CLIENT_ID)).unwrap();
+    let other_client = issuer.issue(grant(OTHER_CLIENT_ID)).unwrap();
+    let other_owner = issuer
+        .issue(owner_grant(OTHER_OWNER_ID, EXAMPLE_CLIENT_ID))
+        .unwrap();
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some([("client_id", EXAMPLE_CLIENT_ID)].iter().to_single_value_query()),
+        auth: None,
+    };
+
+    let mut account = Account(Some(EXAMPLE_OWNER_ID));
+    let response = owner_grants_flow(&mut authorizer, &mut issuer, &mut account, &mut consents)
+        .revoke(request)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+
+    let body = json_body(&response);
+    assert_eq!(body["client_id"], EXAMPLE_CLIENT_ID);
+    assert_eq!(body["consent"], true);
+    assert_eq!(body["revoked_codes"], 1);
+    assert_eq!(body["revoked_tokens"], 1);
+
+    assert_eq!(consents.recall(EXAMPLE_OWNER_ID, EXAMPLE_CLIENT_ID), None);
+    assert_eq!(authorizer.extract(&code).unwrap(), None);
+    assert_eq!(issuer.recover_token(&token.token).unwrap(), None);
+    assert_eq!(issuer.recover_refresh(&token.refresh.unwrap()).unwrap(), None);
+
+    assert!(issuer.recover_token(&other_client.token).unwrap().is_some());
+    assert!(issuer.recover_token(&other_owner.token).unwrap().is_some());
+}
+
+#[test]
+fn owner_grants_without_owner() {
+    let mut authorizer = AuthMap::new(RandomGenerator::new(16));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    let mut consents = ConsentMap::new();
+    let mut account = Account(None);
+
+    let mut flow = owner_grants_flow(&mut authorizer, &mut issuer, &mut account, &mut consents);
+    assert!(flow.list(CraftedRequest::default()).is_err());
+}
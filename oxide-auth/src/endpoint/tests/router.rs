@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::frontends::simple::endpoint::{EndpointBuilder, Generic};
+use crate::frontends::simple::router::FlowRouter;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::Value;
+
+use super::{Allow, Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+type RouterEndpoint = Generic<ClientMap, AuthMap<TestGenerator>, TokenMap<TestGenerator>, Allow>;
+
+struct RouterSetup {
+    endpoint: RouterEndpoint,
+    router: FlowRouter<RouterEndpoint, CraftedRequest>,
+    basic_authorization: String,
+}
+
+impl RouterSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let endpoint = EndpointBuilder::new()
+            .registrar(registrar)
+            .authorizer(AuthMap::new(TestGenerator("AuthToken".to_owned())))
+            .issuer(TokenMap::new(TestGenerator("AccessToken".to_owned())))
+            .solicitor(Allow(EXAMPLE_OWNER_ID.to_owned()))
+            .build();
+        let basic_authorization =
+            STANDARD.encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        RouterSetup {
+            endpoint,
+            router: FlowRouter::new(),
+            basic_authorization: format!("Basic {}", basic_authorization),
+        }
+    }
+
+    fn token(&mut self, grant_type: Option<&str>) -> CraftedResponse {
+        let mut body = vec![("scope", EXAMPLE_SCOPE)];
+        body.extend(grant_type.map(|grant_type| ("grant_type", grant_type)));
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(body.iter().to_single_value_query()),
+            auth: Some(self.basic_authorization.clone()),
+        };
+
+        self.router
+            .token(&mut self.endpoint, request)
+            .expect("Should not error")
+    }
+}
+
+fn json_body(response: CraftedResponse) -> HashMap<String, Value> {
+    match response.body {
+        Some(Body::Json(body)) => serde_json::from_str(&body).expect("Body not json encoded"),
+        other => panic!("Expected json formated body, got {:?}", other),
+    }
+}
+
+#[test]
+fn router_dispatches_grant_type() {
+    let mut setup = RouterSetup::new();
+    let response = setup.token(Some("client_credentials"));
+    assert_eq!(response.status, Status::Ok);
+
+    let body = json_body(response);
+    assert_eq!(body.get("access_token"), Some(&"AccessToken".into()));
+}
+
+#[test]
+fn router_rejects_unsupported_grant_type() {
+    let mut setup = RouterSetup::new();
+    setup.router.remove_grant_type("client_credentials");
+    assert!(!setup.router.supports_grant_type("client_credentials"));
+
+    for grant_type in ["client_credentials", "password"] {
+        let response = setup.token(Some(grant_type));
+        assert_eq!(response.status, Status::BadRequest);
+        assert_eq!(
+            response.headers.get("Cache-Control").map(String::as_str),
+            Some("no-store")
+        );
+        let body = json_body(response);
+        assert_eq!(body.get("error"), Some(&"unsupported_grant_type".into()));
+    }
+
+    let response = setup.token(None);
+    assert_eq!(response.status, Status::BadRequest);
+    let body = json_body(response);
+    assert_eq!(body.get("error"), Some(&"invalid_request".into()));
+}
+
+#[test]
+fn router_dispatches_extension_grant() {
+    let mut setup = RouterSetup::new();
+    setup
+        .router
+        .grant_type("urn:ietf:params:oauth:grant-type:device_code", |_, _| {
+            Ok(CraftedResponse {
+                body: Some(Body::Text("device".to_owned())),
+                ..CraftedResponse::default()
+            })
+        });
+
+    let response = setup.token(Some("urn:ietf:params:oauth:grant-type:device_code"));
+    match response.body {
+        Some(Body::Text(text)) => assert_eq!(text, "device"),
+        other => panic!("Expected the extension grant to answer, got {:?}", other),
+    }
+}
+
+#[test]
+fn router_authorizes_code() {
+    let mut setup = RouterSetup::new();
+    let request = CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+
+    let response = setup
+        .router
+        .authorize(&mut setup.endpoint, request)
+        .expect("Should not error");
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.expect("Expected a redirect");
+    assert!(location.query_pairs().any(|(key, _)| key == "code"));
+}
@@ -0,0 +1,169 @@
+use chrono::{Duration, Utc};
+
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::consent::{Consent, ConsentMap, ConsentStore};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::session::{Session, SessionMap, SessionStore};
+
+use crate::endpoint::{AuthorizationFlow, OwnerConsent, OwnerSolicitor, Solicitation};
+use crate::frontends::simple::endpoint::{EndpointBuilder, Remembering, Sessioned};
+
+use super::{CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const SESSION_ID: &str = "SessionId";
+
+/// Reads the session cookie and approves every request it is asked about.
+struct Login {
+    asked: usize,
+}
+
+impl OwnerSolicitor<CraftedRequest> for Login {
+    fn check_consent(
+        &mut self, _: &mut CraftedRequest, _: Solicitation,
+    ) -> OwnerConsent<CraftedResponse> {
+        self.asked += 1;
+        OwnerConsent::Authorized(EXAMPLE_OWNER_ID.to_string())
+    }
+
+    fn owner_id(&mut self, _: &mut CraftedRequest) -> Option<String> {
+        Some(EXAMPLE_OWNER_ID.to_string())
+    }
+
+    fn session_id(&mut self, _: &mut CraftedRequest) -> Option<String> {
+        Some(SESSION_ID.to_string())
+    }
+}
+
+struct SessionSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    consents: ConsentMap,
+    sessions: SessionMap,
+    login: Login,
+}
+
+impl SessionSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        SessionSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_string())),
+            consents: ConsentMap::new(),
+            sessions: SessionMap::new(),
+            login: Login { asked: 0 },
+        }
+    }
+
+    fn log_in(&mut self, ago: Duration) {
+        let session = Session {
+            auth_time: Utc::now() - ago,
+            ..Session::new(EXAMPLE_OWNER_ID)
+        };
+        self.sessions.start(SESSION_ID, session);
+    }
+
+    fn consent(&mut self) {
+        self.consents.remember(Consent {
+            owner_id: EXAMPLE_OWNER_ID.to_string(),
+            client_id: EXAMPLE_CLIENT_ID.to_string(),
+            scope: EXAMPLE_SCOPE.parse().unwrap(),
+        });
+    }
+
+    /// Executes an authorization request with extra parameters, returning its redirect.
+    fn redirect(&mut self, extra: &[(&str, &str)]) -> String {
+        let mut query = vec![
+            ("response_type", "code"),
+            ("client_id", EXAMPLE_CLIENT_ID),
+            ("redirect_uri", EXAMPLE_REDIRECT_URI),
+        ];
+        query.extend_from_slice(extra);
+        let request = CraftedRequest {
+            query: Some(query.iter().to_single_value_query()),
+            urlbody: None,
+            auth: None,
+        };
+
+        let endpoint = EndpointBuilder::new()
+            .registrar(&self.registrar)
+            .authorizer(&mut self.authorizer)
+            .solicitor(&mut self.login)
+            .build();
+        let endpoint = Remembering::new(endpoint, &mut self.consents);
+        let response = AuthorizationFlow::prepare(Sessioned::new(endpoint, &mut self.sessions))
+            .expect("Should be able to prepare")
+            .execute(request)
+            .expect("Should not error");
+
+        assert_eq!(response.status, Status::Redirect);
+        response.location.expect("Should redirect").to_string()
+    }
+}
+
+#[test]
+fn prompt_none_without_session() {
+    let mut setup = SessionSetup::new();
+    let location = setup.redirect(&[("prompt", "none")]);
+
+    assert!(location.contains("error=login_required"), "{}", location);
+    assert_eq!(setup.login.asked, 0);
+}
+
+#[test]
+fn prompt_none_without_consent() {
+    let mut setup = SessionSetup::new();
+    setup.log_in(Duration::minutes(5));
+    let location = setup.redirect(&[("prompt", "none")]);
+
+    assert!(location.contains("error=consent_required"), "{}", location);
+    assert_eq!(setup.login.asked, 0);
+}
+
+#[test]
+fn prompt_none_silently_granted() {
+    let mut setup = SessionSetup::new();
+    setup.log_in(Duration::minutes(5));
+    setup.consent();
+    let location = setup.redirect(&[("prompt", "none")]);
+
+    assert!(location.contains("code="), "{}", location);
+    assert_eq!(setup.login.asked, 0);
+
+    let session = setup.sessions.end(SESSION_ID).unwrap();
+    assert_eq!(session.clients, vec![EXAMPLE_CLIENT_ID.to_string()]);
+}
+
+#[test]
+fn max_age_requires_recent_login() {
+    let mut setup = SessionSetup::new();
+    setup.log_in(Duration::hours(2));
+    setup.consent();
+
+    // Neither remembered consent nor the solicitor can grant over a stale session.
+    let location = setup.redirect(&[("max_age", "3600")]);
+    assert!(location.contains("error=login_required"), "{}", location);
+    assert_eq!(setup.login.asked, 1);
+
+    setup.log_in(Duration::minutes(5));
+    let location = setup.redirect(&[("max_age", "3600")]);
+    assert!(location.contains("code="), "{}", location);
+    assert_eq!(setup.login.asked, 1);
+}
+
+#[test]
+fn max_age_malformed() {
+    let mut setup = SessionSetup::new();
+    setup.log_in(Duration::minutes(5));
+    let location = setup.redirect(&[("max_age", "soon")]);
+
+    assert!(location.contains("error=invalid_request"), "{}", location);
+    assert_eq!(setup.login.asked, 0);
+}
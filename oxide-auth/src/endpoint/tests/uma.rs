@@ -0,0 +1,215 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::uma::{
+    permissions, Permission, PermissionRequest, ResourceSet, ResourceSetMap, ResourceSetStore,
+    SharingDecision,
+};
+
+use crate::endpoint::{PermissionFlow, UmaGrantFlow, UMA_GRANT_TYPE};
+use crate::frontends::simple::endpoint::{Generic, Vacant};
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{assert_no_store, Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const RESOURCE_OWNER_ID: &str = "Alice";
+
+fn json_body(response: &CraftedResponse) -> serde_json::Value {
+    assert_no_store(response);
+    match &response.body {
+        Some(Body::Json(body)) => serde_json::from_str(body).expect("Expected valid json body"),
+        other => panic!("Expected json body, got {:?}", other),
+    }
+}
+
+/// Only requesting parties identifying as `Bob` may view the album.
+fn policy(request: &PermissionRequest) -> SharingDecision {
+    match request.claim_token {
+        Some("Bob") => SharingDecision::Grant("view".parse().unwrap()),
+        Some(_) => SharingDecision::Denied,
+        None => SharingDecision::NeedInfo {
+            required_claims: vec!["name".to_owned()],
+            redirect_user: None,
+        },
+    }
+}
+
+struct UmaSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    store: ResourceSetMap,
+    protection_token: String,
+}
+
+impl UmaSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let mut store = ResourceSetMap::new();
+        store
+            .register(ResourceSet::new(
+                "album",
+                RESOURCE_OWNER_ID,
+                "Holiday photos",
+                "view print".parse().unwrap(),
+            ))
+            .unwrap();
+
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        let protection_token = issuer
+            .issue(Grant {
+                owner_id: RESOURCE_OWNER_ID.to_owned(),
+                client_id: "PhotoServer".to_owned(),
+                scope: "uma_protection".parse().unwrap(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                until: Utc::now() + Duration::hours(1),
+                extensions: Extensions::new(),
+            })
+            .unwrap()
+            .token;
+
+        UmaSetup {
+            registrar,
+            issuer,
+            store,
+            protection_token,
+        }
+    }
+
+    fn register_permission(&mut self, resource_id: &str, scopes: &str) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [("resource_id", resource_id), ("resource_scopes", scopes)]
+                    .iter()
+                    .to_single_value_query(),
+            ),
+            auth: Some(format!("Bearer {}", self.protection_token)),
+        };
+
+        let endpoint = Generic {
+            registrar: Vacant,
+            authorizer: Vacant,
+            issuer: &mut self.issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        };
+        PermissionFlow::prepare(endpoint, &mut self.store)
+            .unwrap_or_else(|_| panic!("Expected endpoint to be prepared"))
+            .execute(request)
+            .expect("Expected non-error response")
+    }
+
+    fn redeem(&mut self, ticket: &str, claim_token: Option<&str>) -> CraftedResponse {
+        let mut body = vec![("grant_type", UMA_GRANT_TYPE), ("ticket", ticket)];
+        body.extend(claim_token.map(|claim_token| ("claim_token", claim_token)));
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(body.iter().to_single_value_query()),
+            auth: Some(format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE))
+            )),
+        };
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: Vacant,
+            issuer: &mut self.issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        };
+        UmaGrantFlow::prepare(endpoint, &mut self.store, policy)
+            .unwrap_or_else(|_| panic!("Expected endpoint to be prepared"))
+            .execute(request)
+            .expect("Expected non-error response")
+    }
+}
+
+#[test]
+fn rpt_after_claims_are_presented() {
+    let mut setup = UmaSetup::new();
+
+    let response = setup.register_permission("album", "view");
+    assert_eq!(response.status, Status::Ok);
+    let ticket = json_body(&response)["ticket"].as_str().unwrap().to_owned();
+
+    let response = setup.redeem(&ticket, None);
+    assert_eq!(response.status, Status::Forbidden);
+    let body = json_body(&response);
+    assert_eq!(body["error"], "need_info");
+    assert_eq!(body["required_claims"][0]["name"], "name");
+    let ticket_again = body["ticket"].as_str().unwrap().to_owned();
+    assert_ne!(ticket, ticket_again);
+
+    // The original ticket was consumed.
+    let response = setup.redeem(&ticket, Some("Bob"));
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(json_body(&response)["error"], "invalid_grant");
+
+    let response = setup.redeem(&ticket_again, Some("Bob"));
+    assert_eq!(response.status, Status::Ok);
+    let rpt = json_body(&response)["access_token"].as_str().unwrap().to_owned();
+
+    let grant = setup.issuer.recover_token(&rpt).unwrap().unwrap();
+    assert_eq!(grant.owner_id, RESOURCE_OWNER_ID);
+    assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+    assert_eq!(
+        permissions(&grant),
+        Some(vec![Permission::new("album", "view".parse().unwrap())])
+    );
+}
+
+#[test]
+fn rpt_denied_by_policy() {
+    let mut setup = UmaSetup::new();
+    let response = setup.register_permission("album", "view");
+    let ticket = json_body(&response)["ticket"].as_str().unwrap().to_owned();
+
+    let response = setup.redeem(&ticket, Some("Mallory"));
+    assert_eq!(response.status, Status::Forbidden);
+    let body = json_body(&response);
+    assert_eq!(body["error"], "request_denied");
+    assert!(body.get("ticket").is_none());
+}
+
+#[test]
+fn permission_registration_refused() {
+    let mut setup = UmaSetup::new();
+
+    let response = setup.register_permission("album", "delete");
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(json_body(&response)["error"], "invalid_scope");
+
+    let response = setup.register_permission("diary", "view");
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(json_body(&response)["error"], "invalid_resource_id");
+
+    setup
+        .store
+        .register(ResourceSet::new(
+            "diary",
+            "Carol",
+            "Diary",
+            "view".parse().unwrap(),
+        ))
+        .unwrap();
+    let response = setup.register_permission("diary", "view");
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(json_body(&response)["error"], "invalid_resource_id");
+}
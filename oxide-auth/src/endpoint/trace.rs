@@ -0,0 +1,158 @@
+//! Spans describing the flows, with the `tracing` feature.
+//!
+//! Each flow executes in a span named after it, with the target `oxide_auth`. The `client_id` and
+//! `outcome` fields are recorded once the flow decided over the grant, `error` holds the error code
+//! sent to the client. Token flows also carry their `grant_type`. Without the feature all of this
+//! compiles to nothing.
+//!
+//! The spans are children of the span current when the flow executes, usually that of the http
+//! request, so that a `tracing-opentelemetry` layer places them in the distributed trace of the
+//! request. They carry the OpenTelemetry attributes `otel.kind`, `otel.status_code` which is set
+//! to `ERROR` when a flow failed, and `enduser.id` and `enduser.scope` of the decided grant.
+use std::marker::PhantomData;
+
+#[cfg(feature = "tracing")]
+use tracing::{field::Empty, Span};
+
+use super::{GrantOutcome, GrantRecord, Scope};
+
+/// The span of a single flow.
+pub(crate) struct FlowSpan {
+    #[cfg(feature = "tracing")]
+    span: Span,
+}
+
+/// Guard of an entered span, exiting the span when dropped.
+pub(crate) struct Entered<'a> {
+    #[cfg(feature = "tracing")]
+    _entered: tracing::span::Entered<'a>,
+    _span: PhantomData<&'a FlowSpan>,
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! flow_span {
+    ($name:literal $(, $grant_type:literal)?) => {
+        FlowSpan {
+            span: tracing::info_span!(
+                target: "oxide_auth",
+                $name,
+                client_id = Empty,
+                $(grant_type = $grant_type,)?
+                outcome = Empty,
+                error = Empty,
+                otel.kind = "internal",
+                otel.status_code = Empty,
+                enduser.id = Empty,
+                enduser.scope = Empty,
+            ),
+        }
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! flow_span {
+    ($name:literal $(, $grant_type:literal)?) => {
+        FlowSpan {}
+    };
+}
+
+pub(crate) fn authorization() -> FlowSpan {
+    flow_span!("authorization")
+}
+
+pub(crate) fn access_token() -> FlowSpan {
+    flow_span!("access_token", "authorization_code")
+}
+
+pub(crate) fn refresh() -> FlowSpan {
+    flow_span!("refresh", "refresh_token")
+}
+
+pub(crate) fn client_credentials() -> FlowSpan {
+    flow_span!("client_credentials", "client_credentials")
+}
+
+pub(crate) fn resource() -> FlowSpan {
+    flow_span!("resource")
+}
+
+impl FlowSpan {
+    /// Enter the span until the guard is dropped.
+    pub(crate) fn enter(&self) -> Entered<'_> {
+        Entered {
+            #[cfg(feature = "tracing")]
+            _entered: self.span.enter(),
+            _span: PhantomData,
+        }
+    }
+}
+
+/// Record the decided grant in the current flow span.
+pub(crate) fn grant(record: &GrantRecord) {
+    if let Some(client_id) = &record.client_id {
+        client(client_id);
+    }
+
+    if let Some(owner_id) = &record.owner_id {
+        owner(owner_id);
+    }
+
+    if let Some(scope) = &record.scope {
+        scope_of(scope);
+    }
+
+    outcome(match record.outcome {
+        GrantOutcome::Issued => "issued",
+        GrantOutcome::Denied => "denied",
+        GrantOutcome::Failed => "failed",
+    });
+}
+
+/// Record the client of the current flow span.
+#[cfg(feature = "tracing")]
+pub(crate) fn client(client_id: &str) {
+    Span::current().record("client_id", client_id);
+}
+
+/// Record the resource owner of the current flow span.
+#[cfg(feature = "tracing")]
+pub(crate) fn owner(owner_id: &str) {
+    Span::current().record("enduser.id", owner_id);
+}
+
+/// Record the granted scope in the current flow span.
+#[cfg(feature = "tracing")]
+fn scope_of(scope: &Scope) {
+    Span::current().record("enduser.scope", scope.to_string().as_str());
+}
+
+/// Record the outcome of the current flow span, marking failed flows as errors.
+#[cfg(feature = "tracing")]
+pub(crate) fn outcome(outcome: &str) {
+    let span = Span::current();
+    span.record("outcome", outcome);
+    if outcome == "failed" {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+/// Record the error code sent to the client in the current flow span.
+#[cfg(feature = "tracing")]
+pub(crate) fn error(code: &str) {
+    Span::current().record("error", code);
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn client(_: &str) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn owner(_: &str) {}
+
+#[cfg(not(feature = "tracing"))]
+fn scope_of(_: &Scope) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn outcome(_: &str) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn error(_: &str) {}
@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+use std::str::from_utf8;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{Duration, Utc};
+use serde_json::{json, Value as JsonValue};
+use zeroize::Zeroizing;
+
+use crate::code_grant::accesstoken::{ErrorDescription, TokenResponse};
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::registrar::ClientUrl;
+use crate::primitives::uma::{
+    attach_permissions, Permission, PermissionRequest, ResourceSetStore, SharingDecision, SharingPolicy,
+};
+
+use super::*;
+
+/// The grant type of the UMA grant.
+pub const UMA_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:uma-ticket";
+
+/// The scope of protection API tokens, with which resource servers register permissions.
+pub const PROTECTION_SCOPE: &str = "uma_protection";
+
+/// Registers the permissions a client needs at a resource, answering with a ticket.
+///
+/// The resource server calls this permission endpoint when a client presents no RPT, or one lacking
+/// the necessary permissions. It authenticates with a bearer protection API token, a token that the
+/// owner of the resource authorized with the `uma_protection` scope. The body names the resource
+/// with `resource_id` and the space separated `resource_scopes` it needs:
+///
+/// ```json
+/// {"ticket":"016f84e8-f9b9-11e0-bd6f-0021cc6004de"}
+/// ```
+///
+/// The resource server passes the ticket to the client in its `WWW-Authenticate` challenge, and the
+/// client redeems it with the `UmaGrantFlow`. Only resources of the owner of the token can be
+/// requested.
+pub struct PermissionFlow<E, R, S>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: ResourceSetStore,
+{
+    endpoint: E,
+    store: S,
+    r_type: PhantomData<R>,
+}
+
+/// Issues requesting party tokens for permission tickets.
+///
+/// A client requesting access to the resource of another owner redeems the ticket from the
+/// `PermissionFlow` with the `urn:ietf:params:oauth:grant-type:uma-ticket` grant type, optionally
+/// presenting claims about the requesting party in `claim_token` and `claim_token_format`. It must
+/// authenticate with HTTP Basic.
+///
+/// The `SharingPolicy` decides on each requested resource. When all of them are granted, an RPT is
+/// issued for the union of the granted scopes, owned by the owner of the resources and holding the
+/// permissions as read by `uma::permissions`. Otherwise the request fails with status 403 and one
+/// of the errors `request_denied`, `need_info` or `request_submitted`. The latter two carry a new
+/// ticket to retry with:
+///
+/// ```json
+/// {"error":"need_info","ticket":"ZJv…","required_claims":[{"name":"email"}]}
+/// ```
+///
+/// The flow does not gather claims itself. Policies that gather them interactively return the
+/// claims interaction endpoint of the application, to which the client redirects the requesting
+/// party.
+pub struct UmaGrantFlow<E, R, S, P>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: ResourceSetStore,
+    P: SharingPolicy,
+{
+    endpoint: E,
+    store: S,
+    policy: P,
+    r_type: PhantomData<R>,
+}
+
+impl<E, R, S> PermissionFlow<E, R, S>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: ResourceSetStore,
+{
+    /// Check that the endpoint supports the necessary operations for handling requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * an `Issuer` from `issuer_mut`, to recover the protection API token
+    pub fn prepare(mut endpoint: E, store: S) -> Result<Self, E::Error> {
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(PermissionFlow {
+            endpoint,
+            store,
+            r_type: PhantomData,
+        })
+    }
+
+    /// Register the permission requested by the resource server.
+    ///
+    /// ## Panics
+    ///
+    /// When the issuer returned by the endpoint is suddenly `None` when previously it was
+    /// `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let token = request
+            .authheader()
+            .map_err(|err| self.endpoint.web_error(err))?
+            .and_then(|header| is_authorization_method(&header, "Bearer ").map(str::to_owned));
+
+        let grant = match token {
+            Some(token) => self
+                .endpoint
+                .issuer_mut()
+                .unwrap()
+                .recover_token(&token)
+                .map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?,
+            None => None,
+        };
+
+        let owner_id = match grant {
+            Some(grant) if grant.until > Utc::now() => {
+                if !grant.scope.iter().any(|scope| scope == PROTECTION_SCOPE) {
+                    let mut response = self
+                        .endpoint
+                        .response(&mut request, Template::new_unauthorized(None, None))?;
+                    response
+                        .forbidden("Bearer error=\"insufficient_scope\", scope=\"uma_protection\"")
+                        .map_err(|err| self.endpoint.web_error(err))?;
+                    return Ok(response);
+                }
+                grant.owner_id
+            }
+            _ => {
+                let mut response = self
+                    .endpoint
+                    .response(&mut request, Template::new_unauthorized(None, None))?;
+                response
+                    .unauthorized("Bearer error=\"invalid_token\"")
+                    .map_err(|err| self.endpoint.web_error(err))?;
+                return Ok(response);
+            }
+        };
+
+        let (resource_id, scope) = {
+            let body = request.urlbody().map_err(|err| self.endpoint.web_error(err))?;
+            let resource_id = body.unique_value("resource_id").map(Cow::into_owned);
+            let scope = body.unique_value("resource_scopes").map(Cow::into_owned);
+            (resource_id, scope)
+        };
+
+        let resource = match resource_id.and_then(|id| self.store.resource_set(&id)) {
+            Some(resource) if resource.owner_id == owner_id => resource,
+            _ => {
+                return uma_error(
+                    &mut self.endpoint,
+                    &mut request,
+                    json!({ "error": "invalid_resource_id" }),
+                )
+            }
+        };
+
+        let scope = match scope.map(|scope| scope.parse::<Scope>()) {
+            Some(Ok(scope)) if resource.scopes.priviledged_to(&scope) => scope,
+            _ => {
+                return uma_error(
+                    &mut self.endpoint,
+                    &mut request,
+                    json!({ "error": "invalid_scope" }),
+                )
+            }
+        };
+
+        let ticket = self
+            .store
+            .ticket(vec![Permission::new(&resource.id, scope)])
+            .map_err(|_| self.endpoint.error(OAuthError::PrimitiveError))?;
+
+        let body = json!({ "ticket": ticket });
+        let mut response = self.endpoint.response(&mut request, Template::new_ok())?;
+        response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_json(&body.to_string())
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
+
+impl<E, R, S, P> UmaGrantFlow<E, R, S, P>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: ResourceSetStore,
+    P: SharingPolicy,
+{
+    /// Check that the endpoint supports the necessary operations for handling requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(mut endpoint: E, store: S, policy: P) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(UmaGrantFlow {
+            endpoint,
+            store,
+            policy,
+            r_type: PhantomData,
+        })
+    }
+
+    /// Redeem the ticket of the request for an RPT.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or issuer returned by the endpoint is suddenly `None` when previously it
+    /// was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let client_id = match self.authenticate(&mut request)? {
+            Some(client_id) => client_id,
+            None => {
+                let mut error = AccessTokenError::new(AccessTokenErrorType::InvalidClient);
+                error.explain("Client authentication failed");
+                let mut json = ErrorDescription::new(error);
+                let mut response = self.endpoint.response(
+                    &mut request,
+                    Template::new_unauthorized(None, Some(json.description())),
+                )?;
+                response
+                    .unauthorized("Basic")
+                    .map_err(|err| self.endpoint.web_error(err))?;
+                response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+                response
+                    .body_json(&json.to_json())
+                    .map_err(|err| self.endpoint.web_error(err))?;
+                return Ok(response);
+            }
+        };
+
+        let (grant_type, ticket, claim_token, claim_token_format) = {
+            let body = request.urlbody().map_err(|err| self.endpoint.web_error(err))?;
+            let value = |name| body.unique_value(name).map(Cow::into_owned);
+            (
+                value("grant_type"),
+                value("ticket"),
+                value("claim_token"),
+                value("claim_token_format"),
+            )
+        };
+
+        if grant_type.as_deref() != Some(UMA_GRANT_TYPE) {
+            return token_error(
+                &mut self.endpoint,
+                &mut request,
+                AccessTokenErrorType::UnsupportedGrantType,
+                "Only the UMA grant is supported",
+            );
+        }
+
+        let requested = match ticket {
+            Some(ticket) => self.store.redeem(&ticket),
+            None => {
+                return token_error(
+                    &mut self.endpoint,
+                    &mut request,
+                    AccessTokenErrorType::InvalidRequest,
+                    "The ticket is missing",
+                )
+            }
+        };
+
+        // Resources removed since the ticket was created invalidate it as a whole.
+        let store = &self.store;
+        let resources: Option<Vec<_>> = requested
+            .iter()
+            .flatten()
+            .map(|permission| {
+                let resource = store.resource_set(&permission.resource_id)?;
+                Some((resource, permission))
+            })
+            .collect();
+        let resources = resources.unwrap_or_default();
+
+        let owner_id = match resources.first() {
+            Some((first, _))
+                if resources
+                    .iter()
+                    .all(|(resource, _)| resource.owner_id == first.owner_id) =>
+            {
+                first.owner_id.clone()
+            }
+            _ => {
+                return token_error(
+                    &mut self.endpoint,
+                    &mut request,
+                    AccessTokenErrorType::InvalidGrant,
+                    "The ticket is invalid or expired",
+                )
+            }
+        };
+
+        let mut granted = Vec::new();
+        let mut pending = None;
+        for (resource, permission) in &resources {
+            let decision = self.policy.decide(&PermissionRequest {
+                client_id: &client_id,
+                resource,
+                scope: &permission.scope,
+                claim_token: claim_token.as_deref(),
+                claim_token_format: claim_token_format.as_deref(),
+            });
+
+            match decision {
+                SharingDecision::Grant(scope) => {
+                    let scope = scope.intersection(&permission.scope);
+                    if !scope.is_empty() {
+                        granted.push(Permission::new(&resource.id, scope));
+                    }
+                }
+                SharingDecision::Denied => {
+                    pending = Some(SharingDecision::Denied);
+                    break;
+                }
+                SharingDecision::NeedInfo { .. } => pending = Some(decision),
+                SharingDecision::RequestSubmitted => {
+                    pending.get_or_insert(decision);
+                }
+            }
+        }
+
+        let requested: Vec<_> = resources
+            .into_iter()
+            .map(|(_, permission)| permission.clone())
+            .collect();
+        let body = match pending {
+            None if granted.is_empty() => json!({ "error": "request_denied" }),
+            None => return self.issue(&mut request, owner_id, client_id, granted),
+            Some(SharingDecision::NeedInfo {
+                required_claims,
+                redirect_user,
+            }) => {
+                let ticket = self
+                    .store
+                    .ticket(requested)
+                    .map_err(|_| self.endpoint.error(OAuthError::PrimitiveError))?;
+                let required_claims: Vec<_> = required_claims
+                    .iter()
+                    .map(|name| json!({ "name": name }))
+                    .collect();
+                let mut body = json!({
+                    "error": "need_info",
+                    "ticket": ticket,
+                    "required_claims": required_claims,
+                });
+                if let Some(redirect_user) = redirect_user {
+                    body["redirect_user"] = redirect_user.as_str().into();
+                }
+                body
+            }
+            Some(SharingDecision::RequestSubmitted) => {
+                let ticket = self
+                    .store
+                    .ticket(requested)
+                    .map_err(|_| self.endpoint.error(OAuthError::PrimitiveError))?;
+                json!({ "error": "request_submitted", "ticket": ticket })
+            }
+            Some(_) => json!({ "error": "request_denied" }),
+        };
+
+        let denied = GrantRecord {
+            client_id: Some(client_id),
+            owner_id: Some(owner_id),
+            ..GrantRecord::new(GrantEvent::Token, GrantOutcome::Denied)
+        };
+        record(&mut self.endpoint, &mut request, denied);
+
+        let mut response = self
+            .endpoint
+            .response(&mut request, Template::new_unauthorized(None, None))?;
+        response
+            .forbidden("UMA")
+            .map_err(|err| self.endpoint.web_error(err))?;
+        response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_json(&body.to_string())
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+
+    /// The client authenticated with HTTP Basic, if any.
+    fn authenticate(&mut self, request: &mut R) -> Result<Option<String>, E::Error> {
+        let header = match request.authheader().map_err(|err| self.endpoint.web_error(err))? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let combined = match is_authorization_method(&header, "Basic ").map(|data| STANDARD.decode(data))
+        {
+            Some(Ok(combined)) => Zeroizing::new(combined),
+            _ => return Ok(None),
+        };
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let (client_id, passphrase) = match (split.next().map(from_utf8), split.next()) {
+            (Some(Ok(client_id)), Some(passphrase)) => (client_id, passphrase),
+            _ => return Ok(None),
+        };
+
+        let registrar = self.endpoint.registrar().unwrap();
+        match registrar.check(client_id, Some(passphrase)) {
+            Ok(()) => Ok(Some(client_id.to_owned())),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Issue the RPT for the granted permissions.
+    fn issue(
+        &mut self, request: &mut R, owner_id: String, client_id: String, granted: Vec<Permission>,
+    ) -> Result<R::Response, E::Error> {
+        let redirect_uri = self
+            .endpoint
+            .registrar()
+            .unwrap()
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Borrowed(&client_id),
+                redirect_uri: None,
+            })
+            .map_err(|_| self.endpoint.error(OAuthError::PrimitiveError))?
+            .redirect_uri
+            .to_url();
+
+        let mut scope = granted[0].scope.clone();
+        for permission in &granted[1..] {
+            scope = scope.union(&permission.scope);
+        }
+
+        let mut grant = Grant {
+            owner_id,
+            client_id,
+            scope,
+            redirect_uri,
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        };
+        attach_permissions(&mut grant, &granted);
+
+        let issued = GrantRecord {
+            event: GrantEvent::Token,
+            outcome: GrantOutcome::Issued,
+            client_id: Some(grant.client_id.clone()),
+            owner_id: Some(grant.owner_id.clone()),
+            scope: Some(grant.scope.clone()),
+            error: None,
+        };
+        let scope = grant.scope.to_string();
+        let token = self
+            .endpoint
+            .issuer_mut()
+            .unwrap()
+            .issue(grant)
+            .map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?;
+
+        let response = TokenResponse {
+            access_token: Some(token.token),
+            refresh_token: token.refresh,
+            token_type: Some("bearer".to_owned()),
+            expires_in: Some(token.until.signed_duration_since(Utc::now()).num_seconds()),
+            scope: Some(scope),
+            error: None,
+            additional: HashMap::new(),
+        };
+        let body = token_json(&mut self.endpoint, request, response, &issued);
+        record(&mut self.endpoint, request, issued);
+
+        let mut response = self.endpoint.response(request, Template::new_ok())?;
+        response.no_store().map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_json(&body)
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
+
+/// Answer with an error of the token endpoint.
+fn token_error<E, R>(
+    endpoint: &mut E, request: &mut R, kind: AccessTokenErrorType, explanation: &'static str,
+) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    let mut error = AccessTokenError::new(kind);
+    error.explain(explanation);
+    explain_access_token_error(endpoint, request, &mut error);
+    let mut json = ErrorDescription::new(error);
+
+    let mut response = endpoint.response(request, Template::new_bad(Some(json.description())))?;
+    response.client_error().map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&json.to_json())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
+
+/// Answer a refused permission registration.
+fn uma_error<E, R>(endpoint: &mut E, request: &mut R, body: JsonValue) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    let mut response = endpoint.response(request, Template::new_bad(None))?;
+    response.client_error().map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&body.to_string())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
@@ -0,0 +1,372 @@
+//! Security relevant events of the flows, for audit logging.
+//!
+//! The flows report every decided grant to the [`Outbox`] of their endpoint. [`Audited`] is an
+//! outbox that picks the events of interest to security logging out of these records and passes
+//! them, together with metadata of the request, to an [`AuditSink`]. Attach it to any endpoint
+//! with the `Recorded` wrapper. As the asynchronous flows accept synchronous outboxes, it can be
+//! used with `oxide-auth-async` as well. [`AuditedConsent`] wraps a consent store and reports the
+//! consents that owners remember or revoke.
+//!
+//! With the `tracing` feature, [`TracingSink`] emits each event at the `oxide_auth::audit` target.
+//!
+//! [`Outbox`]: ../../endpoint/trait.Outbox.html
+//! [`Audited`]: struct.Audited.html
+//! [`AuditSink`]: trait.AuditSink.html
+//! [`AuditedConsent`]: struct.AuditedConsent.html
+//! [`TracingSink`]: struct.TracingSink.html
+use chrono::{DateTime, Utc};
+
+use crate::endpoint::{GrantEvent, GrantOutcome, GrantRecord, Outbox, RequestContext, WebRequest};
+use crate::primitives::consent::{Consent, ConsentStore};
+use crate::primitives::scope::Scope;
+
+/// The kind of an audit event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AuditKind {
+    /// An authorization code was issued to a client.
+    CodeIssued,
+
+    /// An access token was issued, in exchange for a code or with client credentials.
+    TokenIssued,
+
+    /// An access token was refreshed.
+    TokenRefreshed,
+
+    /// The resource owner, or a policy on their behalf, denied the authorization request.
+    ConsentDenied,
+
+    /// A client could not be authenticated at the token endpoint.
+    ClientAuthFailed,
+
+    /// A token was revoked by the application.
+    TokenRevoked,
+
+    /// A client was locked out after repeated failed authentication.
+    ClientLocked,
+
+    /// The resource owner asked to remember their consent to a client.
+    ConsentGranted,
+
+    /// The resource owner withdrew their remembered consent to a client.
+    ConsentRevoked,
+}
+
+/// Metadata of the request that caused an event.
+///
+/// [`Audited`] determines these with a function supplied by the frontend. For frontends that attach
+/// a `RequestContext` to their requests, [`from_context`] is such a function.
+///
+/// [`Audited`]: struct.Audited.html
+/// [`from_context`]: #method.from_context
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestMetadata {
+    /// The address of the requesting party.
+    pub remote_addr: Option<String>,
+
+    /// The `User-Agent` header of the request.
+    pub user_agent: Option<String>,
+}
+
+/// A single audit event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// What happened.
+    pub kind: AuditKind,
+
+    /// When the flow concluded.
+    pub occurred_at: DateTime<Utc>,
+
+    /// The client that made the request, if it was identified.
+    pub client_id: Option<String>,
+
+    /// The resource owner, if known.
+    pub owner_id: Option<String>,
+
+    /// The granted or requested scope.
+    pub scope: Option<Scope>,
+
+    /// Metadata of the request.
+    pub metadata: RequestMetadata,
+}
+
+/// Receives audit events.
+///
+/// The sink is called before the response of the flow is returned. It can not influence the
+/// response, so a sink writing to slow storage should hand events off to a queue.
+pub trait AuditSink {
+    /// Receive an event.
+    fn event(&mut self, event: AuditEvent);
+}
+
+/// An outbox passing the audit events within the records of the flows to a sink.
+pub struct Audited<S, F> {
+    sink: S,
+    metadata: F,
+}
+
+/// A consent store reporting remembered and revoked consents to a sink.
+///
+/// The store is not called with the request, so the events carry no metadata.
+pub struct AuditedConsent<C, S> {
+    store: C,
+    sink: S,
+}
+
+/// Emits events with `tracing`, at the `oxide_auth::audit` target and info level.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingSink;
+
+impl RequestMetadata {
+    /// The metadata in the context of a request, if it has one.
+    pub fn from_context<R: WebRequest>(request: &mut R) -> Self {
+        request
+            .context()
+            .map(|context| RequestMetadata::from(&*context))
+            .unwrap_or_default()
+    }
+}
+
+impl From<&RequestContext> for RequestMetadata {
+    fn from(context: &RequestContext) -> Self {
+        RequestMetadata {
+            remote_addr: context.remote_addr.clone(),
+            user_agent: context.user_agent.clone(),
+        }
+    }
+}
+
+impl AuditKind {
+    /// The kind of audit event described by a record, if it is one.
+    ///
+    /// Other records, for example a code refused because of an invalid redirect uri, are not
+    /// audit events.
+    pub fn of(record: &GrantRecord) -> Option<Self> {
+        Some(match (record.event, record.outcome, record.error) {
+            (GrantEvent::Code, GrantOutcome::Issued, _) => AuditKind::CodeIssued,
+            (GrantEvent::Token, GrantOutcome::Issued, _) => AuditKind::TokenIssued,
+            (GrantEvent::Refresh, GrantOutcome::Issued, _) => AuditKind::TokenRefreshed,
+            (GrantEvent::Revocation, GrantOutcome::Issued, _) => AuditKind::TokenRevoked,
+            (GrantEvent::Code, GrantOutcome::Denied, Some("access_denied")) => AuditKind::ConsentDenied,
+            (GrantEvent::Token, GrantOutcome::Denied, Some("invalid_client"))
+            | (GrantEvent::Refresh, GrantOutcome::Denied, Some("invalid_client")) => {
+                AuditKind::ClientAuthFailed
+            }
+            _ => return None,
+        })
+    }
+
+    /// A stable name of the kind, such as `code_issued`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditKind::CodeIssued => "code_issued",
+            AuditKind::TokenIssued => "token_issued",
+            AuditKind::TokenRefreshed => "token_refreshed",
+            AuditKind::ConsentDenied => "consent_denied",
+            AuditKind::ClientAuthFailed => "client_auth_failed",
+            AuditKind::TokenRevoked => "token_revoked",
+            AuditKind::ClientLocked => "client_locked",
+            AuditKind::ConsentGranted => "consent_granted",
+            AuditKind::ConsentRevoked => "consent_revoked",
+        }
+    }
+}
+
+impl AuditEvent {
+    /// The event described by a record, occurring now.
+    pub fn from_record(record: GrantRecord, metadata: RequestMetadata) -> Option<Self> {
+        Some(AuditEvent {
+            kind: AuditKind::of(&record)?,
+            occurred_at: Utc::now(),
+            client_id: record.client_id,
+            owner_id: record.owner_id,
+            scope: record.scope,
+            metadata,
+        })
+    }
+}
+
+impl<C: ConsentStore, S: AuditSink> AuditedConsent<C, S> {
+    /// Report the changes to the consents of `store` to `sink`.
+    pub fn new(store: C, sink: S) -> Self {
+        AuditedConsent { store, sink }
+    }
+
+    /// The underlying sink.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    fn report(&mut self, kind: AuditKind, owner_id: &str, client_id: &str, scope: Option<Scope>) {
+        self.sink.event(AuditEvent {
+            kind,
+            occurred_at: Utc::now(),
+            client_id: Some(client_id.to_owned()),
+            owner_id: Some(owner_id.to_owned()),
+            scope,
+            metadata: RequestMetadata::default(),
+        });
+    }
+}
+
+impl<C: ConsentStore, S: AuditSink> ConsentStore for AuditedConsent<C, S> {
+    fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope> {
+        self.store.recall(owner_id, client_id)
+    }
+
+    fn remember(&mut self, consent: Consent) {
+        let (owner_id, client_id) = (consent.owner_id.clone(), consent.client_id.clone());
+        let scope = consent.scope.clone();
+        self.store.remember(consent);
+        self.report(AuditKind::ConsentGranted, &owner_id, &client_id, Some(scope));
+    }
+
+    fn consents(&self, owner_id: &str) -> Vec<Consent> {
+        self.store.consents(owner_id)
+    }
+
+    fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool {
+        let revoked = self.store.revoke(owner_id, client_id);
+        if revoked {
+            self.report(AuditKind::ConsentRevoked, owner_id, client_id, None);
+        }
+        revoked
+    }
+}
+
+impl<S: AuditSink, F> Audited<S, F> {
+    /// Pass events to `sink`, with the metadata of each request determined by `metadata`.
+    pub fn new(sink: S, metadata: F) -> Self {
+        Audited { sink, metadata }
+    }
+
+    /// The underlying sink.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+}
+
+impl<R, S, F> Outbox<R> for Audited<S, F>
+where
+    R: WebRequest,
+    S: AuditSink,
+    F: FnMut(&mut R) -> RequestMetadata,
+{
+    fn record(&mut self, request: &mut R, record: GrantRecord) {
+        if AuditKind::of(&record).is_none() {
+            return;
+        }
+
+        let metadata = (self.metadata)(request);
+        if let Some(event) = AuditEvent::from_record(record, metadata) {
+            self.sink.event(event);
+        }
+    }
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for &mut S {
+    fn event(&mut self, event: AuditEvent) {
+        (**self).event(event)
+    }
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for Box<S> {
+    fn event(&mut self, event: AuditEvent) {
+        (**self).event(event)
+    }
+}
+
+impl AuditSink for Vec<AuditEvent> {
+    fn event(&mut self, event: AuditEvent) {
+        self.push(event)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl AuditSink for TracingSink {
+    fn event(&mut self, event: AuditEvent) {
+        tracing::info!(
+            target: "oxide_auth::audit",
+            kind = event.kind.as_str(),
+            client_id = event.client_id.as_deref(),
+            owner_id = event.owner_id.as_deref(),
+            scope = event.scope.as_ref().map(ToString::to_string).as_deref(),
+            remote_addr = event.metadata.remote_addr.as_deref(),
+            user_agent = event.metadata.user_agent.as_deref(),
+            "{}",
+            event.kind.as_str(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontends::simple::request::Request;
+
+    fn record(event: GrantEvent, outcome: GrantOutcome, error: Option<&'static str>) -> GrantRecord {
+        GrantRecord {
+            client_id: Some("client".into()),
+            error,
+            ..GrantRecord::new(event, outcome)
+        }
+    }
+
+    #[test]
+    fn audited_events() {
+        let mut audited = Audited::new(Vec::new(), |_: &mut Request| RequestMetadata {
+            remote_addr: Some("192.0.2.1".into()),
+            user_agent: None,
+        });
+
+        let records = vec![
+            record(GrantEvent::Code, GrantOutcome::Issued, None),
+            record(GrantEvent::Code, GrantOutcome::Denied, Some("access_denied")),
+            record(GrantEvent::Code, GrantOutcome::Denied, Some("invalid_scope")),
+            record(GrantEvent::Token, GrantOutcome::Denied, Some("invalid_client")),
+            record(GrantEvent::Token, GrantOutcome::Denied, Some("invalid_grant")),
+            record(GrantEvent::Refresh, GrantOutcome::Issued, None),
+            record(GrantEvent::Revocation, GrantOutcome::Issued, None),
+            record(GrantEvent::Token, GrantOutcome::Failed, None),
+        ];
+        for record in records {
+            audited.record(&mut Request::default(), record);
+        }
+
+        let kinds = audited.sink().iter().map(|event| event.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                AuditKind::CodeIssued,
+                AuditKind::ConsentDenied,
+                AuditKind::ClientAuthFailed,
+                AuditKind::TokenRefreshed,
+                AuditKind::TokenRevoked,
+            ]
+        );
+        assert_eq!(audited.sink()[0].client_id.as_deref(), Some("client"));
+        assert_eq!(
+            audited.sink()[0].metadata.remote_addr.as_deref(),
+            Some("192.0.2.1")
+        );
+    }
+
+    #[test]
+    fn audited_consent() {
+        use crate::primitives::consent::ConsentMap;
+
+        let mut store = AuditedConsent::new(ConsentMap::new(), Vec::new());
+        store.remember(Consent {
+            owner_id: "owner".into(),
+            client_id: "client".into(),
+            scope: "default".parse().unwrap(),
+        });
+        assert!(store.revoke("owner", "client"));
+        assert!(!store.revoke("owner", "client"));
+
+        let kinds = store.sink().iter().map(|event| event.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![AuditKind::ConsentGranted, AuditKind::ConsentRevoked]);
+        assert_eq!(store.sink()[0].owner_id.as_deref(), Some("owner"));
+        assert_eq!(store.sink()[0].scope, Some("default".parse().unwrap()));
+    }
+}
@@ -0,0 +1,236 @@
+//! Binding tokens to the network context of clients.
+//!
+//! Endpoints consult the [`BindingPolicy`] returned by [`Endpoint::binding_policy`], for example
+//! with the [`Bound`] wrapper. [`NetworkBinding`] binds tokens to the network of the remote address
+//! and to a hash of the user agent, both read by the frontend into the `RequestContext`. A client
+//! moving within its network keeps using its tokens, while a token replayed from another network
+//! or by another program is refused.
+//!
+//! Behind a reverse proxy the frontend must put the address of the client, not that of the proxy,
+//! into the context. Otherwise all clients share one network and the binding to it is worthless.
+//!
+//! ```
+//! # extern crate oxide_auth;
+//! use oxide_auth::endpoint::{BindingDecision, BindingPolicy, RequestContext};
+//! use oxide_auth::frontends::binding::NetworkBinding;
+//!
+//! let mut policy = NetworkBinding::new().on_mismatch(BindingDecision::Reauthenticate);
+//! let issued = RequestContext {
+//!     remote_addr: Some("198.51.100.7:53124".to_owned()),
+//!     user_agent: Some("Client/1.0".to_owned()),
+//!     ..RequestContext::default()
+//! };
+//! let binding = policy.bind(&issued).unwrap();
+//!
+//! let moved = RequestContext {
+//!     remote_addr: Some("198.51.100.42".to_owned()),
+//!     ..issued.clone()
+//! };
+//! assert_eq!(policy.check(&binding, Some(&moved)), BindingDecision::Allow);
+//! assert_eq!(policy.check(&binding, None), BindingDecision::Reauthenticate);
+//! ```
+//!
+//! [`BindingPolicy`]: ../../endpoint/trait.BindingPolicy.html
+//! [`Endpoint::binding_policy`]: ../../endpoint/trait.Endpoint.html#method.binding_policy
+//! [`Bound`]: ../simple/endpoint/struct.Bound.html
+//! [`NetworkBinding`]: struct.NetworkBinding.html
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::endpoint::{BindingDecision, BindingPolicy, RequestContext};
+
+/// Binds tokens to the network prefix of the remote address and to the user agent.
+///
+/// Each part of the context is only bound when the frontend supplied it, a token issued without a
+/// user agent is accepted from any user agent. The binding keeps the network and a digest of the
+/// user agent, not the address or the user agent itself.
+#[derive(Clone, Copy, Debug)]
+pub struct NetworkBinding {
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    user_agent: bool,
+    mismatch: BindingDecision,
+}
+
+impl NetworkBinding {
+    /// Bind to the `/24` network of IPv4 and the `/64` network of IPv6 addresses and to the user
+    /// agent, rejecting requests that do not match.
+    pub fn new() -> Self {
+        NetworkBinding {
+            ipv4_prefix: 24,
+            ipv6_prefix: 64,
+            user_agent: true,
+            mismatch: BindingDecision::Reject,
+        }
+    }
+
+    /// Bind to the networks with these prefix lengths.
+    ///
+    /// Longer prefixes are shortened to the length of the address. A length of `0` does not bind
+    /// to addresses of that family.
+    pub fn prefixes(mut self, ipv4: u8, ipv6: u8) -> Self {
+        self.ipv4_prefix = ipv4.min(32);
+        self.ipv6_prefix = ipv6.min(128);
+        self
+    }
+
+    /// Whether to bind to the user agent.
+    pub fn user_agent(mut self, bind: bool) -> Self {
+        self.user_agent = bind;
+        self
+    }
+
+    /// The decision over requests not matching the binding of their token.
+    ///
+    /// `BindingDecision::Allow` disables the check while still binding tokens, for example to
+    /// observe mismatches before enforcing them.
+    pub fn on_mismatch(mut self, decision: BindingDecision) -> Self {
+        self.mismatch = decision;
+        self
+    }
+
+    /// The bound parts of a context, each as `name=value`.
+    fn parts(&self, context: &RequestContext) -> Vec<String> {
+        let mut parts = Vec::new();
+        let network = context.remote_addr.as_deref().and_then(|addr| self.network(addr));
+        if let Some(network) = network {
+            parts.push(format!("net={}", network));
+        }
+
+        if let Some(user_agent) = context.user_agent.as_deref().filter(|_| self.user_agent) {
+            let digest = URL_SAFE_NO_PAD.encode(Sha256::digest(user_agent.as_bytes()));
+            parts.push(format!("ua={}", digest));
+        }
+
+        parts
+    }
+
+    /// The network of an address, with or without a port.
+    fn network(&self, addr: &str) -> Option<String> {
+        let ip = match addr.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => addr.parse::<SocketAddr>().ok()?.ip(),
+        };
+
+        // Clients reaching a dual stack socket over IPv4 must not change their network.
+        let ip = match ip {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(IpAddr::V6(ip)),
+            ip => ip,
+        };
+
+        match ip {
+            IpAddr::V4(_) if self.ipv4_prefix == 0 => None,
+            IpAddr::V6(_) if self.ipv6_prefix == 0 => None,
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX << (32 - u32::from(self.ipv4_prefix));
+                let network = Ipv4Addr::from(u32::from(ip) & mask);
+                Some(format!("{}/{}", network, self.ipv4_prefix))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX << (128 - u32::from(self.ipv6_prefix));
+                let network = Ipv6Addr::from(u128::from(ip) & mask);
+                Some(format!("{}/{}", network, self.ipv6_prefix))
+            }
+        }
+    }
+}
+
+impl Default for NetworkBinding {
+    fn default() -> Self {
+        NetworkBinding::new()
+    }
+}
+
+impl BindingPolicy for NetworkBinding {
+    fn bind(&mut self, context: &RequestContext) -> Option<String> {
+        let parts = self.parts(context);
+        if parts.is_empty() {
+            return None;
+        }
+
+        Some(parts.join(";"))
+    }
+
+    fn check(&mut self, binding: &str, context: Option<&RequestContext>) -> BindingDecision {
+        let current = context.map(|context| self.parts(context)).unwrap_or_default();
+        let matches = binding
+            .split(';')
+            .filter(|part| !part.is_empty())
+            .all(|part| current.iter().any(|current| current == part));
+
+        if matches {
+            BindingDecision::Allow
+        } else {
+            self.mismatch
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(remote_addr: Option<&str>, user_agent: Option<&str>) -> RequestContext {
+        RequestContext {
+            remote_addr: remote_addr.map(str::to_owned),
+            user_agent: user_agent.map(str::to_owned),
+            ..RequestContext::default()
+        }
+    }
+
+    #[test]
+    fn binds_networks() {
+        let mut policy = NetworkBinding::new();
+        let binding = policy
+            .bind(&context(Some("198.51.100.7:443"), Some("Client/1.0")))
+            .unwrap();
+        assert!(binding.starts_with("net=198.51.100.0/24;ua="));
+        assert!(!binding.contains("Client"));
+
+        let same = context(Some("198.51.100.200"), Some("Client/1.0"));
+        assert_eq!(policy.check(&binding, Some(&same)), BindingDecision::Allow);
+        let mapped = context(Some("[::ffff:198.51.100.9]:443"), Some("Client/1.0"));
+        assert_eq!(policy.check(&binding, Some(&mapped)), BindingDecision::Allow);
+
+        let network = context(Some("198.51.101.7"), Some("Client/1.0"));
+        assert_eq!(policy.check(&binding, Some(&network)), BindingDecision::Reject);
+        let agent = context(Some("198.51.100.7"), Some("Other/2.0"));
+        assert_eq!(policy.check(&binding, Some(&agent)), BindingDecision::Reject);
+        let missing = context(None, Some("Client/1.0"));
+        assert_eq!(policy.check(&binding, Some(&missing)), BindingDecision::Reject);
+        assert_eq!(policy.check(&binding, None), BindingDecision::Reject);
+
+        let binding = policy.bind(&context(Some("2001:db8:1:2:3::4"), None)).unwrap();
+        assert_eq!(binding, "net=2001:db8:1:2::/64");
+        let roamed = context(Some("2001:db8:1:2:ffff::1"), Some("Client/1.0"));
+        assert_eq!(policy.check(&binding, Some(&roamed)), BindingDecision::Allow);
+    }
+
+    #[test]
+    fn configured_binding() {
+        let mut policy = NetworkBinding::new()
+            .prefixes(0, 48)
+            .user_agent(false)
+            .on_mismatch(BindingDecision::Reauthenticate);
+        assert_eq!(
+            policy.bind(&context(Some("198.51.100.7"), Some("Client/1.0"))),
+            None
+        );
+        assert_eq!(policy.bind(&context(Some("not an address"), None)), None);
+
+        let binding = policy.bind(&context(Some("2001:db8:1:2::1"), None)).unwrap();
+        assert_eq!(binding, "net=2001:db8:1::/48");
+        let other = context(Some("2001:db8:2::1"), None);
+        assert_eq!(
+            policy.check(&binding, Some(&other)),
+            BindingDecision::Reauthenticate
+        );
+
+        let mut full = NetworkBinding::new().prefixes(40, 200);
+        let binding = full.bind(&context(Some("198.51.100.7"), None)).unwrap();
+        assert_eq!(binding, "net=198.51.100.7/32");
+    }
+}
@@ -0,0 +1,103 @@
+use base64::{engine::general_purpose::STANDARD, engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+/// The certificate a client presented during the TLS handshake.
+///
+/// Frontends attach it to their requests when the connection was established with mutual TLS,
+/// either terminated by the server itself or by a trusted proxy forwarding the certificate. It
+/// authenticates clients as described in RFC 8705 and binds tokens to the certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCertificate {
+    der: Vec<u8>,
+}
+
+impl PeerCertificate {
+    /// The name of the header in which proxies such as Envoy forward the client certificate.
+    pub const FORWARDED_HEADER: &'static str = "x-forwarded-client-cert";
+
+    /// A certificate in its DER encoding, as provided by TLS libraries.
+    pub fn from_der(der: Vec<u8>) -> Self {
+        PeerCertificate { der }
+    }
+
+    /// Parse the first certificate of a PEM document.
+    pub fn from_pem(pem: &str) -> Option<Self> {
+        let start = pem.find("-----BEGIN CERTIFICATE-----")? + "-----BEGIN CERTIFICATE-----".len();
+        let end = start + pem[start..].find("-----END CERTIFICATE-----")?;
+        let base64: String = pem[start..end].split_whitespace().collect();
+        STANDARD.decode(base64).ok().map(PeerCertificate::from_der)
+    }
+
+    /// Read the certificate of the client from the value of an `X-Forwarded-Client-Cert` header.
+    ///
+    /// Proxies append an element for each hop, the first one describes the original client. Its
+    /// `Cert` field is the url encoded PEM certificate.
+    pub fn from_forwarded(header: &str) -> Option<Self> {
+        let element = header.split(',').next()?;
+        let cert = element.split(';').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            if key.trim().eq_ignore_ascii_case("cert") {
+                Some(value.trim().trim_matches('"'))
+            } else {
+                None
+            }
+        })?;
+
+        PeerCertificate::from_pem(&percent_decode(cert)?)
+    }
+
+    /// The DER encoding of the certificate.
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// The SHA-256 thumbprint, base64url encoded as in the `x5t#S256` confirmation method.
+    pub fn thumbprint(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(&self.der))
+    }
+}
+
+fn percent_decode(encoded: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+
+        let high = char::from(bytes.next()?).to_digit(16)?;
+        let low = char::from(bytes.next()?).to_digit(16)?;
+        decoded.push((high * 16 + low) as u8);
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEM: &str = "-----BEGIN CERTIFICATE-----\nAAECAwQF\nBgc=\n-----END CERTIFICATE-----\n";
+
+    #[test]
+    fn parses_pem() {
+        let certificate = PeerCertificate::from_pem(PEM).unwrap();
+        assert_eq!(certificate.der(), &[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert!(PeerCertificate::from_pem("-----BEGIN CERTIFICATE-----\n!!\n").is_none());
+    }
+
+    #[test]
+    fn parses_forwarded_header() {
+        let cert = "-----BEGIN%20CERTIFICATE-----%0AAAECAwQF%0ABgc%3D%0A-----END%20CERTIFICATE-----%0A";
+        let header = format!(
+            "By=spiffe://proxy;Hash=abc;Cert=\"{}\";Subject=\"CN=client\",By=spiffe://other;Hash=def",
+            cert
+        );
+
+        let certificate = PeerCertificate::from_forwarded(&header).unwrap();
+        assert_eq!(certificate, PeerCertificate::from_pem(PEM).unwrap());
+        assert_eq!(certificate.thumbprint().len(), 43);
+        assert!(PeerCertificate::from_forwarded("By=spiffe://proxy;Hash=abc").is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use url::{Origin, Url};
+
+use crate::primitives::registrar::{EncodedClient, RegisteredUrl};
+
+/// The methods of the token, revocation and metadata endpoints.
+const ALLOWED_METHODS: &str = "GET, POST";
+
+/// Clients authenticate with the `Authorization` header and may send JSON.
+const ALLOWED_HEADERS: &str = "authorization, content-type";
+
+/// The origins allowed to call endpoints from a browser, for cross-origin resource sharing.
+///
+/// Public clients running in the browser, for example single page applications using PKCE, call
+/// the token endpoint from the origin of their redirect URI. Derive the allowed origins from the
+/// registered clients with [`Cors::from_clients`] and add others with [`Cors::allow_origin`].
+/// Frontends use the headers computed here to answer preflight requests and to decorate the actual
+/// responses.
+///
+/// Cookies are never allowed, since none of the endpoints rely on them.
+#[derive(Clone, Debug)]
+pub struct Cors {
+    origins: HashSet<Origin>,
+    /// Scheme and host of redirect URIs on `localhost` that accept any port.
+    any_port: HashSet<(String, String)>,
+    max_age: u32,
+}
+
+impl Cors {
+    /// A policy allowing no origin at all.
+    pub fn new() -> Self {
+        Cors {
+            origins: HashSet::new(),
+            any_port: HashSet::new(),
+            max_age: 600,
+        }
+    }
+
+    /// Allow the origins of the redirect URIs of all clients.
+    ///
+    /// Redirect URIs that ignore the port on `localhost` allow all ports of that host.
+    pub fn from_clients<'a, I>(clients: I) -> Self
+    where
+        I: IntoIterator<Item = &'a EncodedClient>,
+    {
+        let mut cors = Cors::new();
+        for client in clients {
+            let uris = Some(&client.redirect_uri).into_iter();
+            for uri in uris.chain(&client.additional_redirect_uris) {
+                cors.allow_redirect_uri(uri);
+            }
+        }
+        cors
+    }
+
+    /// Allow requests from the origin of the url.
+    pub fn allow_origin(&mut self, url: &Url) {
+        let origin = url.origin();
+        if origin.is_tuple() {
+            self.origins.insert(origin);
+        }
+    }
+
+    fn allow_redirect_uri(&mut self, uri: &RegisteredUrl) {
+        let url = uri.to_url();
+        match (uri, url.host_str()) {
+            (RegisteredUrl::IgnorePortOnLocalhost(_), Some("localhost")) => {
+                self.any_port
+                    .insert((url.scheme().to_owned(), "localhost".to_owned()));
+            }
+            _ => self.allow_origin(&url),
+        }
+    }
+
+    /// Set how long browsers may cache the answer to a preflight request, in seconds.
+    ///
+    /// Ten minutes by default.
+    pub fn set_max_age(&mut self, seconds: u32) {
+        self.max_age = seconds;
+    }
+
+    /// Whether requests with the value of this `Origin` header are allowed.
+    pub fn allows(&self, origin: &str) -> bool {
+        let url = match Url::parse(origin) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+
+        if self.origins.contains(&url.origin()) {
+            return true;
+        }
+
+        match url.host_str() {
+            Some(host) => self
+                .any_port
+                .contains(&(url.scheme().to_owned(), host.to_owned())),
+            None => false,
+        }
+    }
+
+    /// Headers to add to the response of an actual request from the origin.
+    ///
+    /// Empty if the origin is not allowed. Responses should nevertheless carry `Vary: Origin`
+    /// since their headers depend on it.
+    pub fn response_headers(&self, origin: &str) -> Vec<(&'static str, String)> {
+        if !self.allows(origin) {
+            return Vec::new();
+        }
+
+        vec![("access-control-allow-origin", origin.to_owned())]
+    }
+
+    /// Headers answering a preflight request from the origin.
+    ///
+    /// Empty if the origin is not allowed, in which case the browser will not send the actual
+    /// request.
+    pub fn preflight_headers(&self, origin: &str) -> Vec<(&'static str, String)> {
+        if !self.allows(origin) {
+            return Vec::new();
+        }
+
+        vec![
+            ("access-control-allow-origin", origin.to_owned()),
+            ("access-control-allow-methods", ALLOWED_METHODS.to_owned()),
+            ("access-control-allow-headers", ALLOWED_HEADERS.to_owned()),
+            ("access-control-max-age", self.max_age.to_string()),
+        ]
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::registrar::{Client, ClientMap, IgnoreLocalPortUrl};
+
+    fn cors() -> Cors {
+        let mut clients = ClientMap::new();
+        clients.register_client(Client::public(
+            "spa",
+            "https://app.example/callback".parse::<Url>().unwrap().into(),
+            "default".parse().unwrap(),
+        ));
+        clients.register_client(Client::public(
+            "native",
+            IgnoreLocalPortUrl::new("http://localhost/callback")
+                .unwrap()
+                .into(),
+            "default".parse().unwrap(),
+        ));
+        Cors::from_clients(clients.clients())
+    }
+
+    #[test]
+    fn allows_client_origins() {
+        let cors = cors();
+        assert!(cors.allows("https://app.example"));
+        assert!(cors.allows("https://app.example:443"));
+        assert!(cors.allows("http://localhost:8080"));
+        assert!(cors.allows("http://localhost"));
+
+        assert!(!cors.allows("http://app.example"));
+        assert!(!cors.allows("https://evil.example"));
+        assert!(!cors.allows("https://localhost:8080"));
+        assert!(!cors.allows("null"));
+    }
+
+    #[test]
+    fn preflight() {
+        let mut cors = cors();
+        cors.set_max_age(60);
+
+        let headers = cors.preflight_headers("https://app.example");
+        assert!(headers.contains(&("access-control-allow-origin", "https://app.example".into())));
+        assert!(headers.contains(&("access-control-max-age", "60".into())));
+        assert!(cors.preflight_headers("https://evil.example").is_empty());
+        assert!(cors.response_headers("https://evil.example").is_empty());
+    }
+}
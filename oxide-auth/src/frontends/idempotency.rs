@@ -0,0 +1,130 @@
+//! Replaying token responses to retried requests.
+//!
+//! Endpoints consult the [`IdempotencyStore`] returned by [`Endpoint::idempotency_store`], for
+//! example with the [`Idempotent`] wrapper. Clients mark their token requests with an
+//! `Idempotency-Key` header, which frontends read into the `RequestContext`. A retry with the same
+//! key and the same parameters receives the originally issued token instead of an `invalid_grant`
+//! error for its already redeemed code.
+//!
+//! [`IdempotencyMap`] keeps the responses in memory, for a bounded time and up to a bounded
+//! number of keys.
+//!
+//! [`IdempotencyStore`]: ../../endpoint/trait.IdempotencyStore.html
+//! [`Endpoint::idempotency_store`]: ../../endpoint/trait.Endpoint.html#method.idempotency_store
+//! [`Idempotent`]: ../simple/endpoint/struct.Idempotent.html
+//! [`IdempotencyMap`]: struct.IdempotencyMap.html
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::endpoint::{IdempotencyStore, IdempotentResponse};
+
+/// Remembers responses in memory for a limited time.
+///
+/// When full, expired responses are dropped first and then the oldest ones.
+pub struct IdempotencyMap {
+    retention: Duration,
+    capacity: usize,
+    responses: HashMap<String, Remembered>,
+}
+
+struct Remembered {
+    at: DateTime<Utc>,
+    response: IdempotentResponse,
+}
+
+impl IdempotencyMap {
+    /// Remember up to `capacity` responses, each for the duration of `retention`.
+    pub fn new(retention: Duration, capacity: usize) -> Self {
+        IdempotencyMap {
+            retention,
+            capacity,
+            responses: HashMap::new(),
+        }
+    }
+
+    fn replay_at(&mut self, key: &str, now: DateTime<Utc>) -> Option<IdempotentResponse> {
+        match self.responses.get(key) {
+            Some(remembered) if remembered.at + self.retention > now => {
+                Some(remembered.response.clone())
+            }
+            Some(_) => {
+                self.responses.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn remember_at(&mut self, key: &str, response: IdempotentResponse, now: DateTime<Utc>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.responses.len() >= self.capacity && !self.responses.contains_key(key) {
+            let retention = self.retention;
+            self.responses
+                .retain(|_, remembered| remembered.at + retention > now);
+        }
+
+        while self.responses.len() >= self.capacity && !self.responses.contains_key(key) {
+            let oldest = self
+                .responses
+                .iter()
+                .min_by_key(|(_, remembered)| remembered.at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.responses.remove(&oldest),
+                None => break,
+            };
+        }
+
+        self.responses
+            .insert(key.to_owned(), Remembered { at: now, response });
+    }
+}
+
+impl IdempotencyStore for IdempotencyMap {
+    fn replay(&mut self, key: &str) -> Option<IdempotentResponse> {
+        self.replay_at(key, Utc::now())
+    }
+
+    fn remember(&mut self, key: &str, response: IdempotentResponse) {
+        self.remember_at(key, response, Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &str) -> IdempotentResponse {
+        IdempotentResponse {
+            fingerprint: "fingerprint".to_owned(),
+            body: body.to_owned(),
+        }
+    }
+
+    #[test]
+    fn bounded_retention() {
+        let mut map = IdempotencyMap::new(Duration::seconds(60), 2);
+        let now = Utc::now();
+        let later = now + Duration::seconds(30);
+
+        map.remember_at("first", response("1"), now);
+        map.remember_at("second", response("2"), now + Duration::seconds(10));
+        assert_eq!(map.replay_at("first", later), Some(response("1")));
+
+        // The oldest response makes room for the new one.
+        map.remember_at("third", response("3"), later);
+        assert_eq!(map.replay_at("first", later), None);
+        assert_eq!(map.replay_at("second", later), Some(response("2")));
+        assert_eq!(map.replay_at("third", later), Some(response("3")));
+
+        assert_eq!(map.replay_at("second", now + Duration::seconds(70)), None);
+        assert_eq!(
+            map.replay_at("third", now + Duration::seconds(70)),
+            Some(response("3"))
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::endpoint::NormalizedParameter;
+
+/// Bounds on the size of requests, enforced by frontends before parameters are parsed.
+///
+/// None of the flows need large requests, so the defaults are generous for legitimate clients
+/// while an endpoint no longer buffers and parses megabytes of form data on behalf of anyone who
+/// sends it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum length of a body in bytes, 64 KiB by default.
+    pub body: usize,
+
+    /// The maximum length of the query string in bytes, 8 KiB by default.
+    pub query: usize,
+
+    /// The maximum number of parameters in either the query or the body, 64 by default.
+    pub parameters: usize,
+}
+
+impl Limits {
+    /// Whether a urlencoded query string is within the limits.
+    pub fn allow_query(&self, query: &str) -> bool {
+        query.len() <= self.query && count_pairs(query.as_bytes()) <= self.parameters
+    }
+
+    /// Whether a body of the announced or already read length is within the limits.
+    pub fn allow_length(&self, length: usize) -> bool {
+        length <= self.body
+    }
+
+    /// Whether a urlencoded body is within the limits.
+    pub fn allow_form(&self, body: &[u8]) -> bool {
+        self.allow_length(body.len()) && count_pairs(body) <= self.parameters
+    }
+
+    /// Whether already parsed parameters, for example of a JSON body, are within the limits.
+    pub fn allow_parameters(&self, parameters: &NormalizedParameter) -> bool {
+        parameters.len() <= self.parameters
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            body: 64 * 1024,
+            query: 8 * 1024,
+            parameters: 64,
+        }
+    }
+}
+
+/// Count the pairs of a urlencoded string without decoding them.
+fn count_pairs(encoded: &[u8]) -> usize {
+    encoded
+        .split(|&byte| byte == b'&')
+        .filter(|pair| !pair.is_empty())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_parameters() {
+        let limits = Limits {
+            parameters: 2,
+            ..Limits::default()
+        };
+
+        assert!(limits.allow_query("response_type=code&client_id=client"));
+        assert!(limits.allow_query("response_type=code&&client_id=client&"));
+        assert!(!limits.allow_query("response_type=code&client_id=client&state=abc"));
+        assert!(!limits.allow_form(b"a=1&b=2&c=3"));
+        assert!(limits.allow_form(b""));
+    }
+
+    #[test]
+    fn limits_length() {
+        let limits = Limits {
+            body: 8,
+            query: 4,
+            ..Limits::default()
+        };
+
+        assert!(limits.allow_query("a=12"));
+        assert!(!limits.allow_query("a=123"));
+        assert!(limits.allow_form(b"code=123"));
+        assert!(!limits.allow_form(b"code=1234"));
+        assert!(!limits.allow_length(9));
+    }
+}
@@ -0,0 +1,451 @@
+//! Lockout of clients after repeated failed authentication.
+//!
+//! Without a limit, the secret of a confidential client can be guessed at the token endpoint as
+//! fast as the endpoint answers. [`Lockout`] wraps a registrar and counts the failed
+//! authentications of each client in a [`LockoutStore`]. After a threshold of consecutive
+//! failures the client is locked for a while, with the duration doubling for each further failure.
+//! A locked client is refused without checking its credentials, so the flows answer as for any
+//! other failed authentication. A successful authentication resets the count. Failures are only
+//! counted for clients known to the registrar, guessing client ids does not fill the store.
+//!
+//! The store counts each failure atomically, so that concurrent guesses all count towards the lock.
+//! [`MemoryStore`] keeps the counts of a single server. Servers sharing the work of an endpoint
+//! should implement the store on top of their shared storage instead, incrementing the count in a
+//! single operation. Locking a client is reported as an [`AuditKind::ClientLocked`] event to an
+//! optional audit sink.
+//!
+//! [`Lockout`]: struct.Lockout.html
+//! [`LockoutStore`]: trait.LockoutStore.html
+//! [`MemoryStore`]: struct.MemoryStore.html
+//! [`AuditKind::ClientLocked`]: ../audit/enum.AuditKind.html#variant.ClientLocked
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::frontends::audit::{AuditEvent, AuditKind, AuditSink, RequestMetadata};
+use crate::primitives::registrar::{
+    BoundClient, ClientAuthMethod, ClientUrl, GrantType, PreGrant, RefreshPolicy, Registrar,
+    RegistrarError,
+};
+use crate::primitives::scope::Scope;
+
+/// The number of tracked clients above which the memory store drops forgotten failures.
+const PRUNE_AT: usize = 1024;
+
+/// The default number of clients tracked by the memory store.
+const CAPACITY: usize = 65536;
+
+/// Failures are forgotten a day after the last one, which also bounds the duration of locks.
+fn forgotten(failures: &Failures, now: DateTime<Utc>) -> bool {
+    failures.last + Duration::days(1) <= now
+}
+
+/// The failed authentications of a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Failures {
+    /// The number of consecutive failures.
+    pub count: u32,
+
+    /// The time of the last failure.
+    pub last: DateTime<Utc>,
+}
+
+/// Stores the failed authentications of clients.
+pub trait LockoutStore {
+    /// The failures recorded for a client.
+    fn get(&self, client_id: &str) -> Option<Failures>;
+
+    /// Count another failure of a client at `now`, returning the failures including it.
+    ///
+    /// The count must be incremented atomically, concurrent failures must each be counted. It
+    /// starts over at one when the last failure was more than a day ago.
+    fn record_failure(&self, client_id: &str, now: DateTime<Utc>) -> Failures;
+
+    /// Forget the failures of a client, after it authenticated successfully.
+    fn remove(&self, client_id: &str);
+}
+
+/// Keeps failed authentications in memory.
+///
+/// At most a fixed number of clients are tracked, 65536 by default. When full, the client with the
+/// oldest failure is forgotten.
+pub struct MemoryStore {
+    capacity: usize,
+    inner: Mutex<MemoryInner>,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    failures: HashMap<String, Failures>,
+    pruned: Option<DateTime<Utc>>,
+}
+
+/// A registrar locking out clients after repeated failed authentication.
+pub struct Lockout<R, S> {
+    registrar: R,
+    store: S,
+    threshold: u32,
+    duration: Duration,
+    max_duration: Duration,
+    sink: Option<Mutex<Box<dyn AuditSink + Send>>>,
+}
+
+impl MemoryStore {
+    /// A store without any failures.
+    pub fn new() -> Self {
+        MemoryStore::with_capacity(CAPACITY)
+    }
+
+    /// A store tracking at most `capacity` clients.
+    pub fn with_capacity(capacity: usize) -> Self {
+        MemoryStore {
+            capacity: capacity.max(1),
+            inner: Mutex::new(MemoryInner::default()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryInner> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new()
+    }
+}
+
+impl MemoryInner {
+    /// Make room for another client.
+    fn reserve(&mut self, capacity: usize, now: DateTime<Utc>) {
+        // Drop forgotten failures at most hourly, not on every failure of a full store.
+        let due = match self.pruned {
+            Some(pruned) => pruned + Duration::hours(1) <= now,
+            None => true,
+        };
+        if self.failures.len() >= PRUNE_AT && due {
+            self.failures.retain(|_, kept| !forgotten(kept, now));
+            self.pruned = Some(now);
+        }
+
+        if self.failures.len() >= capacity {
+            let oldest = self
+                .failures
+                .iter()
+                .min_by_key(|(_, failures)| failures.last)
+                .map(|(client_id, _)| client_id.clone());
+            if let Some(oldest) = oldest {
+                self.failures.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl LockoutStore for MemoryStore {
+    fn get(&self, client_id: &str) -> Option<Failures> {
+        self.lock().failures.get(client_id).copied()
+    }
+
+    fn record_failure(&self, client_id: &str, now: DateTime<Utc>) -> Failures {
+        let mut inner = self.lock();
+        if !inner.failures.contains_key(client_id) {
+            inner.reserve(self.capacity, now);
+        }
+
+        let failures = inner
+            .failures
+            .entry(client_id.to_owned())
+            .or_insert(Failures { count: 0, last: now });
+        if forgotten(failures, now) {
+            failures.count = 0;
+        }
+
+        failures.count = failures.count.saturating_add(1);
+        failures.last = failures.last.max(now);
+        *failures
+    }
+
+    fn remove(&self, client_id: &str) {
+        self.lock().failures.remove(client_id);
+    }
+}
+
+impl<S: LockoutStore + ?Sized> LockoutStore for &S {
+    fn get(&self, client_id: &str) -> Option<Failures> {
+        (**self).get(client_id)
+    }
+
+    fn record_failure(&self, client_id: &str, now: DateTime<Utc>) -> Failures {
+        (**self).record_failure(client_id, now)
+    }
+
+    fn remove(&self, client_id: &str) {
+        (**self).remove(client_id)
+    }
+}
+
+impl<S: LockoutStore + ?Sized> LockoutStore for Arc<S> {
+    fn get(&self, client_id: &str) -> Option<Failures> {
+        (**self).get(client_id)
+    }
+
+    fn record_failure(&self, client_id: &str, now: DateTime<Utc>) -> Failures {
+        (**self).record_failure(client_id, now)
+    }
+
+    fn remove(&self, client_id: &str) {
+        (**self).remove(client_id)
+    }
+}
+
+impl<R, S> Lockout<R, S> {
+    /// Lock out clients of a registrar, with the failures kept in `store`.
+    ///
+    /// By default a client is locked for one minute after five consecutive failures. Each
+    /// further failure doubles the duration, up to an hour.
+    pub fn new(registrar: R, store: S) -> Self {
+        Lockout {
+            registrar,
+            store,
+            threshold: 5,
+            duration: Duration::minutes(1),
+            max_duration: Duration::hours(1),
+            sink: None,
+        }
+    }
+
+    /// Lock clients after this many consecutive failures.
+    pub fn with_threshold(self, threshold: u32) -> Self {
+        Lockout { threshold, ..self }
+    }
+
+    /// Lock clients for `duration` at first, and at most for `max_duration`.
+    ///
+    /// Failures are forgotten after a day, so no lock lasts longer than that.
+    pub fn with_duration(self, duration: Duration, max_duration: Duration) -> Self {
+        Lockout {
+            duration,
+            max_duration: max_duration.min(Duration::days(1)),
+            ..self
+        }
+    }
+
+    /// Report locked clients to an audit sink.
+    pub fn with_sink<A: AuditSink + Send + 'static>(self, sink: A) -> Self {
+        Lockout {
+            sink: Some(Mutex::new(Box::new(sink))),
+            ..self
+        }
+    }
+
+    /// The wrapped registrar.
+    pub fn registrar(&self) -> &R {
+        &self.registrar
+    }
+}
+
+impl<R: Registrar, S: LockoutStore> Lockout<R, S> {
+    fn check_at(
+        &self, client_id: &str, passphrase: Option<&[u8]>, now: DateTime<Utc>,
+    ) -> Result<(), RegistrarError> {
+        let failures = self.store.get(client_id);
+        if let Some(until) = failures.and_then(|failures| self.locked_until(&failures)) {
+            if until > now {
+                return Err(RegistrarError::Unspecified);
+            }
+        }
+
+        match self.registrar.check(client_id, passphrase) {
+            Ok(()) => {
+                if failures.is_some() {
+                    self.store.remove(client_id);
+                }
+                Ok(())
+            }
+            Err(RegistrarError::Unspecified) => {
+                self.failed(client_id, now);
+                Err(RegistrarError::Unspecified)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The end of the lock following these failures, if any.
+    fn locked_until(&self, failures: &Failures) -> Option<DateTime<Utc>> {
+        let beyond = failures.count.checked_sub(self.threshold)?;
+        let factor = 1i32.checked_shl(beyond.min(30)).unwrap_or(i32::MAX);
+        let duration = self
+            .duration
+            .checked_mul(factor)
+            .map_or(self.max_duration, |duration| duration.min(self.max_duration));
+        Some(failures.last + duration)
+    }
+
+    fn failed(&self, client_id: &str, now: DateTime<Utc>) {
+        // Unknown clients can not be locked out, counting them only fills the store.
+        let known = ClientUrl {
+            client_id: Cow::Borrowed(client_id),
+            redirect_uri: None,
+        };
+        if self.registrar.bound_redirect(known).is_err() {
+            return;
+        }
+
+        let failures = self.store.record_failure(client_id, now);
+        if self.locked_until(&failures).is_some() {
+            self.report(client_id, now);
+        }
+    }
+
+    fn report(&self, client_id: &str, now: DateTime<Utc>) {
+        let sink = match &self.sink {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        let event = AuditEvent {
+            kind: AuditKind::ClientLocked,
+            occurred_at: now,
+            client_id: Some(client_id.to_owned()),
+            owner_id: None,
+            scope: None,
+            metadata: RequestMetadata::default(),
+        };
+        sink.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .event(event);
+    }
+}
+
+impl<R: Registrar, S: LockoutStore> Registrar for Lockout<R, S> {
+    fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        self.registrar.bound_redirect(bound)
+    }
+
+    fn negotiate(&self, bound: BoundClient, scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
+        self.registrar.negotiate(bound, scope)
+    }
+
+    fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        self.check_at(client_id, passphrase, Utc::now())
+    }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.registrar.refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.registrar.permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.registrar.auth_method(client_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<AuditEvent>>>);
+
+    impl AuditSink for Shared {
+        fn event(&mut self, event: AuditEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    fn registrar() -> ClientMap {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            "client",
+            RegisteredUrl::Semantic("https://client.example/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+            b"secret",
+        ));
+        registrar
+    }
+
+    #[test]
+    fn locks_after_threshold() {
+        let events = Shared::default();
+        let lockout = Lockout::new(registrar(), MemoryStore::new())
+            .with_threshold(2)
+            .with_sink(events.clone());
+        let now = Utc::now();
+
+        assert!(lockout.check_at("client", Some(b"guess"), now).is_err());
+        assert!(lockout.check_at("client", Some(b"secret"), now).is_ok());
+
+        assert!(lockout.check_at("client", Some(b"guess"), now).is_err());
+        assert!(lockout.check_at("client", Some(b"guess"), now).is_err());
+        assert_eq!(events.0.lock().unwrap()[0].kind, AuditKind::ClientLocked);
+
+        // Locked, even with the correct secret.
+        assert!(lockout.check_at("client", Some(b"secret"), now).is_err());
+        let later = now + Duration::minutes(1);
+        assert!(lockout.check_at("client", Some(b"guess"), later).is_err());
+
+        // The next lock lasts twice as long.
+        let next = later + Duration::minutes(1);
+        assert!(lockout.check_at("client", Some(b"secret"), next).is_err());
+        let after = later + Duration::minutes(2);
+        assert!(lockout.check_at("client", Some(b"secret"), after).is_ok());
+        assert_eq!(lockout.store.get("client"), None);
+    }
+
+    #[test]
+    fn counts_concurrent_failures() {
+        let lockout = Lockout::new(registrar(), MemoryStore::new()).with_threshold(8);
+        let now = Utc::now();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..4 {
+                        let _ = lockout.check_at("client", Some(b"guess"), now);
+                    }
+                });
+            }
+        });
+
+        let failures = lockout.store.get("client").unwrap();
+        assert!(failures.count >= 8);
+        assert!(lockout.check_at("client", Some(b"secret"), now).is_err());
+    }
+
+    #[test]
+    fn ignores_unknown_clients() {
+        let lockout = Lockout::new(registrar(), MemoryStore::new());
+        let now = Utc::now();
+
+        for guess in 0..16 {
+            let client_id = format!("unknown-{}", guess);
+            assert!(lockout.check_at(&client_id, Some(b"guess"), now).is_err());
+        }
+        assert!(lockout.store.lock().failures.is_empty());
+    }
+
+    #[test]
+    fn bounded_memory_store() {
+        let store = MemoryStore::with_capacity(2);
+        let now = Utc::now();
+
+        store.record_failure("first", now);
+        store.record_failure("second", now + Duration::seconds(1));
+        store.record_failure("second", now + Duration::seconds(2));
+        store.record_failure("third", now + Duration::seconds(3));
+
+        assert_eq!(store.get("first"), None);
+        assert_eq!(store.get("second").unwrap().count, 2);
+        assert_eq!(store.get("third").unwrap().count, 1);
+
+        // The count starts over after a day without failures.
+        let later = now + Duration::days(2);
+        assert_eq!(store.record_failure("second", later).count, 1);
+    }
+}
@@ -0,0 +1,331 @@
+//! Metrics of the flows, in the Prometheus text format.
+//!
+//! Endpoints report to any [`Metrics`] collector returned by [`Endpoint::metrics`], for example
+//! with the [`Metered`] wrapper. [`PrometheusMetrics`] counts them in memory and renders them in
+//! the text exposition format for a scrape handler, without depending on a metrics library. Other
+//! systems can be plugged in by implementing the trait instead. Issuers are not aware of endpoints,
+//! wrap them in a [`TimedIssuer`] to measure their latency and count active tokens.
+//!
+//! The following metrics are exposed:
+//!
+//! * `oxide_auth_grants_total`, a counter labelled with the `event` and its `outcome`.
+//! * `oxide_auth_errors_total`, a counter labelled with the `error` code sent to clients.
+//! * `oxide_auth_issuer_duration_seconds`, a histogram labelled with the issuer `call`.
+//! * `oxide_auth_active_tokens`, a gauge estimating the issued access tokens not yet expired. It
+//!   does not know about revoked tokens.
+//! * `oxide_auth_swept_total`, a counter labelled with the `store` that expired entries were
+//!   removed from by a [`Sweeper`].
+//!
+//! [`Metrics`]: ../../endpoint/trait.Metrics.html
+//! [`Endpoint::metrics`]: ../../endpoint/trait.Endpoint.html#method.metrics
+//! [`Metered`]: ../simple/endpoint/struct.Metered.html
+//! [`PrometheusMetrics`]: struct.PrometheusMetrics.html
+//! [`TimedIssuer`]: struct.TimedIssuer.html
+//! [`Sweeper`]: ../sweep/struct.Sweeper.html
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fmt::Write;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::endpoint::{GrantEvent, GrantOutcome, GrantRecord, IssuerCall, Metrics};
+use crate::primitives::grant::Grant;
+use crate::primitives::issuer::{IssuedToken, Issuer, RefreshedToken};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Collects metrics in memory and renders them for Prometheus.
+#[derive(Default)]
+pub struct PrometheusMetrics {
+    inner: Mutex<Collected>,
+}
+
+#[derive(Default)]
+struct Collected {
+    grants: BTreeMap<(&'static str, &'static str), u64>,
+    errors: BTreeMap<String, u64>,
+    issuer: BTreeMap<&'static str, Histogram>,
+    expiries: BinaryHeap<Reverse<DateTime<Utc>>>,
+    swept: BTreeMap<String, u64>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// Measures the calls of an issuer and reports issued tokens.
+pub struct TimedIssuer<I, M> {
+    /// The measured issuer.
+    pub issuer: I,
+
+    /// Collects the measurements.
+    pub metrics: M,
+}
+
+impl PrometheusMetrics {
+    /// A collector without any recorded values.
+    pub fn new() -> Self {
+        PrometheusMetrics::default()
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        self.render_at(Utc::now())
+    }
+
+    fn render_at(&self, now: DateTime<Utc>) -> String {
+        let mut collected = self.lock();
+        collected.expire(now);
+
+        let mut out = String::new();
+        out.push_str("# HELP oxide_auth_grants_total Grants decided by the flows.\n");
+        out.push_str("# TYPE oxide_auth_grants_total counter\n");
+        for ((event, outcome), count) in collected.grants.iter() {
+            let _ = writeln!(
+                out,
+                "oxide_auth_grants_total{{event=\"{}\",outcome=\"{}\"}} {}",
+                event, outcome, count
+            );
+        }
+
+        out.push_str("# HELP oxide_auth_errors_total Errors sent to clients.\n");
+        out.push_str("# TYPE oxide_auth_errors_total counter\n");
+        for (error, count) in collected.errors.iter() {
+            let _ = writeln!(out, "oxide_auth_errors_total{{error=\"{}\"}} {}", error, count);
+        }
+
+        out.push_str("# HELP oxide_auth_issuer_duration_seconds Latency of issuer calls.\n");
+        out.push_str("# TYPE oxide_auth_issuer_duration_seconds histogram\n");
+        for (call, histogram) in collected.issuer.iter() {
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "oxide_auth_issuer_duration_seconds_bucket{{call=\"{}\",le=\"{}\"}} {}",
+                    call, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "oxide_auth_issuer_duration_seconds_bucket{{call=\"{}\",le=\"+Inf\"}} {}",
+                call, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "oxide_auth_issuer_duration_seconds_sum{{call=\"{}\"}} {}",
+                call, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "oxide_auth_issuer_duration_seconds_count{{call=\"{}\"}} {}",
+                call, histogram.count
+            );
+        }
+
+        out.push_str("# HELP oxide_auth_active_tokens Issued access tokens that have not expired.\n");
+        out.push_str("# TYPE oxide_auth_active_tokens gauge\n");
+        let _ = writeln!(out, "oxide_auth_active_tokens {}", collected.expiries.len());
+
+        out.push_str("# HELP oxide_auth_swept_total Expired entries removed from stores.\n");
+        out.push_str("# TYPE oxide_auth_swept_total counter\n");
+        for (store, count) in collected.swept.iter() {
+            let _ = writeln!(out, "oxide_auth_swept_total{{store=\"{}\"}} {}", store, count);
+        }
+        out
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Collected> {
+        // The counters stay consistent even if another thread panicked while holding the lock.
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Collected {
+    fn expire(&mut self, now: DateTime<Utc>) {
+        while let Some(Reverse(until)) = self.expiries.peek() {
+            if *until > now {
+                break;
+            }
+            self.expiries.pop();
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn grant(&self, record: &GrantRecord) {
+        let event = match record.event {
+            GrantEvent::Code => "code",
+            GrantEvent::Token => "token",
+            GrantEvent::Refresh => "refresh",
+            GrantEvent::Revocation => "revocation",
+        };
+        let outcome = match record.outcome {
+            GrantOutcome::Issued => "issued",
+            GrantOutcome::Denied => "denied",
+            GrantOutcome::Failed => "failed",
+        };
+        *self.lock().grants.entry((event, outcome)).or_default() += 1;
+    }
+
+    fn error(&self, code: &str) {
+        *self.lock().errors.entry(code.to_owned()).or_default() += 1;
+    }
+
+    fn issuer_call(&self, call: IssuerCall, elapsed: Duration) {
+        let call = match call {
+            IssuerCall::Issue => "issue",
+            IssuerCall::Refresh => "refresh",
+            IssuerCall::RecoverToken => "recover_token",
+            IssuerCall::RecoverRefresh => "recover_refresh",
+        };
+        self.lock()
+            .issuer
+            .entry(call)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    fn token_issued(&self, until: DateTime<Utc>) {
+        let mut collected = self.lock();
+        collected.expire(Utc::now());
+        collected.expiries.push(Reverse(until));
+    }
+
+    fn swept(&self, store: &str, removed: usize) {
+        *self.lock().swept.entry(store.to_owned()).or_default() += removed as u64;
+    }
+}
+
+impl<I, M> TimedIssuer<I, M> {
+    /// Measure the calls of an issuer.
+    pub fn new(issuer: I, metrics: M) -> Self {
+        TimedIssuer { issuer, metrics }
+    }
+}
+
+impl<I: Issuer, M: Metrics> TimedIssuer<I, M> {
+    fn timed<T>(&self, call: IssuerCall, start: DateTime<Utc>, result: T) -> T {
+        // Measured with the wall clock, as `Instant` is not available on all targets.
+        let elapsed = (Utc::now() - start).to_std().unwrap_or_default();
+        self.metrics.issuer_call(call, elapsed);
+        result
+    }
+}
+
+impl<I: Issuer, M: Metrics> Issuer for TimedIssuer<I, M> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        let start = Utc::now();
+        let issued = self.issuer.issue(grant);
+        let issued = self.timed(IssuerCall::Issue, start, issued);
+        if let Ok(token) = &issued {
+            self.metrics.token_issued(token.until);
+        }
+        issued
+    }
+
+    fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        let start = Utc::now();
+        let refreshed = self.issuer.refresh(refresh, grant);
+        let refreshed = self.timed(IssuerCall::Refresh, start, refreshed);
+        if let Ok(token) = &refreshed {
+            self.metrics.token_issued(token.until);
+        }
+        refreshed
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        let start = Utc::now();
+        let grant = self.issuer.recover_token(token);
+        self.timed(IssuerCall::RecoverToken, start, grant)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        let start = Utc::now();
+        let grant = self.issuer.recover_refresh(token);
+        self.timed(IssuerCall::RecoverRefresh, start, grant)
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.issuer.revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.issuer.revoke_grant(owner_id, client_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration as TimeDelta;
+
+    #[test]
+    fn render_collected() {
+        let metrics = PrometheusMetrics::new();
+        metrics.grant(&GrantRecord::new(GrantEvent::Token, GrantOutcome::Issued));
+        metrics.grant(&GrantRecord::new(GrantEvent::Token, GrantOutcome::Issued));
+        metrics.grant(&GrantRecord::new(GrantEvent::Code, GrantOutcome::Denied));
+        metrics.error("invalid_grant");
+        metrics.issuer_call(IssuerCall::Issue, Duration::from_millis(20));
+        metrics.issuer_call(IssuerCall::Issue, Duration::from_secs(20));
+
+        let now = Utc::now();
+        metrics.token_issued(now + TimeDelta::minutes(10));
+        metrics.token_issued(now + TimeDelta::minutes(30));
+        metrics.swept("tokens", 3);
+        metrics.swept("tokens", 2);
+
+        let text = metrics.render_at(now);
+        assert!(text.contains("oxide_auth_grants_total{event=\"token\",outcome=\"issued\"} 2\n"));
+        assert!(text.contains("oxide_auth_grants_total{event=\"code\",outcome=\"denied\"} 1\n"));
+        assert!(text.contains("oxide_auth_errors_total{error=\"invalid_grant\"} 1\n"));
+        assert!(
+            text.contains("oxide_auth_issuer_duration_seconds_bucket{call=\"issue\",le=\"0.01\"} 0\n")
+        );
+        assert!(
+            text.contains("oxide_auth_issuer_duration_seconds_bucket{call=\"issue\",le=\"0.025\"} 1\n")
+        );
+        assert!(text.contains("oxide_auth_issuer_duration_seconds_bucket{call=\"issue\",le=\"10\"} 1\n"));
+        assert!(
+            text.contains("oxide_auth_issuer_duration_seconds_bucket{call=\"issue\",le=\"+Inf\"} 2\n")
+        );
+        assert!(text.contains("oxide_auth_issuer_duration_seconds_count{call=\"issue\"} 2\n"));
+        assert!(text.contains("oxide_auth_active_tokens 2\n"));
+        assert!(text.contains("oxide_auth_swept_total{store=\"tokens\"} 5\n"));
+
+        let text = metrics.render_at(now + TimeDelta::minutes(20));
+        assert!(text.contains("oxide_auth_active_tokens 1\n"));
+    }
+
+    #[test]
+    fn timed_issuer() {
+        use crate::primitives::issuer::{tests::simple_test_suite, TokenMap};
+        use crate::primitives::generator::RandomGenerator;
+
+        let metrics = PrometheusMetrics::new();
+        let mut issuer = TimedIssuer::new(TokenMap::new(RandomGenerator::new(16)), &metrics);
+        simple_test_suite(&mut issuer);
+
+        let text = metrics.render();
+        assert!(text.contains("oxide_auth_issuer_duration_seconds_count{call=\"issue\"} 2\n"));
+        assert!(text.contains("oxide_auth_issuer_duration_seconds_count{call=\"recover_token\"} 1\n"));
+        assert!(text.contains("oxide_auth_active_tokens 2\n"));
+    }
+}
@@ -174,12 +174,33 @@
 //! [`code_grant::endpoint::{AuthorizationFlow, GrantFlow, AccessFlow}`]: ../code_grant/endpoint/index.html
 //!
 
+pub mod audit;
+pub mod binding;
+mod certificate;
+mod cors;
+mod limits;
+pub mod idempotency;
+pub mod lockout;
+pub mod metrics;
+mod proxy;
+pub mod ratelimit;
+mod render;
 pub mod simple;
+pub mod sweep;
+pub mod templates;
+mod tenant;
 
 /// Simply a prelude useful for writing front-ends.
 pub mod dev {
+    pub use super::certificate::PeerCertificate;
+    pub use super::cors::Cors;
+    pub use super::limits::Limits;
+    pub use super::proxy::TrustedProxy;
+    pub use super::render::{ErrorInfo, RenderError, RenderedError};
+    pub use super::tenant::{Tenant, TenantResolver};
     pub use std::borrow::Cow;
     pub use url::Url;
     pub use crate::endpoint::{Endpoint, WebRequest, WebResponse};
     pub use crate::endpoint::{OAuthError, OwnerSolicitor, NormalizedParameter, QueryParameter};
+    pub use crate::endpoint::RequestContext;
 }
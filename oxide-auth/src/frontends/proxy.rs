@@ -0,0 +1,165 @@
+use url::Url;
+
+/// Which information added to requests by a reverse proxy in front of the server is trusted.
+///
+/// Nothing is trusted by default. Only enable the headers which the proxy sets itself, while
+/// removing any value sent by the client, since otherwise clients can forge them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrustedProxy {
+    /// Take the client certificate from the `X-Forwarded-Client-Cert` header.
+    pub client_certificate: bool,
+
+    /// Take the scheme and host from the `Forwarded` header, or from `X-Forwarded-Proto` and
+    /// `X-Forwarded-Host`, and the path prefix from `X-Forwarded-Prefix`.
+    pub forwarded: bool,
+}
+
+impl TrustedProxy {
+    /// Reconstruct the url under which the client reached the server.
+    ///
+    /// The `scheme` and `host` are those of the connection to the server, usually the `Host`
+    /// header. Trusted forwarding headers are looked up with `header` by their lowercase name and
+    /// override them. Values which would change more than the scheme, host or prefix are ignored.
+    pub fn request_url<'a, H>(
+        &self, scheme: &str, host: &str, path_and_query: &str, header: H,
+    ) -> Option<Url>
+    where
+        H: Fn(&str) -> Option<&'a str>,
+    {
+        let mut scheme = scheme;
+        let mut host = host;
+        let mut prefix = "";
+
+        if self.forwarded {
+            let (proto, forwarded_host) = match header("forwarded") {
+                Some(forwarded) => parse_forwarded(forwarded),
+                None => (
+                    header("x-forwarded-proto").map(first_value),
+                    header("x-forwarded-host").map(first_value),
+                ),
+            };
+
+            if let Some(proto) = proto.filter(|proto| is_scheme(proto)) {
+                scheme = proto;
+            }
+            if let Some(forwarded_host) = forwarded_host.filter(|host| is_host(host)) {
+                host = forwarded_host;
+            }
+            if let Some(forwarded_prefix) = header("x-forwarded-prefix").map(first_value) {
+                if is_prefix(forwarded_prefix) {
+                    prefix = forwarded_prefix.trim_end_matches('/');
+                }
+            }
+        }
+
+        if !is_host(host) {
+            return None;
+        }
+
+        Url::parse(&format!("{}://{}{}{}", scheme, host, prefix, path_and_query)).ok()
+    }
+}
+
+/// The `proto` and `host` of the element added by the proxy closest to the client.
+fn parse_forwarded(header: &str) -> (Option<&str>, Option<&str>) {
+    let element = first_value(header);
+    let mut proto = None;
+    let mut host = None;
+
+    for pair in element.split(';') {
+        let (key, value) = match pair.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let value = value.trim().trim_matches('"');
+        match key.trim().to_ascii_lowercase().as_str() {
+            "proto" => proto = Some(value),
+            "host" => host = Some(value),
+            _ => (),
+        }
+    }
+
+    (proto, host)
+}
+
+fn first_value(header: &str) -> &str {
+    header.split(',').next().unwrap_or("").trim()
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+}
+
+fn is_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b".-:[]".contains(&b))
+}
+
+fn is_prefix(prefix: &str) -> bool {
+    prefix.starts_with('/') && !prefix.starts_with("//") && !prefix.contains(['?', '#', '\\'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(proxy: TrustedProxy, headers: &[(&str, &'static str)]) -> String {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+        };
+        proxy
+            .request_url("http", "localhost:8020", "/authorize?state=a", header)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn forwarded_urls() {
+        let trusted = TrustedProxy {
+            forwarded: true,
+            ..TrustedProxy::default()
+        };
+
+        let forwarded = [
+            (
+                "forwarded",
+                "for=192.0.2.1;proto=https;host=\"auth.example\", for=10.0.0.1",
+            ),
+            ("x-forwarded-proto", "http"),
+        ];
+        assert_eq!(url(trusted, &forwarded), "https://auth.example/authorize?state=a");
+        assert_eq!(
+            url(TrustedProxy::default(), &forwarded),
+            "http://localhost:8020/authorize?state=a"
+        );
+
+        let x_forwarded = [
+            ("x-forwarded-proto", "https, http"),
+            ("x-forwarded-host", "auth.example:8443"),
+            ("x-forwarded-prefix", "/oauth/"),
+        ];
+        assert_eq!(
+            url(trusted, &x_forwarded),
+            "https://auth.example:8443/oauth/authorize?state=a"
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_values() {
+        let trusted = TrustedProxy {
+            forwarded: true,
+            ..TrustedProxy::default()
+        };
+        let headers = [
+            ("x-forwarded-proto", "javascript"),
+            ("x-forwarded-host", "evil.example/path@"),
+            ("x-forwarded-prefix", "//evil.example"),
+        ];
+        assert_eq!(url(trusted, &headers), "http://localhost:8020/authorize?state=a");
+    }
+}
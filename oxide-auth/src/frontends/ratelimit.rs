@@ -0,0 +1,212 @@
+//! Rate limiting of the token endpoint.
+//!
+//! Endpoints consult the [`RateLimiter`] returned by [`Endpoint::rate_limiter`], for example with
+//! the [`Limited`] wrapper. [`WindowLimiter`] counts requests per key in fixed windows of time.
+//! The key is chosen by a function, which has access to the request. [`address_key`] limits by the
+//! remote address in the `RequestContext` of `WebRequest::context`, which frontends fill from
+//! their connection, and [`client_key`] by the client claimed in the request.
+//!
+//! Clients polling with the device code grant are asked to slow down, as RFC 8628 defines. All
+//! other requests over the limit are answered with status 429 and a `Retry-After` header.
+//!
+//! [`RateLimiter`]: ../../endpoint/trait.RateLimiter.html
+//! [`Endpoint::rate_limiter`]: ../../endpoint/trait.Endpoint.html#method.rate_limiter
+//! [`Limited`]: ../simple/endpoint/struct.Limited.html
+//! [`WindowLimiter`]: struct.WindowLimiter.html
+//! [`address_key`]: fn.address_key.html
+//! [`client_key`]: fn.client_key.html
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::endpoint::{LimitedRequest, RateDecision, RateLimiter, WebRequest};
+
+/// The grant type of the device authorization grant, whose clients are asked to slow down.
+pub const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// The number of tracked keys above which expired windows are dropped, at most once per window.
+const PRUNE_AT: usize = 1024;
+
+/// Allows a fixed number of requests per key in each window of time.
+pub struct WindowLimiter<F> {
+    limit: u32,
+    window: Duration,
+    key: F,
+    windows: HashMap<String, Window>,
+    pruned: Option<DateTime<Utc>>,
+}
+
+struct Window {
+    start: DateTime<Utc>,
+    count: u32,
+}
+
+/// Limit requests by the client they claim.
+///
+/// Requests without a client are not limited.
+pub fn client_key<R: WebRequest>(_: &mut R, limited: &LimitedRequest) -> Option<String> {
+    limited.client_id.map(str::to_owned)
+}
+
+/// Limit requests by their remote address.
+///
+/// Requests without a context or address are not limited. Behind a reverse proxy the frontend must
+/// put the address of the client into the context, otherwise all clients share one limit.
+pub fn address_key<R: WebRequest>(request: &mut R, _: &LimitedRequest) -> Option<String> {
+    request.context()?.remote_addr.clone()
+}
+
+impl<F> WindowLimiter<F> {
+    /// Allow `limit` requests per key in each `window`, with the keys chosen by `key`.
+    ///
+    /// Requests for which `key` returns `None` are not limited.
+    pub fn new(limit: u32, window: Duration, key: F) -> Self {
+        WindowLimiter {
+            limit,
+            window,
+            key,
+            windows: HashMap::new(),
+            pruned: None,
+        }
+    }
+
+    fn check_at(&mut self, key: String, limited: &LimitedRequest, now: DateTime<Utc>) -> RateDecision {
+        // Keys rotated by a client would otherwise make every request scan all windows.
+        let due = match self.pruned {
+            Some(pruned) => pruned + self.window <= now,
+            None => true,
+        };
+        if self.windows.len() >= PRUNE_AT && due {
+            let window = self.window;
+            self.windows.retain(|_, open| open.start + window > now);
+            self.pruned = Some(now);
+        }
+
+        let window = self.windows.entry(key).or_insert(Window { start: now, count: 0 });
+        if window.start + self.window <= now {
+            *window = Window { start: now, count: 0 };
+        }
+
+        window.count = window.count.saturating_add(1);
+        if window.count <= self.limit {
+            return RateDecision::Allow;
+        }
+
+        if limited.kind == DEVICE_CODE_GRANT {
+            return RateDecision::SlowDown;
+        }
+
+        let remaining = window.start + self.window - now;
+        // Round up, clients retrying early are only limited again.
+        let retry_after = (remaining.num_milliseconds() + 999) / 1000;
+        RateDecision::TooManyRequests {
+            retry_after: Some(retry_after.max(0) as u64),
+        }
+    }
+}
+
+impl<R, F> RateLimiter<R> for WindowLimiter<F>
+where
+    R: WebRequest,
+    F: FnMut(&mut R, &LimitedRequest) -> Option<String>,
+{
+    fn check(&mut self, request: &mut R, limited: &LimitedRequest) -> RateDecision {
+        match (self.key)(request, limited) {
+            Some(key) => self.check_at(key, limited, Utc::now()),
+            None => RateDecision::Allow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoint::RequestContext;
+    use crate::frontends::simple::request::Request;
+
+    fn limited<'a>(kind: &'a str, client_id: &'a str) -> LimitedRequest<'a> {
+        LimitedRequest {
+            kind,
+            client_id: Some(client_id),
+        }
+    }
+
+    #[test]
+    fn fixed_windows() {
+        let mut limiter = WindowLimiter::new(2, Duration::seconds(60), client_key::<Request>);
+        let now = Utc::now();
+        let token = limited("authorization_code", "client");
+        let device = limited(DEVICE_CODE_GRANT, "client");
+
+        assert_eq!(
+            limiter.check_at("client".into(), &token, now),
+            RateDecision::Allow
+        );
+        assert_eq!(
+            limiter.check_at("client".into(), &token, now),
+            RateDecision::Allow
+        );
+        assert_eq!(
+            limiter.check_at("client".into(), &token, now + Duration::seconds(15)),
+            RateDecision::TooManyRequests {
+                retry_after: Some(45)
+            }
+        );
+        assert_eq!(
+            limiter.check_at("client".into(), &device, now),
+            RateDecision::SlowDown
+        );
+        assert_eq!(limiter.check_at("other".into(), &token, now), RateDecision::Allow);
+        assert_eq!(
+            limiter.check_at("client".into(), &token, now + Duration::seconds(60)),
+            RateDecision::Allow
+        );
+    }
+
+    #[test]
+    fn prunes_once_per_window() {
+        let mut limiter = WindowLimiter::new(1, Duration::seconds(60), client_key::<Request>);
+        let now = Utc::now();
+        let token = limited("authorization_code", "client");
+
+        for key in 0..PRUNE_AT {
+            limiter.check_at(key.to_string(), &token, now);
+        }
+        limiter.check_at("first".into(), &token, now + Duration::seconds(60));
+        assert_eq!(limiter.windows.len(), 1);
+
+        for key in 0..PRUNE_AT {
+            limiter.check_at(key.to_string(), &token, now + Duration::seconds(90));
+        }
+        limiter.check_at("early".into(), &token, now + Duration::seconds(100));
+        assert_eq!(limiter.windows.len(), PRUNE_AT + 2);
+
+        limiter.check_at("late".into(), &token, now + Duration::seconds(160));
+        assert_eq!(limiter.windows.len(), 1);
+    }
+
+    #[test]
+    fn address_keys() {
+        let mut request = Request {
+            context: Some(RequestContext {
+                remote_addr: Some("198.51.100.7".into()),
+                ..RequestContext::default()
+            }),
+            ..Request::default()
+        };
+        let token = limited("authorization_code", "client");
+        assert_eq!(address_key(&mut request, &token).as_deref(), Some("198.51.100.7"));
+        assert_eq!(address_key(&mut Request::default(), &token), None);
+    }
+
+    #[test]
+    fn unkeyed_requests() {
+        let mut limiter = WindowLimiter::new(0, Duration::seconds(60), client_key);
+        let anonymous = LimitedRequest {
+            kind: "client_credentials",
+            client_id: None,
+        };
+        let decision = limiter.check(&mut Request::default(), &anonymous);
+        assert_eq!(decision, RateDecision::Allow);
+    }
+}
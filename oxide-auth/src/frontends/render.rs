@@ -0,0 +1,145 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// An error response about to be sent, as input for rendering its body.
+///
+/// The fields are those of the standard error response of RFC 6749, if the endpoint produced one.
+/// Errors that occurred outside of a flow, such as malformed requests or failed primitives, carry
+/// only their status.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ErrorInfo {
+    /// The status code of the response, which is not changed by rendering.
+    pub status: u16,
+
+    /// The `error` code, such as `invalid_grant`.
+    pub error: Option<String>,
+
+    /// The human readable `error_description`.
+    pub description: Option<String>,
+
+    /// The `error_uri` of a page describing the error.
+    pub uri: Option<String>,
+}
+
+/// A body replacing the default body of an error response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderedError {
+    /// An html page, for example a branded page for users of the authorization endpoint.
+    Html(String),
+
+    /// Plain text.
+    Text(String),
+
+    /// Members added to the standard JSON error object.
+    ///
+    /// The standard members `error`, `error_description` and `error_uri` always reflect the
+    /// original error and can not be replaced, so clients keep understanding the response.
+    Json(Map<String, Value>),
+}
+
+/// Renders the bodies of error responses in the manner of the operator.
+///
+/// Frontends consult the renderer for every error response of the wrapped routes. Returning
+/// `None` keeps the default body. Since rendering happens while the request is handled, a renderer
+/// can find request scoped information such as the current tracing span on its own.
+pub trait RenderError: Send + Sync {
+    /// The body to send instead of the default one.
+    fn render(&self, error: &ErrorInfo) -> Option<RenderedError>;
+}
+
+impl<F> RenderError for F
+where
+    F: Fn(&ErrorInfo) -> Option<RenderedError> + Send + Sync,
+{
+    fn render(&self, error: &ErrorInfo) -> Option<RenderedError> {
+        self(error)
+    }
+}
+
+impl ErrorInfo {
+    /// An error without any information besides its status code.
+    pub fn new(status: u16) -> Self {
+        ErrorInfo {
+            status,
+            ..ErrorInfo::default()
+        }
+    }
+
+    /// Read the standard members of a JSON error body.
+    ///
+    /// Bodies that are not JSON objects yield an error with only the status.
+    pub fn from_json(status: u16, body: &[u8]) -> Self {
+        let mut object = match serde_json::from_slice(body) {
+            Ok(Value::Object(object)) => object,
+            _ => return ErrorInfo::new(status),
+        };
+
+        let mut member = |key| match object.remove(key) {
+            Some(Value::String(value)) => Some(value),
+            _ => None,
+        };
+
+        ErrorInfo {
+            status,
+            error: member("error"),
+            description: member("error_description"),
+            uri: member("error_uri"),
+        }
+    }
+}
+
+impl RenderedError {
+    /// The content type and the encoded body.
+    pub fn into_body(self, error: &ErrorInfo) -> (&'static str, String) {
+        match self {
+            RenderedError::Html(html) => ("text/html; charset=utf-8", html),
+            RenderedError::Text(text) => ("text/plain; charset=utf-8", text),
+            RenderedError::Json(mut object) => {
+                let standard = [
+                    ("error", &error.error),
+                    ("error_description", &error.description),
+                    ("error_uri", &error.uri),
+                ];
+                for (key, value) in standard.iter() {
+                    match value {
+                        Some(value) => object.insert(key.to_string(), Value::String(value.clone())),
+                        None => object.remove(*key),
+                    };
+                }
+
+                ("application/json", Value::Object(object).to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_standard_members() {
+        let body = br#"{"error":"invalid_grant","error_description":"Expired code"}"#;
+        let error = ErrorInfo::from_json(400, body);
+        assert_eq!(error.error.as_deref(), Some("invalid_grant"));
+        assert_eq!(error.description.as_deref(), Some("Expired code"));
+
+        let mut object = Map::new();
+        object.insert("trace_id".into(), "abc".into());
+        object.insert("error".into(), "server_error".into());
+        object.insert("error_uri".into(), "https://docs.example".into());
+
+        let (content_type, body) = RenderedError::Json(object).into_body(&error);
+        assert_eq!(content_type, "application/json");
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["error"], "invalid_grant");
+        assert_eq!(body["error_description"], "Expired code");
+        assert_eq!(body["trace_id"], "abc");
+        assert!(body.get("error_uri").is_none());
+    }
+
+    #[test]
+    fn other_bodies() {
+        assert_eq!(ErrorInfo::from_json(500, b"Server failure"), ErrorInfo::new(500));
+    }
+}
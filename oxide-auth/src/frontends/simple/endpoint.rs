@@ -8,17 +8,26 @@
 //! [`Endpoint`]: ../../endpoint/trait.Endpoint.html
 
 use crate::primitives::authorizer::Authorizer;
+use crate::primitives::consent::ConsentStore;
 use crate::primitives::issuer::Issuer;
+use crate::primitives::nonce::NonceStore;
 use crate::primitives::registrar::Registrar;
-use crate::primitives::scope::Scope;
+use crate::primitives::scope::{Scope, ScopeMatching};
+use crate::primitives::session::SessionStore;
 
 use crate::endpoint::{AccessTokenFlow, AuthorizationFlow, ResourceFlow, RefreshFlow, ClientCredentialsFlow};
-use crate::endpoint::{Endpoint, Extension, OAuthError, PreGrant, Template, Scopes};
-use crate::endpoint::{OwnerConsent, OwnerSolicitor, Solicitation};
+use crate::endpoint::{ClientRevocationFlow, OwnerGrantsFlow};
+use crate::endpoint::{BindingPolicy, Endpoint, Extension, OAuthError, PreGrant, Template, Scopes};
+use crate::endpoint::{OwnerConsent, OwnerSolicitor, RateLimiter, ScopePolicy, Solicitation};
+use crate::endpoint::{ErrorCustomizer, GrantPolicy, GrantRecord, IdempotencyStore, Metrics, Outbox};
+use crate::endpoint::{PushedRequests, TokenResponseCustomizer};
 use crate::endpoint::WebRequest;
 
+use std::collections::HashMap;
 use std::marker::PhantomData;
 
+use serde_json::Value as JsonValue;
+
 /// Errors either caused by the underlying web types or the library.
 #[derive(Debug)]
 pub enum Error<W: WebRequest> {
@@ -52,11 +61,58 @@ pub enum Error<W: WebRequest> {
 /// `authorization_flow`, `access_token_flow`, and `resource_flow` over the erroring preparation
 /// methods in [`AuthorizationFlow`], [`AccessTokenFlow`], and [`ResourceFlow`] respectively.
 ///
+/// An [`EndpointBuilder`] assembles a `Generic` without spelling out the `Vacant` members.
+///
 /// This should not be used when you special interacting primitives are used, that originate from
 /// outside this library. For example if you intend for your [`Scopes`] to be dynamically generated
 /// from a list of registered clients, its likely cleaner to provide your own [`Endpoint`]
 /// implementation instead.
 ///
+/// ## Sharing
+///
+/// Servers handling requests concurrently can keep the primitives in an `Arc` and construct a
+/// `Generic` from clones for each request. An `Arc<RwLock<_>>` registrar and issuer and an
+/// `Arc<Mutex<_>>` authorizer are locked only for each single operation, with client and token
+/// lookups taking the read lock. Holding a `MutexGuard` for the whole flow instead serializes
+/// all requests, including those that only read.
+///
+/// ```
+/// # extern crate oxide_auth;
+/// # use oxide_auth::frontends::simple::endpoint::Vacant;
+/// # use oxide_auth::frontends::simple::endpoint::Generic;
+/// use std::sync::{Arc, Mutex, RwLock};
+/// use oxide_auth::endpoint::{AccessTokenFlow, Endpoint, WebRequest};
+/// use oxide_auth::primitives::{
+///     authorizer::AuthMap,
+///     generator::RandomGenerator,
+///     issuer::TokenMap,
+///     registrar::ClientMap,
+/// };
+///
+/// #[derive(Clone)]
+/// struct State {
+///     registrar: Arc<RwLock<ClientMap>>,
+///     authorizer: Arc<Mutex<AuthMap<RandomGenerator>>>,
+///     issuer: Arc<RwLock<TokenMap<RandomGenerator>>>,
+/// }
+///
+/// impl State {
+///     fn access_token_flow<R: WebRequest>(&self) -> AccessTokenFlow<impl Endpoint<R>, R>
+///         where R::Response: Default,
+///     {
+///         Generic {
+///             registrar: self.registrar.clone(),
+///             authorizer: self.authorizer.clone(),
+///             issuer: self.issuer.clone(),
+///             scopes: Vacant,
+///             solicitor: Vacant,
+///             response: Vacant,
+///         }
+///         .access_token_flow()
+///     }
+/// }
+/// ```
+///
 /// ## Example
 ///
 /// Here is an example where a `Generic` is used to set up an endpoint that is filled with the
@@ -95,6 +151,7 @@ pub enum Error<W: WebRequest> {
 /// [`AccessTokenFlow`]: ../../../endpoint/struct.AccessTokenFlow.html
 /// [`ResourceFlow`]: ../../../endpoint/struct.ResourceFlow.html
 /// [`ResourceFlow`]: ../../../endpoint/trait.Scopes.html
+/// [`EndpointBuilder`]: struct.EndpointBuilder.html
 pub struct Generic<R, A, I, S = Vacant, C = Vacant, L = Vacant> {
     /// The registrar implementation, or `Vacant` if it is not necesary.
     pub registrar: R,
@@ -115,6 +172,53 @@ pub struct Generic<R, A, I, S = Vacant, C = Vacant, L = Vacant> {
     pub response: L,
 }
 
+/// Fluently assembles a [`Generic`] endpoint.
+///
+/// Starts out with all primitives [`Vacant`], so only those needed by the intended flows have to
+/// be provided. Responses are created with `Default::default` unless a [`ResponseCreator`] is
+/// configured, and errors are reported as [`Error`].
+///
+/// ## Example
+///
+/// An endpoint for the authorization and access token flows, protecting resources with the `api`
+/// scope.
+///
+/// ```
+/// # extern crate oxide_auth;
+/// use oxide_auth::endpoint::{Endpoint, OwnerConsent, Solicitation, WebRequest};
+/// use oxide_auth::frontends::simple::endpoint::{EndpointBuilder, FnSolicitor};
+/// use oxide_auth::primitives::{
+///     authorizer::AuthMap,
+///     generator::RandomGenerator,
+///     issuer::TokenMap,
+///     registrar::ClientMap,
+/// };
+///
+/// fn consent<R: WebRequest>(_: &mut R, _: Solicitation) -> OwnerConsent<R::Response> {
+///     OwnerConsent::Authorized("owner".into())
+/// }
+///
+/// fn endpoint<R: WebRequest>() -> impl Endpoint<R>
+///     where R::Response: Default,
+/// {
+///     EndpointBuilder::new()
+///         .registrar(ClientMap::new())
+///         .authorizer(AuthMap::new(RandomGenerator::new(16)))
+///         .issuer(TokenMap::new(RandomGenerator::new(16)))
+///         .solicitor(FnSolicitor(consent::<R>))
+///         .scopes(vec!["api".parse().unwrap()])
+///         .build()
+/// }
+/// ```
+///
+/// [`Generic`]: struct.Generic.html
+/// [`Vacant`]: struct.Vacant.html
+/// [`ResponseCreator`]: trait.ResponseCreator.html
+/// [`Error`]: enum.Error.html
+pub struct EndpointBuilder<R = Vacant, A = Vacant, I = Vacant, S = Vacant, C = Vacant, L = Vacant> {
+    endpoint: Generic<R, A, I, S, C, L>,
+}
+
 /// A simple wrapper around an Endpoint to change it's error type into anything `Into`-able.
 pub struct ErrorInto<E, Error>(E, PhantomData<Error>);
 
@@ -125,6 +229,233 @@ impl<E, Error> ErrorInto<E, Error> {
     }
 }
 
+/// An endpoint that records every decided grant into an outbox.
+///
+/// All other methods are delegated to the inner endpoint, whose own outbox is hidden.
+pub struct Recorded<Inner, O> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Receives the records of all flows.
+    pub outbox: O,
+}
+
+impl<Inner, O> Recorded<Inner, O> {
+    /// Record all grants decided by the inner endpoint into an outbox.
+    pub fn new(inner: Inner, outbox: O) -> Self {
+        Recorded { inner, outbox }
+    }
+}
+
+/// An endpoint that adds members to each token response of the inner endpoint.
+///
+/// All other methods are delegated to the inner endpoint, whose own customizer is hidden.
+pub struct Customized<Inner, C> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Customizes the token responses of all flows.
+    pub customizer: C,
+}
+
+impl<Inner, C> Customized<Inner, C> {
+    /// Customize the token responses of the inner endpoint.
+    pub fn new(inner: Inner, customizer: C) -> Self {
+        Customized { inner, customizer }
+    }
+}
+
+/// An endpoint that explains each error of the inner endpoint to clients.
+///
+/// All other methods are delegated to the inner endpoint, whose own error customizer is hidden.
+pub struct Explained<Inner, C> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Explains the errors of all flows.
+    pub customizer: C,
+}
+
+impl<Inner, C> Explained<Inner, C> {
+    /// Explain the errors of the inner endpoint with a customizer.
+    pub fn new(inner: Inner, customizer: C) -> Self {
+        Explained { inner, customizer }
+    }
+}
+
+/// An endpoint that remembers the consent of resource owners in a store.
+///
+/// Owners identified by [`OwnerSolicitor::owner_id`] are not asked again for a scope they have
+/// already approved for a client. All other methods are delegated to the inner endpoint, whose own
+/// consent store is hidden.
+///
+/// [`OwnerSolicitor::owner_id`]: ../../../endpoint/trait.OwnerSolicitor.html#method.owner_id
+pub struct Remembering<Inner, S> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Stores the consent of owners.
+    pub store: S,
+}
+
+impl<Inner, S> Remembering<Inner, S> {
+    /// Remember the consent given to the inner endpoint in a store.
+    pub fn new(inner: Inner, store: S) -> Self {
+        Remembering { inner, store }
+    }
+}
+
+/// An endpoint narrowing the scope of authorization requests with a policy.
+///
+/// All other methods are delegated to the inner endpoint, whose own scope policy is hidden.
+pub struct Policed<Inner, P> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Decides the scope of authorization requests.
+    pub policy: P,
+}
+
+impl<Inner, P> Policed<Inner, P> {
+    /// Decide the scope of authorization requests to the inner endpoint with a policy.
+    pub fn new(inner: Inner, policy: P) -> Self {
+        Policed { inner, policy }
+    }
+}
+
+/// An endpoint collecting metrics of its flows.
+///
+/// All other methods are delegated to the inner endpoint, whose own metrics are hidden.
+pub struct Metered<Inner, M> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Collects the metrics.
+    pub metrics: M,
+}
+
+impl<Inner, M> Metered<Inner, M> {
+    /// Collect metrics of the flows of the inner endpoint.
+    pub fn new(inner: Inner, metrics: M) -> Self {
+        Metered { inner, metrics }
+    }
+}
+
+/// An endpoint limiting the rate of requests to the token endpoint.
+///
+/// All other methods are delegated to the inner endpoint, whose own rate limiter is hidden.
+pub struct Limited<Inner, L> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Decides whether requests are processed.
+    pub limiter: L,
+}
+
+impl<Inner, L> Limited<Inner, L> {
+    /// Limit the rate of token requests to the inner endpoint.
+    pub fn new(inner: Inner, limiter: L) -> Self {
+        Limited { inner, limiter }
+    }
+}
+
+/// An endpoint deciding over grants with a policy just before they are issued.
+///
+/// All other methods are delegated to the inner endpoint, whose own grant policy is hidden.
+pub struct Governed<Inner, P> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Decides over the grants.
+    pub policy: P,
+}
+
+impl<Inner, P> Governed<Inner, P> {
+    /// Decide over the grants issued by the inner endpoint with a policy.
+    pub fn new(inner: Inner, policy: P) -> Self {
+        Governed { inner, policy }
+    }
+}
+
+/// An endpoint replaying token responses to retried requests from a store.
+///
+/// All other methods are delegated to the inner endpoint, whose own store is hidden.
+pub struct Idempotent<Inner, S> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Remembers the token responses.
+    pub store: S,
+}
+
+impl<Inner, S> Idempotent<Inner, S> {
+    /// Replay the token responses of the inner endpoint from a store.
+    pub fn new(inner: Inner, store: S) -> Self {
+        Idempotent { inner, store }
+    }
+}
+
+/// An endpoint tracking the authenticated sessions of resource owners in a store.
+///
+/// Sessions are identified by [`OwnerSolicitor::session_id`]. All other methods are delegated to
+/// the inner endpoint, whose own session store is hidden.
+///
+/// [`OwnerSolicitor::session_id`]: ../../../endpoint/trait.OwnerSolicitor.html#method.session_id
+pub struct Sessioned<Inner, S> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Stores the sessions of owners.
+    pub store: S,
+}
+
+impl<Inner, S> Sessioned<Inner, S> {
+    /// Track the sessions of owners authorizing with the inner endpoint in a store.
+    pub fn new(inner: Inner, store: S) -> Self {
+        Sessioned { inner, store }
+    }
+}
+
+/// An endpoint requiring DPoP proofs to contain the nonces of a store.
+///
+/// See [`require_dpop_nonce`]. All other methods are delegated to the inner endpoint, whose own
+/// nonce store is hidden.
+///
+/// [`require_dpop_nonce`]: ../../../endpoint/fn.require_dpop_nonce.html
+pub struct Nonced<Inner, N> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Provides the nonces.
+    pub store: N,
+}
+
+impl<Inner, N> Nonced<Inner, N> {
+    /// Require the nonces of a store in the DPoP proofs of requests to the inner endpoint.
+    pub fn new(inner: Inner, store: N) -> Self {
+        Nonced { inner, store }
+    }
+}
+
+/// An endpoint binding the tokens it issues to the context of requests with a policy.
+///
+/// The resource flow checks the binding of presented tokens with the same policy. All other
+/// methods are delegated to the inner endpoint, whose own policy is hidden.
+pub struct Bound<Inner, P> {
+    /// The wrapped endpoint.
+    pub inner: Inner,
+
+    /// Binds tokens and checks their bindings.
+    pub policy: P,
+}
+
+impl<Inner, P> Bound<Inner, P> {
+    /// Bind the tokens of the inner endpoint to the context of requests with a policy.
+    pub fn new(inner: Inner, policy: P) -> Self {
+        Bound { inner, policy }
+    }
+}
+
 /// Marker struct if some primitive is not provided.
 ///
 /// Used in place of other primitives when those are not provided. The exact semantics depend on
@@ -161,6 +492,17 @@ pub struct Vacant;
 /// A simple wrapper for functions and lambdas to be used as solicitors.
 pub struct FnSolicitor<F>(pub F);
 
+/// A simple wrapper for functions and lambdas to be used as outbox.
+pub struct FnOutbox<F>(pub F);
+
+/// A simple wrapper for functions and lambdas to be used as token response customizer.
+pub struct FnCustomizer<F>(pub F);
+
+/// Matches the wrapped scopes hierarchically, see [`ScopeMatching::Hierarchical`].
+///
+/// [`ScopeMatching::Hierarchical`]: ../../../primitives/scope/enum.ScopeMatching.html#variant.Hierarchical
+pub struct Hierarchical<S>(pub S);
+
 /// Use a predetermined grant and owner as solicitor.
 ///
 /// Convenience wrapper when the owner and her/his consent to a grant can be identified without
@@ -271,6 +613,19 @@ type ClientCredentials<'a, W> = Generic<
 type Refresh<'a> =
     Generic<&'a (dyn Registrar + 'a), Vacant, &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
 type Resource<'a> = Generic<Vacant, Vacant, &'a mut (dyn Issuer + 'a), Vacant, &'a [Scope], Vacant>;
+type ClientRevocation<'a> =
+    Generic<Vacant, &'a mut (dyn Authorizer + 'a), &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
+type OwnerGrants<'a, W> = Remembering<
+    Generic<
+        Vacant,
+        &'a mut (dyn Authorizer + 'a),
+        &'a mut (dyn Issuer + 'a),
+        &'a mut (dyn OwnerSolicitor<W> + 'a),
+        Vacant,
+        Vacant,
+    >,
+    &'a mut (dyn ConsentStore + 'a),
+>;
 
 /// Create an ad-hoc authorization flow.
 ///
@@ -423,6 +778,60 @@ where
     }
 }
 
+/// Create an ad-hoc client revocation flow.
+///
+/// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
+/// fail or panic, compared to preparing one with `ClientRevocationFlow`.
+pub fn client_revocation_flow<'a, W>(
+    authorizer: &'a mut dyn Authorizer, issuer: &'a mut dyn Issuer,
+) -> ClientRevocationFlow<ClientRevocation<'a>, W>
+where
+    W: WebRequest,
+    W::Response: Default,
+{
+    let flow = ClientRevocationFlow::prepare(Generic {
+        registrar: Vacant,
+        authorizer,
+        issuer,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    });
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
+/// Create an ad-hoc flow for owners to list and revoke their authorizations.
+///
+/// The solicitor only needs to identify the owner of a request with `owner_id`. Since all
+/// necessary primitives are expected in the function syntax, this is guaranteed to never fail or
+/// panic, compared to preparing one with `OwnerGrantsFlow`.
+pub fn owner_grants_flow<'a, W>(
+    authorizer: &'a mut dyn Authorizer, issuer: &'a mut dyn Issuer,
+    solicitor: &'a mut dyn OwnerSolicitor<W>, consents: &'a mut dyn ConsentStore,
+) -> OwnerGrantsFlow<OwnerGrants<'a, W>, W>
+where
+    W: WebRequest,
+    W::Response: Default,
+{
+    let endpoint = Generic {
+        registrar: Vacant,
+        authorizer,
+        issuer,
+        solicitor,
+        scopes: Vacant,
+        response: Vacant,
+    };
+
+    match OwnerGrantsFlow::prepare(Remembering::new(endpoint, consents)) {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
 impl<R, A, I, O, C, L> Generic<R, A, I, O, C, L> {
     /// Change the used solicitor.
     pub fn with_solicitor<N>(self, new_solicitor: N) -> Generic<R, A, I, N, C, L> {
@@ -521,6 +930,144 @@ impl<R, A, I, O, C, L> Generic<R, A, I, O, C, L> {
     }
 }
 
+impl EndpointBuilder {
+    /// Start with an endpoint where all primitives are `Vacant`.
+    pub fn new() -> Self {
+        EndpointBuilder {
+            endpoint: Generic {
+                registrar: Vacant,
+                authorizer: Vacant,
+                issuer: Vacant,
+                solicitor: Vacant,
+                scopes: Vacant,
+                response: Vacant,
+            },
+        }
+    }
+}
+
+impl Default for EndpointBuilder {
+    fn default() -> Self {
+        EndpointBuilder::new()
+    }
+}
+
+impl<R, A, I, S, C, L> EndpointBuilder<R, A, I, S, C, L> {
+    /// Use a registrar, needed by all flows except the resource flow.
+    pub fn registrar<N: Registrar>(self, registrar: N) -> EndpointBuilder<N, A, I, S, C, L> {
+        let Generic {
+            authorizer,
+            issuer,
+            solicitor,
+            scopes,
+            response,
+            ..
+        } = self.endpoint;
+        EndpointBuilder {
+            endpoint: Generic {
+                registrar,
+                authorizer,
+                issuer,
+                solicitor,
+                scopes,
+                response,
+            },
+        }
+    }
+
+    /// Use an authorizer, needed by the authorization and access token flows.
+    pub fn authorizer<N: Authorizer>(self, authorizer: N) -> EndpointBuilder<R, N, I, S, C, L> {
+        let Generic {
+            registrar,
+            issuer,
+            solicitor,
+            scopes,
+            response,
+            ..
+        } = self.endpoint;
+        EndpointBuilder {
+            endpoint: Generic {
+                registrar,
+                authorizer,
+                issuer,
+                solicitor,
+                scopes,
+                response,
+            },
+        }
+    }
+
+    /// Use an issuer, needed by all flows except the authorization flow.
+    pub fn issuer<N: Issuer>(self, issuer: N) -> EndpointBuilder<R, A, N, S, C, L> {
+        let Generic {
+            registrar,
+            authorizer,
+            solicitor,
+            scopes,
+            response,
+            ..
+        } = self.endpoint;
+        EndpointBuilder {
+            endpoint: Generic {
+                registrar,
+                authorizer,
+                issuer,
+                solicitor,
+                scopes,
+                response,
+            },
+        }
+    }
+
+    /// Ask the resource owner for consent with a solicitor.
+    ///
+    /// Without one, all authorization and client credentials requests are denied.
+    pub fn solicitor<N>(self, solicitor: N) -> EndpointBuilder<R, A, I, N, C, L> {
+        EndpointBuilder {
+            endpoint: self.endpoint.with_solicitor(solicitor),
+        }
+    }
+
+    /// Require one of these scopes for resource access.
+    ///
+    /// Without scopes, all resource requests are denied.
+    pub fn scopes<N>(self, scopes: N) -> EndpointBuilder<R, A, I, S, N, L> {
+        EndpointBuilder {
+            endpoint: self.endpoint.with_scopes(scopes),
+        }
+    }
+
+    /// Create responses with a `ResponseCreator` instead of `Default::default`.
+    pub fn response<N>(self, response: N) -> EndpointBuilder<R, A, I, S, C, N> {
+        let Generic {
+            registrar,
+            authorizer,
+            issuer,
+            solicitor,
+            scopes,
+            ..
+        } = self.endpoint;
+        EndpointBuilder {
+            endpoint: Generic {
+                registrar,
+                authorizer,
+                issuer,
+                solicitor,
+                scopes,
+                response,
+            },
+        }
+    }
+
+    /// The assembled endpoint.
+    ///
+    /// Its flow constructors such as `Generic::authorization_flow` statically check that the
+    /// primitives of the flow have been provided.
+    pub fn build(self) -> Generic<R, A, I, S, C, L> {
+        self.endpoint
+    }
+}
+
 impl<W: WebRequest> Error<W> {
     /// Convert into a single error type.
     ///
@@ -584,86 +1131,1354 @@ where
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         self.0.extension()
     }
-}
 
-impl<W, R, A, I, O, C, L> Endpoint<W> for Generic<R, A, I, O, C, L>
-where
-    W: WebRequest,
-    R: OptRegistrar,
-    A: OptAuthorizer,
-    I: OptIssuer,
-    O: OwnerSolicitor<W>,
-    C: Scopes<W>,
-    L: ResponseCreator<W>,
-{
-    type Error = Error<W>;
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.0.outbox()
+    }
 
-    fn registrar(&self) -> Option<&dyn Registrar> {
-        self.registrar.opt_ref()
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.0.token_customizer()
     }
 
-    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
-        self.authorizer.opt_mut()
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.0.error_customizer()
     }
 
-    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
-        self.issuer.opt_mut()
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.0.consent_store()
     }
 
-    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
-        Some(&mut self.solicitor)
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.0.scope_policy()
     }
 
-    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
-        Some(&mut self.scopes)
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.0.metrics()
     }
 
-    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
-        Ok(self.response.create(request, kind))
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.0.rate_limiter()
     }
 
-    fn error(&mut self, err: OAuthError) -> Error<W> {
-        Error::OAuth(err)
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.0.grant_policy()
     }
 
-    fn web_error(&mut self, err: W::Error) -> Error<W> {
-        Error::Web(err)
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.0.idempotency_store()
     }
-}
 
-impl<T: Registrar> OptRegistrar for T {
-    fn opt_ref(&self) -> Option<&dyn Registrar> {
-        Some(self)
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.0.session_store()
     }
-}
 
-impl<T: Authorizer> OptAuthorizer for T {
-    fn opt_mut(&mut self) -> Option<&mut dyn Authorizer> {
-        Some(self)
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.0.nonce_store()
     }
-}
 
-impl<T: Issuer> OptIssuer for T {
-    fn opt_mut(&mut self) -> Option<&mut dyn Issuer> {
-        Some(self)
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.0.binding_policy()
     }
-}
 
-impl OptRegistrar for Vacant {
-    fn opt_ref(&self) -> Option<&dyn Registrar> {
-        Option::None
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.0.pushed_requests()
     }
-}
 
-impl OptAuthorizer for Vacant {
-    fn opt_mut(&mut self) -> Option<&mut dyn Authorizer> {
-        Option::None
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.0.issuer_identifier()
     }
 }
 
-impl OptIssuer for Vacant {
-    fn opt_mut(&mut self) -> Option<&mut dyn Issuer> {
-        Option::None
+impl<W, Inner, O> Endpoint<W> for Recorded<Inner, O>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    O: Outbox<W>,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        Some(&mut self.outbox)
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, C> Endpoint<W> for Customized<Inner, C>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    C: TokenResponseCustomizer<W>,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        Some(&mut self.customizer)
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, C> Endpoint<W> for Explained<Inner, C>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    C: ErrorCustomizer<W>,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        Some(&mut self.customizer)
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, S> Endpoint<W> for Remembering<Inner, S>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    S: ConsentStore,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        Some(&mut self.store)
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, P> Endpoint<W> for Policed<Inner, P>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    P: ScopePolicy<W>,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        Some(&mut self.policy)
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, M> Endpoint<W> for Metered<Inner, M>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    M: Metrics,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        Some(&self.metrics)
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, L> Endpoint<W> for Limited<Inner, L>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    L: RateLimiter<W>,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        Some(&mut self.limiter)
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, P> Endpoint<W> for Governed<Inner, P>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    P: GrantPolicy,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        Some(&mut self.policy)
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, S> Endpoint<W> for Idempotent<Inner, S>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    S: IdempotencyStore,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        Some(&mut self.store)
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, S> Endpoint<W> for Sessioned<Inner, S>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    S: SessionStore,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        Some(&mut self.store)
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, N> Endpoint<W> for Nonced<Inner, N>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    N: NonceStore,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        Some(&mut self.store)
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, Inner, P> Endpoint<W> for Bound<Inner, P>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+    P: BindingPolicy,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.inner.extension()
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        Some(&mut self.policy)
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
+}
+
+impl<W, R, A, I, O, C, L> Endpoint<W> for Generic<R, A, I, O, C, L>
+where
+    W: WebRequest,
+    R: OptRegistrar,
+    A: OptAuthorizer,
+    I: OptIssuer,
+    O: OwnerSolicitor<W>,
+    C: Scopes<W>,
+    L: ResponseCreator<W>,
+{
+    type Error = Error<W>;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.registrar.opt_ref()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.authorizer.opt_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.issuer.opt_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        Some(&mut self.solicitor)
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        Some(&mut self.scopes)
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        Ok(self.response.create(request, kind))
+    }
+
+    fn error(&mut self, err: OAuthError) -> Error<W> {
+        Error::OAuth(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Error<W> {
+        Error::Web(err)
+    }
+}
+
+impl<T: Registrar> OptRegistrar for T {
+    fn opt_ref(&self) -> Option<&dyn Registrar> {
+        Some(self)
+    }
+}
+
+impl<T: Authorizer> OptAuthorizer for T {
+    fn opt_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        Some(self)
+    }
+}
+
+impl<T: Issuer> OptIssuer for T {
+    fn opt_mut(&mut self) -> Option<&mut dyn Issuer> {
+        Some(self)
+    }
+}
+
+impl OptRegistrar for Vacant {
+    fn opt_ref(&self) -> Option<&dyn Registrar> {
+        Option::None
+    }
+}
+
+impl OptAuthorizer for Vacant {
+    fn opt_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        Option::None
+    }
+}
+
+impl OptIssuer for Vacant {
+    fn opt_mut(&mut self) -> Option<&mut dyn Issuer> {
+        Option::None
     }
 }
 
@@ -680,6 +2495,16 @@ impl<W: WebRequest> Scopes<W> for Vacant {
     }
 }
 
+impl<W: WebRequest, S: Scopes<W>> Scopes<W> for Hierarchical<S> {
+    fn scopes(&mut self, request: &mut W) -> &[Scope] {
+        self.0.scopes(request)
+    }
+
+    fn matching(&mut self) -> ScopeMatching {
+        ScopeMatching::Hierarchical
+    }
+}
+
 impl<W, F> OwnerSolicitor<W> for FnSolicitor<F>
 where
     W: WebRequest,
@@ -692,6 +2517,28 @@ where
     }
 }
 
+impl<W, F> Outbox<W> for FnOutbox<F>
+where
+    W: WebRequest,
+    F: FnMut(&mut W, GrantRecord),
+{
+    fn record(&mut self, request: &mut W, record: GrantRecord) {
+        (self.0)(request, record)
+    }
+}
+
+impl<W, F> TokenResponseCustomizer<W> for FnCustomizer<F>
+where
+    W: WebRequest,
+    F: FnMut(&mut W, &GrantRecord, &mut HashMap<String, JsonValue>),
+{
+    fn customize(
+        &mut self, request: &mut W, grant: &GrantRecord, additional: &mut HashMap<String, JsonValue>,
+    ) {
+        (self.0)(request, grant, additional)
+    }
+}
+
 impl<W: WebRequest> OwnerSolicitor<W> for ApprovedGrant {
     /// Approve if the grant matches *exactly*.
     ///
@@ -1,7 +1,14 @@
-use crate::endpoint::{Endpoint, Extension, OAuthError, OwnerSolicitor, Scopes, Template, WebRequest};
+use crate::endpoint::{
+    BindingPolicy, Endpoint, ErrorCustomizer, Extension, GrantPolicy, IdempotencyStore, Metrics,
+    OAuthError, Outbox, OwnerSolicitor, PushedRequests, RateLimiter, ScopePolicy, Scopes, Template,
+    TokenResponseCustomizer, WebRequest,
+};
 use crate::primitives::authorizer::Authorizer;
+use crate::primitives::consent::ConsentStore;
 use crate::primitives::issuer::Issuer;
+use crate::primitives::nonce::NonceStore;
 use crate::primitives::registrar::Registrar;
+use crate::primitives::session::SessionStore;
 
 use super::AddonList;
 
@@ -95,4 +102,60 @@ where
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         Some(&mut self.addons)
     }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<Request>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<Request>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<Request>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<Request>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<Request>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        self.inner.binding_policy()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        self.inner.pushed_requests()
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        self.inner.issuer_identifier()
+    }
 }
@@ -1,6 +1,57 @@
 //! Basic extension systems.
 //!
 //! Note that extensions will probably return in `v0.4` but not its preview versions.
+//!
+//! ## Passing data from the authorization to the token request
+//!
+//! An addon registered for the whole code grant with [`AddonList::push_code`] receives the data it
+//! returned for the authorization request again when the code is exchanged for a token. Keep such
+//! data private, it stays with the authorization code on the server. Public data returned when
+//! the token is issued is instead published to the client, as a member of the token response
+//! named by the identifier of the addon.
+//!
+//! This answers the `nonce` of an OpenID Connect authentication request with an `id_token`:
+//!
+//! ```
+//! use oxide_auth::frontends::simple::extensions::{
+//!     AccessTokenAddon, AccessTokenRequest, AddonList, AddonResult, AuthorizationAddon,
+//!     AuthorizationRequest,
+//! };
+//! use oxide_auth::primitives::grant::{GrantExtension, Value};
+//!
+//! struct IdToken;
+//!
+//! impl GrantExtension for IdToken {
+//!     fn identifier(&self) -> &'static str {
+//!         "id_token"
+//!     }
+//! }
+//!
+//! impl AuthorizationAddon for IdToken {
+//!     fn execute(&self, request: &dyn AuthorizationRequest) -> AddonResult {
+//!         match request.extension("nonce") {
+//!             Some(nonce) => AddonResult::Data(Value::private(Some(nonce.into_owned()))),
+//!             None => AddonResult::Ok,
+//!         }
+//!     }
+//! }
+//!
+//! impl AccessTokenAddon for IdToken {
+//!     fn execute(&self, _: &dyn AccessTokenRequest, code_data: Option<Value>) -> AddonResult {
+//!         let nonce = match code_data.map(Value::into_private_value) {
+//!             Some(Ok(Some(nonce))) => nonce,
+//!             _ => return AddonResult::Ok,
+//!         };
+//!
+//!         // Sign the claims, including the nonce, into a JWT here.
+//!         let id_token = format!("eyJhbGciOiJSUzI1NiJ9.{}.signature", nonce);
+//!         AddonResult::Data(Value::public(Some(id_token)))
+//!     }
+//! }
+//!
+//! let mut addons = AddonList::new();
+//! addons.push_code(IdToken);
+//! ```
 pub use crate::code_grant::authorization::Request as AuthorizationRequest;
 pub use crate::code_grant::accesstoken::Request as AccessTokenRequest;
 pub use crate::code_grant::client_credentials::Request as ClientCredentialsRequest;
@@ -11,4 +11,10 @@ pub mod endpoint;
 
 pub mod extensions;
 
+pub mod openapi;
+
+pub mod profile;
+
 pub mod request;
+
+pub mod router;
@@ -0,0 +1,525 @@
+//! Describes the mounted endpoints of an authorization server as an OpenAPI document.
+//!
+//! The grant types served at the token endpoint and the response types of the authorization
+//! endpoint are read from a [`FlowRouter`]. The router does not know where the endpoints are
+//! mounted, so the paths of all endpoints are given to the [`OpenApi`] builder. Endpoints without a
+//! path are left out of the document.
+//!
+//! ```
+//! # extern crate oxide_auth;
+//! # use oxide_auth::frontends::simple::endpoint::Vacant;
+//! # use oxide_auth::frontends::simple::request::{Request, Response};
+//! use oxide_auth::frontends::simple::openapi::OpenApi;
+//! use oxide_auth::frontends::simple::router::FlowRouter;
+//! # type Endpoint = oxide_auth::frontends::simple::endpoint::Generic<
+//! #     oxide_auth::primitives::registrar::ClientMap,
+//! #     Vacant, Vacant, Vacant>;
+//!
+//! let router = FlowRouter::<Endpoint, Request>::new();
+//! let document = OpenApi::for_router(&router, "https://auth.example".parse().unwrap())
+//!     .authorization("/authorize")
+//!     .token("/token")
+//!     .revocation("/revoke")
+//!     .document();
+//!
+//! assert_eq!(document["openapi"], "3.1.0");
+//! assert!(document["paths"]["/token"]["post"].is_object());
+//! ```
+//!
+//! [`FlowRouter`]: ../router/struct.FlowRouter.html
+//! [`OpenApi`]: struct.OpenApi.html
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+use url::Url;
+
+use super::router::FlowRouter;
+use crate::endpoint::{Endpoint, WebRequest};
+use crate::primitives::scope::ScopeRegistry;
+
+/// Builds the OpenAPI 3.1 document of an authorization server.
+///
+/// Describes the authorization endpoint, the token endpoint, token introspection (RFC 7662),
+/// token revocation (RFC 7009) and dynamic client registration (RFC 7591), each once it has been
+/// given a path. The security schemes cover client authentication with HTTP Basic, bearer tokens
+/// for the registration and introspection endpoints, and the OAuth 2.0 flows with their scopes.
+#[derive(Clone, Debug)]
+pub struct OpenApi {
+    server: Url,
+    title: String,
+    version: String,
+    grant_types: Vec<String>,
+    response_types: Vec<String>,
+    scopes: BTreeMap<String, String>,
+    authorization: Option<String>,
+    token: Option<String>,
+    introspection: Option<String>,
+    revocation: Option<String>,
+    registration: Option<String>,
+}
+
+impl OpenApi {
+    /// Describe a server with the grant types implemented by this library.
+    pub fn new(server: Url) -> Self {
+        OpenApi {
+            server,
+            title: "OAuth 2.0 Authorization Server".to_owned(),
+            version: "1.0.0".to_owned(),
+            grant_types: vec![
+                "authorization_code".to_owned(),
+                "client_credentials".to_owned(),
+                "refresh_token".to_owned(),
+            ],
+            response_types: vec!["code".to_owned()],
+            scopes: BTreeMap::new(),
+            authorization: None,
+            token: None,
+            introspection: None,
+            revocation: None,
+            registration: None,
+        }
+    }
+
+    /// Describe a server with the grant and response types of a router.
+    pub fn for_router<E, W>(router: &FlowRouter<E, W>, server: Url) -> Self
+    where
+        E: Endpoint<W>,
+        W: WebRequest,
+    {
+        let mut openapi = OpenApi::new(server);
+        openapi.grant_types = router.grant_types().into_iter().map(str::to_owned).collect();
+        let registered = router.response_types().into_iter().map(str::to_owned);
+        openapi
+            .response_types
+            .extend(registered.filter(|response| response != "code"));
+        openapi
+    }
+
+    /// Set the title and version of the described API.
+    pub fn info(mut self, title: &str, version: &str) -> Self {
+        self.title = title.to_owned();
+        self.version = version.to_owned();
+        self
+    }
+
+    /// Document a scope token of the OAuth 2.0 flows.
+    pub fn scope(mut self, token: &str, description: &str) -> Self {
+        self.scopes.insert(token.to_owned(), description.to_owned());
+        self
+    }
+
+    /// Document all known scope tokens of a registry, described by their description or name.
+    pub fn scopes(mut self, registry: &ScopeRegistry) -> Self {
+        for (token, info) in registry.iter() {
+            let description = info.description.as_ref().unwrap_or(&info.name);
+            self.scopes.insert(token.to_owned(), description.clone());
+        }
+        self
+    }
+
+    /// Mount the authorization endpoint at the path.
+    pub fn authorization(mut self, path: &str) -> Self {
+        self.authorization = Some(path.to_owned());
+        self
+    }
+
+    /// Mount the token endpoint at the path.
+    pub fn token(mut self, path: &str) -> Self {
+        self.token = Some(path.to_owned());
+        self
+    }
+
+    /// Mount the token introspection endpoint at the path.
+    pub fn introspection(mut self, path: &str) -> Self {
+        self.introspection = Some(path.to_owned());
+        self
+    }
+
+    /// Mount the token revocation endpoint at the path.
+    pub fn revocation(mut self, path: &str) -> Self {
+        self.revocation = Some(path.to_owned());
+        self
+    }
+
+    /// Mount the dynamic client registration endpoint at the path.
+    pub fn registration(mut self, path: &str) -> Self {
+        self.registration = Some(path.to_owned());
+        self
+    }
+
+    /// The OpenAPI document.
+    pub fn document(&self) -> Value {
+        let mut paths = Map::new();
+        if let Some(path) = &self.authorization {
+            paths.insert(path.clone(), self.authorization_path());
+        }
+        if let Some(path) = &self.token {
+            paths.insert(path.clone(), self.token_path());
+        }
+        if let Some(path) = &self.introspection {
+            paths.insert(path.clone(), introspection_path());
+        }
+        if let Some(path) = &self.revocation {
+            paths.insert(path.clone(), revocation_path());
+        }
+        if let Some(path) = &self.registration {
+            paths.insert(path.clone(), registration_path());
+        }
+
+        json!({
+            "openapi": "3.1.0",
+            "info": {
+                "title": self.title,
+                "version": self.version,
+            },
+            "servers": [{ "url": self.server.as_str() }],
+            "paths": paths,
+            "components": {
+                "securitySchemes": self.security_schemes(),
+                "schemas": schemas(),
+            },
+        })
+    }
+
+    /// The document serialized as JSON.
+    pub fn to_json(&self) -> String {
+        self.document().to_string()
+    }
+
+    fn supports(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|supported| supported == grant_type)
+    }
+
+    fn url(&self, path: &str) -> String {
+        self.server
+            .join(path)
+            .map(String::from)
+            .unwrap_or_else(|_| path.to_owned())
+    }
+
+    fn authorization_path(&self) -> Value {
+        json!({
+            "get": {
+                "summary": "Authorization request",
+                "description": "Asks the resource owner to authorize the client (RFC 6749, 4.1.1).",
+                "parameters": [
+                    query("response_type", true, json!({ "type": "string", "enum": self.response_types })),
+                    query("client_id", true, string()),
+                    query("redirect_uri", false, json!({ "type": "string", "format": "uri" })),
+                    query("scope", false, string()),
+                    query("state", false, string()),
+                    query("code_challenge", false, string()),
+                    query("code_challenge_method", false, json!({ "type": "string", "enum": ["plain", "S256"] })),
+                ],
+                "responses": {
+                    "302": { "description": "Redirect to the client with a code or an error." },
+                    "400": { "description": "The client or redirect uri is invalid." },
+                },
+            },
+        })
+    }
+
+    fn token_path(&self) -> Value {
+        json!({
+            "post": {
+                "summary": "Token request",
+                "description": "Issues tokens for the grant of the request (RFC 6749, 3.2).",
+                "security": client_security(),
+                "requestBody": form("TokenRequest", json!({
+                    "type": "object",
+                    "required": ["grant_type"],
+                    "properties": {
+                        "grant_type": { "type": "string", "enum": self.grant_types },
+                        "code": string(),
+                        "redirect_uri": { "type": "string", "format": "uri" },
+                        "code_verifier": string(),
+                        "refresh_token": string(),
+                        "scope": string(),
+                        "client_id": string(),
+                        "client_secret": string(),
+                    },
+                })),
+                "responses": {
+                    "200": json_response("The issued token.", "TokenResponse"),
+                    "400": json_response("The request or grant is invalid.", "ErrorResponse"),
+                    "401": json_response("The client failed to authenticate.", "ErrorResponse"),
+                },
+            },
+        })
+    }
+
+    fn security_schemes(&self) -> Value {
+        let mut flows = Map::new();
+        let authorization = self.authorization.as_ref().map(|path| self.url(path));
+        let token = self.token.as_ref().map(|path| self.url(path));
+
+        if let (Some(authorization), Some(token)) = (&authorization, &token) {
+            if self.supports("authorization_code") {
+                let mut flow = json!({
+                    "authorizationUrl": authorization,
+                    "tokenUrl": token,
+                    "scopes": self.scopes,
+                });
+                if self.supports("refresh_token") {
+                    flow["refreshUrl"] = json!(token);
+                }
+                flows.insert("authorizationCode".to_owned(), flow);
+            }
+        }
+        if let Some(token) = &token {
+            if self.supports("client_credentials") {
+                let flow = json!({ "tokenUrl": token, "scopes": self.scopes });
+                flows.insert("clientCredentials".to_owned(), flow);
+            }
+        }
+
+        let mut schemes = json!({
+            "clientSecretBasic": {
+                "type": "http",
+                "scheme": "basic",
+                "description": "The client id and secret of a confidential client.",
+            },
+            "bearerToken": {
+                "type": "http",
+                "scheme": "bearer",
+                "description": "An access token or initial access token.",
+            },
+        });
+        if !flows.is_empty() {
+            schemes["oauth2"] = json!({ "type": "oauth2", "flows": flows });
+        }
+        schemes
+    }
+}
+
+fn introspection_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Token introspection",
+            "description": "Describes the state and grant of a token (RFC 7662).",
+            "security": [{ "clientSecretBasic": [] }, { "bearerToken": [] }],
+            "requestBody": form("IntrospectionRequest", token_request()),
+            "responses": {
+                "200": json_response("The state of the token.", "IntrospectionResponse"),
+                "401": json_response("The caller failed to authenticate.", "ErrorResponse"),
+            },
+        },
+    })
+}
+
+fn revocation_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Token revocation",
+            "description": "Revokes an access or refresh token (RFC 7009).",
+            "security": client_security(),
+            "requestBody": form("RevocationRequest", token_request()),
+            "responses": {
+                "200": { "description": "The token is revoked or was not valid." },
+                "400": json_response("The request is invalid.", "ErrorResponse"),
+                "401": json_response("The client failed to authenticate.", "ErrorResponse"),
+            },
+        },
+    })
+}
+
+fn registration_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Client registration",
+            "description": "Registers a new client (RFC 7591).",
+            "security": [{ "bearerToken": [] }, {}],
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": { "schema": reference("ClientMetadata") },
+                },
+            },
+            "responses": {
+                "201": json_response("The registered client.", "ClientInformation"),
+                "400": json_response("The metadata is invalid.", "ErrorResponse"),
+            },
+        },
+    })
+}
+
+/// The schemas shared by the endpoints.
+fn schemas() -> Value {
+    json!({
+        "TokenResponse": {
+            "type": "object",
+            "required": ["access_token", "token_type"],
+            "properties": {
+                "access_token": string(),
+                "token_type": string(),
+                "expires_in": { "type": "integer" },
+                "refresh_token": string(),
+                "scope": string(),
+            },
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "required": ["error"],
+            "properties": {
+                "error": string(),
+                "error_description": string(),
+                "error_uri": { "type": "string", "format": "uri" },
+            },
+        },
+        "IntrospectionResponse": {
+            "type": "object",
+            "required": ["active"],
+            "properties": {
+                "active": { "type": "boolean" },
+                "scope": string(),
+                "client_id": string(),
+                "username": string(),
+                "token_type": string(),
+                "exp": { "type": "integer" },
+                "iat": { "type": "integer" },
+                "sub": string(),
+                "aud": string(),
+                "iss": string(),
+            },
+        },
+        "ClientMetadata": {
+            "type": "object",
+            "properties": {
+                "redirect_uris": { "type": "array", "items": { "type": "string", "format": "uri" } },
+                "token_endpoint_auth_method": {
+                    "type": "string",
+                    "enum": ["client_secret_basic", "client_secret_post", "none"],
+                },
+                "grant_types": { "type": "array", "items": string() },
+                "response_types": { "type": "array", "items": string() },
+                "client_name": string(),
+                "client_uri": { "type": "string", "format": "uri" },
+                "logo_uri": { "type": "string", "format": "uri" },
+                "scope": string(),
+                "contacts": { "type": "array", "items": string() },
+            },
+        },
+        "ClientInformation": {
+            "allOf": [
+                reference("ClientMetadata"),
+                {
+                    "type": "object",
+                    "required": ["client_id"],
+                    "properties": {
+                        "client_id": string(),
+                        "client_secret": string(),
+                        "client_id_issued_at": { "type": "integer" },
+                        "client_secret_expires_at": { "type": "integer" },
+                    },
+                },
+            ],
+        },
+    })
+}
+
+/// Clients authenticate with HTTP Basic, in the body, or not at all if public.
+fn client_security() -> Value {
+    json!([{ "clientSecretBasic": [] }, {}])
+}
+
+/// The body of introspection and revocation requests.
+fn token_request() -> Value {
+    json!({
+        "type": "object",
+        "required": ["token"],
+        "properties": {
+            "token": string(),
+            "token_type_hint": { "type": "string", "enum": ["access_token", "refresh_token"] },
+        },
+    })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn reference(schema: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", schema) })
+}
+
+fn query(name: &str, required: bool, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": required, "schema": schema })
+}
+
+fn form(title: &str, mut schema: Value) -> Value {
+    schema["title"] = json!(title);
+    json!({
+        "required": true,
+        "content": {
+            "application/x-www-form-urlencoded": { "schema": schema },
+        },
+    })
+}
+
+fn json_response(description: &str, schema: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": { "schema": reference(schema) },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontends::simple::endpoint::{Generic, Vacant};
+    use crate::frontends::simple::request::Request;
+    use crate::primitives::registrar::ClientMap;
+
+    type Router = FlowRouter<Generic<ClientMap, Vacant, Vacant, Vacant>, Request>;
+
+    #[test]
+    fn router_grants() {
+        let mut router = Router::new();
+        router.remove_grant_type("client_credentials");
+        router.grant_type(
+            "urn:ietf:params:oauth:grant-type:device_code",
+            |_, _| unreachable!(),
+        );
+
+        let document = OpenApi::for_router(&router, "https://auth.example/oauth/".parse().unwrap())
+            .authorization("authorize")
+            .token("token")
+            .scope("read", "Read access")
+            .document();
+
+        let grant_types = &document["paths"]["token"]["post"]["requestBody"]["content"]
+            ["application/x-www-form-urlencoded"]["schema"]["properties"]["grant_type"]["enum"];
+        assert_eq!(
+            grant_types,
+            &json!([
+                "authorization_code",
+                "refresh_token",
+                "urn:ietf:params:oauth:grant-type:device_code"
+            ])
+        );
+
+        let flows = &document["components"]["securitySchemes"]["oauth2"]["flows"];
+        assert!(flows["clientCredentials"].is_null());
+        let code = &flows["authorizationCode"];
+        assert_eq!(code["authorizationUrl"], "https://auth.example/oauth/authorize");
+        assert_eq!(code["refreshUrl"], "https://auth.example/oauth/token");
+        assert_eq!(code["scopes"]["read"], "Read access");
+    }
+
+    #[test]
+    fn mounted_endpoints_only() {
+        let document = OpenApi::new("https://auth.example".parse().unwrap())
+            .introspection("/introspect")
+            .registration("/register")
+            .document();
+
+        let paths = document["paths"].as_object().unwrap();
+        let mut mounted: Vec<_> = paths.keys().map(String::as_str).collect();
+        mounted.sort_unstable();
+        assert_eq!(mounted, ["/introspect", "/register"]);
+        // Without the token endpoint there are no OAuth flows to describe.
+        assert!(document["components"]["securitySchemes"]["oauth2"].is_null());
+    }
+}
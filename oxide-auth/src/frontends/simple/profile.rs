@@ -0,0 +1,366 @@
+//! Endpoint presets of security profiles.
+//!
+//! A [`Profile`] switches on the features a security profile requires and refuses to build an
+//! endpoint if a prerequisite is not configured, instead of silently serving a weaker flow.
+//!
+//! ## FAPI 2.0
+//!
+//! [`Profile::Fapi2`] follows the FAPI 2.0 security profile:
+//!
+//! * Authorization requests must be pushed ahead of time, see [`primitives::pushed`]. Requests
+//!   carrying their parameters in the query are ignored.
+//! * PKCE with the `S256` method is required.
+//! * Tokens must be sender-constrained by a [`SenderConstraint`], a binding policy tying them to
+//!   the DPoP key or the client certificate of the token request. The crate does not ship one yet,
+//!   and [`NetworkBinding`] does not qualify: it binds to the unverified address and user agent of
+//!   the client and leaves tokens issued without a request context unbound.
+//! * Authorization responses name the issuer in the `iss` parameter (RFC 9207).
+//! * Pushed requests must name their `redirect_uri` and may not use other response modes or
+//!   request objects.
+//!
+//! ```
+//! # extern crate oxide_auth;
+//! use oxide_auth::frontends::simple::endpoint::EndpointBuilder;
+//! use oxide_auth::frontends::simple::profile::{Profile, ProfileError};
+//! use oxide_auth::primitives::pushed::PushedMap;
+//!
+//! let endpoint = || EndpointBuilder::new().build();
+//!
+//! let missing = Profile::Fapi2.endpoint(endpoint()).build();
+//! assert_eq!(missing.err(), Some(ProfileError::MissingPushedRequests));
+//!
+//! let unbound = Profile::Fapi2
+//!     .endpoint(endpoint())
+//!     .pushed_requests(PushedMap::new())
+//!     .issuer("https://as.example".parse().unwrap())
+//!     .build();
+//! assert_eq!(unbound.err(), Some(ProfileError::MissingSenderConstraint));
+//! ```
+//!
+//! [`Profile`]: enum.Profile.html
+//! [`Profile::Fapi2`]: enum.Profile.html#variant.Fapi2
+//! [`primitives::pushed`]: ../../../primitives/pushed/index.html
+//! [`SenderConstraint`]: trait.SenderConstraint.html
+//! [`NetworkBinding`]: ../../binding/struct.NetworkBinding.html
+use std::fmt;
+
+use url::Url;
+
+use crate::endpoint::{BindingPolicy, Endpoint, Extension, OAuthError, Template, WebRequest};
+use crate::endpoint::{ErrorCustomizer, GrantPolicy, IdempotencyStore, Metrics, Outbox, OwnerSolicitor};
+use crate::endpoint::{PushedRequests, RateLimiter, ScopePolicy, Scopes, TokenResponseCustomizer};
+use crate::primitives::authorizer::Authorizer;
+use crate::primitives::consent::ConsentStore;
+use crate::primitives::grant::GrantExtension;
+use crate::primitives::issuer::Issuer;
+use crate::primitives::nonce::NonceStore;
+use crate::primitives::registrar::Registrar;
+use crate::primitives::session::SessionStore;
+
+use super::extensions::{AddonList, AddonResult, AuthorizationAddon, AuthorizationRequest, Pkce};
+
+/// A security profile an endpoint can be configured for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// The FAPI 2.0 security profile, see the [module documentation](index.html#fapi-20).
+    Fapi2,
+}
+
+/// Collects the prerequisites of a profile for an endpoint.
+pub struct Preset<E> {
+    profile: Profile,
+    inner: E,
+    addons: AddonList,
+    pushed: Option<Box<dyn PushedRequests + Send>>,
+    sender: Option<Box<dyn BindingPolicy + Send>>,
+    issuer: Option<Url>,
+}
+
+/// An endpoint following a security profile.
+///
+/// The addons of the profile replace the extension of the inner endpoint, pass further addons to
+/// [`Preset::addons`]. All other methods are delegated to the inner endpoint, whose own pushed
+/// requests, binding policy and issuer identifier are hidden.
+///
+/// [`Preset::addons`]: struct.Preset.html#method.addons
+pub struct Profiled<E> {
+    inner: E,
+    addons: AddonList,
+    pushed: Box<dyn PushedRequests + Send>,
+    sender: Box<dyn BindingPolicy + Send>,
+    issuer: String,
+}
+
+/// A prerequisite of the profile is missing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    /// No store for pushed authorization requests was configured.
+    MissingPushedRequests,
+
+    /// Tokens are not bound to the DPoP key or client certificate of their sender.
+    MissingSenderConstraint,
+
+    /// No issuer identifier was configured.
+    MissingIssuer,
+
+    /// The issuer identifier is not an `https` url without query and fragment.
+    InvalidIssuer,
+}
+
+/// A binding policy tying tokens to the DPoP key or client certificate of the token request.
+///
+/// The FAPI 2.0 profile refuses bearer tokens usable by anyone holding them. Implement this marker
+/// only for a policy that binds every token to the thumbprint of a key or certificate the frontend
+/// verified, and rejects requests without a matching proof. Policies binding to the network
+/// context, such as `NetworkBinding`, do not qualify.
+pub trait SenderConstraint: BindingPolicy {}
+
+/// Refuses pushed requests the profile does not allow.
+struct StrictRequest;
+
+impl Profile {
+    /// Configure an endpoint for the profile.
+    pub fn endpoint<E>(self, inner: E) -> Preset<E> {
+        Preset {
+            profile: self,
+            inner,
+            addons: AddonList::new(),
+            pushed: None,
+            sender: None,
+            issuer: None,
+        }
+    }
+}
+
+impl<E> Preset<E> {
+    /// The store holding the pushed authorization requests.
+    pub fn pushed_requests<P>(self, store: P) -> Self
+    where
+        P: PushedRequests + Send + 'static,
+    {
+        Preset {
+            pushed: Some(Box::new(store)),
+            ..self
+        }
+    }
+
+    /// The policy binding tokens to the DPoP key or client certificate of their sender.
+    pub fn sender_constraint<S>(self, constraint: S) -> Self
+    where
+        S: SenderConstraint + Send + 'static,
+    {
+        Preset {
+            sender: Some(Box::new(constraint)),
+            ..self
+        }
+    }
+
+    /// The issuer identifier of the server, as published in its metadata.
+    pub fn issuer(self, issuer: Url) -> Self {
+        Preset {
+            issuer: Some(issuer),
+            ..self
+        }
+    }
+
+    /// Further addons, run after those required by the profile.
+    pub fn addons(self, addons: AddonList) -> Self {
+        Preset { addons, ..self }
+    }
+
+    /// Build the endpoint, failing if a prerequisite of the profile is missing.
+    pub fn build(self) -> Result<Profiled<E>, ProfileError> {
+        match self.profile {
+            Profile::Fapi2 => self.fapi2(),
+        }
+    }
+
+    fn fapi2(self) -> Result<Profiled<E>, ProfileError> {
+        let pushed = self.pushed.ok_or(ProfileError::MissingPushedRequests)?;
+        let issuer = self.issuer.ok_or(ProfileError::MissingIssuer)?;
+        if issuer.scheme() != "https" || issuer.query().is_some() || issuer.fragment().is_some() {
+            return Err(ProfileError::InvalidIssuer);
+        }
+        let sender = self.sender.ok_or(ProfileError::MissingSenderConstraint)?;
+
+        let mut addons = AddonList::new();
+        addons.push_code(Pkce::required());
+        addons.push_authorization(StrictRequest);
+        addons.authorization.extend(self.addons.authorization);
+        addons.access_token.extend(self.addons.access_token);
+        addons.client_credentials.extend(self.addons.client_credentials);
+
+        Ok(Profiled {
+            inner: self.inner,
+            addons,
+            pushed,
+            sender,
+            // The url parser adds a trailing slash to bare origins, which the metadata omits.
+            issuer: issuer.as_str().trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+impl<E> Profiled<E> {
+    /// The store holding the pushed authorization requests.
+    ///
+    /// The endpoint receiving pushed requests stores them here after authenticating the client.
+    pub fn pushed_requests_mut(&mut self) -> &mut dyn PushedRequests {
+        &mut *self.pushed
+    }
+
+    /// The wrapped endpoint.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// A mutable reference to the wrapped endpoint.
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+}
+
+impl GrantExtension for StrictRequest {
+    fn identifier(&self) -> &'static str {
+        "strict_request"
+    }
+}
+
+impl AuthorizationAddon for StrictRequest {
+    fn execute(&self, request: &dyn AuthorizationRequest) -> AddonResult {
+        if request.redirect_uri().is_none() {
+            return AddonResult::Err;
+        }
+
+        let response_mode = request.extension("response_mode");
+        if response_mode.is_some_and(|mode| mode != "query") {
+            return AddonResult::Err;
+        }
+
+        // Request objects are not verified, their parameters must not be mistaken as trusted.
+        if request.extension("request").is_some() {
+            return AddonResult::Err;
+        }
+
+        AddonResult::Ok
+    }
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::MissingPushedRequests => {
+                f.write_str("the profile requires a store of pushed authorization requests")
+            }
+            ProfileError::MissingSenderConstraint => {
+                f.write_str("the profile requires tokens bound to their sender")
+            }
+            ProfileError::MissingIssuer => f.write_str("the profile requires an issuer identifier"),
+            ProfileError::InvalidIssuer => {
+                f.write_str("the issuer identifier is not an https url without query and fragment")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl<W, Inner> Endpoint<W> for Profiled<Inner>
+where
+    W: WebRequest,
+    Inner: Endpoint<W>,
+{
+    type Error = Inner::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.inner.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.inner.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.inner.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.inner.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.inner.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.inner.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.inner.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.inner.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        Some(&mut self.addons)
+    }
+
+    fn binding_policy(&mut self) -> Option<&mut dyn BindingPolicy> {
+        Some(&mut *self.sender)
+    }
+
+    fn outbox(&mut self) -> Option<&mut dyn Outbox<W>> {
+        self.inner.outbox()
+    }
+
+    fn token_customizer(&mut self) -> Option<&mut dyn TokenResponseCustomizer<W>> {
+        self.inner.token_customizer()
+    }
+
+    fn error_customizer(&mut self) -> Option<&mut dyn ErrorCustomizer<W>> {
+        self.inner.error_customizer()
+    }
+
+    fn consent_store(&mut self) -> Option<&mut dyn ConsentStore> {
+        self.inner.consent_store()
+    }
+
+    fn scope_policy(&mut self) -> Option<&mut dyn ScopePolicy<W>> {
+        self.inner.scope_policy()
+    }
+
+    fn metrics(&mut self) -> Option<&dyn Metrics> {
+        self.inner.metrics()
+    }
+
+    fn rate_limiter(&mut self) -> Option<&mut dyn RateLimiter<W>> {
+        self.inner.rate_limiter()
+    }
+
+    fn grant_policy(&mut self) -> Option<&mut dyn GrantPolicy> {
+        self.inner.grant_policy()
+    }
+
+    fn idempotency_store(&mut self) -> Option<&mut dyn IdempotencyStore> {
+        self.inner.idempotency_store()
+    }
+
+    fn session_store(&mut self) -> Option<&mut dyn SessionStore> {
+        self.inner.session_store()
+    }
+
+    fn nonce_store(&mut self) -> Option<&mut dyn NonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn pushed_requests(&mut self) -> Option<&mut dyn PushedRequests> {
+        Some(&mut *self.pushed)
+    }
+
+    fn issuer_identifier(&self) -> Option<&str> {
+        Some(&self.issuer)
+    }
+}
@@ -1,7 +1,7 @@
 //! Simple, owning request and response types.
 use std::marker::PhantomData;
 
-use crate::endpoint::{QueryParameter, WebRequest, WebResponse};
+use crate::endpoint::{QueryParameter, RequestContext, WebRequest, WebResponse};
 
 use std::borrow::Cow;
 use std::collections::HashMap;
@@ -19,6 +19,9 @@ pub struct Request {
 
     /// Provided authorization header.
     pub auth: Option<String>,
+
+    /// Context of the request, such as the remote address.
+    pub context: Option<RequestContext>,
 }
 
 /// Open and simple implementation of `WebResponse`.
@@ -39,6 +42,9 @@ pub struct Response {
     ///
     /// One variant for each possible encoding type.
     pub body: Option<Body>,
+
+    /// Further headers, such as `Cache-Control` on token responses.
+    pub headers: Vec<(String, String)>,
 }
 
 /// An enum containing the necessary HTTP status codes.
@@ -56,6 +62,12 @@ pub enum Status {
 
     /// Http status code 401.
     Unauthorized,
+
+    /// Http status code 403.
+    Forbidden,
+
+    /// Http status code 429.
+    TooManyRequests,
 }
 
 /// Models the necessary body contents.
@@ -130,6 +142,10 @@ impl WebRequest for Request {
     fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
         Ok(self.auth.as_ref().map(|string| Cow::Borrowed(string.as_str())))
     }
+
+    fn context(&mut self) -> Option<Cow<'_, RequestContext>> {
+        self.context.as_ref().map(Cow::Borrowed)
+    }
 }
 
 impl WebResponse for Response {
@@ -166,6 +182,14 @@ impl WebResponse for Response {
         Ok(())
     }
 
+    /// Set the response status to 403 and add a `WWW-Authenticate` header.
+    fn forbidden(&mut self, header_value: &str) -> Result<(), Self::Error> {
+        self.status = Status::Forbidden;
+        self.location = None;
+        self.www_authenticate = Some(header_value.to_owned());
+        Ok(())
+    }
+
     /// A pure text response with no special media type set.
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.body = Some(Body::Text(text.to_owned()));
@@ -177,6 +201,23 @@ impl WebResponse for Response {
         self.body = Some(Body::Json(data.to_owned()));
         Ok(())
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        self.headers.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_owned(), value.to_owned()));
+        Ok(())
+    }
+
+    /// Set the response status to 429 and add a `Retry-After` header, if the delay is known.
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.status = Status::TooManyRequests;
+        self.location = None;
+        self.www_authenticate = None;
+        if let Some(seconds) = retry_after {
+            self.set_header("Retry-After", &seconds.to_string())?;
+        }
+        Ok(())
+    }
 }
 
 impl NoError {
@@ -206,6 +247,10 @@ where
     fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
         self.0.authheader().map_err(&mut self.1)
     }
+
+    fn context(&mut self) -> Option<Cow<'_, RequestContext>> {
+        self.0.context()
+    }
 }
 
 impl<W: WebResponse, F, T> WebResponse for MapErr<W, F, T>
@@ -233,6 +278,11 @@ where
         self.0.unauthorized(header_value).map_err(&mut self.1)
     }
 
+    /// Set the response status to 403 and add a `WWW-Authenticate` header.
+    fn forbidden(&mut self, header_value: &str) -> Result<(), Self::Error> {
+        self.0.forbidden(header_value).map_err(&mut self.1)
+    }
+
     /// A pure text response with no special media type set.
     fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
         self.0.body_text(text).map_err(&mut self.1)
@@ -242,4 +292,16 @@ where
     fn body_json(&mut self, data: &str) -> Result<(), Self::Error> {
         self.0.body_json(data).map_err(&mut self.1)
     }
+
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        self.0.set_header(name, value).map_err(&mut self.1)
+    }
+
+    fn no_store(&mut self) -> Result<(), Self::Error> {
+        self.0.no_store().map_err(&mut self.1)
+    }
+
+    fn too_many_requests(&mut self, retry_after: Option<u64>) -> Result<(), Self::Error> {
+        self.0.too_many_requests(retry_after).map_err(&mut self.1)
+    }
 }
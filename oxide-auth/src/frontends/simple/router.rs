@@ -0,0 +1,194 @@
+//! Dispatches requests to the flow of their grant or response type.
+//!
+//! The token endpoint serves several grants under a single url, distinguished only by the
+//! `grant_type` of the request body. A [`FlowRouter`] holds one handler per grant type, starting
+//! out with the grants implemented by this library, and answers requests for any other grant type
+//! with an `unsupported_grant_type` error.
+//!
+//! [`FlowRouter`]: struct.FlowRouter.html
+use std::collections::HashMap;
+
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::endpoint::{AccessTokenFlow, AuthorizationFlow, ClientCredentialsFlow, RefreshFlow};
+use crate::endpoint::{explain_access_token_error, Endpoint, Template, WebRequest, WebResponse};
+
+/// Executes the flow of a grant or response type on an endpoint.
+pub type FlowHandler<E, W> =
+    Box<dyn FnMut(&mut E, W) -> Result<<W as WebRequest>::Response, <E as Endpoint<W>>::Error>>;
+
+/// Dispatches requests on the `grant_type` or `response_type` to a registered flow.
+///
+/// A new router handles the `authorization_code`, `refresh_token` and `client_credentials` grants
+/// at the token endpoint with the respective flows. Extension grants, such as the device grant, are
+/// added with [`grant_type`] and standard grants an endpoint does not support are removed with
+/// [`remove_grant_type`].
+///
+/// The authorization endpoint runs an `AuthorizationFlow` for all requests, unless a handler has
+/// been registered for their `response_type` with [`response_type`]. The flow validates the client
+/// and its redirect uri before rejecting any response type except `code`, so errors only ever
+/// reach registered redirect uris.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate oxide_auth;
+/// use oxide_auth::endpoint::{Endpoint, WebRequest};
+/// use oxide_auth::frontends::simple::router::FlowRouter;
+///
+/// fn token<E, W>(router: &mut FlowRouter<E, W>, endpoint: &mut E, request: W)
+///     -> Result<W::Response, E::Error>
+/// where
+///     E: Endpoint<W>,
+///     W: WebRequest,
+/// {
+///     // No more matching on `grant_type` by hand.
+///     router.token(endpoint, request)
+/// }
+/// ```
+///
+/// [`grant_type`]: #method.grant_type
+/// [`remove_grant_type`]: #method.remove_grant_type
+/// [`response_type`]: #method.response_type
+pub struct FlowRouter<E, W>
+where
+    E: Endpoint<W>,
+    W: WebRequest,
+{
+    grants: HashMap<String, FlowHandler<E, W>>,
+    responses: HashMap<String, FlowHandler<E, W>>,
+}
+
+impl<E, W> FlowRouter<E, W>
+where
+    E: Endpoint<W>,
+    W: WebRequest,
+{
+    /// A router for the grants implemented by this library.
+    pub fn new() -> Self {
+        let mut router = FlowRouter {
+            grants: HashMap::new(),
+            responses: HashMap::new(),
+        };
+
+        router.grant_type("authorization_code", |endpoint, request| {
+            AccessTokenFlow::prepare(endpoint)?.execute(request)
+        });
+        router.grant_type("refresh_token", |endpoint, request| {
+            RefreshFlow::prepare(endpoint)?.execute(request)
+        });
+        router.grant_type("client_credentials", |endpoint, request| {
+            ClientCredentialsFlow::prepare(endpoint)?.execute(request)
+        });
+        router
+    }
+
+    /// Handle token requests of a grant type, replacing any previous handler.
+    pub fn grant_type<F>(&mut self, grant_type: &str, handler: F)
+    where
+        F: FnMut(&mut E, W) -> Result<W::Response, E::Error> + 'static,
+    {
+        self.grants.insert(grant_type.to_owned(), Box::new(handler));
+    }
+
+    /// Reject token requests of a grant type as unsupported.
+    pub fn remove_grant_type(&mut self, grant_type: &str) {
+        self.grants.remove(grant_type);
+    }
+
+    /// Handle authorization requests of a response type, instead of the `AuthorizationFlow`.
+    pub fn response_type<F>(&mut self, response_type: &str, handler: F)
+    where
+        F: FnMut(&mut E, W) -> Result<W::Response, E::Error> + 'static,
+    {
+        self.responses.insert(response_type.to_owned(), Box::new(handler));
+    }
+
+    /// Whether token requests of the grant type are handled, for example to publish in metadata.
+    pub fn supports_grant_type(&self, grant_type: &str) -> bool {
+        self.grants.contains_key(grant_type)
+    }
+
+    /// The handled grant types, sorted.
+    pub fn grant_types(&self) -> Vec<&str> {
+        let mut grant_types: Vec<_> = self.grants.keys().map(String::as_str).collect();
+        grant_types.sort_unstable();
+        grant_types
+    }
+
+    /// The response types with a registered handler, sorted, besides the `code` of the
+    /// `AuthorizationFlow`.
+    pub fn response_types(&self) -> Vec<&str> {
+        let mut response_types: Vec<_> = self.responses.keys().map(String::as_str).collect();
+        response_types.sort_unstable();
+        response_types
+    }
+
+    /// Answer a request to the token endpoint with the flow of its grant type.
+    pub fn token(&mut self, endpoint: &mut E, mut request: W) -> Result<W::Response, E::Error> {
+        let grant_type = request
+            .urlbody()
+            .map_err(|err| endpoint.web_error(err))?
+            .unique_value("grant_type")
+            .map(|grant_type| grant_type.into_owned());
+
+        let kind = match grant_type {
+            None => AccessTokenErrorType::InvalidRequest,
+            Some(grant_type) => match self.grants.get_mut(&grant_type) {
+                Some(handler) => return handler(endpoint, request),
+                None => AccessTokenErrorType::UnsupportedGrantType,
+            },
+        };
+
+        unsupported(endpoint, &mut request, kind)
+    }
+
+    /// Answer a request to the authorization endpoint with the flow of its response type.
+    pub fn authorize(&mut self, endpoint: &mut E, mut request: W) -> Result<W::Response, E::Error> {
+        let response_type = request
+            .query()
+            .map_err(|err| endpoint.web_error(err))?
+            .unique_value("response_type")
+            .map(|response_type| response_type.into_owned());
+
+        match response_type.and_then(|response_type| self.responses.get_mut(&response_type)) {
+            Some(handler) => handler(endpoint, request),
+            None => AuthorizationFlow::prepare(endpoint)?.execute(request),
+        }
+    }
+}
+
+impl<E, W> Default for FlowRouter<E, W>
+where
+    E: Endpoint<W>,
+    W: WebRequest,
+{
+    fn default() -> Self {
+        FlowRouter::new()
+    }
+}
+
+/// Answer a token request that no flow handles.
+fn unsupported<E, W>(
+    endpoint: &mut E, request: &mut W, kind: AccessTokenErrorType,
+) -> Result<W::Response, E::Error>
+where
+    E: Endpoint<W>,
+    W: WebRequest,
+{
+    let mut error = AccessTokenError::default();
+    error.set_type(kind);
+    explain_access_token_error(endpoint, request, &mut error);
+
+    let mut response = endpoint.response(request, Template::new_bad(Some(&mut error)))?;
+    response.client_error().map_err(|err| endpoint.web_error(err))?;
+    response.no_store().map_err(|err| endpoint.web_error(err))?;
+
+    let body = error
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.into_owned()))
+        .collect::<HashMap<String, String>>();
+    response
+        .body_json(&serde_json::to_string(&body).unwrap())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
@@ -0,0 +1,215 @@
+//! Removing expired codes and tokens from the stores holding them.
+//!
+//! Stores refuse expired codes and tokens when they are used, but most only delete them when they
+//! are redeemed or revoked. Codes that are never redeemed and tokens that are never refreshed stay
+//! around indefinitely. A [`Sweeper`] purges the [`Expiring`] stores registered with it each time
+//! its `sweep` method is called, from any scheduler, and reports the removed entries to a
+//! [`Metrics`] collector under the name of the store.
+//!
+//! Shared stores are locked for the duration of their purge only. `oxide-auth-async` runs a
+//! sweeper periodically on `tokio`.
+//!
+//! ```
+//! # extern crate oxide_auth;
+//! use std::sync::{Arc, Mutex, RwLock};
+//! use oxide_auth::frontends::metrics::PrometheusMetrics;
+//! use oxide_auth::frontends::sweep::Sweeper;
+//! use oxide_auth::primitives::authorizer::AuthMap;
+//! use oxide_auth::primitives::generator::RandomGenerator;
+//! use oxide_auth::primitives::issuer::TokenMap;
+//!
+//! let authorizer = Arc::new(Mutex::new(AuthMap::new(RandomGenerator::new(16))));
+//! let issuer = Arc::new(RwLock::new(TokenMap::new(RandomGenerator::new(16))));
+//! let metrics = Arc::new(PrometheusMetrics::new());
+//!
+//! let mut sweeper = Sweeper::new()
+//!     .store("codes", authorizer.clone())
+//!     .store("tokens", issuer.clone())
+//!     .with_metrics(metrics.clone());
+//!
+//! // Call this periodically, for example from a thread or a task of your runtime.
+//! assert_eq!(sweeper.sweep(), 0);
+//! ```
+//!
+//! [`Sweeper`]: struct.Sweeper.html
+//! [`Expiring`]: trait.Expiring.html
+//! [`Metrics`]: ../../endpoint/trait.Metrics.html
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::{DateTime, Utc};
+
+use crate::endpoint::Metrics;
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::generator::TagGrant;
+use crate::primitives::issuer::TokenMap;
+
+/// A store keeping codes or tokens past their expiry.
+pub trait Expiring {
+    /// Remove the entries expired at `now`, returning how many there were.
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()>;
+}
+
+/// Purges expired entries from a set of stores.
+#[derive(Default)]
+pub struct Sweeper {
+    stores: Vec<(String, Box<dyn Expiring + Send>)>,
+    metrics: Option<Box<dyn Metrics + Send + Sync>>,
+}
+
+impl Sweeper {
+    /// A sweeper without any stores.
+    pub fn new() -> Self {
+        Sweeper::default()
+    }
+
+    /// Add a store, reported to metrics under `name`.
+    pub fn store<E>(mut self, name: &str, store: E) -> Self
+    where
+        E: Expiring + Send + 'static,
+    {
+        self.stores.push((name.to_owned(), Box::new(store)));
+        self
+    }
+
+    /// Report the removed entries of each store to a collector.
+    pub fn with_metrics<M>(mut self, metrics: M) -> Self
+    where
+        M: Metrics + Send + Sync + 'static,
+    {
+        self.metrics = Some(Box::new(metrics));
+        self
+    }
+
+    /// Purge all stores, returning how many entries were removed in total.
+    ///
+    /// A store that fails is skipped and tried again on the next sweep.
+    pub fn sweep(&mut self) -> usize {
+        self.sweep_at(Utc::now())
+    }
+
+    /// Purge all stores of the entries expired at `now`.
+    pub fn sweep_at(&mut self, now: DateTime<Utc>) -> usize {
+        let mut total = 0;
+        for (name, store) in self.stores.iter_mut() {
+            let removed = match store.purge_expired(now) {
+                Ok(removed) => removed,
+                Err(()) => continue,
+            };
+
+            if let Some(metrics) = &self.metrics {
+                metrics.swept(name, removed);
+            }
+            total += removed;
+        }
+        total
+    }
+}
+
+impl<I: TagGrant> Expiring for AuthMap<I> {
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        Ok(AuthMap::purge_expired(self, now))
+    }
+}
+
+impl<G: TagGrant> Expiring for TokenMap<G> {
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        Ok(TokenMap::purge_expired(self, now))
+    }
+}
+
+impl<E: Expiring + ?Sized> Expiring for &mut E {
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        (**self).purge_expired(now)
+    }
+}
+
+impl<E: Expiring + ?Sized> Expiring for Box<E> {
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        (**self).purge_expired(now)
+    }
+}
+
+impl<E: Expiring + ?Sized> Expiring for Arc<Mutex<E>> {
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        self.lock().map_err(|_| ())?.purge_expired(now)
+    }
+}
+
+impl<E: Expiring + ?Sized> Expiring for Arc<RwLock<E>> {
+    fn purge_expired(&mut self, now: DateTime<Utc>) -> Result<usize, ()> {
+        self.write().map_err(|_| ())?.purge_expired(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    use crate::frontends::metrics::PrometheusMetrics;
+    use crate::primitives::authorizer::Authorizer;
+    use crate::primitives::generator::RandomGenerator;
+    use crate::primitives::grant::{Extensions, Grant};
+    use crate::primitives::issuer::Issuer;
+
+    fn grant(until: DateTime<Utc>) -> Grant {
+        Grant {
+            owner_id: "Owner".into(),
+            client_id: "Client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com".parse().unwrap(),
+            until,
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn sweeps_expired_entries() {
+        let now = Utc::now();
+        let authorizer = Arc::new(Mutex::new(AuthMap::new(RandomGenerator::new(16))));
+        let expired = authorizer.lock().unwrap().authorize(grant(now)).unwrap();
+        let valid = authorizer
+            .lock()
+            .unwrap()
+            .authorize(grant(now + Duration::minutes(10)))
+            .unwrap();
+
+        // The refresh token expires along with the access token by default.
+        let issuer = Arc::new(RwLock::new(TokenMap::new(RandomGenerator::new(16))));
+        let token = issuer
+            .write()
+            .unwrap()
+            .issue(grant(now + Duration::hours(1)))
+            .unwrap();
+
+        let metrics = Arc::new(PrometheusMetrics::new());
+        let mut sweeper = Sweeper::new()
+            .store("codes", authorizer.clone())
+            .store("tokens", issuer.clone())
+            .with_metrics(metrics.clone());
+
+        assert_eq!(sweeper.sweep_at(now), 1);
+        assert_eq!(authorizer.lock().unwrap().extract(&expired), Ok(None));
+        assert!(authorizer.lock().unwrap().extract(&valid).unwrap().is_some());
+        assert!(issuer
+            .read()
+            .unwrap()
+            .recover_token(&token.token)
+            .unwrap()
+            .is_some());
+
+        assert_eq!(sweeper.sweep_at(now + Duration::hours(2)), 2);
+        assert_eq!(issuer.read().unwrap().recover_token(&token.token), Ok(None));
+        assert_eq!(
+            issuer
+                .read()
+                .unwrap()
+                .recover_refresh(token.refresh.as_deref().unwrap()),
+            Ok(None)
+        );
+
+        let text = metrics.render();
+        assert!(text.contains("oxide_auth_swept_total{store=\"codes\"} 1\n"));
+        assert!(text.contains("oxide_auth_swept_total{store=\"tokens\"} 2\n"));
+    }
+}
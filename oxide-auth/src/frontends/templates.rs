@@ -0,0 +1,348 @@
+//! Pages shown to resource owners, with default templates.
+//!
+//! The consent page asks the owner to approve a client, the error page explains a failed request
+//! that could not be redirected back to the client. [`ConsentPage`] and [`ErrorInfo`] hold all
+//! data such pages need and can be serialized into the context of any template engine, so that
+//! no html has to be assembled by formatting strings.
+//!
+//! With the `templates` feature, [`Templates`] renders them with `minijinja`, from default
+//! templates or from your own. It also implements [`RenderError`] to render the body of html error
+//! responses.
+//!
+//! [`ConsentPage`]: struct.ConsentPage.html
+//! [`ErrorInfo`]: ../dev/struct.ErrorInfo.html
+//! [`Templates`]: struct.Templates.html
+//! [`RenderError`]: ../dev/trait.RenderError.html
+use serde::Serialize;
+use url::form_urlencoded;
+
+use crate::endpoint::Solicitation;
+use crate::primitives::scope::{ScopeRegistry, Sensitivity};
+
+#[cfg(feature = "templates")]
+use super::render::{ErrorInfo, RenderError, RenderedError};
+
+/// The data of a consent page for a pending authorization request.
+#[derive(Clone, Debug, Serialize)]
+pub struct ConsentPage {
+    /// The id of the requesting client.
+    pub client_id: String,
+
+    /// A name of the client to display instead of its id.
+    pub client_name: Option<String>,
+
+    /// The uri the owner is sent back to after deciding.
+    pub redirect_uri: String,
+
+    /// The requested scopes, sorted by name.
+    pub scopes: Vec<ScopeItem>,
+
+    /// The url the decision of the owner is posted to.
+    pub form_action: String,
+
+    /// The parameters of the authorization request, urlencoded.
+    ///
+    /// The decision must be posted with these in the query, so that the authorization flow can
+    /// process the request again. They include parameters of extensions such as PKCE when the
+    /// solicitation holds all request parameters.
+    pub query: String,
+
+    /// A token that the form submits as `csrf_token`, to be checked by the decision handler.
+    pub csrf_token: Option<String>,
+}
+
+/// A single requested scope token.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ScopeItem {
+    /// The scope token, submitted as a `scope` value when the owner keeps it selected.
+    pub name: String,
+
+    /// A human readable name of the scope.
+    pub title: Option<String>,
+
+    /// A description of the access granted by the scope, for the owner.
+    pub description: Option<String>,
+
+    /// How sensitive the granted access is, if known.
+    pub sensitivity: Option<Sensitivity>,
+}
+
+impl ConsentPage {
+    /// The page for a solicitation, posting the decision to `form_action`.
+    pub fn new(solicitation: &Solicitation, form_action: &str) -> Self {
+        let grant = solicitation.pre_grant();
+
+        let mut scopes = grant
+            .scope
+            .iter()
+            .map(|name| ScopeItem {
+                name: name.to_owned(),
+                title: None,
+                description: None,
+                sensitivity: None,
+            })
+            .collect::<Vec<_>>();
+        scopes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        match solicitation.parameters() {
+            Some(parameters) => {
+                let mut parameters = parameters.iter().collect::<Vec<_>>();
+                parameters.sort();
+                query.extend_pairs(parameters);
+            }
+            None => {
+                query
+                    .append_pair("response_type", "code")
+                    .append_pair("client_id", &grant.client_id)
+                    .append_pair("redirect_uri", grant.redirect_uri.as_str())
+                    .append_pair("scope", &grant.scope.to_string());
+                if let Some(state) = solicitation.state() {
+                    query.append_pair("state", state);
+                }
+            }
+        }
+
+        ConsentPage {
+            client_id: grant.client_id.clone(),
+            client_name: None,
+            redirect_uri: grant.redirect_uri.to_string(),
+            scopes,
+            form_action: form_action.to_owned(),
+            query: query.finish(),
+            csrf_token: None,
+        }
+    }
+
+    /// Display a name for the client.
+    pub fn with_client_name(self, client_name: &str) -> Self {
+        ConsentPage {
+            client_name: Some(client_name.to_owned()),
+            ..self
+        }
+    }
+
+    /// Submit a token against cross site request forgery with the form.
+    pub fn with_csrf_token(self, csrf_token: &str) -> Self {
+        ConsentPage {
+            csrf_token: Some(csrf_token.to_owned()),
+            ..self
+        }
+    }
+
+    /// Describe the scopes, leaving those unknown to `describe` without description.
+    pub fn describe_scopes<F>(mut self, describe: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        for scope in self.scopes.iter_mut() {
+            scope.description = describe(&scope.name);
+        }
+        self
+    }
+
+    /// Name, describe and classify the scopes known to a registry.
+    pub fn with_registry(mut self, registry: &ScopeRegistry) -> Self {
+        for scope in self.scopes.iter_mut() {
+            if let Some(info) = registry.get(&scope.name) {
+                scope.title = Some(info.name.clone());
+                scope.description = info.description.clone();
+                scope.sensitivity = Some(info.sensitivity);
+            }
+        }
+        self
+    }
+}
+
+/// The default template of the consent page.
+///
+/// Rendered with a [`ConsentPage`] as its context. Both buttons post the form to the `form_action`
+/// with the request parameters in the query, and add `allow=true` or `deny=true` to it. The
+/// selected scopes are submitted as `scope` values in the body.
+///
+/// [`ConsentPage`]: struct.ConsentPage.html
+pub const CONSENT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Authorize {{ client_name or client_id }}</title></head>
+<body>
+<form method="post">
+  <p>'{{ client_name or client_id }}' (at {{ redirect_uri }}) is requesting permission to:</p>
+  <ul>
+  {%- for scope in scopes %}
+    <li><label><input type="checkbox" name="scope" value="{{ scope.name }}" checked>
+      {{ scope.title or scope.name }}</label>
+      {%- if scope.sensitivity == "high" %} <strong>(sensitive)</strong>{% endif %}
+      {%- if scope.description %}<br>{{ scope.description }}{% endif %}</li>
+  {%- endfor %}
+  </ul>
+  {%- if csrf_token %}
+  <input type="hidden" name="csrf_token" value="{{ csrf_token }}">
+  {%- endif %}
+  <input type="submit" value="Accept" formaction="{{ form_action }}?{{ query }}&allow=true">
+  <input type="submit" value="Deny" formaction="{{ form_action }}?{{ query }}&deny=true">
+</form>
+</body>
+</html>
+"#;
+
+/// The default template of the error page.
+///
+/// Rendered with an [`ErrorInfo`] as its context.
+///
+/// [`ErrorInfo`]: ../dev/struct.ErrorInfo.html
+pub const ERROR_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Authorization failed</title></head>
+<body>
+<p>The request could not be processed{% if error %} ({{ error }}){% endif %}.</p>
+{%- if description %}
+<p>{{ description }}</p>
+{%- endif %}
+{%- if uri %}
+<p><a href="{{ uri }}">More information</a></p>
+{%- endif %}
+</body>
+</html>
+"#;
+
+/// Renders consent and error pages with `minijinja`.
+///
+/// Starts out with the default templates, which can be replaced one by one. All values are html
+/// escaped.
+#[cfg(feature = "templates")]
+pub struct Templates {
+    environment: minijinja::Environment<'static>,
+}
+
+#[cfg(feature = "templates")]
+impl Templates {
+    const CONSENT: &'static str = "consent.html";
+    const ERROR: &'static str = "error.html";
+
+    /// Renderer with the default templates.
+    pub fn new() -> Self {
+        let mut environment = minijinja::Environment::new();
+        environment
+            .add_template(Self::CONSENT, CONSENT_TEMPLATE)
+            .expect("Default consent template is valid");
+        environment
+            .add_template(Self::ERROR, ERROR_TEMPLATE)
+            .expect("Default error template is valid");
+        Templates { environment }
+    }
+
+    /// Replace the consent page template, which is rendered with a `ConsentPage`.
+    pub fn set_consent_template(&mut self, source: String) -> Result<(), minijinja::Error> {
+        self.environment.add_template_owned(Self::CONSENT, source)
+    }
+
+    /// Replace the error page template, which is rendered with an `ErrorInfo`.
+    pub fn set_error_template(&mut self, source: String) -> Result<(), minijinja::Error> {
+        self.environment.add_template_owned(Self::ERROR, source)
+    }
+
+    /// Render the consent page.
+    pub fn consent(&self, page: &ConsentPage) -> Result<String, minijinja::Error> {
+        self.environment.get_template(Self::CONSENT)?.render(page)
+    }
+
+    /// Render the error page.
+    pub fn error(&self, error: &ErrorInfo) -> Result<String, minijinja::Error> {
+        self.environment.get_template(Self::ERROR)?.render(error)
+    }
+}
+
+#[cfg(feature = "templates")]
+impl Default for Templates {
+    fn default() -> Self {
+        Templates::new()
+    }
+}
+
+#[cfg(feature = "templates")]
+impl RenderError for Templates {
+    fn render(&self, error: &ErrorInfo) -> Option<RenderedError> {
+        self.error(error).ok().map(RenderedError::Html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoint::{NormalizedParameter, PreGrant};
+
+    fn pre_grant() -> PreGrant {
+        PreGrant {
+            client_id: "client".to_owned(),
+            redirect_uri: "https://client.example/cb".parse::<url::Url>().unwrap().into(),
+            scope: "write read".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn consent_page_data() {
+        let grant = pre_grant();
+        let mut parameters = NormalizedParameter::new();
+        parameters.insert_or_poison("client_id".into(), "client".into());
+        parameters.insert_or_poison("code_challenge".into(), "abc&".into());
+
+        let solicitation = Solicitation::new(&grant).with_parameters(&parameters);
+        let page = ConsentPage::new(&solicitation, "/authorize")
+            .with_client_name("Client")
+            .describe_scopes(|scope| match scope {
+                "read" => Some("Read your data".to_owned()),
+                _ => None,
+            });
+
+        assert_eq!(page.query, "client_id=client&code_challenge=abc%26");
+        assert_eq!(page.scopes[0].name, "read");
+        assert_eq!(page.scopes[0].description.as_deref(), Some("Read your data"));
+        assert_eq!(page.scopes[1].description, None);
+
+        let solicitation = Solicitation::new(&grant).with_state("xyz");
+        let page = ConsentPage::new(&solicitation, "/authorize");
+        assert!(page.query.starts_with("response_type=code&client_id=client&"));
+        assert!(page.query.ends_with("&state=xyz"));
+    }
+
+    #[cfg(feature = "templates")]
+    #[test]
+    fn default_templates() {
+        use crate::primitives::scope::ScopeInfo;
+
+        let grant = pre_grant();
+        let solicitation = Solicitation::new(&grant);
+        let page = ConsentPage::new(&solicitation, "/authorize")
+            .with_client_name("<Client>")
+            .with_csrf_token("token");
+
+        let html = Templates::new().consent(&page).unwrap();
+        assert!(html.contains("'&lt;Client&gt;'"));
+        assert!(html.contains(r#"name="csrf_token" value="token""#));
+        assert!(html.contains(r#"value="read" checked"#));
+
+        let mut registry = ScopeRegistry::new();
+        registry.register(
+            "write",
+            ScopeInfo::new("Edit")
+                .with_description("Change your data")
+                .with_sensitivity(Sensitivity::High),
+        );
+        let page = ConsentPage::new(&solicitation, "/authorize").with_registry(&registry);
+        assert_eq!(page.scopes[0].title, None);
+        assert_eq!(page.scopes[1].title.as_deref(), Some("Edit"));
+
+        let html = Templates::new().consent(&page).unwrap();
+        assert!(html.contains("Edit</label> <strong>(sensitive)</strong><br>Change your data"));
+
+        let error = ErrorInfo {
+            error: Some("invalid_request".into()),
+            ..ErrorInfo::new(400)
+        };
+        let rendered = Templates::new().render(&error);
+        match rendered {
+            Some(RenderedError::Html(html)) => assert!(html.contains("(invalid_request)")),
+            other => panic!("Expected an html page: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use url::Url;
+
+/// One isolated authorization server among many served by the same process.
+///
+/// The endpoint holds the primitives of the tenant, typically its own registrar and an issuer
+/// signing with its own key, so that clients and tokens of different tenants never mix.
+pub struct Tenant<E> {
+    /// Identifies the tenant, for example in logs or as the key of its database rows.
+    pub name: String,
+
+    /// The issuer identifier of the tenant, as published in its metadata and tokens.
+    pub issuer_url: Url,
+
+    /// The endpoint serving the flows of the tenant.
+    pub endpoint: E,
+}
+
+/// Selects the tenant serving a request by its host name or path prefix.
+///
+/// Frontends reconstruct the url of a request, for example with [`TrustedProxy::request_url`],
+/// and resolve the tenant before preparing a flow with its endpoint. Tenants routed by host take
+/// precedence over those routed by path, and the longest matching path prefix wins. Requests
+/// matching no tenant should be answered with `404 Not Found`.
+///
+/// [`TrustedProxy::request_url`]: struct.TrustedProxy.html#method.request_url
+pub struct TenantResolver<E> {
+    routes: Vec<(Route, Tenant<E>)>,
+}
+
+enum Route {
+    Host(String),
+    PathPrefix(String),
+}
+
+impl<E> TenantResolver<E> {
+    /// A resolver without any tenants.
+    pub fn new() -> Self {
+        TenantResolver { routes: Vec::new() }
+    }
+
+    /// Serve requests to a host name with the tenant.
+    ///
+    /// The host is compared case insensitively and without the port.
+    pub fn add_host(&mut self, host: &str, tenant: Tenant<E>) {
+        self.routes.push((Route::Host(host.to_ascii_lowercase()), tenant));
+    }
+
+    /// Serve requests below a path with the tenant.
+    ///
+    /// The prefix matches whole path segments, `/acme` matches `/acme/token` but not `/acmecorp`.
+    pub fn add_path_prefix(&mut self, prefix: &str, tenant: Tenant<E>) {
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        self.routes.push((Route::PathPrefix(prefix), tenant));
+    }
+
+    /// The tenant serving a request to the url.
+    pub fn resolve(&self, url: &Url) -> Option<&Tenant<E>> {
+        self.position(url).map(|index| &self.routes[index].1)
+    }
+
+    /// The tenant serving a request to the url, to prepare a flow with its endpoint.
+    pub fn resolve_mut(&mut self, url: &Url) -> Option<&mut Tenant<E>> {
+        self.position(url).map(move |index| &mut self.routes[index].1)
+    }
+
+    /// Iterate over all tenants, for example to expire their tokens.
+    pub fn tenants(&self) -> impl Iterator<Item = &Tenant<E>> {
+        self.routes.iter().map(|(_, tenant)| tenant)
+    }
+
+    fn position(&self, url: &Url) -> Option<usize> {
+        if let Some(host) = url.host_str() {
+            let by_host = self.routes.iter().position(|(route, _)| match route {
+                Route::Host(name) => name.eq_ignore_ascii_case(host),
+                Route::PathPrefix(_) => false,
+            });
+            if by_host.is_some() {
+                return by_host;
+            }
+        }
+
+        let path = url.path();
+        self.routes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (route, _))| match route {
+                Route::PathPrefix(prefix) if is_below(path, prefix) => Some((index, prefix.len())),
+                _ => None,
+            })
+            .max_by_key(|&(_, len)| len)
+            .map(|(index, _)| index)
+    }
+}
+
+impl<E> Default for TenantResolver<E> {
+    fn default() -> Self {
+        TenantResolver::new()
+    }
+}
+
+/// Whether the path is the prefix itself or lies below it.
+fn is_below(path: &str, prefix: &str) -> bool {
+    if prefix == "/" {
+        return true;
+    }
+
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(name: &str) -> Tenant<()> {
+        Tenant {
+            name: name.to_owned(),
+            issuer_url: format!("https://auth.example/{}", name).parse().unwrap(),
+            endpoint: (),
+        }
+    }
+
+    fn resolved(resolver: &TenantResolver<()>, url: &str) -> Option<String> {
+        resolver
+            .resolve(&url.parse().unwrap())
+            .map(|tenant| tenant.name.clone())
+    }
+
+    #[test]
+    fn resolves_hosts_and_prefixes() {
+        let mut resolver = TenantResolver::new();
+        resolver.add_host("Acme.Example", tenant("acme"));
+        resolver.add_path_prefix("/initech/", tenant("initech"));
+        resolver.add_path_prefix("/initech/eu", tenant("initech-eu"));
+
+        let name = resolved(&resolver, "https://acme.example:8443/initech/token");
+        assert_eq!(name.as_deref(), Some("acme"));
+        let name = resolved(&resolver, "https://auth.example/initech/token");
+        assert_eq!(name.as_deref(), Some("initech"));
+        let name = resolved(&resolver, "https://auth.example/initech/eu/token");
+        assert_eq!(name.as_deref(), Some("initech-eu"));
+        assert_eq!(resolved(&resolver, "https://auth.example/initechs/token"), None);
+        assert_eq!(resolved(&resolver, "https://other.example/token"), None);
+    }
+}
@@ -50,6 +50,25 @@
 //!
 //! For more information, see the documentation of [`endpoint`] and [`frontends`].
 //!
+//! ## Security profiles
+//!
+//! A [`Profile`] configures an endpoint for a security profile and refuses to build it while a
+//! prerequisite is missing. [`Profile::Fapi2`] accepts only pushed authorization requests from the
+//! store of [`primitives::pushed`], requires PKCE with `S256` and adds the issuer identifier to
+//! authorization responses as `iss`. Frontends validating DPoP proofs can additionally demand
+//! fresh nonces with [`require_dpop_nonce`]. The profile also requires a `SenderConstraint`, a
+//! binding policy tying access tokens to a DPoP key or a client certificate. The crate ships none
+//! yet and network bindings do not qualify, so building the preset fails with
+//! `ProfileError::MissingSenderConstraint` until the frontend provides one.
+//!
+//! ## Without `std`
+//!
+//! Gateways on embedded targets can check the tokens and scopes of requests with the same types as
+//! the server by disabling the default `std` feature. Only an allocator is needed for the
+//! remaining [`scope`], [`grant`] and [`generator`] primitives and the resource checks of
+//! [`code_grant::resource`]. The caller then provides the current time with [`Resource::at`] and
+//! the randomness of generated tokens and keys as a [`RandomSource`].
+//!
 //! [`WebRequest`]: code_grant/frontend/trait.WebRequest.html
 //! [`WebResponse`]: code_grant/frontend/trait.WebResponse.html
 //! [`endpoint`]: endpoint/index.html
@@ -63,9 +82,24 @@
 //! [`Issuer`]: primitives/issuer/trait.Issuer.html
 //! [`OwnerSolicitor`]: endpoint/trait.OwnerSolicitor.html
 //! [`Scopes`]: endpoint/trait.Scopes.html
+//! [`Profile`]: frontends/simple/profile/enum.Profile.html
+//! [`Profile::Fapi2`]: frontends/simple/profile/enum.Profile.html#variant.Fapi2
+//! [`primitives::pushed`]: primitives/pushed/index.html
+//! [`require_dpop_nonce`]: endpoint/fn.require_dpop_nonce.html
+//! [`scope`]: primitives/scope/index.html
+//! [`grant`]: primitives/grant/index.html
+//! [`generator`]: primitives/generator/index.html
+//! [`code_grant::resource`]: code_grant/resource/index.html
+//! [`Resource::at`]: code_grant/resource/struct.Resource.html#method.at
+//! [`RandomSource`]: primitives/generator/trait.RandomSource.html
 #![warn(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 pub mod code_grant;
+#[cfg(feature = "std")]
 pub mod endpoint;
+#[cfg(feature = "std")]
 pub mod frontends;
 pub mod primitives;
@@ -6,7 +6,10 @@
 //! side request, it will then check the given parameters to determine the authorization of such
 //! clients.
 use std::collections::HashMap;
-use std::sync::{MutexGuard, RwLockWriteGuard};
+use std::sync::{Arc, Mutex, MutexGuard, RwLockWriteGuard};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
 
 use super::grant::Grant;
 use super::generator::TagGrant;
@@ -22,6 +25,23 @@ pub trait Authorizer {
     /// particular, a code should not be usable twice (there is no stateless implementation of an
     /// authorizer for this reason).
     fn extract(&mut self, token: &str) -> Result<Option<Grant>, ()>;
+
+    /// Invalidate all codes issued to a client, returning how many there were.
+    ///
+    /// Used to contain a leaked client secret. The default implementation supports no bulk
+    /// revocation and fails.
+    fn revoke_client(&mut self, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
+
+    /// Invalidate the codes issued to a client on behalf of one owner, returning how many there
+    /// were.
+    ///
+    /// Used when an owner withdraws their authorization of the client. The default implementation
+    /// supports no bulk revocation and fails.
+    fn revoke_grant(&mut self, _owner_id: &str, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
 }
 
 /// An in-memory hash map.
@@ -35,6 +55,19 @@ pub struct AuthMap<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
     tokens: HashMap<String, Grant>,
 }
 
+/// The codes of an `AuthMap`, to persist them across restarts.
+///
+/// Created by `AuthMap::export` and restored with `AuthMap::import`. An `AuthMap` serializes as its
+/// snapshot, deserialize the snapshot to read it back since the tagger is not part of the state.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthMapSnapshot {
+    /// The number of codes the tagger was asked for.
+    pub usage: u64,
+
+    /// The grants by their code.
+    pub grants: HashMap<String, Grant>,
+}
+
 impl<I: TagGrant> AuthMap<I> {
     /// Create an authorizer generating tokens with the `tagger`.
     ///
@@ -49,6 +82,49 @@ impl<I: TagGrant> AuthMap<I> {
             tokens: HashMap::new(),
         }
     }
+
+    /// Store a grant under a code issued elsewhere, such as by another authorizer.
+    pub fn import_grant(&mut self, code: String, grant: Grant) {
+        self.tokens.insert(code, grant);
+    }
+
+    /// Iterate over all codes that have not been extracted yet, along with their grants.
+    pub fn grants(&self) -> impl Iterator<Item = (&str, &Grant)> {
+        self.tokens.iter().map(|(code, grant)| (code.as_str(), grant))
+    }
+
+    /// Copy all codes that have not been extracted yet.
+    pub fn export(&self) -> AuthMapSnapshot {
+        AuthMapSnapshot {
+            usage: self.usage,
+            grants: self.tokens.clone(),
+        }
+    }
+
+    /// Restore the codes of a snapshot, in addition to the current ones.
+    ///
+    /// Codes present in both are replaced by the snapshot. The usage counter continues from the
+    /// larger of both, so that a tagger depending on it does not repeat codes.
+    pub fn import(&mut self, snapshot: AuthMapSnapshot) {
+        self.usage = self.usage.max(snapshot.usage);
+        self.tokens.extend(snapshot.grants);
+    }
+
+    /// Remove the codes that expired at `now`, returning how many there were.
+    ///
+    /// Expired codes are refused when they are redeemed, but stay in memory until then or until
+    /// they are purged.
+    pub fn purge_expired(&mut self, now: DateTime<Utc>) -> usize {
+        let before = self.tokens.len();
+        self.tokens.retain(|_, grant| grant.until > now);
+        before - self.tokens.len()
+    }
+}
+
+impl<I: TagGrant> Serialize for AuthMap<I> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.export().serialize(serializer)
+    }
 }
 
 impl<A: Authorizer + ?Sized> Authorizer for &mut A {
@@ -59,6 +135,14 @@ impl<A: Authorizer + ?Sized> Authorizer for &mut A {
     fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
         (**self).extract(code)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
 }
 
 impl<A: Authorizer + ?Sized> Authorizer for Box<A> {
@@ -69,6 +153,14 @@ impl<A: Authorizer + ?Sized> Authorizer for Box<A> {
     fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
         (**self).extract(code)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
 }
 
 impl<'a, A: Authorizer + ?Sized> Authorizer for MutexGuard<'a, A> {
@@ -79,6 +171,14 @@ impl<'a, A: Authorizer + ?Sized> Authorizer for MutexGuard<'a, A> {
     fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
         (**self).extract(code)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
 }
 
 impl<'a, A: Authorizer + ?Sized> Authorizer for RwLockWriteGuard<'a, A> {
@@ -89,6 +189,36 @@ impl<'a, A: Authorizer + ?Sized> Authorizer for RwLockWriteGuard<'a, A> {
     fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
         (**self).extract(code)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
+}
+
+/// Shares an authorizer between threads, holding the lock only for each operation.
+///
+/// Each request can build its endpoint with a clone of the `Arc` instead of holding a guard for
+/// the whole flow. Extracting a code stays atomic, as it happens under a single lock.
+impl<A: Authorizer + ?Sized> Authorizer for Arc<Mutex<A>> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+        self.lock().map_err(|_| ())?.authorize(grant)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        self.lock().map_err(|_| ())?.extract(code)
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.lock().map_err(|_| ())?.revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.lock().map_err(|_| ())?.revoke_grant(owner_id, client_id)
+    }
 }
 
 impl<I: TagGrant> Authorizer for AuthMap<I> {
@@ -107,6 +237,134 @@ impl<I: TagGrant> Authorizer for AuthMap<I> {
     fn extract(&mut self, grant: &str) -> Result<Option<Grant>, ()> {
         Ok(self.tokens.remove(grant))
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        let before = self.tokens.len();
+        self.tokens.retain(|_, grant| grant.client_id != client_id);
+        Ok(before - self.tokens.len())
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        let before = self.tokens.len();
+        self.tokens
+            .retain(|_, grant| grant.owner_id != owner_id || grant.client_id != client_id);
+        Ok(before - self.tokens.len())
+    }
+}
+
+/// Ensures that each code is redeemed at most once, across all replicas of a server.
+///
+/// An authorizer that looks a code up and deletes it in two separate steps lets two replicas
+/// racing on the same code both obtain its grant. Wrapping such an authorizer in [`SingleUse`]
+/// claims every extracted code with a guard shared by all replicas, only the first claim wins.
+///
+/// [`SingleUse`]: struct.SingleUse.html
+pub trait SingleUseGuard {
+    /// Claim a code, returning `true` only for the first claim.
+    ///
+    /// The claim needs to be remembered at least `until` the code expires, later claims of the
+    /// same code are refused by the flow anyways.
+    fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError>;
+}
+
+/// A guard could not decide whether a code was claimed before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GuardError;
+
+/// Claims remembered in process memory.
+///
+/// Only guards the codes redeemed by a single process, for multiple replicas use a guard backed
+/// by a shared store instead. Expired claims are dropped as new ones arrive.
+#[derive(Debug, Default)]
+pub struct UsedCodes {
+    claims: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+/// An authorizer whose codes are claimed with a `SingleUseGuard` when extracted.
+///
+/// Extracting a code that was already claimed yields no grant, so the access token flow refuses
+/// the request as it would for an unknown code. A failing guard fails the extraction.
+pub struct SingleUse<A, G> {
+    authorizer: A,
+    guard: G,
+}
+
+impl UsedCodes {
+    /// Create a guard without any claims.
+    pub fn new() -> Self {
+        UsedCodes::default()
+    }
+}
+
+impl SingleUseGuard for UsedCodes {
+    fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        let mut claims = self.claims.lock().map_err(|_| GuardError)?;
+        let now = Utc::now();
+        claims.retain(|_, until| *until > now);
+        if claims.contains_key(code) {
+            return Ok(false);
+        }
+
+        claims.insert(code.to_owned(), until);
+        Ok(true)
+    }
+}
+
+impl<G: SingleUseGuard + ?Sized> SingleUseGuard for &G {
+    fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        (**self).claim(code, until)
+    }
+}
+
+impl<G: SingleUseGuard + ?Sized> SingleUseGuard for Box<G> {
+    fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        (**self).claim(code, until)
+    }
+}
+
+impl<G: SingleUseGuard + ?Sized> SingleUseGuard for Arc<G> {
+    fn claim(&self, code: &str, until: DateTime<Utc>) -> Result<bool, GuardError> {
+        (**self).claim(code, until)
+    }
+}
+
+impl<A: Authorizer, G: SingleUseGuard> SingleUse<A, G> {
+    /// Claim the codes extracted from `authorizer` with the `guard`.
+    pub fn new(authorizer: A, guard: G) -> Self {
+        SingleUse { authorizer, guard }
+    }
+
+    /// Unwrap the authorizer and the guard.
+    pub fn into_inner(self) -> (A, G) {
+        (self.authorizer, self.guard)
+    }
+}
+
+impl<A: Authorizer, G: SingleUseGuard> Authorizer for SingleUse<A, G> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+        self.authorizer.authorize(grant)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        let grant = match self.authorizer.extract(code)? {
+            Some(grant) => grant,
+            None => return Ok(None),
+        };
+
+        match self.guard.claim(code, grant.until) {
+            Ok(true) => Ok(Some(grant)),
+            Ok(false) => Ok(None),
+            Err(GuardError) => Err(()),
+        }
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.authorizer.revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.authorizer.revoke_grant(owner_id, client_id)
+    }
 }
 
 #[cfg(test)]
@@ -183,4 +441,64 @@ pub mod tests {
         let mut storage = AuthMap::new(BadGenerator);
         simple_test_suite(&mut storage);
     }
+
+    #[test]
+    fn single_use_test_suite() {
+        let mut storage = SingleUse::new(AuthMap::new(RandomGenerator::new(16)), UsedCodes::new());
+        simple_test_suite(&mut storage);
+    }
+
+    #[test]
+    fn single_use_replicas() {
+        // Two replicas of an authorizer that forgets to delete its codes.
+        struct Replica(Grant);
+        impl Authorizer for Replica {
+            fn authorize(&mut self, _: Grant) -> Result<String, ()> {
+                Ok("code".into())
+            }
+
+            fn extract(&mut self, _: &str) -> Result<Option<Grant>, ()> {
+                Ok(Some(self.0.clone()))
+            }
+        }
+
+        let grant = Grant {
+            owner_id: "Owner".to_string(),
+            client_id: "Client".to_string(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/redirect_me".parse().unwrap(),
+            until: Utc::now() + chrono::Duration::minutes(1),
+            extensions: Extensions::new(),
+        };
+
+        let guard = Arc::new(UsedCodes::new());
+        let mut first = SingleUse::new(Replica(grant.clone()), guard.clone());
+        let mut second = SingleUse::new(Replica(grant.clone()), guard);
+
+        assert_eq!(first.extract("code"), Ok(Some(grant)));
+        assert_eq!(second.extract("code"), Ok(None));
+        assert_eq!(first.extract("code"), Ok(None));
+        assert!(second.extract("other").unwrap().is_some());
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let mut storage = AuthMap::new(RandomGenerator::new(16));
+        let grant = Grant {
+            owner_id: "Owner".to_string(),
+            client_id: "Client".to_string(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com".parse().unwrap(),
+            until: Utc::now(),
+            extensions: Extensions::new(),
+        };
+        let code = storage.authorize(grant.clone()).unwrap();
+
+        let json = serde_json::to_string(&storage).unwrap();
+        let snapshot: AuthMapSnapshot = serde_json::from_str(&json).unwrap();
+
+        let mut restored = AuthMap::new(RandomGenerator::new(16));
+        restored.import(snapshot);
+        assert_eq!(restored.extract(&code), Ok(Some(grant)));
+    }
 }
@@ -0,0 +1,182 @@
+//! Additional claims of JWT access tokens and ID tokens.
+//!
+//! Deployments often need more in their tokens than the owner, client and scope of a grant: roles,
+//! a tenant id or entitlements of the owner. A [`ClaimsMapper`] provides these for a grant, and
+//! [`access_token_claims`] and [`id_token_claims`] combine them with the registered claims of the
+//! token before it is signed. The registered claims are always taken from the grant, a mapper can
+//! not replace them.
+//!
+//! ```
+//! use oxide_auth::primitives::claims::{access_token_claims, Claims, ClaimsMapper, TokenKind};
+//! # use oxide_auth::primitives::grant::{Extensions, Grant};
+//! # let grant = Grant {
+//! #     owner_id: "alice".into(),
+//! #     client_id: "client".into(),
+//! #     scope: "read".parse().unwrap(),
+//! #     redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+//! #     until: chrono::Utc::now(),
+//! #     extensions: Extensions::new(),
+//! # };
+//!
+//! let roles = |grant: &Grant, _: TokenKind| {
+//!     let mut claims = Claims::new();
+//!     if grant.owner_id == "alice" {
+//!         claims.insert("roles".into(), serde_json::json!(["admin"]));
+//!     }
+//!     Ok(claims)
+//! };
+//!
+//! let mapped = roles.claims(&grant, TokenKind::AccessToken).unwrap();
+//! let claims = access_token_claims(&grant, "https://as.example", "https://rs.example", mapped);
+//! assert_eq!(claims["roles"][0], "admin");
+//! ```
+//!
+//! [`ClaimsMapper`]: trait.ClaimsMapper.html
+//! [`access_token_claims`]: fn.access_token_claims.html
+//! [`id_token_claims`]: fn.id_token_claims.html
+use std::fmt;
+
+use chrono::Utc;
+use serde_json::Value;
+
+use super::grant::Grant;
+
+/// The claims of a token, as the members of its JSON payload.
+pub type Claims = serde_json::Map<String, Value>;
+
+/// The token that claims are mapped for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A JWT access token presented to resource servers.
+    AccessToken,
+
+    /// An OpenID Connect ID token presented to the client.
+    IdToken,
+}
+
+/// The claims of a grant could not be determined.
+///
+/// The token should then not be issued, rather than being issued without the claims.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClaimsError;
+
+/// Provides additional claims of the tokens issued for a grant.
+///
+/// Closures taking the grant and the kind of token are mappers as well.
+pub trait ClaimsMapper {
+    /// The claims to add to a token of `kind` for the grant.
+    ///
+    /// Claims named like a registered claim of the token are ignored.
+    fn claims(&self, grant: &Grant, kind: TokenKind) -> Result<Claims, ClaimsError>;
+}
+
+/// The claims an access token of [rfc9068] asserts about its grant.
+///
+/// These are `iss`, `sub`, `aud`, `client_id`, `scope`, `exp` and `iat`, followed by the `mapped`
+/// claims. Further registered claims such as a `jti` can be inserted into the result.
+///
+/// [rfc9068]: https://tools.ietf.org/html/rfc9068
+pub fn access_token_claims(grant: &Grant, issuer: &str, audience: &str, mapped: Claims) -> Claims {
+    let mut claims = Claims::new();
+    claims.insert("iss".into(), issuer.into());
+    claims.insert("sub".into(), grant.owner_id.as_str().into());
+    claims.insert("aud".into(), audience.into());
+    claims.insert("client_id".into(), grant.client_id.as_str().into());
+    claims.insert("scope".into(), grant.scope.to_string().into());
+    claims.insert("exp".into(), grant.until.timestamp().into());
+    claims.insert("iat".into(), Utc::now().timestamp().into());
+    extend(claims, mapped)
+}
+
+/// The claims an OpenID Connect ID token asserts about the authenticated owner.
+///
+/// These are `iss`, `sub`, `aud` naming the client, `exp`, `iat` and the `nonce` of the
+/// authentication request if it had one, followed by the `mapped` claims.
+pub fn id_token_claims(grant: &Grant, issuer: &str, nonce: Option<&str>, mapped: Claims) -> Claims {
+    let mut claims = Claims::new();
+    claims.insert("iss".into(), issuer.into());
+    claims.insert("sub".into(), grant.owner_id.as_str().into());
+    claims.insert("aud".into(), grant.client_id.as_str().into());
+    claims.insert("exp".into(), grant.until.timestamp().into());
+    claims.insert("iat".into(), Utc::now().timestamp().into());
+    if let Some(nonce) = nonce {
+        claims.insert("nonce".into(), nonce.into());
+    }
+    extend(claims, mapped)
+}
+
+/// Add the mapped claims not named like the registered ones.
+fn extend(mut claims: Claims, mapped: Claims) -> Claims {
+    for (name, value) in mapped {
+        claims.entry(name).or_insert(value);
+    }
+    claims
+}
+
+impl fmt::Display for ClaimsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the claims of the grant could not be determined")
+    }
+}
+
+impl std::error::Error for ClaimsError {}
+
+impl<F> ClaimsMapper for F
+where
+    F: Fn(&Grant, TokenKind) -> Result<Claims, ClaimsError>,
+{
+    fn claims(&self, grant: &Grant, kind: TokenKind) -> Result<Claims, ClaimsError> {
+        self(grant, kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::grant::Extensions;
+    use chrono::Duration;
+    use serde_json::json;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "alice".to_owned(),
+            client_id: "client".to_owned(),
+            scope: "read".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    fn tenant(grant: &Grant, kind: TokenKind) -> Result<Claims, ClaimsError> {
+        let mut claims = Claims::new();
+        claims.insert("tenant".into(), json!("acme"));
+        claims.insert("sub".into(), json!("mallory"));
+        if kind == TokenKind::IdToken {
+            claims.insert("name".into(), json!(grant.owner_id.to_uppercase()));
+        }
+        Ok(claims)
+    }
+
+    #[test]
+    fn mapped_claims_do_not_replace_registered() {
+        let grant = grant();
+        let mapper: Box<dyn ClaimsMapper> = Box::new(tenant);
+
+        let mapped = mapper.claims(&grant, TokenKind::AccessToken).unwrap();
+        let access = access_token_claims(&grant, "https://as.example", "https://rs.example", mapped);
+        assert_eq!(access["sub"], "alice");
+        assert_eq!(access["aud"], "https://rs.example");
+        assert_eq!(access["client_id"], "client");
+        assert_eq!(access["scope"], "read");
+        assert_eq!(access["tenant"], "acme");
+        assert!(access.get("name").is_none());
+
+        let mapped = mapper.claims(&grant, TokenKind::IdToken).unwrap();
+        let id = id_token_claims(&grant, "https://as.example", Some("n-0S6"), mapped);
+        assert_eq!(id["sub"], "alice");
+        assert_eq!(id["aud"], "client");
+        assert_eq!(id["nonce"], "n-0S6");
+        assert_eq!(id["name"], "ALICE");
+    }
+}
@@ -0,0 +1,202 @@
+//! Remembers the consent of resource owners to the grants of clients.
+//!
+//! Asking the owner to approve the same client every time it requests a code quickly becomes a
+//! nuisance. When the owner has chosen to remember a decision the authorization flow stores it in
+//! a [`ConsentStore`] and grants later requests of the client without a consent page, as long as
+//! they do not ask for more than the owner has approved. Owners should be able to review and
+//! withdraw their consent, which is what [`consents`] and [`revoke`] are for.
+//!
+//! [`ConsentStore`]: trait.ConsentStore.html
+//! [`consents`]: trait.ConsentStore.html#tymethod.consents
+//! [`revoke`]: trait.ConsentStore.html#tymethod.revoke
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use super::scope::Scope;
+
+/// The approval of a client by a resource owner, for some scope.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Consent {
+    /// The resource owner who gave consent.
+    pub owner_id: String,
+
+    /// The client that was approved.
+    pub client_id: String,
+
+    /// The scope the client may be granted without asking the owner again.
+    pub scope: Scope,
+}
+
+/// Stores the decisions of resource owners that they asked to be remembered.
+pub trait ConsentStore {
+    /// The scope an owner has approved for a client, if any.
+    ///
+    /// A store that can not answer, for example because its database is unreachable, should return
+    /// `None`. The owner is then simply asked again.
+    fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope>;
+
+    /// Remember the consent, extending any scope previously approved for the client.
+    fn remember(&mut self, consent: Consent);
+
+    /// All consents an owner has given, for example to list them on an account page.
+    fn consents(&self, owner_id: &str) -> Vec<Consent>;
+
+    /// Forget the consent of an owner to a client, returning whether there was one.
+    ///
+    /// This does not revoke tokens that have already been issued on the basis of the consent.
+    fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool;
+}
+
+/// An in-memory hash map of consents, keyed by owner and client.
+#[derive(Default)]
+pub struct ConsentMap {
+    owners: HashMap<String, HashMap<String, Scope>>,
+}
+
+impl ConsentMap {
+    /// An empty store.
+    pub fn new() -> Self {
+        ConsentMap::default()
+    }
+}
+
+impl ConsentStore for ConsentMap {
+    fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope> {
+        self.owners.get(owner_id)?.get(client_id).cloned()
+    }
+
+    fn remember(&mut self, consent: Consent) {
+        let clients = self.owners.entry(consent.owner_id).or_default();
+        let scope = match clients.remove(&consent.client_id) {
+            Some(previous) => previous.union(&consent.scope),
+            None => consent.scope,
+        };
+        clients.insert(consent.client_id, scope);
+    }
+
+    fn consents(&self, owner_id: &str) -> Vec<Consent> {
+        let clients = match self.owners.get(owner_id) {
+            Some(clients) => clients,
+            None => return Vec::new(),
+        };
+
+        clients
+            .iter()
+            .map(|(client_id, scope)| Consent {
+                owner_id: owner_id.to_owned(),
+                client_id: client_id.clone(),
+                scope: scope.clone(),
+            })
+            .collect()
+    }
+
+    fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool {
+        match self.owners.get_mut(owner_id) {
+            Some(clients) => clients.remove(client_id).is_some(),
+            None => false,
+        }
+    }
+}
+
+impl<S: ConsentStore + ?Sized> ConsentStore for &mut S {
+    fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope> {
+        (**self).recall(owner_id, client_id)
+    }
+
+    fn remember(&mut self, consent: Consent) {
+        (**self).remember(consent)
+    }
+
+    fn consents(&self, owner_id: &str) -> Vec<Consent> {
+        (**self).consents(owner_id)
+    }
+
+    fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool {
+        (**self).revoke(owner_id, client_id)
+    }
+}
+
+impl<S: ConsentStore + ?Sized> ConsentStore for Box<S> {
+    fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope> {
+        (**self).recall(owner_id, client_id)
+    }
+
+    fn remember(&mut self, consent: Consent) {
+        (**self).remember(consent)
+    }
+
+    fn consents(&self, owner_id: &str) -> Vec<Consent> {
+        (**self).consents(owner_id)
+    }
+
+    fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool {
+        (**self).revoke(owner_id, client_id)
+    }
+}
+
+impl<'a, S: ConsentStore + ?Sized> ConsentStore for MutexGuard<'a, S> {
+    fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope> {
+        (**self).recall(owner_id, client_id)
+    }
+
+    fn remember(&mut self, consent: Consent) {
+        (**self).remember(consent)
+    }
+
+    fn consents(&self, owner_id: &str) -> Vec<Consent> {
+        (**self).consents(owner_id)
+    }
+
+    fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool {
+        (**self).revoke(owner_id, client_id)
+    }
+}
+
+impl<'a, S: ConsentStore + ?Sized> ConsentStore for RwLockWriteGuard<'a, S> {
+    fn recall(&self, owner_id: &str, client_id: &str) -> Option<Scope> {
+        (**self).recall(owner_id, client_id)
+    }
+
+    fn remember(&mut self, consent: Consent) {
+        (**self).remember(consent)
+    }
+
+    fn consents(&self, owner_id: &str) -> Vec<Consent> {
+        (**self).consents(owner_id)
+    }
+
+    fn revoke(&mut self, owner_id: &str, client_id: &str) -> bool {
+        (**self).revoke(owner_id, client_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consent(client_id: &str, scope: &str) -> Consent {
+        Consent {
+            owner_id: "alice".to_owned(),
+            client_id: client_id.to_owned(),
+            scope: scope.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn remember_extends_and_revoke_forgets() {
+        let mut store = ConsentMap::new();
+        store.remember(consent("client", "read"));
+        store.remember(consent("client", "write"));
+        store.remember(consent("other", "read"));
+
+        let scope = store.recall("alice", "client").unwrap();
+        assert_eq!(scope, "read write".parse().unwrap());
+        assert_eq!(store.consents("alice").len(), 2);
+        assert!(store.consents("bob").is_empty());
+
+        assert!(store.revoke("alice", "client"));
+        assert!(!store.revoke("alice", "client"));
+        assert_eq!(store.recall("alice", "client"), None);
+        assert_eq!(store.consents("alice"), vec![consent("other", "read")]);
+    }
+}
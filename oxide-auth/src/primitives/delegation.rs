@@ -0,0 +1,225 @@
+//! Delegation chains, as described by the `act` claim of RFC 8693.
+//!
+//! A party acting on behalf of the owner of a grant, for example a service calling another one
+//! with a token it was given, is its actor. When that service delegates further, the chain grows:
+//! the current actor comes first and each earlier one is nested in the `act` claim of its
+//! successor. Audits need to see the whole chain, not only who presented the token last.
+//!
+//! A [`Delegation`] is kept with the [`Grant`] as a private extension, so that it is stored by the
+//! issuer together with the token and returned by the resource flow. Introspection responses
+//! publish it as the `act` claim. Chains are limited to a maximum depth, chosen by the caller
+//! delegating or reading them, so that a long chain of services can not launder a token
+//! indefinitely.
+//!
+//! This crate does not implement the token exchange grant itself. An extension grant handler
+//! creating the exchanged grant adds the chain with [`Delegation::delegate`] and [`attach`].
+//!
+//! [`Delegation`]: struct.Delegation.html
+//! [`Delegation::delegate`]: struct.Delegation.html#method.delegate
+//! [`attach`]: struct.Delegation.html#method.attach
+//! [`Grant`]: ../grant/struct.Grant.html
+use std::fmt;
+
+use serde_json::{Map, Value as JsonValue};
+
+use super::grant::{Grant, Value};
+
+/// The identifier of the grant extension holding the chain.
+const IDENTIFIER: &str = "act";
+
+/// A party acting on behalf of the owner of a grant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Actor {
+    /// The subject of the actor, its `sub` claim.
+    pub sub: String,
+
+    /// The client through which the actor acts, if any.
+    pub client_id: Option<String>,
+}
+
+/// The actors of a grant, the current one first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delegation {
+    actors: Vec<Actor>,
+}
+
+/// A delegation chain was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelegationError {
+    /// The claim or the stored extension is not a valid chain.
+    Malformed,
+
+    /// The chain has more actors than allowed.
+    TooDeep,
+}
+
+impl Actor {
+    /// An actor identified only by its subject.
+    pub fn new(sub: &str) -> Self {
+        Actor {
+            sub: sub.to_owned(),
+            client_id: None,
+        }
+    }
+
+    /// An actor acting through a client.
+    pub fn with_client(sub: &str, client_id: &str) -> Self {
+        Actor {
+            sub: sub.to_owned(),
+            client_id: Some(client_id.to_owned()),
+        }
+    }
+}
+
+impl Delegation {
+    /// A chain of a single actor.
+    pub fn new(actor: Actor) -> Self {
+        Delegation { actors: vec![actor] }
+    }
+
+    /// The chain with a new current actor, who acts on behalf of all previous ones.
+    ///
+    /// Fails with `TooDeep` if the chain would have more than `max_depth` actors.
+    pub fn delegate(&self, actor: Actor, max_depth: usize) -> Result<Self, DelegationError> {
+        if self.actors.len() >= max_depth {
+            return Err(DelegationError::TooDeep);
+        }
+
+        let mut actors = Vec::with_capacity(self.actors.len() + 1);
+        actors.push(actor);
+        actors.extend(self.actors.iter().cloned());
+        Ok(Delegation { actors })
+    }
+
+    /// The actors, the current one first.
+    pub fn actors(&self) -> &[Actor] {
+        &self.actors
+    }
+
+    /// The number of actors in the chain.
+    pub fn depth(&self) -> usize {
+        self.actors.len()
+    }
+
+    /// The chain as the nested value of an `act` claim.
+    pub fn to_claim(&self) -> JsonValue {
+        let mut claim = None;
+        for actor in self.actors.iter().rev() {
+            let mut object = Map::new();
+            object.insert("sub".into(), actor.sub.as_str().into());
+            if let Some(client_id) = &actor.client_id {
+                object.insert("client_id".into(), client_id.as_str().into());
+            }
+            if let Some(prior) = claim.take() {
+                object.insert("act".into(), prior);
+            }
+            claim = Some(JsonValue::Object(object));
+        }
+
+        claim.unwrap_or(JsonValue::Null)
+    }
+
+    /// Read the chain of an `act` claim, with at most `max_depth` actors.
+    pub fn from_claim(claim: &JsonValue, max_depth: usize) -> Result<Self, DelegationError> {
+        let mut actors = Vec::new();
+        let mut current = claim;
+        loop {
+            if actors.len() >= max_depth {
+                return Err(DelegationError::TooDeep);
+            }
+
+            let sub = current["sub"].as_str().ok_or(DelegationError::Malformed)?;
+            let client_id = match &current["client_id"] {
+                JsonValue::Null => None,
+                JsonValue::String(client_id) => Some(client_id.clone()),
+                _ => return Err(DelegationError::Malformed),
+            };
+            actors.push(Actor {
+                sub: sub.to_owned(),
+                client_id,
+            });
+
+            match &current["act"] {
+                JsonValue::Null => break,
+                prior => current = prior,
+            }
+        }
+
+        Ok(Delegation { actors })
+    }
+
+    /// The chain of a grant, if it has one.
+    ///
+    /// The stored chain is read with the depth it was attached with.
+    pub fn of(grant: &Grant) -> Option<Result<Self, DelegationError>> {
+        let (_, stored) = grant
+            .extensions
+            .private()
+            .find(|(identifier, _)| *identifier == IDENTIFIER)?;
+        let claim = stored
+            .and_then(|stored| serde_json::from_str(stored).ok())
+            .ok_or(DelegationError::Malformed);
+        Some(claim.and_then(|claim| Delegation::from_claim(&claim, usize::MAX)))
+    }
+
+    /// Keep the chain with the grant, replacing any previous one.
+    pub fn attach(&self, grant: &mut Grant) {
+        let stored = Value::private(Some(self.to_claim().to_string()));
+        grant.extensions.set_raw(IDENTIFIER.to_owned(), stored);
+    }
+}
+
+impl fmt::Display for DelegationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DelegationError::Malformed => f.write_str("malformed delegation chain"),
+            DelegationError::TooDeep => f.write_str("delegation chain exceeds its maximum depth"),
+        }
+    }
+}
+
+impl std::error::Error for DelegationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::grant::Extensions;
+    use chrono::Utc;
+    use serde_json::json;
+
+    #[test]
+    fn nested_claims() {
+        let chain = Delegation::new(Actor::with_client("gateway", "edge"))
+            .delegate(Actor::new("billing"), 3)
+            .unwrap();
+        let claim = chain.to_claim();
+        assert_eq!(
+            claim,
+            json!({ "sub": "billing", "act": { "sub": "gateway", "client_id": "edge" } })
+        );
+        assert_eq!(Delegation::from_claim(&claim, 3), Ok(chain.clone()));
+
+        assert_eq!(
+            chain.delegate(Actor::new("ledger"), 2),
+            Err(DelegationError::TooDeep)
+        );
+        assert_eq!(Delegation::from_claim(&claim, 1), Err(DelegationError::TooDeep));
+        assert_eq!(
+            Delegation::from_claim(&json!({ "act": { "sub": "gateway" } }), 3),
+            Err(DelegationError::Malformed)
+        );
+
+        let mut grant = Grant {
+            owner_id: "alice".into(),
+            client_id: "billing".into(),
+            scope: "invoices".parse().unwrap(),
+            redirect_uri: "https://billing.example/".parse().unwrap(),
+            until: Utc::now(),
+            extensions: Extensions::new(),
+        };
+        assert!(Delegation::of(&grant).is_none());
+        chain.attach(&mut grant);
+        assert_eq!(Delegation::of(&grant), Some(Ok(chain)));
+        assert_eq!(grant.extensions.public().count(), 0);
+    }
+}
@@ -5,7 +5,7 @@
 //!
 //! Two major implementation exists:
 //!     - `RandomGenerator` depends on the entropy of the generated token to make guessing
-//!     infeasible.
+//!     infeasible. The entropy is drawn from a `RandomSource`, the operating system by default.
 //!     - `Assertion` cryptographically verifies the integrity of a token, trading security without
 //!     persistent storage for the loss of revocability. It is thus unfit for some backends, which
 //!     is not currently expressed in the type system or with traits.
@@ -14,16 +14,22 @@ use super::grant::{Value, Extensions, Grant};
 use super::{Url, Time};
 use super::scope::Scope;
 
-use std::collections::HashMap;
-use std::rc::Rc;
-use std::sync::Arc;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
 
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
 use hmac::{digest::CtOutput, Mac, Hmac};
-use rand::{rngs::OsRng, RngCore, thread_rng};
-use serde::{Deserialize, Serialize};
-use rmp_serde;
+#[cfg(feature = "std")]
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+use zeroize::Zeroizing;
 
 /// Generic token for a specific grant.
 ///
@@ -46,31 +52,117 @@ pub trait TagGrant {
     fn tag(&mut self, usage: u64, grant: &Grant) -> Result<String, ()>;
 }
 
+/// A source of cryptographically secure random bytes.
+///
+/// The operating system provides the default source, `OsRandom`. Deployments can route the
+/// generation of tokens and keys through a hardware security module or a validated DRBG by
+/// implementing this trait. Any `CryptoRng` behind a `Mutex` is a source as well, for example a
+/// seeded `rand::rngs::StdRng` producing reproducible tokens in tests.
+pub trait RandomSource {
+    /// Fill `dest` entirely with random bytes.
+    fn fill(&self, dest: &mut [u8]) -> Result<(), RandomError>;
+}
+
+/// A random source failed to provide bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RandomError;
+
+/// Random bytes from the operating system.
+///
+/// Only a `RandomSource` with the `std` feature.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsRandom;
+
+/// Signs the data of tokens.
+///
+/// `Assertion` signs with a key held in process memory. Implement this trait on top of the client
+/// of a KMS or an HSM to keep the key outside the process instead. Services reached over the
+/// network are better served by the asynchronous `Signer` of `oxide-auth-async`.
+pub trait Signer {
+    /// Sign the data.
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError>;
+}
+
+/// Verifies the signatures of a `Signer`.
+pub trait Verifier {
+    /// Check that `signature` is a valid signature of `data`.
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError>;
+}
+
+/// Signing data or verifying a signature failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignError;
+
+/// The signed data of a grant, in the token format of `Assertion`.
+///
+/// Encoding is separate from signing, so that the tokens of all signers share one format.
+pub struct SignedGrant {
+    data: Vec<u8>,
+}
+
 /// Generates tokens from random bytes.
 ///
-/// Each byte is chosen randomly from the basic `rand::thread_rng`. This generator will always
-/// succeed.
-pub struct RandomGenerator {
-    random: OsRng,
+/// Each byte is chosen randomly from a `RandomSource`, by default the operating system. Generating
+/// a token only fails if the source fails.
+pub struct RandomGenerator<S = OsRandom> {
+    random: S,
     len: usize,
 }
 
+#[cfg(feature = "std")]
+impl RandomSource for OsRandom {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), RandomError> {
+        OsRng.try_fill_bytes(dest).map_err(|_| RandomError)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: RngCore + CryptoRng> RandomSource for Mutex<R> {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), RandomError> {
+        let mut rng = self.lock().map_err(|_| RandomError)?;
+        rng.try_fill_bytes(dest).map_err(|_| RandomError)
+    }
+}
+
+impl<S: RandomSource + ?Sized> RandomSource for &S {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), RandomError> {
+        (**self).fill(dest)
+    }
+}
+
+impl<S: RandomSource + ?Sized> RandomSource for Box<S> {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), RandomError> {
+        (**self).fill(dest)
+    }
+}
+
+impl<S: RandomSource + ?Sized> RandomSource for Arc<S> {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), RandomError> {
+        (**self).fill(dest)
+    }
+}
+
+#[cfg(feature = "std")]
 impl RandomGenerator {
     /// Generates tokens with a specific byte length.
     pub fn new(length: usize) -> RandomGenerator {
+        RandomGenerator::with_source(length, OsRandom)
+    }
+}
+
+impl<S: RandomSource> RandomGenerator<S> {
+    /// Generates tokens with a specific byte length, from the bytes of another source.
+    pub fn with_source(length: usize, source: S) -> Self {
         RandomGenerator {
-            random: OsRng {},
+            random: source,
             len: length,
         }
     }
 
-    fn generate(&self) -> String {
-        let mut result = vec![0; self.len];
-        let mut rnd = self.random;
-        rnd.try_fill_bytes(result.as_mut_slice())
-            .expect("Failed to generate random token");
-
-        STANDARD.encode(result)
+    fn generate(&self) -> Result<String, ()> {
+        let mut result = Zeroizing::new(vec![0; self.len]);
+        self.random.fill(result.as_mut_slice()).map_err(|_| ())?;
+        Ok(STANDARD.encode(result.as_slice()))
     }
 }
 
@@ -83,8 +175,10 @@ impl RandomGenerator {
 /// The actual generator is given by a `TaggedAssertion` from `Assertion::tag` which enables
 /// signing the same grant for different uses, i.e. separating authorization from bearer grants and
 /// refresh tokens.
+///
+/// The key is wiped from memory when the assertion is dropped.
 pub struct Assertion {
-    hasher: Hmac<sha2::Sha256>,
+    key: Zeroizing<Vec<u8>>,
 }
 
 /// The cryptographic suite ensuring integrity of tokens.
@@ -97,8 +191,8 @@ pub enum AssertionKind {
     HmacSha256,
 }
 
-#[derive(Serialize, Deserialize)]
-struct SerdeAssertionGrant {
+/// The parts of a grant encoded into the tokens of an `Assertion`.
+struct AssertionGrant {
     /// Identifies the owner of the resource.
     owner_id: String,
 
@@ -106,27 +200,21 @@ struct SerdeAssertionGrant {
     client_id: String,
 
     /// The scope granted to the client.
-    #[serde(with = "scope_serde")]
     scope: Scope,
 
     /// The redirection uri under which the client resides. The url package does indeed seem to
     /// parse valid URIs as well.
-    #[serde(with = "url_serde")]
     redirect_uri: Url,
 
     /// Expiration date of the grant (Utc).
-    #[serde(with = "time_serde")]
     until: Time,
 
     /// The public extensions, private extensions not supported currently
-    public_extensions: HashMap<String, Option<String>>,
+    public_extensions: BTreeMap<String, Option<String>>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct AssertGrant(Vec<u8>, Vec<u8>);
-
 /// Binds a tag to the data. The signature will be unique for data as well as the tag.
-pub struct TaggedAssertion<'a>(&'a Assertion, &'a str);
+pub struct TaggedAssertion<'a, S: ?Sized = Assertion>(&'a S, &'a str);
 
 impl Assertion {
     /// Construct an assertion from a custom secret.
@@ -143,19 +231,24 @@ impl Assertion {
     pub fn new(kind: AssertionKind, key: &[u8]) -> Self {
         match kind {
             AssertionKind::HmacSha256 => Assertion {
-                hasher: Hmac::<sha2::Sha256>::new_from_slice(key).unwrap(),
+                key: Zeroizing::new(key.to_vec()),
             },
         }
     }
 
     /// Construct an assertion instance whose tokens are only valid for the program execution.
+    #[cfg(feature = "std")]
     pub fn ephemeral() -> Self {
+        Assertion::ephemeral_from(&OsRandom).expect("Failed to generate assertion key")
+    }
+
+    /// Construct an assertion instance with a key drawn from a random source, whose tokens are
+    /// only valid for the program execution.
+    pub fn ephemeral_from<S: RandomSource + ?Sized>(source: &S) -> Result<Self, RandomError> {
         // TODO Extract KeySize from currently selected hasher
-        let mut rand_bytes: [u8; 32] = [0; 32];
-        thread_rng().fill_bytes(&mut rand_bytes);
-        Assertion {
-            hasher: Hmac::<sha2::Sha256>::new_from_slice(&rand_bytes).unwrap(),
-        }
+        let mut key = Zeroizing::new(vec![0; 32]);
+        source.fill(key.as_mut_slice())?;
+        Ok(Assertion { key })
     }
 
     /// Get a reference to generator for the given tag.
@@ -163,44 +256,119 @@ impl Assertion {
         TaggedAssertion(self, tag)
     }
 
-    fn extract(&self, token: &str) -> Result<(Grant, String), ()> {
-        let decoded = STANDARD.decode(token).map_err(|_| ())?;
-        let assertion: AssertGrant = rmp_serde::from_slice(&decoded).map_err(|_| ())?;
-
-        let mut hasher = self.hasher.clone();
-        hasher.update(&assertion.0);
-        hasher.verify_slice(assertion.1.as_slice()).map_err(|_| ())?;
-
-        let (_, serde_grant, tag): (u64, SerdeAssertionGrant, String) =
-            rmp_serde::from_slice(&assertion.0).map_err(|_| ())?;
-
-        Ok((serde_grant.grant(), tag))
+    fn hasher(&self) -> Hmac<sha2::Sha256> {
+        // Hmac accepts keys of any length.
+        Hmac::<sha2::Sha256>::new_from_slice(&self.key).unwrap()
     }
 
     fn signature(&self, data: &[u8]) -> CtOutput<hmac::Hmac<sha2::Sha256>> {
-        let mut hasher = self.hasher.clone();
+        let mut hasher = self.hasher();
         hasher.update(data);
         hasher.finalize()
     }
 
     fn counted_signature(&self, counter: u64, grant: &Grant) -> Result<String, ()> {
-        let serde_grant = SerdeAssertionGrant::try_from(grant)?;
-        let tosign = rmp_serde::to_vec(&(serde_grant, counter)).unwrap();
+        let assertion_grant = AssertionGrant::try_from(grant)?;
+        let tosign = encoding::counted(&assertion_grant, counter).map_err(|_| ())?;
         let signature = self.signature(&tosign);
         Ok(STANDARD.encode(signature.into_bytes()))
     }
+}
 
-    fn generate_tagged(&self, counter: u64, grant: &Grant, tag: &str) -> Result<String, ()> {
-        let serde_grant = SerdeAssertionGrant::try_from(grant)?;
-        let tosign = rmp_serde::to_vec(&(counter, serde_grant, tag)).unwrap();
-        let signature = self.signature(&tosign);
-        let assert = AssertGrant(tosign, signature.into_bytes().to_vec());
+impl SignedGrant {
+    /// The data to sign for a grant, with a usage tag and a counter unique to the token.
+    ///
+    /// Fails if the grant has private extensions, as the data is not encrypted.
+    pub fn new(counter: u64, grant: &Grant, tag: &str) -> Result<Self, SignError> {
+        let assertion_grant = AssertionGrant::try_from(grant).map_err(|()| SignError)?;
+        let data = encoding::signed(counter, &assertion_grant, tag)?;
+        Ok(SignedGrant { data })
+    }
+
+    /// Split a token into the signed data and the signature.
+    pub fn from_token(token: &str) -> Result<(Self, Vec<u8>), SignError> {
+        let decoded = STANDARD.decode(token).map_err(|_| SignError)?;
+        let (data, signature) = encoding::read_token(&decoded)?;
+        Ok((SignedGrant { data }, signature))
+    }
+
+    /// The bytes to sign.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The token made of the data and its signature.
+    pub fn into_token(self, signature: Vec<u8>) -> String {
+        STANDARD.encode(encoding::token(&self.data, &signature))
+    }
+
+    /// The grant and the usage tag within the data.
+    ///
+    /// These are only to be trusted after the signature has been verified.
+    pub fn grant(&self) -> Result<(Grant, String), SignError> {
+        let (assertion_grant, tag) = encoding::read_signed(&self.data)?;
+        Ok((assertion_grant.grant(), tag))
+    }
+}
+
+impl Signer for Assertion {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        Ok(self.signature(data).into_bytes().to_vec())
+    }
+}
+
+impl Verifier for Assertion {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.verify_slice(signature).map_err(|_| SignError)
+    }
+}
+
+impl<S: Signer + ?Sized> Signer for &S {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        (**self).sign(data)
+    }
+}
+
+impl<S: Signer + ?Sized> Signer for Box<S> {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        (**self).sign(data)
+    }
+}
+
+impl<S: Signer + ?Sized> Signer for Arc<S> {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        (**self).sign(data)
+    }
+}
+
+impl<V: Verifier + ?Sized> Verifier for &V {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError> {
+        (**self).verify(data, signature)
+    }
+}
+
+impl<V: Verifier + ?Sized> Verifier for Box<V> {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError> {
+        (**self).verify(data, signature)
+    }
+}
 
-        Ok(STANDARD.encode(rmp_serde::to_vec(&assert).unwrap()))
+impl<V: Verifier + ?Sized> Verifier for Arc<V> {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), SignError> {
+        (**self).verify(data, signature)
     }
 }
 
-impl<'a> TaggedAssertion<'a> {
+impl<'a, S: ?Sized> TaggedAssertion<'a, S> {
+    /// Sign for the given tag with any signer.
+    pub fn new(signer: &'a S, tag: &'a str) -> Self {
+        TaggedAssertion(signer, tag)
+    }
+}
+
+impl<'a, S: Signer + Verifier + ?Sized> TaggedAssertion<'a, S> {
     /// Sign the grant for this usage.
     ///
     /// This commits to a token that can be used–according to the usage tag–while the endpoint can
@@ -209,7 +377,9 @@ impl<'a> TaggedAssertion<'a> {
     /// grant (which may have multiple tokens). Note that the `tag` will be recovered and checked
     /// while the IV will not.
     pub fn sign(&self, counter: u64, grant: &Grant) -> Result<String, ()> {
-        self.0.generate_tagged(counter, grant, self.1)
+        let signed = SignedGrant::new(counter, grant, self.1).map_err(|_| ())?;
+        let signature = self.0.sign(signed.data()).map_err(|_| ())?;
+        Ok(signed.into_token(signature))
     }
 
     /// Inverse operation of generate, retrieve the underlying token.
@@ -217,9 +387,14 @@ impl<'a> TaggedAssertion<'a> {
     /// Result in an Err if either the signature is invalid or if the tag does not match the
     /// expected usage tag given to this assertion.
     pub fn extract(&self, token: &str) -> Result<Grant, ()> {
-        self.0
-            .extract(token)
-            .and_then(|(token, tag)| if tag == self.1 { Ok(token) } else { Err(()) })
+        let (signed, signature) = SignedGrant::from_token(token).map_err(|_| ())?;
+        self.0.verify(signed.data(), &signature).map_err(|_| ())?;
+        let (grant, tag) = signed.grant().map_err(|_| ())?;
+        if tag == self.1 {
+            Ok(grant)
+        } else {
+            Err(())
+        }
     }
 }
 
@@ -235,27 +410,27 @@ impl<'a, T: TagGrant + ?Sized + 'a> TagGrant for &'a mut T {
     }
 }
 
-impl TagGrant for RandomGenerator {
+impl<S: RandomSource> TagGrant for RandomGenerator<S> {
     fn tag(&mut self, _: u64, _: &Grant) -> Result<String, ()> {
-        Ok(self.generate())
+        self.generate()
     }
 }
 
-impl TagGrant for &RandomGenerator {
+impl<S: RandomSource> TagGrant for &RandomGenerator<S> {
     fn tag(&mut self, _: u64, _: &Grant) -> Result<String, ()> {
-        Ok(self.generate())
+        self.generate()
     }
 }
 
-impl TagGrant for Rc<RandomGenerator> {
+impl<S: RandomSource> TagGrant for Rc<RandomGenerator<S>> {
     fn tag(&mut self, _: u64, _: &Grant) -> Result<String, ()> {
-        Ok(self.generate())
+        self.generate()
     }
 }
 
-impl TagGrant for Arc<RandomGenerator> {
+impl<S: RandomSource> TagGrant for Arc<RandomGenerator<S>> {
     fn tag(&mut self, _: u64, _: &Grant) -> Result<String, ()> {
-        Ok(self.generate())
+        self.generate()
     }
 }
 
@@ -283,58 +458,9 @@ impl TagGrant for Arc<Assertion> {
     }
 }
 
-mod scope_serde {
-    use crate::primitives::scope::Scope;
-
-    use serde::ser::{Serializer};
-    use serde::de::{Deserialize, Deserializer, Error};
-
-    pub fn serialize<S: Serializer>(scope: &Scope, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_str(&scope.to_string())
-    }
-
-    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Scope, D::Error> {
-        let as_string: &str = <&str>::deserialize(deserializer)?;
-        as_string.parse().map_err(Error::custom)
-    }
-}
-
-mod url_serde {
-    use super::Url;
-
-    use serde::ser::{Serializer};
-    use serde::de::{Deserialize, Deserializer, Error};
-
-    pub fn serialize<S: Serializer>(url: &Url, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_str(url.as_ref())
-    }
-
-    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Url, D::Error> {
-        let as_string: &str = <&str>::deserialize(deserializer)?;
-        as_string.parse().map_err(Error::custom)
-    }
-}
-
-mod time_serde {
-    use super::Time;
-    use chrono::{TimeZone, Utc};
-
-    use serde::ser::{Serializer};
-    use serde::de::{Deserialize, Deserializer};
-
-    pub fn serialize<S: Serializer>(time: &Time, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_i64(time.timestamp())
-    }
-
-    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Time, D::Error> {
-        let as_timestamp: i64 = <i64>::deserialize(deserializer)?;
-        Ok(Utc.timestamp_opt(as_timestamp, 0).unwrap())
-    }
-}
-
-impl SerdeAssertionGrant {
+impl AssertionGrant {
     fn try_from(grant: &Grant) -> Result<Self, ()> {
-        let mut public_extensions: HashMap<String, Option<String>> = HashMap::new();
+        let mut public_extensions = BTreeMap::new();
 
         if grant.extensions.private().any(|_| true) {
             return Err(());
@@ -344,7 +470,7 @@ impl SerdeAssertionGrant {
             public_extensions.insert(name.to_string(), content.map(str::to_string));
         }
 
-        Ok(SerdeAssertionGrant {
+        Ok(AssertionGrant {
             owner_id: grant.owner_id.clone(),
             client_id: grant.client_id.clone(),
             scope: grant.scope.clone(),
@@ -370,6 +496,163 @@ impl SerdeAssertionGrant {
     }
 }
 
+/// The MessagePack encoding of tokens, identical to the one of the former serde representation.
+///
+/// Written without serde so that the format does not need `std`. Integers are always written in
+/// their shortest form but read from any.
+mod encoding {
+    use super::{AssertionGrant, SignError};
+
+    use core::convert::TryFrom;
+    use alloc::collections::BTreeMap;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    use chrono::{TimeZone, Utc};
+    use rmp::{decode, encode, Marker};
+
+    /// The data signed by a `SignedGrant`: the counter, the grant and the usage tag.
+    pub fn signed(counter: u64, grant: &AssertionGrant, tag: &str) -> Result<Vec<u8>, SignError> {
+        let mut buf = Vec::new();
+        encode::write_array_len(&mut buf, 3).map_err(fail)?;
+        encode::write_uint(&mut buf, counter).map_err(fail)?;
+        write_grant(&mut buf, grant)?;
+        write_str(&mut buf, tag)?;
+        Ok(buf)
+    }
+
+    /// The inverse of `signed`, dropping the counter.
+    pub fn read_signed(mut data: &[u8]) -> Result<(AssertionGrant, String), SignError> {
+        let rd = &mut data;
+        read_array_len(rd, 3)?;
+        decode::read_int::<u64, _>(rd).map_err(fail)?;
+        let grant = read_grant(rd)?;
+        let tag = read_str(rd)?.to_string();
+        Ok((grant, tag))
+    }
+
+    /// The data tagged by an `Assertion` as a `TagGrant`.
+    pub fn counted(grant: &AssertionGrant, counter: u64) -> Result<Vec<u8>, SignError> {
+        let mut buf = Vec::new();
+        encode::write_array_len(&mut buf, 2).map_err(fail)?;
+        write_grant(&mut buf, grant)?;
+        encode::write_uint(&mut buf, counter).map_err(fail)?;
+        Ok(buf)
+    }
+
+    /// The signed data and its signature, before the base64 encoding of the token.
+    pub fn token(data: &[u8], signature: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        // Writing to a vector does not fail.
+        let _ = encode::write_array_len(&mut buf, 2);
+        for bytes in [data, signature] {
+            let _ = encode::write_array_len(&mut buf, bytes.len() as u32);
+            for &byte in bytes {
+                let _ = encode::write_uint(&mut buf, byte.into());
+            }
+        }
+        buf
+    }
+
+    /// The inverse of `token`.
+    pub fn read_token(mut token: &[u8]) -> Result<(Vec<u8>, Vec<u8>), SignError> {
+        let rd = &mut token;
+        read_array_len(rd, 2)?;
+        let data = read_bytes(rd)?;
+        let signature = read_bytes(rd)?;
+        Ok((data, signature))
+    }
+
+    fn write_grant(buf: &mut Vec<u8>, grant: &AssertionGrant) -> Result<(), SignError> {
+        encode::write_array_len(buf, 6).map_err(fail)?;
+        write_str(buf, &grant.owner_id)?;
+        write_str(buf, &grant.client_id)?;
+        write_str(buf, &grant.scope.to_string())?;
+        write_str(buf, grant.redirect_uri.as_str())?;
+        encode::write_sint(buf, grant.until.timestamp()).map_err(fail)?;
+
+        let len = u32::try_from(grant.public_extensions.len()).map_err(fail)?;
+        encode::write_map_len(buf, len).map_err(fail)?;
+        for (name, content) in &grant.public_extensions {
+            write_str(buf, name)?;
+            match content {
+                Some(content) => write_str(buf, content)?,
+                None => encode::write_nil(buf).map_err(fail)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_grant(rd: &mut &[u8]) -> Result<AssertionGrant, SignError> {
+        read_array_len(rd, 6)?;
+        let owner_id = read_str(rd)?.to_string();
+        let client_id = read_str(rd)?.to_string();
+        let scope = read_str(rd)?.parse().map_err(fail)?;
+        let redirect_uri = read_str(rd)?.parse().map_err(fail)?;
+        let timestamp = decode::read_int::<i64, _>(rd).map_err(fail)?;
+        let until = Utc.timestamp_opt(timestamp, 0).single().ok_or(SignError)?;
+
+        let mut public_extensions = BTreeMap::new();
+        for _ in 0..decode::read_map_len(rd).map_err(fail)? {
+            let name = read_str(rd)?.to_string();
+            let content = match rd.split_first() {
+                Some((&marker, rest)) if marker == Marker::Null.to_u8() => {
+                    *rd = rest;
+                    None
+                }
+                _ => Some(read_str(rd)?.to_string()),
+            };
+            public_extensions.insert(name, content);
+        }
+
+        Ok(AssertionGrant {
+            owner_id,
+            client_id,
+            scope,
+            redirect_uri,
+            until,
+            public_extensions,
+        })
+    }
+
+    fn write_str(buf: &mut Vec<u8>, string: &str) -> Result<(), SignError> {
+        encode::write_str(buf, string).map_err(fail)
+    }
+
+    fn read_str<'a>(rd: &mut &'a [u8]) -> Result<&'a str, SignError> {
+        let len = decode::read_str_len(rd).map_err(fail)? as usize;
+        if rd.len() < len {
+            return Err(SignError);
+        }
+
+        let (string, rest) = rd.split_at(len);
+        *rd = rest;
+        core::str::from_utf8(string).map_err(fail)
+    }
+
+    fn read_bytes(rd: &mut &[u8]) -> Result<Vec<u8>, SignError> {
+        let len = decode::read_array_len(rd).map_err(fail)?;
+        // Each byte takes at least one byte of the input, do not trust the length any further.
+        let mut bytes = Vec::with_capacity(rd.len().min(len as usize));
+        for _ in 0..len {
+            bytes.push(decode::read_int(rd).map_err(fail)?);
+        }
+        Ok(bytes)
+    }
+
+    fn read_array_len(rd: &mut &[u8], expected: u32) -> Result<(), SignError> {
+        match decode::read_array_len(rd).map_err(fail)? {
+            len if len == expected => Ok(()),
+            _ => Err(SignError),
+        }
+    }
+
+    fn fail<E>(_: E) -> SignError {
+        SignError
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -382,4 +665,95 @@ mod tests {
         let fake_key = [0u8; 16];
         uses(Assertion::new(AssertionKind::HmacSha256, &fake_key));
     }
+
+    #[test]
+    fn seeded_source() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let grant = Grant {
+            owner_id: "owner".into(),
+            client_id: "client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: chrono::Utc::now(),
+            extensions: Extensions::new(),
+        };
+
+        let mut first = RandomGenerator::with_source(16, Mutex::new(StdRng::seed_from_u64(7)));
+        let mut second = RandomGenerator::with_source(16, Mutex::new(StdRng::seed_from_u64(7)));
+        let token = first.tag(0, &grant).unwrap();
+        assert_eq!(token, second.tag(0, &grant).unwrap());
+        assert_ne!(token, first.tag(1, &grant).unwrap());
+    }
+
+    #[test]
+    fn token_format() {
+        use chrono::TimeZone;
+        use serde::{Deserialize, Serialize};
+
+        // The serde representation of tokens before, written with `rmp_serde`.
+        #[derive(Serialize, Deserialize)]
+        struct SerdeAssertionGrant(
+            String,
+            String,
+            String,
+            String,
+            i64,
+            BTreeMap<String, Option<String>>,
+        );
+
+        let mut extensions = Extensions::new();
+        extensions.set_raw("public".into(), Value::public(Some("content".into())));
+        extensions.set_raw("presence".into(), Value::public(None));
+        let grant = Grant {
+            owner_id: "owner".into(),
+            client_id: "client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            extensions,
+        };
+        let serde_grant = || {
+            let public = [("presence", None), ("public", Some("content".to_string()))];
+            SerdeAssertionGrant(
+                "owner".into(),
+                "client".into(),
+                "default".into(),
+                "https://client.example/endpoint".into(),
+                1_700_000_000,
+                public.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
+            )
+        };
+
+        let counter = 1 << 40;
+        let signed = SignedGrant::new(counter, &grant, "token").unwrap();
+        let expected = rmp_serde::to_vec(&(counter, serde_grant(), "token")).unwrap();
+        assert_eq!(signed.data(), &expected[..]);
+
+        let signature = vec![0, 127, 128, 255];
+        let token = SignedGrant::new(counter, &grant, "token")
+            .unwrap()
+            .into_token(signature.clone());
+        let serde_token = rmp_serde::to_vec(&(expected, signature.clone())).unwrap();
+        assert_eq!(token, STANDARD.encode(&serde_token));
+
+        let (recovered, recovered_signature) = SignedGrant::from_token(&token).unwrap();
+        assert_eq!(recovered_signature, signature);
+        assert_eq!(recovered.grant().unwrap(), (grant.clone(), "token".to_string()));
+
+        let mut assertion = Assertion::new(AssertionKind::HmacSha256, b"key");
+        let counted = rmp_serde::to_vec(&(serde_grant(), counter)).unwrap();
+        let expected = STANDARD.encode(assertion.sign(&counted).unwrap());
+        assert_eq!(TagGrant::tag(&mut assertion, counter, &grant).unwrap(), expected);
+    }
+
+    #[test]
+    fn malformed_tokens() {
+        assert!(SignedGrant::from_token("").is_err());
+        assert!(
+            SignedGrant::from_token(&STANDARD.encode([0x92, 0xdd, 0xff, 0xff, 0xff, 0xff])).is_err()
+        );
+        let (data, _) = SignedGrant::from_token(&STANDARD.encode([0x92, 0x91, 0x01, 0x90])).unwrap();
+        assert!(data.grant().is_err());
+    }
 }
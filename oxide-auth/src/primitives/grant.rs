@@ -2,11 +2,15 @@
 use super::{Url, Time};
 use super::scope::Scope;
 
-use std::borrow::{Cow, ToOwned};
-use std::collections::HashMap;
-use std::collections::hash_map::Iter;
-use std::rc::Rc;
-use std::sync::Arc;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::collections::btree_map::Iter;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+
+use serde::{Deserialize, Serialize};
 
 /// Provides a name registry for extensions.
 pub trait GrantExtension {
@@ -24,7 +28,8 @@ pub trait GrantExtension {
 ///
 /// Some extensions have semantics where the presence alone is the stored data, so storing data
 /// is optional and storing no data is distinct from not attaching any extension instance at all.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Value {
     /// An extension that the token owner is allowed to read and interpret.
     Public(Option<String>),
@@ -39,16 +44,24 @@ pub enum Value {
 ///
 /// This also serves as a clean interface for both frontend and backend to reliably and
 /// conveniently manipulate or query the stored data sets.
-#[derive(Clone, Default, Debug, PartialEq, Eq)]
+///
+/// Serialized as a map from the identifier to the value of each extension. Private extensions are
+/// included in plain text, so that they are not lost when the state of a server is persisted.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Extensions {
-    extensions: HashMap<String, Value>,
+    extensions: BTreeMap<String, Value>,
 }
 
 /// Owning copy of a grant.
 ///
 /// This can be stored in a database without worrying about lifetimes or shared across thread
 /// boundaries. A reference to this can be converted to a purely referential `GrantRef`.
-#[derive(Clone, Debug, PartialEq, Eq)]
+///
+/// The serialized form contains all extensions, including private ones, and must be stored as
+/// confidentially as the tokens referring to the grant. Self-encoded tokens use the `TokenSigner`
+/// instead, which refuses to encode private extensions.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Grant {
     /// Identifies the owner of the resource.
     pub owner_id: String,
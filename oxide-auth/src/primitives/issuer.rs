@@ -4,15 +4,18 @@
 //! renewed. There exist two fundamental implementation as well, one utilizing in memory hash maps
 //! while the other uses cryptographic signing.
 use std::collections::HashMap;
-use std::sync::{Arc, MutexGuard, RwLockWriteGuard};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, MutexGuard, RwLock, RwLockWriteGuard};
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 
-use chrono::{Duration, Utc};
+use chrono::{Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use zeroize::Zeroize;
 
-use crate::{endpoint::PreGrant, code_grant::accesstoken::BearerToken};
+use crate::endpoint::PreGrant;
+use crate::code_grant::accesstoken::{BearerToken, Parties};
 use super::Time;
-use super::grant::Grant;
-use super::generator::{TagGrant, TaggedAssertion, Assertion};
+use super::grant::{Extensions, Grant};
+use super::generator::{Assertion, Signer, TagGrant, TaggedAssertion, Verifier};
 
 /// Issuers create bearer tokens.
 ///
@@ -32,6 +35,24 @@ pub trait Issuer {
 
     /// Get the values corresponding to a refresh token
     fn recover_refresh<'a>(&'a self, _: &'a str) -> Result<Option<Grant>, ()>;
+
+    /// Invalidate all access and refresh tokens issued to a client, returning how many grants
+    /// they belonged to.
+    ///
+    /// Used to contain a leaked client secret. The default implementation supports no bulk
+    /// revocation and fails, as do issuers that can not revoke their tokens at all.
+    fn revoke_client(&mut self, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
+
+    /// Invalidate the access and refresh tokens issued to a client on behalf of one owner,
+    /// returning how many grants they belonged to.
+    ///
+    /// Used when an owner withdraws their authorization of the client. The default implementation
+    /// supports no bulk revocation and fails.
+    fn revoke_grant(&mut self, _owner_id: &str, _client_id: &str) -> Result<usize, ()> {
+        Err(())
+    }
 }
 
 /// Token parameters returned to a client.
@@ -92,19 +113,81 @@ pub struct RefreshedToken {
     pub token_type: TokenType,
 }
 
+/// How long refresh tokens remain usable.
+///
+/// By default a refresh token expires along with the access token it was issued with. Each of the
+/// limits shortens the time in which the grant can be refreshed, the earliest one applies.
+///
+/// Issuers report the expiry of a refresh token as the `until` of the grant returned from
+/// `recover_refresh`, which the refresh flow refuses once it has passed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RefreshLifetime {
+    /// How long a refresh token is valid after it was issued, independent of its access token.
+    pub valid_for: Option<Duration>,
+
+    /// How long after its first token was issued a grant can be refreshed at all.
+    ///
+    /// Access tokens issued for the grant do not outlive this either, so that the owner has to
+    /// authorize the client again afterwards.
+    pub absolute: Option<Duration>,
+
+    /// How long the grant can go without its access or refresh tokens being used.
+    pub idle: Option<Duration>,
+}
+
 /// Keeps track of access and refresh tokens by a hash-map.
 ///
 /// The generator is itself trait based and can be chosen during construction. It is assumed to not
 /// be possible (or at least very unlikely during their overlapping lifetime) for two different
 /// grants to generate the same token in the grant tagger.
+///
+/// The token strings are wiped from memory when they are revoked, refreshed or expire from the map.
 pub struct TokenMap<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
     duration: Option<Duration>,
+    lifetime: RefreshLifetime,
     generator: G,
     usage: u64,
     access: HashMap<Arc<str>, Arc<Token>>,
     refresh: HashMap<Arc<str>, Arc<Token>>,
 }
 
+/// The tokens of a `TokenMap`, to persist them across restarts.
+///
+/// Created by `TokenMap::export` and restored with `TokenMap::import`. A `TokenMap` serializes as
+/// its snapshot, deserialize the snapshot to read it back since the generator and the lifetimes
+/// are configuration rather than state. The snapshot contains the tokens in plain text and must be
+/// stored as confidentially as the tokens themselves.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMapSnapshot {
+    /// The number of tokens the generator was asked for.
+    pub usage: u64,
+
+    /// All grants with a usable access or refresh token.
+    pub tokens: Vec<TokenSnapshot>,
+}
+
+/// A grant of a `TokenMapSnapshot` with its tokens.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenSnapshot {
+    /// The access token, `None` if it was revoked while the refresh token was not.
+    pub access: Option<String>,
+
+    /// The refresh token, if any.
+    pub refresh: Option<String>,
+
+    /// The grant of the tokens.
+    pub grant: Grant,
+
+    /// When the refresh token expires, before considering the idle time.
+    pub refresh_until: Time,
+
+    /// When the first token of the grant was issued.
+    pub issued_at: Time,
+
+    /// When the tokens were last used.
+    pub last_used: Time,
+}
+
 struct Token {
     /// Back link to the access token.
     access: Arc<str>,
@@ -114,6 +197,44 @@ struct Token {
 
     /// The grant that was originally granted.
     grant: Grant,
+
+    /// When the refresh token expires, before considering the idle time.
+    refresh_until: Time,
+
+    /// When the first token of the grant was issued.
+    issued_at: Time,
+
+    /// When the tokens were last used, in milliseconds since the epoch.
+    last_used: AtomicI64,
+}
+
+impl RefreshLifetime {
+    /// The expiry of a refresh token issued at `now` with an access token valid `until`.
+    ///
+    /// The first token of its grant was issued at `issued_at`.
+    pub fn refresh_until(&self, until: Time, issued_at: Time, now: Time) -> Time {
+        let until = match self.valid_for {
+            Some(valid_for) => now + valid_for,
+            None => until,
+        };
+        self.cap(until, issued_at)
+    }
+
+    /// Limit an expiry to the absolute lifetime of a grant first issued at `issued_at`.
+    pub fn cap(&self, until: Time, issued_at: Time) -> Time {
+        match self.absolute {
+            Some(absolute) => until.min(issued_at + absolute),
+            None => until,
+        }
+    }
+
+    /// Limit the expiry of a refresh token whose grant was last used at `last_used`.
+    pub fn idle_until(&self, until: Time, last_used: Time) -> Time {
+        match self.idle {
+            Some(idle) => until.min(last_used + idle),
+            None => until,
+        }
+    }
 }
 
 impl<G: TagGrant> TokenMap<G> {
@@ -121,6 +242,7 @@ impl<G: TagGrant> TokenMap<G> {
     pub fn new(generator: G) -> Self {
         Self {
             duration: None,
+            lifetime: RefreshLifetime::default(),
             generator,
             usage: 0,
             access: HashMap::new(),
@@ -138,6 +260,14 @@ impl<G: TagGrant> TokenMap<G> {
         self.duration = None;
     }
 
+    /// Limit how long refresh tokens remain usable.
+    ///
+    /// The validity and absolute lifetime apply to tokens issued or refreshed from now on, the idle
+    /// time to all tokens.
+    pub fn refresh_lifetime(&mut self, lifetime: RefreshLifetime) {
+        self.lifetime = lifetime;
+    }
+
     /// Unconditionally delete grant associated with the token.
     ///
     /// This is the main advantage over signing tokens. By keeping internal state of allowed
@@ -160,6 +290,143 @@ impl<G: TagGrant> TokenMap<G> {
         self.access.insert(key, Arc::new(token));
     }
 
+    /// Restore a token exactly as it was issued by another issuer.
+    ///
+    /// Unlike `import_grant`, this keeps the expiration time of the grant and also makes the
+    /// refresh token usable, if there is one. Intended to migrate tokens between storages. The
+    /// refresh token expires with the access token, and its absolute lifetime counts from the
+    /// import.
+    pub fn import_token(&mut self, access: String, refresh: Option<String>, grant: Grant) {
+        let access: Arc<str> = Arc::from(access);
+        let refresh: Option<Arc<str>> = refresh.map(Arc::from);
+        let mut token = Token::from_access(access.clone(), grant);
+        token.refresh = refresh.clone();
+        let token = Arc::new(token);
+
+        if let Some(refresh) = refresh {
+            self.refresh.insert(refresh, token.clone());
+        }
+        self.access.insert(access, token);
+    }
+
+    /// Iterate over all tokens, as access token, refresh token and grant.
+    ///
+    /// Tokens are visited in no particular order.
+    pub fn tokens(&self) -> impl Iterator<Item = (&str, Option<&str>, &Grant)> {
+        self.access
+            .values()
+            .map(|token| (&*token.access, token.refresh.as_deref(), &token.grant))
+    }
+
+    /// Copy all tokens, for example to restore them after a restart.
+    pub fn export(&self) -> TokenMapSnapshot {
+        // A token is reachable from its access token, its refresh token or both.
+        let mut seen = HashMap::new();
+        for token in self.access.values().chain(self.refresh.values()) {
+            seen.entry(Arc::as_ptr(token)).or_insert(token);
+        }
+
+        let mut tokens: Vec<_> = seen
+            .into_values()
+            .map(|token| TokenSnapshot {
+                access: Some(&token.access)
+                    .filter(|access| self.access.contains_key(&***access))
+                    .map(|access| access.to_string()),
+                refresh: token
+                    .refresh
+                    .as_ref()
+                    .filter(|refresh| self.refresh.contains_key(&***refresh))
+                    .map(|refresh| refresh.to_string()),
+                grant: token.grant.clone(),
+                refresh_until: token.refresh_until,
+                issued_at: token.issued_at,
+                last_used: Utc
+                    .timestamp_millis_opt(token.last_used.load(Ordering::Relaxed))
+                    .single()
+                    .unwrap_or(token.issued_at),
+            })
+            .collect();
+        // Order independently of the hash maps, so that equal maps have equal snapshots.
+        tokens.sort_by(|a, b| {
+            (a.issued_at, &a.access, &a.refresh).cmp(&(b.issued_at, &b.access, &b.refresh))
+        });
+
+        TokenMapSnapshot {
+            usage: self.usage,
+            tokens,
+        }
+    }
+
+    /// Restore the tokens of a snapshot, in addition to the current ones.
+    ///
+    /// Unlike `import_token`, the expiry and idle time of refresh tokens are restored exactly.
+    /// Tokens present in both are replaced by the snapshot. The usage counter continues from the
+    /// larger of both, so that a generator depending on it does not repeat tokens.
+    pub fn import(&mut self, snapshot: TokenMapSnapshot) {
+        self.usage = self.usage.max(snapshot.usage);
+        for stored in snapshot.tokens {
+            for key in stored.access.iter().chain(&stored.refresh) {
+                self.remove_grant_of(key);
+            }
+
+            let access: Arc<str> = Arc::from(stored.access.clone().unwrap_or_default());
+            let refresh: Option<Arc<str>> = stored.refresh.map(Arc::from);
+            let token = Arc::new(Token {
+                access: access.clone(),
+                refresh: refresh.clone(),
+                grant: stored.grant,
+                refresh_until: stored.refresh_until,
+                issued_at: stored.issued_at,
+                last_used: AtomicI64::new(stored.last_used.timestamp_millis()),
+            });
+
+            if stored.access.is_some() {
+                self.access.insert(access, token.clone());
+            }
+            if let Some(refresh) = refresh {
+                self.refresh.insert(refresh, token);
+            }
+        }
+    }
+
+    /// Remove the access and refresh tokens that expired at `now`, returning how many there were.
+    ///
+    /// Expired tokens are refused when they are used, but stay in memory until they are revoked,
+    /// refreshed or purged. A refresh token outliving its access token is kept.
+    pub fn purge_expired(&mut self, now: Time) -> usize {
+        let before = self.access.len() + self.refresh.len();
+        let lifetime = self.lifetime;
+        self.access.retain(|_, token| token.grant.until > now);
+        self.refresh
+            .retain(|_, token| token.refresh_expiry(&lifetime) > now);
+        before - self.access.len() - self.refresh.len()
+    }
+
+    /// Remove the token under the key along with the other token of the same grant.
+    fn remove_grant_of(&mut self, key: &str) {
+        let token = match self.access.get(key).or_else(|| self.refresh.get(key)) {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        if self
+            .access
+            .get(&token.access)
+            .is_some_and(|other| Arc::ptr_eq(other, &token))
+        {
+            self.access.remove(&token.access);
+        }
+        if let Some(refresh) = &token.refresh {
+            if self
+                .refresh
+                .get(refresh)
+                .is_some_and(|other| Arc::ptr_eq(other, &token))
+            {
+                self.refresh.remove(refresh);
+            }
+        }
+    }
+
     fn set_duration(&self, grant: &mut Grant) {
         if let Some(duration) = &self.duration {
             grant.until = Utc::now() + *duration;
@@ -169,20 +436,61 @@ impl<G: TagGrant> TokenMap<G> {
 
 impl Token {
     fn from_access(access: Arc<str>, grant: Grant) -> Self {
+        let now = Utc::now();
         Token {
             access,
             refresh: None,
+            refresh_until: grant.until,
             grant,
+            issued_at: now,
+            last_used: AtomicI64::new(now.timestamp_millis()),
         }
     }
 
-    fn from_refresh(access: Arc<str>, refresh: Arc<str>, grant: Grant) -> Self {
+    fn from_refresh(
+        access: Arc<str>, refresh: Arc<str>, grant: Grant, refresh_until: Time, issued_at: Time,
+    ) -> Self {
         Token {
             access,
             refresh: Some(refresh),
             grant,
+            refresh_until,
+            issued_at,
+            last_used: AtomicI64::new(issued_at.timestamp_millis()),
         }
     }
+
+    fn used(&self) {
+        self.last_used
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// The expiry of the refresh token, considering how long the grant has been idle.
+    fn refresh_expiry(&self, lifetime: &RefreshLifetime) -> Time {
+        let last_used = Utc
+            .timestamp_millis_opt(self.last_used.load(Ordering::Relaxed))
+            .single()
+            .unwrap_or(self.issued_at);
+        lifetime.idle_until(self.refresh_until, last_used)
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        // The maps drop their keys before their values, so the strings are usually unique by now.
+        if let Some(access) = Arc::get_mut(&mut self.access) {
+            access.zeroize();
+        }
+        if let Some(refresh) = self.refresh.as_mut().and_then(Arc::get_mut) {
+            refresh.zeroize();
+        }
+    }
+}
+
+impl<G: TagGrant> Serialize for TokenMap<G> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.export().serialize(serializer)
+    }
 }
 
 impl IssuedToken {
@@ -236,14 +544,22 @@ impl IssuedToken {
     }
 
     /// Convert this issued token to an access bearer token given a grant
+    ///
+    /// The resource owner of the resulting token is unknown.
     pub fn convert_bearer_token(self, pre_grant: PreGrant) -> BearerToken {
-        BearerToken(self, pre_grant.scope)
+        let parties = Parties {
+            client_id: pre_grant.client_id,
+            owner_id: None,
+        };
+        BearerToken(self, pre_grant.scope, parties, Extensions::new())
     }
 }
 
 impl<G: TagGrant> Issuer for TokenMap<G> {
     fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
+        let now = Utc::now();
         self.set_duration(&mut grant);
+        grant.until = self.lifetime.cap(grant.until, now);
         // The (usage, grant) tuple needs to be unique. Since this wraps after 2^63 operations, we
         // expect the validity time of the grant to have changed by then. This works when you don't
         // set your system time forward/backward ~10billion seconds, assuming ~10^9 operations per
@@ -265,9 +581,11 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
         };
 
         let until = grant.until;
+        let refresh_until = self.lifetime.refresh_until(until, now, now);
         let access_key: Arc<str> = Arc::from(access.clone());
         let refresh_key: Arc<str> = Arc::from(refresh.clone());
-        let token = Token::from_refresh(access_key.clone(), refresh_key.clone(), grant);
+        let token =
+            Token::from_refresh(access_key.clone(), refresh_key.clone(), grant, refresh_until, now);
         let token = Arc::new(token);
 
         self.access.insert(access_key, token.clone());
@@ -290,8 +608,11 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
             .ok_or(())?;
 
         assert!(Arc::ptr_eq(token.refresh.as_ref().unwrap(), &refresh_key));
+        let now = Utc::now();
         self.set_duration(&mut grant);
+        grant.until = self.lifetime.cap(grant.until, token.issued_at);
         let until = grant.until;
+        let refresh_until = self.lifetime.refresh_until(until, token.issued_at, now);
 
         let tag = self.usage;
         let new_access = self.generator.tag(tag, &grant)?;
@@ -314,6 +635,8 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
             mut_token.access = new_access_key.clone();
             mut_token.refresh = Some(new_refresh_key.clone());
             mut_token.grant = grant;
+            mut_token.refresh_until = refresh_until;
+            mut_token.used();
         }
 
         self.access.insert(new_access_key, token.clone());
@@ -329,11 +652,33 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
     }
 
     fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
-        Ok(self.access.get(token).map(|token| token.grant.clone()))
+        Ok(self.access.get(token).map(|token| {
+            token.used();
+            token.grant.clone()
+        }))
     }
 
     fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
-        Ok(self.refresh.get(token).map(|token| token.grant.clone()))
+        Ok(self.refresh.get(token).map(|token| Grant {
+            until: token.refresh_expiry(&self.lifetime),
+            ..token.grant.clone()
+        }))
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        let before = self.access.len();
+        self.access.retain(|_, token| token.grant.client_id != client_id);
+        self.refresh.retain(|_, token| token.grant.client_id != client_id);
+        Ok(before - self.access.len())
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        let kept =
+            |token: &Arc<Token>| token.grant.owner_id != owner_id || token.grant.client_id != client_id;
+        let before = self.access.len();
+        self.access.retain(|_, token| kept(token));
+        self.refresh.retain(|_, token| kept(token));
+        Ok(before - self.access.len())
     }
 }
 
@@ -341,20 +686,34 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
 ///
 /// Although this token instance allows preservation of memory it also implies that tokens, once
 /// issued, are impossible to revoke.
-pub struct TokenSigner {
+///
+/// Tokens are signed with an `Assertion` by default. Any other `Signer` and `Verifier`, for
+/// example one keeping its key in a KMS or HSM, produces tokens of the same format.
+pub struct TokenSigner<S = Assertion> {
     duration: Option<Duration>,
-    signer: Assertion,
+    signer: S,
     // FIXME: make this an AtomicU64 once stable.
     counter: AtomicUsize,
     have_refresh: bool,
 }
 
 impl TokenSigner {
+    /// Construct a signing instance whose tokens only live for the program execution.
+    ///
+    /// Useful for rapid prototyping where tokens need not be stored in a persistent database and
+    /// can be invalidated at any time. This interface is provided with simplicity in mind, using
+    /// the default system random generator (`ring::rand::SystemRandom`).
+    pub fn ephemeral() -> TokenSigner {
+        TokenSigner::new(Assertion::ephemeral())
+    }
+}
+
+impl<S> TokenSigner<S> {
     /// Construct a signing instance from a private signing key.
     ///
     /// Security notice: Never use a password alone to construct the signing key. Instead, generate
     /// a new key using a utility such as `openssl rand` that you then store away securely.
-    pub fn new(secret: Assertion) -> TokenSigner {
+    pub fn new(secret: S) -> Self {
         TokenSigner {
             duration: None,
             signer: secret,
@@ -363,15 +722,6 @@ impl TokenSigner {
         }
     }
 
-    /// Construct a signing instance whose tokens only live for the program execution.
-    ///
-    /// Useful for rapid prototyping where tokens need not be stored in a persistent database and
-    /// can be invalidated at any time. This interface is provided with simplicity in mind, using
-    /// the default system random generator (`ring::rand::SystemRandom`).
-    pub fn ephemeral() -> TokenSigner {
-        TokenSigner::new(Assertion::ephemeral())
-    }
-
     /// Set the validity of all issued grants to the specified duration.
     ///
     /// This only affects tokens issued after this call. The default duration is 1 (ONE) hour for
@@ -408,7 +758,9 @@ impl TokenSigner {
         // thread.
         self.counter.fetch_add(1, Ordering::Relaxed)
     }
+}
 
+impl<S: Signer + Verifier> TokenSigner<S> {
     fn refreshable_token(&self, grant: &Grant) -> Result<IssuedToken, ()> {
         let first_ctr = self.next_counter() as u64;
         let second_ctr = self.next_counter() as u64;
@@ -432,12 +784,12 @@ impl TokenSigner {
         Ok(IssuedToken::without_refresh(token, grant.until))
     }
 
-    fn as_token(&self) -> TaggedAssertion<'_> {
-        self.signer.tag("token")
+    fn as_token(&self) -> TaggedAssertion<'_, S> {
+        TaggedAssertion::new(&self.signer, "token")
     }
 
-    fn as_refresh(&self) -> TaggedAssertion<'_> {
-        self.signer.tag("refresh")
+    fn as_refresh(&self) -> TaggedAssertion<'_, S> {
+        TaggedAssertion::new(&self.signer, "refresh")
     }
 }
 
@@ -457,6 +809,14 @@ impl<I: Issuer + ?Sized> Issuer for &mut I {
     fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
 }
 
 impl<I: Issuer + ?Sized> Issuer for Box<I> {
@@ -475,6 +835,14 @@ impl<I: Issuer + ?Sized> Issuer for Box<I> {
     fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
 }
 
 impl<'s, I: Issuer + ?Sized> Issuer for MutexGuard<'s, I> {
@@ -493,6 +861,14 @@ impl<'s, I: Issuer + ?Sized> Issuer for MutexGuard<'s, I> {
     fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
 }
 
 impl<'s, I: Issuer + ?Sized> Issuer for RwLockWriteGuard<'s, I> {
@@ -511,9 +887,48 @@ impl<'s, I: Issuer + ?Sized> Issuer for RwLockWriteGuard<'s, I> {
     fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        (**self).revoke_grant(owner_id, client_id)
+    }
+}
+
+/// Shares an issuer between threads, locking it only for each operation.
+///
+/// Issuing, refreshing and revoking take the write lock while recovering tokens only takes the
+/// read lock, so that resource requests do not wait for each other. Each request can build its
+/// endpoint with a clone of the `Arc` instead of holding a guard for the whole flow.
+impl<I: Issuer + ?Sized> Issuer for Arc<RwLock<I>> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+        self.write().map_err(|_| ())?.issue(grant)
+    }
+
+    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+        self.write().map_err(|_| ())?.refresh(token, grant)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        self.read().map_err(|_| ())?.recover_token(token)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        self.read().map_err(|_| ())?.recover_refresh(token)
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.write().map_err(|_| ())?.revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.write().map_err(|_| ())?.revoke_grant(owner_id, client_id)
+    }
 }
 
-impl Issuer for TokenSigner {
+impl<S: Signer + Verifier> Issuer for TokenSigner<S> {
     fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
         (&mut &*self).issue(grant)
     }
@@ -531,7 +946,7 @@ impl Issuer for TokenSigner {
     }
 }
 
-impl Issuer for &TokenSigner {
+impl<S: Signer + Verifier> Issuer for &TokenSigner<S> {
     fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
         if let Some(duration) = &self.duration {
             grant.until = Utc::now() + *duration;
@@ -565,7 +980,7 @@ impl Issuer for &TokenSigner {
 /// Tests for issuer implementations, including those provided here.
 pub mod tests {
     use super::*;
-    use crate::primitives::grant::Extensions;
+    use crate::primitives::grant::{Extensions, Value};
     use crate::primitives::generator::RandomGenerator;
     use chrono::{Duration, Utc};
 
@@ -626,6 +1041,36 @@ pub mod tests {
         assert!(!token.refreshable());
     }
 
+    #[test]
+    fn signer_custom_key() {
+        use crate::primitives::generator::SignError;
+
+        /// Signs with a shared key, but refuses every signature.
+        struct Refusing(Arc<Assertion>);
+
+        impl Signer for Refusing {
+            fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+                self.0.sign(data)
+            }
+        }
+
+        impl Verifier for Refusing {
+            fn verify(&self, _: &[u8], _: &[u8]) -> Result<(), SignError> {
+                Err(SignError)
+            }
+        }
+
+        let key = Arc::new(Assertion::ephemeral());
+        let mut signer = TokenSigner::new(key.clone());
+        signer.generate_refresh_tokens(true);
+        simple_test_suite(&mut signer);
+
+        let mut refusing = TokenSigner::new(Refusing(key));
+        let issued = refusing.issue(grant_template()).unwrap();
+        assert!(signer.recover_token(&issued.token).unwrap().is_some());
+        assert!(refusing.recover_token(&issued.token).unwrap().is_none());
+    }
+
     #[test]
     fn random_test_suite() {
         let mut token_map = TokenMap::new(RandomGenerator::new(16));
@@ -658,6 +1103,76 @@ pub mod tests {
         assert!(refresh != new_refresh);
     }
 
+    #[test]
+    fn random_refresh_lifetime() {
+        let mut token_map = TokenMap::new(RandomGenerator::new(16));
+        token_map.valid_for(Duration::minutes(10));
+        token_map.refresh_lifetime(RefreshLifetime {
+            valid_for: Some(Duration::days(30)),
+            absolute: Some(Duration::days(7)),
+            idle: None,
+        });
+
+        let issued = token_map.issue(grant_template()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        let recovered = token_map.recover_refresh(&refresh).unwrap().unwrap();
+        // The refresh token outlives its access token, but not the absolute lifetime.
+        assert!(recovered.until > issued.until + Duration::days(6));
+        assert!(recovered.until <= Utc::now() + Duration::days(7));
+
+        let refreshed = token_map.refresh(&refresh, recovered.clone()).unwrap();
+        let refresh = refreshed.refresh.unwrap();
+        assert_eq!(
+            token_map.recover_refresh(&refresh).unwrap().unwrap().until,
+            recovered.until
+        );
+
+        token_map.refresh_lifetime(RefreshLifetime {
+            idle: Some(Duration::hours(1)),
+            ..RefreshLifetime::default()
+        });
+        let recovered = token_map.recover_refresh(&refresh).unwrap().unwrap();
+        assert!(recovered.until <= Utc::now() + Duration::hours(1));
+    }
+
+    #[test]
+    fn refresh_lifetime_limits() {
+        let now = Utc::now();
+        let until = now + Duration::hours(1);
+        let lifetime = RefreshLifetime {
+            valid_for: Some(Duration::days(1)),
+            absolute: Some(Duration::days(2)),
+            idle: Some(Duration::hours(12)),
+        };
+
+        assert_eq!(RefreshLifetime::default().refresh_until(until, now, now), until);
+        assert_eq!(lifetime.refresh_until(until, now, now), now + Duration::days(1));
+        let later = now + Duration::days(3) / 2;
+        assert_eq!(lifetime.refresh_until(until, now, later), now + Duration::days(2));
+        assert_eq!(
+            lifetime.cap(later + Duration::days(1), now),
+            now + Duration::days(2)
+        );
+        assert_eq!(lifetime.idle_until(later, now), now + Duration::hours(12));
+    }
+
+    #[test]
+    fn random_import_roundtrip() {
+        let mut token_map = TokenMap::new(RandomGenerator::new(16));
+        let issued = token_map.issue(grant_template()).unwrap();
+
+        let mut imported = TokenMap::new(RandomGenerator::new(16));
+        for (access, refresh, grant) in token_map.tokens() {
+            imported.import_token(access.to_owned(), refresh.map(str::to_owned), grant.clone());
+        }
+
+        let original = token_map.recover_token(&issued.token).unwrap().unwrap();
+        let recovered = imported.recover_token(&issued.token).unwrap().unwrap();
+        assert_eq!(recovered, original);
+        let refresh = issued.refresh.expect("No refresh token returned");
+        assert!(imported.recover_refresh(&refresh).unwrap().is_some());
+    }
+
     #[test]
     #[should_panic]
     fn bad_generator() {
@@ -670,4 +1185,34 @@ pub mod tests {
         let mut token_map = TokenMap::new(BadGenerator);
         simple_test_suite(&mut token_map);
     }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let mut token_map = TokenMap::new(RandomGenerator::new(16));
+        let mut grant = grant_template();
+        grant
+            .extensions
+            .set_raw("private".into(), Value::private(Some("secret".into())));
+        let kept = token_map.issue(grant.clone()).unwrap();
+        let revoked = token_map.issue(grant_template()).unwrap();
+        token_map.revoke(&revoked.token);
+
+        let json = serde_json::to_string(&token_map).unwrap();
+        let snapshot: TokenMapSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, token_map.export());
+
+        let mut restored = TokenMap::new(RandomGenerator::new(16));
+        restored.import(snapshot);
+        assert_eq!(restored.recover_token(&kept.token), Ok(Some(grant)));
+        assert_eq!(restored.recover_token(&revoked.token), Ok(None));
+
+        // The refresh token of the revoked access token is still usable.
+        let refresh = revoked.refresh.unwrap();
+        assert!(restored.recover_refresh(&refresh).unwrap().is_some());
+        assert!(restored.refresh(&refresh, grant_template()).is_ok());
+
+        // Importing again replaces the tokens instead of duplicating them.
+        restored.import(token_map.export());
+        assert_eq!(restored.tokens().count(), 2);
+    }
 }
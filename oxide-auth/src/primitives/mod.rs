@@ -36,20 +36,42 @@ use chrono::DateTime;
 use chrono::Utc;
 use url::Url;
 
+#[cfg(feature = "std")]
 pub mod authorizer;
+#[cfg(feature = "std")]
+pub mod claims;
+#[cfg(feature = "std")]
+pub mod consent;
+#[cfg(feature = "std")]
+pub mod delegation;
 pub mod generator;
 pub mod grant;
+#[cfg(feature = "std")]
 pub mod issuer;
+#[cfg(feature = "std")]
+pub mod nonce;
+#[cfg(feature = "std")]
+pub mod pushed;
+#[cfg(feature = "std")]
 pub mod registrar;
 pub mod scope;
+#[cfg(feature = "std")]
+pub mod session;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod uma;
 
 type Time = DateTime<Utc>;
 
 /// Commonly used primitives for frontends and backends.
+#[cfg(feature = "std")]
 pub mod prelude {
     pub use super::authorizer::{Authorizer, AuthMap};
+    pub use super::consent::{Consent, ConsentMap, ConsentStore};
     pub use super::issuer::{IssuedToken, Issuer, TokenMap, TokenSigner};
-    pub use super::generator::{Assertion, TagGrant, RandomGenerator};
-    pub use super::registrar::{Registrar, Client, ClientUrl, ClientMap, PreGrant};
-    pub use super::scope::Scope;
+    pub use super::generator::{Assertion, TagGrant, RandomGenerator, Signer, Verifier};
+    pub use super::registrar::{Registrar, Client, ClientUrl, ClientMap, KnownScopes, PreGrant};
+    pub use super::scope::{Scope, ScopeInfo, ScopeRegistry, Sensitivity};
+    pub use super::session::{Session, SessionMap, SessionStore};
 }
@@ -0,0 +1,188 @@
+//! Nonces provided by the server for DPoP proofs.
+//!
+//! A DPoP proof is signed by the client for each request. Without a nonce chosen by the server, a
+//! client could create proofs ahead of time and a leaked proof could be replayed for as long as
+//! its `iat` is accepted. RFC 9449 lets the server provide a nonce in the `DPoP-Nonce` header
+//! instead, which proofs must repeat in their `nonce` claim. A proof without a current nonce is
+//! refused with the `use_dpop_nonce` error, carrying a fresh nonce to retry with.
+//!
+//! This crate does not validate DPoP proofs. A frontend that does passes the `nonce` claim of each
+//! verified proof to [`require_dpop_nonce`], which checks it against the [`NonceStore`] of the
+//! endpoint and answers the error.
+//!
+//! [`require_dpop_nonce`]: ../../endpoint/fn.require_dpop_nonce.html
+//! [`NonceStore`]: trait.NonceStore.html
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use subtle::ConstantTimeEq;
+
+use super::generator::{OsRandom, RandomSource};
+
+/// Provides the nonces that DPoP proofs must contain.
+pub trait NonceStore {
+    /// The nonce that clients should include in their next proof.
+    fn current(&mut self) -> Result<String, NonceError>;
+
+    /// Whether the nonce of a proof is still accepted.
+    fn check(&mut self, nonce: &str) -> bool;
+}
+
+/// A fresh nonce could not be provided.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NonceError;
+
+/// Nonces held in process memory, replaced after a fixed lifetime.
+///
+/// The previous nonce is accepted for another lifetime, so that proofs created just before the
+/// nonce was replaced are not refused. Replicas of a server each provide their own nonces, put a
+/// store shared by all of them in front of a load balancer without sticky sessions.
+pub struct NonceWindow<S: RandomSource = OsRandom> {
+    source: S,
+    lifetime: Duration,
+    current: Option<(String, DateTime<Utc>)>,
+    previous: Option<String>,
+}
+
+impl NonceWindow {
+    /// Nonces from the random source of the operating system, replaced every five minutes.
+    pub fn new() -> Self {
+        NonceWindow::with_source(OsRandom)
+    }
+}
+
+impl Default for NonceWindow {
+    fn default() -> Self {
+        NonceWindow::new()
+    }
+}
+
+impl<S: RandomSource> NonceWindow<S> {
+    /// Nonces from another random source, replaced every five minutes.
+    pub fn with_source(source: S) -> Self {
+        NonceWindow {
+            source,
+            lifetime: Duration::minutes(5),
+            current: None,
+            previous: None,
+        }
+    }
+
+    /// Replace nonces after another lifetime.
+    ///
+    /// Proofs are accepted for up to twice the lifetime after their nonce was provided.
+    pub fn with_lifetime(self, lifetime: Duration) -> Self {
+        NonceWindow { lifetime, ..self }
+    }
+
+    /// Replace the current nonce if it has reached its lifetime.
+    fn rotate(&mut self, now: DateTime<Utc>) -> Result<&str, NonceError> {
+        let expired = match &self.current {
+            Some((_, issued)) => *issued + self.lifetime <= now,
+            None => true,
+        };
+
+        if expired {
+            let mut bytes = [0; 16];
+            self.source.fill(&mut bytes).map_err(|_| NonceError)?;
+            let fresh = (URL_SAFE_NO_PAD.encode(bytes), now);
+            // The previous nonce is only kept if it has not been stale for a whole lifetime.
+            self.previous = match self.current.replace(fresh) {
+                Some((nonce, issued)) if issued + self.lifetime * 2 > now => Some(nonce),
+                _ => None,
+            };
+        }
+
+        match &self.current {
+            Some((nonce, _)) => Ok(nonce),
+            None => Err(NonceError),
+        }
+    }
+
+    fn check_at(&mut self, nonce: &str, now: DateTime<Utc>) -> bool {
+        if self.rotate(now).is_err() {
+            return false;
+        }
+
+        let matches = |known: &str| bool::from(known.as_bytes().ct_eq(nonce.as_bytes()));
+        self.current.as_ref().is_some_and(|(current, _)| matches(current))
+            || self.previous.as_deref().is_some_and(matches)
+    }
+}
+
+impl<S: RandomSource> NonceStore for NonceWindow<S> {
+    fn current(&mut self) -> Result<String, NonceError> {
+        self.rotate(Utc::now()).map(str::to_owned)
+    }
+
+    fn check(&mut self, nonce: &str) -> bool {
+        self.check_at(nonce, Utc::now())
+    }
+}
+
+impl<N: NonceStore + ?Sized> NonceStore for &mut N {
+    fn current(&mut self) -> Result<String, NonceError> {
+        (**self).current()
+    }
+
+    fn check(&mut self, nonce: &str) -> bool {
+        (**self).check(nonce)
+    }
+}
+
+impl<N: NonceStore + ?Sized> NonceStore for Box<N> {
+    fn current(&mut self) -> Result<String, NonceError> {
+        (**self).current()
+    }
+
+    fn check(&mut self, nonce: &str) -> bool {
+        (**self).check(nonce)
+    }
+}
+
+impl<'a, N: NonceStore + ?Sized> NonceStore for MutexGuard<'a, N> {
+    fn current(&mut self) -> Result<String, NonceError> {
+        (**self).current()
+    }
+
+    fn check(&mut self, nonce: &str) -> bool {
+        (**self).check(nonce)
+    }
+}
+
+impl<'a, N: NonceStore + ?Sized> NonceStore for RwLockWriteGuard<'a, N> {
+    fn current(&mut self) -> Result<String, NonceError> {
+        (**self).current()
+    }
+
+    fn check(&mut self, nonce: &str) -> bool {
+        (**self).check(nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonces_rotate_after_their_lifetime() {
+        let mut window = NonceWindow::new().with_lifetime(Duration::minutes(1));
+        let start = Utc::now();
+
+        let first = window.rotate(start).unwrap().to_owned();
+        assert!(window.check_at(&first, start + Duration::seconds(30)));
+        assert!(!window.check_at("forged", start));
+
+        // The previous nonce stays valid for one more lifetime.
+        let rotated = start + Duration::seconds(90);
+        let second = window.rotate(rotated).unwrap().to_owned();
+        assert_ne!(first, second);
+        assert!(window.check_at(&first, rotated));
+        assert!(window.check_at(&second, rotated));
+
+        let later = start + Duration::seconds(150);
+        assert!(!window.check_at(&first, later));
+        assert!(window.check_at(&second, later + Duration::seconds(30)));
+    }
+}
@@ -0,0 +1,275 @@
+//! Authorization requests pushed by clients ahead of time.
+//!
+//! With pushed authorization requests (RFC 9126) a client sends the parameters of its
+//! authorization request directly to the server, authenticated like a token request. It receives a
+//! `request_uri` in return and the user agent visits the authorization endpoint with only this
+//! reference and the `client_id`. The parameters can then neither be read nor altered on their way
+//! through the browser.
+//!
+//! The endpoint receiving pushed requests authenticates the client and stores the parameters with
+//! [`PushedRequests::push`]. An endpoint returning a store from [`Endpoint::pushed_requests`] then
+//! only accepts authorization requests referring to pushed parameters. The parameters stay
+//! available while the owner is asked for consent, and are consumed once the request is decided.
+//!
+//! [`PushedRequests::push`]: trait.PushedRequests.html#tymethod.push
+//! [`Endpoint::pushed_requests`]: ../../endpoint/trait.Endpoint.html#method.pushed_requests
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+
+use super::generator::{OsRandom, RandomSource};
+use crate::endpoint::{NormalizedParameter, QueryParameter};
+
+/// The prefix of request uris referring to pushed requests, defined in RFC 9126.
+pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// Holds the parameters of pushed authorization requests.
+pub trait PushedRequests {
+    /// Store the parameters pushed by an authenticated client.
+    ///
+    /// The parameters must name the client as their `client_id`. Returns the reference that the
+    /// client passes to the authorization endpoint as the `request_uri`.
+    fn push(&mut self, client_id: &str, parameters: NormalizedParameter) -> Result<Pushed, PushError>;
+
+    /// Find the parameters a client pushed under a request uri.
+    ///
+    /// Returns `None` for unknown, consumed and expired request uris and for those of other
+    /// clients. The parameters are kept, the flow may take several requests to ask the owner for
+    /// consent.
+    fn find(&mut self, client_id: &str, request_uri: &str) -> Option<NormalizedParameter>;
+
+    /// Remove the parameters of a request uri after its authorization request was decided.
+    ///
+    /// A request uri can not be used again afterwards.
+    fn consume(&mut self, request_uri: &str);
+}
+
+/// The reference to a pushed request, answered to the client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pushed {
+    /// The `request_uri` to pass to the authorization endpoint.
+    pub request_uri: String,
+
+    /// Seconds until the request uri expires.
+    pub expires_in: i64,
+}
+
+/// The parameters of a pushed request were not stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushError {
+    /// The parameters name another client than the one that pushed them.
+    ClientMismatch,
+
+    /// The store failed, for example to generate a request uri.
+    PrimitiveError,
+}
+
+/// Pushed requests held in process memory.
+///
+/// Request uris expire after a minute by default. Replicas of a server need a shared store
+/// instead, as the authorization request may reach another replica than the pushed request.
+pub struct PushedMap<S: RandomSource = OsRandom> {
+    source: S,
+    lifetime: Duration,
+    requests: HashMap<String, Entry>,
+}
+
+struct Entry {
+    client_id: String,
+    parameters: NormalizedParameter,
+    until: DateTime<Utc>,
+}
+
+impl PushedMap {
+    /// Request uris from the random source of the operating system, expiring after a minute.
+    pub fn new() -> Self {
+        PushedMap::with_source(OsRandom)
+    }
+}
+
+impl Default for PushedMap {
+    fn default() -> Self {
+        PushedMap::new()
+    }
+}
+
+impl<S: RandomSource> PushedMap<S> {
+    /// Request uris from another random source, expiring after a minute.
+    pub fn with_source(source: S) -> Self {
+        PushedMap {
+            source,
+            lifetime: Duration::minutes(1),
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Expire request uris after another lifetime.
+    ///
+    /// RFC 9126 recommends a short lifetime, between five and six hundred seconds.
+    pub fn with_lifetime(self, lifetime: Duration) -> Self {
+        PushedMap { lifetime, ..self }
+    }
+
+    fn push_at(
+        &mut self, client_id: &str, parameters: NormalizedParameter, now: DateTime<Utc>,
+    ) -> Result<Pushed, PushError> {
+        if parameters.unique_value("client_id").as_deref() != Some(client_id) {
+            return Err(PushError::ClientMismatch);
+        }
+
+        let mut bytes = [0; 24];
+        self.source
+            .fill(&mut bytes)
+            .map_err(|_| PushError::PrimitiveError)?;
+        let request_uri = format!("{}{}", REQUEST_URI_PREFIX, URL_SAFE_NO_PAD.encode(bytes));
+
+        self.requests.retain(|_, entry| entry.until > now);
+        self.requests.insert(
+            request_uri.clone(),
+            Entry {
+                client_id: client_id.to_owned(),
+                parameters,
+                until: now + self.lifetime,
+            },
+        );
+
+        Ok(Pushed {
+            request_uri,
+            expires_in: self.lifetime.num_seconds(),
+        })
+    }
+
+    fn find_at(
+        &mut self, client_id: &str, request_uri: &str, now: DateTime<Utc>,
+    ) -> Option<NormalizedParameter> {
+        let entry = self.requests.get(request_uri)?;
+        if entry.until <= now {
+            self.requests.remove(request_uri);
+            return None;
+        }
+
+        if entry.client_id != client_id {
+            return None;
+        }
+
+        Some(entry.parameters.clone())
+    }
+}
+
+impl<S: RandomSource> PushedRequests for PushedMap<S> {
+    fn push(&mut self, client_id: &str, parameters: NormalizedParameter) -> Result<Pushed, PushError> {
+        self.push_at(client_id, parameters, Utc::now())
+    }
+
+    fn find(&mut self, client_id: &str, request_uri: &str) -> Option<NormalizedParameter> {
+        self.find_at(client_id, request_uri, Utc::now())
+    }
+
+    fn consume(&mut self, request_uri: &str) {
+        self.requests.remove(request_uri);
+    }
+}
+
+impl<P: PushedRequests + ?Sized> PushedRequests for &mut P {
+    fn push(&mut self, client_id: &str, parameters: NormalizedParameter) -> Result<Pushed, PushError> {
+        (**self).push(client_id, parameters)
+    }
+
+    fn find(&mut self, client_id: &str, request_uri: &str) -> Option<NormalizedParameter> {
+        (**self).find(client_id, request_uri)
+    }
+
+    fn consume(&mut self, request_uri: &str) {
+        (**self).consume(request_uri)
+    }
+}
+
+impl<P: PushedRequests + ?Sized> PushedRequests for Box<P> {
+    fn push(&mut self, client_id: &str, parameters: NormalizedParameter) -> Result<Pushed, PushError> {
+        (**self).push(client_id, parameters)
+    }
+
+    fn find(&mut self, client_id: &str, request_uri: &str) -> Option<NormalizedParameter> {
+        (**self).find(client_id, request_uri)
+    }
+
+    fn consume(&mut self, request_uri: &str) {
+        (**self).consume(request_uri)
+    }
+}
+
+impl<'a, P: PushedRequests + ?Sized> PushedRequests for MutexGuard<'a, P> {
+    fn push(&mut self, client_id: &str, parameters: NormalizedParameter) -> Result<Pushed, PushError> {
+        (**self).push(client_id, parameters)
+    }
+
+    fn find(&mut self, client_id: &str, request_uri: &str) -> Option<NormalizedParameter> {
+        (**self).find(client_id, request_uri)
+    }
+
+    fn consume(&mut self, request_uri: &str) {
+        (**self).consume(request_uri)
+    }
+}
+
+impl<'a, P: PushedRequests + ?Sized> PushedRequests for RwLockWriteGuard<'a, P> {
+    fn push(&mut self, client_id: &str, parameters: NormalizedParameter) -> Result<Pushed, PushError> {
+        (**self).push(client_id, parameters)
+    }
+
+    fn find(&mut self, client_id: &str, request_uri: &str) -> Option<NormalizedParameter> {
+        (**self).find(client_id, request_uri)
+    }
+
+    fn consume(&mut self, request_uri: &str) {
+        (**self).consume(request_uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(client_id: &str) -> NormalizedParameter {
+        vec![("client_id", client_id), ("response_type", "code")]
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn pushed_requests_are_kept_until_consumed() {
+        let mut map = PushedMap::new();
+        let start = Utc::now();
+
+        let pushed = map.push_at("client", parameters("client"), start).unwrap();
+        assert!(pushed.request_uri.starts_with(REQUEST_URI_PREFIX));
+        assert_eq!(pushed.expires_in, 60);
+
+        assert!(map.find_at("other", &pushed.request_uri, start).is_none());
+
+        let found = map.find_at("client", &pushed.request_uri, start).unwrap();
+        assert_eq!(found.unique_value("response_type").as_deref(), Some("code"));
+        assert!(map.find_at("client", &pushed.request_uri, start).is_some());
+
+        map.consume(&pushed.request_uri);
+        assert!(map.find_at("client", &pushed.request_uri, start).is_none());
+    }
+
+    #[test]
+    fn pushed_requests_expire() {
+        let mut map = PushedMap::new().with_lifetime(Duration::seconds(10));
+        let start = Utc::now();
+
+        let pushed = map.push_at("client", parameters("client"), start).unwrap();
+        let later = start + Duration::seconds(10);
+        assert!(map.find_at("client", &pushed.request_uri, later).is_none());
+
+        assert_eq!(
+            map.push_at("client", parameters("other"), start),
+            Err(PushError::ClientMismatch)
+        );
+    }
+}
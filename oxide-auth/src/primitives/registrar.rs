@@ -3,21 +3,26 @@
 //! It will govern their redirect urls and allowed scopes to request tokens for. When an oauth
 //! request turns up, it is the registrars duty to verify the requested scope and redirect url for
 //! consistency in the permissions granted and urls registered.
-use super::scope::Scope;
+use super::scope::{Scope, ScopeRegistry};
 
 use std::borrow::Cow;
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::iter::{Extend, FromIterator};
 use std::rc::Rc;
-use std::sync::{Arc, MutexGuard, RwLockWriteGuard};
+use std::str;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
 
 use argon2::{self, Config};
+use hmac::{Hmac, Mac};
 use once_cell::sync::Lazy;
 use rand::{RngCore, thread_rng};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use url::{Url, ParseError as ParseUrlError};
+use zeroize::Zeroize;
 
 /// Registrars provie a way to interact with clients.
 ///
@@ -44,6 +49,79 @@ pub trait Registrar {
 
     /// Try to login as client with some authentication.
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError>;
+
+    /// When the client is issued refresh tokens along with its access tokens.
+    ///
+    /// The access token flow consults this for each issued token. The default implementation
+    /// returns `RefreshPolicy::Always`.
+    fn refresh_policy(&self, _client_id: &str) -> RefreshPolicy {
+        RefreshPolicy::Always
+    }
+
+    /// Whether the client may use a grant type at the token endpoint.
+    ///
+    /// The token flows consult this once the client is authenticated and answer
+    /// `unauthorized_client` otherwise. The default implementation permits all grant types.
+    fn permits_grant(&self, _client_id: &str, _grant_type: GrantType) -> bool {
+        true
+    }
+
+    /// How the client must authenticate at the token endpoint, if it registered a method.
+    ///
+    /// The token flows reject clients authenticating with any other method as `invalid_client`.
+    /// The default implementation accepts all methods.
+    fn auth_method(&self, _client_id: &str) -> Option<ClientAuthMethod> {
+        None
+    }
+}
+
+/// Decides when a client is issued refresh tokens.
+///
+/// Refresh tokens let a client act for the owner long after the owner has left. Clients that do
+/// not need this should not hold one, and under `OfflineAccess` they only get one for grants whose
+/// negotiated scope includes `offline_access`. The client must thus both ask for it and be allowed
+/// the scope by the registrar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefreshPolicy {
+    /// Issue a refresh token with every access token.
+    #[default]
+    Always,
+
+    /// Issue a refresh token only if the grant includes the `offline_access` scope.
+    OfflineAccess,
+
+    /// Never issue refresh tokens.
+    Never,
+}
+
+/// A grant type a client may use at the token endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrantType {
+    /// Exchanging an authorization code, `authorization_code`.
+    AuthorizationCode,
+
+    /// Refreshing an access token, `refresh_token`.
+    RefreshToken,
+
+    /// Authenticating as the client alone, `client_credentials`.
+    ClientCredentials,
+}
+
+/// How a client authenticates at the token endpoint.
+///
+/// The names and meaning follow the `token_endpoint_auth_method` of RFC 7591.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuthMethod {
+    /// The passphrase is sent with HTTP Basic authentication.
+    ClientSecretBasic,
+
+    /// The passphrase is sent in the request body.
+    ClientSecretPost,
+
+    /// The client is public and does not authenticate.
+    None,
 }
 
 /// An url that has been registered.
@@ -238,6 +316,63 @@ pub struct Client {
     additional_redirect_uris: Vec<RegisteredUrl>,
     default_scope: Scope,
     client_type: ClientType,
+    refresh_policy: RefreshPolicy,
+    allowed_scope: Option<Scope>,
+    grant_types: Vec<GrantType>,
+    auth_method: Option<ClientAuthMethod>,
+    metadata: BTreeMap<String, String>,
+}
+
+/// Collects the registration of a client.
+///
+/// Created by [`Client::builder`]. A client needs at least one redirect uri and a default scope,
+/// it is public unless given a passphrase.
+///
+/// ```
+/// # use oxide_auth::primitives::registrar::{Client, ClientAuthMethod, GrantType};
+/// # use url::Url;
+/// let client = Client::builder("example")
+///     .redirect_uri("https://client.example/callback".parse::<Url>().unwrap())
+///     .redirect_uri("https://client.example/other".parse::<Url>().unwrap())
+///     .default_scope("read".parse().unwrap())
+///     .allowed_scope("read write".parse().unwrap())
+///     .grant_types([GrantType::AuthorizationCode, GrantType::RefreshToken])
+///     .passphrase(b"secret")
+///     .auth_method(ClientAuthMethod::ClientSecretBasic)
+///     .metadata("client_name", "Example")
+///     .build()
+///     .unwrap();
+/// # let _ = client;
+/// ```
+///
+/// [`Client::builder`]: struct.Client.html#method.builder
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    client_id: String,
+    redirect_uris: Vec<RegisteredUrl>,
+    default_scope: Option<Scope>,
+    allowed_scope: Option<Scope>,
+    passphrase: Option<Vec<u8>>,
+    refresh_policy: RefreshPolicy,
+    grant_types: Vec<GrantType>,
+    auth_method: Option<ClientAuthMethod>,
+    metadata: BTreeMap<String, String>,
+}
+
+/// The registration collected by a `ClientBuilder` is incomplete or contradictory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientBuilderError {
+    /// No redirect uri was given.
+    MissingRedirectUri,
+
+    /// No default scope was given.
+    MissingDefaultScope,
+
+    /// The default scope is not part of the allowed scope.
+    DefaultScopeNotAllowed,
+
+    /// The authentication method does not fit whether the client has a passphrase.
+    AuthMethodMismatch,
 }
 
 /// A client whose credentials have been wrapped by a password policy.
@@ -264,6 +399,30 @@ pub struct EncodedClient {
 
     /// The authentication data.
     pub encoded_client: ClientType,
+
+    /// When the client is issued refresh tokens.
+    #[serde(default)]
+    pub refresh_policy: RefreshPolicy,
+
+    /// The scope the client may request, if it may request other than its default scope.
+    #[serde(default)]
+    pub allowed_scope: Option<Scope>,
+
+    /// The grant types the client registered for, all if empty.
+    #[serde(default)]
+    pub grant_types: Vec<GrantType>,
+
+    /// How the client authenticates, any method fitting its type if not given.
+    #[serde(default)]
+    pub auth_method: Option<ClientAuthMethod>,
+
+    /// Further registration metadata, such as a `client_name` or `logo_uri`.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+
+    /// A disabled client can neither authenticate nor bind a redirect uri.
+    #[serde(default)]
+    pub disabled: bool,
 }
 
 /// Recombines an `EncodedClient` and a  `PasswordPolicy` to check authentication.
@@ -273,6 +432,8 @@ pub struct RegisteredClient<'a> {
 }
 
 /// Enumeration of the two defined client types.
+///
+/// The passdata is wiped from memory when the client type is dropped.
 #[derive(Clone, Serialize, Deserialize)]
 pub enum ClientType {
     /// A public client with no authentication information.
@@ -292,6 +453,18 @@ pub struct ClientMap {
     password_policy: Option<Box<dyn PasswordPolicy>>,
 }
 
+/// Rejects requests for scope tokens not found in a registry.
+///
+/// Requests with unknown scope tokens are denied with an `invalid_scope` error before the wrapped
+/// registrar negotiates their scope. Requests without a scope are passed on as they are.
+pub struct KnownScopes<R> {
+    /// The registrar negotiating the scope of valid requests.
+    pub registrar: R,
+
+    /// The known scope tokens.
+    pub registry: ScopeRegistry,
+}
+
 impl fmt::Debug for ClientType {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
@@ -301,6 +474,14 @@ impl fmt::Debug for ClientType {
     }
 }
 
+impl Drop for ClientType {
+    fn drop(&mut self) {
+        if let ClientType::Confidential { passdata } = self {
+            passdata.zeroize();
+        }
+    }
+}
+
 impl RegisteredUrl {
     /// View the url as a string.
     pub fn as_str(&self) -> &str {
@@ -492,30 +673,48 @@ impl core::str::FromStr for IgnoreLocalPortUrl {
 }
 
 impl Client {
-    /// Create a public client.
-    pub fn public(client_id: &str, redirect_uri: RegisteredUrl, default_scope: Scope) -> Client {
-        Client {
+    /// Start the registration of a client.
+    pub fn builder(client_id: &str) -> ClientBuilder {
+        ClientBuilder {
             client_id: client_id.to_string(),
-            redirect_uri,
-            additional_redirect_uris: vec![],
-            default_scope,
-            client_type: ClientType::Public,
+            redirect_uris: vec![],
+            default_scope: None,
+            allowed_scope: None,
+            passphrase: None,
+            refresh_policy: RefreshPolicy::Always,
+            grant_types: vec![],
+            auth_method: None,
+            metadata: BTreeMap::new(),
         }
     }
 
+    /// Create a public client.
+    ///
+    /// A shorthand for a [`builder`] with a single redirect uri.
+    ///
+    /// [`builder`]: #method.builder
+    pub fn public(client_id: &str, redirect_uri: RegisteredUrl, default_scope: Scope) -> Client {
+        Client::builder(client_id)
+            .redirect_uri(redirect_uri)
+            .default_scope(default_scope)
+            .build()
+            .expect("A redirect uri and scope are all a public client needs")
+    }
+
     /// Create a confidential client.
+    ///
+    /// A shorthand for a [`builder`] with a single redirect uri and a passphrase.
+    ///
+    /// [`builder`]: #method.builder
     pub fn confidential(
         client_id: &str, redirect_uri: RegisteredUrl, default_scope: Scope, passphrase: &[u8],
     ) -> Client {
-        Client {
-            client_id: client_id.to_string(),
-            redirect_uri,
-            additional_redirect_uris: vec![],
-            default_scope,
-            client_type: ClientType::Confidential {
-                passdata: passphrase.to_owned(),
-            },
-        }
+        Client::builder(client_id)
+            .redirect_uri(redirect_uri)
+            .default_scope(default_scope)
+            .passphrase(passphrase)
+            .build()
+            .expect("A redirect uri, scope and passphrase are all a confidential client needs")
     }
 
     /// Add additional redirect uris.
@@ -524,16 +723,22 @@ impl Client {
         self
     }
 
+    /// Decide when the client is issued refresh tokens, by default always.
+    pub fn with_refresh_policy(mut self, policy: RefreshPolicy) -> Self {
+        self.refresh_policy = policy;
+        self
+    }
+
     /// Obscure the clients authentication data.
     ///
     /// This could apply a one-way function to the passphrase using an adequate password hashing
     /// method. The resulting passdata is then used for validating authentication details provided
     /// when later reasserting the identity of a client.
     pub fn encode(self, policy: &dyn PasswordPolicy) -> EncodedClient {
-        let encoded_client = match self.client_type {
+        let encoded_client = match &self.client_type {
             ClientType::Public => ClientType::Public,
             ClientType::Confidential { passdata: passphrase } => ClientType::Confidential {
-                passdata: policy.store(&self.client_id, &passphrase),
+                passdata: policy.store(&self.client_id, passphrase),
             },
         };
 
@@ -543,7 +748,169 @@ impl Client {
             additional_redirect_uris: self.additional_redirect_uris,
             default_scope: self.default_scope,
             encoded_client,
+            refresh_policy: self.refresh_policy,
+            allowed_scope: self.allowed_scope,
+            grant_types: self.grant_types,
+            auth_method: self.auth_method,
+            metadata: self.metadata,
+            disabled: false,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Add a redirect uri.
+    ///
+    /// The first one is the default, used when a request names none.
+    pub fn redirect_uri<U: Into<RegisteredUrl>>(mut self, uri: U) -> Self {
+        self.redirect_uris.push(uri.into());
+        self
+    }
+
+    /// Add several redirect uris.
+    pub fn redirect_uris<I>(mut self, uris: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<RegisteredUrl>,
+    {
+        self.redirect_uris.extend(uris.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set the scope the client gets if it requests none.
+    pub fn default_scope(mut self, scope: Scope) -> Self {
+        self.default_scope = Some(scope);
+        self
+    }
+
+    /// Let the client request any part of the scope.
+    ///
+    /// Without it, the client always gets its default scope.
+    pub fn allowed_scope(mut self, scope: Scope) -> Self {
+        self.allowed_scope = Some(scope);
+        self
+    }
+
+    /// Make the client confidential, authenticating with the passphrase.
+    pub fn passphrase(mut self, passphrase: &[u8]) -> Self {
+        self.passphrase = Some(passphrase.to_owned());
+        self
+    }
+
+    /// Decide when the client is issued refresh tokens, by default always.
+    pub fn refresh_policy(mut self, policy: RefreshPolicy) -> Self {
+        self.refresh_policy = policy;
+        self
+    }
+
+    /// Restrict the grant types of the client, by default it may use all.
+    pub fn grant_types<I: IntoIterator<Item = GrantType>>(mut self, grant_types: I) -> Self {
+        self.grant_types.extend(grant_types);
+        self
+    }
+
+    /// Set how the client authenticates at the token endpoint.
+    pub fn auth_method(mut self, method: ClientAuthMethod) -> Self {
+        self.auth_method = Some(method);
+        self
+    }
+
+    /// Record further registration metadata.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Finish the registration.
+    pub fn build(self) -> Result<Client, ClientBuilderError> {
+        let mut redirect_uris = self.redirect_uris.into_iter();
+        let redirect_uri = redirect_uris
+            .next()
+            .ok_or(ClientBuilderError::MissingRedirectUri)?;
+        let default_scope = self
+            .default_scope
+            .ok_or(ClientBuilderError::MissingDefaultScope)?;
+
+        if let Some(allowed) = &self.allowed_scope {
+            if !allowed.priviledged_to(&default_scope) {
+                return Err(ClientBuilderError::DefaultScopeNotAllowed);
+            }
+        }
+
+        let client_type = match self.passphrase {
+            None => ClientType::Public,
+            Some(passdata) => ClientType::Confidential { passdata },
+        };
+
+        match (&client_type, self.auth_method) {
+            (_, None)
+            | (ClientType::Public, Some(ClientAuthMethod::None))
+            | (ClientType::Confidential { .. }, Some(ClientAuthMethod::ClientSecretBasic))
+            | (ClientType::Confidential { .. }, Some(ClientAuthMethod::ClientSecretPost)) => (),
+            _ => return Err(ClientBuilderError::AuthMethodMismatch),
+        }
+
+        Ok(Client {
+            client_id: self.client_id,
+            redirect_uri,
+            additional_redirect_uris: redirect_uris.collect(),
+            default_scope,
+            client_type,
+            refresh_policy: self.refresh_policy,
+            allowed_scope: self.allowed_scope,
+            grant_types: self.grant_types,
+            auth_method: self.auth_method,
+            metadata: self.metadata,
+        })
+    }
+}
+
+impl EncodedClient {
+    /// Whether the client registered for the grant type.
+    pub fn permits_grant(&self, grant_type: GrantType) -> bool {
+        self.grant_types.is_empty() || self.grant_types.contains(&grant_type)
+    }
+}
+
+impl fmt::Display for ClientBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientBuilderError::MissingRedirectUri => f.write_str("the client has no redirect uri"),
+            ClientBuilderError::MissingDefaultScope => f.write_str("the client has no default scope"),
+            ClientBuilderError::DefaultScopeNotAllowed => {
+                f.write_str("the default scope of the client is not allowed to it")
+            }
+            ClientBuilderError::AuthMethodMismatch => {
+                f.write_str("the authentication method does not fit the client type")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientBuilderError {}
+
+impl RefreshPolicy {
+    /// Whether a grant of the scope comes with a refresh token.
+    pub fn permits(self, scope: &Scope) -> bool {
+        match self {
+            RefreshPolicy::Always => true,
+            RefreshPolicy::OfflineAccess => scope.iter().any(|token| token == "offline_access"),
+            RefreshPolicy::Never => false,
+        }
+    }
+
+    /// Whether a grant of the scope comes with a refresh token, removing `offline_access` from the
+    /// scope if it does not.
+    ///
+    /// The token response then reports that offline access was not granted.
+    pub fn narrow(self, scope: &mut Scope) -> bool {
+        if self.permits(scope) {
+            return true;
         }
+
+        let offline: Scope = "offline_access".parse().unwrap();
+        *scope = scope.difference(&offline);
+        false
     }
 }
 
@@ -558,8 +925,12 @@ impl<'a> RegisteredClient<'a> {
 
     /// Try to authenticate with the client and passphrase. This check will success if either the
     /// client is public and no passphrase was provided or if the client is confidential and the
-    /// passphrase matches.
+    /// passphrase matches. Disabled clients never authenticate.
     pub fn check_authentication(&self, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        if self.client.disabled {
+            return Err(RegistrarError::Unspecified);
+        }
+
         match (passphrase, &self.client.encoded_client) {
             (None, &ClientType::Public) => Ok(()),
             (Some(provided), ClientType::Confidential { passdata: ref stored }) => {
@@ -616,8 +987,8 @@ impl PasswordPolicy for Argon2 {
     }
 
     fn check(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), RegistrarError> {
-        let hash = String::from_utf8(stored.to_vec()).map_err(|_| RegistrarError::PrimitiveError)?;
-        let valid = argon2::verify_encoded_ext(&hash, passphrase, &[], client_id.as_bytes())
+        let hash = str::from_utf8(stored).map_err(|_| RegistrarError::PrimitiveError)?;
+        let valid = argon2::verify_encoded_ext(hash, passphrase, &[], client_id.as_bytes())
             .map_err(|_| RegistrarError::PrimitiveError)?;
         match valid {
             true => Ok(()),
@@ -626,6 +997,180 @@ impl PasswordPolicy for Argon2 {
     }
 }
 
+/// Refuses malformed passphrases before, and remembers verified ones instead of, checking them with
+/// another policy.
+///
+/// Policies such as `Argon2` are slow on purpose, which is also what clients pay for every request
+/// to the token endpoint. This wrapper first requires the presented passphrase to have a length
+/// within bounds and, optionally, to consist of visible ASCII characters only. Both are checked in
+/// time only dependent on the length. Passphrases failing them are refused without consulting the
+/// inner policy, their hashing never starts.
+///
+/// With `cache_verified`, a passphrase that matched the stored data of a client is also remembered
+/// for a short time to live, so that a service client requesting tokens many times per second is
+/// hashed once. Entries are keyed by an HMAC, under a random key of this wrapper, of the client id,
+/// the stored data and the passphrase. Changing the stored data of a client thus never reuses a
+/// result, and the cache holds no passphrases. Only successful checks are cached, wrong guesses can
+/// neither fill the cache nor push out the entries of legitimate clients.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use oxide_auth::primitives::registrar::{Argon2, ClientMap, Prechecked};
+/// let mut registrar = ClientMap::new();
+/// registrar.set_password_policy(
+///     Prechecked::new(Argon2::default())
+///         .length(16, 128)
+///         .ascii_graphic()
+///         .cache_verified(1024, Duration::from_secs(10)),
+/// );
+/// ```
+pub struct Prechecked<P> {
+    inner: P,
+    min_length: usize,
+    max_length: usize,
+    ascii_graphic: bool,
+    verified: Option<VerifiedCache>,
+}
+
+/// Earlier successful checks, by the HMAC of their inputs.
+struct VerifiedCache {
+    key: [u8; 32],
+    capacity: usize,
+    ttl: Duration,
+    verified: Mutex<HashMap<[u8; 32], Instant>>,
+}
+
+impl<P: PasswordPolicy> Prechecked<P> {
+    /// Pre-check passphrases of 1 to 1024 bytes of any content, without a cache.
+    pub fn new(inner: P) -> Self {
+        Prechecked {
+            inner,
+            min_length: 1,
+            max_length: 1024,
+            ascii_graphic: false,
+            verified: None,
+        }
+    }
+
+    /// Refuse passphrases shorter than `min` or longer than `max` bytes.
+    pub fn length(mut self, min: usize, max: usize) -> Self {
+        self.min_length = min;
+        self.max_length = max;
+        self
+    }
+
+    /// Refuse passphrases containing other bytes than visible ASCII characters.
+    ///
+    /// Generated secrets in hexadecimal, base64 or similar encodings satisfy this.
+    pub fn ascii_graphic(mut self) -> Self {
+        self.ascii_graphic = true;
+        self
+    }
+
+    /// Remember up to `capacity` successful checks, each for at most `ttl`.
+    ///
+    /// When full, expired entries are dropped first and then the oldest one.
+    pub fn cache_verified(mut self, capacity: usize, ttl: Duration) -> Self {
+        let mut key = [0; 32];
+        thread_rng()
+            .try_fill_bytes(&mut key)
+            .expect("Failed to generate cache key");
+        self.verified = Some(VerifiedCache {
+            key,
+            capacity,
+            ttl,
+            verified: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    /// Whether the passphrase can be valid at all, in time independent of its content.
+    fn well_formed(&self, passphrase: &[u8]) -> bool {
+        let length = passphrase.len();
+        if length < self.min_length || length > self.max_length {
+            return false;
+        }
+
+        if !self.ascii_graphic {
+            return true;
+        }
+
+        // Accumulate without branching on individual bytes.
+        let graphic = passphrase
+            .iter()
+            .fold(1u8, |all, &byte| all & u8::from(byte.wrapping_sub(0x21) < 0x5e));
+        graphic == 1
+    }
+}
+
+impl VerifiedCache {
+    fn digest(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> [u8; 32] {
+        // Hmac accepts keys of any length.
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        for part in [client_id.as_bytes(), stored, passphrase] {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        mac.finalize().into_bytes().into()
+    }
+
+    fn contains(&self, digest: &[u8; 32]) -> bool {
+        let verified = self.verified.lock().unwrap_or_else(|poison| poison.into_inner());
+        matches!(verified.get(digest), Some(at) if at.elapsed() < self.ttl)
+    }
+
+    fn insert(&self, digest: [u8; 32]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut verified = self.verified.lock().unwrap_or_else(|poison| poison.into_inner());
+        if verified.len() >= self.capacity && !verified.contains_key(&digest) {
+            let ttl = self.ttl;
+            verified.retain(|_, at| at.elapsed() < ttl);
+        }
+
+        // Only reached by correct passphrases, so the scan is as rare as new clients.
+        if verified.len() >= self.capacity && !verified.contains_key(&digest) {
+            let oldest = verified
+                .iter()
+                .min_by_key(|(_, at)| **at)
+                .map(|(digest, _)| *digest);
+            if let Some(oldest) = oldest {
+                verified.remove(&oldest);
+            }
+        }
+
+        verified.insert(digest, Instant::now());
+    }
+}
+
+impl<P: PasswordPolicy> PasswordPolicy for Prechecked<P> {
+    fn store(&self, client_id: &str, passphrase: &[u8]) -> Vec<u8> {
+        self.inner.store(client_id, passphrase)
+    }
+
+    fn check(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), RegistrarError> {
+        if !self.well_formed(passphrase) {
+            return Err(RegistrarError::Unspecified);
+        }
+
+        let verified = match &self.verified {
+            Some(verified) => verified,
+            None => return self.inner.check(client_id, passphrase, stored),
+        };
+
+        let digest = verified.digest(client_id, passphrase, stored);
+        if verified.contains(&digest) {
+            return Ok(());
+        }
+
+        self.inner.check(client_id, passphrase, stored)?;
+        verified.insert(digest);
+        Ok(())
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 //                             Standard Implementations of Registrars                            //
 ///////////////////////////////////////////////////////////////////////////////////////////////////
@@ -645,6 +1190,18 @@ impl ClientMap {
             .insert(client.client_id.clone(), client.encode(password_policy));
     }
 
+    /// Insert or update a client whose passphrase is already encoded.
+    ///
+    /// The encoding must be one understood by the password policy of this map.
+    pub fn import_client(&mut self, client: EncodedClient) {
+        self.clients.insert(client.client_id.clone(), client);
+    }
+
+    /// Iterate over all registered clients.
+    pub fn clients(&self) -> impl Iterator<Item = &EncodedClient> {
+        self.clients.values()
+    }
+
     /// Change how passwords are encoded while stored.
     pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
         self.password_policy = Some(Box::new(new_policy))
@@ -691,6 +1248,18 @@ impl<R: Registrar + ?Sized> Registrar for &R {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        (**self).refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        (**self).permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        (**self).auth_method(client_id)
+    }
 }
 
 impl<R: Registrar + ?Sized> Registrar for &mut R {
@@ -705,6 +1274,18 @@ impl<R: Registrar + ?Sized> Registrar for &mut R {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        (**self).refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        (**self).permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        (**self).auth_method(client_id)
+    }
 }
 
 impl<R: Registrar + ?Sized> Registrar for Box<R> {
@@ -719,6 +1300,18 @@ impl<R: Registrar + ?Sized> Registrar for Box<R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        (**self).refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        (**self).permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        (**self).auth_method(client_id)
+    }
 }
 
 impl<R: Registrar + ?Sized> Registrar for Rc<R> {
@@ -733,6 +1326,18 @@ impl<R: Registrar + ?Sized> Registrar for Rc<R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        (**self).refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        (**self).permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        (**self).auth_method(client_id)
+    }
 }
 
 impl<R: Registrar + ?Sized> Registrar for Arc<R> {
@@ -747,6 +1352,18 @@ impl<R: Registrar + ?Sized> Registrar for Arc<R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        (**self).refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        (**self).permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        (**self).auth_method(client_id)
+    }
 }
 
 impl<'s, R: Registrar + ?Sized + 's> Registrar for MutexGuard<'s, R> {
@@ -761,6 +1378,18 @@ impl<'s, R: Registrar + ?Sized + 's> Registrar for MutexGuard<'s, R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        (**self).refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        (**self).permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        (**self).auth_method(client_id)
+    }
 }
 
 impl<'s, R: Registrar + ?Sized + 's> Registrar for RwLockWriteGuard<'s, R> {
@@ -775,13 +1404,69 @@ impl<'s, R: Registrar + ?Sized + 's> Registrar for RwLockWriteGuard<'s, R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        (**self).refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        (**self).permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        (**self).auth_method(client_id)
+    }
+}
+
+/// Shares a registrar between threads, holding the read lock only for each lookup.
+///
+/// Registering clients takes the write lock briefly, without blocking flows for their duration.
+/// Wrapped in an `Arc`, each request can build its endpoint with a clone.
+impl<R: Registrar + ?Sized> Registrar for RwLock<R> {
+    fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        self.read()
+            .map_err(|_| RegistrarError::PrimitiveError)?
+            .bound_redirect(bound)
+    }
+
+    fn negotiate(&self, bound: BoundClient, scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
+        self.read()
+            .map_err(|_| RegistrarError::PrimitiveError)?
+            .negotiate(bound, scope)
+    }
+
+    fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        self.read()
+            .map_err(|_| RegistrarError::PrimitiveError)?
+            .check(client_id, passphrase)
+    }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        match self.read() {
+            Ok(registrar) => registrar.refresh_policy(client_id),
+            // Without the registrar there is no reason to hand out a long-lived credential.
+            Err(_) => RefreshPolicy::Never,
+        }
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        match self.read() {
+            Ok(registrar) => registrar.permits_grant(client_id, grant_type),
+            Err(_) => false,
+        }
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        // Authentication fails without the registrar regardless of the method.
+        self.read().ok()?.auth_method(client_id)
+    }
 }
 
 impl Registrar for ClientMap {
     fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
         let client = match self.clients.get(bound.client_id.as_ref()) {
-            None => return Err(RegistrarError::Unspecified),
-            Some(stored) => stored,
+            Some(stored) if !stored.disabled => stored,
+            _ => return Err(RegistrarError::Unspecified),
         };
 
         // Perform exact matching as motivated in the rfc
@@ -807,16 +1492,21 @@ impl Registrar for ClientMap {
         })
     }
 
-    /// Always overrides the scope with a default scope.
-    fn negotiate(&self, bound: BoundClient, _scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
+    /// Grants a requested scope within the allowed scope of the client, otherwise overrides it
+    /// with the default scope.
+    fn negotiate(&self, bound: BoundClient, scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
         let client = self
             .clients
             .get(bound.client_id.as_ref())
             .expect("Bound client appears to not have been constructed with this registrar");
+        let scope = match (scope, &client.allowed_scope) {
+            (Some(scope), Some(allowed)) if allowed.priviledged_to(&scope) => scope,
+            _ => client.default_scope.clone(),
+        };
         Ok(PreGrant {
             client_id: bound.client_id.into_owned(),
             redirect_uri: bound.redirect_uri.into_owned(),
-            scope: client.default_scope.clone(),
+            scope,
         })
     }
 
@@ -832,6 +1522,62 @@ impl Registrar for ClientMap {
 
         Ok(())
     }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.clients
+            .get(client_id)
+            .map(|client| client.refresh_policy)
+            .unwrap_or_default()
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.clients
+            .get(client_id)
+            .is_some_and(|client| client.permits_grant(grant_type))
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.clients.get(client_id)?.auth_method
+    }
+}
+
+impl<R> KnownScopes<R> {
+    /// Validate the requested scopes of a registrar against a registry.
+    pub fn new(registrar: R, registry: ScopeRegistry) -> Self {
+        KnownScopes { registrar, registry }
+    }
+}
+
+impl<R: Registrar> Registrar for KnownScopes<R> {
+    fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        self.registrar.bound_redirect(bound)
+    }
+
+    fn negotiate(&self, bound: BoundClient, scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
+        if let Some(scope) = &scope {
+            self.registry
+                .validate(scope)
+                .map_err(|_| RegistrarError::Unspecified)?;
+        }
+
+        self.registrar.negotiate(bound, scope)
+    }
+
+    fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        self.registrar.check(client_id, passphrase)
+    }
+
+    fn refresh_policy(&self, client_id: &str) -> RefreshPolicy {
+        self.registrar.refresh_policy(client_id)
+    }
+
+    fn permits_grant(&self, client_id: &str, grant_type: GrantType) -> bool {
+        self.registrar.permits_grant(client_id, grant_type)
+    }
+
+    fn auth_method(&self, client_id: &str) -> Option<ClientAuthMethod> {
+        self.registrar.auth_method(client_id)
+    }
 }
 
 #[cfg(test)]
@@ -921,6 +1667,118 @@ mod tests {
         assert!(client.check_authentication(Some(b"")).is_err());
     }
 
+    /// Counts the checks reaching the inner policy.
+    #[derive(Default)]
+    struct Counting {
+        checks: std::sync::atomic::AtomicUsize,
+    }
+
+    impl PasswordPolicy for Counting {
+        fn store(&self, _: &str, passphrase: &[u8]) -> Vec<u8> {
+            passphrase.to_vec()
+        }
+
+        fn check(&self, _: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), RegistrarError> {
+            self.checks.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            match passphrase == stored {
+                true => Ok(()),
+                false => Err(RegistrarError::Unspecified),
+            }
+        }
+    }
+
+    impl Prechecked<Counting> {
+        fn checks(&self) -> usize {
+            self.inner.checks.load(std::sync::atomic::Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn prechecked_passphrases() {
+        let policy = Prechecked::new(Counting::default()).length(4, 8).ascii_graphic();
+        let stored = policy.store("ClientId", b"secret");
+
+        assert!(policy.check("ClientId", b"abc", &stored).is_err());
+        assert!(policy.check("ClientId", b"too long!", &stored).is_err());
+        assert!(policy.check("ClientId", b"sec ret", &stored).is_err());
+        assert!(policy.check("ClientId", "secrét".as_bytes(), &stored).is_err());
+        assert_eq!(policy.checks(), 0);
+
+        assert!(policy.check("ClientId", b"secret", &stored).is_ok());
+        assert!(policy.check("ClientId", b"secreT", &stored).is_err());
+        assert_eq!(policy.checks(), 2);
+    }
+
+    #[test]
+    fn cached_verification() {
+        let policy = Prechecked::new(Counting::default()).cache_verified(8, Duration::from_secs(60));
+        let stored = policy.store("ClientId", b"secret");
+
+        for _ in 0..3 {
+            assert!(policy.check("ClientId", b"secret", &stored).is_ok());
+        }
+        assert_eq!(policy.checks(), 1);
+
+        // Failures are always checked again.
+        for _ in 0..3 {
+            assert!(policy.check("ClientId", b"wrong", &stored).is_err());
+        }
+        assert_eq!(policy.checks(), 4);
+
+        // Results do not carry over to other clients or changed passphrases.
+        assert!(policy.check("OtherId", b"secret", &stored).is_ok());
+        let changed = policy.store("ClientId", b"changed");
+        assert!(policy.check("ClientId", b"secret", &changed).is_err());
+        assert_eq!(policy.checks(), 6);
+
+        let expiring = Prechecked::new(Counting::default()).cache_verified(8, Duration::from_secs(0));
+        assert!(expiring.check("ClientId", b"secret", &stored).is_ok());
+        assert!(expiring.check("ClientId", b"secret", &stored).is_ok());
+        assert_eq!(expiring.checks(), 2);
+    }
+
+    #[test]
+    fn verified_cache_evicts_oldest() {
+        let policy = Prechecked::new(Counting::default()).cache_verified(2, Duration::from_secs(60));
+        let first = policy.store("First", b"first");
+        let second = policy.store("Second", b"second");
+        assert!(policy.check("First", b"first", &first).is_ok());
+        assert!(policy.check("Second", b"second", &second).is_ok());
+
+        // Wrong guesses do not displace anything.
+        for guess in 0..16u8 {
+            assert!(policy.check("First", &[b'x', guess], &first).is_err());
+        }
+        assert!(policy.check("First", b"first", &first).is_ok());
+        assert!(policy.check("Second", b"second", &second).is_ok());
+        assert_eq!(policy.checks(), 18);
+
+        // A third client only pushes out the oldest entry.
+        let third = policy.store("Third", b"third");
+        assert!(policy.check("Third", b"third", &third).is_ok());
+        assert!(policy.check("Second", b"second", &second).is_ok());
+        assert_eq!(policy.checks(), 19);
+        assert!(policy.check("First", b"first", &first).is_ok());
+        assert_eq!(policy.checks(), 20);
+    }
+
+    #[test]
+    fn disabled_client() {
+        let policy = Argon2::default();
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        let mut client = Client::confidential(
+            "ClientId",
+            "https://example.com".parse::<Url>().unwrap().into(),
+            "default".parse().unwrap(),
+            pass,
+        )
+        .encode(&policy);
+        client.disabled = true;
+
+        let registered = RegisteredClient::new(&client, &policy);
+        assert!(registered.check_authentication(Some(pass)).is_err());
+    }
+
     #[test]
     fn with_additional_redirect_uris() {
         let client_id = "ClientId";
@@ -1017,12 +1875,135 @@ mod tests {
         }
     }
 
+    #[test]
+    fn client_builder() {
+        let client = Client::builder("ClientId")
+            .redirect_uris(vec![
+                "https://example.com/foo".parse::<Url>().unwrap(),
+                "https://example.com/bar".parse::<Url>().unwrap(),
+            ])
+            .default_scope("read".parse().unwrap())
+            .allowed_scope("read write".parse().unwrap())
+            .grant_types([GrantType::AuthorizationCode])
+            .passphrase(b"secret")
+            .auth_method(ClientAuthMethod::ClientSecretPost)
+            .metadata("client_name", "Example")
+            .build()
+            .unwrap();
+
+        let mut client_map = ClientMap::new();
+        client_map.register_client(client);
+        assert!(client_map.check("ClientId", Some(b"secret")).is_ok());
+
+        let encoded = client_map.clients().next().unwrap();
+        assert_eq!(encoded.additional_redirect_uris.len(), 1);
+        assert_eq!(encoded.metadata["client_name"], "Example");
+        assert!(encoded.permits_grant(GrantType::AuthorizationCode));
+        assert!(!encoded.permits_grant(GrantType::ClientCredentials));
+
+        let bound = || BoundClient {
+            client_id: Cow::from("ClientId"),
+            redirect_uri: Cow::Owned("https://example.com/bar".parse::<Url>().unwrap().into()),
+        };
+        let negotiated = |scope: Option<&str>| {
+            client_map
+                .negotiate(bound(), scope.map(|scope| scope.parse().unwrap()))
+                .unwrap()
+                .scope
+        };
+        assert_eq!(negotiated(None), "read".parse().unwrap());
+        assert_eq!(negotiated(Some("write")), "write".parse().unwrap());
+        assert_eq!(negotiated(Some("write admin")), "read".parse().unwrap());
+    }
+
+    #[test]
+    fn client_builder_errors() {
+        let url = || "https://example.com".parse::<Url>().unwrap();
+        let scope = || "read".parse::<Scope>().unwrap();
+
+        let missing_uri = Client::builder("ClientId").default_scope(scope()).build();
+        assert_eq!(missing_uri.unwrap_err(), ClientBuilderError::MissingRedirectUri);
+
+        let missing_scope = Client::builder("ClientId").redirect_uri(url()).build();
+        assert_eq!(
+            missing_scope.unwrap_err(),
+            ClientBuilderError::MissingDefaultScope
+        );
+
+        let not_allowed = Client::builder("ClientId")
+            .redirect_uri(url())
+            .default_scope(scope())
+            .allowed_scope("write".parse().unwrap())
+            .build();
+        assert_eq!(
+            not_allowed.unwrap_err(),
+            ClientBuilderError::DefaultScopeNotAllowed
+        );
+
+        let public_with_secret = Client::builder("ClientId")
+            .redirect_uri(url())
+            .default_scope(scope())
+            .auth_method(ClientAuthMethod::ClientSecretBasic)
+            .build();
+        assert_eq!(
+            public_with_secret.unwrap_err(),
+            ClientBuilderError::AuthMethodMismatch
+        );
+    }
+
     #[test]
     fn client_map() {
         let mut client_map = ClientMap::new();
         simple_test_suite(&mut client_map, ClientMap::register_client);
     }
 
+    #[test]
+    fn refresh_policy() {
+        let offline = || "offline_access read".parse::<Scope>().unwrap();
+
+        let mut scope = offline();
+        assert!(RefreshPolicy::OfflineAccess.narrow(&mut scope));
+        assert_eq!(scope, offline());
+
+        let mut scope = offline();
+        assert!(!RefreshPolicy::Never.narrow(&mut scope));
+        assert_eq!(scope, "read".parse().unwrap());
+
+        let mut scope = "read".parse().unwrap();
+        assert!(!RefreshPolicy::OfflineAccess.narrow(&mut scope));
+        assert!(RefreshPolicy::Always.permits(&scope));
+    }
+
+    #[test]
+    fn known_scopes() {
+        use crate::primitives::scope::ScopeInfo;
+
+        let client_id = "ClientId";
+        let mut client_map = ClientMap::new();
+        client_map.register_client(Client::public(
+            client_id,
+            "https://example.com/foo".parse::<Url>().unwrap().into(),
+            "default".parse().unwrap(),
+        ));
+
+        let mut registry = ScopeRegistry::new();
+        registry.register("default", ScopeInfo::new("Default"));
+        let registrar = KnownScopes::new(client_map, registry);
+
+        let bound = || BoundClient {
+            client_id: Cow::from(client_id),
+            redirect_uri: Cow::Owned("https://example.com/foo".parse::<Url>().unwrap().into()),
+        };
+
+        assert!(registrar.negotiate(bound(), None).is_ok());
+        assert!(registrar
+            .negotiate(bound(), Some("default".parse().unwrap()))
+            .is_ok());
+        assert!(registrar
+            .negotiate(bound(), Some("default unknown".parse().unwrap()))
+            .is_err());
+    }
+
     #[test]
     fn ignore_local_port_url_eq_local() {
         let url = IgnoreLocalPortUrl::new("https://localhost/cb").unwrap();
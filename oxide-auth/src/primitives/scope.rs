@@ -1,7 +1,11 @@
 //! Defines the Scope type and parsing/formatting according to the rfc.
-use std::{cmp, fmt, str, error};
+use core::{cmp, fmt, str, error};
+use core::iter::FromIterator;
 
-use std::collections::HashSet;
+use alloc::borrow::ToOwned;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
 
 /// Scope of a given grant or resource, a set of scope-tokens separated by spaces.
@@ -47,7 +51,7 @@ use serde::{Deserialize, Serialize};
 ///
 #[derive(Clone, PartialEq, Eq)]
 pub struct Scope {
-    tokens: HashSet<String>,
+    tokens: BTreeSet<String>,
 }
 
 impl Serialize for Scope {
@@ -92,12 +96,209 @@ impl Scope {
         self <= rhs
     }
 
+    /// Determines if a resource protected by this scope should allow access to a token with the
+    /// grant on the right side, matching the scope tokens in the given mode.
+    pub fn allow_access_with(&self, rhs: &Scope, matching: ScopeMatching) -> bool {
+        match matching {
+            ScopeMatching::Exact => self.allow_access(rhs),
+            ScopeMatching::Hierarchical => self
+                .tokens
+                .iter()
+                .all(|required| rhs.tokens.iter().any(|granted| covers(granted, required))),
+        }
+    }
+
+    /// The scope tokens contained in both scopes.
+    pub fn intersection(&self, rhs: &Scope) -> Scope {
+        Scope {
+            tokens: self.tokens.intersection(&rhs.tokens).cloned().collect(),
+        }
+    }
+
+    /// The scope tokens of this scope that are not contained in the other one.
+    pub fn difference(&self, rhs: &Scope) -> Scope {
+        Scope {
+            tokens: self.tokens.difference(&rhs.tokens).cloned().collect(),
+        }
+    }
+
+    /// The scope tokens contained in either scope.
+    pub fn union(&self, rhs: &Scope) -> Scope {
+        Scope {
+            tokens: self.tokens.union(&rhs.tokens).cloned().collect(),
+        }
+    }
+
+    /// Determines if the scope contains no scope tokens.
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
     /// Create an iterator over the individual scopes.
     pub fn iter(&self) -> impl Iterator<Item = &str> {
         self.tokens.iter().map(AsRef::as_ref)
     }
 }
 
+/// How the scope tokens of a grant are matched against those required by a resource.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ScopeMatching {
+    /// A required scope token must be granted as is.
+    #[default]
+    Exact,
+
+    /// Scope tokens form a hierarchy of `:` separated segments.
+    ///
+    /// A required scope token such as `repo:read` is also satisfied by a granted parent, `repo`,
+    /// or by a wildcard of a parent, `repo:*` or `*`.
+    Hierarchical,
+}
+
+/// Whether a granted scope token satisfies a required one in a hierarchy.
+fn covers(granted: &str, required: &str) -> bool {
+    if granted == required || granted == "*" {
+        return true;
+    }
+
+    let parent = granted.strip_suffix(":*").unwrap_or(granted);
+    match required.strip_prefix(parent) {
+        Some(rest) => rest.starts_with(':'),
+        None => false,
+    }
+}
+
+/// How much harm a client could do with a scope, for owners deciding whether to approve it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sensitivity {
+    /// Access to public or otherwise harmless data.
+    Low,
+
+    /// The sensitivity of scopes not explicitly classified.
+    #[default]
+    Normal,
+
+    /// Access to private data or to actions that are hard to undo.
+    High,
+}
+
+/// The metadata of a known scope token.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScopeInfo {
+    /// A short human readable name.
+    pub name: String,
+
+    /// A longer description of the access granted.
+    pub description: Option<String>,
+
+    /// How sensitive the granted access is.
+    pub sensitivity: Sensitivity,
+}
+
+/// The scope tokens known to a server, with metadata describing them.
+///
+/// Provides the descriptions of consent pages, the list of `scopes_supported` published in server
+/// metadata, and can reject requests for scopes the server does not know.
+#[derive(Clone, Debug, Default)]
+pub struct ScopeRegistry {
+    scopes: BTreeMap<String, ScopeInfo>,
+}
+
+impl ScopeInfo {
+    /// A scope of normal sensitivity, without description.
+    pub fn new(name: &str) -> Self {
+        ScopeInfo {
+            name: name.to_owned(),
+            description: None,
+            sensitivity: Sensitivity::Normal,
+        }
+    }
+
+    /// Describe the access granted by the scope.
+    pub fn with_description(self, description: &str) -> Self {
+        ScopeInfo {
+            description: Some(description.to_owned()),
+            ..self
+        }
+    }
+
+    /// Classify the sensitivity of the scope.
+    pub fn with_sensitivity(self, sensitivity: Sensitivity) -> Self {
+        ScopeInfo { sensitivity, ..self }
+    }
+}
+
+impl ScopeRegistry {
+    /// A registry without any known scope.
+    pub fn new() -> Self {
+        ScopeRegistry::default()
+    }
+
+    /// Insert or replace the metadata of a scope token.
+    pub fn register(&mut self, token: &str, info: ScopeInfo) {
+        self.scopes.insert(token.to_owned(), info);
+    }
+
+    /// The metadata of a scope token, if it is known.
+    pub fn get(&self, token: &str) -> Option<&ScopeInfo> {
+        self.scopes.get(token)
+    }
+
+    /// Iterate over all known scope tokens and their metadata, sorted by token.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ScopeInfo)> {
+        self.scopes.iter().map(|(token, info)| (token.as_str(), info))
+    }
+
+    /// All known scope tokens, sorted, as published in the `scopes_supported` server metadata.
+    pub fn scopes_supported(&self) -> Vec<&str> {
+        self.scopes.keys().map(String::as_str).collect()
+    }
+
+    /// The scope tokens of a scope that are not known.
+    pub fn unknown(&self, scope: &Scope) -> Scope {
+        Scope {
+            tokens: scope
+                .tokens
+                .iter()
+                .filter(|token| !self.scopes.contains_key(token.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Check that all tokens of a requested scope are known.
+    ///
+    /// Returns the unknown tokens as error.
+    pub fn validate(&self, scope: &Scope) -> Result<(), Scope> {
+        let unknown = self.unknown(scope);
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(unknown)
+        }
+    }
+
+    /// The highest sensitivity of the tokens in a scope, ignoring unknown ones.
+    pub fn sensitivity(&self, scope: &Scope) -> Option<Sensitivity> {
+        scope
+            .iter()
+            .filter_map(|token| self.get(token))
+            .map(|info| info.sensitivity)
+            .max()
+    }
+}
+
+impl FromIterator<(String, ScopeInfo)> for ScopeRegistry {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (String, ScopeInfo)>,
+    {
+        ScopeRegistry {
+            scopes: iter.into_iter().collect(),
+        }
+    }
+}
+
 /// Error returned from parsing a scope as encoded in an authorization token request.
 #[derive(Debug)]
 pub enum ParseScopeErr {
@@ -220,6 +421,63 @@ mod tests {
         assert!(!scope_uncmp.allow_access(&scope_base));
     }
 
+    #[test]
+    fn test_hierarchical() {
+        let required = "repo:read user".parse::<Scope>().unwrap();
+        let exact = "repo:read user".parse::<Scope>().unwrap();
+        let parent = "repo user".parse::<Scope>().unwrap();
+        let wildcard = "repo:* user:email".parse::<Scope>().unwrap();
+        let sibling = "repo:write user".parse::<Scope>().unwrap();
+        let prefix = "rep user".parse::<Scope>().unwrap();
+
+        assert!(required.allow_access_with(&exact, ScopeMatching::Hierarchical));
+        assert!(required.allow_access_with(&parent, ScopeMatching::Hierarchical));
+        assert!(!required.allow_access_with(&parent, ScopeMatching::Exact));
+        assert!(!required.allow_access_with(&wildcard, ScopeMatching::Hierarchical));
+        assert!(!required.allow_access_with(&sibling, ScopeMatching::Hierarchical));
+        assert!(!required.allow_access_with(&prefix, ScopeMatching::Hierarchical));
+
+        let wildcard = "repo:* user".parse::<Scope>().unwrap();
+        assert!(required.allow_access_with(&wildcard, ScopeMatching::Hierarchical));
+        let all = "*".parse::<Scope>().unwrap();
+        assert!(required.allow_access_with(&all, ScopeMatching::Hierarchical));
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let lhs = "cap1 cap2".parse::<Scope>().unwrap();
+        let rhs = "cap2 cap3".parse::<Scope>().unwrap();
+
+        assert_eq!(lhs.intersection(&rhs), "cap2".parse().unwrap());
+        assert_eq!(lhs.difference(&rhs), "cap1".parse().unwrap());
+        assert_eq!(lhs.union(&rhs), "cap1 cap2 cap3".parse().unwrap());
+        assert!(lhs.difference(&lhs).is_empty());
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = ScopeRegistry::new();
+        registry.register("read", ScopeInfo::new("Read").with_sensitivity(Sensitivity::Low));
+        registry.register(
+            "admin",
+            ScopeInfo::new("Administrate")
+                .with_description("Manage all settings")
+                .with_sensitivity(Sensitivity::High),
+        );
+
+        assert_eq!(registry.scopes_supported(), vec!["admin", "read"]);
+        assert_eq!(registry.get("read").unwrap().name, "Read");
+        assert!(registry.validate(&"read admin".parse().unwrap()).is_ok());
+
+        let requested = "read write".parse().unwrap();
+        assert_eq!(registry.validate(&requested), Err("write".parse().unwrap()));
+        assert_eq!(registry.sensitivity(&requested), Some(Sensitivity::Low));
+        assert_eq!(
+            registry.sensitivity(&"read admin".parse().unwrap()),
+            Some(Sensitivity::High)
+        );
+    }
+
     #[test]
     fn test_iterating() {
         let scope = "cap1 cap2 cap3".parse::<Scope>().unwrap();
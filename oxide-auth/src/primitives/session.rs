@@ -0,0 +1,203 @@
+//! Tracks the sessions in which resource owners authenticated.
+//!
+//! The application starts a session in a [`SessionStore`] when an owner logs in, and identifies it
+//! in later requests with [`OwnerSolicitor::session_id`], typically from a cookie. The
+//! authorization flow then knows who is logged in, and since when, without asking the owner:
+//!
+//! * `prompt=none` requests are answered without any interaction. They are granted to the owner of
+//!   the session if they already consented to the client, and refused with `login_required` or
+//!   `consent_required` otherwise.
+//! * `max_age` requests are only granted to an owner who authenticated at most that many seconds
+//!   ago, and refused with `login_required` otherwise.
+//! * Each client issued a code in the session is recorded with it, so that all of them can be
+//!   notified or have their tokens revoked when the session [`end`]s, for single logout.
+//!
+//! [`SessionStore`]: trait.SessionStore.html
+//! [`OwnerSolicitor::session_id`]: ../../endpoint/trait.OwnerSolicitor.html#method.session_id
+//! [`end`]: trait.SessionStore.html#tymethod.end
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use chrono::{DateTime, Duration, Utc};
+
+/// An authenticated session of a resource owner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// The resource owner who authenticated.
+    pub owner_id: String,
+
+    /// When the owner last authenticated.
+    pub auth_time: DateTime<Utc>,
+
+    /// The clients that were issued a code in the session.
+    pub clients: Vec<String>,
+}
+
+/// Stores the sessions of resource owners, by an id known to the application.
+pub trait SessionStore {
+    /// The session with the given id, if it exists.
+    fn session(&self, session_id: &str) -> Option<Session>;
+
+    /// Start a session, or replace the session with the same id after the owner authenticated again.
+    fn start(&mut self, session_id: &str, session: Session);
+
+    /// Record that a client was issued a code in the session.
+    fn associate(&mut self, session_id: &str, client_id: &str);
+
+    /// End a session, returning it with the clients that must be informed of the logout.
+    fn end(&mut self, session_id: &str) -> Option<Session>;
+}
+
+/// An in-memory hash map of sessions.
+#[derive(Default)]
+pub struct SessionMap {
+    sessions: HashMap<String, Session>,
+}
+
+impl Session {
+    /// A session of an owner who has just authenticated.
+    pub fn new(owner_id: &str) -> Self {
+        Session {
+            owner_id: owner_id.to_owned(),
+            auth_time: Utc::now(),
+            clients: Vec::new(),
+        }
+    }
+
+    /// Whether the owner authenticated at most `max_age` before `now`.
+    pub fn authenticated_within(&self, max_age: Duration, now: DateTime<Utc>) -> bool {
+        self.auth_time + max_age >= now
+    }
+}
+
+impl SessionMap {
+    /// An empty store.
+    pub fn new() -> Self {
+        SessionMap::default()
+    }
+}
+
+impl SessionStore for SessionMap {
+    fn session(&self, session_id: &str) -> Option<Session> {
+        self.sessions.get(session_id).cloned()
+    }
+
+    fn start(&mut self, session_id: &str, session: Session) {
+        self.sessions.insert(session_id.to_owned(), session);
+    }
+
+    fn associate(&mut self, session_id: &str, client_id: &str) {
+        if let Some(session) = self.sessions.get_mut(session_id) {
+            if !session.clients.iter().any(|client| client == client_id) {
+                session.clients.push(client_id.to_owned());
+            }
+        }
+    }
+
+    fn end(&mut self, session_id: &str) -> Option<Session> {
+        self.sessions.remove(session_id)
+    }
+}
+
+impl<S: SessionStore + ?Sized> SessionStore for &mut S {
+    fn session(&self, session_id: &str) -> Option<Session> {
+        (**self).session(session_id)
+    }
+
+    fn start(&mut self, session_id: &str, session: Session) {
+        (**self).start(session_id, session)
+    }
+
+    fn associate(&mut self, session_id: &str, client_id: &str) {
+        (**self).associate(session_id, client_id)
+    }
+
+    fn end(&mut self, session_id: &str) -> Option<Session> {
+        (**self).end(session_id)
+    }
+}
+
+impl<S: SessionStore + ?Sized> SessionStore for Box<S> {
+    fn session(&self, session_id: &str) -> Option<Session> {
+        (**self).session(session_id)
+    }
+
+    fn start(&mut self, session_id: &str, session: Session) {
+        (**self).start(session_id, session)
+    }
+
+    fn associate(&mut self, session_id: &str, client_id: &str) {
+        (**self).associate(session_id, client_id)
+    }
+
+    fn end(&mut self, session_id: &str) -> Option<Session> {
+        (**self).end(session_id)
+    }
+}
+
+impl<'a, S: SessionStore + ?Sized> SessionStore for MutexGuard<'a, S> {
+    fn session(&self, session_id: &str) -> Option<Session> {
+        (**self).session(session_id)
+    }
+
+    fn start(&mut self, session_id: &str, session: Session) {
+        (**self).start(session_id, session)
+    }
+
+    fn associate(&mut self, session_id: &str, client_id: &str) {
+        (**self).associate(session_id, client_id)
+    }
+
+    fn end(&mut self, session_id: &str) -> Option<Session> {
+        (**self).end(session_id)
+    }
+}
+
+impl<'a, S: SessionStore + ?Sized> SessionStore for RwLockWriteGuard<'a, S> {
+    fn session(&self, session_id: &str) -> Option<Session> {
+        (**self).session(session_id)
+    }
+
+    fn start(&mut self, session_id: &str, session: Session) {
+        (**self).start(session_id, session)
+    }
+
+    fn associate(&mut self, session_id: &str, client_id: &str) {
+        (**self).associate(session_id, client_id)
+    }
+
+    fn end(&mut self, session_id: &str) -> Option<Session> {
+        (**self).end(session_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn associate_and_end() {
+        let mut store = SessionMap::new();
+        store.start("sid", Session::new("alice"));
+        store.associate("sid", "client");
+        store.associate("sid", "client");
+        store.associate("sid", "other");
+        store.associate("unknown", "client");
+
+        let session = store.session("sid").unwrap();
+        assert_eq!(session.owner_id, "alice");
+        assert_eq!(session.clients, vec!["client".to_owned(), "other".to_owned()]);
+        assert!(store.session("unknown").is_none());
+
+        assert_eq!(store.end("sid"), Some(session));
+        assert!(store.end("sid").is_none());
+    }
+
+    #[test]
+    fn authenticated_within() {
+        let session = Session::new("alice");
+        let later = session.auth_time + Duration::seconds(90);
+        assert!(session.authenticated_within(Duration::seconds(120), later));
+        assert!(!session.authenticated_within(Duration::seconds(60), later));
+    }
+}
@@ -0,0 +1,341 @@
+//! Primitives with an injected clock and seeded randomness, for reproducible tests.
+//!
+//! Only available with the `test-helpers` feature, usually enabled on the `dev-dependencies` entry
+//! of `oxide-auth`. The seeded tokens are predictable and must never be issued in production.
+//!
+//! The in-memory primitives draw their tokens from the operating system and leave expiry to the
+//! system time. Tests of applications built on them can thus neither compare tokens with known
+//! values nor check expiry without sleeping. A [`TestAuthorizer`] and [`TestIssuer`] instead
+//! generate their codes and tokens from a seed and judge expiry by a [`Clock`], usually a
+//! [`ManualClock`] that the test advances.
+//!
+//! ```
+//! # extern crate oxide_auth;
+//! # extern crate chrono;
+//! use chrono::Duration;
+//! use oxide_auth::primitives::issuer::Issuer;
+//! use oxide_auth::primitives::testing::{ManualClock, TestIssuer};
+//! # use oxide_auth::primitives::grant::{Extensions, Grant};
+//! # fn grant() -> Grant {
+//! #     Grant {
+//! #         owner_id: "owner".into(),
+//! #         client_id: "client".into(),
+//! #         scope: "default".parse().unwrap(),
+//! #         redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+//! #         until: chrono::Utc::now(),
+//! #         extensions: Extensions::new(),
+//! #     }
+//! # }
+//!
+//! let clock = ManualClock::new();
+//! let mut issuer = TestIssuer::new(42, clock.clone());
+//! let token = issuer.issue(grant()).unwrap().token;
+//!
+//! // The same seed always produces the same tokens.
+//! let mut replay = TestIssuer::new(42, ManualClock::new());
+//! assert_eq!(token, replay.issue(grant()).unwrap().token);
+//!
+//! // Expire the token without sleeping.
+//! clock.advance(Duration::hours(1));
+//! assert_eq!(issuer.recover_token(&token), Ok(None));
+//! ```
+//!
+//! The flows still compare the expiry of grants with the system time, so that a clock must not be
+//! set before the current time when the primitives are used through an endpoint. Start it with
+//! `ManualClock::new` or at a fixed time in the future.
+//!
+//! [`TestAuthorizer`]: struct.TestAuthorizer.html
+//! [`TestIssuer`]: struct.TestIssuer.html
+//! [`Clock`]: trait.Clock.html
+//! [`ManualClock`]: struct.ManualClock.html
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Duration, Utc};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use super::authorizer::{AuthMap, Authorizer};
+use super::generator::RandomGenerator;
+use super::grant::Grant;
+use super::issuer::{IssuedToken, Issuer, RefreshedToken, TokenMap};
+use super::Time;
+
+/// The source of the current time.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> Time;
+}
+
+/// The system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+/// A clock that only moves when told to.
+///
+/// Clones share the time, so a test keeps one to advance the clock of the primitives.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Time>>,
+}
+
+/// An authorizer with seeded codes, whose codes expire by a clock.
+///
+/// The expiry of each grant is set when authorizing it, to the current time of the clock plus the
+/// lifetime of codes.
+pub struct TestAuthorizer<C = ManualClock> {
+    inner: AuthMap<RandomGenerator<Mutex<StdRng>>>,
+    clock: C,
+    lifetime: Duration,
+}
+
+/// An issuer with seeded tokens, whose tokens expire by a clock.
+///
+/// Access tokens are valid for an hour by default, refresh tokens until they are used or revoked.
+/// Both lifetimes count from the time of the clock when the token was issued.
+pub struct TestIssuer<C = ManualClock> {
+    inner: TokenMap<RandomGenerator<Mutex<StdRng>>>,
+    clock: C,
+    access_lifetime: Duration,
+    refresh_lifetime: Option<Duration>,
+    /// When each refresh token was issued.
+    refresh_issued: HashMap<String, Time>,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Time {
+        Utc::now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Time {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> Time {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Time {
+        (**self).now()
+    }
+}
+
+impl ManualClock {
+    /// A clock stopped at the current system time.
+    pub fn new() -> Self {
+        ManualClock::at(Utc::now())
+    }
+
+    /// A clock stopped at the time.
+    pub fn at(now: Time) -> Self {
+        ManualClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    /// Set the clock to a time.
+    pub fn set(&self, now: Time) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Time {
+        *self.now.lock().unwrap()
+    }
+}
+
+impl<C: Clock> TestAuthorizer<C> {
+    /// Generate 16 byte codes from the seed, with a lifetime of ten minutes.
+    pub fn new(seed: u64, clock: C) -> Self {
+        TestAuthorizer {
+            inner: AuthMap::new(seeded(seed)),
+            clock,
+            lifetime: Duration::minutes(10),
+        }
+    }
+
+    /// Set the lifetime of codes authorized from now on.
+    pub fn valid_for(&mut self, lifetime: Duration) {
+        self.lifetime = lifetime;
+    }
+}
+
+impl<C: Clock> Authorizer for TestAuthorizer<C> {
+    fn authorize(&mut self, mut grant: Grant) -> Result<String, ()> {
+        grant.until = self.clock.now() + self.lifetime;
+        self.inner.authorize(grant)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        let now = self.clock.now();
+        Ok(self.inner.extract(code)?.filter(|grant| grant.until > now))
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.inner.revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.inner.revoke_grant(owner_id, client_id)
+    }
+}
+
+impl<C: Clock> TestIssuer<C> {
+    /// Generate 16 byte tokens from the seed.
+    pub fn new(seed: u64, clock: C) -> Self {
+        TestIssuer {
+            inner: TokenMap::new(seeded(seed)),
+            clock,
+            access_lifetime: Duration::hours(1),
+            refresh_lifetime: None,
+            refresh_issued: HashMap::new(),
+        }
+    }
+
+    /// Set the lifetime of access tokens issued from now on.
+    pub fn valid_for(&mut self, lifetime: Duration) {
+        self.access_lifetime = lifetime;
+    }
+
+    /// Let refresh tokens expire after the lifetime, counted from when they were issued.
+    pub fn refresh_valid_for(&mut self, lifetime: Duration) {
+        self.refresh_lifetime = Some(lifetime);
+    }
+
+    fn refresh_expired(&self, refresh: &str) -> bool {
+        let issued = match self.refresh_issued.get(refresh) {
+            Some(issued) => *issued,
+            None => return false,
+        };
+
+        match self.refresh_lifetime {
+            Some(lifetime) => issued + lifetime <= self.clock.now(),
+            None => false,
+        }
+    }
+}
+
+impl<C: Clock> Issuer for TestIssuer<C> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
+        let now = self.clock.now();
+        grant.until = now + self.access_lifetime;
+        let issued = self.inner.issue(grant)?;
+        if let Some(refresh) = &issued.refresh {
+            self.refresh_issued.insert(refresh.clone(), now);
+        }
+        Ok(issued)
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, ()> {
+        if self.refresh_expired(refresh) {
+            return Err(());
+        }
+
+        let now = self.clock.now();
+        grant.until = now + self.access_lifetime;
+        let refreshed = self.inner.refresh(refresh, grant)?;
+        self.refresh_issued.remove(refresh);
+        if let Some(refresh) = &refreshed.refresh {
+            self.refresh_issued.insert(refresh.clone(), now);
+        }
+        Ok(refreshed)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        let now = self.clock.now();
+        Ok(self.inner.recover_token(token)?.filter(|grant| grant.until > now))
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        if self.refresh_expired(token) {
+            return Ok(None);
+        }
+
+        self.inner.recover_refresh(token)
+    }
+
+    fn revoke_client(&mut self, client_id: &str) -> Result<usize, ()> {
+        self.inner.revoke_client(client_id)
+    }
+
+    fn revoke_grant(&mut self, owner_id: &str, client_id: &str) -> Result<usize, ()> {
+        self.inner.revoke_grant(owner_id, client_id)
+    }
+}
+
+fn seeded(seed: u64) -> RandomGenerator<Mutex<StdRng>> {
+    RandomGenerator::with_source(16, Mutex::new(StdRng::seed_from_u64(seed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::grant::Extensions;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "owner".into(),
+            client_id: "client".into(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn reproducible_tokens() {
+        let mut first = TestIssuer::new(7, ManualClock::new());
+        let mut second = TestIssuer::new(7, ManualClock::new());
+        let issued = first.issue(grant()).unwrap();
+        let replayed = second.issue(grant()).unwrap();
+        assert_eq!(issued.token, replayed.token);
+        assert_eq!(issued.refresh, replayed.refresh);
+
+        let mut first = TestAuthorizer::new(7, ManualClock::new());
+        let mut second = TestAuthorizer::new(7, ManualClock::new());
+        assert_eq!(first.authorize(grant()), second.authorize(grant()));
+    }
+
+    #[test]
+    fn expiry_by_clock() {
+        let clock = ManualClock::new();
+        let mut issuer = TestIssuer::new(0, clock.clone());
+        issuer.valid_for(Duration::minutes(5));
+        issuer.refresh_valid_for(Duration::days(1));
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        assert_eq!(issued.until, clock.now() + Duration::minutes(5));
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(issuer.recover_token(&issued.token), Ok(None));
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_some());
+
+        clock.advance(Duration::days(1));
+        assert_eq!(issuer.recover_refresh(&refresh), Ok(None));
+        assert!(issuer.refresh(&refresh, grant()).is_err());
+
+        let mut authorizer = TestAuthorizer::new(0, clock.clone());
+        let code = authorizer.authorize(grant()).unwrap();
+        clock.advance(Duration::minutes(10));
+        assert_eq!(authorizer.extract(&code), Ok(None));
+    }
+}
@@ -0,0 +1,469 @@
+//! Resource sets and permission tickets of User-Managed Access.
+//!
+//! With UMA 2.0 a resource owner registers their resources at the authorization server and
+//! decides, by a policy, which other parties may access them. Such a requesting party does not need
+//! an account at the resource server. When their client is refused at a resource, the resource
+//! server registers the permissions it needs as a ticket. The client redeems the ticket at the
+//! token endpoint with the `urn:ietf:params:oauth:grant-type:uma-ticket` grant, possibly
+//! presenting claims about the requesting party, and the policy of the owner decides whether a
+//! requesting party token, an RPT, is issued.
+//!
+//! A [`ResourceSetStore`] keeps the registered resources together with the outstanding tickets,
+//! and a [`SharingPolicy`] makes the decisions of the owners. The endpoint flows using them are
+//! `PermissionFlow` and `UmaGrantFlow`. The permissions of an issued RPT are kept with its grant
+//! and read back with [`permissions`].
+//!
+//! [`ResourceSetStore`]: trait.ResourceSetStore.html
+//! [`SharingPolicy`]: trait.SharingPolicy.html
+//! [`permissions`]: fn.permissions.html
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value as JsonValue};
+
+use super::generator::{OsRandom, RandomSource};
+use super::grant::{Grant, Value};
+use super::scope::Scope;
+use super::Url;
+
+/// The identifier of the grant extension holding the permissions of an RPT.
+const IDENTIFIER: &str = "permissions";
+
+/// A resource registered by its owner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceSet {
+    /// Identifies the resource, chosen by the resource server.
+    pub id: String,
+
+    /// The owner of the resource, who decides on access by others.
+    pub owner_id: String,
+
+    /// A name of the resource, shown to its owner.
+    pub name: String,
+
+    /// The scopes that can be requested for the resource.
+    pub scopes: Scope,
+}
+
+/// Access to one resource with some of its scopes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permission {
+    /// The registered resource.
+    pub resource_id: String,
+
+    /// The requested or granted scopes of the resource.
+    pub scope: Scope,
+}
+
+/// Keeps registered resources and the outstanding permission tickets.
+pub trait ResourceSetStore {
+    /// Register a resource, or replace the registration of its owner.
+    ///
+    /// Fails with `Conflict` if a resource with the same id belongs to another owner.
+    fn register(&mut self, resource: ResourceSet) -> Result<(), ResourceSetError>;
+
+    /// The resource with an id, if it is registered.
+    fn resource_set(&self, id: &str) -> Option<ResourceSet>;
+
+    /// Remove a resource of an owner, returning it if it was registered.
+    fn remove(&mut self, owner_id: &str, id: &str) -> Option<ResourceSet>;
+
+    /// The resources registered by an owner.
+    fn resource_sets(&self, owner_id: &str) -> Vec<ResourceSet>;
+
+    /// Create a ticket for requested permissions.
+    fn ticket(&mut self, permissions: Vec<Permission>) -> Result<String, ResourceSetError>;
+
+    /// Redeem a ticket for its permissions.
+    ///
+    /// A ticket can be redeemed once. Expired and unknown tickets have no permissions.
+    fn redeem(&mut self, ticket: &str) -> Option<Vec<Permission>>;
+}
+
+/// The store refused or failed an operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceSetError {
+    /// The resource is registered by another owner.
+    Conflict,
+
+    /// The store could not complete the operation.
+    Unavailable,
+}
+
+/// A request of a party for access to a resource of another owner.
+#[derive(Clone, Copy, Debug)]
+pub struct PermissionRequest<'a> {
+    /// The client requesting the RPT.
+    pub client_id: &'a str,
+
+    /// The requested resource.
+    pub resource: &'a ResourceSet,
+
+    /// The requested scopes of the resource.
+    pub scope: &'a Scope,
+
+    /// Claims about the requesting party presented by the client, if any.
+    pub claim_token: Option<&'a str>,
+
+    /// The format of the claims, such as `http://openid.net/specs/openid-connect-core-1_0.html#IDToken`.
+    pub claim_token_format: Option<&'a str>,
+}
+
+/// The decision of an owner on a permission request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SharingDecision {
+    /// Grant some of the requested scopes.
+    Grant(Scope),
+
+    /// More claims about the requesting party are needed.
+    ///
+    /// The client can present them with a new ticket, or redirect the requesting party to the
+    /// claims interaction endpoint to gather them interactively.
+    NeedInfo {
+        /// The names of the missing claims.
+        required_claims: Vec<String>,
+
+        /// The claims interaction endpoint, if claims are gathered interactively.
+        redirect_user: Option<Url>,
+    },
+
+    /// The owner has been asked and has not decided yet.
+    ///
+    /// The client may retry with a new ticket later.
+    RequestSubmitted,
+
+    /// The request is denied.
+    Denied,
+}
+
+/// Decides on requests for access to the resources of owners.
+///
+/// Closures taking the request are policies as well.
+pub trait SharingPolicy {
+    /// Decide on a request for one resource.
+    fn decide(&mut self, request: &PermissionRequest) -> SharingDecision;
+}
+
+/// Resources and tickets held in process memory.
+///
+/// Tickets expire after a fixed lifetime.
+pub struct ResourceSetMap<S: RandomSource = OsRandom> {
+    source: S,
+    lifetime: Duration,
+    resources: HashMap<String, ResourceSet>,
+    tickets: HashMap<String, (Vec<Permission>, DateTime<Utc>)>,
+}
+
+impl ResourceSet {
+    /// A resource of an owner with the scopes that can be requested for it.
+    pub fn new(id: &str, owner_id: &str, name: &str, scopes: Scope) -> Self {
+        ResourceSet {
+            id: id.to_owned(),
+            owner_id: owner_id.to_owned(),
+            name: name.to_owned(),
+            scopes,
+        }
+    }
+}
+
+impl Permission {
+    /// Access to a resource with some scopes.
+    pub fn new(resource_id: &str, scope: Scope) -> Self {
+        Permission {
+            resource_id: resource_id.to_owned(),
+            scope,
+        }
+    }
+
+    /// The permission as a member of the `permissions` of an introspection response.
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "resource_id": self.resource_id,
+            "resource_scopes": self.scope.iter().collect::<Vec<_>>(),
+        })
+    }
+
+    /// Read a permission from its JSON form.
+    fn from_json(value: &JsonValue) -> Option<Self> {
+        let resource_id = value["resource_id"].as_str()?;
+        let scopes = value["resource_scopes"]
+            .as_array()?
+            .iter()
+            .map(JsonValue::as_str)
+            .collect::<Option<Vec<_>>>()?;
+        let scope = scopes.join(" ").parse().ok()?;
+        Some(Permission::new(resource_id, scope))
+    }
+}
+
+/// The permissions of an RPT, if the grant is one.
+pub fn permissions(grant: &Grant) -> Option<Vec<Permission>> {
+    let (_, stored) = grant
+        .extensions
+        .private()
+        .find(|(identifier, _)| *identifier == IDENTIFIER)?;
+    let stored: JsonValue = serde_json::from_str(stored?).ok()?;
+    stored.as_array()?.iter().map(Permission::from_json).collect()
+}
+
+/// Keep the permissions of an RPT with its grant.
+pub fn attach_permissions(grant: &mut Grant, permissions: &[Permission]) {
+    let stored: Vec<_> = permissions.iter().map(Permission::to_json).collect();
+    let stored = Value::private(Some(JsonValue::Array(stored).to_string()));
+    grant.extensions.set_raw(IDENTIFIER.to_owned(), stored);
+}
+
+impl ResourceSetMap {
+    /// An empty store with tickets from the random source of the operating system, expiring after
+    /// five minutes.
+    pub fn new() -> Self {
+        ResourceSetMap::with_source(OsRandom)
+    }
+}
+
+impl Default for ResourceSetMap {
+    fn default() -> Self {
+        ResourceSetMap::new()
+    }
+}
+
+impl<S: RandomSource> ResourceSetMap<S> {
+    /// An empty store with tickets from another random source, expiring after five minutes.
+    pub fn with_source(source: S) -> Self {
+        ResourceSetMap {
+            source,
+            lifetime: Duration::minutes(5),
+            resources: HashMap::new(),
+            tickets: HashMap::new(),
+        }
+    }
+
+    /// Expire tickets after another lifetime.
+    pub fn with_lifetime(self, lifetime: Duration) -> Self {
+        ResourceSetMap { lifetime, ..self }
+    }
+}
+
+impl<S: RandomSource> ResourceSetStore for ResourceSetMap<S> {
+    fn register(&mut self, resource: ResourceSet) -> Result<(), ResourceSetError> {
+        match self.resources.get(&resource.id) {
+            Some(existing) if existing.owner_id != resource.owner_id => Err(ResourceSetError::Conflict),
+            _ => {
+                self.resources.insert(resource.id.clone(), resource);
+                Ok(())
+            }
+        }
+    }
+
+    fn resource_set(&self, id: &str) -> Option<ResourceSet> {
+        self.resources.get(id).cloned()
+    }
+
+    fn remove(&mut self, owner_id: &str, id: &str) -> Option<ResourceSet> {
+        match self.resources.get(id) {
+            Some(existing) if existing.owner_id == owner_id => self.resources.remove(id),
+            _ => None,
+        }
+    }
+
+    fn resource_sets(&self, owner_id: &str) -> Vec<ResourceSet> {
+        self.resources
+            .values()
+            .filter(|resource| resource.owner_id == owner_id)
+            .cloned()
+            .collect()
+    }
+
+    fn ticket(&mut self, permissions: Vec<Permission>) -> Result<String, ResourceSetError> {
+        let now = Utc::now();
+        self.tickets.retain(|_, (_, until)| *until > now);
+
+        let mut bytes = [0; 16];
+        self.source
+            .fill(&mut bytes)
+            .map_err(|_| ResourceSetError::Unavailable)?;
+        let ticket = URL_SAFE_NO_PAD.encode(bytes);
+        self.tickets
+            .insert(ticket.clone(), (permissions, now + self.lifetime));
+        Ok(ticket)
+    }
+
+    fn redeem(&mut self, ticket: &str) -> Option<Vec<Permission>> {
+        match self.tickets.remove(ticket) {
+            Some((permissions, until)) if until > Utc::now() => Some(permissions),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ResourceSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResourceSetError::Conflict => f.write_str("resource is registered by another owner"),
+            ResourceSetError::Unavailable => f.write_str("resource set store is unavailable"),
+        }
+    }
+}
+
+impl std::error::Error for ResourceSetError {}
+
+impl<F> SharingPolicy for F
+where
+    F: FnMut(&PermissionRequest) -> SharingDecision,
+{
+    fn decide(&mut self, request: &PermissionRequest) -> SharingDecision {
+        self(request)
+    }
+}
+
+impl<T: ResourceSetStore + ?Sized> ResourceSetStore for &mut T {
+    fn register(&mut self, resource: ResourceSet) -> Result<(), ResourceSetError> {
+        (**self).register(resource)
+    }
+
+    fn resource_set(&self, id: &str) -> Option<ResourceSet> {
+        (**self).resource_set(id)
+    }
+
+    fn remove(&mut self, owner_id: &str, id: &str) -> Option<ResourceSet> {
+        (**self).remove(owner_id, id)
+    }
+
+    fn resource_sets(&self, owner_id: &str) -> Vec<ResourceSet> {
+        (**self).resource_sets(owner_id)
+    }
+
+    fn ticket(&mut self, permissions: Vec<Permission>) -> Result<String, ResourceSetError> {
+        (**self).ticket(permissions)
+    }
+
+    fn redeem(&mut self, ticket: &str) -> Option<Vec<Permission>> {
+        (**self).redeem(ticket)
+    }
+}
+
+impl<T: ResourceSetStore + ?Sized> ResourceSetStore for Box<T> {
+    fn register(&mut self, resource: ResourceSet) -> Result<(), ResourceSetError> {
+        (**self).register(resource)
+    }
+
+    fn resource_set(&self, id: &str) -> Option<ResourceSet> {
+        (**self).resource_set(id)
+    }
+
+    fn remove(&mut self, owner_id: &str, id: &str) -> Option<ResourceSet> {
+        (**self).remove(owner_id, id)
+    }
+
+    fn resource_sets(&self, owner_id: &str) -> Vec<ResourceSet> {
+        (**self).resource_sets(owner_id)
+    }
+
+    fn ticket(&mut self, permissions: Vec<Permission>) -> Result<String, ResourceSetError> {
+        (**self).ticket(permissions)
+    }
+
+    fn redeem(&mut self, ticket: &str) -> Option<Vec<Permission>> {
+        (**self).redeem(ticket)
+    }
+}
+
+impl<'a, T: ResourceSetStore + ?Sized> ResourceSetStore for MutexGuard<'a, T> {
+    fn register(&mut self, resource: ResourceSet) -> Result<(), ResourceSetError> {
+        (**self).register(resource)
+    }
+
+    fn resource_set(&self, id: &str) -> Option<ResourceSet> {
+        (**self).resource_set(id)
+    }
+
+    fn remove(&mut self, owner_id: &str, id: &str) -> Option<ResourceSet> {
+        (**self).remove(owner_id, id)
+    }
+
+    fn resource_sets(&self, owner_id: &str) -> Vec<ResourceSet> {
+        (**self).resource_sets(owner_id)
+    }
+
+    fn ticket(&mut self, permissions: Vec<Permission>) -> Result<String, ResourceSetError> {
+        (**self).ticket(permissions)
+    }
+
+    fn redeem(&mut self, ticket: &str) -> Option<Vec<Permission>> {
+        (**self).redeem(ticket)
+    }
+}
+
+impl<'a, T: ResourceSetStore + ?Sized> ResourceSetStore for RwLockWriteGuard<'a, T> {
+    fn register(&mut self, resource: ResourceSet) -> Result<(), ResourceSetError> {
+        (**self).register(resource)
+    }
+
+    fn resource_set(&self, id: &str) -> Option<ResourceSet> {
+        (**self).resource_set(id)
+    }
+
+    fn remove(&mut self, owner_id: &str, id: &str) -> Option<ResourceSet> {
+        (**self).remove(owner_id, id)
+    }
+
+    fn resource_sets(&self, owner_id: &str) -> Vec<ResourceSet> {
+        (**self).resource_sets(owner_id)
+    }
+
+    fn ticket(&mut self, permissions: Vec<Permission>) -> Result<String, ResourceSetError> {
+        (**self).ticket(permissions)
+    }
+
+    fn redeem(&mut self, ticket: &str) -> Option<Vec<Permission>> {
+        (**self).redeem(ticket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::grant::Extensions;
+
+    #[test]
+    fn tickets_are_redeemed_once() {
+        let mut store = ResourceSetMap::new();
+        let photos = ResourceSet::new("photos", "alice", "Photo album", "view print".parse().unwrap());
+        store.register(photos.clone()).unwrap();
+        assert_eq!(
+            store.register(ResourceSet::new(
+                "photos",
+                "mallory",
+                "Stolen",
+                "view".parse().unwrap()
+            )),
+            Err(ResourceSetError::Conflict)
+        );
+        assert_eq!(store.resource_sets("alice"), vec![photos]);
+        assert!(store.resource_sets("mallory").is_empty());
+
+        let requested = vec![Permission::new("photos", "view".parse().unwrap())];
+        let ticket = store.ticket(requested.clone()).unwrap();
+        assert_eq!(store.redeem(&ticket), Some(requested.clone()));
+        assert_eq!(store.redeem(&ticket), None);
+
+        let mut expiring = ResourceSetMap::new().with_lifetime(Duration::zero());
+        let ticket = expiring.ticket(requested.clone()).unwrap();
+        assert_eq!(expiring.redeem(&ticket), None);
+
+        let mut grant = Grant {
+            owner_id: "alice".into(),
+            client_id: "bob-client".into(),
+            scope: "view".parse().unwrap(),
+            redirect_uri: "https://bob.example/".parse().unwrap(),
+            until: Utc::now(),
+            extensions: Extensions::new(),
+        };
+        assert_eq!(permissions(&grant), None);
+        attach_permissions(&mut grant, &requested);
+        assert_eq!(permissions(&grant), Some(requested));
+    }
+}